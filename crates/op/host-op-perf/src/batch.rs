@@ -0,0 +1,90 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use perf_event_rs::{
+    config::{Cpu as RawCpu, Process as RawProcess},
+    counting::Config as RawConfig,
+};
+use wasmtime::component::Resource;
+
+use crate::{
+    Counter, FixedCounterGroup, PerfCtx,
+    convert::Wrap,
+    ext::profiling::perf_ext::batch::Host,
+    profiling::perf::{
+        config::{Config, Cpu, Process},
+        counter::CounterStat,
+        counter_group::CounterGroupStat,
+    },
+};
+
+impl Host for PerfCtx {
+    fn open_counters(
+        &mut self,
+        processes: Vec<Process>,
+        cpu: Cpu,
+        cfg: Config,
+    ) -> wasmtime::Result<Vec<Result<Resource<Counter>, String>>> {
+        let cpu = Wrap::<RawCpu>::from(&cpu).into_inner();
+        let mut cfg = match Wrap::<RawConfig>::try_from(&cfg) {
+            Ok(cfg) => cfg.into_inner(),
+            Err(err) => {
+                let err = err.to_string();
+                return Ok(processes.iter().map(|_| Err(err.clone())).collect());
+            }
+        };
+        processes
+            .iter()
+            .map(|process| {
+                let process = Wrap::<RawProcess>::from(process).into_inner();
+                Ok(match Counter::new(&process, &cpu, &mut cfg) {
+                    Ok(counter) => Ok(self.table.push(counter)?),
+                    Err(err) => Err(err.to_string()),
+                })
+            })
+            .collect()
+    }
+
+    fn stat_counters(
+        &mut self,
+        counters: Vec<Resource<Counter>>,
+    ) -> wasmtime::Result<Vec<Result<CounterStat, String>>> {
+        counters
+            .iter()
+            .map(|it| {
+                let counter: &mut Counter = self.table.get_mut(it)?;
+                Ok(counter
+                    .stat()
+                    .map(|stat| Wrap::<CounterStat>::from(&stat).into_inner())
+                    .map_err(|err| err.to_string()))
+            })
+            .collect()
+    }
+
+    fn stat_groups(
+        &mut self,
+        groups: Vec<Resource<FixedCounterGroup>>,
+    ) -> wasmtime::Result<Vec<Result<CounterGroupStat, String>>> {
+        groups
+            .iter()
+            .map(|it| {
+                let group: &mut FixedCounterGroup = self.table.get_mut(it)?;
+                Ok(group
+                    .stat()
+                    .map(|stat| Wrap::<CounterGroupStat>::from(&stat).into_inner())
+                    .map_err(|err| err.to_string()))
+            })
+            .collect()
+    }
+}
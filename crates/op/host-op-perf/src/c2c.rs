@@ -0,0 +1,510 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::HashMap,
+    io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    ptr,
+    sync::atomic::{Ordering, fence},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ext::profiling::perf_ext::analysis::{C2cSummary, HotLine, LineContender},
+    tlb::is_intel,
+};
+
+const PERF_TYPE_RAW: u32 = 4;
+const PERF_SAMPLE_IP: u64 = 1 << 0;
+const PERF_SAMPLE_TID: u64 = 1 << 1;
+const PERF_SAMPLE_ADDR: u64 = 1 << 3;
+const PERF_SAMPLE_WEIGHT: u64 = 1 << 14;
+const PERF_SAMPLE_DATA_SRC: u64 = 1 << 15;
+const PERF_RECORD_LOST: u32 = 2;
+const PERF_RECORD_SAMPLE: u32 = 9;
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+const PERF_EVENT_IOC_ENABLE: libc::c_ulong = 0x2400;
+const PERF_EVENT_IOC_DISABLE: libc::c_ulong = 0x2401;
+// `disabled`, `exclude_hv` and `precise_ip = 2` of `perf_event_attr`
+const ATTR_FLAGS: u64 = 1 | (1 << 6) | (2 << 15);
+
+// MEM_TRANS_RETIRED.LOAD_LATENCY and MEM_INST_RETIRED.ALL_STORES, the
+// events of `perf mem` on Intel from Skylake on
+const INTEL_LOAD_LATENCY: u64 = 0x1cd;
+const INTEL_ALL_STORES: u64 = 0x82d0;
+// loads faster than this many cycles are L1 hits and never HITM
+const LOAD_LATENCY_THRESHOLD: u64 = 30;
+// prime, so samples don't line up with loops
+const SAMPLE_PERIOD: u64 = 10_007;
+// ring buffer of every cpu and event, a power of two
+const DATA_PAGES: usize = 64;
+const DRAIN_INTERVAL: Duration = Duration::from_millis(50);
+// every cpu is sampled meanwhile, longer captures are cut to this
+const MAX_DURATION: Duration = Duration::from_secs(30);
+const CACHE_LINE: u64 = 64;
+const MAX_CONTENDERS: usize = 16;
+
+// `perf_mem_data_src`
+const MEM_LVL_SHIFT: u64 = 5;
+const MEM_LVL_REM_CCE1: u64 = 0x400;
+const MEM_LVL_REM_CCE2: u64 = 0x800;
+const MEM_SNOOP_SHIFT: u64 = 19;
+const MEM_SNOOP_HITM: u64 = 0x10;
+const MEM_REMOTE: u64 = 1 << 37;
+
+/// `perf_event_attr` up to `PERF_ATTR_SIZE_VER5`.
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    type_: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+    config2: u64,
+    branch_sample_type: u64,
+    sample_regs_user: u64,
+    sample_stack_user: u32,
+    clockid: i32,
+    sample_regs_intr: u64,
+    aux_watermark: u32,
+    sample_max_stack: u16,
+    reserved: u16,
+}
+
+/// What a sample of `PERF_SAMPLE_IP | TID | ADDR | WEIGHT | DATA_SRC`
+/// carries, in this order.
+struct Sample {
+    ip: u64,
+    pid: i32,
+    addr: u64,
+    weight: u64,
+    data_src: u64,
+}
+
+impl Sample {
+    fn parse(body: &[u8]) -> Option<Self> {
+        let u64_at = |at: usize| Some(u64::from_ne_bytes(body.get(at..at + 8)?.try_into().ok()?));
+        let u32_at = |at: usize| Some(u32::from_ne_bytes(body.get(at..at + 4)?.try_into().ok()?));
+        Some(Self {
+            ip: u64_at(0)?,
+            pid: u32_at(8)? as i32,
+            addr: u64_at(16)?,
+            weight: u64_at(24)?,
+            data_src: u64_at(32)?,
+        })
+    }
+
+    const fn is_hitm(&self) -> bool {
+        (self.data_src >> MEM_SNOOP_SHIFT) & MEM_SNOOP_HITM != 0
+    }
+
+    const fn is_remote(&self) -> bool {
+        let lvl = self.data_src >> MEM_LVL_SHIFT;
+        lvl & (MEM_LVL_REM_CCE1 | MEM_LVL_REM_CCE2) != 0 || self.data_src & MEM_REMOTE != 0
+    }
+}
+
+/// Sampled event on one cpu with its ring buffer.
+struct Sampler {
+    fd: OwnedFd,
+    base: *mut u8,
+    len: usize,
+    data_size: usize,
+    store: bool,
+}
+
+impl Sampler {
+    fn open(config: u64, store: bool, cpu: i32) -> io::Result<Self> {
+        let attr = PerfEventAttr {
+            type_: PERF_TYPE_RAW,
+            size: mem::size_of::<PerfEventAttr>() as u32,
+            config,
+            sample_period: SAMPLE_PERIOD,
+            sample_type: PERF_SAMPLE_IP
+                | PERF_SAMPLE_TID
+                | PERF_SAMPLE_ADDR
+                | PERF_SAMPLE_WEIGHT
+                | PERF_SAMPLE_DATA_SRC,
+            flags: ATTR_FLAGS,
+            // `ldlat`, ignored by stores
+            config1: if store { 0 } else { LOAD_LATENCY_THRESHOLD },
+            ..Default::default()
+        };
+        // SAFETY: `attr` is a valid `perf_event_attr` of `attr.size` bytes
+        // that outlives the call, the kernel only reads it.
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                ptr::from_ref(&attr),
+                -1,
+                cpu,
+                -1,
+                PERF_FLAG_FD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `fd` was just returned by `perf_event_open` and nothing
+        // else owns it.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+        // SAFETY: `sysconf` has no preconditions.
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let len = (DATA_PAGES + 1) * page_size;
+        // SAFETY: a fresh shared mapping of the perf fd, one metadata page and
+        // a power of two data pages as `perf_event_open(2)` requires, that
+        // aliases no Rust memory. It is unmapped on drop.
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd,
+            base: base.cast(),
+            len,
+            data_size: DATA_PAGES * page_size,
+            store,
+        })
+    }
+
+    fn ioctl(&self, request: libc::c_ulong) -> io::Result<()> {
+        // SAFETY: `fd` is an open perf fd and the enable and disable
+        // requests take no pointer.
+        if unsafe { libc::ioctl(self.fd.as_raw_fd(), request, 0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Records written since the last call, by `PERF_RECORD_*` type and
+    /// without their header.
+    fn drain(&mut self, mut f: impl FnMut(u32, &[u8])) {
+        // `data_head` and `data_tail` of `perf_event_mmap_page`
+        // SAFETY: both offsets are within the first, metadata page of the
+        // mapping and 8-byte aligned as the page is.
+        let head_ptr = unsafe { self.base.add(1024) }.cast::<u64>();
+        // SAFETY: as above.
+        let tail_ptr = unsafe { self.base.add(1032) }.cast::<u64>();
+        // SAFETY: the kernel writes `data_head` concurrently, hence the
+        // volatile read of a valid, aligned pointer.
+        let head = unsafe { ptr::read_volatile(head_ptr) };
+        fence(Ordering::Acquire);
+        // SAFETY: `data_tail` is only written by us, below.
+        let mut tail = unsafe { ptr::read_volatile(tail_ptr) };
+        // SAFETY: the data pages are the last `data_size` bytes of the
+        // mapping of `len` bytes, alive as long as `self`. The kernel only
+        // writes past `head`, which is not read before the next call.
+        let data = unsafe {
+            std::slice::from_raw_parts(self.base.add(self.len - self.data_size), self.data_size)
+        };
+        let mut record = vec![];
+        while tail < head {
+            let at = |offset: u64| data[((tail + offset) % self.data_size as u64) as usize];
+            let header: Vec<u8> = (0..8).map(at).collect();
+            let type_ = u32::from_ne_bytes(header[..4].try_into().unwrap());
+            let size = u16::from_ne_bytes(header[6..8].try_into().unwrap()) as u64;
+            if size < 8 {
+                break;
+            }
+            record.clear();
+            record.extend((8..size).map(at));
+            f(type_, &record);
+            tail += size;
+        }
+        fence(Ordering::Release);
+        // SAFETY: a valid, aligned pointer into the mapping, the kernel reads
+        // `data_tail` to know what it may overwrite.
+        unsafe { ptr::write_volatile(tail_ptr, tail) };
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        // SAFETY: `base` and `len` are the mapping of `open`, and no slice of
+        // it outlives `drain`.
+        unsafe { libc::munmap(self.base.cast(), self.len) };
+    }
+}
+
+#[derive(Default)]
+struct Contender {
+    offset: u32,
+    hitm: u64,
+    stores: u64,
+}
+
+#[derive(Default)]
+struct Line {
+    hitm_local: u64,
+    hitm_remote: u64,
+    loads: u64,
+    stores: u64,
+    latency: u64,
+    contenders: HashMap<(i32, u64), Contender>,
+}
+
+#[derive(Default)]
+struct Lines {
+    samples: u64,
+    lost: u64,
+    lines: HashMap<u64, Line>,
+}
+
+impl Lines {
+    fn record(&mut self, sample: &Sample, store: bool, pid: Option<i32>) {
+        self.samples += 1;
+        if sample.addr == 0 || pid.is_some_and(|it| it != sample.pid) {
+            return;
+        }
+        let line = self
+            .lines
+            .entry(sample.addr & !(CACHE_LINE - 1))
+            .or_default();
+        let contender = line
+            .contenders
+            .entry((sample.pid, sample.ip))
+            .or_insert_with(|| Contender {
+                offset: (sample.addr % CACHE_LINE) as u32,
+                ..Default::default()
+            });
+        if store {
+            line.stores += 1;
+            contender.stores += 1;
+            return;
+        }
+        line.loads += 1;
+        line.latency += sample.weight;
+        if sample.is_hitm() {
+            contender.hitm += 1;
+            if sample.is_remote() {
+                line.hitm_remote += 1;
+            } else {
+                line.hitm_local += 1;
+            }
+        }
+    }
+
+    /// Lines with HITM loads, the most contended first.
+    fn summary(self, duration: Duration, max_lines: usize) -> C2cSummary {
+        let (mut hitm_local, mut hitm_remote) = (0, 0);
+        let mut lines: Vec<_> = self
+            .lines
+            .into_iter()
+            .filter(|(_, line)| line.hitm_local + line.hitm_remote > 0)
+            .inspect(|(_, line)| {
+                hitm_local += line.hitm_local;
+                hitm_remote += line.hitm_remote;
+            })
+            .collect();
+        lines.sort_by_key(|(address, line)| {
+            (
+                std::cmp::Reverse(line.hitm_local + line.hitm_remote),
+                *address,
+            )
+        });
+        lines.truncate(max_lines);
+        let lines = lines
+            .into_iter()
+            .map(|(address, line)| {
+                let mut contenders: Vec<_> = line
+                    .contenders
+                    .into_iter()
+                    .filter(|(_, it)| it.hitm + it.stores > 0)
+                    .map(|((pid, ip), it)| LineContender {
+                        pid,
+                        ip,
+                        offset: it.offset,
+                        hitm: it.hitm,
+                        stores: it.stores,
+                    })
+                    .collect();
+                contenders
+                    .sort_by_key(|it| (std::cmp::Reverse(it.hitm + it.stores), it.pid, it.ip));
+                contenders.truncate(MAX_CONTENDERS);
+                HotLine {
+                    address,
+                    hitm_local: line.hitm_local,
+                    hitm_remote: line.hitm_remote,
+                    loads: line.loads,
+                    stores: line.stores,
+                    avg_load_latency_cycles: (line.loads > 0)
+                        .then(|| line.latency as f64 / line.loads as f64),
+                    contenders,
+                }
+            })
+            .collect();
+        C2cSummary {
+            duration_ns: duration.as_nanos() as u64,
+            samples: self.samples,
+            lost: self.lost,
+            hitm_local,
+            hitm_remote,
+            lines,
+        }
+    }
+}
+
+/// Cache lines contended between cpus, like `perf c2c`: loads and stores are
+/// sampled with their data address on every cpu for `duration`, and loads
+/// that hit a line modified in the cache of another core (HITM) are summed
+/// up by line, of `pid` only if set, so only the most contended lines reach
+/// the component. `duration` is capped to [`MAX_DURATION`].
+pub fn false_sharing(
+    duration: Duration,
+    max_lines: usize,
+    pid: Option<i32>,
+) -> io::Result<C2cSummary> {
+    if !is_intel() {
+        return Err(io::Error::other(
+            "Cache line contention needs load latency sampling of Intel cpus",
+        ));
+    }
+    let duration = duration.min(MAX_DURATION);
+    // SAFETY: `sysconf` has no preconditions.
+    let cpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) }.max(1) as i32;
+    // offline cpus can't be sampled
+    let mut samplers: Vec<_> = (0..cpus)
+        .flat_map(|cpu| {
+            [
+                Sampler::open(INTEL_LOAD_LATENCY, false, cpu),
+                Sampler::open(INTEL_ALL_STORES, true, cpu),
+            ]
+        })
+        .filter_map(Result::ok)
+        .collect();
+    if !samplers.iter().any(|it| !it.store) {
+        return Err(io::Error::other(
+            "Failed to sample loads, perf_event_paranoid may not allow it",
+        ));
+    }
+
+    let mut lines = Lines::default();
+    let drain = |samplers: &mut [Sampler], lines: &mut Lines| {
+        for sampler in samplers {
+            let store = sampler.store;
+            sampler.drain(|type_, body| match type_ {
+                PERF_RECORD_SAMPLE => {
+                    if let Some(sample) = Sample::parse(body) {
+                        lines.record(&sample, store, pid);
+                    }
+                }
+                PERF_RECORD_LOST => {
+                    // id and the number of lost records
+                    if let Some(lost) = body.get(8..16) {
+                        lines.lost += u64::from_ne_bytes(lost.try_into().unwrap());
+                    }
+                }
+                _ => {}
+            });
+        }
+    };
+    samplers
+        .iter()
+        .try_for_each(|it| it.ioctl(PERF_EVENT_IOC_ENABLE))?;
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        thread::sleep(DRAIN_INTERVAL.min(duration.saturating_sub(start.elapsed())));
+        drain(&mut samplers, &mut lines);
+    }
+    samplers
+        .iter()
+        .try_for_each(|it| it.ioctl(PERF_EVENT_IOC_DISABLE))?;
+    drain(&mut samplers, &mut lines);
+    Ok(lines.summary(duration, max_lines))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Lines, MEM_LVL_REM_CCE1, MEM_LVL_SHIFT, MEM_SNOOP_HITM, MEM_SNOOP_SHIFT, Sample};
+
+    const HITM: u64 = MEM_SNOOP_HITM << MEM_SNOOP_SHIFT;
+
+    fn sample(pid: i32, addr: u64, data_src: u64) -> Sample {
+        Sample {
+            ip: 0x1000,
+            pid,
+            addr,
+            weight: 100,
+            data_src,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut body = vec![];
+        body.extend(0x1000u64.to_ne_bytes());
+        body.extend(42u32.to_ne_bytes());
+        body.extend(43u32.to_ne_bytes());
+        body.extend(0x2040u64.to_ne_bytes());
+        body.extend(120u64.to_ne_bytes());
+        body.extend(HITM.to_ne_bytes());
+        let sample = Sample::parse(&body).unwrap();
+        assert_eq!(sample.ip, 0x1000);
+        assert_eq!(sample.pid, 42);
+        assert_eq!(sample.addr, 0x2040);
+        assert_eq!(sample.weight, 120);
+        assert!(sample.is_hitm());
+        assert!(Sample::parse(&body[..39]).is_none());
+    }
+
+    #[test]
+    fn test_is_hitm() {
+        assert!(!sample(1, 0x40, 0).is_hitm());
+        assert!(sample(1, 0x40, HITM).is_hitm());
+        assert!(!sample(1, 0x40, HITM).is_remote());
+        let remote = HITM | (MEM_LVL_REM_CCE1 << MEM_LVL_SHIFT);
+        assert!(sample(1, 0x40, remote).is_remote());
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut lines = Lines::default();
+        lines.record(&sample(1, 0x1008, HITM), false, None);
+        lines.record(&sample(2, 0x1030, 0), true, None);
+        lines.record(&sample(1, 0x2000, 0), false, None);
+        // another process and no data address
+        lines.record(&sample(3, 0x1008, HITM), false, Some(1));
+        lines.record(&sample(1, 0, HITM), false, Some(1));
+        let summary = lines.summary(Duration::from_secs(1), 8);
+        assert_eq!(summary.samples, 5);
+        assert_eq!(summary.hitm_local, 1);
+        assert_eq!(summary.hitm_remote, 0);
+        assert_eq!(summary.lines.len(), 1);
+        let line = &summary.lines[0];
+        assert_eq!(line.address, 0x1000);
+        assert_eq!((line.loads, line.stores), (1, 1));
+        assert_eq!(line.avg_load_latency_cycles, Some(100.0));
+        assert_eq!(line.contenders.len(), 2);
+        assert_eq!(line.contenders[0].offset, 8);
+    }
+}
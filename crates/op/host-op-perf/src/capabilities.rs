@@ -0,0 +1,71 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{fs, io, path::Path, str::FromStr};
+
+use crate::ext::profiling::perf_ext::perf::{Capabilities, Pmu};
+
+const PMU_DIR: &str = "/sys/bus/event_source/devices";
+const CAP_SYS_ADMIN: u32 = 21;
+const CAP_PERFMON: u32 = 38;
+
+fn read_value<T: FromStr>(path: impl AsRef<Path>) -> io::Result<T> {
+    let path = path.as_ref();
+    fs::read_to_string(path)?.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid value in {}", path.display()),
+        )
+    })
+}
+
+fn effective_caps() -> io::Result<u64> {
+    let status = fs::read_to_string("/proc/self/status")?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "No CapEff in status"))
+}
+
+fn pmus() -> io::Result<Vec<Pmu>> {
+    let mut pmus = Vec::new();
+    for entry in fs::read_dir(PMU_DIR)? {
+        let entry = entry?;
+        let Ok(ty) = read_value(entry.path().join("type")) else {
+            continue;
+        };
+        pmus.push(Pmu {
+            name: entry.file_name().to_string_lossy().to_string(),
+            ty,
+        });
+    }
+    pmus.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(pmus)
+}
+
+pub fn probe() -> io::Result<Capabilities> {
+    let caps = effective_caps()?;
+    let has_cap = |cap: u32| caps & (1 << cap) != 0;
+
+    Ok(Capabilities {
+        paranoid: read_value("/proc/sys/kernel/perf_event_paranoid")?,
+        // absent means no restriction
+        kptr_restrict: read_value("/proc/sys/kernel/kptr_restrict").unwrap_or(0),
+        max_sample_rate: read_value("/proc/sys/kernel/perf_event_max_sample_rate").ok(),
+        max_stack: read_value("/proc/sys/kernel/perf_event_max_stack").ok(),
+        privileged: has_cap(CAP_PERFMON) || has_cap(CAP_SYS_ADMIN),
+        pmus: pmus()?,
+    })
+}
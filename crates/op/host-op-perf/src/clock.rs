@@ -12,26 +12,23 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
-use std::{fs, ops::Not, process::Command};
+use std::sync::OnceLock;
 
-fn main() {
-    let _ = fs::remove_file("src/imports.rs");
-    let mut cmd = Command::new("wit-bindgen");
-    cmd.args([
-        "rust",
-        "--stubs",
-        "--out-dir",
-        "src/",
-        "../../../psh-sdk-wit/wit/deps/perf",
-    ]);
+use psh_system::{clock::PerfClock, error::Result};
 
-    let output = cmd
-        .output()
-        .unwrap_or_else(|it| panic!("Failed to generate imports: \n{}", it));
-    if output.status.success().not() {
-        panic!(
-            "Failed to generate imports: \n{}",
-            String::from_utf8(output.stderr).unwrap()
-        );
+static CLOCK: OnceLock<PerfClock> = OnceLock::new();
+
+/// Shared by all components, the drift estimate improves the longer the
+/// daemon runs.
+fn perf_clock() -> Result<&'static PerfClock> {
+    if let Some(clock) = CLOCK.get() {
+        return Ok(clock);
     }
+    let clock = PerfClock::new()?;
+    Ok(CLOCK.get_or_init(|| clock))
+}
+
+pub fn to_wall(timestamps: &[u64]) -> Result<Vec<u64>> {
+    let clock = perf_clock()?;
+    timestamps.iter().map(|&it| clock.to_wall(it)).collect()
 }
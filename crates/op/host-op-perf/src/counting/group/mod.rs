@@ -28,6 +28,9 @@ impl HostCounterGroup for PerfCtx {
         process: Process,
         cpu: Cpu,
     ) -> wasmtime::Result<Result<Resource<CounterGroup>, String>> {
+        if let Err(err) = self.rate_limit() {
+            return Ok(Err(err));
+        }
         let counter_group = {
             let process = Wrap::<RawProcess>::from(&process).into_inner();
             let cpu = Wrap::<RawCpu>::from(&cpu).into_inner();
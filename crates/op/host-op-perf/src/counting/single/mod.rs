@@ -29,6 +29,9 @@ impl HostCounter for PerfCtx {
         cpu: Cpu,
         cfg: Config,
     ) -> wasmtime::Result<Result<Resource<Counter>, String>> {
+        if let Err(err) = self.rate_limit() {
+            return Ok(Err(err));
+        }
         let create_counter = || -> Result<_, String> {
             let process = Wrap::<RawProcess>::from(&process).into_inner();
             let cpu = Wrap::<RawCpu>::from(&cpu).into_inner();
@@ -12,15 +12,26 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
+use std::time::Duration;
+
+use psh_system::rate_limit::RateLimiter;
 use wasmtime::component::{Linker, ResourceTable};
 
+mod batch;
+mod c2c;
+mod capabilities;
+mod clock;
 pub mod convert;
 pub mod counting;
+mod numa;
+pub mod pmu_events;
+mod tlb;
 
 pub type Counter = perf_event_rs::counting::Counter;
 pub type CounterGroup = perf_event_rs::counting::CounterGroup;
 pub type FixedCounterGroup = perf_event_rs::counting::FixedCounterGroup;
 pub type CounterGuard = perf_event_rs::counting::CounterGuard;
+pub type CounterSet = numa::CounterSet;
 
 wasmtime::component::bindgen!({
     path: "../../../psh-sdk-wit/wit/deps/perf",
@@ -39,26 +50,117 @@ wasmtime::component::bindgen!({
     trappable_imports: true,
 });
 
+/// Host interfaces not yet published in psh-sdk-wit, see `wit/perf-ext`.
+pub mod ext {
+    wasmtime::component::bindgen!({
+        path: "../../../wit/perf-ext",
+        world: "imports",
+        with: {
+            "profiling:perf/config"                : crate::profiling::perf::config,
+            "profiling:perf/counter"               : crate::profiling::perf::counter,
+            "profiling:perf/counter-group"         : crate::profiling::perf::counter_group,
+            "profiling:perf-ext/numa/counter-set"  : crate::CounterSet,
+        },
+        trappable_imports: true,
+    });
+}
+
 pub struct PerfCtx {
     table: ResourceTable,
+    rate_limiter: RateLimiter,
 }
 
+/// Opening counters is limited as the `perf` interface.
+const RATE_LIMITED_INTERFACE: &str = "perf";
+
 #[allow(clippy::new_without_default)]
 impl PerfCtx {
     pub fn new() -> Self {
         Self {
             table: ResourceTable::new(),
+            rate_limiter: RateLimiter::default(),
         }
     }
+
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = limiter;
+        self
+    }
+
+    /// Calls made so far to open counters, as `perf`.
+    pub fn host_calls(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.rate_limiter
+            .calls()
+            .iter()
+            .map(|(k, v)| (k.as_str(), *v))
+    }
+
+    fn rate_limit(&mut self) -> Result<(), String> {
+        self.rate_limiter
+            .check(RATE_LIMITED_INTERFACE)
+            .map_err(|err| err.to_string())
+    }
 }
 
 impl profiling::perf::config::Host for PerfCtx {}
 impl profiling::perf::counter::Host for PerfCtx {}
 impl profiling::perf::counter_group::Host for PerfCtx {}
 
+impl ext::profiling::perf_ext::perf::Host for PerfCtx {
+    fn capabilities(
+        &mut self,
+    ) -> wasmtime::Result<Result<ext::profiling::perf_ext::perf::Capabilities, String>> {
+        Ok(capabilities::probe().map_err(|err| err.to_string()))
+    }
+
+    fn wall_clock(&mut self, timestamps: Vec<u64>) -> wasmtime::Result<Result<Vec<u64>, String>> {
+        Ok(clock::to_wall(&timestamps).map_err(|err| err.to_string()))
+    }
+
+    fn resolve_event(
+        &mut self,
+        name: String,
+    ) -> wasmtime::Result<Result<ext::profiling::perf_ext::perf::NamedEvent, String>> {
+        Ok(pmu_events::resolve(&name))
+    }
+
+    fn event_names(&mut self) -> wasmtime::Result<Result<Vec<String>, String>> {
+        Ok(pmu_events::names())
+    }
+}
+
+impl ext::profiling::perf_ext::analysis::Host for PerfCtx {
+    fn tlb_pressure(
+        &mut self,
+        pid: i32,
+        duration_ms: u64,
+    ) -> wasmtime::Result<Result<ext::profiling::perf_ext::analysis::TlbSummary, String>> {
+        if let Err(err) = self.rate_limit() {
+            return Ok(Err(err));
+        }
+        Ok(tlb::pressure(pid, Duration::from_millis(duration_ms)).map_err(|err| err.to_string()))
+    }
+
+    fn false_sharing(
+        &mut self,
+        duration_ms: u64,
+        max_lines: u32,
+        pid: Option<i32>,
+    ) -> wasmtime::Result<Result<ext::profiling::perf_ext::analysis::C2cSummary, String>> {
+        if let Err(err) = self.rate_limit() {
+            return Ok(Err(err));
+        }
+        Ok(
+            c2c::false_sharing(Duration::from_millis(duration_ms), max_lines as usize, pid)
+                .map_err(|err| err.to_string()),
+        )
+    }
+}
+
 pub fn add_to_linker<T>(
     l: &mut Linker<T>,
     f: impl (Fn(&mut T) -> &mut PerfCtx) + Copy + Send + Sync + 'static,
 ) -> anyhow::Result<()> {
-    crate::Imports::add_to_linker(l, f)
+    crate::Imports::add_to_linker(l, f)?;
+    ext::Imports::add_to_linker(l, f)
 }
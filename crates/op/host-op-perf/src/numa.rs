@@ -0,0 +1,162 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{collections::BTreeMap, io};
+
+use perf_event_rs::{
+    config::{Cpu as RawCpu, Process as RawProcess},
+    counting::{Config as RawConfig, Counter},
+};
+use psh_system::cpu::{CpuHandle, CpuTopology};
+use wasmtime::component::Resource;
+
+use crate::{
+    PerfCtx,
+    convert::Wrap,
+    ext::profiling::perf_ext::numa::{Domain, DomainCpus, DomainStat, Host, HostCounterSet},
+    profiling::perf::config::{Config, Process},
+};
+
+/// One counter per cpu, grouped by the domain the cpus belong to.
+pub struct CounterSet {
+    domains: Vec<(u32, Vec<Counter>)>,
+}
+
+fn domains(domain: Domain) -> Result<BTreeMap<u32, Vec<u32>>, String> {
+    let topology: CpuTopology = CpuHandle::new()
+        .topology(None)
+        .map_err(|err| err.to_string())?;
+    Ok(match domain {
+        Domain::Socket => topology.sockets(),
+        Domain::Node => topology.nodes(),
+    })
+}
+
+impl CounterSet {
+    fn open(domain: Domain, process: &Process, cfg: &Config) -> Result<Self, String> {
+        let process = Wrap::<RawProcess>::from(process).into_inner();
+        let mut cfg = Wrap::<RawConfig>::try_from(cfg)
+            .map_err(|err| err.to_string())?
+            .into_inner();
+        let domains = domains(domain)?
+            .into_iter()
+            .map(|(id, cpus)| {
+                let counters = cpus
+                    .into_iter()
+                    .map(|cpu| {
+                        Counter::new(&process, &RawCpu::Id(cpu), &mut cfg)
+                            .map_err(|err| format!("cpu {cpu}: {err}"))
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok((id, counters))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { domains })
+    }
+
+    fn for_each(&self, f: fn(&Counter) -> io::Result<()>) -> Result<(), String> {
+        self.domains
+            .iter()
+            .flat_map(|(_, counters)| counters)
+            .try_for_each(f)
+            .map_err(|err| err.to_string())
+    }
+
+    fn stat(&mut self) -> Result<Vec<DomainStat>, String> {
+        self.domains
+            .iter_mut()
+            .map(|(id, counters)| {
+                let mut stat = DomainStat {
+                    id: *id,
+                    event_count: 0,
+                    scaled_count: 0,
+                    time_enabled: 0,
+                    time_running: u64::MAX,
+                };
+                for counter in counters {
+                    let it = counter.stat().map_err(|err| err.to_string())?;
+                    stat.event_count += it.event_count;
+                    // a counter that never ran counted nothing
+                    if it.time_running > 0 {
+                        stat.scaled_count +=
+                            (u128::from(it.event_count) * u128::from(it.time_enabled)
+                                / u128::from(it.time_running)) as u64;
+                    }
+                    stat.time_enabled = stat.time_enabled.max(it.time_enabled);
+                    stat.time_running = stat.time_running.min(it.time_running);
+                }
+                if stat.time_running == u64::MAX {
+                    stat.time_running = 0;
+                }
+                Ok(stat)
+            })
+            .collect()
+    }
+}
+
+impl Host for PerfCtx {
+    fn domains(&mut self, domain: Domain) -> wasmtime::Result<Result<Vec<DomainCpus>, String>> {
+        Ok(domains(domain).map(|domains| {
+            domains
+                .into_iter()
+                .map(|(id, cpus)| DomainCpus { id, cpus })
+                .collect()
+        }))
+    }
+}
+
+impl HostCounterSet for PerfCtx {
+    fn open(
+        &mut self,
+        domain: Domain,
+        process: Process,
+        cfg: Config,
+    ) -> wasmtime::Result<Result<Resource<CounterSet>, String>> {
+        if let Err(err) = self.rate_limit() {
+            return Ok(Err(err));
+        }
+        Ok(match CounterSet::open(domain, &process, &cfg) {
+            Ok(set) => Ok(self.table.push(set)?),
+            Err(err) => Err(err),
+        })
+    }
+
+    fn enable(&mut self, self_: Resource<CounterSet>) -> wasmtime::Result<Result<(), String>> {
+        let set: &CounterSet = self.table.get(&self_)?;
+        Ok(set.for_each(Counter::enable))
+    }
+
+    fn disable(&mut self, self_: Resource<CounterSet>) -> wasmtime::Result<Result<(), String>> {
+        let set: &CounterSet = self.table.get(&self_)?;
+        Ok(set.for_each(Counter::disable))
+    }
+
+    fn reset(&mut self, self_: Resource<CounterSet>) -> wasmtime::Result<Result<(), String>> {
+        let set: &CounterSet = self.table.get(&self_)?;
+        Ok(set.for_each(Counter::reset))
+    }
+
+    fn stat(
+        &mut self,
+        self_: Resource<CounterSet>,
+    ) -> wasmtime::Result<Result<Vec<DomainStat>, String>> {
+        let set: &mut CounterSet = self.table.get_mut(&self_)?;
+        Ok(set.stat())
+    }
+
+    fn drop(&mut self, rep: Resource<CounterSet>) -> wasmtime::Result<()> {
+        self.table.delete(rep)?;
+        Ok(())
+    }
+}
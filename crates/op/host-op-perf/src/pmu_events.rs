@@ -0,0 +1,380 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{Context, Result, bail};
+use psh_system::cpu::{CpuHandle, CpuInfo};
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::ext::profiling::perf_ext::perf::NamedEvent;
+
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+static CATALOG: OnceLock<Result<Catalog, String>> = OnceLock::new();
+
+// MIDR_EL1 variant and revision, the tables are per part
+const MIDR_VARIANT_REVISION: u64 = 0x00f0_000f;
+
+// PERF_TYPE_RAW, the type of the core pmu of non hybrid cpus
+const PERF_TYPE_RAW: u32 = 4;
+
+// core pmu of the efficient cores of hybrid cpus, its events are named
+// `cpu_atom/NAME`
+const ATOM_PMU: &str = "cpu_atom";
+
+/// Loads event tables from `dir`, a copy of perf's `tools/perf/pmu-events/arch`.
+/// The tables of the current cpu are read on first use.
+pub fn init(dir: impl Into<PathBuf>) -> Result<()> {
+    if DIR.set(dir.into()).is_err() {
+        bail!("PMU event tables already initialized");
+    }
+    Ok(())
+}
+
+/// Core events of the current cpu by name.
+struct Catalog(BTreeMap<String, NamedEvent>);
+
+fn catalog() -> Result<&'static Catalog, String> {
+    let dir = DIR
+        .get()
+        .ok_or_else(|| "PMU event tables are disabled".to_string())?;
+    CATALOG
+        .get_or_init(|| Catalog::load(dir).map_err(|err| format!("{err:#}")))
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+/// Resolves a vendor event name, e.g. `UOPS_RETIRED.RETIRE_SLOTS`, case
+/// insensitively. Events of the efficient cores of hybrid cpus are named
+/// like `cpu_atom/UOPS_RETIRED.ALL`.
+pub fn resolve(name: &str) -> Result<NamedEvent, String> {
+    catalog()?
+        .0
+        .get(&key(name))
+        .cloned()
+        .ok_or_else(|| format!("Unknown event {name} for this cpu"))
+}
+
+/// Upper case event name, the pmu prefix as is.
+fn key(name: &str) -> String {
+    match name.split_once('/') {
+        Some((pmu, event)) => format!("{pmu}/{}", event.to_ascii_uppercase()),
+        None => name.to_ascii_uppercase(),
+    }
+}
+
+pub fn names() -> Result<Vec<String>, String> {
+    Ok(catalog()?.0.keys().cloned().collect())
+}
+
+/// How the current cpu is named in `mapfile.csv`.
+enum CpuId {
+    /// `GenuineIntel-6-55-7`, vendor, family, model and stepping
+    X86(String),
+    /// MIDR_EL1
+    Arm64(u64),
+}
+
+impl CpuId {
+    fn current() -> Result<Self> {
+        match CpuHandle::new().info()? {
+            CpuInfo::X86_64(cpus) => {
+                let cpu = cpus.first().context("No cpu in /proc/cpuinfo")?;
+                Ok(Self::X86(format!(
+                    "{}-{}-{:X}-{:X}",
+                    cpu.vendor_id, cpu.cpu_family, cpu.model, cpu.stepping
+                )))
+            }
+            CpuInfo::Arm64(_) => {
+                let midr =
+                    fs::read_to_string("/sys/devices/system/cpu/cpu0/regs/identification/midr_el1")
+                        .context("Failed to read MIDR_EL1")?;
+                let midr = u64::from_str_radix(midr.trim().trim_start_matches("0x"), 16)?;
+                Ok(Self::Arm64(midr))
+            }
+            _ => bail!("No PMU event tables for this architecture"),
+        }
+    }
+
+    const fn arch(&self) -> &'static str {
+        match self {
+            Self::X86(_) => "x86",
+            Self::Arm64(_) => "arm64",
+        }
+    }
+
+    /// Like perf, x86 patterns without a stepping match every stepping and
+    /// arm64 ones without variant and revision every variant and revision.
+    fn matches(&self, pattern: &str) -> Result<bool> {
+        match self {
+            Self::X86(id) => {
+                let id = match pattern.matches('-').count() {
+                    3.. => id.as_str(),
+                    _ => id.rsplit_once('-').map_or(id.as_str(), |(it, _)| it),
+                };
+                Ok(Regex::new(&format!("^(?:{pattern})$"))?.is_match(id))
+            }
+            Self::Arm64(midr) => {
+                let pattern = u64::from_str_radix(pattern.trim_start_matches("0x"), 16)?;
+                let mask = if pattern & MIDR_VARIANT_REVISION == 0 {
+                    !MIDR_VARIANT_REVISION
+                } else {
+                    u64::MAX
+                };
+                Ok(midr & mask == pattern)
+            }
+        }
+    }
+}
+
+impl Catalog {
+    fn load(dir: &Path) -> Result<Self> {
+        Self::read(dir, &CpuId::current()?)
+    }
+
+    fn read(dir: &Path, cpu: &CpuId) -> Result<Self> {
+        let arch = dir.join(cpu.arch());
+        let mapfile = arch.join("mapfile.csv");
+        let mapfile = fs::read_to_string(&mapfile)
+            .with_context(|| format!("Failed to read {}", mapfile.display()))?;
+
+        // Family-model,Version,Filename,EventType
+        let tables = mapfile
+            .lines()
+            .filter(|it| !it.starts_with('#'))
+            .find_map(|line| {
+                let fields: Vec<_> = line.split(',').map(str::trim).collect();
+                match fields[..] {
+                    [pattern, _, filename, "core"] if cpu.matches(pattern).unwrap_or(false) => {
+                        Some(arch.join(filename))
+                    }
+                    _ => None,
+                }
+            });
+        let Some(tables) = tables else {
+            bail!("No PMU event tables for this cpu in {}", arch.display());
+        };
+
+        // arm64 tables refer to the architecture standard events of the
+        // json files next to the mapfile by name
+        let standard = read_events(&arch)?
+            .into_iter()
+            .filter_map(|it| Some((str_field(&it, "EventName")?.to_string(), it)))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut events = BTreeMap::new();
+        let mut types = BTreeMap::new();
+        for mut event in read_events(&tables)? {
+            // hybrid cpus have a core pmu per kind of core, uncore events
+            // need their own pmu
+            let pmu = match str_field(&event, "Unit") {
+                None | Some("cpu") => "cpu",
+                Some(unit @ ("cpu_core" | "cpu_atom")) => unit,
+                Some(_) => continue,
+            }
+            .to_string();
+            if let Some(common) = str_field(&event, "ArchStdEvent").and_then(|it| standard.get(it))
+            {
+                for (key, value) in common {
+                    event.entry(key.clone()).or_insert_with(|| value.clone());
+                }
+            }
+            let name = str_field(&event, "EventName")
+                .or_else(|| str_field(&event, "ArchStdEvent"))
+                .map(|it| match pmu.as_str() {
+                    ATOM_PMU => key(&format!("{ATOM_PMU}/{it}")),
+                    _ => key(it),
+                });
+            let (Some(name), Some(config)) = (name, encode(cpu, &event)) else {
+                continue;
+            };
+            let description = str_field(&event, "BriefDescription")
+                .or_else(|| str_field(&event, "PublicDescription"))
+                .unwrap_or_default()
+                .to_string();
+            events.insert(
+                name.clone(),
+                NamedEvent {
+                    name,
+                    description,
+                    config,
+                    pmu_type: *types.entry(pmu.clone()).or_insert_with(|| pmu_type(&pmu)),
+                    pmu,
+                },
+            );
+        }
+        Ok(Self(events))
+    }
+}
+
+/// `perf_event_attr.type` of the core pmu `pmu`, that of the raw events if
+/// the kernel does not list it.
+fn pmu_type(pmu: &str) -> u32 {
+    fs::read_to_string(format!("/sys/bus/event_source/devices/{pmu}/type"))
+        .ok()
+        .and_then(|it| it.trim().parse().ok())
+        .unwrap_or(PERF_TYPE_RAW)
+}
+
+/// Events of the json files directly in `dir`.
+fn read_events(dir: &Path) -> Result<Vec<Map<String, Value>>> {
+    let mut events = vec![];
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|it| it != "json") {
+            continue;
+        }
+        let content = fs::read(&path)?;
+        let Value::Array(list) = serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?
+        else {
+            continue;
+        };
+        events.extend(list.into_iter().filter_map(|it| match it {
+            Value::Object(event) => Some(event),
+            _ => None,
+        }));
+    }
+    Ok(events)
+}
+
+fn str_field<'a>(event: &'a Map<String, Value>, key: &str) -> Option<&'a str> {
+    event.get(key).and_then(Value::as_str)
+}
+
+/// `"0xC2"`, `"1"` or a number, the first of `"0xB7,0xBB"`.
+fn num_field(event: &Map<String, Value>, key: &str) -> Option<u64> {
+    match event.get(key)? {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => {
+            let s = s.split(',').next()?.trim();
+            match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+                Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                None => s.parse().ok(),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// `perf_event_attr.config` of a raw event, `None` for events that need
+/// more than that, like the offcore response ones.
+fn encode(cpu: &CpuId, event: &Map<String, Value>) -> Option<u64> {
+    let code = num_field(event, "EventCode")?;
+    match cpu {
+        CpuId::Arm64(_) => Some(code),
+        CpuId::X86(_) => {
+            if num_field(event, "MSRValue").is_some_and(|it| it != 0) {
+                return None;
+            }
+            let flag = |key| u64::from(num_field(event, key).is_some_and(|it| it != 0));
+            // AMD extends the event select by 4 bits, at 32 to 35
+            Some(
+                (code & 0xff)
+                    | (((code >> 8) & 0xf) << 32)
+                    | ((num_field(event, "UMask").unwrap_or(0) & 0xff) << 8)
+                    | (flag("EdgeDetect") << 18)
+                    | (flag("AnyThread") << 21)
+                    | (flag("Invert") << 23)
+                    | ((num_field(event, "CounterMask").unwrap_or(0) & 0xff) << 24),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Catalog, CpuId, key};
+
+    const DIR: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../../test_resources/pmu-events"
+    );
+
+    fn config(catalog: &Catalog, name: &str) -> Option<u64> {
+        catalog.0.get(name).map(|it| it.config)
+    }
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key("uops_retired.all"), "UOPS_RETIRED.ALL");
+        assert_eq!(
+            key("cpu_atom/uops_retired.all"),
+            "cpu_atom/UOPS_RETIRED.ALL"
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let cpu = CpuId::X86("GenuineIntel-6-97-2".to_string());
+        assert!(cpu.matches("GenuineIntel-6-(97|9A|B7|BA|BF)").unwrap());
+        assert!(!cpu.matches("GenuineIntel-6-55-[01234]").unwrap());
+
+        let cpu = CpuId::Arm64(0x413f_d0c1);
+        assert!(cpu.matches("0x410fd0c0").unwrap());
+        assert!(cpu.matches("0x413fd0c1").unwrap());
+        assert!(!cpu.matches("0x410fd400").unwrap());
+    }
+
+    #[test]
+    fn test_read() {
+        let cpu = CpuId::X86("GenuineIntel-6-55-4".to_string());
+        let catalog = Catalog::read(Path::new(DIR), &cpu).unwrap();
+        assert_eq!(config(&catalog, "UOPS_RETIRED.RETIRE_SLOTS"), Some(0x02c2));
+        assert_eq!(
+            config(&catalog, "UOPS_RETIRED.STALL_CYCLES"),
+            Some(0x0180_01c2)
+        );
+        // offcore response events need more than the config
+        assert_eq!(
+            config(&catalog, "OFFCORE_RESPONSE.DEMAND_DATA_RD.L3_HIT.ANY_SNOOP"),
+            None
+        );
+        assert_eq!(config(&catalog, "UNC_M_CAS_COUNT.RD"), None);
+        assert_eq!(catalog.0["UOPS_RETIRED.RETIRE_SLOTS"].pmu, "cpu");
+    }
+
+    #[test]
+    fn test_read_hybrid() {
+        let cpu = CpuId::X86("GenuineIntel-6-97-2".to_string());
+        let catalog = Catalog::read(Path::new(DIR), &cpu).unwrap();
+        assert_eq!(
+            catalog.0.keys().collect::<Vec<_>>(),
+            [
+                "INST_RETIRED.ANY_P",
+                "TOPDOWN.SLOTS_P",
+                "cpu_atom/INST_RETIRED.ANY_P",
+                "cpu_atom/UOPS_RETIRED.ALL",
+            ]
+        );
+        assert_eq!(catalog.0["INST_RETIRED.ANY_P"].pmu, "cpu_core");
+        assert_eq!(catalog.0["cpu_atom/INST_RETIRED.ANY_P"].pmu, "cpu_atom");
+        assert_eq!(config(&catalog, "TOPDOWN.SLOTS_P"), Some(0x01a4));
+        assert_eq!(config(&catalog, "cpu_atom/UOPS_RETIRED.ALL"), Some(0xc2));
+    }
+
+    #[test]
+    fn test_read_unknown_cpu() {
+        let cpu = CpuId::X86("AuthenticAMD-25-1-1".to_string());
+        assert!(Catalog::read(Path::new(DIR), &cpu).is_err());
+    }
+}
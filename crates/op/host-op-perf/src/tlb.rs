@@ -0,0 +1,189 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{fs, io, thread, time::Duration};
+
+use perf_event_rs::{
+    config::{Cpu as RawCpu, Process as RawProcess},
+    counting::{Config as RawConfig, Counter, ExtraConfig as RawExtraConfig},
+    event::{
+        CacheOp as RawCacheOp, CacheOpResult as RawCacheOpResult, Event as RawEv,
+        EventScope as RawEvScope, HardwareEvent as RawHwEv, RawEvent as RawRawEv,
+    },
+};
+use psh_system::cpu::{CpuHandle, CpuInfo};
+
+use crate::ext::profiling::perf_ext::analysis::TlbSummary;
+
+// DTLB_LOAD_MISSES.WALK_DURATION (WALK_PENDING since Skylake) and
+// ITLB_MISSES.WALK_DURATION, cycles a page walk was in progress, same
+// encoding from Haswell on
+const INTEL_DTLB_WALK_CYCLES: u64 = 0x1008;
+const INTEL_ITLB_WALK_CYCLES: u64 = 0x1085;
+
+// AMD and Arm count page walks but not the cycles spent in them
+pub(crate) fn is_intel() -> bool {
+    matches!(
+        CpuHandle::new().info(),
+        Ok(CpuInfo::X86_64(cpus)) if cpus.first().is_some_and(|it| it.vendor_id == "GenuineIntel")
+    )
+}
+
+/// One event counted on every thread of the target.
+struct Events(Vec<Counter>);
+
+impl Events {
+    /// `None` if the cpu or kernel doesn't support the event.
+    fn open(event: RawEv, tids: &[i32]) -> Option<Self> {
+        let extra = RawExtraConfig {
+            pinned: false,
+            exclusive: false,
+            // threads started while counting count as well
+            inherit: true,
+            inherit_stat: false,
+            inherit_thread: false,
+            enable_on_exec: false,
+            #[cfg(feature = "linux-5.13")]
+            remove_on_exec: false,
+        };
+        let mut cfg = RawConfig::extra_new(&event, &[RawEvScope::User, RawEvScope::Kernel], &extra);
+        // threads may exit before they are counted, only give up if none is left
+        let counters: Vec<_> = tids
+            .iter()
+            .filter_map(|&tid| Counter::new(&RawProcess::Pid(tid), &RawCpu::Any, &mut cfg).ok())
+            .collect();
+        (!counters.is_empty()).then_some(Self(counters))
+    }
+
+    fn enable(&self) -> io::Result<()> {
+        self.0.iter().try_for_each(Counter::enable)
+    }
+
+    fn disable(&self) -> io::Result<()> {
+        self.0.iter().try_for_each(Counter::disable)
+    }
+
+    /// Sum of the counts, scaled for time the counters were multiplexed out.
+    fn count(&mut self) -> io::Result<u64> {
+        let mut sum = 0;
+        for counter in &mut self.0 {
+            let stat = counter.stat()?;
+            if stat.time_running > 0 {
+                sum += (u128::from(stat.event_count) * u128::from(stat.time_enabled)
+                    / u128::from(stat.time_running)) as u64;
+            }
+        }
+        Ok(sum)
+    }
+}
+
+fn threads(pid: i32) -> io::Result<Vec<i32>> {
+    let mut tids = vec![];
+    for entry in fs::read_dir(format!("/proc/{pid}/task"))? {
+        if let Ok(tid) = entry?.file_name().to_string_lossy().parse() {
+            tids.push(tid);
+        }
+    }
+    Ok(tids)
+}
+
+/// `(Rss, AnonHugePages)` in bytes from `/proc/<pid>/smaps_rollup`.
+fn huge_page_usage(pid: i32) -> Option<(u64, u64)> {
+    let rollup = fs::read_to_string(format!("/proc/{pid}/smaps_rollup")).ok()?;
+    let field = |name: &str| {
+        rollup.lines().find_map(|line| {
+            let kib = line.strip_prefix(name)?.trim().strip_suffix("kB")?;
+            kib.trim().parse::<u64>().ok().map(|it| it * 1024)
+        })
+    };
+    Some((field("Rss:")?, field("AnonHugePages:")?))
+}
+
+fn ratio(part: Option<u64>, total: Option<u64>) -> Option<f64> {
+    match (part, total) {
+        (Some(part), Some(total)) if total > 0 => Some(part as f64 / total as f64),
+        _ => None,
+    }
+}
+
+/// Counts TLB misses and page walk cycles of every thread of `pid` for
+/// `duration`.
+pub fn pressure(pid: i32, duration: Duration) -> io::Result<TlbSummary> {
+    if pid <= 0 {
+        return Err(io::Error::other(format!("Invalid pid {pid}")));
+    }
+    let tids = threads(pid)?;
+    let intel = is_intel();
+    let cache = |ev: fn(RawCacheOp, RawCacheOpResult) -> RawHwEv, result: RawCacheOpResult| {
+        Events::open(RawEv::Hardware(ev(RawCacheOp::Read, result)), &tids)
+    };
+    // SAFETY: only given the `INTEL_*_WALK_CYCLES` encodings and only on
+    // Intel CPUs, so the PMU is never programmed with a foreign raw config
+    let raw = |config| Events::open(RawEv::Raw(unsafe { RawRawEv::new(config) }), &tids);
+
+    let events = [
+        Events::open(RawEv::Hardware(RawHwEv::CpuCycles), &tids),
+        Events::open(RawEv::Hardware(RawHwEv::Instructions), &tids),
+        cache(RawHwEv::CacheDtlb, RawCacheOpResult::Access),
+        cache(RawHwEv::CacheDtlb, RawCacheOpResult::Miss),
+        cache(RawHwEv::CacheItlb, RawCacheOpResult::Miss),
+        intel.then(|| raw(INTEL_DTLB_WALK_CYCLES)).flatten(),
+        intel.then(|| raw(INTEL_ITLB_WALK_CYCLES)).flatten(),
+    ];
+    if events[0].is_none() {
+        return Err(io::Error::other(format!(
+            "Failed to count cycles of process {pid}"
+        )));
+    }
+
+    events.iter().flatten().try_for_each(Events::enable)?;
+    thread::sleep(duration);
+    events.iter().flatten().try_for_each(Events::disable)?;
+    let [
+        cycles,
+        instructions,
+        dtlb_loads,
+        dtlb_misses,
+        itlb_misses,
+        dtlb_walk,
+        itlb_walk,
+    ] = events.map(|it| it.map(|mut it| it.count()).transpose());
+    let (cycles, instructions) = (cycles?.unwrap_or(0), instructions?);
+    let (dtlb_loads, dtlb_misses, itlb_misses) = (dtlb_loads?, dtlb_misses?, itlb_misses?);
+    let (dtlb_walk, itlb_walk) = (dtlb_walk?, itlb_walk?);
+
+    let misses = match (dtlb_misses, itlb_misses) {
+        (None, None) => None,
+        (d, i) => Some(d.unwrap_or(0) + i.unwrap_or(0)),
+    };
+    let walk_cycles = dtlb_walk.map(|d| d + itlb_walk.unwrap_or(0));
+    let usage = huge_page_usage(pid);
+
+    Ok(TlbSummary {
+        duration_ns: duration.as_nanos() as u64,
+        threads: tids.len() as u32,
+        cycles,
+        instructions,
+        dtlb_loads,
+        dtlb_load_misses: dtlb_misses,
+        itlb_misses,
+        dtlb_walk_cycles: dtlb_walk,
+        itlb_walk_cycles: itlb_walk,
+        misses_per_kilo_instructions: ratio(misses.map(|it| it * 1000), instructions),
+        dtlb_miss_ratio: ratio(dtlb_misses, dtlb_loads),
+        walk_cycle_ratio: ratio(walk_cycles, Some(cycles)),
+        rss_bytes: usage.map(|it| it.0),
+        anon_huge_page_bytes: usage.map(|it| it.1),
+    })
+}
@@ -0,0 +1,238 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{collections::HashMap, time::Duration};
+
+use psh_system::{
+    cpu::CpuInfo,
+    ftrace::{ThreadState, Timeline},
+};
+
+use crate::{SysCtx, ext::profiling::system_ext::bulk};
+
+const MAGIC: &[u8; 4] = b"PSHB";
+const VERSION: u16 = 1;
+const KIND_PROCESS_TABLE: u16 = 1;
+const KIND_CPUINFO: u16 = 2;
+const KIND_THREAD_TIMELINE: u16 = 3;
+const PROCESS_RECORD_LEN: usize = 120;
+const CPUINFO_RECORD_LEN: usize = 40;
+const INTERVAL_RECORD_LEN: usize = 40;
+
+/// Builds the packed layout documented in `wit/system-ext/bulk.wit`.
+struct Packer {
+    records: Vec<u8>,
+    strings: Vec<u8>,
+    /// offsets of the strings in the pool
+    interned: HashMap<String, u32>,
+    record_len: usize,
+    count: u32,
+}
+
+impl Packer {
+    fn new(record_len: usize, capacity: usize) -> Self {
+        Self {
+            records: Vec::with_capacity(record_len * capacity),
+            strings: Vec::new(),
+            interned: HashMap::new(),
+            record_len,
+            count: 0,
+        }
+    }
+
+    fn record(&mut self) -> Record<'_> {
+        self.count += 1;
+        let start = self.records.len();
+        self.records.resize(start + self.record_len, 0);
+        Record {
+            buf: &mut self.records[start..],
+            strings: &mut self.strings,
+            interned: &mut self.interned,
+        }
+    }
+
+    fn finish(self, kind: u16) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16 + self.records.len() + self.strings.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&kind.to_le_bytes());
+        bytes.extend_from_slice(&self.count.to_le_bytes());
+        bytes.extend_from_slice(&(self.record_len as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.records);
+        bytes.extend_from_slice(&self.strings);
+        bytes
+    }
+}
+
+struct Record<'a> {
+    buf: &'a mut [u8],
+    strings: &'a mut Vec<u8>,
+    interned: &'a mut HashMap<String, u32>,
+}
+
+impl Record<'_> {
+    fn put(&mut self, offset: usize, bytes: &[u8]) -> &mut Self {
+        self.buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+        self
+    }
+
+    fn str(&mut self, offset: usize, s: &str) -> &mut Self {
+        let start = match self.interned.get(s) {
+            Some(&start) => start,
+            None => {
+                let start = self.strings.len() as u32;
+                self.strings.extend_from_slice(s.as_bytes());
+                self.interned.insert(s.to_string(), start);
+                start
+            }
+        };
+        self.put(offset, &start.to_le_bytes())
+            .put(offset + 4, &(s.len() as u32).to_le_bytes())
+    }
+}
+
+impl bulk::Host for SysCtx {
+    fn process_table(&mut self, interval_ms: u64) -> Result<Vec<u8>, String> {
+        self.rate_limit("bulk")?;
+        let entries = self
+            .process
+            .table(Some(Duration::from_millis(interval_ms)))
+            .map_err(|err| err.to_string())?;
+        let ms = |ticks: u64| ticks * 1000 / self.system.tick_per_sec;
+        let signed_ms = |ticks: i64| ticks * 1000 / self.system.tick_per_sec as i64;
+        let page_size = self.system.page_size;
+
+        let mut packer = Packer::new(PROCESS_RECORD_LEN, entries.len());
+        for entry in &entries {
+            let (stat, io, statm) = (&entry.stat, &entry.io, &entry.statm);
+            packer
+                .record()
+                .put(0, &entry.process.pid.to_le_bytes())
+                .put(4, &stat.ppid.to_le_bytes())
+                .put(8, &[stat.state as u8])
+                .str(12, &stat.comm)
+                .put(24, &stat.num_threads.to_le_bytes())
+                .put(32, &stat.priority.to_le_bytes())
+                .put(40, &stat.nice.to_le_bytes())
+                .put(48, &ms(stat.utime).to_le_bytes())
+                .put(56, &ms(stat.stime).to_le_bytes())
+                .put(64, &signed_ms(stat.cutime).to_le_bytes())
+                .put(72, &signed_ms(stat.cstime).to_le_bytes())
+                .put(80, &ms(stat.starttime).to_le_bytes())
+                .put(88, &io.read_bytes.to_le_bytes())
+                .put(96, &io.write_bytes.to_le_bytes())
+                .put(104, &(statm.resident * page_size).to_le_bytes())
+                .put(112, &(statm.size * page_size).to_le_bytes());
+        }
+        Ok(packer.finish(KIND_PROCESS_TABLE))
+    }
+
+    fn cpuinfo(&mut self) -> Result<Vec<u8>, String> {
+        self.rate_limit("bulk")?;
+        let info = self.cpu.info().map_err(|err| err.to_string())?;
+        let packer = match &info {
+            CpuInfo::X86_64(cpus) => {
+                let mut packer = Packer::new(CPUINFO_RECORD_LEN, cpus.len());
+                for cpu in cpus {
+                    packer
+                        .record()
+                        .put(0, &(cpu.processor as u32).to_le_bytes())
+                        .put(4, &(cpu.physical_id as u32).to_le_bytes())
+                        .put(8, &(cpu.core_id as u32).to_le_bytes())
+                        .put(16, &cpu.cpu_mhz.to_le_bytes())
+                        .str(24, &cpu.model_name)
+                        .str(32, &cpu.flags.join(" "));
+                }
+                packer
+            }
+            CpuInfo::Arm64(cpus) => {
+                let mut packer = Packer::new(CPUINFO_RECORD_LEN, cpus.len());
+                for cpu in cpus {
+                    // arm64 cpuinfo has no topology, every cpu is its own core
+                    packer
+                        .record()
+                        .put(0, &(cpu.processor as u32).to_le_bytes())
+                        .put(8, &(cpu.processor as u32).to_le_bytes())
+                        .str(24, "")
+                        .str(32, &cpu.features.join(" "));
+                }
+                packer
+            }
+            CpuInfo::RiscV64(cpus) => {
+                let mut packer = Packer::new(CPUINFO_RECORD_LEN, cpus.len());
+                for cpu in cpus {
+                    // like arm64, one core per hart
+                    packer
+                        .record()
+                        .put(0, &(cpu.processor as u32).to_le_bytes())
+                        .put(8, &(cpu.hart as u32).to_le_bytes())
+                        .str(24, &cpu.uarch)
+                        .str(32, &cpu.extensions.join(" "));
+                }
+                packer
+            }
+            CpuInfo::S390x(cpus) => {
+                let mut packer = Packer::new(CPUINFO_RECORD_LEN, cpus.len());
+                for cpu in cpus {
+                    packer
+                        .record()
+                        .put(0, &(cpu.processor as u32).to_le_bytes())
+                        .put(4, &(cpu.physical_id as u32).to_le_bytes())
+                        .put(8, &(cpu.core_id as u32).to_le_bytes())
+                        .put(16, &f64::from(cpu.cpu_mhz_dynamic).to_le_bytes())
+                        .str(24, &cpu.machine)
+                        .str(32, &cpu.features.join(" "));
+                }
+                packer
+            }
+            CpuInfo::Unsupported(reason) => return Err(reason.clone()),
+        };
+        Ok(packer.finish(KIND_CPUINFO))
+    }
+
+    fn thread_timeline(&mut self, pid: u32, window_ms: u64) -> Result<bulk::Timeline, String> {
+        self.rate_limit("bulk")?;
+        let timeline = Timeline::collect(pid, Duration::from_millis(window_ms))
+            .map_err(|err| err.to_string())?;
+        let intervals = timeline.intervals();
+        let mut packer = Packer::new(INTERVAL_RECORD_LEN, intervals.len());
+        for interval in intervals {
+            let state: u8 = match interval.state {
+                ThreadState::Running => 0,
+                ThreadState::Preempted => 1,
+                ThreadState::Woken => 2,
+                ThreadState::Sleeping => 3,
+                ThreadState::Blocked => 4,
+                ThreadState::Other => 5,
+            };
+            let name = timeline
+                .names()
+                .get(&interval.tid)
+                .map_or("", String::as_str);
+            packer
+                .record()
+                .put(0, &interval.tid.to_le_bytes())
+                .put(4, &[state])
+                .put(8, &interval.start_ns.to_le_bytes())
+                .put(16, &interval.end_ns.to_le_bytes())
+                .put(24, &interval.cpu.to_le_bytes())
+                .put(28, &interval.waker.to_le_bytes())
+                .str(32, name);
+        }
+        Ok(bulk::Timeline {
+            intervals: packer.finish(KIND_THREAD_TIMELINE),
+            truncated: timeline.truncated(),
+        })
+    }
+}
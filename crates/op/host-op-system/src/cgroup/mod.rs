@@ -0,0 +1,152 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use psh_system::cgroup::{
+    CgroupIo, IoDevice as HostIoDevice, ThrottleDelta as HostThrottleDelta, ThrottleSnapshot,
+    ThrottleWatcher,
+};
+use wasmtime::component::Resource;
+
+use crate::{
+    SysCtx,
+    ext::profiling::system_ext::cgroup::{
+        self, CpuThrottling, HostThrottleWatch, IoDevice as GuestIoDevice, IoStat,
+        ThrottleDelta as GuestThrottleDelta,
+    },
+};
+
+// every poll walks the whole hierarchy
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct ThrottleWatch {
+    watcher: ThrottleWatcher,
+    interval: Duration,
+}
+
+impl From<HostThrottleDelta> for GuestThrottleDelta {
+    fn from(value: HostThrottleDelta) -> Self {
+        Self {
+            cgroup: value.cgroup,
+            interval_ms: value.interval.as_millis() as u64,
+            periods: value.periods,
+            throttled_periods: value.throttled_periods,
+            throttled_usec: value.throttled_usec,
+            throttled_ratio: value.throttled_ratio,
+        }
+    }
+}
+
+impl From<HostIoDevice> for GuestIoDevice {
+    fn from(value: HostIoDevice) -> Self {
+        Self {
+            device: value.device,
+            name: value.name,
+            rbytes: value.rbytes,
+            wbytes: value.wbytes,
+            rios: value.rios,
+            wios: value.wios,
+            dbytes: value.dbytes,
+            dios: value.dios,
+            avg_lat_usec: value.avg_lat_usec,
+            rbps: value.rbps,
+            wbps: value.wbps,
+            riops: value.riops,
+            wiops: value.wiops,
+            latency_target_usec: value.latency_target_usec,
+        }
+    }
+}
+
+impl From<CgroupIo> for IoStat {
+    fn from(value: CgroupIo) -> Self {
+        Self {
+            cgroup: value.cgroup,
+            devices: value.devices.into_iter().map(Into::into).collect(),
+            pressure_some_usec: value.pressure_some_usec,
+            pressure_full_usec: value.pressure_full_usec,
+        }
+    }
+}
+
+impl HostThrottleWatch for SysCtx {
+    fn next(
+        &mut self,
+        self_: Resource<ThrottleWatch>,
+        timeout_ms: u64,
+    ) -> Result<Vec<GuestThrottleDelta>, String> {
+        let watch = self.table.get_mut(&self_).map_err(|err| err.to_string())?;
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+        loop {
+            thread::sleep(watch.interval);
+            let newly = watch.watcher.poll().map_err(|err| err.to_string())?;
+            if !newly.is_empty() || Instant::now() >= deadline {
+                return Ok(newly.into_iter().map(Into::into).collect());
+            }
+        }
+    }
+
+    fn drop(&mut self, rep: Resource<ThrottleWatch>) -> wasmtime::Result<()> {
+        self.table.delete(rep)?;
+        Ok(())
+    }
+}
+
+impl cgroup::Host for SysCtx {
+    fn cpu_throttling(&mut self) -> Result<Vec<CpuThrottling>, String> {
+        self.rate_limit("cgroup")?;
+        let snapshot = ThrottleSnapshot::capture().map_err(|err| err.to_string())?;
+        Ok(snapshot
+            .stats
+            .into_iter()
+            .map(|(cgroup, stat)| CpuThrottling {
+                cgroup,
+                nr_periods: stat.nr_periods,
+                nr_throttled: stat.nr_throttled,
+                throttled_usec: stat.throttled_usec,
+            })
+            .collect())
+    }
+
+    fn throttle_delta(&mut self, interval_ms: u64) -> Result<Vec<GuestThrottleDelta>, String> {
+        self.rate_limit("cgroup")?;
+        let before = ThrottleSnapshot::capture().map_err(|err| err.to_string())?;
+        thread::sleep(Duration::from_millis(interval_ms));
+        let after = ThrottleSnapshot::capture().map_err(|err| err.to_string())?;
+        Ok(HostThrottleDelta::between(&before, &after)
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    fn watch_throttling(&mut self, interval_ms: u64) -> Result<Resource<ThrottleWatch>, String> {
+        self.rate_limit("cgroup")?;
+        let watch = ThrottleWatch {
+            watcher: ThrottleWatcher::new().map_err(|err| err.to_string())?,
+            interval: Duration::from_millis(interval_ms).max(MIN_INTERVAL),
+        };
+        self.table.push(watch).map_err(|err| err.to_string())
+    }
+
+    fn io_stats(&mut self) -> Result<Vec<IoStat>, String> {
+        self.rate_limit("cgroup")?;
+        CgroupIo::capture()
+            .map(|cgroups| cgroups.into_iter().map(Into::into).collect())
+            .map_err(|err| err.to_string())
+    }
+}
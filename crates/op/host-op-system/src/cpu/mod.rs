@@ -15,13 +15,22 @@
 use std::time::Duration;
 
 use psh_system::cpu::{
-    AddressSizes as HostAddressSizes, Arm64CpuInfo as HostArm64CpuInfo, CpuInfo as HostCpuInfo,
-    CpuMask as HostCpuMask, CpuStats as HostCpuStats, CpuTime as HostCpuStat,
-    TlbSize as HostTlbSize, X86_64CpuInfo as HostX86_64CpuInfo,
+    AddressSizes as HostAddressSizes, Arm64CpuInfo as HostArm64CpuInfo, CacheKind as HostCacheKind,
+    CpuCache as HostCpuCache, CpuInfo as HostCpuInfo, CpuMask as HostCpuMask,
+    CpuPlacement as HostCpuPlacement, CpuStats as HostCpuStats, CpuTime as HostCpuStat,
+    CpuTopology as HostCpuTopology, RiscV64CpuInfo as HostRiscV64CpuInfo,
+    S390xCpuInfo as HostS390xCpuInfo, TlbSize as HostTlbSize, Vulnerability as HostVulnerability,
+    VulnerabilityStatus as HostVulnerabilityStatus, X86_64CpuInfo as HostX86_64CpuInfo,
 };
 
 use crate::{
     SysCtx,
+    ext::profiling::system_ext::cpu::{
+        self as cpu_ext, Cache as GuestCache, CacheKind as GuestCacheKind, CpuInfo as ExtCpuInfo,
+        CpuPlacement as GuestCpuPlacement, CpuTopology as GuestCpuTopology,
+        Riscv64CpuInfo as GuestRiscV64CpuInfo, S390xCpuInfo as GuestS390xCpuInfo,
+        Vulnerability as GuestVulnerability, VulnerabilityStatus as GuestVulnerabilityStatus,
+    },
     profiling::system::cpu::{
         self, AddressSizes as GuestAddressSizes, Arm64CpuInfo as GuestArm64CpuInfo,
         CpuInfo as GuestCpuInfo, CpuMask as GuestCpuMask, CpuStat as GuestCpuStat,
@@ -184,6 +193,9 @@ impl From<&HostCpuInfo> for GuestCpuInfo {
         match value {
             HostCpuInfo::X86_64(x64) => Self::X64(x64.iter().map(Into::into).collect()),
             HostCpuInfo::Arm64(arm64) => Self::Arm64(arm64.iter().map(Into::into).collect()),
+            // see `profiling:system-ext/cpu`
+            HostCpuInfo::RiscV64(_) => Self::Unsupported("riscv64".to_string()),
+            HostCpuInfo::S390x(_) => Self::Unsupported("s390x".to_string()),
             HostCpuInfo::Unsupported(unsupported) => Self::Unsupported(unsupported.clone()),
         }
     }
@@ -194,11 +206,61 @@ impl From<HostCpuInfo> for GuestCpuInfo {
         match value {
             HostCpuInfo::X86_64(x64) => Self::X64(x64.into_iter().map(Into::into).collect()),
             HostCpuInfo::Arm64(arm64) => Self::Arm64(arm64.into_iter().map(Into::into).collect()),
+            HostCpuInfo::RiscV64(_) => Self::Unsupported("riscv64".to_string()),
+            HostCpuInfo::S390x(_) => Self::Unsupported("s390x".to_string()),
             HostCpuInfo::Unsupported(unsupported) => Self::Unsupported(unsupported),
         }
     }
 }
 
+impl From<HostRiscV64CpuInfo> for GuestRiscV64CpuInfo {
+    fn from(value: HostRiscV64CpuInfo) -> Self {
+        Self {
+            processor: value.processor as u32,
+            hart: value.hart as u32,
+            isa: value.isa,
+            extensions: value.extensions,
+            mmu: value.mmu,
+            uarch: value.uarch,
+            mvendorid: value.mvendorid,
+            marchid: value.marchid,
+            mimpid: value.mimpid,
+        }
+    }
+}
+
+impl From<HostS390xCpuInfo> for GuestS390xCpuInfo {
+    fn from(value: HostS390xCpuInfo) -> Self {
+        Self {
+            processor: value.processor as u32,
+            vendor_id: value.vendor_id,
+            bogomips: value.bogomips,
+            features: value.features,
+            physical_id: value.physical_id as u32,
+            core_id: value.core_id as u32,
+            book_id: value.book_id as u32,
+            drawer_id: value.drawer_id as u32,
+            version: value.version,
+            identification: value.identification,
+            machine: value.machine,
+            cpu_mhz_dynamic: value.cpu_mhz_dynamic,
+            cpu_mhz_static: value.cpu_mhz_static,
+        }
+    }
+}
+
+impl From<HostCpuInfo> for ExtCpuInfo {
+    fn from(value: HostCpuInfo) -> Self {
+        match value {
+            HostCpuInfo::RiscV64(cpus) => Self::Riscv64(cpus.into_iter().map(Into::into).collect()),
+            HostCpuInfo::S390x(cpus) => Self::S390x(cpus.into_iter().map(Into::into).collect()),
+            HostCpuInfo::X86_64(_) => Self::Other("x86_64".to_string()),
+            HostCpuInfo::Arm64(_) => Self::Other("aarch64".to_string()),
+            HostCpuInfo::Unsupported(unsupported) => Self::Other(unsupported),
+        }
+    }
+}
+
 impl From<&HostCpuStat> for GuestCpuStat {
     fn from(value: &HostCpuStat) -> Self {
         Self {
@@ -255,6 +317,7 @@ impl From<HostCpuStats> for GuestCpuStats {
 
 impl cpu::Host for SysCtx {
     fn info(&mut self) -> Result<GuestCpuInfo, String> {
+        self.rate_limit("cpu")?;
         self.cpu
             .info()
             .map(Into::into)
@@ -262,9 +325,91 @@ impl cpu::Host for SysCtx {
     }
 
     fn stat(&mut self, interval_ms: u64) -> Result<GuestCpuStats, String> {
+        self.rate_limit("cpu")?;
         self.cpu
             .stat(Some(Duration::from_millis(interval_ms)))
             .map(Into::into)
             .map_err(|err| err.to_string())
     }
 }
+
+impl From<HostCpuPlacement> for GuestCpuPlacement {
+    fn from(value: HostCpuPlacement) -> Self {
+        Self {
+            cpu: value.cpu,
+            socket: value.socket,
+            die: value.die,
+            core: value.core,
+            node: value.node,
+            thread_siblings: value.thread_siblings,
+        }
+    }
+}
+
+impl From<HostCpuCache> for GuestCache {
+    fn from(value: HostCpuCache) -> Self {
+        Self {
+            level: value.level,
+            kind: match value.kind {
+                HostCacheKind::Data => GuestCacheKind::Data,
+                HostCacheKind::Instruction => GuestCacheKind::Instruction,
+                HostCacheKind::Unified => GuestCacheKind::Unified,
+            },
+            size: value.size,
+            line_size: value.line_size,
+            ways: value.ways,
+            shared_cpus: value.shared_cpus,
+        }
+    }
+}
+
+impl From<HostCpuTopology> for GuestCpuTopology {
+    fn from(value: HostCpuTopology) -> Self {
+        Self {
+            caches: value.caches().into_iter().map(Into::into).collect(),
+            cpus: value.0.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<HostVulnerability> for GuestVulnerability {
+    fn from(value: HostVulnerability) -> Self {
+        Self {
+            name: value.name,
+            status: match value.status {
+                HostVulnerabilityStatus::NotAffected => GuestVulnerabilityStatus::NotAffected,
+                HostVulnerabilityStatus::Mitigated => GuestVulnerabilityStatus::Mitigated,
+                HostVulnerabilityStatus::Vulnerable => GuestVulnerabilityStatus::Vulnerable,
+                HostVulnerabilityStatus::Unknown => GuestVulnerabilityStatus::Unknown,
+            },
+            mitigation: value.mitigation,
+            detail: value.detail,
+        }
+    }
+}
+
+impl cpu_ext::Host for SysCtx {
+    fn info(&mut self) -> Result<ExtCpuInfo, String> {
+        self.rate_limit("cpu")?;
+        self.cpu
+            .info()
+            .map(Into::into)
+            .map_err(|err| err.to_string())
+    }
+
+    fn topology(&mut self) -> Result<GuestCpuTopology, String> {
+        self.rate_limit("cpu")?;
+        self.cpu
+            .topology(None)
+            .map(Into::into)
+            .map_err(|err| err.to_string())
+    }
+
+    fn vulnerabilities(&mut self) -> Result<Vec<GuestVulnerability>, String> {
+        self.rate_limit("cpu")?;
+        self.cpu
+            .vulnerabilities()
+            .map(|it| it.into_iter().map(Into::into).collect())
+            .map_err(|err| err.to_string())
+    }
+}
@@ -111,6 +111,7 @@ impl From<HostDiskStat> for GuestDiskStat {
 
 impl disk::Host for SysCtx {
     fn stat(&mut self, interval_ms: u64) -> Result<Vec<GuestDiskStat>, String> {
+        self.rate_limit("disk")?;
         self.disk
             .stat(Some(Duration::from_millis(interval_ms)))
             .map(|disks| disks.into_iter().map(Into::into).collect())
@@ -0,0 +1,110 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::sync::OnceLock;
+
+use anyhow::{Result, bail};
+
+static GLOBAL: OnceLock<EnvironPolicy> = OnceLock::new();
+
+/// Lets components read process environments, filtered by `policy`.
+pub fn init(policy: EnvironPolicy) -> Result<()> {
+    if GLOBAL.set(policy).is_err() {
+        bail!("Environ policy already initialized");
+    }
+    Ok(())
+}
+
+pub fn global() -> Option<&'static EnvironPolicy> {
+    GLOBAL.get()
+}
+
+/// Which variables of process environments components may see.
+///
+/// Names are matched exactly, or by prefix if they end with `*`, e.g.
+/// `LC_*`. A variable is visible if it matches `allow`, or `allow` is
+/// empty, and does not match `deny`.
+#[derive(Debug)]
+pub struct EnvironPolicy {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+fn matches(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|it| match it.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == it,
+    })
+}
+
+impl EnvironPolicy {
+    pub fn new(allow: &[String], deny: &[String]) -> Self {
+        Self {
+            allow: allow.to_vec(),
+            deny: deny.to_vec(),
+        }
+    }
+
+    pub fn permits(&self, name: &str) -> bool {
+        (self.allow.is_empty() || matches(&self.allow, name)) && !matches(&self.deny, name)
+    }
+
+    pub fn filter(&self, vars: Vec<(String, String)>) -> Vec<(String, String)> {
+        vars.into_iter()
+            .filter(|(name, _)| self.permits(name))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EnvironPolicy;
+
+    fn names(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_default_permits_all() {
+        let policy = EnvironPolicy::new(&[], &[]);
+        assert!(policy.permits("PATH"));
+        assert!(policy.permits("AWS_SECRET_ACCESS_KEY"));
+    }
+
+    #[test]
+    fn test_allow() {
+        let policy = EnvironPolicy::new(&names(&["PATH", "LC_*"]), &[]);
+        assert!(policy.permits("PATH"));
+        assert!(policy.permits("LC_ALL"));
+        assert!(!policy.permits("PATHEXT"));
+        assert!(!policy.permits("HOME"));
+    }
+
+    #[test]
+    fn test_deny() {
+        let policy = EnvironPolicy::new(&names(&["LC_*", "AWS_*"]), &names(&["AWS_SECRET*"]));
+        assert!(policy.permits("AWS_REGION"));
+        assert!(!policy.permits("AWS_SECRET_ACCESS_KEY"));
+
+        let policy = EnvironPolicy::new(&[], &names(&["TOKEN"]));
+        let vars = vec![
+            ("TOKEN".to_string(), "abc".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ];
+        assert_eq!(
+            policy.filter(vars),
+            [("HOME".to_string(), "/root".to_string())]
+        );
+    }
+}
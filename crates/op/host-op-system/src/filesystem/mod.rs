@@ -0,0 +1,78 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use psh_system::filesystem::{
+    LocalMountStats, NfsMountStats, NfsOpStats, local_mount_stats, nfs_mount_stats,
+};
+
+use crate::{
+    SysCtx,
+    ext::profiling::system_ext::filesystem::{self, LocalMount, NfsMount, NfsOp},
+};
+
+impl From<NfsOpStats> for NfsOp {
+    fn from(value: NfsOpStats) -> Self {
+        Self {
+            name: value.name,
+            ops: value.ops,
+            transmissions: value.transmissions,
+            major_timeouts: value.major_timeouts,
+            bytes_sent: value.bytes_sent,
+            bytes_received: value.bytes_received,
+            queue_ms: value.queue_ms,
+            rtt_ms: value.rtt_ms,
+            execute_ms: value.execute_ms,
+            errors: value.errors,
+        }
+    }
+}
+
+impl From<NfsMountStats> for NfsMount {
+    fn from(value: NfsMountStats) -> Self {
+        Self {
+            device: value.device,
+            mount_point: value.mount_point,
+            fstype: value.fstype,
+            age_secs: value.age,
+            ops: value.ops.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<LocalMountStats> for LocalMount {
+    fn from(value: LocalMountStats) -> Self {
+        Self {
+            device: value.device,
+            mount_point: value.mount_point,
+            fstype: value.fstype,
+            counters: value.counters.into_iter().collect(),
+        }
+    }
+}
+
+impl filesystem::Host for SysCtx {
+    fn nfs_mounts(&mut self) -> Result<Vec<NfsMount>, String> {
+        self.rate_limit("filesystem")?;
+        nfs_mount_stats()
+            .map(|it| it.into_iter().map(Into::into).collect())
+            .map_err(|err| err.to_string())
+    }
+
+    fn local_mounts(&mut self) -> Result<Vec<LocalMount>, String> {
+        self.rate_limit("filesystem")?;
+        local_mount_stats()
+            .map(|it| it.into_iter().map(Into::into).collect())
+            .map_err(|err| err.to_string())
+    }
+}
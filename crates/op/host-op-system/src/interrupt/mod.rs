@@ -14,9 +14,11 @@
 
 use std::time::Duration;
 
-use psh_system::interrupt::{InterruptDetails, InterruptType, IrqDetails};
+use psh_system::interrupt::{InterruptDetails, InterruptType, IrqDetails, IrqDevice};
 
-use crate::{SysCtx, profiling::system::interrupt};
+use crate::{
+    SysCtx, ext::profiling::system_ext::interrupt as interrupt_ext, profiling::system::interrupt,
+};
 
 impl From<&InterruptType> for interrupt::InterruptType {
     fn from(value: &InterruptType) -> Self {
@@ -80,6 +82,7 @@ impl From<InterruptDetails> for interrupt::InterruptStat {
 
 impl interrupt::Host for SysCtx {
     fn info(&mut self) -> Result<Vec<interrupt::InterruptInfo>, String> {
+        self.rate_limit("interrupt")?;
         self.interrupt
             .info()
             .map(|ints| ints.into_iter().map(Into::into).collect())
@@ -87,9 +90,35 @@ impl interrupt::Host for SysCtx {
     }
 
     fn stat(&mut self, interval_ms: u64) -> Result<Vec<interrupt::InterruptStat>, String> {
+        self.rate_limit("interrupt")?;
         self.interrupt
             .stat(Some(Duration::from_millis(interval_ms)))
             .map(|stats| stats.into_iter().map(Into::into).collect())
             .map_err(|err| err.to_string())
     }
 }
+
+impl From<IrqDevice> for interrupt_ext::IrqDevice {
+    fn from(value: IrqDevice) -> Self {
+        Self {
+            number: value.irq_number,
+            actions: value.actions,
+            chip_name: value.chip_name,
+            hwirq: value.hwirq,
+            pci_device: value.pci_device,
+            driver: value.driver,
+            net_devices: value.net_devices,
+            queue: value.queue,
+        }
+    }
+}
+
+impl interrupt_ext::Host for SysCtx {
+    fn devices(&mut self) -> Result<Vec<interrupt_ext::IrqDevice>, String> {
+        self.rate_limit("interrupt")?;
+        self.interrupt
+            .devices()
+            .map(|devices| devices.into_iter().map(Into::into).collect())
+            .map_err(|err| err.to_string())
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::time::Duration;
+
+use psh_system::ftrace::{
+    BlockLatency, GroupBy as HostGroupBy, Log2Histogram, RunQueueLatency, Syscalls,
+};
+
+use crate::{
+    SysCtx,
+    ext::profiling::system_ext::latency::{
+        self, DeviceLatency, GroupBy as GuestGroupBy, Histogram, ProcessSyscalls, SyscallLatency,
+    },
+};
+
+pub(crate) fn histogram(key: String, value: Log2Histogram) -> Histogram {
+    Histogram {
+        key,
+        count: value.count,
+        sum_us: value.sum,
+        max_us: value.max,
+        buckets: value.buckets,
+    }
+}
+
+impl latency::Host for SysCtx {
+    fn run_queue(
+        &mut self,
+        window_ms: u64,
+        group_by: GuestGroupBy,
+    ) -> Result<Vec<Histogram>, String> {
+        self.rate_limit("latency")?;
+        let group_by = match group_by {
+            GuestGroupBy::Cpu => HostGroupBy::Cpu,
+            GuestGroupBy::Cgroup => HostGroupBy::Cgroup,
+        };
+        RunQueueLatency::collect(Duration::from_millis(window_ms), group_by)
+            .map(|it| it.into_iter().map(|(key, it)| histogram(key, it)).collect())
+            .map_err(|err| err.to_string())
+    }
+
+    fn block_io(&mut self, window_ms: u64, outlier_us: u64) -> Result<Vec<DeviceLatency>, String> {
+        self.rate_limit("latency")?;
+        BlockLatency::collect(Duration::from_millis(window_ms), outlier_us)
+            .map(|it| {
+                it.into_iter()
+                    .map(|(device, it)| DeviceLatency {
+                        histogram: histogram(device, it.histogram),
+                        outliers: it.outliers,
+                    })
+                    .collect()
+            })
+            .map_err(|err| err.to_string())
+    }
+
+    fn syscalls(
+        &mut self,
+        window_ms: u64,
+        max_processes: u32,
+        max_syscalls: u32,
+    ) -> Result<Vec<ProcessSyscalls>, String> {
+        self.rate_limit("latency")?;
+        Syscalls::collect(
+            Duration::from_millis(window_ms),
+            max_processes as usize,
+            max_syscalls as usize,
+        )
+        .map(|it| {
+            it.into_iter()
+                .map(|it| ProcessSyscalls {
+                    pid: it.pid,
+                    comm: it.comm,
+                    total: histogram(it.pid.to_string(), it.total.latency),
+                    errors: it.total.errors,
+                    syscalls: it
+                        .syscalls
+                        .into_iter()
+                        .map(|(nr, it)| SyscallLatency {
+                            nr,
+                            histogram: histogram(nr.to_string(), it.latency),
+                            errors: it.errors,
+                        })
+                        .collect(),
+                })
+                .collect()
+        })
+        .map_err(|err| err.to_string())
+    }
+}
@@ -12,14 +12,21 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
+mod bulk;
+mod cgroup;
 mod cpu;
 mod disk;
+pub mod environ;
+mod filesystem;
 mod interrupt;
+mod latency;
 mod memory;
 mod network;
 mod os;
 mod process;
+pub mod redact;
 mod rps;
+mod tcp;
 mod vmstat;
 
 use std::sync::Arc;
@@ -33,6 +40,7 @@ use psh_system::{
     network::NetworkHandle,
     os::OsHandle,
     process::{Process, ProcessHandle},
+    rate_limit::RateLimiter,
     rps::RpsHandle,
     vmstat::VmstatHandle,
 };
@@ -64,6 +72,17 @@ wasmtime::component::bindgen!({
     ],
 });
 
+/// Host interfaces not yet published in psh-sdk-wit, see `wit/system-ext`.
+pub mod ext {
+    wasmtime::component::bindgen!({
+        path: "../../../wit/system-ext",
+        world: "imports",
+        with: {
+            "profiling:system-ext/cgroup/throttle-watch": crate::cgroup::ThrottleWatch,
+        },
+    });
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Default)]
 pub struct SysCtx {
@@ -78,11 +97,42 @@ pub struct SysCtx {
     network: NetworkHandle,
     interrupt: InterruptHandle,
     vmstat: VmstatHandle,
+    allow_environ: bool,
+    rate_limiter: RateLimiter,
+}
+
+impl SysCtx {
+    /// Process environments also need [`environ::init`].
+    pub const fn allow_environ(mut self, enable: bool) -> Self {
+        self.allow_environ = enable;
+        self
+    }
+
+    /// Calls per second by interface, e.g. `process` or `cpu`.
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = limiter;
+        self
+    }
+
+    /// Calls made so far by interface.
+    pub fn host_calls(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.rate_limiter
+            .calls()
+            .iter()
+            .map(|(k, v)| (k.as_str(), *v))
+    }
+
+    fn rate_limit(&mut self, interface: &str) -> Result<(), String> {
+        self.rate_limiter
+            .check(interface)
+            .map_err(|err| err.to_string())
+    }
 }
 
 pub fn add_to_linker<T>(
     l: &mut Linker<T>,
     f: impl (Fn(&mut T) -> &mut SysCtx) + Copy + Send + Sync + 'static,
 ) -> anyhow::Result<()> {
-    crate::Imports::add_to_linker(l, f)
+    crate::Imports::add_to_linker(l, f)?;
+    ext::Imports::add_to_linker(l, f)
 }
@@ -14,10 +14,17 @@
 
 use std::time::Duration;
 
-use psh_system::memory::{Meminfo as HostMemoryStat, MemoryModule as HostMemoryInfo};
+use psh_system::memory::{
+    Meminfo as HostMemoryStat, MemoryDelta as HostMemoryDelta, MemoryModule as HostMemoryInfo,
+    ProcessSwap as HostProcessSwap, SwapDevice as HostSwapDevice, SwapRate as HostSwapRate,
+};
 
 use crate::{
     SysCtx,
+    ext::profiling::system_ext::memory::{
+        self as ext_memory, MemoryDelta as GuestMemoryDelta, ProcessSwap as GuestProcessSwap,
+        SwapDevice as GuestSwapDevice, SwapRate as GuestSwapRate,
+    },
     profiling::system::memory::{
         self, MemoryInfo as GuestMemoryInfo, MemoryStat as GuestMemoryStat,
     },
@@ -172,8 +179,65 @@ impl From<HostMemoryInfo> for GuestMemoryInfo {
     }
 }
 
+impl From<HostMemoryDelta> for GuestMemoryDelta {
+    fn from(value: HostMemoryDelta) -> Self {
+        Self {
+            interval_ms: value.interval.as_millis() as u64,
+            page_in_bytes_per_sec: value.page_in_bytes_per_sec,
+            page_out_bytes_per_sec: value.page_out_bytes_per_sec,
+            swap_in_bytes_per_sec: value.swap_in_bytes_per_sec,
+            swap_out_bytes_per_sec: value.swap_out_bytes_per_sec,
+            page_faults_per_sec: value.page_faults_per_sec,
+            major_faults_per_sec: value.major_faults_per_sec,
+            kswapd_scan_per_sec: value.kswapd_scan_per_sec,
+            direct_scan_per_sec: value.direct_scan_per_sec,
+            kswapd_steal_per_sec: value.kswapd_steal_per_sec,
+            direct_steal_per_sec: value.direct_steal_per_sec,
+            reclaim_efficiency: value.reclaim_efficiency,
+            dirty_growth: value.dirty_growth,
+            writeback_growth: value.writeback_growth,
+            mem_available_change: value.mem_available_change,
+        }
+    }
+}
+
+impl From<HostSwapDevice> for GuestSwapDevice {
+    fn from(value: HostSwapDevice) -> Self {
+        Self {
+            path: value.path,
+            kind: value.kind,
+            size_bytes: value.size_bytes,
+            used_bytes: value.used_bytes,
+            priority: value.priority,
+        }
+    }
+}
+
+impl From<HostProcessSwap> for GuestProcessSwap {
+    fn from(value: HostProcessSwap) -> Self {
+        Self {
+            pid: value.pid,
+            name: value.name,
+            swap_bytes: value.swap_bytes,
+            swap_pss_bytes: value.swap_pss_bytes,
+        }
+    }
+}
+
+impl From<HostSwapRate> for GuestSwapRate {
+    fn from(value: HostSwapRate) -> Self {
+        Self {
+            interval_ms: value.interval.as_millis() as u64,
+            swap_in_bytes_per_sec: value.swap_in_bytes_per_sec,
+            swap_out_bytes_per_sec: value.swap_out_bytes_per_sec,
+            used_change: value.used_change,
+        }
+    }
+}
+
 impl memory::Host for SysCtx {
     fn stat(&mut self, interval_ms: u64) -> Result<GuestMemoryStat, String> {
+        self.rate_limit("memory")?;
         self.memory
             .stat(Some(Duration::from_millis(interval_ms)))
             .map(Into::into)
@@ -181,9 +245,42 @@ impl memory::Host for SysCtx {
     }
 
     fn info(&mut self) -> Result<Vec<GuestMemoryInfo>, String> {
+        self.rate_limit("memory")?;
         self.memory
             .info()
             .map(|info| info.into_iter().map(Into::into).collect())
             .map_err(|err| err.to_string())
     }
 }
+
+impl ext_memory::Host for SysCtx {
+    fn delta(&mut self, interval_ms: u64) -> Result<GuestMemoryDelta, String> {
+        self.rate_limit("memory")?;
+        self.memory
+            .delta(Duration::from_millis(interval_ms))
+            .map(Into::into)
+            .map_err(|err| err.to_string())
+    }
+
+    fn swap_devices(&mut self) -> Result<Vec<GuestSwapDevice>, String> {
+        self.rate_limit("memory")?;
+        HostSwapDevice::capture()
+            .map(|devices| devices.into_iter().map(Into::into).collect())
+            .map_err(|err| err.to_string())
+    }
+
+    fn process_swap(&mut self) -> Result<Vec<GuestProcessSwap>, String> {
+        self.rate_limit("memory")?;
+        HostProcessSwap::capture()
+            .map(|swaps| swaps.into_iter().map(Into::into).collect())
+            .map_err(|err| err.to_string())
+    }
+
+    fn swap_rate(&mut self, interval_ms: u64) -> Result<GuestSwapRate, String> {
+        self.rate_limit("memory")?;
+        self.memory
+            .swap_rate(Duration::from_millis(interval_ms))
+            .map(Into::into)
+            .map_err(|err| err.to_string())
+    }
+}
@@ -71,6 +71,7 @@ impl From<DeviceStatus> for GuestNetworkStat {
 
 impl network::Host for SysCtx {
     fn stat(&mut self, interval_ms: u64) -> Result<Vec<GuestNetworkStat>, String> {
+        self.rate_limit("network")?;
         self.network
             .stat(Some(Duration::from_millis(interval_ms)))
             .map(|nets| nets.into_values().map(Into::into).collect())
@@ -125,6 +125,7 @@ impl From<HostOsInfo> for GuestOsInfo {
 
 impl os::Host for SysCtx {
     fn info(&mut self) -> Result<GuestOsInfo, String> {
+        self.rate_limit("os")?;
         self.os
             .info()
             .map(Into::into)
@@ -14,14 +14,16 @@
 
 use std::{collections::HashMap, ffi::OsString, path::PathBuf, sync::Arc, time::Duration};
 
-use psh_system::process::{ProcState, Process};
+use psh_system::process::{MemoryReclaim, OomScore, ProcState, Process, ProcessEntry};
 use wasmtime::component::Resource;
 
 use crate::{
-    SysCtx,
+    SysCtx, environ,
+    ext::profiling::system_ext::process as process_ext,
     profiling::system::process::{
         self, ProcessStat as GuestProcessStat, ProcessState as GuestProcessState,
     },
+    redact,
 };
 
 impl From<&ProcState> for GuestProcessState {
@@ -84,7 +86,13 @@ impl process::HostProcess for SysCtx {
         self_: Resource<Arc<Process>>,
     ) -> wasmtime::Result<Result<Vec<String>, String>> {
         let proc = self.table.get(&self_)?;
-        Ok(proc.cmdline().map_err(|err| err.to_string()))
+        Ok(proc
+            .cmdline()
+            .map_err(|err| err.to_string())
+            .map(|args| match redact::global() {
+                Some(redactor) => redactor.redact_cmdline(args),
+                None => args,
+            }))
     }
 
     fn exe(&mut self, self_: Resource<Arc<Process>>) -> wasmtime::Result<Result<String, String>> {
@@ -96,11 +104,24 @@ impl process::HostProcess for SysCtx {
         &mut self,
         self_: Resource<Arc<Process>>,
     ) -> wasmtime::Result<Result<Vec<(String, String)>, String>> {
+        if !self.allow_environ {
+            return Ok(Err(
+                "Reading process environments is not allowed".to_string()
+            ));
+        }
+        let Some(policy) = environ::global() else {
+            return Ok(Err("Reading process environments is disabled".to_string()));
+        };
         let proc = self.table.get(&self_)?;
         Ok(proc
             .environ()
             .map_err(|err| err.to_string())
-            .map(envs_to_vec))
+            .map(envs_to_vec)
+            .map(|vars| policy.filter(vars))
+            .map(|vars| match redact::global() {
+                Some(redactor) => redactor.redact_env(vars),
+                None => vars,
+            }))
     }
 
     fn cwd(&mut self, self_: Resource<Arc<Process>>) -> wasmtime::Result<Result<String, String>> {
@@ -126,32 +147,32 @@ impl process::HostProcess for SysCtx {
 
 impl process::Host for SysCtx {
     fn all(&mut self, interval_ms: u64) -> wasmtime::Result<Result<Vec<GuestProcessStat>, String>> {
+        if let Err(err) = self.rate_limit("process") {
+            return Ok(Err(err));
+        }
         // don't return top level Error unless it's not our fault
         // example: self.table.(push/get/delete)
-        let procs = match self.process.all(Some(Duration::from_millis(interval_ms))) {
-            Ok(procs) => procs,
+        let entries = match self.process.table(Some(Duration::from_millis(interval_ms))) {
+            Ok(entries) => entries,
             Err(err) => return Ok(Err(err.to_string())),
         };
 
-        let processes = procs.into_iter().filter_map(|proc| {
-            let (Ok(stat), Ok(io), Ok(mem)) = (proc.stat(), proc.io(), proc.statm()) else {
-                return None;
-            };
-            let Ok(state) = stat.state() else {
-                return None;
-            };
-            Some((proc, stat, io, mem, state))
+        let processes = entries.iter().filter_map(|entry| {
+            let state = entry.stat.state().ok()?;
+            Some((entry, state))
         });
 
         let processes: Vec<_> = processes
-            .into_iter()
-            .map(|(proc, stat, io, mem, ref state)| {
-                let (pid, parent_id) = (proc.pid, stat.ppid);
-                match self.table.push(proc) {
+            .map(|(entry, ref state)| {
+                let ProcessEntry {
+                    stat, io, statm, ..
+                } = &**entry;
+                let (pid, parent_id) = (entry.process.pid, stat.ppid);
+                match self.table.push(Arc::clone(&entry.process)) {
                     Ok(proc) => Ok(GuestProcessStat {
                         pid,
                         proc,
-                        name: stat.comm,
+                        name: stat.comm.clone(),
                         utime: stat.utime * 1000 / self.system.tick_per_sec,
                         stime: stat.stime * 1000 / self.system.tick_per_sec,
                         cutime: stat.cutime * 1000 / self.system.tick_per_sec as i64,
@@ -163,8 +184,8 @@ impl process::Host for SysCtx {
                         state: state.into(),
                         written_bytes: io.write_bytes,
                         read_bytes: io.read_bytes,
-                        memory_usage: mem.resident * self.system.page_size,
-                        virtual_memory_usage: mem.size * self.system.page_size,
+                        memory_usage: statm.resident * self.system.page_size,
+                        virtual_memory_usage: statm.size * self.system.page_size,
                         parent_id,
                     }),
                     Err(err) => Err(err),
@@ -176,6 +197,9 @@ impl process::Host for SysCtx {
     }
 
     fn current(&mut self) -> wasmtime::Result<Result<Resource<Arc<Process>>, String>> {
+        if let Err(err) = self.rate_limit("process") {
+            return Ok(Err(err));
+        }
         let proc = match self.process.myself() {
             Ok(proc) => Ok(self.table.push(proc)?),
             Err(err) => Err(err.to_string()),
@@ -183,3 +207,47 @@ impl process::Host for SysCtx {
         Ok(proc)
     }
 }
+
+impl From<MemoryReclaim> for process_ext::MemoryReclaim {
+    fn from(value: MemoryReclaim) -> Self {
+        Self {
+            cgroup: value.cgroup,
+            workingset_refault_anon: value.workingset_refault_anon,
+            workingset_refault_file: value.workingset_refault_file,
+            pgscan: value.pgscan,
+            pgsteal: value.pgsteal,
+            pgmajfault: value.pgmajfault,
+        }
+    }
+}
+
+impl From<OomScore> for process_ext::OomScore {
+    fn from(value: OomScore) -> Self {
+        Self {
+            pid: value.pid,
+            name: value.name,
+            oom_score: value.oom_score,
+            oom_score_adj: value.oom_score_adj,
+            rss_bytes: value.rss_bytes,
+            swap_bytes: value.swap_bytes,
+            major_faults: value.major_faults,
+            reclaim: value.reclaim.map(Into::into),
+        }
+    }
+}
+
+impl process_ext::Host for SysCtx {
+    fn oom_score_of(&mut self, pid: i32) -> Result<process_ext::OomScore, String> {
+        self.rate_limit("process")?;
+        OomScore::capture(pid)
+            .map(Into::into)
+            .map_err(|err| err.to_string())
+    }
+
+    fn oom_scores(&mut self) -> Result<Vec<process_ext::OomScore>, String> {
+        self.rate_limit("process")?;
+        OomScore::capture_all()
+            .map(|scores| scores.into_iter().map(Into::into).collect())
+            .map_err(|err| err.to_string())
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{borrow::Cow, sync::OnceLock};
+
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+
+static GLOBAL: OnceLock<Redactor> = OnceLock::new();
+
+/// Installs the rules applied to command lines, environments and exported
+/// data of all components.
+pub fn init(redactor: Redactor) -> Result<()> {
+    if GLOBAL.set(redactor).is_err() {
+        bail!("Redaction already initialized");
+    }
+    Ok(())
+}
+
+pub fn global() -> Option<&'static Redactor> {
+    GLOBAL.get()
+}
+
+/// Masks secrets before they leave the host.
+///
+/// Values of sensitive fields are masked as a whole, a field is sensitive
+/// if its name contains one of `fields` ignoring case: environment
+/// variables, `--field value`, `--field=value` and `field=value` arguments
+/// and tags, the value after `--field` even if it starts with a dash.
+/// Everything else is searched for `patterns`, a match is masked, or only
+/// its `secret` group if it has one.
+#[derive(Debug)]
+pub struct Redactor {
+    fields: Vec<String>,
+    patterns: Vec<Regex>,
+    mask: String,
+}
+
+impl Redactor {
+    pub fn new(fields: &[String], patterns: &[String], mask: &str) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|it| Regex::new(it).with_context(|| format!("Invalid redaction pattern {it}")))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            fields: fields.iter().map(|it| it.to_lowercase()).collect(),
+            patterns,
+            mask: mask.to_string(),
+        })
+    }
+
+    pub fn is_sensitive(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.fields.iter().any(|it| name.contains(it.as_str()))
+    }
+
+    /// `text` with every match of the patterns masked.
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for pattern in &self.patterns {
+            if !pattern.is_match(&text) {
+                continue;
+            }
+            let redacted = pattern
+                .replace_all(&text, |caps: &regex::Captures<'_>| {
+                    let whole = caps.get(0).expect("group 0 always matches");
+                    match caps.name("secret") {
+                        Some(secret) => {
+                            let start = secret.start() - whole.start();
+                            let end = secret.end() - whole.start();
+                            let whole = whole.as_str();
+                            format!("{}{}{}", &whole[..start], self.mask, &whole[end..])
+                        }
+                        None => self.mask.clone(),
+                    }
+                })
+                .into_owned();
+            text = Cow::Owned(redacted);
+        }
+        text
+    }
+
+    /// The value of a field named `name`.
+    pub fn redact_value<'a>(&self, name: &str, value: &'a str) -> Cow<'a, str> {
+        if self.is_sensitive(name) {
+            Cow::Owned(self.mask.clone())
+        } else {
+            self.redact(value)
+        }
+    }
+
+    pub fn redact_cmdline(&self, args: Vec<String>) -> Vec<String> {
+        let mut redacted = Vec::with_capacity(args.len());
+        let mut mask_next = false;
+        for arg in args {
+            // a secret may start with a dash too, as in `--password -x1`
+            if mask_next {
+                mask_next = false;
+                redacted.push(self.mask.clone());
+                continue;
+            }
+            if let Some((name, value)) = arg.split_once('=') {
+                let value = self.redact_value(name, value);
+                redacted.push(format!("{name}={value}"));
+                continue;
+            }
+            if arg.starts_with('-') && self.is_sensitive(arg.trim_start_matches('-')) {
+                mask_next = true;
+            }
+            redacted.push(self.redact(&arg).into_owned());
+        }
+        redacted
+    }
+
+    pub fn redact_env(&self, vars: Vec<(String, String)>) -> Vec<(String, String)> {
+        vars.into_iter()
+            .map(|(name, value)| {
+                let value = self.redact_value(&name, &value).into_owned();
+                (name, value)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Redactor;
+
+    fn redactor() -> Redactor {
+        Redactor::new(
+            &["password".to_string(), "token".to_string()],
+            &["(?i)bearer (?P<secret>\\S+)".to_string()],
+            "***",
+        )
+        .unwrap()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_redact_cmdline() {
+        let redactor = redactor();
+        let redact = |it: &[&str]| redactor.redact_cmdline(args(it));
+        assert_eq!(
+            redact(&["app", "--password=hunter2", "--verbose"]),
+            args(&["app", "--password=***", "--verbose"])
+        );
+        assert_eq!(
+            redact(&["app", "--password", "hunter2", "-v"]),
+            args(&["app", "--password", "***", "-v"])
+        );
+        assert_eq!(
+            redact(&["app", "--password", "-hunter2"]),
+            args(&["app", "--password", "***"])
+        );
+        assert_eq!(
+            redact(&["env", "API_TOKEN=abc", "HOME=/root"]),
+            args(&["env", "API_TOKEN=***", "HOME=/root"])
+        );
+        assert_eq!(
+            redact(&["curl", "-H", "Authorization: Bearer abc"]),
+            args(&["curl", "-H", "Authorization: Bearer ***"])
+        );
+    }
+
+    #[test]
+    fn test_redact_env() {
+        let redactor = redactor();
+        let vars = vec![
+            ("DB_PASSWORD".to_string(), "hunter2".to_string()),
+            ("PATH".to_string(), "/bin".to_string()),
+        ];
+        assert_eq!(
+            redactor.redact_env(vars),
+            [
+                ("DB_PASSWORD".to_string(), "***".to_string()),
+                ("PATH".to_string(), "/bin".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,53 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{net::SocketAddr, time::Duration};
+
+use psh_system::ftrace::{Direction as HostDirection, TcpConnections};
+
+use crate::{
+    SysCtx,
+    ext::profiling::system_ext::tcp::{self, Direction as GuestDirection, Endpoint},
+    latency::histogram,
+};
+
+impl tcp::Host for SysCtx {
+    fn endpoints(&mut self, window_ms: u64) -> Result<Vec<Endpoint>, String> {
+        self.rate_limit("tcp")?;
+        let endpoints = TcpConnections::collect(Duration::from_millis(window_ms))
+            .map_err(|err| err.to_string())?;
+        Ok(endpoints
+            .into_iter()
+            .map(|(endpoint, stats)| {
+                let key = match endpoint.address {
+                    Some(address) => SocketAddr::new(address, endpoint.port).to_string(),
+                    None => endpoint.port.to_string(),
+                };
+                Endpoint {
+                    direction: match endpoint.direction {
+                        HostDirection::Inbound => GuestDirection::Inbound,
+                        HostDirection::Outbound => GuestDirection::Outbound,
+                    },
+                    address: endpoint.address.map(|it| it.to_string()),
+                    port: endpoint.port,
+                    established: stats.established,
+                    failed: stats.failed,
+                    handshake: histogram(key.clone(), stats.handshake),
+                    retransmits: stats.retransmits,
+                    rtt: histogram(key, stats.rtt),
+                }
+            })
+            .collect())
+    }
+}
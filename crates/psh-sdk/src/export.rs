@@ -0,0 +1,144 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use crate::bindings::data_export::profiling::data_export::{
+    common::FieldValue,
+    measurement::{self, Point},
+    metric::{self, Sample},
+};
+
+impl From<i64> for FieldValue {
+    fn from(value: i64) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<u64> for FieldValue {
+    fn from(value: u64) -> Self {
+        Self::Uint(value)
+    }
+}
+
+impl From<f64> for FieldValue {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<bool> for FieldValue {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+/// Builds a [`Point`], the host adds its own tags, and a timestamp unless
+/// one is set.
+#[derive(Clone, Debug)]
+pub struct PointBuilder {
+    point: Point,
+}
+
+impl PointBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            point: Point {
+                name: name.into(),
+                tags: vec![],
+                fields: vec![],
+                ns_ts: None,
+            },
+        }
+    }
+
+    pub fn tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.point.tags.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn field(mut self, key: impl Into<String>, value: impl Into<FieldValue>) -> Self {
+        self.point.fields.push((key.into(), value.into()));
+        self
+    }
+
+    /// In nanoseconds since the epoch.
+    pub const fn timestamp(mut self, ns: u64) -> Self {
+        self.point.ns_ts = Some(ns);
+        self
+    }
+
+    pub fn build(self) -> Point {
+        self.point
+    }
+
+    pub fn export(self) -> Result<(), String> {
+        measurement::export_point(&self.point)
+    }
+}
+
+/// Exports a metric of a single value.
+pub fn sample(
+    name: impl Into<String>,
+    value: impl Into<FieldValue>,
+    tags: &[(&str, &str)],
+) -> Result<(), String> {
+    metric::export_sample(&Sample {
+        name: name.into(),
+        tags: tags
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        value: value.into(),
+        ns_ts: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PointBuilder;
+    use crate::bindings::data_export::profiling::data_export::common::FieldValue;
+
+    #[test]
+    fn test_point_builder() {
+        let point = PointBuilder::new("cpu")
+            .tag("cpu", "0")
+            .field("busy", 0.5)
+            .field("cycles", 100u64)
+            .field("online", true)
+            .timestamp(1)
+            .build();
+        assert_eq!(point.name, "cpu");
+        assert_eq!(point.tags, [("cpu".to_string(), "0".to_string())]);
+        assert!(matches!(
+            point.fields[..],
+            [
+                (_, FieldValue::Float(0.5)),
+                (_, FieldValue::Uint(100)),
+                (_, FieldValue::Boolean(true)),
+            ]
+        ));
+        assert_eq!(point.ns_ts, Some(1));
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+pub mod export;
+pub mod perf;
+pub mod process;
+
+/// Bindings of the host interfaces, generated from their WIT.
+pub mod bindings {
+    pub mod data_export {
+        wit_bindgen::generate!({
+            path: "wit/data-export",
+            world: "imports",
+        });
+    }
+
+    pub mod perf {
+        wit_bindgen::generate!({
+            path: "wit/perf",
+            world: "imports",
+        });
+    }
+
+    pub mod system {
+        wit_bindgen::generate!({
+            path: "wit/system",
+            world: "imports",
+        });
+    }
+
+    /// Interfaces not yet published in psh-sdk-wit, from `wit/data-export-ext`.
+    pub mod data_export_ext {
+        wit_bindgen::generate!({
+            path: "wit/data-export-ext",
+            world: "imports",
+        });
+    }
+
+    /// Interfaces not yet published in psh-sdk-wit, from `wit/perf-ext`.
+    pub mod perf_ext {
+        wit_bindgen::generate!({
+            path: "wit/perf-ext",
+            world: "imports",
+            with: {
+                "profiling:perf/config": crate::bindings::perf::profiling::perf::config,
+                "profiling:perf/counter": crate::bindings::perf::profiling::perf::counter,
+                "profiling:perf/counter-group": crate::bindings::perf::profiling::perf::counter_group,
+            },
+        });
+    }
+
+    /// Interfaces not yet published in psh-sdk-wit, from `wit/system-ext`.
+    pub mod system_ext {
+        wit_bindgen::generate!({
+            path: "wit/system-ext",
+            world: "imports",
+        });
+    }
+
+    /// Interfaces implemented by the daemon itself, from `wit/host`.
+    pub mod host {
+        wit_bindgen::generate!({
+            path: "wit/host",
+            world: "imports",
+            generate_all,
+        });
+    }
+
+    pub mod probe {
+        wit_bindgen::generate!({
+            path: "wit/probe",
+            world: "imports",
+        });
+    }
+}
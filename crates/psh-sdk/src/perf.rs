@@ -0,0 +1,182 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use crate::bindings::{
+    perf::profiling::perf::{
+        config::{Config, Cpu, Event, EventScope, ExtraConfig, Process},
+        counter::{Counter, CounterStat},
+        counter_group::{CounterGroupStat, FixedCounterGroup},
+    },
+    perf_ext::profiling::perf_ext::batch,
+};
+
+/// Builds the [`Config`] of a counter, by default it counts in every scope
+/// without extra options, like a zeroed `perf_event_attr`.
+#[derive(Clone, Debug)]
+pub struct ConfigBuilder {
+    cfg: Config,
+}
+
+impl ConfigBuilder {
+    pub fn new(event: Event) -> Self {
+        Self {
+            cfg: Config {
+                event,
+                scopes: vec![
+                    EventScope::User,
+                    EventScope::Kernel,
+                    EventScope::Hv,
+                    EventScope::Idle,
+                    EventScope::Host,
+                    EventScope::Guest,
+                ],
+                extra_config: ExtraConfig {
+                    pinned: false,
+                    exclusive: false,
+                    inherit: false,
+                    inherit_stat: false,
+                    inherit_thread: false,
+                    enable_on_exec: false,
+                    remove_on_exec: false,
+                },
+            },
+        }
+    }
+
+    /// Replaces the scopes, e.g. `[EventScope::User]` to leave the kernel out.
+    pub fn scopes(mut self, scopes: impl IntoIterator<Item = EventScope>) -> Self {
+        self.cfg.scopes = scopes.into_iter().collect();
+        self
+    }
+
+    pub const fn pinned(mut self, enable: bool) -> Self {
+        self.cfg.extra_config.pinned = enable;
+        self
+    }
+
+    pub const fn exclusive(mut self, enable: bool) -> Self {
+        self.cfg.extra_config.exclusive = enable;
+        self
+    }
+
+    /// Also counts the children of the process created after it is opened.
+    pub const fn inherit(mut self, enable: bool) -> Self {
+        self.cfg.extra_config.inherit = enable;
+        self
+    }
+
+    pub const fn inherit_stat(mut self, enable: bool) -> Self {
+        self.cfg.extra_config.inherit_stat = enable;
+        self
+    }
+
+    pub const fn inherit_thread(mut self, enable: bool) -> Self {
+        self.cfg.extra_config.inherit_thread = enable;
+        self
+    }
+
+    pub const fn enable_on_exec(mut self, enable: bool) -> Self {
+        self.cfg.extra_config.enable_on_exec = enable;
+        self
+    }
+
+    pub const fn remove_on_exec(mut self, enable: bool) -> Self {
+        self.cfg.extra_config.remove_on_exec = enable;
+        self
+    }
+
+    pub fn build(self) -> Config {
+        self.cfg
+    }
+
+    /// Opens a counter of `process` on `cpu`, disabled until
+    /// [`Counter::enable`].
+    pub fn open(&self, process: Process, cpu: Cpu) -> Result<Counter, String> {
+        Counter::new(process, cpu, &self.cfg)
+    }
+
+    /// Opens a counter for each of `pids` on `cpu` in one host call,
+    /// results are in the order of `pids`.
+    pub fn open_all(
+        &self,
+        pids: impl IntoIterator<Item = i32>,
+        cpu: Cpu,
+    ) -> Vec<Result<Counter, String>> {
+        let processes: Vec<_> = pids.into_iter().map(Process::Pid).collect();
+        batch::open_counters(&processes, cpu, &self.cfg)
+    }
+}
+
+/// Reads `counters` in one host call, results are in their order.
+pub fn stat_all<'a>(
+    counters: impl IntoIterator<Item = &'a Counter>,
+) -> Vec<Result<CounterStat, String>> {
+    let counters: Vec<_> = counters.into_iter().collect();
+    batch::stat_counters(&counters)
+}
+
+/// Reads `groups` in one host call, results are in their order.
+pub fn stat_all_groups<'a>(
+    groups: impl IntoIterator<Item = &'a FixedCounterGroup>,
+) -> Vec<Result<CounterGroupStat, String>> {
+    let groups: Vec<_> = groups.into_iter().collect();
+    batch::stat_groups(&groups)
+}
+
+/// The count extrapolated to the whole time the counter was enabled, which
+/// accounts for counters multiplexed with others, 0 if it never ran.
+pub fn scaled(stat: &CounterStat) -> u64 {
+    if stat.time_running == 0 {
+        return 0;
+    }
+    (u128::from(stat.event_count) * u128::from(stat.time_enabled) / u128::from(stat.time_running))
+        as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigBuilder, scaled};
+    use crate::bindings::perf::profiling::perf::{
+        config::{Event, EventScope, HardwareEvent},
+        counter::CounterStat,
+    };
+
+    #[test]
+    fn test_config_builder() {
+        let cfg = ConfigBuilder::new(Event::Hardware(HardwareEvent::Instructions))
+            .scopes([EventScope::User])
+            .inherit(true)
+            .build();
+        assert!(matches!(
+            cfg.event,
+            Event::Hardware(HardwareEvent::Instructions)
+        ));
+        assert!(matches!(cfg.scopes[..], [EventScope::User]));
+        assert!(cfg.extra_config.inherit);
+        assert!(!cfg.extra_config.pinned);
+    }
+
+    #[test]
+    fn test_scaled() {
+        let stat = |event_count, time_enabled, time_running| CounterStat {
+            event_id: 1,
+            event_count,
+            time_enabled,
+            time_running,
+        };
+        assert_eq!(scaled(&stat(100, 10, 10)), 100);
+        assert_eq!(scaled(&stat(100, 30, 10)), 300);
+        assert_eq!(scaled(&stat(100, 30, 0)), 0);
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{collections::HashMap, time::Instant};
+
+use crate::bindings::system::profiling::system::process::{self, ProcessStat};
+
+/// Processes of the host, from a snapshot the host took at most
+/// `max_age_ms` ago.
+pub fn all(max_age_ms: u64) -> Result<impl Iterator<Item = ProcessStat>, String> {
+    process::all(max_age_ms).map(Vec::into_iter)
+}
+
+/// What a process did between two snapshots of a [`Sampler`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Usage {
+    pub pid: i32,
+    pub name: String,
+    /// cpu time per elapsed time, 1.0 is one cpu fully busy
+    pub cpu: f64,
+    /// resident memory in bytes
+    pub memory: u64,
+    /// bytes per second
+    pub read_rate: f64,
+    /// bytes per second
+    pub write_rate: f64,
+}
+
+#[derive(Clone, Copy)]
+struct Counters {
+    /// user and system time in ms
+    cpu_ms: u64,
+    read_bytes: u64,
+    written_bytes: u64,
+}
+
+impl Counters {
+    fn of(stat: &ProcessStat) -> Self {
+        Self {
+            cpu_ms: stat.utime + stat.stime,
+            read_bytes: stat.read_bytes,
+            written_bytes: stat.written_bytes,
+        }
+    }
+
+    fn usage(&self, next: &Self, elapsed_ms: f64) -> (f64, f64, f64) {
+        let delta = |pre: u64, post: u64| post.saturating_sub(pre) as f64;
+        (
+            delta(self.cpu_ms, next.cpu_ms) / elapsed_ms,
+            delta(self.read_bytes, next.read_bytes) * 1000.0 / elapsed_ms,
+            delta(self.written_bytes, next.written_bytes) * 1000.0 / elapsed_ms,
+        )
+    }
+}
+
+/// Turns consecutive process snapshots into rates.
+#[derive(Default)]
+pub struct Sampler {
+    /// by pid and start time, as pids get reused
+    last: Option<(Instant, HashMap<(i32, u64), Counters>)>,
+}
+
+impl Sampler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes a fresh snapshot, returns the usage of the processes that were
+    /// in the previous one too, nothing on the first call.
+    pub fn sample(&mut self) -> Result<impl Iterator<Item = Usage>, String> {
+        let now = Instant::now();
+        let processes = process::all(0)?;
+        let counters: HashMap<_, _> = processes
+            .iter()
+            .map(|it| ((it.pid, it.start_time), Counters::of(it)))
+            .collect();
+
+        let mut usages = vec![];
+        if let Some((then, last)) = &self.last {
+            let elapsed_ms = (now - *then).as_secs_f64() * 1000.0;
+            for it in processes.iter().filter(|_| elapsed_ms > 0.0) {
+                let Some(pre) = last.get(&(it.pid, it.start_time)) else {
+                    continue;
+                };
+                let (cpu, read_rate, write_rate) = pre.usage(&Counters::of(it), elapsed_ms);
+                usages.push(Usage {
+                    pid: it.pid,
+                    name: it.name.clone(),
+                    cpu,
+                    memory: it.memory_usage,
+                    read_rate,
+                    write_rate,
+                });
+            }
+        }
+        self.last = Some((now, counters));
+        Ok(usages.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Counters;
+
+    #[test]
+    fn test_usage() {
+        let pre = Counters {
+            cpu_ms: 1000,
+            read_bytes: 0,
+            written_bytes: 4096,
+        };
+        let post = Counters {
+            cpu_ms: 1500,
+            read_bytes: 2048,
+            written_bytes: 4096,
+        };
+        assert_eq!(pre.usage(&post, 1000.0), (0.5, 2048.0, 0.0));
+        // counters going backwards mean a different process
+        assert_eq!(post.usage(&pre, 1000.0), (0.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,273 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::error::{Error, Result};
+
+/// Block IO of a cgroup on one device, with the limits set on it.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IoDevice {
+    /// `major:minor`, e.g. `8:0`
+    pub device: String,
+    /// e.g. `sda`, from `/sys/dev/block`
+    pub name: Option<String>,
+    pub rbytes: u64,
+    pub wbytes: u64,
+    pub rios: u64,
+    pub wios: u64,
+    pub dbytes: u64,
+    pub dios: u64,
+    /// average completion latency, if the latency controller is enabled
+    pub avg_lat_usec: Option<u64>,
+    /// limits of `io.max`, `None` if unlimited
+    pub rbps: Option<u64>,
+    pub wbps: Option<u64>,
+    pub riops: Option<u64>,
+    pub wiops: Option<u64>,
+    /// target of `io.latency`
+    pub latency_target_usec: Option<u64>,
+}
+
+impl IoDevice {
+    fn new(device: &str) -> Self {
+        Self {
+            device: device.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub const fn is_limited(&self) -> bool {
+        self.rbps.is_some()
+            || self.wbps.is_some()
+            || self.riops.is_some()
+            || self.wiops.is_some()
+            || self.latency_target_usec.is_some()
+    }
+}
+
+/// Block IO of one cgroup, from `io.stat`, `io.max`, `io.latency` and
+/// `io.pressure`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CgroupIo {
+    /// path in the hierarchy, e.g. `/kubepods.slice`
+    pub cgroup: String,
+    /// ordered by device
+    pub devices: Vec<IoDevice>,
+    /// time some tasks of the cgroup were stalled on IO, cumulative
+    pub pressure_some_usec: Option<u64>,
+    /// time all tasks of the cgroup were stalled on IO, cumulative
+    pub pressure_full_usec: Option<u64>,
+}
+
+/// `key=value` pairs after the device of a line, `max` is `None`.
+fn pairs(rest: &str) -> impl Iterator<Item = (&str, Option<u64>)> {
+    rest.split_whitespace().filter_map(|it| {
+        let (key, value) = it.split_once('=')?;
+        Some((key, value.parse().ok()))
+    })
+}
+
+fn device<'a, 'b>(
+    devices: &'a mut BTreeMap<String, IoDevice>,
+    line: &'b str,
+) -> Option<(&'a mut IoDevice, &'b str)> {
+    let (device, rest) = line.split_once(' ')?;
+    if !device.contains(':') {
+        return None;
+    }
+    let entry = devices
+        .entry(device.to_string())
+        .or_insert_with(|| IoDevice::new(device));
+    Some((entry, rest))
+}
+
+impl CgroupIo {
+    pub fn parse(
+        cgroup: &str,
+        stat: &str,
+        max: Option<&str>,
+        latency: Option<&str>,
+        pressure: Option<&str>,
+    ) -> Self {
+        let mut devices = BTreeMap::new();
+        for line in stat.lines() {
+            let Some((device, rest)) = device(&mut devices, line) else {
+                continue;
+            };
+            for (key, value) in pairs(rest) {
+                let value = value.unwrap_or_default();
+                match key {
+                    "rbytes" => device.rbytes = value,
+                    "wbytes" => device.wbytes = value,
+                    "rios" => device.rios = value,
+                    "wios" => device.wios = value,
+                    "dbytes" => device.dbytes = value,
+                    "dios" => device.dios = value,
+                    "avg_lat" => device.avg_lat_usec = Some(value),
+                    _ => {}
+                }
+            }
+        }
+        for line in max.unwrap_or_default().lines() {
+            let Some((device, rest)) = device(&mut devices, line) else {
+                continue;
+            };
+            for (key, value) in pairs(rest) {
+                match key {
+                    "rbps" => device.rbps = value,
+                    "wbps" => device.wbps = value,
+                    "riops" => device.riops = value,
+                    "wiops" => device.wiops = value,
+                    _ => {}
+                }
+            }
+        }
+        for line in latency.unwrap_or_default().lines() {
+            let Some((device, rest)) = device(&mut devices, line) else {
+                continue;
+            };
+            if let Some((_, target)) = pairs(rest).find(|(key, _)| *key == "target") {
+                device.latency_target_usec = target;
+            }
+        }
+
+        let mut io = Self {
+            cgroup: cgroup.to_string(),
+            devices: devices.into_values().collect(),
+            pressure_some_usec: None,
+            pressure_full_usec: None,
+        };
+        for line in pressure.unwrap_or_default().lines() {
+            let Some((kind, rest)) = line.split_once(' ') else {
+                continue;
+            };
+            let total = pairs(rest)
+                .find(|(key, _)| *key == "total")
+                .and_then(|(_, it)| it);
+            match kind {
+                "some" => io.pressure_some_usec = total,
+                "full" => io.pressure_full_usec = total,
+                _ => {}
+            }
+        }
+        io
+    }
+
+    /// Every cgroup of the unified hierarchy with block IO or IO limits.
+    pub fn capture() -> Result<Vec<Self>> {
+        let root = Path::new(super::CGROUP_ROOT);
+        if !root.join("cgroup.controllers").exists() {
+            return Err(Error::Unsupported("cgroup v1 io controller"));
+        }
+        Self::capture_from(root, Path::new("/sys/dev/block"))
+    }
+
+    pub fn capture_from(root: &Path, dev_block: &Path) -> Result<Vec<Self>> {
+        let mut cgroups = vec![];
+        walk(root, "/", &mut cgroups)?;
+        let mut names = BTreeMap::new();
+        for device in cgroups.iter_mut().flat_map(|it| it.devices.iter_mut()) {
+            device.name = names
+                .entry(device.device.clone())
+                .or_insert_with(|| block_name(dev_block, &device.device))
+                .clone();
+        }
+        Ok(cgroups)
+    }
+}
+
+/// e.g. `sda` for `8:0`.
+fn block_name(dev_block: &Path, device: &str) -> Option<String> {
+    let uevent = fs::read_to_string(dev_block.join(device).join("uevent")).ok()?;
+    uevent
+        .lines()
+        .find_map(|it| it.strip_prefix("DEVNAME="))
+        .map(str::to_string)
+}
+
+fn walk(dir: &Path, path: &str, cgroups: &mut Vec<CgroupIo>) -> Result<()> {
+    let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+    if let Some(stat) = read("io.stat") {
+        let io = CgroupIo::parse(
+            path,
+            &stat,
+            read("io.max").as_deref(),
+            read("io.latency").as_deref(),
+            read("io.pressure").as_deref(),
+        );
+        if !io.devices.is_empty() {
+            cgroups.push(io);
+        }
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let name = entry.file_name();
+        let child = match path {
+            "/" => format!("/{}", name.to_string_lossy()),
+            _ => format!("{path}/{}", name.to_string_lossy()),
+        };
+        // cgroups may vanish while walking
+        let _ = walk(&entry.path(), &child, cgroups);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CgroupIo;
+
+    #[test]
+    fn test_parse_cgroup_io() {
+        let stat = "8:16 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0 \
+                    depth=1 avg_lat=2040 win=100\n\
+                    8:0 rbytes=90430464 wbytes=299008000 rios=8950 wios=1252 dbytes=50331648 dios=3021\n";
+        let max = "8:16 rbps=2097152 wbps=max riops=max wiops=120\n";
+        let latency = "8:16 target=75000\n";
+        let pressure = "some avg10=1.50 avg60=0.80 avg300=0.20 total=1234567\n\
+                        full avg10=0.50 avg60=0.10 avg300=0.00 total=23456\n";
+        let io = CgroupIo::parse(
+            "/kubepods.slice",
+            stat,
+            Some(max),
+            Some(latency),
+            Some(pressure),
+        );
+
+        assert_eq!(io.cgroup, "/kubepods.slice");
+        assert_eq!(io.devices.len(), 2);
+        let sda = &io.devices[0];
+        assert_eq!(sda.device, "8:0");
+        assert_eq!(sda.rbytes, 90430464);
+        assert_eq!(sda.dios, 3021);
+        assert_eq!(sda.avg_lat_usec, None);
+        assert!(!sda.is_limited());
+
+        let sdb = &io.devices[1];
+        assert_eq!(sdb.device, "8:16");
+        assert_eq!(sdb.wios, 353);
+        assert_eq!(sdb.avg_lat_usec, Some(2040));
+        assert_eq!(sdb.rbps, Some(2097152));
+        assert_eq!(sdb.wbps, None);
+        assert_eq!(sdb.wiops, Some(120));
+        assert_eq!(sdb.latency_target_usec, Some(75000));
+        assert!(sdb.is_limited());
+
+        assert_eq!(io.pressure_some_usec, Some(1234567));
+        assert_eq!(io.pressure_full_usec, Some(23456));
+    }
+}
@@ -12,28 +12,25 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
-use std::fs;
-use std::ops::Not;
-use std::process::Command;
+mod io;
+mod throttle;
 
-fn main() {
-    let _ = fs::remove_file("src/imports.rs");
-    let mut cmd = Command::new("wit-bindgen");
-    cmd.args([
-        "rust",
-        "--stubs",
-        "--out-dir",
-        "src/",
-        "../../../psh-sdk-wit/wit/deps/data-export",
-    ]);
+use std::path::{Path, PathBuf};
 
-    let output = cmd
-        .output()
-        .unwrap_or_else(|it| panic!("Failed to generate imports: \n{}", it));
-    if output.status.success().not() {
-        panic!(
-            "Failed to generate imports: \n{}",
-            String::from_utf8(output.stderr).unwrap()
-        );
+pub use io::{CgroupIo, IoDevice};
+pub use throttle::{CpuStat, ThrottleDelta, ThrottleSnapshot, ThrottleWatcher};
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Mount point of the hierarchy with the cpu controller, the unified one
+/// on cgroup v2.
+pub fn cpu_root() -> Option<PathBuf> {
+    let root = Path::new(CGROUP_ROOT);
+    if root.join("cgroup.controllers").exists() {
+        return Some(root.to_path_buf());
     }
+    ["cpu", "cpu,cpuacct"]
+        .iter()
+        .map(|it| root.join(it))
+        .find(|it| it.join("cpu.stat").exists())
 }
@@ -0,0 +1,292 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::error::{Error, Result};
+
+/// CFS bandwidth counters of a cgroup, from `cpu.stat`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CpuStat {
+    /// enforcement intervals that have elapsed
+    pub nr_periods: u64,
+    /// intervals in which the cgroup ran out of quota
+    pub nr_throttled: u64,
+    pub throttled_usec: u64,
+}
+
+impl CpuStat {
+    /// Cgroups without a bandwidth limit have no `nr_periods` on v1 and
+    /// no cpu controller enabled on v2, they are `None`.
+    pub fn parse(content: &str) -> Option<Self> {
+        let mut stat = Self::default();
+        let mut limited = false;
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once(' ') else {
+                continue;
+            };
+            let Ok(value) = value.trim().parse::<u64>() else {
+                continue;
+            };
+            match key {
+                "nr_periods" => {
+                    stat.nr_periods = value;
+                    limited = true;
+                }
+                "nr_throttled" => stat.nr_throttled = value,
+                "throttled_usec" => stat.throttled_usec = value,
+                // cgroup v1 counts in nanoseconds
+                "throttled_time" => stat.throttled_usec = value / 1000,
+                _ => {}
+            }
+        }
+        limited.then_some(stat)
+    }
+}
+
+/// `cpu.stat` of every cgroup at one point in time, keyed by the cgroup
+/// path relative to the hierarchy, e.g. `/kubepods.slice`.
+#[derive(Debug, Clone)]
+pub struct ThrottleSnapshot {
+    pub timestamp: Instant,
+    pub stats: BTreeMap<String, CpuStat>,
+}
+
+impl ThrottleSnapshot {
+    pub fn capture() -> Result<Self> {
+        let root = super::cpu_root().ok_or(Error::Unsupported("cgroup cpu controller"))?;
+        Self::capture_from(&root)
+    }
+
+    pub fn capture_from(root: &Path) -> Result<Self> {
+        let mut stats = BTreeMap::new();
+        walk(root, "/", &mut stats)?;
+        Ok(Self {
+            timestamp: Instant::now(),
+            stats,
+        })
+    }
+}
+
+fn walk(dir: &Path, path: &str, stats: &mut BTreeMap<String, CpuStat>) -> Result<()> {
+    if let Some(stat) = fs::read_to_string(dir.join("cpu.stat"))
+        .ok()
+        .as_deref()
+        .and_then(CpuStat::parse)
+    {
+        stats.insert(path.to_string(), stat);
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let name = entry.file_name();
+        let child = match path {
+            "/" => format!("/{}", name.to_string_lossy()),
+            _ => format!("{path}/{}", name.to_string_lossy()),
+        };
+        // cgroups may vanish while walking
+        let _ = walk(&entry.path(), &child, stats);
+    }
+    Ok(())
+}
+
+/// Throttling of one cgroup between two [`ThrottleSnapshot`]s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThrottleDelta {
+    pub cgroup: String,
+    pub interval: Duration,
+    pub periods: u64,
+    pub throttled_periods: u64,
+    pub throttled_usec: u64,
+    /// throttled periods per elapsed period, 0 if none elapsed
+    pub throttled_ratio: f64,
+}
+
+impl ThrottleDelta {
+    /// Cgroups present in both snapshots with at least one elapsed period,
+    /// a cgroup recreated in between is reported from zero.
+    pub fn between(before: &ThrottleSnapshot, after: &ThrottleSnapshot) -> Vec<Self> {
+        let interval = after.timestamp.saturating_duration_since(before.timestamp);
+        after
+            .stats
+            .iter()
+            .filter_map(|(cgroup, now)| {
+                let then = before.stats.get(cgroup)?;
+                let then = if now.nr_periods < then.nr_periods {
+                    CpuStat::default()
+                } else {
+                    *then
+                };
+                let periods = now.nr_periods - then.nr_periods;
+                if periods == 0 {
+                    return None;
+                }
+                let throttled_periods = now.nr_throttled.saturating_sub(then.nr_throttled);
+                Some(Self {
+                    cgroup: cgroup.clone(),
+                    interval,
+                    periods,
+                    throttled_periods,
+                    throttled_usec: now.throttled_usec.saturating_sub(then.throttled_usec),
+                    throttled_ratio: throttled_periods as f64 / periods as f64,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Reports cgroups throttled since the last poll that were not throttled
+/// in the interval before it.
+#[derive(Debug)]
+pub struct ThrottleWatcher {
+    last: ThrottleSnapshot,
+    throttled: HashSet<String>,
+}
+
+impl ThrottleWatcher {
+    pub fn new() -> Result<Self> {
+        Ok(Self::from_snapshot(ThrottleSnapshot::capture()?))
+    }
+
+    pub fn from_snapshot(snapshot: ThrottleSnapshot) -> Self {
+        Self {
+            last: snapshot,
+            throttled: HashSet::new(),
+        }
+    }
+
+    pub fn poll(&mut self) -> Result<Vec<ThrottleDelta>> {
+        Ok(self.advance(ThrottleSnapshot::capture()?))
+    }
+
+    pub fn advance(&mut self, snapshot: ThrottleSnapshot) -> Vec<ThrottleDelta> {
+        let deltas = ThrottleDelta::between(&self.last, &snapshot);
+        self.last = snapshot;
+
+        let throttled: HashSet<String> = deltas
+            .iter()
+            .filter(|it| it.throttled_periods > 0)
+            .map(|it| it.cgroup.clone())
+            .collect();
+        let newly = deltas
+            .into_iter()
+            .filter(|it| throttled.contains(&it.cgroup) && !self.throttled.contains(&it.cgroup))
+            .collect();
+        self.throttled = throttled;
+        newly
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        time::{Duration, Instant},
+    };
+
+    use super::{CpuStat, ThrottleDelta, ThrottleSnapshot, ThrottleWatcher};
+
+    fn snapshot(timestamp: Instant, stats: &[(&str, u64, u64, u64)]) -> ThrottleSnapshot {
+        let stats = stats
+            .iter()
+            .map(|&(cgroup, nr_periods, nr_throttled, throttled_usec)| {
+                let stat = CpuStat {
+                    nr_periods,
+                    nr_throttled,
+                    throttled_usec,
+                };
+                (cgroup.to_string(), stat)
+            })
+            .collect::<BTreeMap<_, _>>();
+        ThrottleSnapshot { timestamp, stats }
+    }
+
+    #[test]
+    fn test_parse_cpu_stat() {
+        let v2 = "usage_usec 1000\nuser_usec 600\nsystem_usec 400\nnr_periods 50\nnr_throttled 5\nthrottled_usec 12000\nnr_bursts 0\nburst_usec 0\n";
+        assert_eq!(
+            CpuStat::parse(v2),
+            Some(CpuStat {
+                nr_periods: 50,
+                nr_throttled: 5,
+                throttled_usec: 12000,
+            })
+        );
+        let v1 = "nr_periods 10\nnr_throttled 2\nthrottled_time 3000000\n";
+        assert_eq!(CpuStat::parse(v1).unwrap().throttled_usec, 3000);
+        // cpu controller not enabled
+        assert_eq!(CpuStat::parse("usage_usec 1000\nuser_usec 600\n"), None);
+    }
+
+    #[test]
+    fn test_throttle_delta() {
+        let start = Instant::now();
+        let before = snapshot(
+            start,
+            &[("/a", 100, 10, 5000), ("/b", 100, 0, 0), ("/c", 50, 5, 100)],
+        );
+        let after = snapshot(
+            start + Duration::from_secs(1),
+            &[
+                ("/a", 110, 15, 9000),
+                ("/b", 100, 0, 0),
+                ("/c", 4, 1, 20),
+                ("/d", 10, 1, 1),
+            ],
+        );
+        let deltas = ThrottleDelta::between(&before, &after);
+        // `/b` had no period elapse, `/d` is new
+        assert_eq!(deltas.len(), 2);
+        assert_eq!(deltas[0].cgroup, "/a");
+        assert_eq!(deltas[0].periods, 10);
+        assert_eq!(deltas[0].throttled_periods, 5);
+        assert_eq!(deltas[0].throttled_usec, 4000);
+        assert_eq!(deltas[0].throttled_ratio, 0.5);
+        assert_eq!(deltas[0].interval, Duration::from_secs(1));
+        // recreated
+        assert_eq!(deltas[1].cgroup, "/c");
+        assert_eq!(deltas[1].periods, 4);
+        assert_eq!(deltas[1].throttled_periods, 1);
+    }
+
+    #[test]
+    fn test_throttle_watcher() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut watcher =
+            ThrottleWatcher::from_snapshot(snapshot(at(0), &[("/a", 0, 0, 0), ("/b", 0, 0, 0)]));
+
+        let newly = watcher.advance(snapshot(at(1), &[("/a", 10, 2, 100), ("/b", 10, 0, 0)]));
+        assert_eq!(newly.len(), 1);
+        assert_eq!(newly[0].cgroup, "/a");
+
+        // still throttled, not new
+        let newly = watcher.advance(snapshot(at(2), &[("/a", 20, 4, 200), ("/b", 20, 1, 10)]));
+        assert_eq!(newly.len(), 1);
+        assert_eq!(newly[0].cgroup, "/b");
+
+        // `/a` recovers and is throttled again
+        watcher.advance(snapshot(at(3), &[("/a", 30, 4, 200), ("/b", 30, 2, 20)]));
+        let newly = watcher.advance(snapshot(at(4), &[("/a", 40, 5, 300), ("/b", 40, 3, 30)]));
+        assert_eq!(newly.len(), 1);
+        assert_eq!(newly[0].cgroup, "/a");
+    }
+}
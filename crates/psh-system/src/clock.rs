@@ -0,0 +1,172 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{io, sync::Mutex};
+
+use crate::error::{Error, Result};
+
+// NTP slews the wall clock by at most 500ppm, a larger rate is a step
+const MAX_DRIFT: f64 = 500e-6;
+// in nanoseconds of the raw clock
+const REFRESH_INTERVAL: u64 = 1_000_000_000;
+// weight of a new rate estimate
+const SMOOTHING: f64 = 0.25;
+// readings of both clocks, the tightest one is kept
+const READINGS: usize = 3;
+
+/// Maps `CLOCK_MONOTONIC_RAW` timestamps to the wall clock so profiles line
+/// up with application logs.
+///
+/// Perf events only stamp samples with that clock when opened with
+/// `use_clockid` and `clockid = CLOCK_MONOTONIC_RAW`, by default they use
+/// the kernel's perf clock, which has another base and is not mapped here.
+///
+/// The raw clock ticks at the uncorrected hardware rate while NTP slews the
+/// wall clock, a fixed offset between them drifts apart by the hour. The
+/// mapping is re-anchored every second and the rate between the clocks is
+/// tracked across anchors.
+#[derive(Debug)]
+pub struct PerfClock {
+    model: Mutex<Model>,
+}
+
+impl PerfClock {
+    pub fn new() -> Result<Self> {
+        let (raw, wall) = read_pair()?;
+        Ok(Self {
+            model: Mutex::new(Model::new(raw, wall)),
+        })
+    }
+
+    /// Wall clock time in nanoseconds since the epoch of a perf timestamp.
+    pub fn to_wall(&self, raw: u64) -> Result<u64> {
+        let mut model = self.model.lock().map_err(|_| Error::Sync)?;
+        if clock_ns(libc::CLOCK_MONOTONIC_RAW)?.saturating_sub(model.raw) >= REFRESH_INTERVAL {
+            let (raw, wall) = read_pair()?;
+            model.observe(raw, wall);
+        }
+        Ok(model.map(raw))
+    }
+
+    /// Estimated drift of the wall clock against the raw clock, in parts per
+    /// million.
+    pub fn drift_ppm(&self) -> Result<f64> {
+        let model = self.model.lock().map_err(|_| Error::Sync)?;
+        Ok((model.rate - 1.0) * 1e6)
+    }
+}
+
+/// Linear mapping anchored on one reading of both clocks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Model {
+    raw: u64,
+    wall: u64,
+    /// wall clock nanoseconds per raw nanosecond
+    rate: f64,
+}
+
+impl Model {
+    const fn new(raw: u64, wall: u64) -> Self {
+        Self {
+            raw,
+            wall,
+            rate: 1.0,
+        }
+    }
+
+    fn map(&self, raw: u64) -> u64 {
+        let delta = (i128::from(raw) - i128::from(self.raw)) as f64 * self.rate;
+        (i128::from(self.wall) + delta as i128).max(0) as u64
+    }
+
+    /// Re-anchors on a new reading, a step of the wall clock starts over.
+    fn observe(&mut self, raw: u64, wall: u64) {
+        let span = raw.saturating_sub(self.raw);
+        if span == 0 {
+            return;
+        }
+        let rate = (wall as f64 - self.wall as f64) / span as f64;
+        if (rate - 1.0).abs() > MAX_DRIFT {
+            *self = Self::new(raw, wall);
+            return;
+        }
+        *self = Self {
+            raw,
+            wall,
+            rate: (rate - self.rate).mul_add(SMOOTHING, self.rate),
+        };
+    }
+}
+
+/// The raw clock read between two reads of the wall clock, which is taken
+/// as their midpoint.
+fn read_pair() -> Result<(u64, u64)> {
+    let mut best = (0, 0, u64::MAX);
+    for _ in 0..READINGS {
+        let before = clock_ns(libc::CLOCK_REALTIME)?;
+        let raw = clock_ns(libc::CLOCK_MONOTONIC_RAW)?;
+        let after = clock_ns(libc::CLOCK_REALTIME)?;
+        let width = after.saturating_sub(before);
+        if width < best.2 {
+            best = (raw, before + width / 2, width);
+        }
+    }
+    Ok((best.0, best.1))
+}
+
+fn clock_ns(clock: libc::clockid_t) -> Result<u64> {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `ts` is a valid timespec for the kernel to fill in
+    if unsafe { libc::clock_gettime(clock, &mut ts) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Model, PerfClock};
+
+    const SEC: u64 = 1_000_000_000;
+    const WALL: u64 = 1_700_000_000 * SEC;
+
+    #[test]
+    fn test_model_tracks_drift() {
+        let mut model = Model::new(100 * SEC, WALL);
+        assert_eq!(model.map(101 * SEC), WALL + SEC);
+        assert_eq!(model.map(99 * SEC), WALL - SEC);
+
+        // the wall clock runs 100ppm fast
+        for i in 1..=40 {
+            model.observe((100 + i) * SEC, WALL + i * (SEC + 100_000));
+        }
+        assert!((model.rate - 1.0001).abs() < 1e-8);
+        assert!(model.map(141 * SEC).abs_diff(WALL + 41 * (SEC + 100_000)) < 10);
+
+        // a step of the wall clock is no drift
+        model.observe(141 * SEC, WALL + 3600 * SEC);
+        assert_eq!(model, Model::new(141 * SEC, WALL + 3600 * SEC));
+    }
+
+    #[test]
+    fn test_to_wall() {
+        let clock = PerfClock::new().unwrap();
+        let raw = super::clock_ns(libc::CLOCK_MONOTONIC_RAW).unwrap();
+        let wall = super::clock_ns(libc::CLOCK_REALTIME).unwrap();
+        assert!(clock.to_wall(raw).unwrap().abs_diff(wall) < SEC);
+    }
+}
@@ -14,26 +14,25 @@
 
 use std::{sync::LazyLock, time::Duration};
 
-use procfs::CurrentSI;
+use super::{CpuInfo, CpuStats, CpuTopology, Vulnerability};
+use crate::{error::Result, sys, utils::Handle};
 
-use super::{CpuInfo, CpuStats, raw::parse_cpuinfo};
-use crate::{error::Result, utils::Handle};
+static INFO_GLOBAL: LazyLock<Handle<CpuInfo>> = LazyLock::new(|| Handle::new(sys::cpu_info));
 
-static INFO_GLOBAL: LazyLock<Handle<CpuInfo>> =
-    LazyLock::new(|| Handle::new(|| parse_cpuinfo!().map_err(Into::into)));
+static STAT_GLOBAL: LazyLock<Handle<CpuStats>> = LazyLock::new(|| Handle::new(sys::cpu_stats));
 
-static STAT_GLOBAL: LazyLock<Handle<CpuStats>> = LazyLock::new(|| {
-    Handle::new(|| {
-        procfs::KernelStats::current()
-            .map(Into::into)
-            .map_err(Into::into)
-    })
-});
+static TOPOLOGY_GLOBAL: LazyLock<Handle<CpuTopology>> =
+    LazyLock::new(|| Handle::new(sys::cpu_topology));
+
+static VULNERABILITIES_GLOBAL: LazyLock<Handle<Vec<Vulnerability>>> =
+    LazyLock::new(|| Handle::new(sys::cpu_vulnerabilities));
 
 #[derive(Debug, Clone)]
 pub struct CpuHandle {
     info: Handle<CpuInfo>,
     stat: Handle<CpuStats>,
+    topology: Handle<CpuTopology>,
+    vulnerabilities: Handle<Vec<Vulnerability>>,
 }
 
 impl Default for CpuHandle {
@@ -41,6 +40,8 @@ impl Default for CpuHandle {
         Self {
             info: INFO_GLOBAL.clone(),
             stat: STAT_GLOBAL.clone(),
+            topology: TOPOLOGY_GLOBAL.clone(),
+            vulnerabilities: VULNERABILITIES_GLOBAL.clone(),
         }
     }
 }
@@ -57,4 +58,15 @@ impl CpuHandle {
     pub fn stat(&self, interval: Option<Duration>) -> Result<CpuStats> {
         self.stat.get(interval)
     }
+
+    /// Cpus go online and offline, `interval` works as for [`Self::stat`].
+    pub fn topology(&self, interval: Option<Duration>) -> Result<CpuTopology> {
+        self.topology.get(interval)
+    }
+
+    /// Mitigations change with the kernel command line and microcode, both
+    /// need a reboot, so this is read once.
+    pub fn vulnerabilities(&self) -> Result<Vec<Vulnerability>> {
+        self.vulnerabilities.get(None)
+    }
 }
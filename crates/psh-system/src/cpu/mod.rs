@@ -12,16 +12,18 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
-use std::str::FromStr;
+use std::{collections::BTreeMap, str::FromStr};
 
 use crate::error::{Error, Result};
 
 pub(crate) mod handle;
-mod raw;
+pub(crate) mod raw;
+pub(crate) mod topology;
+pub(crate) mod vulnerability;
 
 pub use handle::CpuHandle;
-pub use procfs::CpuTime;
-use procfs::KernelStats;
+pub use procfs_core::CpuTime;
+use procfs_core::KernelStats;
 
 // use Vec<bool> to represent CpuMask but wrap it in a tuple struct to make it a distinct type
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -83,7 +85,7 @@ pub struct Arm64CpuInfo {
 }
 
 impl Arm64CpuInfo {
-    const fn new() -> Self {
+    pub(crate) const fn new() -> Self {
         Self {
             processor: 0,
             bogomips: 0.0,
@@ -130,7 +132,7 @@ pub struct X86_64CpuInfo {
 }
 
 impl X86_64CpuInfo {
-    const fn new() -> Self {
+    pub(crate) const fn new() -> Self {
         Self {
             processor: 0,
             vendor_id: String::new(),
@@ -163,10 +165,85 @@ impl X86_64CpuInfo {
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RiscV64CpuInfo {
+    pub processor: usize,
+    pub hart: usize,
+    /// e.g. `rv64imafdc_zicntr_zicsr`
+    pub isa: String,
+    /// single letter and multi letter extensions of `isa`, e.g. `i`, `zicsr`
+    pub extensions: Vec<String>,
+    /// e.g. `sv39`
+    pub mmu: String,
+    /// e.g. `thead,c910`
+    pub uarch: String,
+    pub mvendorid: u64,
+    pub marchid: u64,
+    pub mimpid: u64,
+}
+
+impl RiscV64CpuInfo {
+    pub(crate) const fn new() -> Self {
+        Self {
+            processor: 0,
+            hart: 0,
+            isa: String::new(),
+            extensions: Vec::<String>::new(),
+            mmu: String::new(),
+            uarch: String::new(),
+            mvendorid: 0,
+            marchid: 0,
+            mimpid: 0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct S390xCpuInfo {
+    pub processor: usize,
+    pub vendor_id: String,
+    pub bogomips: f32,
+    pub features: Vec<String>,
+    pub physical_id: usize,
+    pub core_id: usize,
+    pub book_id: usize,
+    pub drawer_id: usize,
+    /// hex, e.g. `00`
+    pub version: String,
+    /// hex, e.g. `0F7A58`
+    pub identification: String,
+    /// machine type, e.g. `3931` for z16
+    pub machine: String,
+    pub cpu_mhz_dynamic: u32,
+    pub cpu_mhz_static: u32,
+}
+
+impl S390xCpuInfo {
+    pub(crate) const fn new() -> Self {
+        Self {
+            processor: 0,
+            vendor_id: String::new(),
+            bogomips: 0.0,
+            features: Vec::<String>::new(),
+            physical_id: 0,
+            core_id: 0,
+            book_id: 0,
+            drawer_id: 0,
+            version: String::new(),
+            identification: String::new(),
+            machine: String::new(),
+            cpu_mhz_dynamic: 0,
+            cpu_mhz_static: 0,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum CpuInfo {
     X86_64(Vec<X86_64CpuInfo>),
     Arm64(Vec<Arm64CpuInfo>),
+    RiscV64(Vec<RiscV64CpuInfo>),
+    S390x(Vec<S390xCpuInfo>),
     Unsupported(String),
 }
 
@@ -201,6 +278,99 @@ impl From<KernelStats> for CpuStats {
     }
 }
 
+/// Where a logical cpu sits in the machine.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CpuPlacement {
+    pub cpu: u32,
+    /// physical package
+    pub socket: u32,
+    /// die of the package, 0 on kernels before 5.5
+    pub die: u32,
+    pub core: u32,
+    /// NUMA node, 0 on machines without NUMA
+    pub node: u32,
+    /// hardware threads of the same core, including this cpu
+    pub thread_siblings: Vec<u32>,
+    pub caches: Vec<CpuCache>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub enum CacheKind {
+    Data,
+    Instruction,
+    Unified,
+}
+
+/// One of `/sys/devices/system/cpu/cpu*/cache/index*`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CpuCache {
+    pub level: u8,
+    pub kind: CacheKind,
+    /// in bytes, 0 if the firmware doesn't tell
+    pub size: u64,
+    /// in bytes
+    pub line_size: u32,
+    pub ways: u32,
+    /// cpus sharing this cache
+    pub shared_cpus: Vec<u32>,
+}
+
+/// The online cpus ordered by id.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CpuTopology(pub Vec<CpuPlacement>);
+
+impl CpuTopology {
+    /// Cpus of every socket.
+    pub fn sockets(&self) -> BTreeMap<u32, Vec<u32>> {
+        self.group_by(|it| it.socket)
+    }
+
+    /// Cpus of every NUMA node.
+    pub fn nodes(&self) -> BTreeMap<u32, Vec<u32>> {
+        self.group_by(|it| it.node)
+    }
+
+    /// Every cache once, ordered by level, kind and the cpus sharing it.
+    pub fn caches(&self) -> Vec<CpuCache> {
+        self.0
+            .iter()
+            .flat_map(|it| &it.caches)
+            .map(|it| ((it.level, it.kind, it.shared_cpus.clone()), it.clone()))
+            .collect::<BTreeMap<_, _>>()
+            .into_values()
+            .collect()
+    }
+
+    fn group_by(&self, key: impl Fn(&CpuPlacement) -> u32) -> BTreeMap<u32, Vec<u32>> {
+        let mut groups = BTreeMap::<u32, Vec<u32>>::new();
+        for it in &self.0 {
+            groups.entry(key(it)).or_default().push(it.cpu);
+        }
+        groups
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum VulnerabilityStatus {
+    NotAffected,
+    Mitigated,
+    Vulnerable,
+    /// e.g. `Unknown: Dependent on hypervisor status`
+    Unknown,
+}
+
+/// One of `/sys/devices/system/cpu/vulnerabilities`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Vulnerability {
+    /// e.g. `spectre_v2`
+    pub name: String,
+    pub status: VulnerabilityStatus,
+    /// what the kernel does about it, partial ones for vulnerable cpus
+    pub mitigation: Option<String>,
+    /// the line reported by the kernel
+    pub detail: String,
+}
+
 #[cfg(test)]
 mod tests {
     use super::CpuMask;
@@ -13,11 +13,14 @@
 // see <https://www.gnu.org/licenses/>.
 
 use std::{
+    collections::BTreeMap,
     fs::File,
     io::{self, BufRead, BufReader},
 };
 
-use super::{AddressSizes, Arm64CpuInfo, CpuInfo, TlbSize, X86_64CpuInfo};
+use super::{
+    AddressSizes, Arm64CpuInfo, CpuInfo, RiscV64CpuInfo, S390xCpuInfo, TlbSize, X86_64CpuInfo,
+};
 
 fn parse_unit(unit: &str) -> u32 {
     match unit.trim() {
@@ -241,6 +244,159 @@ fn parse_aarch64_cpu_info(reader: BufReader<File>) -> io::Result<Vec<Arm64CpuInf
     Ok(cpu_info_list)
 }
 
+fn parse_hex(value: &str) -> u64 {
+    u64::from_str_radix(value.trim_start_matches("0x"), 16).unwrap_or(0)
+}
+
+/// `rv64imafdc_zicsr_zifencei` to `i`, `m`, `a`, `f`, `d`, `c`, `zicsr`, `zifencei`.
+fn parse_isa_extensions(isa: &str) -> Vec<String> {
+    let isa = isa.to_ascii_lowercase();
+    let Some(rest) = isa
+        .strip_prefix("rv64")
+        .or_else(|| isa.strip_prefix("rv32"))
+    else {
+        return Vec::new();
+    };
+    let mut parts = rest.split('_');
+    let single = parts.next().unwrap_or_default();
+    single
+        .chars()
+        .map(String::from)
+        .chain(parts.filter(|s| !s.is_empty()).map(|s| s.to_string()))
+        .collect()
+}
+
+fn parse_riscv64_cpu_info(reader: BufReader<File>) -> io::Result<Vec<RiscV64CpuInfo>> {
+    let mut cpu_info_list = Vec::new();
+    let mut current_cpu_info = RiscV64CpuInfo::new();
+
+    for line in reader.lines().map_while(Result::ok) {
+        if line.is_empty() {
+            // Empty line indicates the end of one CPU's information
+            cpu_info_list.push(current_cpu_info);
+            current_cpu_info = RiscV64CpuInfo::new();
+            continue;
+        }
+
+        if let Some((key, value)) = line.split_once(':') {
+            let key = key.trim();
+            let value = value.trim();
+
+            match key {
+                "processor" => {
+                    current_cpu_info.processor = value.parse().unwrap_or(0);
+                }
+                "hart" => {
+                    current_cpu_info.hart = value.parse().unwrap_or(0);
+                }
+                // `hart isa` of newer kernels is the subset usable on this
+                // hart, `isa` the one common to all harts
+                "isa" => {
+                    current_cpu_info.isa = value.to_string();
+                    current_cpu_info.extensions = parse_isa_extensions(value);
+                }
+                "mmu" => {
+                    current_cpu_info.mmu = value.to_string();
+                }
+                "uarch" => {
+                    current_cpu_info.uarch = value.to_string();
+                }
+                "mvendorid" => {
+                    current_cpu_info.mvendorid = parse_hex(value);
+                }
+                "marchid" => {
+                    current_cpu_info.marchid = parse_hex(value);
+                }
+                "mimpid" => {
+                    current_cpu_info.mimpid = parse_hex(value);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(cpu_info_list)
+}
+
+/// s390x lists what all cpus share once up front, then a block per cpu.
+fn parse_s390x_cpu_info(reader: BufReader<File>) -> io::Result<Vec<S390xCpuInfo>> {
+    let mut shared = S390xCpuInfo::new();
+    let mut cpus = BTreeMap::<usize, S390xCpuInfo>::new();
+    let mut current: Option<usize> = None;
+
+    for line in reader.lines().map_while(Result::ok) {
+        if line.is_empty() {
+            current = None;
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim();
+        let value = value.trim();
+
+        // `processor 0: version = 00,  identification = 0F7A58,  machine = 3931`
+        if let Some(processor) = key.strip_prefix("processor ") {
+            let Ok(processor) = processor.parse() else {
+                continue;
+            };
+            let cpu = cpus.entry(processor).or_insert_with(S390xCpuInfo::new);
+            for field in value.split(',') {
+                match field.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                    Some(("version", v)) => cpu.version = v.to_string(),
+                    Some(("identification", v)) => cpu.identification = v.to_string(),
+                    Some(("machine", v)) => cpu.machine = v.to_string(),
+                    _ => {}
+                }
+            }
+            continue;
+        }
+        if key == "cpu number" {
+            current = value.parse().ok();
+            continue;
+        }
+
+        let Some(cpu) = current.map(|it| cpus.entry(it).or_insert_with(S390xCpuInfo::new)) else {
+            match key {
+                "vendor_id" => shared.vendor_id = value.to_string(),
+                "bogomips per cpu" => shared.bogomips = value.parse().unwrap_or(0.0),
+                "features" => {
+                    shared.features = value
+                        .split_ascii_whitespace()
+                        .map(|s| s.to_string())
+                        .collect();
+                }
+                _ => {}
+            }
+            continue;
+        };
+        match key {
+            "physical id" => cpu.physical_id = value.parse().unwrap_or(0),
+            "core id" => cpu.core_id = value.parse().unwrap_or(0),
+            "book id" => cpu.book_id = value.parse().unwrap_or(0),
+            "drawer id" => cpu.drawer_id = value.parse().unwrap_or(0),
+            "version" => cpu.version = value.to_string(),
+            "identification" => cpu.identification = value.to_string(),
+            "machine" => cpu.machine = value.to_string(),
+            "cpu MHz dynamic" => cpu.cpu_mhz_dynamic = value.parse().unwrap_or(0),
+            "cpu MHz static" => cpu.cpu_mhz_static = value.parse().unwrap_or(0),
+            _ => {}
+        }
+    }
+
+    Ok(cpus
+        .into_iter()
+        .map(|(processor, cpu)| S390xCpuInfo {
+            processor,
+            vendor_id: shared.vendor_id.clone(),
+            bogomips: shared.bogomips,
+            features: shared.features.clone(),
+            ..cpu
+        })
+        .collect())
+}
+
 #[allow(dead_code)]
 pub fn do_parse_cpuinfo(path: &str, arch: &str) -> io::Result<CpuInfo> {
     let file = File::open(path)?;
@@ -255,6 +411,14 @@ pub fn do_parse_cpuinfo(path: &str, arch: &str) -> io::Result<CpuInfo> {
             let aarch64_cpu_info = parse_aarch64_cpu_info(reader)?;
             CpuInfo::Arm64(aarch64_cpu_info)
         }
+        "riscv64" => {
+            let riscv64_cpu_info = parse_riscv64_cpu_info(reader)?;
+            CpuInfo::RiscV64(riscv64_cpu_info)
+        }
+        "s390x" => {
+            let s390x_cpu_info = parse_s390x_cpu_info(reader)?;
+            CpuInfo::S390x(s390x_cpu_info)
+        }
         _ => CpuInfo::Unsupported(format!("unsupported architecture {}", arch)),
     };
 
@@ -277,7 +441,9 @@ pub(crate) use parse_cpuinfo;
 mod test {
     use std::path::PathBuf;
 
-    use crate::cpu::{AddressSizes, Arm64CpuInfo, CpuInfo, TlbSize, X86_64CpuInfo};
+    use crate::cpu::{
+        AddressSizes, Arm64CpuInfo, CpuInfo, RiscV64CpuInfo, S390xCpuInfo, TlbSize, X86_64CpuInfo,
+    };
 
     #[test]
     #[cfg(target_os = "linux")]
@@ -749,4 +915,92 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_parse_cpu_info_riscv64() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("./test_resources/arch/riscv64/t-head/cpuinfo");
+        let binding = d.into_os_string();
+        let cpuinfo_path = binding.to_str().unwrap();
+
+        let cpus = 4;
+        let cpu_info = parse_cpuinfo!(cpuinfo_path, "riscv64").unwrap();
+        match cpu_info {
+            CpuInfo::RiscV64(cpu_vec) => {
+                let cpu3 = RiscV64CpuInfo {
+                    processor: 3,
+                    hart: 3,
+                    isa: "rv64imafdc_zicntr_zicsr_zifencei_zihpm_xtheadvector".to_string(),
+                    extensions: [
+                        "i",
+                        "m",
+                        "a",
+                        "f",
+                        "d",
+                        "c",
+                        "zicntr",
+                        "zicsr",
+                        "zifencei",
+                        "zihpm",
+                        "xtheadvector",
+                    ]
+                    .into_iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+                    mmu: "sv39".to_string(),
+                    uarch: "thead,c910".to_string(),
+                    mvendorid: 0x5b7,
+                    marchid: 0,
+                    mimpid: 0,
+                };
+                assert_eq!(cpu3, cpu_vec[3]);
+                assert_eq!(cpus, cpu_vec.len());
+            }
+            _ => {
+                panic!("Should not reach here");
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_cpu_info_s390x() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("./test_resources/arch/s390x/ibm/cpuinfo");
+        let binding = d.into_os_string();
+        let cpuinfo_path = binding.to_str().unwrap();
+
+        let cpus = 2;
+        let cpu_info = parse_cpuinfo!(cpuinfo_path, "s390x").unwrap();
+        match cpu_info {
+            CpuInfo::S390x(cpu_vec) => {
+                let cpu1 = S390xCpuInfo {
+                    processor: 1,
+                    vendor_id: "IBM/S390".to_string(),
+                    bogomips: 3241.0,
+                    features: [
+                        "esan3", "zarch", "stfle", "msa", "ldisp", "eimm", "dfp", "edat", "etf3eh",
+                        "highgprs", "te", "vx", "vxd", "vxe", "gs", "vxe2", "vxp", "sort", "dflt",
+                        "sie",
+                    ]
+                    .into_iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+                    physical_id: 2,
+                    core_id: 0,
+                    book_id: 2,
+                    drawer_id: 1,
+                    version: "00".to_string(),
+                    identification: "0F7A58".to_string(),
+                    machine: "3931".to_string(),
+                    cpu_mhz_dynamic: 5200,
+                    cpu_mhz_static: 5200,
+                };
+                assert_eq!(cpu1, cpu_vec[1]);
+                assert_eq!(cpus, cpu_vec.len());
+            }
+            _ => {
+                panic!("Should not reach here");
+            }
+        }
+    }
 }
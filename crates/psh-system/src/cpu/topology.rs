@@ -0,0 +1,225 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{collections::HashMap, fs, io, path::Path};
+
+use super::{CacheKind, CpuCache, CpuPlacement, CpuTopology};
+
+/// Parses a kernel cpu list like `0-3,8,10-11`.
+pub fn parse_cpu_list(list: &str) -> io::Result<Vec<u32>> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid cpu list {list}"),
+        )
+    };
+    let mut cpus = vec![];
+    for range in list.trim().split(',').filter(|it| !it.is_empty()) {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let start: u32 = start.parse().map_err(|_| invalid())?;
+        let end: u32 = end.parse().map_err(|_| invalid())?;
+        if start > end {
+            return Err(invalid());
+        }
+        cpus.extend(start..=end);
+    }
+    Ok(cpus)
+}
+
+fn read_id(path: &Path) -> io::Result<u32> {
+    // -1 where the firmware doesn't tell
+    let id: i64 = fs::read_to_string(path)?
+        .trim()
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, path.display().to_string()))?;
+    Ok(id.max(0) as u32)
+}
+
+/// `32K` or `16M` in bytes.
+fn parse_size(size: &str) -> u64 {
+    let size = size.trim();
+    let (num, unit) = match size.strip_suffix('K') {
+        Some(num) => (num, 1 << 10),
+        None => match size.strip_suffix('M') {
+            Some(num) => (num, 1 << 20),
+            None => (size, 1),
+        },
+    };
+    num.parse::<u64>().map_or(0, |it| it * unit)
+}
+
+fn read_caches(cpu_dir: &Path) -> io::Result<Vec<CpuCache>> {
+    let entries = match fs::read_dir(cpu_dir.join("cache")) {
+        Ok(entries) => entries,
+        // cache information is up to the firmware, often absent in VMs
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut caches = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with("index") {
+            continue;
+        }
+        let dir = entry.path();
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap_or_default();
+        let kind = match read("type").trim() {
+            "Data" => CacheKind::Data,
+            "Instruction" => CacheKind::Instruction,
+            "Unified" => CacheKind::Unified,
+            _ => continue,
+        };
+        caches.push(CpuCache {
+            level: read("level").trim().parse().unwrap_or(0),
+            kind,
+            size: parse_size(&read("size")),
+            line_size: read("coherency_line_size").trim().parse().unwrap_or(0),
+            ways: read("ways_of_associativity").trim().parse().unwrap_or(0),
+            shared_cpus: parse_cpu_list(&read("shared_cpu_list"))?,
+        });
+    }
+    caches.sort_by_key(|it| (it.level, it.kind));
+    Ok(caches)
+}
+
+/// Reads the placement of the online cpus from `sys`, usually `/sys`.
+pub fn read_topology(sys: &Path) -> io::Result<CpuTopology> {
+    let cpu_dir = sys.join("devices/system/cpu");
+    let node_dir = sys.join("devices/system/node");
+
+    let mut nodes = HashMap::new();
+    // kernels without NUMA support have no node directory
+    if let Ok(entries) = fs::read_dir(&node_dir) {
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(node) = name
+                .to_str()
+                .and_then(|it| it.strip_prefix("node"))
+                .and_then(|it| it.parse::<u32>().ok())
+            else {
+                continue;
+            };
+            let cpulist = fs::read_to_string(entry.path().join("cpulist"))?;
+            for cpu in parse_cpu_list(&cpulist)? {
+                nodes.insert(cpu, node);
+            }
+        }
+    }
+
+    let online = fs::read_to_string(cpu_dir.join("online"))?;
+    let placements = parse_cpu_list(&online)?
+        .into_iter()
+        .map(|cpu| {
+            let dir = cpu_dir.join(format!("cpu{cpu}"));
+            let topology = dir.join("topology");
+            let thread_siblings = match fs::read_to_string(topology.join("thread_siblings_list")) {
+                Ok(list) => parse_cpu_list(&list)?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => vec![cpu],
+                Err(e) => return Err(e),
+            };
+            Ok(CpuPlacement {
+                cpu,
+                socket: read_id(&topology.join("physical_package_id"))?,
+                die: read_id(&topology.join("die_id")).unwrap_or(0),
+                core: read_id(&topology.join("core_id"))?,
+                node: nodes.get(&cpu).copied().unwrap_or(0),
+                thread_siblings,
+                caches: read_caches(&dir)?,
+            })
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(CpuTopology(placements))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    use super::{parse_cpu_list, parse_size, read_topology};
+    use crate::cpu::{CacheKind, CpuCache, CpuPlacement};
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n").unwrap(),
+            [0, 1, 2, 3, 8, 10, 11]
+        );
+        assert_eq!(parse_cpu_list("5").unwrap(), [5]);
+        assert!(parse_cpu_list("").unwrap().is_empty());
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("a").is_err());
+    }
+
+    #[test]
+    fn test_read_topology() {
+        let mut sys = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sys.push("./test_resources/arch/x86_64/intel/sys");
+
+        let topology = read_topology(&sys).unwrap();
+        assert_eq!(topology.0.len(), 4);
+        let cache = |level, kind, size, ways, shared_cpus: Vec<u32>| CpuCache {
+            level,
+            kind,
+            size,
+            line_size: 64,
+            ways,
+            shared_cpus,
+        };
+        assert_eq!(
+            topology.0[2],
+            CpuPlacement {
+                cpu: 2,
+                socket: 1,
+                die: 0,
+                core: 0,
+                node: 1,
+                thread_siblings: vec![2],
+                caches: vec![
+                    cache(1, CacheKind::Data, 32 << 10, 8, vec![2]),
+                    cache(1, CacheKind::Instruction, 32 << 10, 8, vec![2]),
+                    cache(2, CacheKind::Unified, 1 << 20, 16, vec![2]),
+                    cache(3, CacheKind::Unified, 36608 << 10, 11, vec![2, 3]),
+                ],
+            }
+        );
+        // without die_id and thread_siblings_list, as before linux 5.5
+        assert_eq!(topology.0[3].die, 0);
+        assert_eq!(topology.0[3].thread_siblings, [3]);
+
+        let caches = topology.caches();
+        assert_eq!(caches.len(), 14);
+        assert_eq!(
+            caches
+                .iter()
+                .filter(|it| it.level == 3)
+                .map(|it| it.shared_cpus.clone())
+                .collect::<Vec<_>>(),
+            [vec![0, 1], vec![2, 3]]
+        );
+        assert_eq!(
+            topology.nodes(),
+            BTreeMap::from([(0, vec![0, 1]), (1, vec![2, 3])])
+        );
+        assert_eq!(topology.sockets(), topology.nodes());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("32K\n"), 32 << 10);
+        assert_eq!(parse_size("16M"), 16 << 20);
+        assert_eq!(parse_size("512"), 512);
+        assert_eq!(parse_size(""), 0);
+    }
+}
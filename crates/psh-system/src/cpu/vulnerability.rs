@@ -0,0 +1,145 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{fs, io, path::Path};
+
+use super::{Vulnerability, VulnerabilityStatus};
+
+/// `Mitigation: PTI`, `Vulnerable: Clear CPU buffers attempted, no microcode; SMT vulnerable`,
+/// `Not affected` and the like.
+pub fn parse_vulnerability(name: &str, content: &str) -> Vulnerability {
+    let content = content.trim();
+    // itlb_multihit reports the hypervisor side, e.g. `KVM: Mitigation: VMX disabled`
+    let state = content.strip_prefix("KVM: ").unwrap_or(content);
+    let (status, mitigation) = if state == "Not affected" {
+        (VulnerabilityStatus::NotAffected, None)
+    } else if let Some(mitigation) = state.strip_prefix("Mitigation: ") {
+        (VulnerabilityStatus::Mitigated, Some(mitigation.to_string()))
+    } else if state.starts_with("Vulnerable") || state.starts_with("Processor vulnerable") {
+        // a partial mitigation follows the colon, e.g. `Vulnerable: eIBRS with unprivileged eBPF`
+        let mitigation = state.split_once(": ").map(|(_, it)| it.to_string());
+        (VulnerabilityStatus::Vulnerable, mitigation)
+    } else {
+        (VulnerabilityStatus::Unknown, None)
+    };
+    Vulnerability {
+        name: name.to_string(),
+        status,
+        mitigation,
+        detail: content.to_string(),
+    }
+}
+
+/// Reads `devices/system/cpu/vulnerabilities` of `sys`, usually `/sys`,
+/// ordered by name.
+pub fn read_vulnerabilities(sys: &Path) -> io::Result<Vec<Vulnerability>> {
+    let dir = sys.join("devices/system/cpu/vulnerabilities");
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        // added in linux 4.15, older kernels don't tell
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut vulnerabilities = vec![];
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let content = fs::read_to_string(entry.path())?;
+        vulnerabilities.push(parse_vulnerability(&name, &content));
+    }
+    vulnerabilities.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(vulnerabilities)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{parse_vulnerability, read_vulnerabilities};
+    use crate::cpu::VulnerabilityStatus;
+
+    #[test]
+    fn test_parse_vulnerability() {
+        let status = |content| {
+            let it = parse_vulnerability("test", content);
+            (it.status, it.mitigation)
+        };
+        assert_eq!(
+            status("Not affected\n"),
+            (VulnerabilityStatus::NotAffected, None)
+        );
+        assert_eq!(
+            status("Mitigation: PTI"),
+            (VulnerabilityStatus::Mitigated, Some("PTI".to_string()))
+        );
+        assert_eq!(
+            status("KVM: Mitigation: VMX disabled"),
+            (
+                VulnerabilityStatus::Mitigated,
+                Some("VMX disabled".to_string())
+            )
+        );
+        assert_eq!(
+            status("Vulnerable"),
+            (VulnerabilityStatus::Vulnerable, None)
+        );
+        assert_eq!(
+            status("Vulnerable: Clear CPU buffers attempted, no microcode; SMT vulnerable"),
+            (
+                VulnerabilityStatus::Vulnerable,
+                Some("Clear CPU buffers attempted, no microcode; SMT vulnerable".to_string())
+            )
+        );
+        assert_eq!(
+            status("Unknown: Dependent on hypervisor status"),
+            (VulnerabilityStatus::Unknown, None)
+        );
+    }
+
+    #[test]
+    fn test_read_vulnerabilities() {
+        let mut sys = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sys.push("./test_resources/arch/x86_64/intel/sys");
+
+        let vulnerabilities = read_vulnerabilities(&sys).unwrap();
+        let names = vulnerabilities
+            .iter()
+            .map(|it| it.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "itlb_multihit",
+                "l1tf",
+                "mds",
+                "meltdown",
+                "spec_store_bypass",
+                "spectre_v1",
+                "spectre_v2",
+            ]
+        );
+        assert_eq!(vulnerabilities[2].status, VulnerabilityStatus::Vulnerable);
+        assert_eq!(
+            vulnerabilities[6].mitigation.as_deref(),
+            Some(
+                "Enhanced / Automatic IBRS; IBPB: conditional; RSB filling; PBRSB-eIBRS: SW sequence; BHI: SW loop, KVM: SW loop"
+            )
+        );
+        assert!(
+            read_vulnerabilities(&sys.join("nonexistent"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
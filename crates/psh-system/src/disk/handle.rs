@@ -14,12 +14,12 @@
 
 use std::{sync::LazyLock, time::Duration};
 
-use procfs::DiskStat;
+use procfs_core::DiskStat;
 
-use crate::{error::Result, utils::Handle};
+use crate::{error::Result, sys, utils::Handle};
 
 static STAT_GLOBAL: LazyLock<Handle<Vec<DiskStat>>> =
-    LazyLock::new(|| Handle::new(|| procfs::diskstats().map_err(Into::into)));
+    LazyLock::new(|| Handle::new(sys::disk_stats));
 
 #[derive(Debug, Clone)]
 pub struct DiskHandle(Handle<Vec<DiskStat>>);
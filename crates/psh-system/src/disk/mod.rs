@@ -14,4 +14,4 @@
 
 pub(crate) mod handle;
 pub use handle::DiskHandle;
-pub use procfs::DiskStat;
+pub use procfs_core::DiskStat;
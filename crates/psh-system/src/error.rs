@@ -23,7 +23,7 @@ pub enum Error {
     #[error("Failed to accquire mutex.")]
     Sync,
     #[error("Failed to retrive information using procfs: {0}.")]
-    Procfs(#[from] procfs::ProcError),
+    Procfs(#[from] procfs_core::ProcError),
     #[error("Failed to decode utf-8 string: {0}.")]
     Utf8(#[from] Utf8Error),
     #[error("Failed to find executable binary: {0}.")]
@@ -32,6 +32,13 @@ pub enum Error {
     InvalidCpuMask(String),
     #[error("Value is empty")]
     EmptyValue,
+    #[error("Not supported on this platform: {0}")]
+    Unsupported(&'static str),
+    #[error("Rate limited: {interface} allows {calls_per_sec} calls per second")]
+    RateLimited {
+        interface: String,
+        calls_per_sec: u32,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
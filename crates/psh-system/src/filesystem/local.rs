@@ -0,0 +1,192 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::error::Result;
+
+// counters are cumulative since mount, ext4 has no per operation latencies
+const EXT4_COUNTERS: [&str; 4] = [
+    "lifetime_write_kbytes",
+    "session_write_kbytes",
+    "delayed_allocation_blocks",
+    "errors_count",
+];
+
+/// Counters of a local filesystem the kernel keeps per mounted device,
+/// from `/sys/fs/ext4/<dev>` or `/sys/fs/xfs/<dev>/stats/stats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocalMountStats {
+    pub device: String,
+    pub mount_point: String,
+    /// `ext4` or `xfs`
+    pub fstype: String,
+    pub counters: BTreeMap<String, u64>,
+}
+
+pub fn local_mount_stats() -> Result<Vec<LocalMountStats>> {
+    let mounts = fs::read_to_string("/proc/self/mounts")?;
+    let mut stats = vec![];
+    for (device, mount_point, fstype) in parse_mounts(&mounts) {
+        // bind mounts of the same device show up more than once
+        if stats.iter().any(|it: &LocalMountStats| it.device == device) {
+            continue;
+        }
+        // /dev/mapper/* are links to /dev/dm-*
+        let name = fs::canonicalize(&device)
+            .ok()
+            .and_then(|it| Some(it.file_name()?.to_string_lossy().into_owned()));
+        let Some(name) = name else {
+            continue;
+        };
+        let counters = match fstype.as_str() {
+            "ext4" => ext4_counters(&Path::new("/sys/fs/ext4").join(&name)),
+            "xfs" => fs::read_to_string(format!("/sys/fs/xfs/{name}/stats/stats"))
+                .map(|it| parse_xfs_stats(&it))
+                .unwrap_or_default(),
+            _ => continue,
+        };
+        if counters.is_empty() {
+            continue;
+        }
+        stats.push(LocalMountStats {
+            device,
+            mount_point,
+            fstype,
+            counters,
+        });
+    }
+    Ok(stats)
+}
+
+/// Device, mount point and type of the mounts in `/proc/<pid>/mounts`.
+fn parse_mounts(content: &str) -> Vec<(String, String, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let device = unescape(fields.next()?);
+            let mount_point = unescape(fields.next()?);
+            let fstype = fields.next()?.to_string();
+            Some((device, mount_point, fstype))
+        })
+        .collect()
+}
+
+/// Spaces, tabs, newlines and backslashes are escaped as octal, e.g. `\040`.
+fn unescape(field: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = field.as_bytes();
+    while let Some((&first, tail)) = rest.split_first() {
+        let octal = tail
+            .get(..3)
+            .and_then(|it| std::str::from_utf8(it).ok())
+            .and_then(|it| u8::from_str_radix(it, 8).ok());
+        match octal {
+            Some(byte) if first == b'\\' => {
+                bytes.push(byte);
+                rest = &tail[3..];
+            }
+            _ => {
+                bytes.push(first);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn ext4_counters(dir: &Path) -> BTreeMap<String, u64> {
+    EXT4_COUNTERS
+        .iter()
+        .filter_map(|name| {
+            let value = fs::read_to_string(dir.join(name)).ok()?;
+            Some((name.to_string(), value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+fn parse_xfs_stats(content: &str) -> BTreeMap<String, u64> {
+    // line prefix, then the names of its leading values
+    const COUNTERS: [(&str, &[&str]); 4] = [
+        ("rw", &["write_calls", "read_calls"]),
+        ("xpc", &["xstrat_bytes", "write_bytes", "read_bytes"]),
+        (
+            "log",
+            &[
+                "log_writes",
+                "log_blocks",
+                "log_noiclogs",
+                "log_force",
+                "log_force_sleep",
+            ],
+        ),
+        ("trans", &["trans_sync", "trans_async", "trans_empty"]),
+    ];
+    let mut counters = BTreeMap::new();
+    for line in content.lines() {
+        let mut values = line.split_whitespace();
+        let Some(prefix) = values.next() else {
+            continue;
+        };
+        let Some((_, names)) = COUNTERS.iter().find(|(it, _)| *it == prefix) else {
+            continue;
+        };
+        for (name, value) in names.iter().zip(values) {
+            if let Ok(value) = value.parse() {
+                counters.insert(name.to_string(), value);
+            }
+        }
+    }
+    counters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_mounts, parse_xfs_stats};
+
+    #[test]
+    fn test_parse_mounts() {
+        let content =
+            "/dev/sda1 / ext4 rw,relatime 0 0\n/dev/sdb1 /mnt/my\\040data xfs rw,noatime 0 0\n";
+        let mounts = parse_mounts(content);
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(
+            mounts[1],
+            (
+                "/dev/sdb1".to_string(),
+                "/mnt/my data".to_string(),
+                "xfs".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_xfs_stats() {
+        let content = "\
+extent_alloc 10 20 30 40
+log 100 200 3 40 5
+trans 7 8 9
+rw 1000 2000
+xpc 4096 8192 16384
+";
+        let counters = parse_xfs_stats(content);
+        assert_eq!(counters.len(), 13);
+        assert_eq!(counters["write_calls"], 1000);
+        assert_eq!(counters["read_calls"], 2000);
+        assert_eq!(counters["read_bytes"], 16384);
+        assert_eq!(counters["log_force_sleep"], 5);
+        assert_eq!(counters["trans_async"], 8);
+    }
+}
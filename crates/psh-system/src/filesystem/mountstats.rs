@@ -0,0 +1,179 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::fs;
+
+use crate::error::Result;
+
+/// Per operation RPC statistics of an NFS mount, cumulative since mount.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NfsOpStats {
+    /// e.g. `READ`, `GETATTR`
+    pub name: String,
+    pub ops: u64,
+    pub transmissions: u64,
+    pub major_timeouts: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// in milliseconds, waiting for a transport slot
+    pub queue_ms: u64,
+    /// in milliseconds, from sending the request to receiving the reply
+    pub rtt_ms: u64,
+    /// in milliseconds, from queueing the request until it completed
+    pub execute_ms: u64,
+    /// replies with an error status, 0 on kernels not counting them
+    pub errors: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NfsMountStats {
+    /// e.g. `server:/export`
+    pub device: String,
+    pub mount_point: String,
+    /// `nfs` or `nfs4`
+    pub fstype: String,
+    /// seconds since mount
+    pub age: u64,
+    /// operations never issued are left out
+    pub ops: Vec<NfsOpStats>,
+}
+
+pub fn nfs_mount_stats() -> Result<Vec<NfsMountStats>> {
+    let content = fs::read_to_string("/proc/self/mountstats")?;
+    Ok(parse_mountstats(&content))
+}
+
+/// Parses the NFS mounts of a `/proc/<pid>/mountstats`, other mounts only
+/// have a `device` line and are skipped.
+pub fn parse_mountstats(content: &str) -> Vec<NfsMountStats> {
+    let mut mounts = vec![];
+    let mut current: Option<NfsMountStats> = None;
+    let mut per_op = false;
+    for line in content.lines() {
+        if let Some(rest) = line.strip_prefix("device ") {
+            mounts.extend(current.take());
+            per_op = false;
+            // device <dev> mounted on <mount point> with fstype <fstype> [statvers=<v>]
+            let Some((device, rest)) = rest.split_once(" mounted on ") else {
+                continue;
+            };
+            let Some((mount_point, rest)) = rest.rsplit_once(" with fstype ") else {
+                continue;
+            };
+            let fstype = rest.split(' ').next().unwrap_or_default();
+            if matches!(fstype, "nfs" | "nfs4") {
+                current = Some(NfsMountStats {
+                    device: device.to_string(),
+                    mount_point: mount_point.to_string(),
+                    fstype: fstype.to_string(),
+                    ..Default::default()
+                });
+            }
+            continue;
+        }
+        let Some(mount) = current.as_mut() else {
+            continue;
+        };
+        let line = line.trim();
+        if let Some(age) = line.strip_prefix("age:") {
+            mount.age = age.trim().parse().unwrap_or_default();
+        } else if line == "per-op statistics" {
+            per_op = true;
+        } else if per_op {
+            mount.ops.extend(parse_op(line).filter(|it| it.ops > 0));
+        }
+    }
+    mounts.extend(current);
+    mounts
+}
+
+// <name>: ops trans timeouts sent recv queue rtt execute [errors]
+fn parse_op(line: &str) -> Option<NfsOpStats> {
+    let (name, values) = line.split_once(':')?;
+    let values = values
+        .split_whitespace()
+        .map(str::parse::<u64>)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .ok()?;
+    if values.len() < 8 {
+        return None;
+    }
+    Some(NfsOpStats {
+        name: name.to_string(),
+        ops: values[0],
+        transmissions: values[1],
+        major_timeouts: values[2],
+        bytes_sent: values[3],
+        bytes_received: values[4],
+        queue_ms: values[5],
+        rtt_ms: values[6],
+        execute_ms: values[7],
+        errors: values.get(8).copied().unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NfsOpStats, parse_mountstats};
+
+    #[test]
+    fn test_parse_mountstats() {
+        let content = "\
+device sysfs mounted on /sys with fstype sysfs
+device /dev/sda1 mounted on / with fstype ext4
+device fileserver:/export/home mounted on /home with fstype nfs4 statvers=1.1
+\topts:\trw,vers=4.2,rsize=1048576,wsize=1048576,hard,proto=tcp,timeo=600
+\tage:\t86400
+\tcaps:\tcaps=0x3ffbffff,wtmult=512,dtsize=32768,bsize=0,namlen=255
+\tsec:\tflavor=1,pseudoflavor=1
+\tevents:\t1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27
+\tbytes:\t1000 2000 0 0 1000 2000 1 1
+\tRPC iostats version: 1.1  p/v: 100003/4 (nfs)
+\txprt:\ttcp 0 1 2 0 10 500 500 0 600 0 2 100 50
+\tper-op statistics
+\t        NULL: 0 0 0 0 0 0 0 0 0
+\t        READ: 100 101 1 14400 1048576 20 1500 1600 2
+\t       WRITE: 50 50 0 524288 8000 5 900 950
+\t     GETATTR: 0 0 0 0 0 0 0 0 0
+
+device nfsd mounted on /proc/fs/nfsd with fstype nfsd
+";
+        let mounts = parse_mountstats(content);
+        assert_eq!(mounts.len(), 1);
+        let mount = &mounts[0];
+        assert_eq!(mount.device, "fileserver:/export/home");
+        assert_eq!(mount.mount_point, "/home");
+        assert_eq!(mount.fstype, "nfs4");
+        assert_eq!(mount.age, 86400);
+        assert_eq!(mount.ops.len(), 2);
+        assert_eq!(
+            mount.ops[0],
+            NfsOpStats {
+                name: "READ".to_string(),
+                ops: 100,
+                transmissions: 101,
+                major_timeouts: 1,
+                bytes_sent: 14400,
+                bytes_received: 1048576,
+                queue_ms: 20,
+                rtt_ms: 1500,
+                execute_ms: 1600,
+                errors: 2,
+            }
+        );
+        // older kernels have no error column
+        assert_eq!(mount.ops[1].name, "WRITE");
+        assert_eq!(mount.ops[1].errors, 0);
+    }
+}
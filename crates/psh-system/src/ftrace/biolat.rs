@@ -0,0 +1,158 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    time::Duration,
+};
+
+use super::{Instance, Log2Histogram, TraceRecord};
+use crate::error::Result;
+
+const EVENTS: [&str; 2] = ["block/block_rq_issue", "block/block_rq_complete"];
+const BUFFER_KB: usize = 4096;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceLatency {
+    pub histogram: Log2Histogram,
+    /// requests that took longer than the outlier threshold
+    pub outliers: u64,
+}
+
+/// Block request latency, the time from issuing a request to the device
+/// driver until it completes, in microseconds.
+#[derive(Debug)]
+pub struct BlockLatency {
+    outlier_us: u64,
+    /// issued at, by device and sector
+    issued: HashMap<(String, u64), u64>,
+    devices: BTreeMap<String, DeviceLatency>,
+}
+
+impl BlockLatency {
+    pub fn new(outlier_us: u64) -> Self {
+        Self {
+            outlier_us,
+            issued: HashMap::new(),
+            devices: BTreeMap::new(),
+        }
+    }
+
+    /// Traces the block layer for `window`, latencies are keyed by device
+    /// name, or `major,minor` if it has none.
+    pub fn collect(window: Duration, outlier_us: u64) -> Result<BTreeMap<String, DeviceLatency>> {
+        let instance = Instance::create(&EVENTS, BUFFER_KB)?;
+        let mut latency = Self::new(outlier_us);
+        instance.read_for(window, |record| latency.handle(record))?;
+        Ok(latency
+            .devices
+            .into_iter()
+            .map(|(dev, it)| (device_name(&dev).unwrap_or(dev), it))
+            .collect())
+    }
+
+    pub fn handle(&mut self, record: &TraceRecord) {
+        let Some(request) = parse_request(record.fields) else {
+            return;
+        };
+        match record.event {
+            "block_rq_issue" => {
+                self.issued.insert(request, record.timestamp_ns);
+            }
+            "block_rq_complete" => {
+                // issued before the window
+                let Some(since) = self.issued.remove(&request) else {
+                    return;
+                };
+                let latency_us = record.timestamp_ns.saturating_sub(since) / 1000;
+                let device = self.devices.entry(request.0).or_default();
+                device.histogram.record(latency_us);
+                if latency_us > self.outlier_us {
+                    device.outliers += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Keyed by `major,minor`.
+    pub fn devices(&self) -> &BTreeMap<String, DeviceLatency> {
+        &self.devices
+    }
+}
+
+/// Device and start sector from `<major>,<minor> <rwbs> ... <sector> + <nr_sector> ...`,
+/// requests without data like flushes are skipped.
+fn parse_request(fields: &str) -> Option<(String, u64)> {
+    let tokens: Vec<&str> = fields.split(' ').collect();
+    let plus = tokens.iter().position(|it| *it == "+")?;
+    if plus < 1 || tokens.get(plus + 1).is_none_or(|it| *it == "0") {
+        return None;
+    }
+    let sector = tokens[plus - 1].parse().ok()?;
+    Some((tokens.first()?.to_string(), sector))
+}
+
+fn device_name(dev: &str) -> Option<String> {
+    let uevent =
+        fs::read_to_string(format!("/sys/dev/block/{}/uevent", dev.replace(',', ":"))).ok()?;
+    uevent
+        .lines()
+        .find_map(|it| it.strip_prefix("DEVNAME="))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockLatency, parse_request};
+    use crate::ftrace::parse_line;
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(
+            parse_request("8,0 WS 4096 () 123456 + 8 [kworker/u16:1]"),
+            Some(("8,0".to_string(), 123456))
+        );
+        assert_eq!(
+            parse_request("259,0 R () 2048 + 16 none,0,0 [0]"),
+            Some(("259,0".to_string(), 2048))
+        );
+        assert_eq!(parse_request("8,0 FF 0 () 0 + 0 [jbd2/sda1-8]"), None);
+    }
+
+    #[test]
+    fn test_block_latency() {
+        let lines = [
+            "fio-500 [001] ..... 20.000000: block_rq_issue: 8,0 R 4096 () 100 + 8 [fio]",
+            "fio-500 [001] ..... 20.000010: block_rq_issue: 8,16 W 4096 () 100 + 8 [fio]",
+            "<idle>-0 [000] d.h1. 20.000200: block_rq_complete: 8,0 R () 100 + 8 [0]",
+            "<idle>-0 [000] d.h1. 20.050010: block_rq_complete: 8,16 W () 100 + 8 [0]",
+            // issued before the window
+            "<idle>-0 [000] d.h1. 20.060000: block_rq_complete: 8,0 R () 999 + 8 [0]",
+        ];
+        let mut latency = BlockLatency::new(10_000);
+        for line in lines {
+            latency.handle(&parse_line(line).unwrap());
+        }
+
+        let devices = latency.devices();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices["8,0"].histogram.count, 1);
+        assert_eq!(devices["8,0"].histogram.sum, 200);
+        assert_eq!(devices["8,0"].outliers, 0);
+        assert_eq!(devices["8,16"].histogram.max, 50_000);
+        assert_eq!(devices["8,16"].outliers, 1);
+    }
+}
@@ -0,0 +1,73 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+/// Histogram with power of two buckets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Log2Histogram {
+    pub count: u64,
+    pub sum: u64,
+    pub max: u64,
+    /// `buckets[i]` counts values from `2^i` up to `2^(i+1)`, and 0 in the first
+    pub buckets: Vec<u64>,
+}
+
+impl Log2Histogram {
+    pub fn record(&mut self, value: u64) {
+        let bucket = value.checked_ilog2().unwrap_or(0) as usize;
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += value;
+        self.max = self.max.max(value);
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        if self.buckets.len() < other.buckets.len() {
+            self.buckets.resize(other.buckets.len(), 0);
+        }
+        for (bucket, count) in self.buckets.iter_mut().zip(&other.buckets) {
+            *bucket += count;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.max = self.max.max(other.max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Log2Histogram;
+
+    #[test]
+    fn test_log2_histogram() {
+        let mut histogram = Log2Histogram::default();
+        for value in [0, 1, 2, 3, 4, 1000] {
+            histogram.record(value);
+        }
+        assert_eq!(histogram.count, 6);
+        assert_eq!(histogram.sum, 1010);
+        assert_eq!(histogram.max, 1000);
+        assert_eq!(histogram.buckets, [2, 2, 1, 0, 0, 0, 0, 0, 0, 1]);
+
+        let mut merged = Log2Histogram::default();
+        merged.record(5000);
+        merged.merge(&histogram);
+        assert_eq!(merged.count, 7);
+        assert_eq!(merged.sum, 6010);
+        assert_eq!(merged.max, 5000);
+        assert_eq!(merged.buckets, [2, 2, 1, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1]);
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU32, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{TraceRecord, parse_line};
+use crate::error::Result;
+
+const TRACEFS: &str = "/sys/kernel/tracing";
+const DEBUGFS_TRACING: &str = "/sys/kernel/debug/tracing";
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+/// A private ftrace instance, with its own buffer and enabled events, so
+/// collectors don't disturb each other or a user running `trace-cmd`.
+///
+/// The instance is removed on drop.
+#[derive(Debug)]
+pub struct Instance {
+    dir: PathBuf,
+}
+
+impl Instance {
+    /// Enables `events` such as `sched/sched_switch`, with `buffer_kb` of
+    /// buffer per cpu.
+    pub fn create(events: &[&str], buffer_kb: usize) -> Result<Self> {
+        let root = if Path::new(TRACEFS).join("instances").exists() {
+            TRACEFS
+        } else {
+            DEBUGFS_TRACING
+        };
+        let name = format!(
+            "psh-{}-{}",
+            process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let dir = Path::new(root).join("instances").join(name);
+        fs::create_dir(&dir)?;
+        let instance = Self { dir };
+
+        fs::write(instance.dir.join("buffer_size_kb"), buffer_kb.to_string())?;
+        // the default `local` clock is not comparable across cpus
+        let _ = fs::write(instance.dir.join("trace_clock"), "mono");
+        for event in events {
+            fs::write(instance.dir.join("events").join(event).join("enable"), "1")?;
+        }
+        fs::write(instance.dir.join("tracing_on"), "1")?;
+        Ok(instance)
+    }
+
+    /// Calls `f` with every record traced during `window`, blocks meanwhile.
+    pub fn read_for(&self, window: Duration, mut f: impl FnMut(&TraceRecord)) -> Result<()> {
+        let mut pipe = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(self.dir.join("trace_pipe"))?;
+        let mut pending = Vec::new();
+        let deadline = Instant::now() + window;
+        while Instant::now() < deadline {
+            if read_available(&mut pipe, &mut pending, &mut f)? == 0 {
+                thread::sleep(
+                    POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())),
+                );
+            }
+        }
+        fs::write(self.dir.join("tracing_on"), "0")?;
+        read_available(&mut pipe, &mut pending, &mut f)?;
+        Ok(())
+    }
+}
+
+/// Reads what the pipe has, returns the number of bytes read.
+fn read_available(
+    pipe: &mut File,
+    pending: &mut Vec<u8>,
+    f: &mut impl FnMut(&TraceRecord),
+) -> Result<usize> {
+    let mut total = 0;
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let len = match pipe.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        total += len;
+        pending.extend_from_slice(&buf[..len]);
+        // a read may end within a line, keep the rest for the next one
+        let complete = pending
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |it| it + 1);
+        for line in String::from_utf8_lossy(&pending[..complete]).lines() {
+            if let Some(record) = parse_line(line) {
+                f(&record);
+            }
+        }
+        pending.drain(..complete);
+    }
+    Ok(total)
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        // best effort, a busy instance is left behind
+        let _ = fs::write(self.dir.join("tracing_on"), "0");
+        let _ = fs::write(self.dir.join("events/enable"), "0");
+        let _ = fs::remove_dir(&self.dir);
+    }
+}
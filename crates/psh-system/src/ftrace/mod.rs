@@ -0,0 +1,127 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+mod biolat;
+mod histogram;
+mod instance;
+mod runqlat;
+mod syscalls;
+mod tcpconn;
+mod timeline;
+
+pub use biolat::{BlockLatency, DeviceLatency};
+pub use histogram::Log2Histogram;
+pub use instance::Instance;
+pub use runqlat::{GroupBy, RunQueueLatency};
+pub use syscalls::{ProcessSyscalls, SyscallStats, Syscalls};
+pub use tcpconn::{Direction, Endpoint, EndpointStats, TcpConnections};
+pub use timeline::{Interval, ThreadState, Timeline};
+
+/// One line of a `trace_pipe`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TraceRecord<'a> {
+    /// thread id of the task the event happened in, 0 if the line has none
+    pub pid: u32,
+    pub cpu: u32,
+    /// in nanoseconds of the instance's trace clock
+    pub timestamp_ns: u64,
+    pub event: &'a str,
+    /// everything after the event name, as printed by the event's format
+    pub fields: &'a str,
+}
+
+impl<'a> TraceRecord<'a> {
+    /// The value of a `key=value` field, up to the next space.
+    pub fn field(&self, key: &str) -> Option<&'a str> {
+        let mut rest = self.fields;
+        loop {
+            let at = rest.find(key)?;
+            let after = &rest[at + key.len()..];
+            if (at == 0 || rest.as_bytes()[at - 1] == b' ') && after.starts_with('=') {
+                return after[1..].split(' ').next();
+            }
+            rest = after;
+        }
+    }
+}
+
+/// Parses `<comm>-<pid> [<cpu>] <flags> <secs>.<usecs>: <event>: <fields>`.
+pub fn parse_line(line: &str) -> Option<TraceRecord<'_>> {
+    let (head, rest) = line.split_once(": ")?;
+    let (event, fields) = rest.split_once(": ").unwrap_or((rest, ""));
+
+    let timestamp = head.rsplit(' ').next()?;
+    let (secs, usecs) = timestamp.split_once('.')?;
+    let timestamp_ns =
+        secs.parse::<u64>().ok()? * 1_000_000_000 + usecs.parse::<u64>().ok()? * 1000;
+
+    // the comm in front may contain brackets too
+    let cpu_start = head.rfind(" [")? + 2;
+    let cpu_end = cpu_start + head[cpu_start..].find(']')?;
+    let cpu = head[cpu_start..cpu_end].parse().ok()?;
+    // older collectors don't need the pid, a line without one still parses
+    let pid = head[..cpu_start - 2]
+        .trim_end()
+        .rsplit_once('-')
+        .and_then(|(_, pid)| pid.parse().ok())
+        .unwrap_or_default();
+
+    Some(TraceRecord {
+        pid,
+        cpu,
+        timestamp_ns,
+        event,
+        fields: fields.trim_end(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TraceRecord, parse_line};
+
+    #[test]
+    fn test_parse_line() {
+        let line = "  Web Content-4242 [003] d..2. 12345.678901: sched_switch: prev_comm=Web Content prev_pid=4242 prev_prio=120 prev_state=R+ ==> next_comm=swapper/3 next_pid=0 next_prio=120\n";
+        let record = parse_line(line).unwrap();
+        assert_eq!(
+            record,
+            TraceRecord {
+                pid: 4242,
+                cpu: 3,
+                timestamp_ns: 12_345_678_901_000,
+                event: "sched_switch",
+                fields: "prev_comm=Web Content prev_pid=4242 prev_prio=120 prev_state=R+ ==> next_comm=swapper/3 next_pid=0 next_prio=120",
+            }
+        );
+        assert_eq!(record.field("prev_pid"), Some("4242"));
+        assert_eq!(record.field("pid"), None);
+        assert_eq!(record.field("next_pid"), Some("0"));
+        assert_eq!(record.field("prev_state"), Some("R+"));
+
+        let line = "kworker/u16:1-87 [000] ..... 99.000001: block_rq_issue: 8,0 WS 4096 () 123456 + 8 [kworker/u16:1]";
+        let record = parse_line(line).unwrap();
+        assert_eq!(record.pid, 87);
+        assert_eq!(record.cpu, 0);
+        assert_eq!(record.event, "block_rq_issue");
+        assert_eq!(record.fields, "8,0 WS 4096 () 123456 + 8 [kworker/u16:1]");
+
+        let line = "kworker [002] ..... 99.000002: block_rq_complete: 8,0 WS () 123456 + 8 [0]";
+        let record = parse_line(line).unwrap();
+        assert_eq!(record.pid, 0);
+        assert_eq!(record.cpu, 2);
+        assert_eq!(record.event, "block_rq_complete");
+
+        assert!(parse_line("CPU:2 [LOST 12 EVENTS]").is_none());
+    }
+}
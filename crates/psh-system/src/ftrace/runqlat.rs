@@ -0,0 +1,154 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    time::Duration,
+};
+
+use super::{Instance, Log2Histogram, TraceRecord};
+use crate::error::Result;
+
+const EVENTS: [&str; 3] = [
+    "sched/sched_wakeup",
+    "sched/sched_wakeup_new",
+    "sched/sched_switch",
+];
+// per cpu, context switches are frequent
+const BUFFER_KB: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Cpu,
+    Cgroup,
+}
+
+/// Run queue latency, the time from a task becoming runnable until it runs,
+/// in microseconds.
+///
+/// A task is runnable once woken up, or when preempted while still runnable.
+#[derive(Debug)]
+pub struct RunQueueLatency {
+    group_by: GroupBy,
+    /// runnable since, by pid
+    runnable: HashMap<u32, u64>,
+    cgroups: HashMap<u32, String>,
+    histograms: BTreeMap<String, Log2Histogram>,
+}
+
+impl RunQueueLatency {
+    pub fn new(group_by: GroupBy) -> Self {
+        Self {
+            group_by,
+            runnable: HashMap::new(),
+            cgroups: HashMap::new(),
+            histograms: BTreeMap::new(),
+        }
+    }
+
+    /// Traces the scheduler for `window`, histograms are keyed by cpu
+    /// number or cgroup path.
+    pub fn collect(window: Duration, group_by: GroupBy) -> Result<BTreeMap<String, Log2Histogram>> {
+        let instance = Instance::create(&EVENTS, BUFFER_KB)?;
+        let mut latency = Self::new(group_by);
+        instance.read_for(window, |record| latency.handle(record))?;
+        Ok(latency.histograms)
+    }
+
+    pub fn handle(&mut self, record: &TraceRecord) {
+        let pid = |key| record.field(key).and_then(|it| it.parse::<u32>().ok());
+        match record.event {
+            "sched_wakeup" | "sched_wakeup_new" => {
+                if let Some(pid) = pid("pid") {
+                    self.runnable.insert(pid, record.timestamp_ns);
+                }
+            }
+            "sched_switch" => {
+                let preempted = record
+                    .field("prev_state")
+                    .is_some_and(|it| it.starts_with('R'));
+                if let Some(prev) = pid("prev_pid").filter(|&it| it != 0 && preempted) {
+                    self.runnable.insert(prev, record.timestamp_ns);
+                }
+                let Some(next) = pid("next_pid").filter(|&it| it != 0) else {
+                    return;
+                };
+                let Some(since) = self.runnable.remove(&next) else {
+                    return;
+                };
+                let key = match self.group_by {
+                    GroupBy::Cpu => record.cpu.to_string(),
+                    GroupBy::Cgroup => self
+                        .cgroups
+                        .entry(next)
+                        .or_insert_with(|| cgroup_of(next))
+                        .clone(),
+                };
+                let latency_us = record.timestamp_ns.saturating_sub(since) / 1000;
+                self.histograms.entry(key).or_default().record(latency_us);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn histograms(&self) -> &BTreeMap<String, Log2Histogram> {
+        &self.histograms
+    }
+}
+
+/// The unified hierarchy path, or the first controller's on cgroup v1.
+fn cgroup_of(pid: u32) -> String {
+    let Ok(cgroups) = fs::read_to_string(format!("/proc/{pid}/cgroup")) else {
+        // already gone
+        return "unknown".to_string();
+    };
+    let path = |line: &str| line.splitn(3, ':').nth(2).map(str::to_string);
+    cgroups
+        .lines()
+        .find(|it| it.starts_with("0::"))
+        .and_then(path)
+        .or_else(|| cgroups.lines().next().and_then(path))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GroupBy, RunQueueLatency};
+    use crate::ftrace::parse_line;
+
+    #[test]
+    fn test_run_queue_latency() {
+        let lines = [
+            "bash-100 [001] d..3. 10.000000: sched_wakeup: comm=worker pid=200 prio=120 target_cpu=002",
+            "<idle>-0 [002] d..2. 10.000150: sched_switch: prev_comm=swapper/2 prev_pid=0 prev_prio=120 prev_state=R ==> next_comm=worker next_pid=200 next_prio=120",
+            "worker-200 [002] d..2. 10.001000: sched_switch: prev_comm=worker prev_pid=200 prev_prio=120 prev_state=R+ ==> next_comm=other next_pid=300 next_prio=120",
+            "other-300 [002] d..2. 10.003000: sched_switch: prev_comm=other prev_pid=300 prev_prio=120 prev_state=S ==> next_comm=worker next_pid=200 next_prio=120",
+        ];
+        let mut latency = RunQueueLatency::new(GroupBy::Cpu);
+        for line in lines {
+            latency.handle(&parse_line(line).unwrap());
+        }
+
+        let histograms = latency.histograms();
+        assert_eq!(histograms.len(), 1);
+        let cpu2 = &histograms["2"];
+        // 150us after the wakeup, 2000us after the preemption
+        assert_eq!(cpu2.count, 2);
+        assert_eq!(cpu2.sum, 2150);
+        assert_eq!(cpu2.max, 2000);
+        // pid 300 never became runnable in the window
+        assert_eq!(cpu2.buckets.iter().sum::<u64>(), 2);
+    }
+}
@@ -0,0 +1,257 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{collections::HashMap, fs, process, time::Duration};
+
+use super::{Instance, Log2Histogram, TraceRecord};
+use crate::error::Result;
+
+const EVENTS: [&str; 2] = ["raw_syscalls/sys_enter", "raw_syscalls/sys_exit"];
+// per cpu, the buffer is drained every 50ms, which makes 1MiB enough for
+// some hundred thousand syscalls per second and cpu
+const BUFFER_KB: usize = 1024;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyscallStats {
+    /// calls returning an error
+    pub errors: u64,
+    /// in microseconds
+    pub latency: Log2Histogram,
+}
+
+impl SyscallStats {
+    fn record(&mut self, latency_us: u64, ret: i64) {
+        self.latency.record(latency_us);
+        if ret < 0 {
+            self.errors += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessSyscalls {
+    pub pid: u32,
+    pub comm: String,
+    pub total: SyscallStats,
+    /// by syscall number, most called first
+    pub syscalls: Vec<(i64, SyscallStats)>,
+}
+
+/// Count, errors and latency of the syscalls of every process, from the
+/// `sys_enter` and `sys_exit` tracepoints.
+///
+/// Only syscalls entered and returning in the window are counted, so ones
+/// like `exit` never are. Syscalls of the daemon itself are skipped, reading
+/// the trace makes some.
+#[derive(Debug)]
+pub struct Syscalls {
+    own: u32,
+    /// entered at and syscall number, by thread id
+    entered: HashMap<u32, (u64, i64)>,
+    /// process ids, by thread id
+    tgids: HashMap<u32, u32>,
+    processes: HashMap<u32, HashMap<i64, SyscallStats>>,
+}
+
+impl Default for Syscalls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Syscalls {
+    pub fn new() -> Self {
+        Self {
+            own: process::id(),
+            entered: HashMap::new(),
+            tgids: HashMap::new(),
+            processes: HashMap::new(),
+        }
+    }
+
+    /// Traces syscalls for `window`, returns the `max_processes` processes
+    /// calling most with their `max_syscalls` most called syscalls.
+    pub fn collect(
+        window: Duration,
+        max_processes: usize,
+        max_syscalls: usize,
+    ) -> Result<Vec<ProcessSyscalls>> {
+        let instance = Instance::create(&EVENTS, BUFFER_KB)?;
+        let mut syscalls = Self::new();
+        instance.read_for(window, |record| syscalls.handle(record))?;
+        Ok(syscalls.top(max_processes, max_syscalls))
+    }
+
+    pub fn handle(&mut self, record: &TraceRecord) {
+        // the idle task
+        if record.pid == 0 {
+            return;
+        }
+        match record.event {
+            "sys_enter" => {
+                if let Some((nr, _)) = parse_syscall(record.fields) {
+                    self.entered.insert(record.pid, (record.timestamp_ns, nr));
+                }
+            }
+            "sys_exit" => {
+                let Some((nr, rest)) = parse_syscall(record.fields) else {
+                    return;
+                };
+                // entered before the window
+                let Some((since, entered)) = self.entered.remove(&record.pid) else {
+                    return;
+                };
+                if entered != nr {
+                    return;
+                }
+                let ret = rest
+                    .strip_prefix("= ")
+                    .and_then(|it| it.parse().ok())
+                    .unwrap_or_default();
+                let tgid = *self
+                    .tgids
+                    .entry(record.pid)
+                    .or_insert_with(|| tgid_of(record.pid));
+                if tgid == self.own {
+                    return;
+                }
+                let latency_us = record.timestamp_ns.saturating_sub(since) / 1000;
+                self.processes
+                    .entry(tgid)
+                    .or_default()
+                    .entry(nr)
+                    .or_default()
+                    .record(latency_us, ret);
+            }
+            _ => {}
+        }
+    }
+
+    /// The `max_processes` processes with the most calls, with their
+    /// `max_syscalls` most called syscalls.
+    pub fn top(&self, max_processes: usize, max_syscalls: usize) -> Vec<ProcessSyscalls> {
+        let mut processes: Vec<ProcessSyscalls> = self
+            .processes
+            .iter()
+            .map(|(&pid, syscalls)| {
+                let mut total = SyscallStats::default();
+                for stats in syscalls.values() {
+                    total.errors += stats.errors;
+                    total.latency.merge(&stats.latency);
+                }
+                let mut syscalls: Vec<_> =
+                    syscalls.iter().map(|(&nr, it)| (nr, it.clone())).collect();
+                syscalls.sort_by(|(a_nr, a), (b_nr, b)| {
+                    b.latency.count.cmp(&a.latency.count).then(a_nr.cmp(b_nr))
+                });
+                syscalls.truncate(max_syscalls);
+                ProcessSyscalls {
+                    pid,
+                    comm: String::new(),
+                    total,
+                    syscalls,
+                }
+            })
+            .collect();
+        processes.sort_by(|a, b| {
+            b.total
+                .latency
+                .count
+                .cmp(&a.total.latency.count)
+                .then(a.pid.cmp(&b.pid))
+        });
+        processes.truncate(max_processes);
+        for it in &mut processes {
+            it.comm = fs::read_to_string(format!("/proc/{}/comm", it.pid))
+                .map(|it| it.trim_end().to_string())
+                .unwrap_or_default();
+        }
+        processes
+    }
+}
+
+/// The number from `NR <nr> (<args>)` or `NR <nr> = <ret>`, and the rest.
+fn parse_syscall(fields: &str) -> Option<(i64, &str)> {
+    let rest = fields.strip_prefix("NR ")?;
+    let (nr, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    Some((nr.parse().ok()?, rest))
+}
+
+/// The thread itself if it is already gone.
+fn tgid_of(tid: u32) -> u32 {
+    fs::read_to_string(format!("/proc/{tid}/status"))
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|it| it.strip_prefix("Tgid:"))
+                .and_then(|it| it.trim().parse().ok())
+        })
+        .unwrap_or(tid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Syscalls, parse_syscall};
+    use crate::ftrace::parse_line;
+
+    #[test]
+    fn test_parse_syscall() {
+        assert_eq!(
+            parse_syscall("NR 0 (3, 7ffd2c3b8a40, 400, 0, 0, 0)"),
+            Some((0, "(3, 7ffd2c3b8a40, 400, 0, 0, 0)"))
+        );
+        assert_eq!(parse_syscall("NR 232 = -4"), Some((232, "= -4")));
+        assert_eq!(parse_syscall("prev_pid=1"), None);
+    }
+
+    #[test]
+    fn test_syscalls() {
+        let lines = [
+            "nginx-501 [001] ..... 30.000000: sys_enter: NR 0 (3, 7ffd2c3b8a40, 400, 0, 0, 0)",
+            "nginx-502 [002] ..... 30.000010: sys_enter: NR 232 (5, 7f10, 512, ffffffff, 0, 0)",
+            "nginx-501 [001] ..... 30.000030: sys_exit: NR 0 = 400",
+            "nginx-501 [001] ..... 30.000040: sys_enter: NR 0 (3, 7ffd2c3b8a40, 400, 0, 0, 0)",
+            "nginx-501 [001] ..... 30.000045: sys_exit: NR 0 = -11",
+            "nginx-502 [002] ..... 30.005010: sys_exit: NR 232 = -4",
+            "redis-700 [003] ..... 30.006000: sys_enter: NR 1 (6, 55d0, 12, 0, 0, 0)",
+            "redis-700 [003] ..... 30.006002: sys_exit: NR 1 = 12",
+            // entered before the window
+            "redis-700 [003] ..... 30.007000: sys_exit: NR 7 = 1",
+        ];
+        let mut syscalls = Syscalls::new();
+        syscalls.tgids.extend([(501, 500), (502, 500), (700, 700)]);
+        for line in lines {
+            syscalls.handle(&parse_line(line).unwrap());
+        }
+
+        let top = syscalls.top(10, 1);
+        assert_eq!(top.len(), 2);
+        let nginx = &top[0];
+        assert_eq!(nginx.pid, 500);
+        assert_eq!(nginx.total.latency.count, 3);
+        assert_eq!(nginx.total.latency.sum, 30 + 5 + 5000);
+        assert_eq!(nginx.total.latency.max, 5000);
+        assert_eq!(nginx.total.errors, 2);
+        assert_eq!(nginx.syscalls.len(), 1);
+        let (nr, read) = &nginx.syscalls[0];
+        assert_eq!(*nr, 0);
+        assert_eq!(read.latency.count, 2);
+        assert_eq!(read.errors, 1);
+        assert_eq!(top[1].pid, 700);
+        assert_eq!(top[1].total.latency.count, 1);
+
+        assert_eq!(syscalls.top(1, 10).len(), 1);
+    }
+}
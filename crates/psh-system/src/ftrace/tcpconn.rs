@@ -0,0 +1,281 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use super::{Instance, Log2Histogram, TraceRecord};
+use crate::{
+    error::Result,
+    network::{TCP_ESTABLISHED, TCP_LISTEN, TcpSocket, tcp_sockets},
+};
+
+const EVENTS: [&str; 2] = ["sock/inet_sock_set_state", "tcp/tcp_retransmit_skb"];
+const BUFFER_KB: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Direction {
+    /// accepted on a listening port
+    Inbound,
+    /// connected to a remote address
+    Outbound,
+}
+
+/// Inbound connections are grouped by listening port, outbound ones by
+/// remote address and port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Endpoint {
+    pub direction: Direction,
+    /// remote address of outbound connections
+    pub address: Option<IpAddr>,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EndpointStats {
+    /// connections established in the window
+    pub established: u64,
+    /// outbound connects that failed in the window
+    pub failed: u64,
+    /// from SYN to established in microseconds, outbound only
+    pub handshake: Log2Histogram,
+    pub retransmits: u64,
+    /// smoothed rtt of the connections open at the end of the window, in
+    /// microseconds
+    pub rtt: Log2Histogram,
+}
+
+/// Golden signals of TCP endpoints, from the `inet_sock_set_state` and
+/// `tcp_retransmit_skb` tracepoints and a `sock_diag` dump.
+#[derive(Debug, Default)]
+pub struct TcpConnections {
+    /// SYN sent at, by remote address, oldest first
+    connecting: HashMap<SocketAddr, VecDeque<u64>>,
+    outbound: HashMap<SocketAddr, EndpointStats>,
+    /// by local port
+    inbound: HashMap<u16, EndpointStats>,
+    /// by local port and remote address, classified once the listening
+    /// ports are known
+    retransmits: HashMap<(u16, SocketAddr), u64>,
+}
+
+impl TcpConnections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Traces connections for `window`.
+    pub fn collect(window: Duration) -> Result<BTreeMap<Endpoint, EndpointStats>> {
+        let instance = Instance::create(&EVENTS, BUFFER_KB)?;
+        let mut connections = Self::new();
+        instance.read_for(window, |record| connections.handle(record))?;
+        Ok(connections.finish(&tcp_sockets()?))
+    }
+
+    pub fn handle(&mut self, record: &TraceRecord) {
+        let Some((sport, remote)) = addresses(record) else {
+            return;
+        };
+        match record.event {
+            "inet_sock_set_state" => {
+                if record.field("protocol") != Some("IPPROTO_TCP") {
+                    return;
+                }
+                let states = (record.field("oldstate"), record.field("newstate"));
+                match states {
+                    (Some("TCP_CLOSE"), Some("TCP_SYN_SENT")) => {
+                        self.connecting
+                            .entry(remote)
+                            .or_default()
+                            .push_back(record.timestamp_ns);
+                    }
+                    (Some("TCP_SYN_SENT"), Some("TCP_ESTABLISHED")) => {
+                        // concurrent connects to the same remote are paired in
+                        // order, the tracepoint has no socket address
+                        let since = self.connect_started(remote);
+                        let stats = self.outbound.entry(remote).or_default();
+                        stats.established += 1;
+                        if let Some(since) = since {
+                            let latency_us = record.timestamp_ns.saturating_sub(since) / 1000;
+                            stats.handshake.record(latency_us);
+                        }
+                    }
+                    (Some("TCP_SYN_SENT"), Some("TCP_CLOSE")) => {
+                        self.connect_started(remote);
+                        self.outbound.entry(remote).or_default().failed += 1;
+                    }
+                    (Some("TCP_SYN_RECV"), Some("TCP_ESTABLISHED")) => {
+                        self.inbound.entry(sport).or_default().established += 1;
+                    }
+                    _ => {}
+                }
+            }
+            "tcp_retransmit_skb" => {
+                *self.retransmits.entry((sport, remote)).or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn connect_started(&mut self, remote: SocketAddr) -> Option<u64> {
+        let queue = self.connecting.get_mut(&remote)?;
+        let since = queue.pop_front();
+        if queue.is_empty() {
+            self.connecting.remove(&remote);
+        }
+        since
+    }
+
+    /// Attributes retransmits and the rtt of open connections to endpoints,
+    /// connections on a port some socket listens on are inbound.
+    pub fn finish(self, sockets: &[TcpSocket]) -> BTreeMap<Endpoint, EndpointStats> {
+        let listening: HashSet<u16> = sockets
+            .iter()
+            .filter(|it| it.state == TCP_LISTEN)
+            .map(|it| it.local.port())
+            .collect();
+        let endpoint = |local_port: u16, remote: SocketAddr| {
+            if listening.contains(&local_port) {
+                Endpoint {
+                    direction: Direction::Inbound,
+                    address: None,
+                    port: local_port,
+                }
+            } else {
+                Endpoint {
+                    direction: Direction::Outbound,
+                    address: Some(remote.ip()),
+                    port: remote.port(),
+                }
+            }
+        };
+
+        let mut endpoints: BTreeMap<Endpoint, EndpointStats> = BTreeMap::new();
+        for (port, stats) in self.inbound {
+            let key = Endpoint {
+                direction: Direction::Inbound,
+                address: None,
+                port,
+            };
+            endpoints.insert(key, stats);
+        }
+        for (remote, stats) in self.outbound {
+            let key = Endpoint {
+                direction: Direction::Outbound,
+                address: Some(remote.ip()),
+                port: remote.port(),
+            };
+            endpoints.insert(key, stats);
+        }
+        for ((local_port, remote), count) in self.retransmits {
+            endpoints
+                .entry(endpoint(local_port, remote))
+                .or_default()
+                .retransmits += count;
+        }
+        for socket in sockets.iter().filter(|it| it.state == TCP_ESTABLISHED) {
+            endpoints
+                .entry(endpoint(socket.local.port(), socket.remote))
+                .or_default()
+                .rtt
+                .record(u64::from(socket.rtt_us));
+        }
+        endpoints
+    }
+}
+
+/// Local port and remote address of a record.
+fn addresses(record: &TraceRecord) -> Option<(u16, SocketAddr)> {
+    let daddr = match record.field("family")? {
+        "AF_INET" => "daddr",
+        "AF_INET6" => "daddrv6",
+        _ => return None,
+    };
+    let sport = record.field("sport")?.parse().ok()?;
+    let dport = record.field("dport")?.parse().ok()?;
+    let daddr: IpAddr = record.field(daddr)?.parse().ok()?;
+    Some((sport, SocketAddr::new(daddr, dport)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, Endpoint, TcpConnections};
+    use crate::{
+        ftrace::parse_line,
+        network::{TCP_ESTABLISHED, TCP_LISTEN, TcpSocket},
+    };
+
+    fn set_state(timestamp: &str, sport: u16, daddr: &str, dport: u16, states: &str) -> String {
+        format!(
+            "app-100 [001] ..... {timestamp}: inet_sock_set_state: family=AF_INET protocol=IPPROTO_TCP sport={sport} dport={dport} saddr=10.0.0.1 daddr={daddr} saddrv6=::ffff:10.0.0.1 daddrv6=::ffff:{daddr} {states}"
+        )
+    }
+
+    #[test]
+    fn test_tcp_connections() {
+        let lines = [
+            set_state("40.000000", 0, "10.0.0.9", 5432, "oldstate=TCP_CLOSE newstate=TCP_SYN_SENT"),
+            set_state("40.000100", 0, "10.0.0.9", 5432, "oldstate=TCP_CLOSE newstate=TCP_SYN_SENT"),
+            set_state("40.000500", 40000, "10.0.0.9", 5432, "oldstate=TCP_SYN_SENT newstate=TCP_ESTABLISHED"),
+            set_state("41.000100", 40001, "10.0.0.9", 5432, "oldstate=TCP_SYN_SENT newstate=TCP_CLOSE"),
+            set_state("41.500000", 443, "10.0.0.7", 51000, "oldstate=TCP_SYN_RECV newstate=TCP_ESTABLISHED"),
+            "<idle>-0 [000] ..s1. 41.600000: tcp_retransmit_skb: skbaddr=0x1 skaddr=0x2 family=AF_INET sport=443 dport=51000 saddr=10.0.0.1 daddr=10.0.0.7 saddrv6=::ffff:10.0.0.1 daddrv6=::ffff:10.0.0.7 state=TCP_ESTABLISHED".to_string(),
+            "<idle>-0 [000] ..s1. 41.700000: tcp_retransmit_skb: skbaddr=0x3 skaddr=0x4 family=AF_INET sport=40000 dport=5432 saddr=10.0.0.1 daddr=10.0.0.9 saddrv6=::ffff:10.0.0.1 daddrv6=::ffff:10.0.0.9 state=TCP_ESTABLISHED".to_string(),
+        ];
+        let mut connections = TcpConnections::new();
+        for line in &lines {
+            connections.handle(&parse_line(line).unwrap());
+        }
+
+        let socket = |state, local: &str, remote: &str, rtt_us| TcpSocket {
+            state,
+            local: local.parse().unwrap(),
+            remote: remote.parse().unwrap(),
+            rtt_us,
+            total_retrans: 0,
+        };
+        let sockets = [
+            socket(TCP_LISTEN, "0.0.0.0:443", "0.0.0.0:0", 0),
+            socket(TCP_ESTABLISHED, "10.0.0.1:443", "10.0.0.7:51000", 300),
+            socket(TCP_ESTABLISHED, "10.0.0.1:40000", "10.0.0.9:5432", 4000),
+        ];
+        let endpoints = connections.finish(&sockets);
+        assert_eq!(endpoints.len(), 2);
+
+        let inbound = &endpoints[&Endpoint {
+            direction: Direction::Inbound,
+            address: None,
+            port: 443,
+        }];
+        assert_eq!(inbound.established, 1);
+        assert_eq!(inbound.retransmits, 1);
+        assert_eq!(inbound.rtt.max, 300);
+        assert_eq!(inbound.handshake.count, 0);
+
+        let outbound = &endpoints[&Endpoint {
+            direction: Direction::Outbound,
+            address: Some("10.0.0.9".parse().unwrap()),
+            port: 5432,
+        }];
+        assert_eq!(outbound.established, 1);
+        assert_eq!(outbound.failed, 1);
+        assert_eq!(outbound.handshake.count, 1);
+        assert_eq!(outbound.handshake.max, 500);
+        assert_eq!(outbound.retransmits, 1);
+        assert_eq!(outbound.rtt.count, 1);
+    }
+}
@@ -0,0 +1,311 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    time::Duration,
+};
+
+use super::{Instance, TraceRecord};
+use crate::error::Result;
+
+const EVENTS: [&str; 4] = [
+    "sched/sched_switch",
+    "sched/sched_wakeup",
+    "sched/sched_wakeup_new",
+    "sched/sched_process_exit",
+];
+// per cpu, every context switch on the host is traced
+const BUFFER_KB: usize = 8192;
+// longer windows are cut to it
+const MAX_WINDOW: Duration = Duration::from_secs(30);
+// 10 MiB packed, later intervals are left out
+const MAX_INTERVALS: usize = 1 << 18;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    Running,
+    /// runnable, waiting for a cpu after being preempted
+    Preempted,
+    /// runnable, waiting for a cpu after being woken up
+    Woken,
+    /// interruptible sleep, e.g. on a futex or in `poll`
+    Sleeping,
+    /// uninterruptible sleep, mostly disk io
+    Blocked,
+    /// stopped, traced or another state
+    Other,
+}
+
+impl ThreadState {
+    /// The state of a task switched out in `prev_state`, e.g. `S` or `R+`,
+    /// `None` if it exited.
+    fn switched_out(prev_state: &str) -> Option<Self> {
+        match prev_state.as_bytes().first() {
+            Some(b'R') => Some(Self::Preempted),
+            // idle kernel threads
+            Some(b'S' | b'I') => Some(Self::Sleeping),
+            Some(b'D') => Some(Self::Blocked),
+            Some(b'X' | b'Z') => None,
+            _ => Some(Self::Other),
+        }
+    }
+}
+
+/// A thread in one state, timestamps in nanoseconds of `CLOCK_MONOTONIC`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub tid: u32,
+    pub state: ThreadState,
+    pub start_ns: u64,
+    pub end_ns: u64,
+    /// the cpu it ran on, or last ran on, or was woken up onto
+    pub cpu: u32,
+    /// thread id of the task that woke it up, 0 unless [`ThreadState::Woken`]
+    pub waker: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Open {
+    state: ThreadState,
+    since_ns: u64,
+    cpu: u32,
+    waker: u32,
+}
+
+/// On and off cpu intervals of the threads of a process, from the
+/// `sched_switch`, `sched_wakeup` and `sched_process_exit` tracepoints.
+///
+/// A thread's first interval starts at its first event in the window, what
+/// it did before is unknown. Threads created in the window are followed
+/// from their `sched_wakeup_new`.
+#[derive(Debug)]
+pub struct Timeline {
+    pid: u32,
+    /// names of the followed threads, by thread id
+    names: BTreeMap<u32, String>,
+    open: HashMap<u32, Open>,
+    intervals: Vec<Interval>,
+    truncated: bool,
+    last_ns: u64,
+}
+
+impl Timeline {
+    pub fn new(pid: u32, threads: impl IntoIterator<Item = (u32, String)>) -> Self {
+        Self {
+            pid,
+            names: threads.into_iter().collect(),
+            open: HashMap::new(),
+            intervals: Vec::new(),
+            truncated: false,
+            last_ns: 0,
+        }
+    }
+
+    /// Traces the scheduler for `window`, 30 seconds at most, following the
+    /// threads of `pid`.
+    pub fn collect(pid: u32, window: Duration) -> Result<Self> {
+        let mut threads = Vec::new();
+        for entry in fs::read_dir(format!("/proc/{pid}/task"))? {
+            let Some(tid) = entry?.file_name().to_str().and_then(|it| it.parse().ok()) else {
+                continue;
+            };
+            let comm =
+                fs::read_to_string(format!("/proc/{pid}/task/{tid}/comm")).unwrap_or_default();
+            threads.push((tid, comm.trim_end().to_string()));
+        }
+        let instance = Instance::create(&EVENTS, BUFFER_KB)?;
+        let mut timeline = Self::new(pid, threads);
+        instance.read_for(window.min(MAX_WINDOW), |record| timeline.handle(record))?;
+        timeline.finish();
+        Ok(timeline)
+    }
+
+    pub fn handle(&mut self, record: &TraceRecord) {
+        let pid = |key| record.field(key).and_then(|it| it.parse::<u32>().ok());
+        let ts = record.timestamp_ns;
+        self.last_ns = self.last_ns.max(ts);
+        match record.event {
+            "sched_switch" => {
+                if let Some(prev) = pid("prev_pid").filter(|it| self.names.contains_key(it)) {
+                    match record
+                        .field("prev_state")
+                        .and_then(ThreadState::switched_out)
+                    {
+                        Some(state) => self.enter(prev, state, ts, record.cpu, 0),
+                        None => self.close(prev, ts),
+                    }
+                }
+                if let Some(next) = pid("next_pid").filter(|it| self.names.contains_key(it)) {
+                    self.enter(next, ThreadState::Running, ts, record.cpu, 0);
+                }
+            }
+            "sched_wakeup" | "sched_wakeup_new" => {
+                let Some(tid) = pid("pid") else {
+                    return;
+                };
+                if record.event == "sched_wakeup_new" && tgid_of(tid) == Some(self.pid) {
+                    let comm = record.field("comm").unwrap_or_default();
+                    self.names.insert(tid, comm.to_string());
+                }
+                if !self.names.contains_key(&tid) {
+                    return;
+                }
+                // a running task is woken up without effect
+                if self
+                    .open
+                    .get(&tid)
+                    .is_some_and(|it| it.state == ThreadState::Running)
+                {
+                    return;
+                }
+                let cpu = pid("target_cpu").unwrap_or(record.cpu);
+                self.enter(tid, ThreadState::Woken, ts, cpu, record.pid);
+            }
+            "sched_process_exit" => {
+                if let Some(tid) = pid("pid") {
+                    self.close(tid, ts);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn enter(&mut self, tid: u32, state: ThreadState, ts: u64, cpu: u32, waker: u32) {
+        self.close(tid, ts);
+        self.open.insert(
+            tid,
+            Open {
+                state,
+                since_ns: ts,
+                cpu,
+                waker,
+            },
+        );
+    }
+
+    fn close(&mut self, tid: u32, ts: u64) {
+        let Some(open) = self.open.remove(&tid) else {
+            return;
+        };
+        if ts <= open.since_ns {
+            return;
+        }
+        if self.intervals.len() >= MAX_INTERVALS {
+            self.truncated = true;
+        } else {
+            self.intervals.push(Interval {
+                tid,
+                state: open.state,
+                start_ns: open.since_ns,
+                end_ns: ts,
+                cpu: open.cpu,
+                waker: open.waker,
+            });
+        }
+    }
+
+    /// Ends the intervals still open at the last traced event.
+    pub fn finish(&mut self) {
+        let tids: Vec<_> = self.open.keys().copied().collect();
+        for tid in tids {
+            self.close(tid, self.last_ns);
+        }
+        self.intervals
+            .sort_unstable_by_key(|it| (it.start_ns, it.tid));
+    }
+
+    /// Ordered by start.
+    pub fn intervals(&self) -> &[Interval] {
+        &self.intervals
+    }
+
+    /// Whether intervals were left out, past 262144 of them.
+    pub const fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Names of the followed threads, by thread id.
+    pub fn names(&self) -> &BTreeMap<u32, String> {
+        &self.names
+    }
+}
+
+fn tgid_of(tid: u32) -> Option<u32> {
+    let status = fs::read_to_string(format!("/proc/{tid}/status")).ok()?;
+    status
+        .lines()
+        .find_map(|it| it.strip_prefix("Tgid:"))
+        .and_then(|it| it.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interval, MAX_INTERVALS, ThreadState, Timeline};
+    use crate::ftrace::parse_line;
+
+    #[test]
+    fn test_timeline() {
+        let lines = [
+            "<idle>-0 [001] d..2. 10.000100: sched_switch: prev_comm=swapper/1 prev_pid=0 prev_prio=120 prev_state=R ==> next_comm=worker next_pid=201 next_prio=120",
+            "worker-201 [001] d..2. 10.000400: sched_switch: prev_comm=worker prev_pid=201 prev_prio=120 prev_state=D ==> next_comm=other next_pid=300 next_prio=120",
+            "irq/42-300 [000] d..3. 10.002400: sched_wakeup: comm=worker pid=201 prio=120 target_cpu=001",
+            "other-300 [001] d..2. 10.002500: sched_switch: prev_comm=other prev_pid=300 prev_prio=120 prev_state=R+ ==> next_comm=worker next_pid=201 next_prio=120",
+            "worker-201 [001] d..2. 10.003000: sched_switch: prev_comm=worker prev_pid=201 prev_prio=120 prev_state=S ==> next_comm=other next_pid=300 next_prio=120",
+            "other-300 [001] d..2. 10.004000: sched_switch: prev_comm=other prev_pid=300 prev_prio=120 prev_state=S ==> next_comm=swapper/1 next_pid=0 next_prio=120",
+        ];
+        let mut timeline = Timeline::new(200, [(201, "worker".to_string())]);
+        for line in lines {
+            timeline.handle(&parse_line(line).unwrap());
+        }
+        timeline.finish();
+
+        let interval = |state, start_ns, end_ns, waker| Interval {
+            tid: 201,
+            state,
+            start_ns,
+            end_ns,
+            cpu: 1,
+            waker,
+        };
+        // pid 300 belongs to another process
+        assert_eq!(
+            timeline.intervals(),
+            [
+                interval(ThreadState::Running, 10_000_100_000, 10_000_400_000, 0),
+                interval(ThreadState::Blocked, 10_000_400_000, 10_002_400_000, 0),
+                interval(ThreadState::Woken, 10_002_400_000, 10_002_500_000, 300),
+                interval(ThreadState::Running, 10_002_500_000, 10_003_000_000, 0),
+                interval(ThreadState::Sleeping, 10_003_000_000, 10_004_000_000, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut timeline = Timeline::new(200, [(201, "worker".to_string())]);
+        for ts in 1..=MAX_INTERVALS as u64 + 2 {
+            let line = format!(
+                "<idle>-0 [001] d..2. {}.{:06}: sched_switch: prev_comm=swapper/1 prev_pid=0 prev_prio=120 prev_state=R ==> next_comm=worker next_pid=201 next_prio=120",
+                ts / 1_000_000,
+                ts % 1_000_000
+            );
+            timeline.handle(&parse_line(&line).unwrap());
+        }
+        timeline.finish();
+        assert_eq!(timeline.intervals().len(), MAX_INTERVALS);
+        assert!(timeline.truncated());
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{fs, path::Path};
+
+use super::IrqDevice;
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|content| content.trim().to_owned())
+        .filter(|content| !content.is_empty())
+}
+
+fn dir_names(path: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort_unstable();
+    names
+}
+
+/// PCI devices by the IRQs they raise, MSI ones or the legacy one.
+fn pci_irqs(pci_devices: &Path) -> Vec<(u32, String)> {
+    let mut irqs = vec![];
+    for device in dir_names(pci_devices) {
+        let path = pci_devices.join(&device);
+        let msi = dir_names(&path.join("msi_irqs"))
+            .into_iter()
+            .filter_map(|it| it.parse::<u32>().ok());
+        let legacy = read_trimmed(&path.join("irq"))
+            .and_then(|it| it.parse::<u32>().ok())
+            .filter(|it| *it != 0);
+        irqs.extend(msi.chain(legacy).map(|irq| (irq, device.clone())));
+    }
+    irqs
+}
+
+/// Joins `/sys/kernel/irq` with the PCI devices raising the IRQs, their
+/// driver and their network interfaces.
+pub fn do_parse_irq_devices(
+    kernel_irq: &str,
+    pci_devices: &str,
+) -> std::io::Result<Vec<IrqDevice>> {
+    let kernel_irq = Path::new(kernel_irq);
+    let pci_devices = Path::new(pci_devices);
+    let pci_irqs = pci_irqs(pci_devices);
+
+    let mut devices: Vec<IrqDevice> = fs::read_dir(kernel_irq)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let irq_number = entry.file_name().to_str()?.parse::<u32>().ok()?;
+            let path = entry.path();
+            let actions = read_trimmed(&path.join("actions"))
+                .map(|it| it.split(',').map(str::to_owned).collect())
+                .unwrap_or_default();
+
+            let pci_device = pci_irqs
+                .iter()
+                .find(|(irq, _)| *irq == irq_number)
+                .map(|(_, device)| device.clone());
+            let device_path = pci_device.as_ref().map(|it| pci_devices.join(it));
+            let driver = device_path.as_ref().and_then(|it| {
+                fs::read_link(it.join("driver"))
+                    .ok()?
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            });
+            let net_devices = device_path
+                .as_ref()
+                .map(|it| dir_names(&it.join("net")))
+                .unwrap_or_default();
+            // e.g. `eth0-TxRx-3` is queue `TxRx-3` of `eth0`
+            let queue = actions.iter().find_map(|action: &String| {
+                net_devices.iter().find_map(|net| {
+                    action
+                        .strip_prefix(net.as_str())?
+                        .strip_prefix('-')
+                        .map(str::to_owned)
+                })
+            });
+
+            Some(IrqDevice {
+                irq_number,
+                actions,
+                chip_name: read_trimmed(&path.join("chip_name")),
+                hwirq: read_trimmed(&path.join("hwirq")).and_then(|it| it.parse().ok()),
+                pci_device,
+                driver,
+                net_devices,
+                queue,
+            })
+        })
+        .collect();
+    devices.sort_unstable_by_key(|it| it.irq_number);
+
+    Ok(devices)
+}
+
+macro_rules! parse_irq_devices {
+    ($kernel_irq:expr, $pci_devices:expr) => {
+        crate::interrupt::device::do_parse_irq_devices($kernel_irq, $pci_devices)
+    };
+    () => {
+        crate::interrupt::device::do_parse_irq_devices("/sys/kernel/irq", "/sys/bus/pci/devices")
+    };
+}
+
+pub(crate) use parse_irq_devices;
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_irq_devices() {
+        let mut sys = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sys.push("./test_resources/arch/x86_64/intel/sys");
+
+        let result = do_parse_irq_devices(
+            sys.join("kernel/irq").to_str().unwrap(),
+            sys.join("bus/pci/devices").to_str().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(result.len(), 2);
+
+        let timer = &result[0];
+        assert_eq!(timer.irq_number, 0);
+        assert_eq!(timer.actions, vec!["timer".to_string()]);
+        assert_eq!(timer.chip_name, Some("IR-IO-APIC".to_string()));
+        assert_eq!(timer.pci_device, None);
+        assert_eq!(timer.queue, None);
+
+        let queue = &result[1];
+        assert_eq!(queue.irq_number, 142);
+        assert_eq!(queue.actions, vec!["eth0-TxRx-3".to_string()]);
+        assert_eq!(
+            queue.chip_name,
+            Some("IR-PCI-MSIX-0000:3b:00.0".to_string())
+        );
+        assert_eq!(queue.hwirq, Some(524292));
+        assert_eq!(queue.pci_device, Some("0000:3b:00.0".to_string()));
+        assert_eq!(queue.driver, Some("ice".to_string()));
+        assert_eq!(queue.net_devices, vec!["eth0".to_string()]);
+        assert_eq!(queue.queue, Some("TxRx-3".to_string()));
+    }
+}
@@ -14,22 +14,26 @@
 
 use std::{sync::LazyLock, time::Duration};
 
-use super::{InterruptDetails, IrqDetails};
+use super::{InterruptDetails, IrqDetails, IrqDevice};
 use crate::{
     error::Result,
-    interrupt::raw::{parse_interrupts, parse_irq},
+    interrupt::raw::{parse_interrupts, parse_irq, parse_irq_devices},
     utils::Handle,
 };
 
 static INFO_GLOBAL: LazyLock<Handle<Vec<IrqDetails>>> =
     LazyLock::new(|| Handle::new(|| parse_irq!().map_err(Into::into)));
 
+static DEVICES_GLOBAL: LazyLock<Handle<Vec<IrqDevice>>> =
+    LazyLock::new(|| Handle::new(|| parse_irq_devices!().map_err(Into::into)));
+
 static STAT_GLOBAL: LazyLock<Handle<Vec<InterruptDetails>>> =
     LazyLock::new(|| Handle::new(|| parse_interrupts!().map_err(Into::into)));
 
 #[derive(Debug, Clone)]
 pub struct InterruptHandle {
     info: Handle<Vec<IrqDetails>>,
+    devices: Handle<Vec<IrqDevice>>,
     stat: Handle<Vec<InterruptDetails>>,
 }
 
@@ -37,6 +41,7 @@ impl Default for InterruptHandle {
     fn default() -> Self {
         Self {
             info: INFO_GLOBAL.clone(),
+            devices: DEVICES_GLOBAL.clone(),
             stat: STAT_GLOBAL.clone(),
         }
     }
@@ -51,6 +56,12 @@ impl InterruptHandle {
         self.info.get(None)
     }
 
+    /// Devices behind the IRQs, for names like `eth0-TxRx-3` instead of
+    /// numbers.
+    pub fn devices(&self) -> Result<Vec<IrqDevice>> {
+        self.devices.get(None)
+    }
+
     pub fn stat(&self, interval: Option<Duration>) -> Result<Vec<InterruptDetails>> {
         self.stat.get(interval)
     }
@@ -12,6 +12,7 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
+mod device;
 pub(crate) mod handle;
 mod irq;
 mod raw;
@@ -74,3 +75,22 @@ impl IrqDetails {
         }
     }
 }
+
+/// The device behind an IRQ, from sysfs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IrqDevice {
+    pub irq_number: u32,
+    /// names of the registered handlers, e.g. `eth0-TxRx-3`
+    pub actions: Vec<String>,
+    /// e.g. `IR-PCI-MSIX-0000:3b:00.0`
+    pub chip_name: Option<String>,
+    /// number of the IRQ in the domain of its chip
+    pub hwirq: Option<u64>,
+    /// PCI address of the device raising it, e.g. `0000:3b:00.0`
+    pub pci_device: Option<String>,
+    pub driver: Option<String>,
+    /// network interfaces of the PCI device
+    pub net_devices: Vec<String>,
+    /// e.g. `TxRx-3` if an action is named after a network interface
+    pub queue: Option<String>,
+}
@@ -0,0 +1,50 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+mod raw;
+mod reader;
+
+pub use raw::parse_record;
+pub use reader::KmsgReader;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum KernelEventKind {
+    OomKill {
+        pid: u32,
+        comm: String,
+    },
+    HungTask {
+        pid: u32,
+        comm: String,
+        blocked_secs: u64,
+    },
+    SoftLockup {
+        cpu: u32,
+        stuck_secs: u64,
+        pid: u32,
+        comm: String,
+    },
+    HardLockup {
+        cpu: u32,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct KernelEvent {
+    pub seq: u64,
+    /// microseconds since boot
+    pub timestamp_us: u64,
+    pub kind: KernelEventKind,
+    pub message: String,
+}
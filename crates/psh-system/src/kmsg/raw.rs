@@ -0,0 +1,172 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use super::{KernelEvent, KernelEventKind};
+
+fn leading_num<T: std::str::FromStr>(s: &str) -> Option<T> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s[..end].parse().ok()
+}
+
+// `comm:pid`, comm itself may contain ':'
+fn comm_pid(s: &str) -> Option<(String, u32)> {
+    let (comm, pid) = s.rsplit_once(':')?;
+    Some((comm.to_string(), pid.parse().ok()?))
+}
+
+fn classify(message: &str) -> Option<KernelEventKind> {
+    // "Out of memory: Killed process 4321 (stress) total-vm:..."
+    // "Memory cgroup out of memory: Killed process 4321 (stress) total-vm:..."
+    if let Some((_, rest)) = message.split_once("Killed process ") {
+        let pid = leading_num(rest)?;
+        let (_, comm) = rest.split_once(" (")?;
+        let (comm, _) = comm.split_once(')')?;
+        return Some(KernelEventKind::OomKill {
+            pid,
+            comm: comm.to_string(),
+        });
+    }
+
+    // "INFO: task kworker/0:1:42 blocked for more than 120 seconds."
+    if let Some(rest) = message.strip_prefix("INFO: task ") {
+        let (task, rest) = rest.split_once(" blocked for more than ")?;
+        let (comm, pid) = comm_pid(task)?;
+        return Some(KernelEventKind::HungTask {
+            pid,
+            comm,
+            blocked_secs: leading_num(rest)?,
+        });
+    }
+
+    // "watchdog: BUG: soft lockup - CPU#3 stuck for 22s! [stress:4321]"
+    if let Some((_, rest)) = message.split_once("soft lockup - CPU#") {
+        let cpu = leading_num(rest)?;
+        let (_, rest) = rest.split_once("stuck for ")?;
+        let stuck_secs = leading_num(rest)?;
+        let (_, task) = rest.split_once('[')?;
+        let (comm, pid) = comm_pid(task.trim_end().trim_end_matches(']'))?;
+        return Some(KernelEventKind::SoftLockup {
+            cpu,
+            stuck_secs,
+            pid,
+            comm,
+        });
+    }
+
+    // "Watchdog detected hard LOCKUP on cpu 3"
+    if let Some((_, rest)) = message.split_once("hard LOCKUP on cpu ") {
+        return Some(KernelEventKind::HardLockup {
+            cpu: leading_num(rest)?,
+        });
+    }
+
+    None
+}
+
+/// Parses one `/dev/kmsg` record, see
+/// <https://www.kernel.org/doc/Documentation/ABI/testing/dev-kmsg>.
+///
+/// Returns `None` for records that are not an interesting kernel event.
+pub fn parse_record(record: &str) -> Option<KernelEvent> {
+    let (header, message) = record.split_once(';')?;
+    // continuation lines (" SUBSYSTEM=...") are not part of the message
+    let message = message.lines().next().unwrap_or_default().trim_end();
+
+    let mut fields = header.split(',');
+    let _prio = fields.next()?;
+    let seq = fields.next()?.parse().ok()?;
+    let timestamp_us = fields.next()?.parse().ok()?;
+
+    Some(KernelEvent {
+        seq,
+        timestamp_us,
+        kind: classify(message)?,
+        message: message.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_record;
+    use crate::kmsg::{KernelEvent, KernelEventKind};
+
+    #[test]
+    fn test_parse_oom_kill() {
+        let message = "Out of memory: Killed process 4321 (stress) total-vm:1048576kB, anon-rss:524288kB, file-rss:0kB, shmem-rss:0kB, UID:0 pgtables:1080kB oom_score_adj:0";
+        let record = format!("3,1024,5678901,-;{message}\n");
+        let event = parse_record(&record).unwrap();
+        assert_eq!(
+            event,
+            KernelEvent {
+                seq: 1024,
+                timestamp_us: 5678901,
+                kind: KernelEventKind::OomKill {
+                    pid: 4321,
+                    comm: "stress".to_string(),
+                },
+                message: message.to_string(),
+            }
+        );
+
+        let record =
+            "3,1025,5678902,-;Memory cgroup out of memory: Killed process 99 (java) total-vm:1kB";
+        assert_eq!(
+            parse_record(record).unwrap().kind,
+            KernelEventKind::OomKill {
+                pid: 99,
+                comm: "java".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_hung_task() {
+        let record = "3,7,100,-;INFO: task kworker/0:1:42 blocked for more than 120 seconds.\n SUBSYSTEM=cpu";
+        assert_eq!(
+            parse_record(record).unwrap().kind,
+            KernelEventKind::HungTask {
+                pid: 42,
+                comm: "kworker/0:1".to_string(),
+                blocked_secs: 120,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_lockups() {
+        let record = "0,8,200,-;watchdog: BUG: soft lockup - CPU#3 stuck for 22s! [stress:4321]";
+        assert_eq!(
+            parse_record(record).unwrap().kind,
+            KernelEventKind::SoftLockup {
+                cpu: 3,
+                stuck_secs: 22,
+                pid: 4321,
+                comm: "stress".to_string(),
+            }
+        );
+
+        let record = "0,9,300,-;Watchdog detected hard LOCKUP on cpu 5";
+        assert_eq!(
+            parse_record(record).unwrap().kind,
+            KernelEventKind::HardLockup { cpu: 5 }
+        );
+    }
+
+    #[test]
+    fn test_parse_uninteresting() {
+        assert_eq!(parse_record("6,10,400,-;eth0: link up"), None);
+        assert_eq!(parse_record("garbage"), None);
+        assert_eq!(parse_record("6,x,400,-;Killed process 1 (init)"), None);
+    }
+}
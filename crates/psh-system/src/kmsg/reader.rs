@@ -0,0 +1,73 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+use super::{KernelEvent, raw::parse_record};
+use crate::error::Result;
+
+// records longer than this are truncated by the kernel anyway
+const RECORD_MAX: usize = 8192;
+
+/// Blocking reader of kernel events from `/dev/kmsg`.
+#[derive(Debug)]
+pub struct KmsgReader {
+    file: File,
+    buf: Vec<u8>,
+}
+
+impl KmsgReader {
+    /// Opens `/dev/kmsg` positioned after the last record, so only events
+    /// happening from now on are reported.
+    pub fn new() -> Result<Self> {
+        Self::open("/dev/kmsg")
+    }
+
+    pub fn open(path: &str) -> Result<Self> {
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            file,
+            buf: vec![0; RECORD_MAX],
+        })
+    }
+
+    /// Blocks until the next interesting kernel event.
+    pub fn next_event(&mut self) -> Result<KernelEvent> {
+        loop {
+            let record = self.next_record()?;
+            if let Some(event) = parse_record(&record) {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Blocks until the next record, e.g. `6,339,5140900,-;NET: Registered PF_INET6`.
+    pub fn next_record(&mut self) -> Result<String> {
+        loop {
+            // every read returns exactly one record
+            let len = match self.file.read(&mut self.buf) {
+                Ok(len) => len,
+                // EPIPE: the ring buffer wrapped and overwrote unread records
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => continue,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            return Ok(String::from_utf8_lossy(&self.buf[..len]).into_owned());
+        }
+    }
+}
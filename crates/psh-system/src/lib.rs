@@ -12,16 +12,35 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
+#[cfg(target_os = "linux")]
+pub mod cgroup;
+#[cfg(target_os = "linux")]
+pub mod clock;
 pub mod cpu;
 pub mod disk;
 pub mod error;
+#[cfg(target_os = "linux")]
+pub mod filesystem;
+#[cfg(target_os = "linux")]
+pub mod ftrace;
+#[cfg(target_os = "linux")]
 pub mod interrupt;
+#[cfg(target_os = "linux")]
+pub mod kmsg;
 pub mod memory;
+#[cfg(target_os = "linux")]
+mod netlink;
 pub mod network;
 pub mod os;
+#[cfg(target_os = "linux")]
+pub mod proc_events;
 pub mod process;
+pub mod rate_limit;
+#[cfg(target_os = "linux")]
 pub mod rps;
+mod sys;
 mod utils;
+#[cfg(target_os = "linux")]
 pub mod vmstat;
 
 #[allow(dead_code)]
@@ -35,9 +54,9 @@ pub struct System {
 impl Default for System {
     fn default() -> Self {
         Self {
-            page_size: procfs::page_size(),
-            boot_time_sec: procfs::boot_time_secs().unwrap_or(0),
-            tick_per_sec: procfs::ticks_per_second(),
+            page_size: sys::page_size(),
+            boot_time_sec: sys::boot_time_secs(),
+            tick_per_sec: sys::ticks_per_second(),
         }
     }
 }
@@ -0,0 +1,224 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::raw::parse_meminfo;
+use crate::error::Result;
+
+// Older kernels split reclaim counters per zone, e.g. `pgscan_kswapd_normal`.
+const ZONES: [&str; 5] = ["", "_dma", "_dma32", "_normal", "_movable"];
+
+#[derive(Debug, Clone)]
+pub struct MemorySnapshot {
+    pub timestamp: Instant,
+    /// in bytes
+    pub mem_available: Option<u64>,
+    /// in bytes
+    pub dirty: u64,
+    /// in bytes
+    pub writeback: u64,
+    /// raw `/proc/vmstat` counters
+    pub vmstat: HashMap<String, i64>,
+}
+
+impl MemorySnapshot {
+    pub fn capture() -> Result<Self> {
+        let meminfo = parse_meminfo!()?;
+        let vmstat = procfs::vmstat()?;
+        Ok(Self {
+            timestamp: Instant::now(),
+            mem_available: meminfo.mem_available,
+            dirty: meminfo.dirty,
+            writeback: meminfo.writeback,
+            vmstat,
+        })
+    }
+
+    fn counter(&self, name: &str) -> i64 {
+        ZONES
+            .iter()
+            .filter_map(|zone| self.vmstat.get(&format!("{name}{zone}")))
+            .sum()
+    }
+}
+
+/// Memory activity derived from two [`MemorySnapshot`]s.
+///
+/// All rates are per second and normalized to bytes where the kernel
+/// reports KiB (`pgpgin`/`pgpgout`) or pages (`pswpin`/`pswpout`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryDelta {
+    pub interval: Duration,
+    pub page_in_bytes_per_sec: f64,
+    pub page_out_bytes_per_sec: f64,
+    pub swap_in_bytes_per_sec: f64,
+    pub swap_out_bytes_per_sec: f64,
+    pub page_faults_per_sec: f64,
+    pub major_faults_per_sec: f64,
+    pub kswapd_scan_per_sec: f64,
+    pub direct_scan_per_sec: f64,
+    pub kswapd_steal_per_sec: f64,
+    pub direct_steal_per_sec: f64,
+    /// Pages reclaimed per page scanned, `None` if nothing was scanned.
+    pub reclaim_efficiency: Option<f64>,
+    /// in bytes, negative if dirty memory shrunk
+    pub dirty_growth: i64,
+    /// in bytes, negative if writeback memory shrunk
+    pub writeback_growth: i64,
+    /// in bytes
+    pub mem_available_change: Option<i64>,
+}
+
+impl MemoryDelta {
+    pub fn between(before: &MemorySnapshot, after: &MemorySnapshot, page_size: u64) -> Self {
+        let interval = after.timestamp.saturating_duration_since(before.timestamp);
+        let secs = interval.as_secs_f64();
+        let rate = |name: &str, unit: u64| {
+            if secs == 0.0 {
+                return 0.0;
+            }
+            // counters may be reset by a kernel with counter wrapping, never report negative rates
+            let diff = (after.counter(name) - before.counter(name)).max(0);
+            (diff as u64 * unit) as f64 / secs
+        };
+        let growth = |before: u64, after: u64| after as i64 - before as i64;
+
+        let kswapd_scan_per_sec = rate("pgscan_kswapd", 1);
+        let direct_scan_per_sec = rate("pgscan_direct", 1);
+        let kswapd_steal_per_sec = rate("pgsteal_kswapd", 1);
+        let direct_steal_per_sec = rate("pgsteal_direct", 1);
+        let scanned = kswapd_scan_per_sec + direct_scan_per_sec;
+        let reclaim_efficiency =
+            (scanned > 0.0).then(|| (kswapd_steal_per_sec + direct_steal_per_sec) / scanned);
+
+        Self {
+            interval,
+            page_in_bytes_per_sec: rate("pgpgin", 1024),
+            page_out_bytes_per_sec: rate("pgpgout", 1024),
+            swap_in_bytes_per_sec: rate("pswpin", page_size),
+            swap_out_bytes_per_sec: rate("pswpout", page_size),
+            page_faults_per_sec: rate("pgfault", 1),
+            major_faults_per_sec: rate("pgmajfault", 1),
+            kswapd_scan_per_sec,
+            direct_scan_per_sec,
+            kswapd_steal_per_sec,
+            direct_steal_per_sec,
+            reclaim_efficiency,
+            dirty_growth: growth(before.dirty, after.dirty),
+            writeback_growth: growth(before.writeback, after.writeback),
+            mem_available_change: before
+                .mem_available
+                .zip(after.mem_available)
+                .map(|(before, after)| growth(before, after)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    };
+
+    use super::{MemoryDelta, MemorySnapshot};
+
+    fn snapshot(timestamp: Instant, dirty: u64, vmstat: &[(&str, i64)]) -> MemorySnapshot {
+        MemorySnapshot {
+            timestamp,
+            mem_available: Some(4096),
+            dirty,
+            writeback: 0,
+            vmstat: vmstat
+                .iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn test_memory_delta() {
+        let now = Instant::now();
+        let before = snapshot(
+            now,
+            8192,
+            &[
+                ("pgpgin", 100),
+                ("pgpgout", 0),
+                ("pswpin", 10),
+                ("pswpout", 0),
+                ("pgfault", 1000),
+                ("pgmajfault", 1),
+                ("pgscan_kswapd", 0),
+                ("pgscan_direct", 0),
+                ("pgsteal_kswapd", 0),
+                ("pgsteal_direct", 0),
+            ],
+        );
+        let after = snapshot(
+            now + Duration::from_secs(2),
+            4096,
+            &[
+                ("pgpgin", 300),
+                ("pgpgout", 2),
+                ("pswpin", 20),
+                ("pswpout", 0),
+                ("pgfault", 3000),
+                ("pgmajfault", 5),
+                ("pgscan_kswapd", 100),
+                ("pgscan_direct", 100),
+                ("pgsteal_kswapd", 50),
+                ("pgsteal_direct", 50),
+            ],
+        );
+
+        let delta = MemoryDelta::between(&before, &after, 4096);
+        assert_eq!(delta.interval, Duration::from_secs(2));
+        assert_eq!(delta.page_in_bytes_per_sec, 100.0 * 1024.0);
+        assert_eq!(delta.page_out_bytes_per_sec, 1024.0);
+        assert_eq!(delta.swap_in_bytes_per_sec, 5.0 * 4096.0);
+        assert_eq!(delta.swap_out_bytes_per_sec, 0.0);
+        assert_eq!(delta.page_faults_per_sec, 1000.0);
+        assert_eq!(delta.major_faults_per_sec, 2.0);
+        assert_eq!(delta.kswapd_scan_per_sec, 50.0);
+        assert_eq!(delta.direct_steal_per_sec, 25.0);
+        assert_eq!(delta.reclaim_efficiency, Some(0.5));
+        assert_eq!(delta.dirty_growth, -4096);
+        assert_eq!(delta.mem_available_change, Some(0));
+    }
+
+    #[test]
+    fn test_memory_delta_per_zone_counters() {
+        let now = Instant::now();
+        let before = snapshot(now, 0, &[]);
+        let after = snapshot(
+            now + Duration::from_secs(1),
+            0,
+            &[
+                ("pgscan_kswapd_dma32", 10),
+                ("pgscan_kswapd_normal", 30),
+                ("pgscan_direct_throttle", 1000),
+            ],
+        );
+
+        let delta = MemoryDelta::between(&before, &after, 4096);
+        assert_eq!(delta.kswapd_scan_per_sec, 40.0);
+        assert_eq!(delta.direct_scan_per_sec, 0.0);
+        assert_eq!(delta.reclaim_efficiency, Some(0.0));
+    }
+}
@@ -12,28 +12,19 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
-use std::{process::Command, sync::LazyLock, time::Duration};
+use std::{sync::LazyLock, time::Duration};
 
-use procfs::Meminfo;
+use procfs_core::Meminfo;
 
-use super::{
-    MemoryModule,
-    raw::{parse_meminfo, parse_memory_module},
-};
-use crate::{error::Result, utils::Handle};
+use super::MemoryModule;
+#[cfg(target_os = "linux")]
+use super::{MemoryDelta, MemorySnapshot, SwapDevice, SwapRate};
+use crate::{error::Result, sys, utils::Handle};
 
-static STAT_GLOBAL: LazyLock<Handle<Meminfo>> =
-    LazyLock::new(|| Handle::new(|| parse_meminfo!().map_err(Into::into)));
+static STAT_GLOBAL: LazyLock<Handle<Meminfo>> = LazyLock::new(|| Handle::new(sys::meminfo));
 
-static INFO_GLOBAL: LazyLock<Handle<Vec<MemoryModule>>> = LazyLock::new(|| {
-    Handle::new(|| {
-        let dmidecode_exe = which::which("dmidecode")?;
-        let output = Command::new(dmidecode_exe).arg("-t").arg("17").output()?;
-        let content = std::str::from_utf8(&output.stdout)?;
-
-        Ok(parse_memory_module(content).into_iter().collect::<Vec<_>>())
-    })
-});
+static INFO_GLOBAL: LazyLock<Handle<Vec<MemoryModule>>> =
+    LazyLock::new(|| Handle::new(sys::memory_modules));
 
 #[derive(Debug, Clone)]
 pub struct MemoryHandle {
@@ -62,4 +53,32 @@ impl MemoryHandle {
     pub fn stat(&self, interval: Option<Duration>) -> Result<Meminfo> {
         self.stat.get(interval)
     }
+
+    /// Blocks for `interval` between two fresh snapshots, cached stats are
+    /// not used as they could make both snapshots identical.
+    #[cfg(target_os = "linux")]
+    pub fn delta(&self, interval: Duration) -> Result<MemoryDelta> {
+        let before = MemorySnapshot::capture()?;
+        std::thread::sleep(interval);
+        let after = MemorySnapshot::capture()?;
+        Ok(MemoryDelta::between(&before, &after, sys::page_size()))
+    }
+
+    /// Swap in and out rates from `/proc/vmstat` and the change of swap
+    /// used, blocks for `interval` like [`Self::delta`].
+    #[cfg(target_os = "linux")]
+    pub fn swap_rate(&self, interval: Duration) -> Result<SwapRate> {
+        let used =
+            || -> Result<u64> { Ok(SwapDevice::capture()?.iter().map(|it| it.used_bytes).sum()) };
+        let (before, used_before) = (MemorySnapshot::capture()?, used()?);
+        std::thread::sleep(interval);
+        let (after, used_after) = (MemorySnapshot::capture()?, used()?);
+        let delta = MemoryDelta::between(&before, &after, sys::page_size());
+        Ok(SwapRate {
+            interval: delta.interval,
+            swap_in_bytes_per_sec: delta.swap_in_bytes_per_sec,
+            swap_out_bytes_per_sec: delta.swap_out_bytes_per_sec,
+            used_change: used_after as i64 - used_before as i64,
+        })
+    }
 }
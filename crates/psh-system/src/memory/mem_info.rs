@@ -12,7 +12,7 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
-use procfs::{FromRead, Meminfo, ProcError};
+use procfs_core::{FromRead, Meminfo, ProcError};
 
 pub fn do_parse_meminfo(path: &str) -> Result<Meminfo, ProcError> {
     Meminfo::from_file(path)
@@ -12,13 +12,19 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
+#[cfg(target_os = "linux")]
+mod delta;
 pub(crate) mod handle;
-mod mem_info;
+pub(crate) mod mem_info;
 mod memory_module;
-mod raw;
+pub(crate) mod raw;
+mod swap;
 
+#[cfg(target_os = "linux")]
+pub use delta::{MemoryDelta, MemorySnapshot};
 pub use handle::MemoryHandle;
-pub use procfs::Meminfo;
+pub use procfs_core::Meminfo;
+pub use swap::{ProcessSwap, SwapDevice, SwapRate};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct MemoryModule {
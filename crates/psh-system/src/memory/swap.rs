@@ -0,0 +1,165 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{fs, path::Path, time::Duration};
+
+use crate::error::Result;
+
+/// A swap partition or file of `/proc/swaps`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapDevice {
+    pub path: String,
+    /// `partition` or `file`
+    pub kind: String,
+    pub size_bytes: u64,
+    pub used_bytes: u64,
+    /// higher is used first
+    pub priority: i32,
+}
+
+impl SwapDevice {
+    pub fn parse(swaps: &str) -> Vec<Self> {
+        swaps
+            .lines()
+            .skip(1)
+            .filter_map(|line| {
+                // the path may contain spaces, escaped as `\040`
+                let mut fields = line.split_whitespace().rev();
+                let priority = fields.next()?.parse().ok()?;
+                let used: u64 = fields.next()?.parse().ok()?;
+                let size: u64 = fields.next()?.parse().ok()?;
+                let kind = fields.next()?.to_string();
+                let path: Vec<_> = fields.rev().collect();
+                Some(Self {
+                    path: path.join(" ").replace("\\040", " "),
+                    kind,
+                    size_bytes: size * 1024,
+                    used_bytes: used * 1024,
+                    priority,
+                })
+            })
+            .collect()
+    }
+
+    pub fn capture() -> Result<Vec<Self>> {
+        Ok(Self::parse(&fs::read_to_string("/proc/swaps")?))
+    }
+}
+
+/// Swap activity between two snapshots, see [`super::MemoryHandle::swap_rate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapRate {
+    pub interval: Duration,
+    pub swap_in_bytes_per_sec: f64,
+    pub swap_out_bytes_per_sec: f64,
+    /// of every swap device, in bytes
+    pub used_change: i64,
+}
+
+/// Swap used by a process, from `/proc/<pid>/smaps_rollup`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessSwap {
+    pub pid: i32,
+    pub name: String,
+    pub swap_bytes: u64,
+    /// the share of the process in swapped out pages it shares with
+    /// others, adds up to the swap used over all processes
+    pub swap_pss_bytes: u64,
+}
+
+/// `Swap:  1234 kB` in bytes.
+fn kb_field(content: &str, key: &str) -> u64 {
+    content
+        .lines()
+        .find_map(|it| it.strip_prefix(key)?.strip_prefix(':'))
+        .and_then(|it| it.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map_or(0, |kb| kb * 1024)
+}
+
+impl ProcessSwap {
+    pub fn parse(pid: i32, comm: &str, smaps_rollup: &str) -> Self {
+        Self {
+            pid,
+            name: comm.trim().to_string(),
+            swap_bytes: kb_field(smaps_rollup, "Swap"),
+            swap_pss_bytes: kb_field(smaps_rollup, "SwapPss"),
+        }
+    }
+
+    /// Processes with swapped out memory, the most first.
+    pub fn capture() -> Result<Vec<Self>> {
+        Self::capture_from(Path::new("/proc"))
+    }
+
+    pub fn capture_from(proc: &Path) -> Result<Vec<Self>> {
+        let mut swaps = vec![];
+        for entry in fs::read_dir(proc)? {
+            let entry = entry?;
+            let Some(pid) = entry.file_name().to_str().and_then(|it| it.parse().ok()) else {
+                continue;
+            };
+            let dir = entry.path();
+            let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+            // smaps_rollup walks every mapping, status is cheap to skip
+            // processes without swap, kernel threads have neither
+            let Some(status) = read("status") else {
+                continue;
+            };
+            if kb_field(&status, "VmSwap") == 0 {
+                continue;
+            }
+            // processes may exit while reading
+            let (Some(comm), Some(rollup)) = (read("comm"), read("smaps_rollup")) else {
+                continue;
+            };
+            swaps.push(Self::parse(pid, &comm, &rollup));
+        }
+        swaps.sort_by(|a, b| b.swap_bytes.cmp(&a.swap_bytes).then(a.pid.cmp(&b.pid)));
+        Ok(swaps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProcessSwap, SwapDevice};
+
+    #[test]
+    fn test_parse_swaps() {
+        let swaps = "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n\
+                     /dev/dm-1                               partition\t8388604\t\t1024\t\t-2\n\
+                     /var/swap\\040file                        file\t\t2097148\t\t0\t\t10\n";
+        let devices = SwapDevice::parse(swaps);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].path, "/dev/dm-1");
+        assert_eq!(devices[0].kind, "partition");
+        assert_eq!(devices[0].size_bytes, 8388604 * 1024);
+        assert_eq!(devices[0].used_bytes, 1024 * 1024);
+        assert_eq!(devices[0].priority, -2);
+        assert_eq!(devices[1].path, "/var/swap file");
+        assert_eq!(devices[1].priority, 10);
+    }
+
+    #[test]
+    fn test_parse_process_swap() {
+        let rollup = "00400000-7ffd5a5f3000 ---p 00000000 00:00 0                  [rollup]\n\
+                      Rss:               20480 kB\n\
+                      Swap:               3072 kB\n\
+                      SwapPss:            1536 kB\n\
+                      Locked:                0 kB\n";
+        let swap = ProcessSwap::parse(42, "redis-server\n", rollup);
+        assert_eq!(swap.name, "redis-server");
+        assert_eq!(swap.swap_bytes, 3072 * 1024);
+        assert_eq!(swap.swap_pss_bytes, 1536 * 1024);
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+};
+
+use crate::error::Result;
+
+pub const NLMSG_ERROR: u16 = 2;
+pub const NLMSG_DONE: u16 = 3;
+pub const NLM_F_REQUEST: u16 = 0x1;
+pub const NLM_F_DUMP: u16 = 0x300;
+
+const HEADER_LEN: usize = 16;
+
+/// A netlink socket talking to the kernel, messages are built and parsed
+/// by the caller.
+#[derive(Debug)]
+pub struct Netlink {
+    fd: OwnedFd,
+    seq: u32,
+}
+
+/// A message without its `nlmsghdr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Message<'a> {
+    pub kind: u16,
+    pub payload: &'a [u8],
+}
+
+impl Netlink {
+    /// Binds to the multicast `groups` of `protocol`, e.g. `NETLINK_SOCK_DIAG`.
+    pub fn open(protocol: libc::c_int, groups: u32) -> Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                protocol,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = groups;
+        let ret = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                (&raw const addr).cast(),
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(Self { fd, seq: 0 })
+    }
+
+    pub fn send(&mut self, kind: u16, flags: u16, payload: &[u8]) -> Result<()> {
+        self.seq = self.seq.wrapping_add(1);
+        let mut msg = Vec::with_capacity(HEADER_LEN + payload.len());
+        msg.extend_from_slice(&((HEADER_LEN + payload.len()) as u32).to_ne_bytes());
+        msg.extend_from_slice(&kind.to_ne_bytes());
+        msg.extend_from_slice(&flags.to_ne_bytes());
+        msg.extend_from_slice(&self.seq.to_ne_bytes());
+        // the kernel fills in the port id
+        msg.extend_from_slice(&0u32.to_ne_bytes());
+        msg.extend_from_slice(payload);
+        let ret = unsafe { libc::send(self.fd.as_raw_fd(), msg.as_ptr().cast(), msg.len(), 0) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    /// Blocks for the next datagram, which may hold several messages.
+    pub fn recv<'a>(&self, buf: &'a mut [u8]) -> Result<Vec<Message<'a>>> {
+        let len = loop {
+            let ret =
+                unsafe { libc::recv(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+            if ret >= 0 {
+                break ret as usize;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err.into());
+            }
+        };
+        Ok(parse_messages(&buf[..len]))
+    }
+
+    /// Sends a dump request and collects the payloads of the replies up to
+    /// `NLMSG_DONE`.
+    pub fn dump(&mut self, kind: u16, payload: &[u8]) -> Result<Vec<Vec<u8>>> {
+        self.send(kind, NLM_F_REQUEST | NLM_F_DUMP, payload)?;
+        let mut buf = vec![0; 32 * 1024];
+        let mut replies = vec![];
+        loop {
+            for msg in self.recv(&mut buf)? {
+                match msg.kind {
+                    NLMSG_DONE => return Ok(replies),
+                    NLMSG_ERROR => {
+                        let errno = msg
+                            .payload
+                            .get(..4)
+                            .map_or(0, |it| i32::from_ne_bytes(it.try_into().unwrap()));
+                        return Err(io::Error::from_raw_os_error(-errno).into());
+                    }
+                    _ => replies.push(msg.payload.to_vec()),
+                }
+            }
+        }
+    }
+}
+
+pub fn parse_messages(mut buf: &[u8]) -> Vec<Message<'_>> {
+    let mut messages = vec![];
+    while buf.len() >= HEADER_LEN {
+        let len = u32::from_ne_bytes(buf[..4].try_into().unwrap()) as usize;
+        if len < HEADER_LEN || len > buf.len() {
+            break;
+        }
+        messages.push(Message {
+            kind: u16::from_ne_bytes(buf[4..6].try_into().unwrap()),
+            payload: &buf[HEADER_LEN..len],
+        });
+        buf = &buf[align(len).min(buf.len())..];
+    }
+    messages
+}
+
+/// Attributes of a message as `(type, value)`, in `rtattr` layout.
+pub fn parse_attributes(mut buf: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut attributes = vec![];
+    while buf.len() >= 4 {
+        let len = u16::from_ne_bytes(buf[..2].try_into().unwrap()) as usize;
+        if len < 4 || len > buf.len() {
+            break;
+        }
+        let kind = u16::from_ne_bytes(buf[2..4].try_into().unwrap());
+        attributes.push((kind, &buf[4..len]));
+        buf = &buf[align(len).min(buf.len())..];
+    }
+    attributes
+}
+
+const fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Message, parse_attributes, parse_messages};
+
+    fn message(kind: u16, payload: &[u8]) -> Vec<u8> {
+        let mut msg = vec![];
+        msg.extend_from_slice(&(16 + payload.len() as u32).to_ne_bytes());
+        msg.extend_from_slice(&kind.to_ne_bytes());
+        msg.extend_from_slice(&[0; 10]);
+        msg.extend_from_slice(payload);
+        msg.resize(msg.len().next_multiple_of(4), 0);
+        msg
+    }
+
+    #[test]
+    fn test_parse_messages() {
+        let mut buf = message(20, &[1, 2, 3]);
+        buf.extend(message(3, &[0; 4]));
+        assert_eq!(
+            parse_messages(&buf),
+            [
+                Message {
+                    kind: 20,
+                    payload: &[1, 2, 3],
+                },
+                Message {
+                    kind: 3,
+                    payload: &[0; 4],
+                },
+            ]
+        );
+        // truncated
+        assert_eq!(parse_messages(&buf[..10]), []);
+    }
+
+    #[test]
+    fn test_parse_attributes() {
+        let buf = [5, 0, 2, 0, 42, 0, 0, 0, 8, 0, 7, 0, 1, 2, 3, 4];
+        assert_eq!(
+            parse_attributes(&buf),
+            [(2, &[42][..]), (7, &[1, 2, 3, 4][..])]
+        );
+    }
+}
@@ -14,12 +14,12 @@
 
 use std::{collections::HashMap, sync::LazyLock, time::Duration};
 
-use procfs::net::{self, DeviceStatus};
+use procfs_core::net::DeviceStatus;
 
-use crate::{error::Result, utils::Handle};
+use crate::{error::Result, sys, utils::Handle};
 
 static STAT_GLOBAL: LazyLock<Handle<HashMap<String, DeviceStatus>>> =
-    LazyLock::new(|| Handle::new(|| net::dev_status().map_err(Into::into)));
+    LazyLock::new(|| Handle::new(sys::net_dev_status));
 
 #[derive(Debug, Clone)]
 pub struct NetworkHandle(Handle<HashMap<String, DeviceStatus>>);
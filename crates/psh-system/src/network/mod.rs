@@ -14,6 +14,10 @@
 
 pub(crate) mod handle;
 pub mod raw;
+#[cfg(target_os = "linux")]
+mod sock_diag;
 pub use handle::NetworkHandle;
-pub use procfs::net::DeviceStatus;
+pub use procfs_core::net::DeviceStatus;
 pub use raw::dev_speed;
+#[cfg(target_os = "linux")]
+pub use sock_diag::{TCP_ESTABLISHED, TCP_LISTEN, TcpSocket, tcp_sockets};
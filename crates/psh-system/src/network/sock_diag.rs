@@ -0,0 +1,142 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::{
+    error::Result,
+    netlink::{Netlink, parse_attributes},
+};
+
+const NETLINK_SOCK_DIAG: libc::c_int = 4;
+const SOCK_DIAG_BY_FAMILY: u16 = 20;
+const INET_DIAG_INFO: u16 = 2;
+// sizeof(struct inet_diag_msg)
+const DIAG_MSG_LEN: usize = 72;
+// offsets in struct tcp_info
+const TCPI_RTT: usize = 68;
+const TCPI_TOTAL_RETRANS: usize = 100;
+
+pub const TCP_ESTABLISHED: u8 = 1;
+pub const TCP_LISTEN: u8 = 10;
+
+/// A TCP socket as reported by `sock_diag`, like `ss -ti` does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpSocket {
+    /// `TCP_ESTABLISHED`, `TCP_LISTEN`, ...
+    pub state: u8,
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    /// smoothed round trip time in microseconds
+    pub rtt_us: u32,
+    pub total_retrans: u32,
+}
+
+/// IPv4 and IPv6 TCP sockets of the caller's network namespace.
+pub fn tcp_sockets() -> Result<Vec<TcpSocket>> {
+    let mut netlink = Netlink::open(NETLINK_SOCK_DIAG, 0)?;
+    let mut sockets = vec![];
+    for family in [libc::AF_INET, libc::AF_INET6] {
+        for reply in netlink.dump(SOCK_DIAG_BY_FAMILY, &request(family as u8))? {
+            sockets.extend(parse_socket(&reply));
+        }
+    }
+    Ok(sockets)
+}
+
+// struct inet_diag_req_v2 for all states, with tcp_info
+fn request(family: u8) -> Vec<u8> {
+    let mut req = vec![
+        family,
+        libc::IPPROTO_TCP as u8,
+        1 << (INET_DIAG_INFO - 1),
+        0,
+    ];
+    req.extend_from_slice(&u32::MAX.to_ne_bytes());
+    // struct inet_diag_sockid, zeroed to match every socket
+    req.resize(56, 0);
+    req
+}
+
+fn parse_socket(msg: &[u8]) -> Option<TcpSocket> {
+    if msg.len() < DIAG_MSG_LEN {
+        return None;
+    }
+    let family = i32::from(msg[0]);
+    let addr = |at: usize, port_at: usize| {
+        let port = u16::from_be_bytes([msg[port_at], msg[port_at + 1]]);
+        let ip = if family == libc::AF_INET {
+            IpAddr::V4(Ipv4Addr::new(
+                msg[at],
+                msg[at + 1],
+                msg[at + 2],
+                msg[at + 3],
+            ))
+        } else {
+            let octets: [u8; 16] = msg[at..at + 16].try_into().unwrap();
+            IpAddr::V6(Ipv6Addr::from(octets))
+        };
+        SocketAddr::new(ip, port)
+    };
+    let info = parse_attributes(&msg[DIAG_MSG_LEN..])
+        .into_iter()
+        .find(|(kind, _)| *kind == INET_DIAG_INFO)
+        .map(|(_, value)| value);
+    // older kernels have a shorter tcp_info
+    let field = |at: usize| {
+        info.and_then(|it| it.get(at..at + 4))
+            .map_or(0, |it| u32::from_ne_bytes(it.try_into().unwrap()))
+    };
+    Some(TcpSocket {
+        state: msg[1],
+        local: addr(8, 4),
+        remote: addr(24, 6),
+        rtt_us: field(TCPI_RTT),
+        total_retrans: field(TCPI_TOTAL_RETRANS),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DIAG_MSG_LEN, TCP_ESTABLISHED, TcpSocket, parse_socket};
+
+    #[test]
+    fn test_parse_socket() {
+        let mut msg = vec![0; DIAG_MSG_LEN];
+        msg[0] = libc::AF_INET as u8;
+        msg[1] = TCP_ESTABLISHED;
+        msg[4..6].copy_from_slice(&443u16.to_be_bytes());
+        msg[6..8].copy_from_slice(&51000u16.to_be_bytes());
+        msg[8..12].copy_from_slice(&[10, 0, 0, 1]);
+        msg[24..28].copy_from_slice(&[10, 0, 0, 2]);
+
+        let mut info = vec![0; 104];
+        info[68..72].copy_from_slice(&1500u32.to_ne_bytes());
+        info[100..104].copy_from_slice(&3u32.to_ne_bytes());
+        msg.extend_from_slice(&(4 + info.len() as u16).to_ne_bytes());
+        msg.extend_from_slice(&2u16.to_ne_bytes());
+        msg.extend_from_slice(&info);
+
+        assert_eq!(
+            parse_socket(&msg),
+            Some(TcpSocket {
+                state: TCP_ESTABLISHED,
+                local: "10.0.0.1:443".parse().unwrap(),
+                remote: "10.0.0.2:51000".parse().unwrap(),
+                rtt_us: 1500,
+                total_retrans: 3,
+            })
+        );
+    }
+}
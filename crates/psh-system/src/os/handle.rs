@@ -14,21 +14,10 @@
 
 use std::sync::LazyLock;
 
-use super::{
-    OsInfo,
-    raw::{get_kernel_version, parse_distro_version},
-};
-use crate::{
-    error::{Error, Result},
-    utils::Handle,
-};
+use super::OsInfo;
+use crate::{error::Result, sys, utils::Handle};
 
-static INFO_GLOBAL: LazyLock<Handle<OsInfo>> = LazyLock::new(|| {
-    Handle::new(|| match (parse_distro_version!(), get_kernel_version()) {
-        (Ok(distro), Ok(kernel)) => Ok(OsInfo { distro, kernel }),
-        _ => Err(Error::EmptyValue),
-    })
-});
+static INFO_GLOBAL: LazyLock<Handle<OsInfo>> = LazyLock::new(|| Handle::new(sys::os_info));
 
 #[derive(Debug, Clone)]
 pub struct OsHandle(Handle<OsInfo>);
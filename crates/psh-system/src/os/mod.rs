@@ -13,7 +13,7 @@
 // see <https://www.gnu.org/licenses/>.
 
 pub(crate) mod handle;
-mod raw;
+pub(crate) mod raw;
 
 use std::fmt::Display;
 
@@ -26,6 +26,7 @@ pub struct KernelVersion {
     pub patch: u16,
 }
 
+#[cfg(target_os = "linux")]
 impl From<procfs::KernelVersion> for KernelVersion {
     fn from(value: procfs::KernelVersion) -> Self {
         Self {
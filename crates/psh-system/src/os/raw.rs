@@ -65,6 +65,7 @@ pub fn parse_distro_version_impl(path: &str) -> anyhow::Result<DistroVersion> {
     Ok(version)
 }
 
+#[cfg(target_os = "linux")]
 pub fn get_kernel_version() -> anyhow::Result<KernelVersion> {
     use procfs::sys::kernel::Version;
     let info = uname::uname()?;
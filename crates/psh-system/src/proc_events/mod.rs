@@ -0,0 +1,151 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+mod reader;
+
+pub use reader::ProcConnector;
+
+const PROC_EVENT_FORK: u32 = 0x0000_0001;
+const PROC_EVENT_EXEC: u32 = 0x0000_0002;
+const PROC_EVENT_EXIT: u32 = 0x8000_0000;
+// sizeof(struct cn_msg)
+const CN_MSG_LEN: usize = 20;
+
+/// A process lifecycle event from the proc connector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcEvent {
+    /// nanoseconds since boot
+    pub timestamp_ns: u64,
+    pub kind: ProcEventKind,
+}
+
+/// Pids are thread ids, tgids process ids.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcEventKind {
+    Fork {
+        parent_pid: u32,
+        parent_tgid: u32,
+        child_pid: u32,
+        child_tgid: u32,
+    },
+    Exec {
+        pid: u32,
+        tgid: u32,
+    },
+    Exit {
+        pid: u32,
+        tgid: u32,
+        /// wait status, as returned by `waitpid`
+        status: u32,
+    },
+}
+
+impl ProcEventKind {
+    /// Whether the event is about a thread rather than a whole process.
+    pub const fn is_thread(&self) -> bool {
+        match *self {
+            Self::Fork {
+                child_pid,
+                child_tgid,
+                ..
+            } => child_pid != child_tgid,
+            Self::Exec { pid, tgid } | Self::Exit { pid, tgid, .. } => pid != tgid,
+        }
+    }
+}
+
+/// Parses a `struct cn_msg` holding a `struct proc_event`, other events
+/// such as uid or comm changes are `None`.
+pub fn parse_event(msg: &[u8]) -> Option<ProcEvent> {
+    let event = msg.get(CN_MSG_LEN..)?;
+    let u32_at = |at: usize| {
+        event
+            .get(at..at + 4)
+            .map(|it| u32::from_ne_bytes(it.try_into().unwrap()))
+    };
+    let what = u32_at(0)?;
+    let timestamp_ns = u64::from_ne_bytes(event.get(8..16)?.try_into().unwrap());
+    // the event data follows `what`, `cpu` and the timestamp
+    let kind = match what {
+        PROC_EVENT_FORK => ProcEventKind::Fork {
+            parent_pid: u32_at(16)?,
+            parent_tgid: u32_at(20)?,
+            child_pid: u32_at(24)?,
+            child_tgid: u32_at(28)?,
+        },
+        PROC_EVENT_EXEC => ProcEventKind::Exec {
+            pid: u32_at(16)?,
+            tgid: u32_at(20)?,
+        },
+        PROC_EVENT_EXIT => ProcEventKind::Exit {
+            pid: u32_at(16)?,
+            tgid: u32_at(20)?,
+            status: u32_at(24)?,
+        },
+        _ => return None,
+    };
+    Some(ProcEvent { timestamp_ns, kind })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CN_MSG_LEN, PROC_EVENT_EXIT, PROC_EVENT_FORK, ProcEvent, ProcEventKind, parse_event,
+    };
+
+    fn message(what: u32, data: &[u32]) -> Vec<u8> {
+        let mut msg = vec![0; CN_MSG_LEN];
+        msg.extend_from_slice(&what.to_ne_bytes());
+        msg.extend_from_slice(&3u32.to_ne_bytes());
+        msg.extend_from_slice(&123_456_789u64.to_ne_bytes());
+        for value in data {
+            msg.extend_from_slice(&value.to_ne_bytes());
+        }
+        msg
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let fork = parse_event(&message(PROC_EVENT_FORK, &[10, 10, 20, 20])).unwrap();
+        assert_eq!(
+            fork,
+            ProcEvent {
+                timestamp_ns: 123_456_789,
+                kind: ProcEventKind::Fork {
+                    parent_pid: 10,
+                    parent_tgid: 10,
+                    child_pid: 20,
+                    child_tgid: 20,
+                },
+            }
+        );
+        assert!(!fork.kind.is_thread());
+
+        // exit of a thread killed by SIGKILL
+        let exit = parse_event(&message(PROC_EVENT_EXIT, &[21, 20, 9, 9, 10, 10])).unwrap();
+        assert_eq!(
+            exit.kind,
+            ProcEventKind::Exit {
+                pid: 21,
+                tgid: 20,
+                status: 9,
+            }
+        );
+        assert!(exit.kind.is_thread());
+
+        // PROC_EVENT_COMM
+        assert_eq!(parse_event(&message(0x200, &[1, 1, 0, 0])), None);
+        assert_eq!(parse_event(&message(PROC_EVENT_FORK, &[1])), None);
+    }
+}
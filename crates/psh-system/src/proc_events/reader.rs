@@ -0,0 +1,76 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::collections::VecDeque;
+
+use super::{ProcEvent, parse_event};
+use crate::{
+    error::{Error, Result},
+    netlink::{NLMSG_DONE, Netlink},
+};
+
+const NETLINK_CONNECTOR: libc::c_int = 11;
+const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+
+/// Blocking reader of process events from the netlink proc connector,
+/// needs `CAP_NET_ADMIN`.
+#[derive(Debug)]
+pub struct ProcConnector {
+    netlink: Netlink,
+    buf: Vec<u8>,
+    pending: VecDeque<ProcEvent>,
+}
+
+impl ProcConnector {
+    pub fn new() -> Result<Self> {
+        let mut netlink = Netlink::open(NETLINK_CONNECTOR, CN_IDX_PROC)?;
+        // struct cn_msg followed by the listen op
+        let mut msg = vec![];
+        msg.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+        msg.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+        msg.extend_from_slice(&[0; 8]);
+        msg.extend_from_slice(&4u16.to_ne_bytes());
+        msg.extend_from_slice(&0u16.to_ne_bytes());
+        msg.extend_from_slice(&PROC_CN_MCAST_LISTEN.to_ne_bytes());
+        netlink.send(NLMSG_DONE, 0, &msg)?;
+        Ok(Self {
+            netlink,
+            buf: vec![0; 8192],
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Blocks until the next fork, exec or exit.
+    pub fn next_event(&mut self) -> Result<ProcEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(event);
+            }
+            let messages = match self.netlink.recv(&mut self.buf) {
+                Ok(messages) => messages,
+                // ENOBUFS: the socket buffer overflowed and events were lost
+                Err(Error::System(err)) if err.raw_os_error() == Some(libc::ENOBUFS) => continue,
+                Err(err) => return Err(err),
+            };
+            self.pending.extend(
+                messages
+                    .iter()
+                    .filter(|it| it.kind == NLMSG_DONE)
+                    .filter_map(|it| parse_event(it.payload)),
+            );
+        }
+    }
+}
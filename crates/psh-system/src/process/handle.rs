@@ -17,25 +17,23 @@ use std::{
     time::Duration,
 };
 
-use procfs::process::Process;
-
-use crate::{error::Result, utils::Handle};
+use super::{Process, ProcessEntry, table};
+use crate::{error::Result, sys, utils::Handle};
 
 static INFO_SELF_GLOBAL: LazyLock<Handle<Arc<Process>>> =
-    LazyLock::new(|| Handle::new(|| Process::myself().map(Arc::new).map_err(Into::into)));
+    LazyLock::new(|| Handle::new(sys::myself));
+
+static STAT_ALL_GLOBAL: LazyLock<Handle<Vec<Arc<Process>>>> =
+    LazyLock::new(|| Handle::new(sys::all_processes));
 
-static STAT_ALL_GLOBAL: LazyLock<Handle<Vec<Arc<Process>>>> = LazyLock::new(|| {
-    Handle::new(|| {
-        procfs::process::all_processes()
-            .map_err(Into::into)
-            .map(|iter| iter.filter_map(|proc| proc.ok().map(Arc::new)).collect())
-    })
-});
+static TABLE_GLOBAL: LazyLock<Handle<Vec<Arc<ProcessEntry>>>> =
+    LazyLock::new(|| Handle::new(table::collect));
 
 #[derive(Debug, Clone)]
 pub struct ProcessHandle {
     myself: Handle<Arc<Process>>,
     all: Handle<Vec<Arc<Process>>>,
+    table: Handle<Vec<Arc<ProcessEntry>>>,
 }
 
 impl Default for ProcessHandle {
@@ -43,6 +41,7 @@ impl Default for ProcessHandle {
         Self {
             myself: INFO_SELF_GLOBAL.clone(),
             all: STAT_ALL_GLOBAL.clone(),
+            table: TABLE_GLOBAL.clone(),
         }
     }
 }
@@ -59,4 +58,10 @@ impl ProcessHandle {
     pub fn all(&self, interval: Option<Duration>) -> Result<Vec<Arc<Process>>> {
         self.all.get(interval)
     }
+
+    /// Every process with its stat, io and statm, read concurrently on
+    /// hosts with many processes.
+    pub fn table(&self, interval: Option<Duration>) -> Result<Vec<Arc<ProcessEntry>>> {
+        self.table.get(interval)
+    }
 }
@@ -13,5 +13,13 @@
 // see <https://www.gnu.org/licenses/>.
 
 pub(crate) mod handle;
+mod oom;
+mod table;
+#[cfg(not(target_os = "linux"))]
+pub use crate::sys::Process;
 pub use handle::ProcessHandle;
-pub use procfs::process::{ProcState, Process};
+pub use oom::{MemoryReclaim, OomScore};
+#[cfg(target_os = "linux")]
+pub use procfs::process::Process;
+pub use procfs_core::process::ProcState;
+pub use table::ProcessEntry;
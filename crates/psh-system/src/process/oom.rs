@@ -0,0 +1,219 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::error::Result;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Reclaim activity of the cgroup of a process, from `memory.stat` on
+/// cgroup v2, the kernel does not count it per process.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemoryReclaim {
+    /// e.g. `/system.slice/nginx.service`
+    pub cgroup: String,
+    /// pages read back in soon after they were reclaimed
+    pub workingset_refault_anon: u64,
+    pub workingset_refault_file: u64,
+    /// pages scanned and reclaimed, by kswapd and directly
+    pub pgscan: u64,
+    pub pgsteal: u64,
+    pub pgmajfault: u64,
+}
+
+impl MemoryReclaim {
+    pub fn parse(cgroup: &str, memory_stat: &str) -> Self {
+        let mut reclaim = Self {
+            cgroup: cgroup.to_string(),
+            ..Default::default()
+        };
+        for line in memory_stat.lines() {
+            let Some((key, value)) = line.split_once(' ') else {
+                continue;
+            };
+            let Ok(value) = value.trim().parse() else {
+                continue;
+            };
+            match key {
+                "workingset_refault_anon" => reclaim.workingset_refault_anon = value,
+                "workingset_refault_file" => reclaim.workingset_refault_file = value,
+                "pgscan" => reclaim.pgscan = value,
+                "pgsteal" => reclaim.pgsteal = value,
+                "pgmajfault" => reclaim.pgmajfault = value,
+                _ => {}
+            }
+        }
+        reclaim
+    }
+}
+
+/// How likely the OOM killer picks a process, the one with the highest
+/// `oom_score` goes first.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OomScore {
+    pub pid: i32,
+    pub name: String,
+    /// 0 to 2000, mostly the share of memory and swap the process uses,
+    /// shifted by `oom_score_adj`
+    pub oom_score: u32,
+    /// -1000 to 1000, -1000 is never killed
+    pub oom_score_adj: i32,
+    pub rss_bytes: u64,
+    pub swap_bytes: u64,
+    pub major_faults: u64,
+    /// `None` on cgroup v1 or if the cgroup is gone
+    pub reclaim: Option<MemoryReclaim>,
+}
+
+/// `VmRSS:	  1234 kB` of `/proc/<pid>/status` in bytes.
+fn status_bytes(status: &str, key: &str) -> u64 {
+    status
+        .lines()
+        .find_map(|it| it.strip_prefix(key)?.strip_prefix(':'))
+        .and_then(|it| it.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map_or(0, |kb| kb * 1024)
+}
+
+/// The 12th field of `/proc/<pid>/stat`, counted past the name, which may
+/// contain spaces and parentheses.
+fn major_faults(stat: &str) -> u64 {
+    stat.rsplit_once(')')
+        .and_then(|(_, rest)| rest.split_whitespace().nth(9)?.parse().ok())
+        .unwrap_or_default()
+}
+
+/// `0::/path` of `/proc/<pid>/cgroup`, only present on cgroup v2.
+fn unified_cgroup(cgroup: &str) -> Option<&str> {
+    cgroup.lines().find_map(|it| it.strip_prefix("0::"))
+}
+
+impl OomScore {
+    pub fn parse(
+        pid: i32,
+        comm: &str,
+        oom_score: &str,
+        oom_score_adj: &str,
+        status: &str,
+        stat: &str,
+    ) -> Self {
+        Self {
+            pid,
+            name: comm.trim().to_string(),
+            oom_score: oom_score.trim().parse().unwrap_or_default(),
+            oom_score_adj: oom_score_adj.trim().parse().unwrap_or_default(),
+            rss_bytes: status_bytes(status, "VmRSS"),
+            swap_bytes: status_bytes(status, "VmSwap"),
+            major_faults: major_faults(stat),
+            reclaim: None,
+        }
+    }
+
+    pub fn capture(pid: i32) -> Result<Self> {
+        Self::read(
+            Path::new("/proc"),
+            Path::new(CGROUP_ROOT),
+            pid,
+            &mut HashMap::new(),
+        )
+    }
+
+    /// Every process, the most likely to be killed first.
+    pub fn capture_all() -> Result<Vec<Self>> {
+        Self::capture_all_from(Path::new("/proc"), Path::new(CGROUP_ROOT))
+    }
+
+    pub fn capture_all_from(proc: &Path, cgroup_root: &Path) -> Result<Vec<Self>> {
+        // processes of a cgroup share its stats
+        let mut reclaims = HashMap::new();
+        let mut scores = vec![];
+        for entry in fs::read_dir(proc)? {
+            let Some(pid) = entry?.file_name().to_str().and_then(|it| it.parse().ok()) else {
+                continue;
+            };
+            // processes may exit while reading
+            if let Ok(score) = Self::read(proc, cgroup_root, pid, &mut reclaims) {
+                scores.push(score);
+            }
+        }
+        scores.sort_by(|a, b| b.oom_score.cmp(&a.oom_score).then(a.pid.cmp(&b.pid)));
+        Ok(scores)
+    }
+
+    fn read(
+        proc: &Path,
+        cgroup_root: &Path,
+        pid: i32,
+        reclaims: &mut HashMap<String, Option<MemoryReclaim>>,
+    ) -> Result<Self> {
+        let dir = proc.join(pid.to_string());
+        let read = |name: &str| fs::read_to_string(dir.join(name));
+        let mut score = Self::parse(
+            pid,
+            &read("comm")?,
+            &read("oom_score")?,
+            &read("oom_score_adj")?,
+            &read("status")?,
+            &read("stat")?,
+        );
+        let cgroup = read("cgroup").unwrap_or_default();
+        if let Some(cgroup) = unified_cgroup(&cgroup) {
+            score.reclaim = reclaims
+                .entry(cgroup.to_string())
+                .or_insert_with(|| {
+                    let path = cgroup_root.join(cgroup.trim_start_matches('/'));
+                    let stat = fs::read_to_string(path.join("memory.stat")).ok()?;
+                    Some(MemoryReclaim::parse(cgroup, &stat))
+                })
+                .clone();
+        }
+        Ok(score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryReclaim, OomScore, unified_cgroup};
+
+    #[test]
+    fn test_parse_oom_score() {
+        let status = "Name:\tjava\nVmRSS:\t  204800 kB\nVmSwap:\t     512 kB\n";
+        let stat = "4242 (java (main)) S 1 4242 4242 0 -1 4194560 91230 0 17 0 \
+                    1200 300 0 0 20 0 42 0 1000 0 0";
+        let score = OomScore::parse(4242, "java\n", "812\n", "-100\n", status, stat);
+        assert_eq!(score.name, "java");
+        assert_eq!(score.oom_score, 812);
+        assert_eq!(score.oom_score_adj, -100);
+        assert_eq!(score.rss_bytes, 204800 * 1024);
+        assert_eq!(score.swap_bytes, 512 * 1024);
+        assert_eq!(score.major_faults, 17);
+        assert_eq!(score.reclaim, None);
+    }
+
+    #[test]
+    fn test_parse_memory_reclaim() {
+        let cgroup = "0::/system.slice/app.service\n";
+        assert_eq!(unified_cgroup(cgroup), Some("/system.slice/app.service"));
+        assert_eq!(unified_cgroup("4:memory:/app\n"), None);
+
+        let stat = "anon 1048576\nworkingset_refault_anon 12\nworkingset_refault_file 3400\n\
+                    pgscan 9000\npgsteal 8100\npgmajfault 77\n";
+        let reclaim = MemoryReclaim::parse("/system.slice/app.service", stat);
+        assert_eq!(reclaim.workingset_refault_anon, 12);
+        assert_eq!(reclaim.workingset_refault_file, 3400);
+        assert_eq!(reclaim.pgscan, 9000);
+        assert_eq!(reclaim.pgsteal, 8100);
+        assert_eq!(reclaim.pgmajfault, 77);
+    }
+}
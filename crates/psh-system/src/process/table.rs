@@ -0,0 +1,162 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use procfs_core::process::{Io, Stat, StatM};
+
+use super::Process;
+use crate::{error::Result, sys};
+
+// below this many processes starting the workers costs more than it saves
+const PARALLEL_THRESHOLD: usize = 256;
+const MAX_WORKERS: usize = 8;
+// processes a worker claims at once
+const CHUNK: usize = 64;
+
+/// A process with the stats read for it during the same collection.
+#[derive(Debug)]
+pub struct ProcessEntry {
+    pub process: Arc<Process>,
+    pub stat: Stat,
+    pub io: Io,
+    pub statm: StatM,
+}
+
+/// Reads the stats of every process, processes that exit or can't be read
+/// meanwhile are left out.
+pub(crate) fn collect() -> Result<Vec<Arc<ProcessEntry>>> {
+    let processes = sys::all_processes()?;
+    let workers = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(MAX_WORKERS);
+    Ok(collect_with(&processes, workers))
+}
+
+fn collect_with(processes: &[Arc<Process>], workers: usize) -> Vec<Arc<ProcessEntry>> {
+    if workers <= 1 || processes.len() < PARALLEL_THRESHOLD {
+        let mut reader = Reader::default();
+        return processes
+            .iter()
+            .filter_map(|it| reader.read(it))
+            .map(Arc::new)
+            .collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut entries: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut reader = Reader::default();
+                    let mut entries = Vec::new();
+                    loop {
+                        let start = next.fetch_add(CHUNK, Ordering::Relaxed);
+                        let Some(chunk) = processes.get(start..).filter(|it| !it.is_empty()) else {
+                            break;
+                        };
+                        entries.extend(
+                            chunk
+                                .iter()
+                                .take(CHUNK)
+                                .enumerate()
+                                .filter_map(|(i, it)| Some((start + i, reader.read(it)?))),
+                        );
+                    }
+                    entries
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|it| it.join().unwrap_or_default())
+            .collect()
+    });
+    // same order as a sequential scan
+    entries.sort_unstable_by_key(|(i, _)| *i);
+    entries.into_iter().map(|(_, it)| Arc::new(it)).collect()
+}
+
+/// Reads the stat files of processes, reusing its buffers on Linux.
+#[derive(Default)]
+struct Reader {
+    #[cfg(target_os = "linux")]
+    path: String,
+    #[cfg(target_os = "linux")]
+    buf: Vec<u8>,
+}
+
+impl Reader {
+    #[cfg(target_os = "linux")]
+    fn read(&mut self, process: &Arc<Process>) -> Option<ProcessEntry> {
+        let pid = process.pid;
+        Some(ProcessEntry {
+            stat: self.read_file(pid, "stat")?,
+            io: self.read_file(pid, "io")?,
+            statm: self.read_file(pid, "statm")?,
+            process: Arc::clone(process),
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read_file<T: procfs_core::FromRead>(&mut self, pid: i32, name: &str) -> Option<T> {
+        use std::{fmt::Write, fs::File, io::Read};
+
+        self.path.clear();
+        let _ = write!(self.path, "/proc/{pid}/{name}");
+        self.buf.clear();
+        File::open(&self.path)
+            .ok()?
+            .read_to_end(&mut self.buf)
+            .ok()?;
+        T::from_read(self.buf.as_slice()).ok()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn read(&mut self, process: &Arc<Process>) -> Option<ProcessEntry> {
+        Some(ProcessEntry {
+            stat: process.stat().ok()?,
+            io: process.io().ok()?,
+            statm: process.statm().ok()?,
+            process: Arc::clone(process),
+        })
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::{PARALLEL_THRESHOLD, collect_with};
+    use crate::sys;
+
+    #[test]
+    fn test_collect_in_parallel() {
+        let myself = sys::myself().unwrap();
+        let processes = vec![myself.clone(); PARALLEL_THRESHOLD * 4 + 1];
+
+        let entries = collect_with(&processes, 4);
+        assert_eq!(entries.len(), processes.len());
+        assert!(
+            entries
+                .iter()
+                .all(|it| it.process.pid == myself.pid && it.stat.pid == myself.pid)
+        );
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+use crate::error::{Error, Result};
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Limits the calls per second a component makes to each host interface,
+/// so polling loops don't hammer `/proc` or perf.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    /// calls per second by interface, unlimited if absent
+    limits: HashMap<String, u32>,
+    /// start of the current window and calls made in it
+    windows: HashMap<String, (Instant, u32)>,
+    /// every call by interface, limited or not
+    calls: HashMap<String, u64>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &BTreeMap<String, u32>) -> Self {
+        Self {
+            limits: limits.iter().map(|(k, v)| (k.clone(), *v)).collect(),
+            windows: HashMap::new(),
+            calls: HashMap::new(),
+        }
+    }
+
+    /// Calls counted so far by interface, including those refused.
+    pub const fn calls(&self) -> &HashMap<String, u64> {
+        &self.calls
+    }
+
+    /// Counts a call to `interface`, fails with [`Error::RateLimited`] if
+    /// it is one too many this second.
+    pub fn check(&mut self, interface: &str) -> Result<()> {
+        self.check_at(interface, Instant::now())
+    }
+
+    fn check_at(&mut self, interface: &str, now: Instant) -> Result<()> {
+        match self.calls.get_mut(interface) {
+            Some(calls) => *calls += 1,
+            None => {
+                self.calls.insert(interface.to_string(), 1);
+            }
+        }
+        let Some(&calls_per_sec) = self.limits.get(interface) else {
+            return Ok(());
+        };
+        let window = self
+            .windows
+            .entry(interface.to_string())
+            .or_insert((now, 0));
+        if now.saturating_duration_since(window.0) >= WINDOW {
+            *window = (now, 0);
+        }
+        if window.1 >= calls_per_sec {
+            return Err(Error::RateLimited {
+                interface: interface.to_string(),
+                calls_per_sec,
+            });
+        }
+        window.1 += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        time::{Duration, Instant},
+    };
+
+    use super::RateLimiter;
+    use crate::error::Error;
+
+    #[test]
+    fn test_rate_limit_per_interface() {
+        let limits = BTreeMap::from([("process".to_string(), 2)]);
+        let mut limiter = RateLimiter::new(&limits);
+        let now = Instant::now();
+
+        assert!(limiter.check_at("process", now).is_ok());
+        assert!(limiter.check_at("process", now).is_ok());
+        let err = limiter.check_at("process", now).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::RateLimited {
+                calls_per_sec: 2,
+                ..
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Rate limited: process allows 2 calls per second"
+        );
+        // other interfaces are unlimited
+        for _ in 0..10 {
+            assert!(limiter.check_at("cpu", now).is_ok());
+        }
+
+        let later = now + Duration::from_secs(1);
+        assert!(limiter.check_at("process", later).is_ok());
+        assert_eq!(limiter.calls()["process"], 4);
+        assert_eq!(limiter.calls()["cpu"], 10);
+    }
+}
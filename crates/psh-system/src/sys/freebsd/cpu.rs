@@ -0,0 +1,107 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{io, mem};
+
+use super::{
+    render::{self, CpuTicks},
+    sysctl,
+};
+use crate::{
+    cpu::{CpuInfo, CpuStats, CpuTopology, Vulnerability},
+    error::{Error, Result},
+};
+
+// indices into `kern.cp_times` from <sys/resource.h>
+const CP_USER: usize = 0;
+const CP_NICE: usize = 1;
+const CP_SYS: usize = 2;
+const CP_INTR: usize = 3;
+const CP_IDLE: usize = 4;
+const CPUSTATES: usize = 5;
+
+fn ncpu() -> usize {
+    sysctl::value::<libc::c_int>("hw.ncpu").map_or(1, |it| it.max(1) as usize)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn cpu_info() -> Result<CpuInfo> {
+    use crate::cpu::X86_64CpuInfo;
+
+    let mut template = X86_64CpuInfo::new();
+    template.model_name = sysctl::string("hw.model").unwrap_or_default();
+    // in MHz
+    template.cpu_mhz = sysctl::value::<libc::c_int>("hw.clockrate").map_or(0.0, f64::from);
+    template.siblings = ncpu();
+
+    let cpus = (0..ncpu())
+        .map(|processor| {
+            let mut info = template.clone();
+            info.processor = processor;
+            info
+        })
+        .collect();
+    Ok(CpuInfo::X86_64(cpus))
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn cpu_info() -> Result<CpuInfo> {
+    use crate::cpu::Arm64CpuInfo;
+
+    let cpus = (0..ncpu())
+        .map(|processor| {
+            let mut info = Arm64CpuInfo::new();
+            info.processor = processor;
+            info
+        })
+        .collect();
+    Ok(CpuInfo::Arm64(cpus))
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn cpu_info() -> Result<CpuInfo> {
+    Ok(CpuInfo::Unsupported(std::env::consts::ARCH.to_string()))
+}
+
+pub fn cpu_stats() -> Result<CpuStats> {
+    let raw = sysctl::raw("kern.cp_times")?;
+    let times: Vec<u64> = raw
+        .chunks_exact(mem::size_of::<libc::c_long>())
+        .map(|it| libc::c_long::from_ne_bytes(it.try_into().unwrap_or_default()) as u64)
+        .collect();
+    if times.len() < CPUSTATES {
+        return Err(io::Error::from(io::ErrorKind::InvalidData).into());
+    }
+
+    let per_cpu: Vec<_> = times
+        .chunks_exact(CPUSTATES)
+        .map(|cpu| CpuTicks {
+            user: cpu[CP_USER],
+            nice: cpu[CP_NICE],
+            system: cpu[CP_SYS],
+            idle: cpu[CP_IDLE],
+            irq: cpu[CP_INTR],
+        })
+        .collect();
+    render::kernel_stats(None, &per_cpu).map(Into::into)
+}
+
+/// Only Linux exposes the NUMA layout, in sysfs.
+pub fn cpu_topology() -> Result<CpuTopology> {
+    Err(Error::Unsupported("cpu topology"))
+}
+
+pub fn cpu_vulnerabilities() -> Result<Vec<Vulnerability>> {
+    Err(Error::Unsupported("cpu vulnerabilities"))
+}
@@ -0,0 +1,123 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{ffi::CStr, mem};
+
+use procfs_core::DiskStat;
+
+use super::sysctl;
+use crate::error::Result;
+
+// diskstats always counts 512 bytes sectors
+const SECTOR_SIZE: u64 = 512;
+
+// <sys/devicestat.h>, the layout of DEVSTAT_VERSION 6
+const DEVSTAT_NAME_LEN: usize = 16;
+const DEVSTAT_N_TRANS_FLAGS: usize = 4;
+const DEVSTAT_READ: usize = 1;
+const DEVSTAT_WRITE: usize = 2;
+const DEVSTAT_FREE: usize = 3;
+const DEVSTAT_TYPE_PASS: libc::c_int = 0x80;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Bintime {
+    sec: libc::time_t,
+    frac: u64,
+}
+
+impl Bintime {
+    const fn ms(&self) -> u64 {
+        self.sec as u64 * 1000 + (((self.frac >> 32) * 1000) >> 32)
+    }
+}
+
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Devstat {
+    sequence0: libc::c_uint,
+    allocated: libc::c_int,
+    start_count: libc::c_uint,
+    end_count: libc::c_uint,
+    busy_from: Bintime,
+    dev_links: *const Devstat,
+    device_number: u32,
+    device_name: [libc::c_char; DEVSTAT_NAME_LEN],
+    unit_number: libc::c_int,
+    bytes: [u64; DEVSTAT_N_TRANS_FLAGS],
+    operations: [u64; DEVSTAT_N_TRANS_FLAGS],
+    duration: [Bintime; DEVSTAT_N_TRANS_FLAGS],
+    busy_time: Bintime,
+    creation_time: Bintime,
+    block_size: u32,
+    tag_types: [u64; 3],
+    flags: libc::c_int,
+    device_type: libc::c_int,
+    priority: libc::c_int,
+    id: *const libc::c_void,
+    sequence1: libc::c_uint,
+}
+
+impl From<&Devstat> for DiskStat {
+    fn from(value: &Devstat) -> Self {
+        let name = unsafe { CStr::from_ptr(value.device_name.as_ptr()) }.to_string_lossy();
+        let (time_reading, time_writing) = (
+            value.duration[DEVSTAT_READ].ms(),
+            value.duration[DEVSTAT_WRITE].ms(),
+        );
+        let time_discarding = value.duration[DEVSTAT_FREE].ms();
+        DiskStat {
+            major: 0,
+            minor: value.unit_number,
+            // e.g. "ada0"
+            name: format!("{name}{}", value.unit_number),
+            reads: value.operations[DEVSTAT_READ],
+            merged: 0,
+            sectors_read: value.bytes[DEVSTAT_READ] / SECTOR_SIZE,
+            time_reading,
+            writes: value.operations[DEVSTAT_WRITE],
+            writes_merged: 0,
+            sectors_written: value.bytes[DEVSTAT_WRITE] / SECTOR_SIZE,
+            time_writing,
+            in_progress: u64::from(value.start_count.wrapping_sub(value.end_count)),
+            time_in_progress: value.busy_time.ms(),
+            weighted_time_in_progress: time_reading + time_writing + time_discarding,
+            discards: Some(value.operations[DEVSTAT_FREE]),
+            discards_merged: Some(0),
+            sectors_discarded: Some(value.bytes[DEVSTAT_FREE] / SECTOR_SIZE),
+            time_discarding: Some(time_discarding),
+            flushes: None,
+            time_flushing: None,
+        }
+    }
+}
+
+/// Counters of every devstat device but pass-through ones, which duplicate
+/// the disk they are attached to.
+pub fn disk_stats() -> Result<Vec<DiskStat>> {
+    // a generation number followed by the devices
+    let raw = sysctl::raw("kern.devstat.all")?;
+    let devices = raw
+        .get(mem::size_of::<libc::c_long>()..)
+        .unwrap_or_default();
+
+    Ok(devices
+        .chunks_exact(mem::size_of::<Devstat>())
+        // SAFETY: every chunk is one devstat, the buffer may be unaligned
+        .map(|chunk| unsafe { chunk.as_ptr().cast::<Devstat>().read_unaligned() })
+        .filter(|dev| dev.device_type & DEVSTAT_TYPE_PASS == 0)
+        .map(|dev| DiskStat::from(&dev))
+        .collect())
+}
@@ -0,0 +1,93 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{io, mem};
+
+use procfs_core::Meminfo;
+
+use super::{page_size, render, sysctl};
+use crate::{
+    error::{Error, Result},
+    memory::MemoryModule,
+};
+
+// struct xswdev from <vm/vm_param.h>
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct XswDev {
+    version: libc::c_uint,
+    dev: libc::dev_t,
+    flags: libc::c_int,
+    /// in pages
+    nblks: libc::c_int,
+    /// in pages
+    used: libc::c_int,
+}
+
+/// `(total, used)` pages over all swap devices.
+fn swap_pages() -> io::Result<(u64, u64)> {
+    let mut mib = sysctl::mib("vm.swap_info")?;
+    let index = mib.len();
+    mib.push(0);
+    let (mut total, mut used) = (0, 0);
+    for dev in 0.. {
+        mib[index] = dev;
+        let Ok(raw) = sysctl::raw_mib(&mib) else {
+            break;
+        };
+        if raw.len() < mem::size_of::<XswDev>() {
+            break;
+        }
+        // SAFETY: size checked above, the buffer may be unaligned
+        let xsw = unsafe { raw.as_ptr().cast::<XswDev>().read_unaligned() };
+        total += xsw.nblks.max(0) as u64;
+        used += xsw.used.max(0) as u64;
+    }
+    Ok((total, used))
+}
+
+/// The page queues mapped onto the `/proc/meminfo` fields with the same
+/// meaning, the laundry queue holds dirty pages waiting for writeback.
+pub fn meminfo() -> Result<Meminfo> {
+    let page_kb = page_size() / 1024;
+    let pages = |name| {
+        sysctl::value::<libc::c_uint>(name)
+            .map(|it| u64::from(it) * page_kb)
+            .ok_or(Error::EmptyValue)
+    };
+    let total = pages("vm.stats.vm.v_page_count")?;
+    let free = pages("vm.stats.vm.v_free_count")?;
+    let inactive = pages("vm.stats.vm.v_inactive_count")?;
+    let laundry = pages("vm.stats.vm.v_laundry_count").unwrap_or(0);
+    let (swap_total, swap_used) = swap_pages().unwrap_or((0, 0));
+    let buffers = sysctl::value::<libc::c_long>("vfs.bufspace").map_or(0, |it| it as u64 / 1024);
+
+    render::meminfo(&[
+        ("MemTotal", total),
+        ("MemFree", free),
+        ("MemAvailable", free + inactive),
+        ("Buffers", buffers),
+        ("Active", pages("vm.stats.vm.v_active_count")?),
+        ("Inactive", inactive + laundry),
+        ("Unevictable", pages("vm.stats.vm.v_wire_count")?),
+        ("Dirty", laundry),
+        ("SwapTotal", swap_total * page_kb),
+        ("SwapFree", swap_total.saturating_sub(swap_used) * page_kb),
+    ])
+}
+
+pub fn memory_modules() -> Result<Vec<MemoryModule>> {
+    Err(Error::Unsupported("memory modules"))
+}
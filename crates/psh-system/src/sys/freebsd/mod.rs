@@ -0,0 +1,61 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+mod cpu;
+mod disk;
+mod memory;
+mod os;
+mod process;
+
+use std::time::Duration;
+
+pub use cpu::{cpu_info, cpu_stats, cpu_topology, cpu_vulnerabilities};
+pub use disk::disk_stats;
+pub use memory::{meminfo, memory_modules};
+pub use os::os_info;
+pub use process::{Process, all_processes, myself};
+
+pub use super::ifaddrs::net_dev_status;
+use super::{render, sysctl};
+
+// struct clockinfo from <sys/time.h>
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ClockInfo {
+    hz: libc::c_int,
+    tick: libc::c_int,
+    spare: libc::c_int,
+    stathz: libc::c_int,
+    profhz: libc::c_int,
+}
+
+pub fn page_size() -> u64 {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 }
+}
+
+pub fn boot_time_secs() -> u64 {
+    sysctl::value::<libc::timeval>("kern.boottime").map_or(0, |it| it.tv_sec as u64)
+}
+
+/// `kern.cp_times` counts in ticks of the statistics clock, not of `hz`.
+pub fn ticks_per_second() -> u64 {
+    sysctl::value::<ClockInfo>("kern.clockrate")
+        .map(|it| if it.stathz > 0 { it.stathz } else { it.hz })
+        .map_or(128, |it| it as u64)
+}
+
+fn duration_to_ticks(duration: Duration) -> u64 {
+    (duration.as_micros() * u128::from(ticks_per_second()) / 1_000_000) as u64
+}
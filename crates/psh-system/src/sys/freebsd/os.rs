@@ -0,0 +1,46 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use super::sysctl;
+use crate::{
+    error::{Error, Result},
+    os::{DistroKind, DistroVersion, KernelVersion, OsInfo},
+};
+
+/// The release (e.g. "14.0-RELEASE-p3") is reported as both distro version
+/// and kernel, FreeBSD ships them together.
+pub fn os_info() -> Result<OsInfo> {
+    let release = sysctl::string("kern.osrelease").ok_or(Error::EmptyValue)?;
+    let (version, branch) = release.split_once('-').unwrap_or((release.as_str(), ""));
+    let (major, minor) = version.split_once('.').unwrap_or((version, "0"));
+    let (Ok(major), Ok(minor)) = (major.parse(), minor.parse()) else {
+        return Err(Error::EmptyValue);
+    };
+    let patch = branch
+        .rsplit_once("-p")
+        .and_then(|(_, patch)| patch.parse().ok())
+        .unwrap_or(0);
+
+    Ok(OsInfo {
+        distro: DistroVersion {
+            distro: DistroKind::Other("FreeBSD".to_string()),
+            version: Some(release),
+        },
+        kernel: KernelVersion {
+            major,
+            minor,
+            patch,
+        },
+    })
+}
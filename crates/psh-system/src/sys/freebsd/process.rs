@@ -0,0 +1,205 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::HashMap,
+    ffi::{CStr, OsStr, OsString},
+    io, mem,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use procfs_core::process::{Io, Stat, StatM};
+
+use super::{boot_time_secs, duration_to_ticks, page_size, render::ProcStat, sysctl};
+use crate::error::{Error, Result};
+
+// `ki_stat` values from <sys/proc.h>
+const SIDL: libc::c_char = 1;
+const SRUN: libc::c_char = 2;
+const SSLEEP: libc::c_char = 3;
+const SSTOP: libc::c_char = 4;
+const SZOMB: libc::c_char = 5;
+const SWAIT: libc::c_char = 6;
+const SLOCK: libc::c_char = 7;
+
+fn kinfo_procs(mib: &[libc::c_int]) -> io::Result<Vec<libc::kinfo_proc>> {
+    let raw = sysctl::raw_mib(mib)?;
+    Ok(raw
+        .chunks_exact(mem::size_of::<libc::kinfo_proc>())
+        // SAFETY: every chunk is one kinfo_proc, the buffer may be unaligned
+        .map(|chunk| unsafe { chunk.as_ptr().cast::<libc::kinfo_proc>().read_unaligned() })
+        .collect())
+}
+
+const fn timeval(tv: &libc::timeval) -> Duration {
+    Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000)
+}
+
+/// A process queried through `kern.proc` sysctls.
+///
+/// Mirrors the methods of `procfs::process::Process` used by the host
+/// ops.
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub pid: i32,
+}
+
+impl Process {
+    const fn proc_mib(&self, node: libc::c_int) -> [libc::c_int; 4] {
+        [libc::CTL_KERN, libc::KERN_PROC, node, self.pid]
+    }
+
+    fn kinfo(&self) -> Result<libc::kinfo_proc> {
+        kinfo_procs(&self.proc_mib(libc::KERN_PROC_PID))?
+            .into_iter()
+            .next()
+            .ok_or(Error::EmptyValue)
+    }
+
+    /// Nul separated strings, as `KERN_PROC_ARGS` and `KERN_PROC_ENV`
+    /// report them.
+    fn strings(&self, node: libc::c_int) -> Result<Vec<OsString>> {
+        let raw = sysctl::raw_mib(&self.proc_mib(node))?;
+        Ok(raw
+            .split(|b| *b == 0)
+            .filter(|it| !it.is_empty())
+            .map(|it| OsStr::from_bytes(it).to_os_string())
+            .collect())
+    }
+
+    pub fn exe(&self) -> Result<PathBuf> {
+        let raw = sysctl::raw_mib(&self.proc_mib(libc::KERN_PROC_PATHNAME))?;
+        let path = CStr::from_bytes_until_nul(&raw).map_err(|_| Error::EmptyValue)?;
+        Ok(PathBuf::from(OsStr::from_bytes(path.to_bytes())))
+    }
+
+    pub fn cmdline(&self) -> Result<Vec<String>> {
+        Ok(self
+            .strings(libc::KERN_PROC_ARGS)?
+            .iter()
+            .map(|it| it.to_string_lossy().into_owned())
+            .collect())
+    }
+
+    pub fn environ(&self) -> Result<HashMap<OsString, OsString>> {
+        Ok(self
+            .strings(libc::KERN_PROC_ENV)?
+            .iter()
+            .filter_map(|it| {
+                let bytes = it.as_bytes();
+                let eq = bytes.iter().position(|b| *b == b'=')?;
+                Some((
+                    OsStr::from_bytes(&bytes[..eq]).to_os_string(),
+                    OsStr::from_bytes(&bytes[eq + 1..]).to_os_string(),
+                ))
+            })
+            .collect())
+    }
+
+    pub fn cwd(&self) -> Result<PathBuf> {
+        // `KERN_PROC_CWD` reports a kinfo_file, whose layout differs
+        // between releases
+        Err(Error::Unsupported("process working directory"))
+    }
+
+    pub fn root(&self) -> Result<PathBuf> {
+        Ok(PathBuf::from("/"))
+    }
+
+    pub fn uid(&self) -> Result<u32> {
+        Ok(self.kinfo()?.ki_uid)
+    }
+
+    /// Rendered as `/proc/<pid>/stat`, times are in ticks of
+    /// [`super::ticks_per_second`].
+    pub fn stat(&self) -> Result<Stat> {
+        let info = self.kinfo()?;
+        let comm = unsafe { CStr::from_ptr(info.ki_comm.as_ptr()) }.to_string_lossy();
+        let state = match info.ki_stat {
+            SIDL => 'I',
+            SRUN => 'R',
+            SSLEEP | SWAIT => 'S',
+            SSTOP => 'T',
+            SZOMB => 'Z',
+            SLOCK => 'D',
+            _ => 'S',
+        };
+        // procfs counts start time in ticks since boot
+        let started = timeval(&info.ki_start).saturating_sub(Duration::from_secs(boot_time_secs()));
+
+        ProcStat {
+            pid: self.pid,
+            comm: &comm,
+            state,
+            ppid: info.ki_ppid,
+            minflt: info.ki_rusage.ru_minflt.max(0) as u64,
+            majflt: info.ki_rusage.ru_majflt.max(0) as u64,
+            utime: duration_to_ticks(timeval(&info.ki_rusage.ru_utime)),
+            stime: duration_to_ticks(timeval(&info.ki_rusage.ru_stime)),
+            priority: i64::from(info.ki_pri.pri_level),
+            nice: i64::from(info.ki_nice),
+            num_threads: i64::from(info.ki_numthreads),
+            starttime: duration_to_ticks(started),
+            vsize: info.ki_size as u64,
+            rss: info.ki_rssize.max(0) as u64,
+        }
+        .parse()
+    }
+
+    /// FreeBSD counts block operations instead of bytes, they are reported
+    /// as syscalls.
+    pub fn io(&self) -> Result<Io> {
+        let usage = self.kinfo()?.ki_rusage;
+        Ok(Io {
+            rchar: 0,
+            wchar: 0,
+            syscr: usage.ru_inblock.max(0) as u64,
+            syscw: usage.ru_oublock.max(0) as u64,
+            read_bytes: 0,
+            write_bytes: 0,
+            cancelled_write_bytes: 0,
+        })
+    }
+
+    /// In pages like `/proc/<pid>/statm`.
+    pub fn statm(&self) -> Result<StatM> {
+        let info = self.kinfo()?;
+        Ok(StatM {
+            size: info.ki_size as u64 / page_size(),
+            resident: info.ki_rssize.max(0) as u64,
+            shared: 0,
+            text: info.ki_tsize.max(0) as u64,
+            lib: 0,
+            data: info.ki_dsize.max(0) as u64,
+            dt: 0,
+        })
+    }
+}
+
+pub fn myself() -> Result<Arc<Process>> {
+    Ok(Arc::new(Process {
+        pid: std::process::id() as i32,
+    }))
+}
+
+pub fn all_processes() -> Result<Vec<Arc<Process>>> {
+    let procs = kinfo_procs(&[libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_PROC, 0])?;
+    Ok(procs
+        .into_iter()
+        .map(|it| Arc::new(Process { pid: it.ki_pid }))
+        .collect())
+}
@@ -0,0 +1,76 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+// getifaddrs(3) reports the interface counters on BSD kernels.
+
+use std::{collections::HashMap, ffi::CStr, io, ptr};
+
+use procfs_core::net::DeviceStatus;
+
+use crate::error::Result;
+
+/// The `if_data` counters of macOS are 32 bits wide and wrap on busy links.
+fn device_status(name: String, data: &libc::if_data) -> DeviceStatus {
+    DeviceStatus {
+        name,
+        recv_bytes: u64::from(data.ifi_ibytes),
+        recv_packets: u64::from(data.ifi_ipackets),
+        recv_errs: u64::from(data.ifi_ierrors),
+        recv_drop: u64::from(data.ifi_iqdrops),
+        recv_fifo: 0,
+        recv_frame: 0,
+        recv_compressed: 0,
+        recv_multicast: u64::from(data.ifi_imcasts),
+        sent_bytes: u64::from(data.ifi_obytes),
+        sent_packets: u64::from(data.ifi_opackets),
+        sent_errs: u64::from(data.ifi_oerrors),
+        #[cfg(target_os = "freebsd")]
+        sent_drop: data.ifi_oqdrops,
+        #[cfg(not(target_os = "freebsd"))]
+        sent_drop: 0,
+        sent_fifo: 0,
+        sent_colls: u64::from(data.ifi_collisions),
+        sent_carrier: 0,
+        sent_compressed: 0,
+    }
+}
+
+pub fn net_dev_status() -> Result<HashMap<String, DeviceStatus>> {
+    let mut addrs: *mut libc::ifaddrs = ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut addrs) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    let mut devices = HashMap::new();
+    let mut next = addrs;
+    while !next.is_null() {
+        // SAFETY: the list stays valid until freed below
+        let ifa = unsafe { &*next };
+        next = ifa.ifa_next;
+        // only the link level entry of an interface carries its counters
+        let is_link = !ifa.ifa_addr.is_null()
+            && i32::from(unsafe { (*ifa.ifa_addr).sa_family }) == libc::AF_LINK;
+        if !is_link || ifa.ifa_data.is_null() {
+            continue;
+        }
+        let name = unsafe { CStr::from_ptr(ifa.ifa_name) }
+            .to_string_lossy()
+            .into_owned();
+        let data = unsafe { &*ifa.ifa_data.cast::<libc::if_data>() };
+        devices.insert(name.clone(), device_status(name, data));
+    }
+    unsafe { libc::freeifaddrs(addrs) };
+
+    Ok(devices)
+}
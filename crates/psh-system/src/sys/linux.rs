@@ -0,0 +1,101 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{collections::HashMap, path::Path, process::Command, sync::Arc};
+
+use procfs::{CurrentSI, process::Process};
+use procfs_core::{DiskStat, Meminfo, net::DeviceStatus};
+
+use crate::{
+    cpu::{
+        CpuInfo, CpuStats, CpuTopology, Vulnerability, raw::parse_cpuinfo, topology::read_topology,
+        vulnerability::read_vulnerabilities,
+    },
+    error::{Error, Result},
+    memory::{
+        MemoryModule,
+        raw::{parse_meminfo, parse_memory_module},
+    },
+    os::{
+        OsInfo,
+        raw::{get_kernel_version, parse_distro_version},
+    },
+};
+
+pub fn page_size() -> u64 {
+    procfs::page_size()
+}
+
+pub fn boot_time_secs() -> u64 {
+    procfs::boot_time_secs().unwrap_or(0)
+}
+
+pub fn ticks_per_second() -> u64 {
+    procfs::ticks_per_second()
+}
+
+pub fn cpu_info() -> Result<CpuInfo> {
+    parse_cpuinfo!().map_err(Into::into)
+}
+
+pub fn cpu_stats() -> Result<CpuStats> {
+    procfs::KernelStats::current()
+        .map(Into::into)
+        .map_err(Into::into)
+}
+
+pub fn cpu_topology() -> Result<CpuTopology> {
+    read_topology(Path::new("/sys")).map_err(Into::into)
+}
+
+pub fn cpu_vulnerabilities() -> Result<Vec<Vulnerability>> {
+    read_vulnerabilities(Path::new("/sys")).map_err(Into::into)
+}
+
+pub fn meminfo() -> Result<Meminfo> {
+    parse_meminfo!().map_err(Into::into)
+}
+
+pub fn memory_modules() -> Result<Vec<MemoryModule>> {
+    let dmidecode_exe = which::which("dmidecode")?;
+    let output = Command::new(dmidecode_exe).arg("-t").arg("17").output()?;
+    let content = std::str::from_utf8(&output.stdout)?;
+
+    Ok(parse_memory_module(content).into_iter().collect::<Vec<_>>())
+}
+
+pub fn disk_stats() -> Result<Vec<DiskStat>> {
+    procfs::diskstats().map_err(Into::into)
+}
+
+pub fn net_dev_status() -> Result<HashMap<String, DeviceStatus>> {
+    procfs::net::dev_status().map_err(Into::into)
+}
+
+pub fn os_info() -> Result<OsInfo> {
+    match (parse_distro_version!(), get_kernel_version()) {
+        (Ok(distro), Ok(kernel)) => Ok(OsInfo { distro, kernel }),
+        _ => Err(Error::EmptyValue),
+    }
+}
+
+pub fn myself() -> Result<Arc<Process>> {
+    Process::myself().map(Arc::new).map_err(Into::into)
+}
+
+pub fn all_processes() -> Result<Vec<Arc<Process>>> {
+    procfs::process::all_processes()
+        .map_err(Into::into)
+        .map(|iter| iter.filter_map(|proc| proc.ok().map(Arc::new)).collect())
+}
@@ -0,0 +1,133 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{io, mem, ptr, slice};
+
+use super::{
+    render::{self, CpuTicks},
+    sysctl,
+};
+use crate::{
+    cpu::{CpuInfo, CpuStats, CpuTopology, Vulnerability},
+    error::{Error, Result},
+};
+
+fn logical_cpus() -> usize {
+    sysctl::value::<libc::c_int>("hw.logicalcpu").map_or(1, |it| it.max(1) as usize)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn cpu_info() -> Result<CpuInfo> {
+    use crate::cpu::X86_64CpuInfo;
+
+    let int = |name| sysctl::value::<libc::c_int>(name).map_or(0, |it| it as usize);
+    let mut template = X86_64CpuInfo::new();
+    template.model_name = sysctl::string("machdep.cpu.brand_string").unwrap_or_default();
+    template.vendor_id = sysctl::string("machdep.cpu.vendor").unwrap_or_default();
+    template.cpu_family = int("machdep.cpu.family");
+    template.model = int("machdep.cpu.model");
+    template.stepping = int("machdep.cpu.stepping");
+    template.cpu_cores = int("machdep.cpu.core_count");
+    template.siblings = int("machdep.cpu.thread_count");
+    template.cpu_mhz = sysctl::value::<u64>("hw.cpufrequency").map_or(0.0, |it| it as f64 / 1e6);
+    // e.g. "FPU VME DE PSE", procfs flags are lowercase
+    template.flags = sysctl::string("machdep.cpu.features")
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|it| it.to_lowercase())
+        .collect();
+
+    let cpus = (0..logical_cpus())
+        .map(|processor| {
+            let mut info = template.clone();
+            info.processor = processor;
+            info
+        })
+        .collect();
+    Ok(CpuInfo::X86_64(cpus))
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn cpu_info() -> Result<CpuInfo> {
+    use crate::cpu::Arm64CpuInfo;
+
+    // Apple doesn't expose MIDR, only the implementer is known
+    const APPLE_IMPLEMENTER: u16 = 0x61;
+
+    let cpus = (0..logical_cpus())
+        .map(|processor| {
+            let mut info = Arm64CpuInfo::new();
+            info.processor = processor;
+            info.cpu_implementer = APPLE_IMPLEMENTER;
+            info.cpu_architecture = 8;
+            info
+        })
+        .collect();
+    Ok(CpuInfo::Arm64(cpus))
+}
+
+#[allow(deprecated)] // libc suggests the mach2 crate for the mach api
+pub fn cpu_stats() -> Result<CpuStats> {
+    let mut cpus: libc::natural_t = 0;
+    let mut info: libc::processor_info_array_t = ptr::null_mut();
+    let mut info_len: libc::mach_msg_type_number_t = 0;
+    let ret = unsafe {
+        libc::host_processor_info(
+            libc::mach_host_self(),
+            libc::PROCESSOR_CPU_LOAD_INFO,
+            &mut cpus,
+            &mut info,
+            &mut info_len,
+        )
+    };
+    if ret != libc::KERN_SUCCESS {
+        return Err(io::Error::other(format!("host_processor_info failed: {ret}")).into());
+    }
+
+    // SAFETY: the kernel mapped one load info per cpu, deallocated below
+    let loads = unsafe {
+        slice::from_raw_parts(info.cast::<libc::processor_cpu_load_info>(), cpus as usize)
+    };
+    let per_cpu: Vec<_> = loads
+        .iter()
+        .map(|load| {
+            let ticks = |state: libc::c_int| u64::from(load.cpu_ticks[state as usize]);
+            CpuTicks {
+                user: ticks(libc::CPU_STATE_USER),
+                nice: ticks(libc::CPU_STATE_NICE),
+                system: ticks(libc::CPU_STATE_SYSTEM),
+                idle: ticks(libc::CPU_STATE_IDLE),
+                irq: 0,
+            }
+        })
+        .collect();
+    unsafe {
+        libc::vm_deallocate(
+            libc::mach_task_self(),
+            info as libc::vm_address_t,
+            info_len as usize * mem::size_of::<libc::integer_t>(),
+        )
+    };
+
+    render::kernel_stats(None, &per_cpu).map(Into::into)
+}
+
+/// Only Linux exposes the NUMA layout, in sysfs.
+pub fn cpu_topology() -> Result<CpuTopology> {
+    Err(Error::Unsupported("cpu topology"))
+}
+
+pub fn cpu_vulnerabilities() -> Result<Vec<Vulnerability>> {
+    Err(Error::Unsupported("cpu vulnerabilities"))
+}
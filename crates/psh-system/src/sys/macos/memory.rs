@@ -0,0 +1,75 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{io, mem};
+
+use procfs_core::Meminfo;
+
+use super::{page_size, render, sysctl};
+use crate::{
+    error::{Error, Result},
+    memory::MemoryModule,
+};
+
+#[allow(deprecated)] // libc suggests the mach2 crate for the mach api
+fn vm_statistics() -> io::Result<libc::vm_statistics64> {
+    let mut stats: libc::vm_statistics64 = unsafe { mem::zeroed() };
+    let mut len = libc::HOST_VM_INFO64_COUNT;
+    let ret = unsafe {
+        libc::host_statistics64(
+            libc::mach_host_self(),
+            libc::HOST_VM_INFO64,
+            (&mut stats as *mut libc::vm_statistics64).cast(),
+            &mut len,
+        )
+    };
+    if ret != libc::KERN_SUCCESS {
+        return Err(io::Error::other(format!("host_statistics64 failed: {ret}")));
+    }
+    Ok(stats)
+}
+
+/// Mach page counts mapped onto the `/proc/meminfo` fields with the same
+/// meaning, file backed pages are reported as cached.
+pub fn meminfo() -> Result<Meminfo> {
+    let total = sysctl::value::<u64>("hw.memsize").ok_or(Error::EmptyValue)? / 1024;
+    let vm = vm_statistics()?;
+    let page_kb = page_size() / 1024;
+    let pages = |count: libc::natural_t| u64::from(count) * page_kb;
+    let (swap_total, swap_free) = sysctl::value::<libc::xsw_usage>("vm.swapusage")
+        .map_or((0, 0), |it| (it.xsu_total / 1024, it.xsu_avail / 1024));
+    // what can be handed out without swapping or compressing
+    let available = pages(vm.free_count)
+        + pages(vm.speculative_count)
+        + pages(vm.external_page_count)
+        + pages(vm.purgeable_count);
+
+    render::meminfo(&[
+        ("MemTotal", total),
+        ("MemFree", pages(vm.free_count)),
+        ("MemAvailable", available.min(total)),
+        ("Cached", pages(vm.external_page_count)),
+        ("Active", pages(vm.active_count)),
+        ("Inactive", pages(vm.inactive_count)),
+        ("Unevictable", pages(vm.wire_count)),
+        ("Mlocked", pages(vm.wire_count)),
+        ("AnonPages", pages(vm.internal_page_count)),
+        ("SwapTotal", swap_total),
+        ("SwapFree", swap_free),
+    ])
+}
+
+pub fn memory_modules() -> Result<Vec<MemoryModule>> {
+    Err(Error::Unsupported("memory modules"))
+}
@@ -0,0 +1,63 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+mod cpu;
+mod memory;
+mod os;
+mod process;
+
+use procfs_core::DiskStat;
+
+pub use cpu::{cpu_info, cpu_stats, cpu_topology, cpu_vulnerabilities};
+pub use memory::{meminfo, memory_modules};
+pub use os::os_info;
+pub use process::{Process, all_processes, myself};
+
+pub use super::ifaddrs::net_dev_status;
+use super::{render, sysctl};
+use crate::error::{Error, Result};
+
+// mach counts cpu time in ticks of the kernel's `hz`, which is fixed at 100
+const TICKS_PER_SECOND: u64 = 100;
+
+pub fn page_size() -> u64 {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 }
+}
+
+pub fn boot_time_secs() -> u64 {
+    sysctl::value::<libc::timeval>("kern.boottime").map_or(0, |it| it.tv_sec as u64)
+}
+
+pub const fn ticks_per_second() -> u64 {
+    TICKS_PER_SECOND
+}
+
+/// Mach absolute time to ticks, it counts nanoseconds on Intel but not on
+/// Apple silicon.
+#[allow(deprecated)] // libc suggests the mach2 crate for the mach api
+fn mach_to_ticks(time: u64) -> u64 {
+    let mut timebase = libc::mach_timebase_info { numer: 0, denom: 0 };
+    if unsafe { libc::mach_timebase_info(&mut timebase) } != libc::KERN_SUCCESS
+        || timebase.denom == 0
+    {
+        return 0;
+    }
+    let nanos = u128::from(time) * u128::from(timebase.numer) / u128::from(timebase.denom);
+    (nanos / (1_000_000_000 / u128::from(TICKS_PER_SECOND))) as u64
+}
+
+/// Disk counters live in the IOKit registry, which needs CoreFoundation.
+pub fn disk_stats() -> Result<Vec<DiskStat>> {
+    Err(Error::Unsupported("disk statistics"))
+}
@@ -0,0 +1,41 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use super::sysctl;
+use crate::{
+    error::{Error, Result},
+    os::{DistroKind, DistroVersion, KernelVersion, OsInfo},
+};
+
+/// The product version (e.g. "14.4.1") is reported as distro, the Darwin
+/// release (e.g. "23.4.0") as kernel.
+pub fn os_info() -> Result<OsInfo> {
+    let release = sysctl::string("kern.osrelease").ok_or(Error::EmptyValue)?;
+    let version: Vec<u16> = release.split('.').map_while(|it| it.parse().ok()).collect();
+    let (Some(major), Some(minor)) = (version.first(), version.get(1)) else {
+        return Err(Error::EmptyValue);
+    };
+
+    Ok(OsInfo {
+        distro: DistroVersion {
+            distro: DistroKind::Other("macOS".to_string()),
+            version: sysctl::string("kern.osproductversion"),
+        },
+        kernel: KernelVersion {
+            major: *major as u8,
+            minor: *minor as u8,
+            patch: version.get(2).copied().unwrap_or(0),
+        },
+    })
+}
@@ -0,0 +1,264 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::HashMap,
+    ffi::{CStr, OsStr, OsString},
+    io, mem,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+    ptr,
+    sync::Arc,
+};
+
+use procfs_core::process::{Io, Stat, StatM};
+
+use super::{TICKS_PER_SECOND, boot_time_secs, mach_to_ticks, page_size, render::ProcStat, sysctl};
+use crate::error::{Error, Result};
+
+// `pbi_status` values from <sys/proc.h>
+const SIDL: u32 = 1;
+const SRUN: u32 = 2;
+const SSLEEP: u32 = 3;
+const SSTOP: u32 = 4;
+const SZOMB: u32 = 5;
+
+/// A process queried through libproc.
+///
+/// Mirrors the methods of `procfs::process::Process` used by the host
+/// ops, other users' processes mostly need root.
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub pid: i32,
+}
+
+impl Process {
+    fn pid_info<T>(&self, flavor: libc::c_int) -> io::Result<T> {
+        let mut info = mem::MaybeUninit::<T>::zeroed();
+        let size = mem::size_of::<T>() as libc::c_int;
+        let len =
+            unsafe { libc::proc_pidinfo(self.pid, flavor, 0, info.as_mut_ptr().cast(), size) };
+        if len != size {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { info.assume_init() })
+    }
+
+    fn task_info(&self) -> io::Result<libc::proc_taskallinfo> {
+        self.pid_info(libc::PROC_PIDTASKALLINFO)
+    }
+
+    /// Arguments and environment from `KERN_PROCARGS2`: argc, the exec
+    /// path padded with nuls, then the nul separated strings.
+    fn procargs(&self) -> io::Result<(Vec<OsString>, Vec<OsString>)> {
+        let mut len =
+            sysctl::value::<libc::c_int>("kern.argmax").map_or(256 * 1024, |it| it as usize);
+        let mut buf = vec![0u8; len];
+        let mut mib = [libc::CTL_KERN, libc::KERN_PROCARGS2, self.pid];
+        let ret = unsafe {
+            libc::sysctl(
+                mib.as_mut_ptr(),
+                mib.len() as libc::c_uint,
+                buf.as_mut_ptr().cast(),
+                &mut len,
+                ptr::null_mut(),
+                0,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(len);
+
+        let (argc, rest) = buf
+            .split_first_chunk::<4>()
+            .ok_or(io::Error::from(io::ErrorKind::InvalidData))?;
+        let argc = i32::from_ne_bytes(*argc).max(0) as usize;
+        let rest = &rest[rest.iter().position(|b| *b == 0).unwrap_or(rest.len())..];
+        let rest = &rest[rest.iter().position(|b| *b != 0).unwrap_or(rest.len())..];
+
+        let mut strings = rest
+            .split(|b| *b == 0)
+            .map(|it| OsStr::from_bytes(it).to_os_string());
+        let args = strings.by_ref().take(argc).collect();
+        let envs = strings.take_while(|it| !it.is_empty()).collect();
+        Ok((args, envs))
+    }
+
+    pub fn exe(&self) -> Result<PathBuf> {
+        let mut buf = vec![0u8; libc::PROC_PIDPATHINFO_MAXSIZE as usize];
+        let len =
+            unsafe { libc::proc_pidpath(self.pid, buf.as_mut_ptr().cast(), buf.len() as u32) };
+        if len <= 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        buf.truncate(len as usize);
+        Ok(PathBuf::from(OsStr::from_bytes(&buf)))
+    }
+
+    pub fn cmdline(&self) -> Result<Vec<String>> {
+        let (args, _) = self.procargs()?;
+        Ok(args
+            .iter()
+            .map(|it| it.to_string_lossy().into_owned())
+            .collect())
+    }
+
+    pub fn environ(&self) -> Result<HashMap<OsString, OsString>> {
+        let (_, envs) = self.procargs()?;
+        Ok(envs
+            .iter()
+            .filter_map(|it| {
+                let bytes = it.as_bytes();
+                let eq = bytes.iter().position(|b| *b == b'=')?;
+                Some((
+                    OsStr::from_bytes(&bytes[..eq]).to_os_string(),
+                    OsStr::from_bytes(&bytes[eq + 1..]).to_os_string(),
+                ))
+            })
+            .collect())
+    }
+
+    fn vnode_paths(&self) -> Result<(PathBuf, PathBuf)> {
+        let info: libc::proc_vnodepathinfo = self.pid_info(libc::PROC_PIDVNODEPATHINFO)?;
+        let path = |it: &libc::vnode_info_path| {
+            // SAFETY: the kernel nul terminates the MAXPATHLEN buffer
+            let path = unsafe { CStr::from_ptr(it.vip_path.as_ptr().cast()) };
+            PathBuf::from(OsStr::from_bytes(path.to_bytes()))
+        };
+        Ok((path(&info.pvi_cdir), path(&info.pvi_rdir)))
+    }
+
+    pub fn cwd(&self) -> Result<PathBuf> {
+        self.vnode_paths().map(|(cwd, _)| cwd)
+    }
+
+    /// `/` unless the process is chrooted.
+    pub fn root(&self) -> Result<PathBuf> {
+        let (_, root) = self.vnode_paths()?;
+        if root.as_os_str().is_empty() {
+            return Ok(PathBuf::from("/"));
+        }
+        Ok(root)
+    }
+
+    pub fn uid(&self) -> Result<u32> {
+        Ok(self.task_info()?.pbsd.pbi_uid)
+    }
+
+    /// Rendered as `/proc/<pid>/stat`, times are in ticks of
+    /// [`super::ticks_per_second`].
+    pub fn stat(&self) -> Result<Stat> {
+        let info = self.task_info()?;
+        let (bsd, task) = (&info.pbsd, &info.ptinfo);
+        let comm = unsafe { CStr::from_ptr(bsd.pbi_comm.as_ptr()) }.to_string_lossy();
+        let state = match bsd.pbi_status {
+            SIDL => 'I',
+            SRUN => 'R',
+            SSLEEP => 'S',
+            SSTOP => 'T',
+            SZOMB => 'Z',
+            _ => 'S',
+        };
+        // procfs counts start time in ticks since boot
+        let started = bsd.pbi_start_tvsec * TICKS_PER_SECOND
+            + bsd.pbi_start_tvusec * TICKS_PER_SECOND / 1_000_000;
+        let faults = task.pti_faults.max(0) as u64;
+        let pageins = task.pti_pageins.max(0) as u64;
+
+        ProcStat {
+            pid: self.pid,
+            comm: &comm,
+            state,
+            ppid: bsd.pbi_ppid as i32,
+            minflt: faults.saturating_sub(pageins),
+            majflt: pageins,
+            utime: mach_to_ticks(task.pti_total_user),
+            stime: mach_to_ticks(task.pti_total_system),
+            priority: i64::from(task.pti_priority),
+            nice: i64::from(bsd.pbi_nice),
+            num_threads: i64::from(task.pti_threadnum),
+            starttime: started.saturating_sub(boot_time_secs() * TICKS_PER_SECOND),
+            vsize: task.pti_virtual_size,
+            rss: task.pti_resident_size / page_size(),
+        }
+        .parse()
+    }
+
+    /// Only storage io is accounted, the syscall counters are zero.
+    pub fn io(&self) -> Result<Io> {
+        let mut usage: libc::rusage_info_v2 = unsafe { mem::zeroed() };
+        let ret = unsafe {
+            libc::proc_pid_rusage(
+                self.pid,
+                libc::RUSAGE_INFO_V2,
+                (&mut usage as *mut libc::rusage_info_v2).cast(),
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(Io {
+            rchar: usage.ri_diskio_bytesread,
+            wchar: usage.ri_diskio_byteswritten,
+            syscr: 0,
+            syscw: 0,
+            read_bytes: usage.ri_diskio_bytesread,
+            write_bytes: usage.ri_diskio_byteswritten,
+            cancelled_write_bytes: 0,
+        })
+    }
+
+    /// In pages like `/proc/<pid>/statm`.
+    pub fn statm(&self) -> Result<StatM> {
+        let task = self.task_info()?.ptinfo;
+        let page_size = page_size();
+        Ok(StatM {
+            size: task.pti_virtual_size / page_size,
+            resident: task.pti_resident_size / page_size,
+            shared: 0,
+            text: 0,
+            lib: 0,
+            data: 0,
+            dt: 0,
+        })
+    }
+}
+
+pub fn myself() -> Result<Arc<Process>> {
+    Ok(Arc::new(Process {
+        pid: std::process::id() as i32,
+    }))
+}
+
+pub fn all_processes() -> Result<Vec<Arc<Process>>> {
+    let count = unsafe { libc::proc_listallpids(ptr::null_mut(), 0) };
+    if count < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    // leave room for processes spawned in between
+    let mut pids = vec![0 as libc::pid_t; count as usize + 64];
+    let size = (pids.len() * mem::size_of::<libc::pid_t>()) as libc::c_int;
+    let count = unsafe { libc::proc_listallpids(pids.as_mut_ptr().cast(), size) };
+    if count < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    pids.truncate(count as usize);
+
+    Ok(pids
+        .into_iter()
+        .filter(|pid| *pid > 0)
+        .map(|pid| Arc::new(Process { pid }))
+        .collect())
+}
@@ -0,0 +1,61 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+// Platform backends behind the handles. Every backend provides the same
+// functions and returns the procfs shaped types, so callers and the WIT
+// conversions don't care where the data came from.
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+mod ifaddrs;
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(not(target_os = "linux"))]
+mod render;
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+mod sysctl;
+#[cfg(target_os = "linux")]
+pub(crate) use linux::*;
+
+#[cfg(all(windows, feature = "windows"))]
+mod windows;
+#[cfg(all(windows, feature = "windows"))]
+pub(crate) use windows::*;
+// the process handle is part of the public api
+#[cfg(all(windows, feature = "windows"))]
+pub use windows::Process;
+
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "macos")]
+pub use macos::Process;
+#[cfg(target_os = "macos")]
+pub(crate) use macos::*;
+
+#[cfg(target_os = "freebsd")]
+mod freebsd;
+#[cfg(target_os = "freebsd")]
+pub use freebsd::Process;
+#[cfg(target_os = "freebsd")]
+pub(crate) use freebsd::*;
+
+#[cfg(all(windows, not(feature = "windows")))]
+compile_error!("psh-system needs the `windows` feature on Windows");
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd",
+    windows
+)))]
+compile_error!("psh-system has no backend for this platform");
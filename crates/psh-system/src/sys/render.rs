@@ -0,0 +1,164 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+// Backends without procfs render the procfs text format and let procfs-core
+// parse it, so they return exactly the types of the Linux backend, private
+// fields and derived values included.
+
+use std::fmt::Write;
+
+use procfs_core::{ExplicitSystemInfo, FromRead, FromReadSI, KernelStats, Meminfo, process::Stat};
+
+use super::{boot_time_secs, page_size, ticks_per_second};
+use crate::error::Result;
+
+// fields procfs-core refuses to parse `/proc/meminfo` without
+const MEMINFO_REQUIRED: [&str; 13] = [
+    "MemTotal",
+    "MemFree",
+    "Buffers",
+    "Cached",
+    "SwapCached",
+    "Active",
+    "Inactive",
+    "SwapTotal",
+    "SwapFree",
+    "Dirty",
+    "Writeback",
+    "Mapped",
+    "Slab",
+];
+
+fn system_info() -> ExplicitSystemInfo {
+    ExplicitSystemInfo {
+        boot_time_secs: boot_time_secs(),
+        ticks_per_second: ticks_per_second(),
+        page_size: page_size(),
+        is_little_endian: cfg!(target_endian = "little"),
+    }
+}
+
+/// `(name, kB)` pairs, required fields missing are reported as zero.
+pub fn meminfo(fields: &[(&str, u64)]) -> Result<Meminfo> {
+    let mut text = String::new();
+    for (name, kb) in fields {
+        let _ = writeln!(text, "{name}: {kb} kB");
+    }
+    for name in MEMINFO_REQUIRED {
+        if !fields.iter().any(|(it, _)| *it == name) {
+            let _ = writeln!(text, "{name}: 0 kB");
+        }
+    }
+    Meminfo::from_read(text.as_bytes()).map_err(Into::into)
+}
+
+/// Cpu times in ticks of [`ticks_per_second`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuTicks {
+    pub user: u64,
+    pub nice: u64,
+    pub system: u64,
+    pub idle: u64,
+    pub irq: u64,
+}
+
+impl CpuTicks {
+    fn line(&self, name: &str) -> String {
+        let Self {
+            user,
+            nice,
+            system,
+            idle,
+            irq,
+        } = self;
+        format!("{name} {user} {nice} {system} {idle} 0 {irq} 0 0 0 0\n")
+    }
+}
+
+/// `total` is summed from `per_cpu` if not given.
+pub fn kernel_stats(total: Option<CpuTicks>, per_cpu: &[CpuTicks]) -> Result<KernelStats> {
+    let total = total.unwrap_or_else(|| {
+        per_cpu
+            .iter()
+            .fold(CpuTicks::default(), |acc, it| CpuTicks {
+                user: acc.user + it.user,
+                nice: acc.nice + it.nice,
+                system: acc.system + it.system,
+                idle: acc.idle + it.idle,
+                irq: acc.irq + it.irq,
+            })
+    });
+
+    let mut text = total.line("cpu ");
+    for (cpu, ticks) in per_cpu.iter().enumerate() {
+        text.push_str(&ticks.line(&format!("cpu{cpu}")));
+    }
+    let _ = write!(text, "ctxt 0\nbtime {}\nprocesses 0\n", boot_time_secs());
+    KernelStats::from_read(text.as_bytes(), &system_info()).map_err(Into::into)
+}
+
+/// The fields of `/proc/<pid>/stat` other platforms can tell.
+#[derive(Debug, Clone, Default)]
+pub struct ProcStat<'a> {
+    pub pid: i32,
+    pub comm: &'a str,
+    /// procfs state letter, e.g. 'R' or 'S'
+    pub state: char,
+    pub ppid: i32,
+    pub minflt: u64,
+    pub majflt: u64,
+    /// in ticks
+    pub utime: u64,
+    /// in ticks
+    pub stime: u64,
+    pub priority: i64,
+    pub nice: i64,
+    pub num_threads: i64,
+    /// in ticks since boot
+    pub starttime: u64,
+    /// in bytes
+    pub vsize: u64,
+    /// in pages
+    pub rss: u64,
+}
+
+impl ProcStat<'_> {
+    pub fn parse(&self) -> Result<Stat> {
+        let Self {
+            pid,
+            comm,
+            state,
+            ppid,
+            minflt,
+            majflt,
+            utime,
+            stime,
+            priority,
+            nice,
+            num_threads,
+            starttime,
+            vsize,
+            rss,
+        } = self;
+        // pid (comm) state ppid pgrp session tty_nr tpgid flags minflt cminflt majflt
+        // cmajflt utime stime cutime cstime priority nice num_threads itrealvalue
+        // starttime vsize rss, followed by 28 fields nobody else has
+        let text = format!(
+            "{pid} ({comm}) {state} {ppid} 0 0 0 0 0 {minflt} 0 {majflt} 0 {utime} {stime} 0 0 \
+             {priority} {nice} {num_threads} 0 {starttime} {vsize} {rss}{}\n",
+            " 0".repeat(28),
+        );
+        Stat::from_read(text.as_bytes()).map_err(Into::into)
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+// sysctl(3), BSD kernels expose most counters this way.
+
+use std::{
+    ffi::{CStr, CString},
+    io,
+    mem::{self, MaybeUninit},
+    ptr,
+};
+
+use libc::c_int;
+
+fn by_name(name: &str, buf: *mut u8, len: &mut usize) -> io::Result<()> {
+    let name = CString::new(name)?;
+    let ret = unsafe { libc::sysctlbyname(name.as_ptr(), buf.cast(), len, ptr::null_mut(), 0) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn raw(name: &str) -> io::Result<Vec<u8>> {
+    let mut len = 0;
+    by_name(name, ptr::null_mut(), &mut len)?;
+    let mut buf = vec![0; len];
+    by_name(name, buf.as_mut_ptr(), &mut len)?;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+pub fn string(name: &str) -> Option<String> {
+    let buf = raw(name).ok()?;
+    let s = CStr::from_bytes_until_nul(&buf).ok()?;
+    Some(s.to_string_lossy().into_owned())
+}
+
+/// `T` must be the plain C type the kernel reports, the size is checked.
+pub fn value<T: Copy>(name: &str) -> Option<T> {
+    let mut value = MaybeUninit::<T>::zeroed();
+    let mut len = mem::size_of::<T>();
+    by_name(name, value.as_mut_ptr().cast(), &mut len).ok()?;
+    (len == mem::size_of::<T>()).then(|| unsafe { value.assume_init() })
+}
+
+/// The numeric name, for nodes that take extra levels like a pid.
+pub fn mib(name: &str) -> io::Result<Vec<c_int>> {
+    let name = CString::new(name)?;
+    let mut mib = vec![0; libc::CTL_MAXNAME as usize];
+    let mut len = mib.len();
+    if unsafe { libc::sysctlnametomib(name.as_ptr(), mib.as_mut_ptr(), &mut len) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    mib.truncate(len);
+    Ok(mib)
+}
+
+fn by_mib(mib: &mut [c_int], buf: *mut u8, len: &mut usize) -> io::Result<()> {
+    let ret = unsafe {
+        libc::sysctl(
+            mib.as_mut_ptr(),
+            mib.len() as libc::c_uint,
+            buf.cast(),
+            len,
+            ptr::null_mut(),
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn raw_mib(mib: &[c_int]) -> io::Result<Vec<u8>> {
+    let mut mib = mib.to_vec();
+    let mut len = 0;
+    by_mib(&mut mib, ptr::null_mut(), &mut len)?;
+    // tables like the process list may grow between the two calls
+    len += len / 4;
+    let mut buf = vec![0; len];
+    by_mib(&mut mib, buf.as_mut_ptr(), &mut len)?;
+    buf.truncate(len);
+    Ok(buf)
+}
@@ -0,0 +1,95 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{io, mem};
+
+use windows_sys::Win32::{Foundation::FILETIME, System::Threading::GetSystemTimes};
+
+use super::{
+    filetime_to_ticks,
+    render::{self, CpuTicks},
+};
+use crate::{
+    cpu::{CpuInfo, CpuStats, CpuTopology, Vulnerability},
+    error::{Error, Result},
+};
+
+const PROCESSOR_KEY: &str = r"HARDWARE\DESCRIPTION\System\CentralProcessor";
+
+#[cfg(target_arch = "x86_64")]
+pub fn cpu_info() -> Result<CpuInfo> {
+    use crate::cpu::X86_64CpuInfo;
+
+    let mut cpus = Vec::new();
+    for processor in 0.. {
+        let key = format!(r"{PROCESSOR_KEY}\{processor}");
+        let Some(model_name) = super::reg_string(&key, "ProcessorNameString") else {
+            break;
+        };
+        let mut info = X86_64CpuInfo::new();
+        info.processor = processor;
+        info.model_name = model_name.trim().to_string();
+        info.vendor_id = super::reg_string(&key, "VendorIdentifier").unwrap_or_default();
+        info.cpu_mhz = super::reg_dword(&key, "~MHz").map_or(0.0, f64::from);
+        // e.g. "Intel64 Family 6 Model 158 Stepping 10"
+        if let Some(identifier) = super::reg_string(&key, "Identifier") {
+            let mut words = identifier.split_whitespace();
+            while let Some(word) = words.next() {
+                let value = words.next().and_then(|it| it.parse().ok());
+                match (word, value) {
+                    ("Family", Some(value)) => info.cpu_family = value,
+                    ("Model", Some(value)) => info.model = value,
+                    ("Stepping", Some(value)) => info.stepping = value,
+                    _ => {}
+                }
+            }
+        }
+        cpus.push(info);
+    }
+    Ok(CpuInfo::X86_64(cpus))
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn cpu_info() -> Result<CpuInfo> {
+    Ok(CpuInfo::Unsupported(std::env::consts::ARCH.to_string()))
+}
+
+/// Only the total line is filled, Windows keeps per-cpu times behind
+/// undocumented `NtQuerySystemInformation` classes.
+pub fn cpu_stats() -> Result<CpuStats> {
+    let (mut idle, mut kernel, mut user): (FILETIME, FILETIME, FILETIME) =
+        unsafe { (mem::zeroed(), mem::zeroed(), mem::zeroed()) };
+    if unsafe { GetSystemTimes(&mut idle, &mut kernel, &mut user) } == 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let idle = filetime_to_ticks(&idle);
+    let total = CpuTicks {
+        user: filetime_to_ticks(&user),
+        nice: 0,
+        // kernel time includes idle time
+        system: filetime_to_ticks(&kernel).saturating_sub(idle),
+        idle,
+        irq: 0,
+    };
+    render::kernel_stats(Some(total), &[]).map(Into::into)
+}
+
+/// Only Linux exposes the NUMA layout, in sysfs.
+pub fn cpu_topology() -> Result<CpuTopology> {
+    Err(Error::Unsupported("cpu topology"))
+}
+
+pub fn cpu_vulnerabilities() -> Result<Vec<Vulnerability>> {
+    Err(Error::Unsupported("cpu vulnerabilities"))
+}
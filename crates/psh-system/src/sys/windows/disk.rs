@@ -0,0 +1,108 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{ffi::c_void, mem, ptr};
+
+use procfs_core::DiskStat;
+use windows_sys::Win32::{
+    Foundation::INVALID_HANDLE_VALUE,
+    Storage::FileSystem::{CreateFileW, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING},
+    System::{
+        IO::DeviceIoControl,
+        Ioctl::{DISK_PERFORMANCE, IOCTL_DISK_PERFORMANCE},
+    },
+};
+
+use super::{OwnedHandle, to_wide};
+use crate::error::Result;
+
+// diskstats always counts 512 bytes sectors
+const SECTOR_SIZE: u64 = 512;
+
+const fn ms(time: i64) -> u64 {
+    // 100ns intervals
+    time as u64 / 10_000
+}
+
+fn performance(drive: &OwnedHandle) -> Option<DISK_PERFORMANCE> {
+    let mut perf: DISK_PERFORMANCE = unsafe { mem::zeroed() };
+    let mut returned = 0;
+    let ok = unsafe {
+        DeviceIoControl(
+            drive.0,
+            IOCTL_DISK_PERFORMANCE,
+            ptr::null(),
+            0,
+            (&mut perf as *mut DISK_PERFORMANCE).cast::<c_void>(),
+            mem::size_of::<DISK_PERFORMANCE>() as u32,
+            &mut returned,
+            ptr::null_mut(),
+        )
+    };
+    (ok != 0).then_some(perf)
+}
+
+/// Counters of every `\\.\PhysicalDriveN`, named after the drive.
+pub fn disk_stats() -> Result<Vec<DiskStat>> {
+    let mut stats = Vec::new();
+    for minor in 0.. {
+        let name = format!("PhysicalDrive{minor}");
+        let path = to_wide(&format!(r"\\.\{name}"));
+        // no access rights are needed for IOCTL_DISK_PERFORMANCE
+        let handle = unsafe {
+            CreateFileW(
+                path.as_ptr(),
+                0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                ptr::null(),
+                OPEN_EXISTING,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            break;
+        }
+        let drive = OwnedHandle(handle);
+        // counters may be disabled for this drive
+        let Some(perf) = performance(&drive) else {
+            continue;
+        };
+
+        let (time_reading, time_writing) = (ms(perf.ReadTime), ms(perf.WriteTime));
+        stats.push(DiskStat {
+            major: 0,
+            minor,
+            name,
+            reads: u64::from(perf.ReadCount),
+            merged: 0,
+            sectors_read: perf.BytesRead as u64 / SECTOR_SIZE,
+            time_reading,
+            writes: u64::from(perf.WriteCount),
+            writes_merged: 0,
+            sectors_written: perf.BytesWritten as u64 / SECTOR_SIZE,
+            time_writing,
+            in_progress: u64::from(perf.QueueDepth),
+            time_in_progress: ms(perf.QueryTime.saturating_sub(perf.IdleTime)),
+            weighted_time_in_progress: time_reading + time_writing,
+            discards: None,
+            discards_merged: None,
+            sectors_discarded: None,
+            time_discarding: None,
+            flushes: None,
+            time_flushing: None,
+        });
+    }
+    Ok(stats)
+}
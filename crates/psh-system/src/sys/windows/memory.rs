@@ -0,0 +1,66 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{io, mem};
+
+use procfs_core::Meminfo;
+use windows_sys::Win32::System::{
+    ProcessStatus::{K32GetPerformanceInfo, PERFORMANCE_INFORMATION},
+    SystemInformation::{GlobalMemoryStatusEx, MEMORYSTATUSEX},
+};
+
+use super::render;
+use crate::{
+    error::{Error, Result},
+    memory::MemoryModule,
+};
+
+pub fn meminfo() -> Result<Meminfo> {
+    let mut status: MEMORYSTATUSEX = unsafe { mem::zeroed() };
+    status.dwLength = mem::size_of::<MEMORYSTATUSEX>() as u32;
+    if unsafe { GlobalMemoryStatusEx(&mut status) } == 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let mut perf: PERFORMANCE_INFORMATION = unsafe { mem::zeroed() };
+    let size = mem::size_of::<PERFORMANCE_INFORMATION>() as u32;
+    if unsafe { K32GetPerformanceInfo(&mut perf, size) } == 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    let kb = |pages: usize| (pages * perf.PageSize / 1024) as u64;
+    let available = status.ullAvailPhys / 1024;
+    let cached = kb(perf.SystemCache);
+    // the page file limits include physical memory
+    let swap_total = status.ullTotalPageFile.saturating_sub(status.ullTotalPhys) / 1024;
+    let swap_free = status.ullAvailPageFile.saturating_sub(status.ullAvailPhys) / 1024;
+
+    render::meminfo(&[
+        ("MemTotal", status.ullTotalPhys / 1024),
+        ("MemFree", available.saturating_sub(cached)),
+        ("MemAvailable", available),
+        ("Cached", cached),
+        ("SwapTotal", swap_total),
+        ("SwapFree", swap_free.min(swap_total)),
+        ("Slab", kb(perf.KernelTotal)),
+        ("SReclaimable", kb(perf.KernelPaged)),
+        ("SUnreclaim", kb(perf.KernelNonpaged)),
+        ("CommitLimit", kb(perf.CommitLimit)),
+        ("Committed_AS", kb(perf.CommitTotal)),
+    ])
+}
+
+pub fn memory_modules() -> Result<Vec<MemoryModule>> {
+    // needs SMBIOS parsing or WMI `Win32_PhysicalMemory`
+    Err(Error::Unsupported("memory module info"))
+}
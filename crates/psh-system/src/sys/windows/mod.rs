@@ -0,0 +1,131 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+mod cpu;
+mod disk;
+mod memory;
+mod network;
+mod os;
+mod process;
+
+use std::{
+    ffi::c_void,
+    mem, ptr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub use cpu::{cpu_info, cpu_stats, cpu_topology, cpu_vulnerabilities};
+pub use disk::disk_stats;
+pub use memory::{meminfo, memory_modules};
+pub use network::net_dev_status;
+pub use os::os_info;
+pub use process::{Process, all_processes, myself};
+use windows_sys::Win32::{
+    Foundation::{CloseHandle, ERROR_SUCCESS, FILETIME, HANDLE},
+    System::{
+        Registry::{HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD, RRF_RT_REG_SZ, RegGetValueW},
+        SystemInformation::{GetSystemInfo, GetTickCount64, SYSTEM_INFO},
+    },
+};
+
+use super::render;
+
+// Windows has no USER_HZ, cpu times are converted to this many ticks per second.
+const TICKS_PER_SECOND: u64 = 100;
+// FILETIME counts 100ns intervals
+const FILETIME_PER_TICK: u64 = 10_000_000 / TICKS_PER_SECOND;
+
+pub fn page_size() -> u64 {
+    let mut info: SYSTEM_INFO = unsafe { mem::zeroed() };
+    unsafe { GetSystemInfo(&mut info) };
+    u64::from(info.dwPageSize)
+}
+
+pub fn boot_time_secs() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |it| it.as_secs());
+    now.saturating_sub(unsafe { GetTickCount64() } / 1000)
+}
+
+pub const fn ticks_per_second() -> u64 {
+    TICKS_PER_SECOND
+}
+
+const fn filetime_to_ticks(time: &FILETIME) -> u64 {
+    ((time.dwHighDateTime as u64) << 32 | time.dwLowDateTime as u64) / FILETIME_PER_TICK
+}
+
+fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain([0]).collect()
+}
+
+fn from_wide(s: &[u16]) -> String {
+    let len = s.iter().position(|c| *c == 0).unwrap_or(s.len());
+    String::from_utf16_lossy(&s[..len])
+}
+
+/// Closes the handle on drop.
+struct OwnedHandle(HANDLE);
+
+impl Drop for OwnedHandle {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+fn reg_value(subkey: &str, value: &str, flags: u32, data: *mut c_void, len: &mut u32) -> bool {
+    let (subkey, value) = (to_wide(subkey), to_wide(value));
+    let err = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            subkey.as_ptr(),
+            value.as_ptr(),
+            flags,
+            ptr::null_mut(),
+            data,
+            len,
+        )
+    };
+    err == ERROR_SUCCESS
+}
+
+fn reg_string(subkey: &str, value: &str) -> Option<String> {
+    let mut len = 0;
+    if !reg_value(subkey, value, RRF_RT_REG_SZ, ptr::null_mut(), &mut len) {
+        return None;
+    }
+    let mut buf = vec![0u16; len as usize / 2];
+    reg_value(
+        subkey,
+        value,
+        RRF_RT_REG_SZ,
+        buf.as_mut_ptr().cast(),
+        &mut len,
+    )
+    .then(|| from_wide(&buf))
+}
+
+fn reg_dword(subkey: &str, value: &str) -> Option<u32> {
+    let mut data = 0u32;
+    let mut len = mem::size_of::<u32>() as u32;
+    reg_value(
+        subkey,
+        value,
+        RRF_RT_REG_DWORD,
+        (&mut data as *mut u32).cast(),
+        &mut len,
+    )
+    .then_some(data)
+}
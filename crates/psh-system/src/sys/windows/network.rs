@@ -0,0 +1,82 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{collections::HashMap, io, ptr, slice};
+
+use procfs_core::net::DeviceStatus;
+use windows_sys::Win32::{
+    Foundation::NO_ERROR,
+    NetworkManagement::IpHelper::{FreeMibTable, GetIfTable2, MIB_IF_ROW2, MIB_IF_TABLE2},
+};
+
+use super::from_wide;
+use crate::error::Result;
+
+// `InterfaceAndOperStatusFlags`: HardwareInterface, FilterInterface, ...
+const FILTER_INTERFACE: u8 = 1 << 1;
+
+fn device_status(row: &MIB_IF_ROW2) -> DeviceStatus {
+    // procfs style names have no whitespace, e.g. "Ethernet 2" becomes "Ethernet_2"
+    let name = from_wide(&row.Alias)
+        .chars()
+        .map(|c| {
+            if c.is_whitespace() || c == ':' {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    DeviceStatus {
+        name,
+        recv_bytes: row.InOctets,
+        recv_packets: row.InUcastPkts + row.InNUcastPkts,
+        recv_errs: row.InErrors,
+        recv_drop: row.InDiscards,
+        recv_fifo: 0,
+        recv_frame: 0,
+        recv_compressed: 0,
+        recv_multicast: row.InNUcastPkts,
+        sent_bytes: row.OutOctets,
+        sent_packets: row.OutUcastPkts + row.OutNUcastPkts,
+        sent_errs: row.OutErrors,
+        sent_drop: row.OutDiscards,
+        sent_fifo: 0,
+        sent_colls: 0,
+        sent_carrier: 0,
+        sent_compressed: 0,
+    }
+}
+
+pub fn net_dev_status() -> Result<HashMap<String, DeviceStatus>> {
+    let mut table: *mut MIB_IF_TABLE2 = ptr::null_mut();
+    let err = unsafe { GetIfTable2(&mut table) };
+    if err != NO_ERROR {
+        return Err(io::Error::from_raw_os_error(err as i32).into());
+    }
+
+    // SAFETY: the table holds `NumEntries` rows until freed below
+    let rows =
+        unsafe { slice::from_raw_parts((*table).Table.as_ptr(), (*table).NumEntries as usize) };
+    let devices = rows
+        .iter()
+        // every NDIS filter shows up as another interface with the same counters
+        .filter(|row| row.InterfaceAndOperStatusFlags._bitfield & FILTER_INTERFACE == 0)
+        .map(device_status)
+        .map(|status| (status.name.clone(), status))
+        .collect();
+    unsafe { FreeMibTable(table.cast()) };
+
+    Ok(devices)
+}
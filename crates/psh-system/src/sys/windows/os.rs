@@ -0,0 +1,46 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    error::{Error, Result},
+    os::{DistroKind, DistroVersion, KernelVersion, OsInfo},
+};
+
+const VERSION_KEY: &str = r"SOFTWARE\Microsoft\Windows NT\CurrentVersion";
+
+/// The product (e.g. "Windows 11 Pro", "23H2") is reported as distro, the
+/// NT version as kernel.
+pub fn os_info() -> Result<OsInfo> {
+    let reg_string = |value| super::reg_string(VERSION_KEY, value);
+    let major = super::reg_dword(VERSION_KEY, "CurrentMajorVersionNumber");
+    let minor = super::reg_dword(VERSION_KEY, "CurrentMinorVersionNumber");
+    let build = reg_string("CurrentBuildNumber").and_then(|it| it.parse().ok());
+    let (Some(major), Some(minor), Some(build)) = (major, minor, build) else {
+        return Err(Error::EmptyValue);
+    };
+
+    Ok(OsInfo {
+        distro: DistroVersion {
+            distro: DistroKind::Other(
+                reg_string("ProductName").unwrap_or_else(|| "Windows".to_string()),
+            ),
+            version: reg_string("DisplayVersion"),
+        },
+        kernel: KernelVersion {
+            major: major as u8,
+            minor: minor as u8,
+            patch: build,
+        },
+    })
+}
@@ -0,0 +1,222 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{collections::HashMap, ffi::OsString, io, mem, path::PathBuf, sync::Arc};
+
+use procfs_core::process::{Io, Stat, StatM};
+use windows_sys::Win32::{
+    Foundation::{FILETIME, INVALID_HANDLE_VALUE},
+    System::{
+        Diagnostics::ToolHelp::{
+            CreateToolhelp32Snapshot, PROCESSENTRY32W, Process32FirstW, Process32NextW,
+            TH32CS_SNAPPROCESS,
+        },
+        ProcessStatus::{K32GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS},
+        Threading::{
+            GetCurrentProcessId, GetProcessIoCounters, GetProcessTimes, IO_COUNTERS, OpenProcess,
+            PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION, QueryFullProcessImageNameW,
+        },
+    },
+};
+
+use super::{
+    OwnedHandle, boot_time_secs, filetime_to_ticks, from_wide, page_size, render::ProcStat,
+};
+use crate::error::{Error, Result};
+
+// FILETIME of 1970-01-01 in ticks
+const UNIX_EPOCH_TICKS: u64 = 11_644_473_600 * super::TICKS_PER_SECOND;
+
+/// A process found by a toolhelp snapshot.
+///
+/// Mirrors the methods of `procfs::process::Process` used by the host
+/// ops, what Windows can't tell (e.g. the environment of another process)
+/// is reported as [`Error::Unsupported`].
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub pid: i32,
+    ppid: i32,
+    comm: String,
+    num_threads: i64,
+}
+
+impl Process {
+    fn open(&self) -> io::Result<OwnedHandle> {
+        let handle = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, self.pid as u32) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(OwnedHandle(handle))
+    }
+
+    pub fn exe(&self) -> Result<PathBuf> {
+        let handle = self.open()?;
+        let mut buf = vec![0u16; 32 * 1024];
+        let mut len = buf.len() as u32;
+        let ok = unsafe {
+            QueryFullProcessImageNameW(handle.0, PROCESS_NAME_WIN32, buf.as_mut_ptr(), &mut len)
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(PathBuf::from(from_wide(&buf[..len as usize])))
+    }
+
+    pub fn cmdline(&self) -> Result<Vec<String>> {
+        // reading another process' PEB is not a public API
+        Ok(vec![self.exe()?.to_string_lossy().to_string()])
+    }
+
+    pub fn environ(&self) -> Result<HashMap<OsString, OsString>> {
+        Err(Error::Unsupported("process environment"))
+    }
+
+    pub fn cwd(&self) -> Result<PathBuf> {
+        Err(Error::Unsupported("process working directory"))
+    }
+
+    pub fn root(&self) -> Result<PathBuf> {
+        Ok(PathBuf::from(r"C:\"))
+    }
+
+    pub fn uid(&self) -> Result<u32> {
+        Err(Error::Unsupported("process user id"))
+    }
+
+    /// Rendered as `/proc/<pid>/stat`, times are in ticks of
+    /// [`super::ticks_per_second`].
+    pub fn stat(&self) -> Result<Stat> {
+        let handle = self.open()?;
+        let (mut creation, mut exit, mut kernel, mut user): (
+            FILETIME,
+            FILETIME,
+            FILETIME,
+            FILETIME,
+        ) = unsafe { (mem::zeroed(), mem::zeroed(), mem::zeroed(), mem::zeroed()) };
+        let ok =
+            unsafe { GetProcessTimes(handle.0, &mut creation, &mut exit, &mut kernel, &mut user) };
+        if ok == 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // procfs counts start time in ticks since boot
+        let boot_ticks = boot_time_secs() * super::TICKS_PER_SECOND;
+        let start_time = filetime_to_ticks(&creation)
+            .saturating_sub(UNIX_EPOCH_TICKS)
+            .saturating_sub(boot_ticks);
+        let mem = self.memory_counters(&handle)?;
+
+        ProcStat {
+            pid: self.pid,
+            comm: &self.comm,
+            // Windows has no notion of sleeping processes
+            state: 'R',
+            ppid: self.ppid,
+            minflt: u64::from(mem.PageFaultCount),
+            utime: filetime_to_ticks(&user),
+            stime: filetime_to_ticks(&kernel),
+            // NORMAL_PRIORITY_CLASS
+            priority: 8,
+            num_threads: self.num_threads,
+            starttime: start_time,
+            vsize: mem.PagefileUsage as u64,
+            rss: mem.WorkingSetSize as u64 / page_size(),
+            ..Default::default()
+        }
+        .parse()
+    }
+
+    pub fn io(&self) -> Result<Io> {
+        let handle = self.open()?;
+        let mut counters: IO_COUNTERS = unsafe { mem::zeroed() };
+        if unsafe { GetProcessIoCounters(handle.0, &mut counters) } == 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // Windows doesn't tell storage io from other io
+        Ok(Io {
+            rchar: counters.ReadTransferCount,
+            wchar: counters.WriteTransferCount,
+            syscr: counters.ReadOperationCount,
+            syscw: counters.WriteOperationCount,
+            read_bytes: counters.ReadTransferCount,
+            write_bytes: counters.WriteTransferCount,
+            cancelled_write_bytes: 0,
+        })
+    }
+
+    /// In pages like `/proc/<pid>/statm`.
+    pub fn statm(&self) -> Result<StatM> {
+        let mem = self.memory_counters(&self.open()?)?;
+        let page_size = page_size();
+        Ok(StatM {
+            size: mem.PagefileUsage as u64 / page_size,
+            resident: mem.WorkingSetSize as u64 / page_size,
+            shared: 0,
+            text: 0,
+            lib: 0,
+            data: mem.PagefileUsage as u64 / page_size,
+            dt: 0,
+        })
+    }
+
+    fn memory_counters(&self, handle: &OwnedHandle) -> io::Result<PROCESS_MEMORY_COUNTERS> {
+        let mut counters: PROCESS_MEMORY_COUNTERS = unsafe { mem::zeroed() };
+        let size = mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+        if unsafe { K32GetProcessMemoryInfo(handle.0, &mut counters, size) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(counters)
+    }
+}
+
+impl From<&PROCESSENTRY32W> for Process {
+    fn from(value: &PROCESSENTRY32W) -> Self {
+        Self {
+            pid: value.th32ProcessID as i32,
+            ppid: value.th32ParentProcessID as i32,
+            comm: from_wide(&value.szExeFile),
+            num_threads: i64::from(value.cntThreads),
+        }
+    }
+}
+
+fn snapshot() -> Result<Vec<Process>> {
+    let handle = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error().into());
+    }
+    let snapshot = OwnedHandle(handle);
+
+    let mut entry: PROCESSENTRY32W = unsafe { mem::zeroed() };
+    entry.dwSize = mem::size_of::<PROCESSENTRY32W>() as u32;
+    let mut procs = Vec::new();
+    let mut ok = unsafe { Process32FirstW(snapshot.0, &mut entry) };
+    while ok != 0 {
+        procs.push(Process::from(&entry));
+        ok = unsafe { Process32NextW(snapshot.0, &mut entry) };
+    }
+    Ok(procs)
+}
+
+pub fn myself() -> Result<Arc<Process>> {
+    let pid = unsafe { GetCurrentProcessId() } as i32;
+    snapshot()?
+        .into_iter()
+        .find(|it| it.pid == pid)
+        .map(Arc::new)
+        .ok_or(Error::EmptyValue)
+}
+
+pub fn all_processes() -> Result<Vec<Arc<Process>>> {
+    Ok(snapshot()?.into_iter().map(Arc::new).collect())
+}
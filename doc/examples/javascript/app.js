@@ -0,0 +1,12 @@
+import { all } from "profiling:system/process";
+
+export const run = {
+  run() {
+    const processes = all(1000n);
+    processes.sort((a, b) => Number(b.memoryUsage - a.memoryUsage));
+    for (const it of processes.slice(0, 5)) {
+      const mib = (Number(it.memoryUsage) / 2 ** 20).toFixed(1);
+      console.log(`${String(it.pid).padStart(7)} ${it.name.padEnd(16)} ${mib.padStart(8)} MiB`);
+    }
+  },
+};
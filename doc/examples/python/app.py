@@ -0,0 +1,10 @@
+from wit_world import exports
+from wit_world.imports import process
+
+
+class Run(exports.Run):
+    def run(self) -> None:
+        processes = process.all(1000)
+        processes.sort(key=lambda it: it.memory_usage, reverse=True)
+        for it in processes[:5]:
+            print(f"{it.pid:>7} {it.name:<16} {it.memory_usage / 2**20:8.1f} MiB")
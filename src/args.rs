@@ -12,7 +12,9 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+
+use crate::config::ComponentConfig;
 
 #[derive(Parser, Debug)]
 pub struct Args {
@@ -45,4 +47,137 @@ pub struct Args {
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     #[arg(verbatim_doc_comment)]
     pub wasm_with_args: Option<Vec<String>>,
+
+    /// Another WASM binary with its arguments, run side by side
+    /// └╴e.g. --wasm "/path/to/other.wasm foo bar", may be repeated
+    ///   Invalid in daemon mode (--daemon)
+    #[arg(long)]
+    #[arg(value_name = "WASM ARGS")]
+    #[arg(verbatim_doc_comment)]
+    pub wasm: Vec<String>,
+
+    /// Seed wasi:random/insecure of the components given on the command line
+    /// └╴e.g. the seed of a run recorded in the ledger, to replay it
+    #[arg(long)]
+    #[arg(verbatim_doc_comment)]
+    pub seed: Option<u64>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Args {
+    /// Components given on the command line, each in its own store.
+    pub fn components(&self) -> Vec<ComponentConfig> {
+        self.wasm_with_args
+            .iter()
+            .cloned()
+            .chain(
+                self.wasm
+                    .iter()
+                    .map(|it| it.split_whitespace().map(str::to_string).collect()),
+            )
+            .filter_map(|mut args: Vec<String>| {
+                if args.is_empty() {
+                    return None;
+                }
+                let path = args.remove(0);
+                Some(ComponentConfig {
+                    path,
+                    sha256: None,
+                    name: None,
+                    args,
+                    env: Default::default(),
+                    tenant: None,
+                    schedule: None,
+                    after: None,
+                    max_cpu_ms: None,
+                    memory_limit: None,
+                    rate_limits: Default::default(),
+                    preopens: vec![],
+                    seed: self.seed,
+                    restart: Default::default(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Components of the catalog configured in `[catalog]`
+    #[command(subcommand)]
+    Component(ComponentCommand),
+    /// Print a file encrypted at rest, see `[at_rest]`
+    Decrypt { path: String },
+    /// Compile components into the cache of `[engine]` ahead of their first run
+    Precompile {
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Collect config, logs, metrics, runs and a system snapshot for support
+    Bundle {
+        /// `psh-bundle-<host>-<time>.tar.gz` in the working directory if not given
+        #[arg(short, long)]
+        #[arg(value_name = "PATH")]
+        output: Option<String>,
+    },
+    /// Check whether psh can satisfy the imports of a component, without running it
+    Validate {
+        path: String,
+        /// Check against the interface policy of the component of this name
+        #[arg(long)]
+        name: Option<String>,
+        /// Check against the permissions of this tenant of `[[tenants]]`
+        #[arg(long)]
+        tenant: Option<String>,
+    },
+    /// Print how psh was built
+    Version {
+        /// As JSON, with the enabled features and the wasmtime version
+        #[arg(long)]
+        json: bool,
+    },
+    /// Run the component sent on stdin, the daemon spawns it with `[engine] isolate`
+    #[command(hide = true)]
+    Isolated,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ComponentCommand {
+    /// List catalog components whose name or description matches QUERY
+    Search { query: Option<String> },
+    /// Download a component of the catalog into the local store
+    Install {
+        name: String,
+        /// The latest if not given
+        #[arg(long)]
+        version: Option<String>,
+    },
+    /// List the installed components
+    List,
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::Args;
+
+    #[test]
+    fn test_same_wasm_with_args() {
+        let args = Args::try_parse_from([
+            "psh",
+            "--wasm",
+            "/opt/collect.wasm --memory",
+            "/opt/collect.wasm",
+            "--cpu",
+        ])
+        .unwrap();
+        let components = args.components();
+        assert_eq!(components.len(), 2);
+        assert!(components.iter().all(|it| it.path == "/opt/collect.wasm"));
+        assert_eq!(components[0].args, ["--cpu"]);
+        assert_eq!(components[1].args, ["--memory"]);
+    }
 }
@@ -0,0 +1,254 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+    sync::OnceLock,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::config::AtRestConfig;
+
+const KEY_LEN: usize = 32;
+/// Starts files sealed as a whole, line sealed files are hex lines.
+const MAGIC: &[u8] = b"PSH-SEALED-1\n";
+
+static GLOBAL: OnceLock<Sealer> = OnceLock::new();
+
+pub fn global() -> Option<&'static Sealer> {
+    GLOBAL.get()
+}
+
+pub fn init(cfg: &AtRestConfig) -> Result<()> {
+    let sealer = Sealer::new(&load_key(cfg, true)?)?;
+    if GLOBAL.set(sealer).is_err() {
+        bail!("Encryption at rest already initialized");
+    }
+    Ok(())
+}
+
+/// The key given in the config, otherwise the one in `key_file`, which is
+/// generated if missing and `generate`.
+fn load_key(cfg: &AtRestConfig, generate: bool) -> Result<Vec<u8>> {
+    if let Some(key) = &cfg.key {
+        return decode_key(key.as_bytes()).context("Invalid key of [at_rest]");
+    }
+    let path = Path::new(&cfg.key_file);
+    match fs::read(path) {
+        Ok(key) => decode_key(&key).with_context(|| format!("Invalid key in {}", path.display())),
+        Err(e) if generate && e.kind() == io::ErrorKind::NotFound => {
+            let mut key = vec![0; KEY_LEN];
+            SystemRandom::new()
+                .fill(&mut key)
+                .map_err(|_| anyhow!("Failed to generate a key"))?;
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+            }
+            // only root may read it, never overwrite a key created meanwhile
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(path)
+                .and_then(|mut file| file.write_all(&key))
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            tracing::info!(
+                "Generated the key for encryption at rest in {}",
+                path.display()
+            );
+            Ok(key)
+        }
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// 32 raw bytes or 64 hex digits, a trailing newline is ignored.
+fn decode_key(key: &[u8]) -> Result<Vec<u8>> {
+    if key.len() == KEY_LEN {
+        return Ok(key.to_vec());
+    }
+    let hex = std::str::from_utf8(key)
+        .map_err(|_| anyhow!("Not hex"))?
+        .trim();
+    let key = decode_hex(hex).ok_or_else(|| anyhow!("Not hex"))?;
+    if key.len() != KEY_LEN {
+        bail!("Expected {KEY_LEN} bytes, got {}", key.len());
+    }
+    Ok(key)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|it| format!("{it:02x}")).collect()
+}
+
+/// Encrypts local files holding telemetry with AES-256-GCM, every sealed
+/// message is the random nonce followed by the ciphertext and its tag.
+pub struct Sealer {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl Sealer {
+    pub fn new(key: &[u8]) -> Result<Self> {
+        let key = UnboundKey::new(&AES_256_GCM, key).map_err(|_| anyhow!("Invalid key length"))?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    pub fn seal(&self, plain: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("Failed to generate a nonce"))?;
+        let mut in_out = plain.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut in_out,
+            )
+            .map_err(|_| anyhow!("Failed to encrypt"))?;
+        Ok([nonce.as_slice(), &in_out].concat())
+    }
+
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            bail!("Sealed data is too short");
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let nonce =
+            Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow!("Invalid nonce"))?;
+        let mut in_out = ciphertext.to_vec();
+        let plain = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut in_out)
+            .map_err(|_| anyhow!("Failed to decrypt, wrong key or corrupted data"))?;
+        Ok(plain.to_vec())
+    }
+
+    /// A whole file, e.g. a black box dump.
+    pub fn seal_file(&self, plain: &[u8]) -> Result<Vec<u8>> {
+        Ok([MAGIC, &self.seal(plain)?].concat())
+    }
+
+    /// One line of an appended file, e.g. the run ledger, as hex.
+    pub fn seal_line(&self, plain: &[u8]) -> Result<String> {
+        Ok(encode_hex(&self.seal(plain)?))
+    }
+
+    pub fn open_line(&self, line: &str) -> Result<Vec<u8>> {
+        let sealed = decode_hex(line.trim()).ok_or_else(|| anyhow!("Not a sealed line"))?;
+        self.open(&sealed)
+    }
+
+    /// Plain content of a file written by [`Self::seal_file`] or made of
+    /// [`Self::seal_line`] lines, which may be mixed with plain ones.
+    pub fn open_file(&self, content: &[u8]) -> Result<Vec<u8>> {
+        if let Some(sealed) = content.strip_prefix(MAGIC) {
+            return self.open(sealed);
+        }
+        let content = std::str::from_utf8(content).context("Not a sealed file")?;
+        let mut plain = Vec::with_capacity(content.len() / 2);
+        for line in content.lines() {
+            match self.open_line(line) {
+                Ok(line) => plain.extend_from_slice(&line),
+                Err(_) => plain.extend_from_slice(line.as_bytes()),
+            }
+            if plain.last() != Some(&b'\n') {
+                plain.push(b'\n');
+            }
+        }
+        Ok(plain)
+    }
+}
+
+/// Runs `psh decrypt <path>`.
+pub fn decrypt(path: &str, cfg: &AtRestConfig) -> Result<()> {
+    let sealer = open_sealer(cfg)?;
+    let content = fs::read(path).with_context(|| format!("Failed to read {path}"))?;
+    io::stdout().write_all(&sealer.open_file(&content)?)?;
+    Ok(())
+}
+
+/// The sealer of `cfg` without installing it, the key is never generated.
+pub fn open_sealer(cfg: &AtRestConfig) -> Result<Sealer> {
+    Sealer::new(&load_key(cfg, false)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sealer, decode_key};
+
+    #[test]
+    fn test_seal() {
+        let sealer = Sealer::new(&[7; 32]).unwrap();
+        let sealed = sealer.seal(b"cmdline=/usr/bin/sshd").unwrap();
+        assert!(!sealed.windows(4).any(|it| it == b"sshd"));
+        assert_eq!(sealer.open(&sealed).unwrap(), b"cmdline=/usr/bin/sshd");
+        // a fresh nonce every time
+        assert_ne!(sealer.seal(b"a").unwrap(), sealer.seal(b"a").unwrap());
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(sealer.open(&tampered).is_err());
+        assert!(Sealer::new(&[8; 32]).unwrap().open(&sealed).is_err());
+        assert!(Sealer::new(&[7; 16]).is_err());
+    }
+
+    #[test]
+    fn test_open_file() {
+        let sealer = Sealer::new(&[7; 32]).unwrap();
+        let file = sealer.seal_file(b"cpu usage=1\n").unwrap();
+        assert_eq!(sealer.open_file(&file).unwrap(), b"cpu usage=1\n");
+
+        let lines = format!(
+            "{}\n{{\"plain\":1}}\n",
+            sealer.seal_line(b"{\"a\":1}").unwrap()
+        );
+        assert_eq!(
+            sealer.open_file(lines.as_bytes()).unwrap(),
+            b"{\"a\":1}\n{\"plain\":1}\n"
+        );
+    }
+
+    #[test]
+    fn test_decode_key() {
+        assert_eq!(decode_key(&[1; 32]).unwrap(), [1; 32]);
+        let hex = format!("{}\n", "ab".repeat(32));
+        assert_eq!(decode_key(hex.as_bytes()).unwrap(), [0xab; 32]);
+        assert!(decode_key(b"abcd").is_err());
+        assert!(decode_key("zz".repeat(32).as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use influxdb_line_protocol::LineProtocolBuilder;
+use psh_system::{
+    cpu::CpuHandle, disk::DiskHandle, memory::MemoryHandle, network::NetworkHandle,
+    vmstat::VmstatHandle,
+};
+
+/// One sample of the black box, cumulative counters are kept raw so the
+/// reader can compute rates over any sub-window.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frame {
+    pub ns_ts: i64,
+    pub cpu_user_ms: u64,
+    pub cpu_system_ms: u64,
+    pub cpu_idle_ms: u64,
+    pub cpu_iowait_ms: u64,
+    pub procs_running: u32,
+    pub procs_blocked: u32,
+    /// in bytes
+    pub mem_available: u64,
+    /// in bytes
+    pub swap_used: u64,
+    /// in bytes
+    pub dirty: u64,
+    pub pgmajfault: i64,
+    pub oom_kill: i64,
+    pub net_recv_bytes: u64,
+    pub net_sent_bytes: u64,
+    pub disk_sectors_read: u64,
+    pub disk_sectors_written: u64,
+}
+
+impl Frame {
+    pub fn sample(interval: Duration) -> Result<Self> {
+        let interval = Some(interval);
+        let cpu = CpuHandle::new().stat(interval)?;
+        let mem = MemoryHandle::new().stat(interval)?;
+        let vmstat = VmstatHandle::new().stat(interval)?;
+        let net = NetworkHandle::new().stat(interval)?;
+        let disks = DiskHandle::new().stat(interval)?;
+
+        Ok(Self {
+            ns_ts: Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+            cpu_user_ms: cpu.total.user_ms(),
+            cpu_system_ms: cpu.total.system_ms(),
+            cpu_idle_ms: cpu.total.idle_ms(),
+            cpu_iowait_ms: cpu.total.iowait_ms().unwrap_or(0),
+            procs_running: cpu.procs_running.unwrap_or(0),
+            procs_blocked: cpu.procs_blocked.unwrap_or(0),
+            mem_available: mem.mem_available.unwrap_or(mem.mem_free),
+            swap_used: mem.swap_total.saturating_sub(mem.swap_free),
+            dirty: mem.dirty,
+            pgmajfault: vmstat.get("pgmajfault").copied().unwrap_or(0),
+            oom_kill: vmstat.get("oom_kill").copied().unwrap_or(0),
+            net_recv_bytes: net.values().map(|it| it.recv_bytes).sum(),
+            net_sent_bytes: net.values().map(|it| it.sent_bytes).sum(),
+            disk_sectors_read: disks.iter().map(|it| it.sectors_read).sum(),
+            disk_sectors_written: disks.iter().map(|it| it.sectors_written).sum(),
+        })
+    }
+
+    pub fn to_line_protocol(&self, reason: &str) -> Vec<u8> {
+        LineProtocolBuilder::new()
+            .measurement("psh_blackbox")
+            .tag("reason", reason)
+            .field("cpu_user_ms", self.cpu_user_ms)
+            .field("cpu_system_ms", self.cpu_system_ms)
+            .field("cpu_idle_ms", self.cpu_idle_ms)
+            .field("cpu_iowait_ms", self.cpu_iowait_ms)
+            .field("procs_running", u64::from(self.procs_running))
+            .field("procs_blocked", u64::from(self.procs_blocked))
+            .field("mem_available", self.mem_available)
+            .field("swap_used", self.swap_used)
+            .field("dirty", self.dirty)
+            .field("pgmajfault", self.pgmajfault)
+            .field("oom_kill", self.oom_kill)
+            .field("net_recv_bytes", self.net_recv_bytes)
+            .field("net_sent_bytes", self.net_sent_bytes)
+            .field("disk_sectors_read", self.disk_sectors_read)
+            .field("disk_sectors_written", self.disk_sectors_written)
+            .timestamp(self.ns_ts)
+            .close_line()
+            .build()
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+mod frame;
+
+use std::{
+    collections::VecDeque,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+pub use frame::Frame;
+
+use crate::{at_rest, config::BlackBoxConfig};
+
+static GLOBAL: OnceLock<BlackBox> = OnceLock::new();
+
+/// The recorder started by [`spawn`], `None` if it is disabled.
+pub fn global() -> Option<&'static BlackBox> {
+    GLOBAL.get()
+}
+
+/// Rolling window of key system metrics, dumped to disk when something
+/// interesting happens.
+#[derive(Clone)]
+pub struct BlackBox {
+    frames: Arc<Mutex<VecDeque<Frame>>>,
+    capacity: usize,
+    dump_dir: PathBuf,
+}
+
+impl BlackBox {
+    pub fn new(capacity: usize, dump_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            frames: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+            dump_dir: dump_dir.into(),
+        }
+    }
+
+    pub fn record(&self, frame: Frame) {
+        let mut frames = self.frames.lock().unwrap();
+        if frames.len() == self.capacity {
+            frames.pop_front();
+        }
+        frames.push_back(frame);
+    }
+
+    pub fn frames(&self) -> Vec<Frame> {
+        self.frames.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Writes the recorded window as line protocol, sealed if encryption at
+    /// rest is enabled, returns the file path.
+    pub fn dump(&self, reason: &str) -> Result<PathBuf> {
+        let frames = self.frames();
+        let bytes: Vec<u8> = frames
+            .iter()
+            .flat_map(|frame| frame.to_line_protocol(reason))
+            .collect();
+
+        fs::create_dir_all(&self.dump_dir)
+            .with_context(|| format!("Failed to create {}", self.dump_dir.display()))?;
+        let reason: String = reason
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let (bytes, extension) = match at_rest::global() {
+            Some(sealer) => (sealer.seal_file(&bytes)?, "lp.sealed"),
+            None => (bytes, "lp"),
+        };
+        let path = self.dump_dir.join(format!(
+            "blackbox-{}-{}.{extension}",
+            Utc::now().format("%Y%m%dT%H%M%S"),
+            reason
+        ));
+        fs::write(&path, bytes).with_context(|| format!("Failed to write {}", path.display()))?;
+        tracing::info!(
+            "Black box dumped {} frames to {}",
+            frames.len(),
+            path.display()
+        );
+        Ok(path)
+    }
+}
+
+/// Starts the sampling thread and installs the global recorder.
+pub fn spawn(cfg: &BlackBoxConfig) -> Result<()> {
+    let interval = Duration::from_millis(cfg.interval_ms.max(1));
+    let capacity = (cfg.window * 1000 / cfg.interval_ms.max(1)).max(1) as usize;
+    let blackbox = BlackBox::new(capacity, &cfg.dump_dir);
+    if GLOBAL.set(blackbox.clone()).is_err() {
+        anyhow::bail!("Black box recorder already started");
+    }
+
+    thread::spawn(move || {
+        let mut oom_kill = None;
+        loop {
+            match Frame::sample(interval) {
+                Ok(frame) => {
+                    let oom_detected = oom_kill.is_some_and(|prev| frame.oom_kill > prev);
+                    oom_kill = Some(frame.oom_kill);
+                    blackbox.record(frame);
+                    if oom_detected {
+                        if let Err(e) = blackbox.dump("oom") {
+                            tracing::error!("Black box: {e}");
+                        }
+                    }
+                }
+                Err(e) => tracing::warn!("Black box sampling failed: {e}"),
+            }
+            thread::sleep(interval);
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlackBox, Frame};
+
+    #[test]
+    fn test_blackbox_keeps_window() {
+        let blackbox = BlackBox::new(3, "/nonexistent");
+        for ns_ts in 0..5 {
+            blackbox.record(Frame {
+                ns_ts,
+                ..Default::default()
+            });
+        }
+        let ts: Vec<_> = blackbox.frames().iter().map(|it| it.ns_ts).collect();
+        assert_eq!(ts, [2, 3, 4]);
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+use chrono::DateTime;
+use serde::Serialize;
+use tonic::metadata::MetadataMap;
+
+/// How this binary was built, set by `build.rs`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub commit: &'static str,
+    /// RFC 3339, of `SOURCE_DATE_EPOCH` for reproducible builds
+    pub build_date: String,
+    pub target: String,
+    pub profile: &'static str,
+    /// cargo features the binary was built with
+    pub features: Vec<&'static str>,
+    pub wasmtime: &'static str,
+}
+
+pub fn get() -> BuildInfo {
+    let build_date = env!("PSH_BUILD_TIME")
+        .parse()
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+        .map_or_else(|| "unknown".to_string(), |it| it.to_rfc3339());
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: env!("PSH_GIT_COMMIT"),
+        build_date,
+        target: format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS),
+        profile: if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+        features: env!("PSH_FEATURES")
+            .split(',')
+            .filter(|it| !it.is_empty())
+            .collect(),
+        wasmtime: env!("PSH_WASMTIME_VERSION"),
+    }
+}
+
+impl BuildInfo {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// `HostInfoReq` has no fields for it, it goes along as request
+    /// metadata, one `x-psh-build-<field>` entry per field.
+    pub fn insert_into(&self, metadata: &mut MetadataMap) {
+        let entries = [
+            ("x-psh-build-version", self.version.to_string()),
+            ("x-psh-build-commit", self.commit.to_string()),
+            ("x-psh-build-date", self.build_date.clone()),
+            ("x-psh-build-target", self.target.clone()),
+            ("x-psh-build-profile", self.profile.to_string()),
+            ("x-psh-build-features", self.features.join(",")),
+            ("x-psh-build-wasmtime", self.wasmtime.to_string()),
+        ];
+        for (key, value) in entries {
+            if let Ok(value) = value.parse() {
+                metadata.insert(key, value);
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "psh {}", self.version)?;
+        writeln!(f, "commit: {}", self.commit)?;
+        writeln!(
+            f,
+            "built: {} ({}, {})",
+            self.build_date, self.target, self.profile
+        )?;
+        writeln!(f, "features: {}", self.features.join(", "))?;
+        write!(f, "wasmtime: {}", self.wasmtime)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::metadata::MetadataMap;
+
+    #[test]
+    fn test_build_info() {
+        let info = super::get();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_ne!(info.build_date, "unknown");
+
+        let json: serde_json::Value = serde_json::from_str(&info.to_json()).unwrap();
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        assert!(json["features"].is_array());
+
+        let mut metadata = MetadataMap::new();
+        info.insert_into(&mut metadata);
+        assert_eq!(metadata.get("x-psh-build-wasmtime").unwrap(), info.wasmtime);
+    }
+}
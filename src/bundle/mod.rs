@@ -0,0 +1,300 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fmt::Write as _,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, anyhow};
+use flate2::{Compression, write::GzEncoder};
+use host_op_system::redact::Redactor;
+use psh_system::{
+    cpu::CpuHandle, disk::DiskHandle, memory::MemoryHandle, network::NetworkHandle, os::OsHandle,
+};
+use tar::{Builder, Header};
+
+use crate::{at_rest, config::Config, ledger};
+
+/// Only the end of a log matters for a support case.
+const LOG_TAIL: u64 = 4 << 20;
+const METRICS_TIMEOUT: Duration = Duration::from_secs(5);
+/// Values of config keys named like these are always masked.
+const SECRETS: &[&str] = &["token", "key", "password", "secret"];
+
+/// Writes the support bundle of the daemon configured in `config_path`.
+///
+/// Every part is collected on a best effort basis, what could not be
+/// collected is listed in `errors.txt` of the bundle.
+pub fn run(config_path: &str, cfg: &Config, output: Option<&str>) -> Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let output = output.map_or_else(|| default_name(now), str::to_string);
+    let redactor = Redactor::new(&cfg.redact.fields, &cfg.redact.patterns, &cfg.redact.mask)?;
+
+    // logs and runs may hold anything, keep them to root
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&output)
+        .with_context(|| format!("Failed to create {output}"))?;
+    let mut bundle = Bundle {
+        tar: Builder::new(GzEncoder::new(file, Compression::default())),
+        mtime: now,
+        errors: String::new(),
+    };
+
+    bundle.add("config.toml", || {
+        redact_config(config_path, &redactor, &cfg.redact.mask)
+    });
+    bundle.add("logs/stdout.log", || {
+        tail_log(&cfg.daemon.stdout, &redactor)
+    });
+    bundle.add("logs/stderr.log", || {
+        tail_log(&cfg.daemon.stderr, &redactor)
+    });
+    bundle.add("metrics.prom", || metrics(cfg));
+    bundle.add("ledger.jsonl", || runs(cfg));
+    bundle.add("system.txt", system);
+
+    let errors = std::mem::take(&mut bundle.errors);
+    if !errors.is_empty() {
+        bundle.append("errors.txt", errors.as_bytes())?;
+    }
+    bundle.tar.into_inner()?.finish()?;
+    println!("{output}");
+    Ok(())
+}
+
+fn default_name(now: u64) -> String {
+    let host = nix::unistd::gethostname()
+        .map(|it| it.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| "unknown".to_string());
+    format!("psh-bundle-{host}-{now}.tar.gz")
+}
+
+struct Bundle {
+    tar: Builder<GzEncoder<File>>,
+    mtime: u64,
+    errors: String,
+}
+
+impl Bundle {
+    /// Appends what `collect` returns, its error goes to `errors.txt` instead.
+    fn add(&mut self, name: &str, collect: impl FnOnce() -> Result<String>) {
+        let result = collect().and_then(|it| self.append(name, it.as_bytes()));
+        if let Err(e) = result {
+            let _ = writeln!(self.errors, "{name}: {e:#}");
+        }
+    }
+
+    fn append(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o600);
+        header.set_mtime(self.mtime);
+        header.set_cksum();
+        self.tar.append_data(&mut header, name, data)?;
+        Ok(())
+    }
+}
+
+/// The config file as read by the daemon, with secrets masked.
+fn redact_config(path: &str, redactor: &Redactor, mask: &str) -> Result<String> {
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?;
+    let mut value: toml::Value = toml::from_str(&content)?;
+    redact_value(None, &mut value, redactor, mask);
+    Ok(toml::to_string_pretty(&value)?)
+}
+
+fn redact_value(name: Option<&str>, value: &mut toml::Value, redactor: &Redactor, mask: &str) {
+    match value {
+        toml::Value::String(it) => {
+            let Some(name) = name else {
+                *it = redactor.redact(it).into_owned();
+                return;
+            };
+            if is_secret(name) && !it.is_empty() {
+                *it = mask.to_string();
+            } else {
+                *it = redactor.redact_value(name, it).into_owned();
+            }
+        }
+        // items of an array are named after the array
+        toml::Value::Array(items) => {
+            for item in items {
+                redact_value(name, item, redactor, mask);
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                redact_value(Some(key), item, redactor, mask);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// e.g. `token`, `remote_token` or `key`, but not `key_file`.
+fn is_secret(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SECRETS
+        .iter()
+        .any(|it| name == *it || name.ends_with(&format!("_{it}")))
+}
+
+fn tail_log(path: &str, redactor: &Redactor) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {path}"))?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(LOG_TAIL)))?;
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+    let content = String::from_utf8_lossy(&content);
+    // the first line is likely cut
+    let lines = if len > LOG_TAIL {
+        content.split_once('\n').map_or("", |(_, rest)| rest)
+    } else {
+        &content
+    };
+    Ok(redactor.redact(lines).into_owned())
+}
+
+/// Self-metrics of the running daemon, served by its health endpoint.
+fn metrics(cfg: &Config) -> Result<String> {
+    if !cfg.health.enable {
+        return Err(anyhow!(
+            "[health] is disabled, self-metrics are only served there"
+        ));
+    }
+    let url = format!("http://{}/metrics", cfg.health.addr);
+    let response = reqwest::blocking::Client::builder()
+        .timeout(METRICS_TIMEOUT)
+        .build()?
+        .get(&url)
+        .send()
+        .and_then(|it| it.error_for_status())
+        .with_context(|| format!("Failed to get {url}"))?;
+    Ok(response.text()?)
+}
+
+fn runs(cfg: &Config) -> Result<String> {
+    if !cfg.ledger.enable {
+        return Err(anyhow!("[ledger] is disabled"));
+    }
+    let sealer = if cfg.at_rest.enable {
+        Some(at_rest::open_sealer(&cfg.at_rest)?)
+    } else {
+        None
+    };
+    let mut lines = String::new();
+    for run in ledger::read(&cfg.ledger.path, sealer.as_ref())? {
+        lines.push_str(&serde_json::to_string(&run)?);
+        lines.push('\n');
+    }
+    Ok(lines)
+}
+
+fn system() -> Result<String> {
+    let mut snapshot = format!("psh {}\n", env!("CARGO_PKG_VERSION"));
+    let mut section = |name: &str, info: Result<String>| {
+        let info = info.unwrap_or_else(|e| format!("unavailable: {e:#}"));
+        let _ = writeln!(snapshot, "\n# {name}\n{info}");
+    };
+    section(
+        "os",
+        OsHandle::new()
+            .info()
+            .map(|it| format!("{it:#?}"))
+            .map_err(Into::into),
+    );
+    section(
+        "cpu",
+        CpuHandle::new()
+            .info()
+            .map(|it| format!("{it:#?}"))
+            .map_err(Into::into),
+    );
+    section(
+        "memory",
+        MemoryHandle::new()
+            .stat(None)
+            .map(|it| format!("{it:#?}"))
+            .map_err(Into::into),
+    );
+    section(
+        "disks",
+        DiskHandle::new()
+            .stat(None)
+            .map(|it| format!("{it:#?}"))
+            .map_err(Into::into),
+    );
+    section(
+        "network",
+        NetworkHandle::new()
+            .stat(None)
+            .map(|it| format!("{it:#?}"))
+            .map_err(Into::into),
+    );
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use host_op_system::redact::Redactor;
+
+    use super::{is_secret, redact_value};
+
+    #[test]
+    fn test_redact_config() {
+        assert!(is_secret("token"));
+        assert!(is_secret("api_key"));
+        assert!(!is_secret("key_file"));
+        assert!(!is_secret("tokens_per_sec"));
+
+        let redactor = Redactor::new(&["passwd".to_string()], &[], "***").unwrap();
+        let mut value: toml::Value = toml::from_str(
+            r#"
+            [remote]
+            addr = "https://psh.example:9090"
+            token = "s3cr3t"
+            [at_rest]
+            key = ""
+            key_file = "/etc/psh/key"
+            [[daemon.components]]
+            env = { DB_PASSWD = "hunter2" }
+            "#,
+        )
+        .unwrap();
+        redact_value(None, &mut value, &redactor, "***");
+
+        assert_eq!(value["remote"]["token"].as_str(), Some("***"));
+        assert_eq!(
+            value["remote"]["addr"].as_str(),
+            Some("https://psh.example:9090")
+        );
+        // nothing to hide
+        assert_eq!(value["at_rest"]["key"].as_str(), Some(""));
+        assert_eq!(value["at_rest"]["key_file"].as_str(), Some("/etc/psh/key"));
+        assert_eq!(
+            value["daemon"]["components"][0]["env"]["DB_PASSWD"].as_str(),
+            Some("***")
+        );
+    }
+}
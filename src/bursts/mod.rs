@@ -0,0 +1,375 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+mod schedstat;
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail};
+use chrono::Utc;
+use schedstat::RunQueue;
+
+use crate::config::BurstsConfig;
+
+const MIN_INTERVAL_MS: u64 = 10;
+const MAX_INTERVAL_MS: u64 = 100;
+
+static GLOBAL: OnceLock<Bursts> = OnceLock::new();
+
+/// The sampler started by [`spawn`], `None` if it is disabled.
+pub fn global() -> Option<&'static Bursts> {
+    GLOBAL.get()
+}
+
+/// One CPU over one interval, 4 bytes as a window holds thousands of ticks
+/// of every CPU.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CpuSample {
+    /// busy share in basis points, 10000 is fully busy
+    pub util: u16,
+    /// tasks waiting for the CPU on average, in hundredths
+    pub queue: u16,
+}
+
+impl CpuSample {
+    /// From the run queue times at both ends of `elapsed` nanoseconds.
+    fn between(prev: &RunQueue, now: &RunQueue, elapsed: u64) -> Self {
+        let share = |prev: u64, now: u64, scale: u64| {
+            let delta = u128::from(now.saturating_sub(prev)) * u128::from(scale);
+            let share = delta / u128::from(elapsed.max(1));
+            u16::try_from(share).unwrap_or(u16::MAX)
+        };
+        Self {
+            util: share(prev.running, now.running, 10000).min(10000),
+            queue: share(prev.waiting, now.waiting, 100),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tick {
+    /// nanoseconds since the epoch, at the end of the interval
+    pub ns_ts: i64,
+    /// in the order of [`Window::cpus`]
+    pub cpus: Box<[CpuSample]>,
+}
+
+/// Ticks of the online CPUs, restarted when one comes or goes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Window {
+    pub cpus: Vec<u32>,
+    pub ticks: VecDeque<Tick>,
+}
+
+/// Consecutive ticks of a CPU at or above one of the thresholds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Burst {
+    pub cpu: u32,
+    /// nanoseconds since the epoch, at the start of the first tick
+    pub start_ns: i64,
+    pub duration_ms: u32,
+    pub peak_util: u16,
+    pub peak_queue: u16,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub cpu: u32,
+    pub samples: u32,
+    pub mean_util: u16,
+    pub p99_util: u16,
+    pub max_util: u16,
+    pub mean_queue: u16,
+    pub max_queue: u16,
+    pub bursts: u32,
+    /// time spent in bursts
+    pub burst_ms: u64,
+    pub longest_burst_ms: u32,
+}
+
+/// Per-CPU utilization and run queue length at a resolution of tens of
+/// milliseconds, the short bursts behind tail latency vanish in averages
+/// over a second.
+#[derive(Clone)]
+pub struct Bursts {
+    window: Arc<Mutex<Window>>,
+    capacity: usize,
+    interval_ms: u32,
+    /// in basis points
+    util_threshold: u16,
+    /// in hundredths of a task
+    queue_threshold: u16,
+}
+
+impl Bursts {
+    pub fn new(cfg: &BurstsConfig) -> Self {
+        let interval_ms = cfg.interval_ms.clamp(MIN_INTERVAL_MS, MAX_INTERVAL_MS);
+        Self {
+            window: Arc::default(),
+            capacity: (cfg.window * 1000 / interval_ms).max(1) as usize,
+            interval_ms: interval_ms as u32,
+            util_threshold: (cfg.util_threshold * 10000.0).clamp(0.0, 10000.0) as u16,
+            queue_threshold: (cfg.queue_threshold * 100.0).clamp(0.0, f64::from(u16::MAX)) as u16,
+        }
+    }
+
+    pub const fn interval_ms(&self) -> u32 {
+        self.interval_ms
+    }
+
+    pub fn record(&self, cpus: &[u32], tick: Tick) {
+        let mut window = self.window.lock().unwrap();
+        if window.cpus != cpus {
+            window.cpus = cpus.to_vec();
+            window.ticks.clear();
+        }
+        if window.ticks.len() == self.capacity {
+            window.ticks.pop_front();
+        }
+        window.ticks.push_back(tick);
+    }
+
+    /// Ticks after `since_ns`, oldest first.
+    pub fn window(&self, since_ns: i64) -> Window {
+        let window = self.window.lock().unwrap();
+        Window {
+            cpus: window.cpus.clone(),
+            ticks: window
+                .ticks
+                .iter()
+                .filter(|it| it.ns_ts > since_ns)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Bursts in the ticks after `since_ns`, by CPU then start.
+    pub fn bursts(&self, since_ns: i64) -> Vec<Burst> {
+        let window = self.window(since_ns);
+        (0..window.cpus.len())
+            .flat_map(|index| self.detect(&window, index))
+            .collect()
+    }
+
+    /// One summary per CPU of the ticks after `since_ns`.
+    pub fn summary(&self, since_ns: i64) -> Vec<Summary> {
+        let window = self.window(since_ns);
+        window
+            .cpus
+            .iter()
+            .enumerate()
+            .map(|(index, &cpu)| {
+                let mut utils: Vec<_> = window.ticks.iter().map(|it| it.cpus[index].util).collect();
+                let queues: Vec<_> = window.ticks.iter().map(|it| it.cpus[index].queue).collect();
+                let bursts = self.detect(&window, index);
+                utils.sort_unstable();
+                let samples = utils.len();
+                let mean = |values: &[u16]| {
+                    let sum: u64 = values.iter().copied().map(u64::from).sum();
+                    (sum / samples.max(1) as u64) as u16
+                };
+                Summary {
+                    cpu,
+                    samples: samples as u32,
+                    mean_util: mean(&utils),
+                    p99_util: utils
+                        .get((samples * 99).div_ceil(100).saturating_sub(1))
+                        .copied()
+                        .unwrap_or_default(),
+                    max_util: utils.last().copied().unwrap_or_default(),
+                    mean_queue: mean(&queues),
+                    max_queue: queues.iter().copied().max().unwrap_or_default(),
+                    bursts: bursts.len() as u32,
+                    burst_ms: bursts.iter().map(|it| u64::from(it.duration_ms)).sum(),
+                    longest_burst_ms: bursts
+                        .iter()
+                        .map(|it| it.duration_ms)
+                        .max()
+                        .unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    const fn is_burst(&self, sample: CpuSample) -> bool {
+        sample.util >= self.util_threshold || sample.queue >= self.queue_threshold
+    }
+
+    /// Bursts of the CPU at `index` of `window`, one still going on at the
+    /// end of the window included.
+    fn detect(&self, window: &Window, index: usize) -> Vec<Burst> {
+        let interval_ns = i64::from(self.interval_ms) * 1_000_000;
+        let mut bursts = vec![];
+        let mut current: Option<Burst> = None;
+        for tick in &window.ticks {
+            let sample = tick.cpus[index];
+            if !self.is_burst(sample) {
+                bursts.extend(current.take());
+                continue;
+            }
+            let burst = current.get_or_insert(Burst {
+                cpu: window.cpus[index],
+                start_ns: tick.ns_ts - interval_ns,
+                duration_ms: 0,
+                peak_util: 0,
+                peak_queue: 0,
+            });
+            burst.duration_ms += self.interval_ms;
+            burst.peak_util = burst.peak_util.max(sample.util);
+            burst.peak_queue = burst.peak_queue.max(sample.queue);
+        }
+        bursts.extend(current);
+        bursts
+    }
+}
+
+/// Starts the sampling thread and installs the global sampler.
+pub fn spawn(cfg: &BurstsConfig) -> Result<()> {
+    let mut prev = schedstat::read()?;
+    let bursts = Bursts::new(cfg);
+    if GLOBAL.set(bursts.clone()).is_err() {
+        bail!("Micro-burst sampler already started");
+    }
+    let interval = Duration::from_millis(u64::from(bursts.interval_ms));
+
+    thread::spawn(move || {
+        let mut last = Instant::now();
+        let mut next = last + interval;
+        loop {
+            thread::sleep(next.saturating_duration_since(Instant::now()));
+            // skips the ticks missed while the host was suspended
+            next = (next + interval).max(Instant::now());
+            let now = match schedstat::read() {
+                Ok(it) => it,
+                Err(e) => {
+                    tracing::warn!("Micro-burst sampling failed: {e:#}");
+                    continue;
+                }
+            };
+            let elapsed = last.elapsed();
+            last = Instant::now();
+            let cpus: Vec<_> = now.iter().map(|it| it.cpu).collect();
+            if prev.iter().map(|it| it.cpu).ne(cpus.iter().copied()) {
+                prev = now;
+                continue;
+            }
+            let elapsed = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+            let samples = prev
+                .iter()
+                .zip(&now)
+                .map(|(prev, now)| CpuSample::between(prev, now, elapsed))
+                .collect();
+            bursts.record(
+                &cpus,
+                Tick {
+                    ns_ts: Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+                    cpus: samples,
+                },
+            );
+            prev = now;
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Burst, Bursts, CpuSample, RunQueue, Tick};
+    use crate::config::BurstsConfig;
+
+    fn tick(ns_ts: i64, util: &[u16]) -> Tick {
+        Tick {
+            ns_ts,
+            cpus: util
+                .iter()
+                .map(|&util| CpuSample { util, queue: 0 })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_sample_between() {
+        let prev = RunQueue {
+            cpu: 0,
+            running: 1_000_000,
+            waiting: 0,
+        };
+        let now = RunQueue {
+            cpu: 0,
+            running: 6_000_000,
+            waiting: 30_000_000,
+        };
+        // 5ms busy and 1.5 tasks waiting on average over 20ms
+        assert_eq!(
+            CpuSample::between(&prev, &now, 20_000_000),
+            CpuSample {
+                util: 2500,
+                queue: 150
+            }
+        );
+    }
+
+    #[test]
+    fn test_bursts() {
+        let bursts = Bursts::new(&BurstsConfig {
+            interval_ms: 10,
+            window: 1,
+            util_threshold: 0.9,
+            ..Default::default()
+        });
+        let utils = [1000, 9500, 10000, 2000, 9000];
+        for (i, util) in utils.into_iter().enumerate() {
+            bursts.record(&[0, 1], tick((i as i64 + 1) * 10_000_000, &[util, 100]));
+        }
+
+        assert_eq!(
+            bursts.bursts(0),
+            [
+                Burst {
+                    cpu: 0,
+                    start_ns: 10_000_000,
+                    duration_ms: 20,
+                    peak_util: 10000,
+                    peak_queue: 0,
+                },
+                Burst {
+                    cpu: 0,
+                    start_ns: 40_000_000,
+                    duration_ms: 10,
+                    peak_util: 9000,
+                    peak_queue: 0,
+                },
+            ]
+        );
+        assert_eq!(bursts.bursts(30_000_000).len(), 1);
+
+        let summary = bursts.summary(0);
+        assert_eq!(summary[0].samples, 5);
+        assert_eq!(summary[0].mean_util, 6300);
+        assert_eq!(summary[0].max_util, 10000);
+        assert_eq!(summary[0].bursts, 2);
+        assert_eq!(summary[0].burst_ms, 30);
+        assert_eq!(summary[0].longest_burst_ms, 20);
+        assert_eq!(summary[1].bursts, 0);
+
+        // a CPU going offline restarts the window
+        bursts.record(&[0], tick(60_000_000, &[0]));
+        assert_eq!(bursts.window(0).ticks.len(), 1);
+    }
+}
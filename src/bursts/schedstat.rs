@@ -0,0 +1,84 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::fs;
+
+use anyhow::{Context, Result};
+
+/// Cumulative times of the run queue of one CPU, in nanoseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RunQueue {
+    pub cpu: u32,
+    /// tasks spent running on the CPU
+    pub running: u64,
+    /// tasks spent runnable, waiting for the CPU
+    pub waiting: u64,
+}
+
+pub fn read() -> Result<Vec<RunQueue>> {
+    let text = fs::read_to_string("/proc/schedstat").context("Failed to read /proc/schedstat")?;
+    Ok(parse(&text))
+}
+
+/// `cpu<N>` lines of `/proc/schedstat`, whose 7th and 8th fields are the
+/// running and waiting times, see `Documentation/scheduler/sched-stats.rst`.
+///
+/// The kernel adds the running time of a task when it is switched out, so a
+/// CPU-bound task that is never preempted reads as idle and then as a burst
+/// of its whole run at once. `/proc/stat` can't tell better at this
+/// resolution, it counts in ticks of 10 ms at best.
+pub fn parse(text: &str) -> Vec<RunQueue> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let cpu = fields.next()?.strip_prefix("cpu")?.parse().ok()?;
+            let mut fields = fields.skip(6).map(|it| it.parse().ok());
+            Some(RunQueue {
+                cpu,
+                running: fields.next()??,
+                waiting: fields.next()??,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RunQueue, parse};
+
+    #[test]
+    fn test_parse() {
+        let text = "version 15
+timestamp 4297330870
+cpu0 0 0 1403 547 655 299 2617401541 413279470 856
+domain0 00000003 1 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+cpu1 0 0 988 403 519 277 1290385112 96004418 585
+";
+        assert_eq!(
+            parse(text),
+            [
+                RunQueue {
+                    cpu: 0,
+                    running: 2617401541,
+                    waiting: 413279470,
+                },
+                RunQueue {
+                    cpu: 1,
+                    running: 1290385112,
+                    waiting: 96004418,
+                },
+            ]
+        );
+    }
+}
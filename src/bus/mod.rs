@@ -0,0 +1,272 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::{Result, bail};
+use bytes::Bytes;
+use chrono::Utc;
+use crossbeam::channel::{self, Receiver, Sender, TrySendError};
+
+use crate::{
+    config::{BusConfig, TopicConfig},
+    runtime::policy::matches,
+};
+
+static GLOBAL: OnceLock<Bus> = OnceLock::new();
+
+/// The bus installed by [`init`], `None` if it is disabled.
+pub fn global() -> Option<&'static Bus> {
+    GLOBAL.get()
+}
+
+pub fn init(cfg: &BusConfig) -> Result<()> {
+    if GLOBAL.set(Bus::new(cfg)).is_err() {
+        bail!("Bus already initialized");
+    }
+    Ok(())
+}
+
+/// A component publishing or subscribing, components only see the topics
+/// of their tenant.
+#[derive(Clone, Copy, Debug)]
+pub struct Peer<'a> {
+    pub tenant: Option<&'a str>,
+    pub component: &'a str,
+}
+
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub topic: String,
+    /// name of the component
+    pub publisher: Arc<str>,
+    pub payload: Bytes,
+    /// in nanoseconds since the epoch
+    pub ns_ts: u64,
+}
+
+struct Subscriber {
+    tenant: Option<String>,
+    component: String,
+    pattern: String,
+    tx: Sender<Message>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// Messages published after it was created on topics matching its
+/// pattern.
+pub struct Subscription {
+    rx: Receiver<Message>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    pub fn next(&self, timeout: Duration) -> Option<Message> {
+        self.rx.recv_timeout(timeout).ok()
+    }
+
+    /// Messages lost as the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Topics components publish on and subscribe to, like a sampler feeding
+/// an anomaly detector.
+///
+/// Each subscription has its own bounded queue, a slow subscriber loses new
+/// messages rather than holding up the publisher.
+pub struct Bus {
+    queue_size: usize,
+    max_message_size: usize,
+    topics: Vec<TopicConfig>,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+/// Topics are names like `cpu.load`, patterns may end with `*`.
+fn check_topic(topic: &str, pattern: bool) -> Result<()> {
+    let name = if pattern {
+        topic.strip_suffix('*').unwrap_or(topic)
+    } else {
+        topic
+    };
+    if (name.is_empty() && !pattern) || name.contains('*') || topic.len() > 256 {
+        bail!("Invalid topic {topic:?}");
+    }
+    Ok(())
+}
+
+impl Bus {
+    pub fn new(cfg: &BusConfig) -> Self {
+        Self {
+            queue_size: cfg.queue_size,
+            max_message_size: cfg.max_message_size,
+            topics: cfg.topics.clone(),
+            subscribers: Mutex::new(vec![]),
+        }
+    }
+
+    /// The first entry matching `topic`, any component may use topics
+    /// without one.
+    fn policy(&self, topic: &str) -> Option<&TopicConfig> {
+        self.topics.iter().find(|it| matches(&it.topic, topic))
+    }
+
+    fn allows(components: &[String], component: &str) -> bool {
+        components.is_empty() || components.iter().any(|it| it == component)
+    }
+
+    pub fn subscribe(&self, peer: Peer, pattern: &str) -> Result<Subscription> {
+        check_topic(pattern, true)?;
+        let queue_size = self
+            .policy(pattern)
+            .and_then(|it| it.queue_size)
+            .unwrap_or(self.queue_size);
+        let (tx, rx) = channel::bounded(queue_size);
+        let dropped = Arc::new(AtomicU64::new(0));
+        self.subscribers.lock().unwrap().push(Subscriber {
+            tenant: peer.tenant.map(str::to_string),
+            component: peer.component.to_string(),
+            pattern: pattern.to_string(),
+            tx,
+            dropped: Arc::clone(&dropped),
+        });
+        Ok(Subscription { rx, dropped })
+    }
+
+    /// Delivers `payload` to the subscriptions of `topic` the policy
+    /// allows, returns how many got it.
+    pub fn publish(&self, peer: Peer, topic: &str, payload: Vec<u8>) -> Result<u32> {
+        check_topic(topic, false)?;
+        if payload.len() > self.max_message_size {
+            bail!(
+                "Message of {} bytes is larger than {} bytes",
+                payload.len(),
+                self.max_message_size
+            );
+        }
+        let policy = self.policy(topic);
+        if policy.is_some_and(|it| !Self::allows(&it.publishers, peer.component)) {
+            bail!("{} may not publish on {topic}", peer.component);
+        }
+        let message = Message {
+            topic: topic.to_string(),
+            publisher: peer.component.into(),
+            payload: payload.into(),
+            ns_ts: Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64,
+        };
+        let mut delivered = 0;
+        self.subscribers.lock().unwrap().retain(|it| {
+            if it.tenant.as_deref() != peer.tenant
+                || !matches(&it.pattern, topic)
+                || policy.is_some_and(|p| !Self::allows(&p.subscribers, &it.component))
+            {
+                return !it.tx.is_disconnected();
+            }
+            match it.tx.try_send(message.clone()) {
+                Ok(()) => {
+                    delivered += 1;
+                    true
+                }
+                Err(TrySendError::Full(_)) => {
+                    it.dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                // the subscription is gone
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+        Ok(delivered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Bus, Peer};
+    use crate::config::{BusConfig, TopicConfig};
+
+    fn peer(component: &str) -> Peer<'_> {
+        Peer {
+            tenant: None,
+            component,
+        }
+    }
+
+    #[test]
+    fn test_publish_subscribe() {
+        let bus = Bus::new(&BusConfig {
+            queue_size: 1,
+            ..Default::default()
+        });
+        let all = bus.subscribe(peer("detector"), "cpu.*").unwrap();
+        let load = bus.subscribe(peer("detector"), "cpu.load").unwrap();
+        let other = Peer {
+            tenant: Some("acme"),
+            component: "detector",
+        };
+        let tenant = bus.subscribe(other, "*").unwrap();
+
+        assert_eq!(
+            bus.publish(peer("sampler"), "cpu.load", vec![1]).unwrap(),
+            2
+        );
+        assert_eq!(
+            bus.publish(peer("sampler"), "cpu.freq", vec![2]).unwrap(),
+            0
+        );
+        let message = load.next(Duration::ZERO).unwrap();
+        assert_eq!(message.publisher.as_ref(), "sampler");
+        assert_eq!(&message.payload[..], [1]);
+        assert_eq!(all.dropped(), 1);
+        assert!(tenant.next(Duration::ZERO).is_none());
+
+        drop(all);
+        assert_eq!(
+            bus.publish(peer("sampler"), "cpu.load", vec![3]).unwrap(),
+            1
+        );
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 2);
+        assert!(bus.publish(peer("sampler"), "cpu.*", vec![]).is_err());
+    }
+
+    #[test]
+    fn test_topic_policy() {
+        let bus = Bus::new(&BusConfig {
+            topics: vec![TopicConfig {
+                topic: "alerts.*".to_string(),
+                publishers: vec!["detector".to_string()],
+                subscribers: vec!["notifier".to_string()],
+                queue_size: None,
+            }],
+            ..Default::default()
+        });
+        let notifier = bus.subscribe(peer("notifier"), "alerts.*").unwrap();
+        let other = bus.subscribe(peer("other"), "alerts.*").unwrap();
+        assert!(bus.publish(peer("sampler"), "alerts.cpu", vec![]).is_err());
+        assert_eq!(
+            bus.publish(peer("detector"), "alerts.cpu", vec![]).unwrap(),
+            1
+        );
+        assert!(notifier.next(Duration::ZERO).is_some());
+        assert!(other.next(Duration::ZERO).is_none());
+    }
+}
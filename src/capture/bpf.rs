@@ -0,0 +1,114 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use anyhow::{Context, Result, bail};
+
+// BPF_MAXINSNS of the kernel
+const MAX_INSTRUCTIONS: usize = 4096;
+
+/// A classic BPF instruction, laid out as `struct sock_filter`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// Parses a program in the decimal form printed by `tcpdump -ddd`.
+///
+/// The first number is the instruction count, followed by one
+/// `code jt jf k` instruction per line. Instructions may also be separated
+/// by commas, as taken by `iptables -m bpf`.
+pub fn parse(program: &str) -> Result<Vec<Instruction>> {
+    let mut lines = program
+        .split(['\n', ','])
+        .map(str::trim)
+        .filter(|it| !it.is_empty());
+    let Some(count) = lines.next() else {
+        return Ok(vec![]);
+    };
+    let count: usize = count
+        .parse()
+        .with_context(|| format!("Invalid instruction count {count}"))?;
+    if count > MAX_INSTRUCTIONS {
+        bail!("Too many instructions: {count}");
+    }
+
+    let instructions = lines
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [code, jt, jf, k] = fields[..] else {
+                bail!("Invalid instruction {line}");
+            };
+            let invalid = || format!("Invalid instruction {line}");
+            Ok(Instruction {
+                code: code.parse().with_context(invalid)?,
+                jt: jt.parse().with_context(invalid)?,
+                jf: jf.parse().with_context(invalid)?,
+                k: k.parse().with_context(invalid)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if instructions.len() != count {
+        bail!("Expected {count} instructions, got {}", instructions.len());
+    }
+    Ok(instructions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Instruction, parse};
+
+    #[test]
+    fn test_parse() {
+        // tcpdump -ddd ip
+        let program = "4\n40 0 0 12\n21 0 1 2048\n6 0 0 262144\n6 0 0 0\n";
+        let expected = vec![
+            Instruction {
+                code: 40,
+                jt: 0,
+                jf: 0,
+                k: 12,
+            },
+            Instruction {
+                code: 21,
+                jt: 0,
+                jf: 1,
+                k: 2048,
+            },
+            Instruction {
+                code: 6,
+                jt: 0,
+                jf: 0,
+                k: 262144,
+            },
+            Instruction {
+                code: 6,
+                jt: 0,
+                jf: 0,
+                k: 0,
+            },
+        ];
+        assert_eq!(parse(program).unwrap(), expected);
+        assert_eq!(
+            parse("4,40 0 0 12,21 0 1 2048,6 0 0 262144,6 0 0 0").unwrap(),
+            expected
+        );
+        assert!(parse("").unwrap().is_empty());
+        assert!(parse("2\n40 0 0 12\n").is_err());
+        assert!(parse("1\n40 0 0\n").is_err());
+    }
+}
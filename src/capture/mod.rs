@@ -0,0 +1,326 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+pub mod bpf;
+
+use std::{
+    ffi::CString,
+    fs, io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    sync::OnceLock,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, bail};
+use bpf::Instruction;
+
+use crate::config::CaptureConfig;
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+// interfaces without link layer header, e.g. tun devices
+const ARPHRD_NONE: u32 = 65534;
+
+static GLOBAL: OnceLock<Capturer> = OnceLock::new();
+
+/// The capture policy installed by [`init`], `None` if capture is disabled.
+pub fn global() -> Option<&'static Capturer> {
+    GLOBAL.get()
+}
+
+pub fn init(cfg: &CaptureConfig) -> Result<()> {
+    if GLOBAL.set(Capturer { cfg: cfg.clone() }).is_err() {
+        bail!("Capturer already initialized");
+    }
+    Ok(())
+}
+
+/// What a component asks to capture, zero limits mean the configured maximum.
+pub struct Request {
+    pub device: String,
+    pub filter: Vec<Instruction>,
+    pub snaplen: u32,
+    pub max_packets: u32,
+    pub timeout: Duration,
+    pub promiscuous: bool,
+}
+
+/// Captures packets on `AF_PACKET` sockets within the configured limits.
+pub struct Capturer {
+    cfg: CaptureConfig,
+}
+
+impl Capturer {
+    /// Captures until the packet, size or time limit is hit, returns a pcap file.
+    pub fn capture(&self, req: &Request) -> Result<Vec<u8>> {
+        if !self.cfg.interfaces.is_empty() && !self.cfg.interfaces.contains(&req.device) {
+            bail!("Capture on {} is not allowed", req.device);
+        }
+        let limit = |requested: u32, max: u32| match requested {
+            0 => max,
+            n => n.min(max),
+        };
+        let snaplen = limit(req.snaplen, self.cfg.max_snaplen).max(1);
+        let max_packets = limit(req.max_packets, self.cfg.max_packets);
+        let max_duration = Duration::from_secs(self.cfg.max_duration);
+        let timeout = match req.timeout {
+            Duration::ZERO => max_duration,
+            t => t.min(max_duration),
+        };
+
+        let ifindex = {
+            let name = CString::new(req.device.as_str())?;
+            match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+                0 => bail!("Unknown interface {}", req.device),
+                n => n,
+            }
+        };
+        let socket = open(ifindex, &req.filter, req.promiscuous)?;
+
+        let mut pcap = Pcap::new(snaplen, link_type(&req.device));
+        let mut buf = vec![0; snaplen as usize];
+        let mut packets = 0;
+        let deadline = Instant::now() + timeout;
+        // a record needs its header and at least one byte of data to fit
+        while packets < max_packets && pcap.room(self.cfg.max_bytes) > 0 {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            set_recv_timeout(&socket, left)?;
+            // with MSG_TRUNC the length on the wire is returned, even if more than `buf`
+            let len = unsafe {
+                libc::recv(
+                    socket.as_raw_fd(),
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                    libc::MSG_TRUNC,
+                )
+            };
+            if len < 0 {
+                let err = io::Error::last_os_error();
+                match err.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => continue,
+                    _ => return Err(err).context("Failed to receive packet"),
+                }
+            }
+            let len = len as usize;
+            // the last record is cut short so the file stays within max_bytes
+            let captured = len.min(buf.len()).min(pcap.room(self.cfg.max_bytes));
+            pcap.packet(SystemTime::now(), &buf[..captured], len);
+            packets += 1;
+        }
+        Ok(pcap.finish())
+    }
+}
+
+fn open(ifindex: u32, filter: &[Instruction], promiscuous: bool) -> Result<OwnedFd> {
+    let protocol = (libc::ETH_P_ALL as u16).to_be();
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_PACKET,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            protocol as i32,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error()).context("Failed to open packet socket");
+    }
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // the socket sees every interface until bound, drop everything until
+    // then and drain what was queued before, as libpcap does
+    let drop_all = [Instruction {
+        code: 6, // BPF_RET | BPF_K
+        jt: 0,
+        jf: 0,
+        k: 0,
+    }];
+    attach_filter(&socket, &drop_all)?;
+
+    let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+    addr.sll_family = libc::AF_PACKET as u16;
+    addr.sll_protocol = protocol;
+    addr.sll_ifindex = ifindex as i32;
+    let ret = unsafe {
+        libc::bind(
+            socket.as_raw_fd(),
+            (&raw const addr).cast(),
+            mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error()).context("Failed to bind packet socket");
+    }
+
+    if promiscuous {
+        // dropped with the socket
+        let mreq = libc::packet_mreq {
+            mr_ifindex: ifindex as i32,
+            mr_type: libc::PACKET_MR_PROMISC as u16,
+            mr_alen: 0,
+            mr_address: [0; 8],
+        };
+        setsockopt(
+            &socket,
+            libc::SOL_PACKET,
+            libc::PACKET_ADD_MEMBERSHIP,
+            &mreq,
+        )
+        .context("Failed to enable promiscuous mode")?;
+    }
+
+    let mut byte = 0u8;
+    while unsafe {
+        libc::recv(
+            socket.as_raw_fd(),
+            (&raw mut byte).cast(),
+            1,
+            libc::MSG_DONTWAIT,
+        )
+    } >= 0
+    {}
+
+    if filter.is_empty() {
+        setsockopt(&socket, libc::SOL_SOCKET, libc::SO_DETACH_FILTER, &0i32)
+            .context("Failed to detach filter")?;
+    } else {
+        attach_filter(&socket, filter)?;
+    }
+
+    Ok(socket)
+}
+
+fn attach_filter(socket: &OwnedFd, filter: &[Instruction]) -> Result<()> {
+    let prog = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_ptr().cast_mut().cast(),
+    };
+    setsockopt(socket, libc::SOL_SOCKET, libc::SO_ATTACH_FILTER, &prog)
+        .context("Failed to attach filter")
+}
+
+fn set_recv_timeout(socket: &OwnedFd, timeout: Duration) -> Result<()> {
+    // a zero timeval blocks forever
+    let timeout = timeout.max(Duration::from_millis(1));
+    let tv = libc::timeval {
+        tv_sec: timeout.as_secs() as _,
+        tv_usec: timeout.subsec_micros() as _,
+    };
+    setsockopt(socket, libc::SOL_SOCKET, libc::SO_RCVTIMEO, &tv)
+        .context("Failed to set receive timeout")
+}
+
+fn setsockopt<T>(socket: &OwnedFd, level: i32, name: i32, value: &T) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            (value as *const T).cast(),
+            mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Raw sockets pass the link layer header as is, which is ethernet for
+/// nearly everything but tun devices.
+fn link_type(device: &str) -> u32 {
+    let ty = fs::read_to_string(format!("/sys/class/net/{device}/type"))
+        .ok()
+        .and_then(|it| it.trim().parse::<u32>().ok());
+    match ty {
+        Some(ARPHRD_NONE) => LINKTYPE_RAW,
+        _ => LINKTYPE_ETHERNET,
+    }
+}
+
+// size of a pcap record header
+const RECORD_HEADER: usize = 16;
+
+/// Writes the classic pcap file format, microsecond timestamps.
+struct Pcap(Vec<u8>);
+
+impl Pcap {
+    fn new(snaplen: u32, link_type: u32) -> Self {
+        let mut bytes = Vec::with_capacity(24);
+        bytes.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&4u16.to_le_bytes());
+        // thiszone and sigfigs, always 0
+        bytes.extend_from_slice(&[0; 8]);
+        bytes.extend_from_slice(&snaplen.to_le_bytes());
+        bytes.extend_from_slice(&link_type.to_le_bytes());
+        Self(bytes)
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Bytes of packet data the next record can hold within `max_bytes`.
+    fn room(&self, max_bytes: usize) -> usize {
+        max_bytes.saturating_sub(self.len() + RECORD_HEADER)
+    }
+
+    fn packet(&mut self, time: SystemTime, data: &[u8], orig_len: usize) {
+        let time = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.0
+            .extend_from_slice(&(time.as_secs() as u32).to_le_bytes());
+        self.0
+            .extend_from_slice(&time.subsec_micros().to_le_bytes());
+        self.0.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        self.0.extend_from_slice(&(orig_len as u32).to_le_bytes());
+        self.0.extend_from_slice(data);
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{LINKTYPE_ETHERNET, Pcap};
+
+    #[test]
+    fn test_pcap() {
+        let mut pcap = Pcap::new(96, LINKTYPE_ETHERNET);
+        assert_eq!(pcap.len(), 24);
+        let time = UNIX_EPOCH + Duration::new(1, 2000);
+        pcap.packet(time, &[0xff; 4], 100);
+        let bytes = pcap.finish();
+        assert_eq!(&bytes[..4], &[0xd4, 0xc3, 0xb2, 0xa1]);
+        assert_eq!(&bytes[16..20], &96u32.to_le_bytes());
+        assert_eq!(&bytes[24..28], &1u32.to_le_bytes());
+        assert_eq!(&bytes[28..32], &2u32.to_le_bytes());
+        assert_eq!(&bytes[32..36], &4u32.to_le_bytes());
+        assert_eq!(&bytes[36..40], &100u32.to_le_bytes());
+        assert_eq!(bytes.len(), 44);
+    }
+
+    #[test]
+    fn test_room() {
+        let pcap = Pcap::new(96, LINKTYPE_ETHERNET);
+        assert_eq!(pcap.room(100), 60);
+        assert_eq!(pcap.room(40), 0);
+        assert_eq!(pcap.room(10), 0);
+    }
+}
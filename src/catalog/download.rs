@@ -0,0 +1,153 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use reqwest::blocking::Client;
+
+use super::{read_capped, sha256_hex};
+use crate::config::{CatalogConfig, ComponentConfig};
+
+/// Components downloaded from `https://` paths, `<dir>/<sha256>.wasm`.
+///
+/// The digest pins the component, a file already downloaded is only
+/// checked again.
+pub struct Downloads {
+    dir: PathBuf,
+    client: Client,
+    max_size: u64,
+}
+
+impl Downloads {
+    pub fn new(cfg: &CatalogConfig) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(cfg.timeout))
+            .https_only(true)
+            .build()?;
+        Ok(Self {
+            dir: cfg.download_dir.clone().into(),
+            client,
+            max_size: cfg.max_size,
+        })
+    }
+
+    /// Path of the component at `url` whose digest is `sha256`, downloads
+    /// it unless it already is.
+    pub fn fetch(&self, url: &str, sha256: &str) -> Result<PathBuf> {
+        if !url.starts_with("https://") {
+            bail!("Only https:// components can be downloaded, not {url}");
+        }
+        if sha256.len() != 64 || !sha256.bytes().all(|it| it.is_ascii_hexdigit()) {
+            bail!("Invalid sha256 of {url}, expected 64 hex digits");
+        }
+        let path = self
+            .dir
+            .join(format!("{}.wasm", sha256.to_ascii_lowercase()));
+        if let Ok(binary) = fs::read(&path) {
+            if sha256_hex(&binary).eq_ignore_ascii_case(sha256) {
+                return Ok(path);
+            }
+            tracing::warn!("{} is corrupted, downloading it again", path.display());
+        }
+
+        let response = self.client.get(url).send()?.error_for_status()?;
+        let len = response.content_length();
+        let binary = read_capped(response, len, self.max_size)
+            .with_context(|| format!("Failed to download {url}"))?;
+        let digest = sha256_hex(&binary);
+        if !digest.eq_ignore_ascii_case(sha256) {
+            bail!("Digest mismatch of {url}, expected {sha256} got {digest}");
+        }
+        store(&path, &binary)?;
+        Ok(path)
+    }
+}
+
+// write then rename, a partial download must never look verified
+fn store(path: &Path, binary: &[u8]) -> Result<()> {
+    let dir = path.parent().expect("joined in fetch");
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let tmp = path.with_extension("part");
+    fs::write(&tmp, binary).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Replaces `https://` paths of `components` by the verified downloads,
+/// fails if one can't be downloaded or doesn't match its `sha256`.
+///
+/// The URL stays the name of the component unless it has one.
+pub fn download_components(components: &mut [ComponentConfig], cfg: &CatalogConfig) -> Result<()> {
+    let mut remote = components
+        .iter_mut()
+        .filter(|it| it.path.contains("://"))
+        .peekable();
+    if remote.peek().is_none() {
+        return Ok(());
+    }
+    let downloads = Downloads::new(cfg)?;
+    for component in remote {
+        let Some(sha256) = &component.sha256 else {
+            bail!(
+                "Component {} needs a sha256 to be downloaded",
+                component.path
+            );
+        };
+        let path = downloads.fetch(&component.path, sha256)?;
+        let url = std::mem::replace(&mut component.path, path.to_string_lossy().into_owned());
+        component.name.get_or_insert(url);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Downloads;
+    use crate::config::CatalogConfig;
+
+    #[test]
+    fn test_fetch_verified_download() {
+        let dir = std::env::temp_dir().join(format!("psh-downloads-{}", std::process::id()));
+        let cfg = CatalogConfig {
+            download_dir: dir.to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+        let downloads = Downloads::new(&cfg).unwrap();
+        // sha256 of "wasm"
+        let sha256 = "336154bf67f765f8f75d16a0accee61b5ee5f6a75b2a2905703df913bd550f3e";
+        let url = "https://example.invalid/c.wasm";
+
+        assert!(
+            downloads
+                .fetch("http://example.invalid/c.wasm", sha256)
+                .is_err()
+        );
+        assert!(downloads.fetch(url, "336154bf").is_err());
+
+        // already downloaded, no request is made
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{sha256}.wasm"));
+        fs::write(&path, b"wasm").unwrap();
+        assert_eq!(downloads.fetch(url, sha256).unwrap(), path);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+mod download;
+
+use std::{
+    cmp::Ordering,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+
+pub use self::download::{Downloads, download_components};
+use crate::{args::ComponentCommand, config::CatalogConfig};
+
+/// A component of the catalog.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    /// e.g. `1.2.0`
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    /// host ops the component needs, e.g. `perf`, `system`, `capture`
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// hex encoded digest of the component binary
+    pub sha256: String,
+}
+
+#[derive(Deserialize)]
+struct EntryList {
+    components: Vec<Entry>,
+}
+
+/// Client of a component catalog.
+///
+/// `GET <url>/v1/components?q=<query>` lists the versions of components
+/// whose name or description matches, `GET <url>/v1/components/<name>/<version>`
+/// returns the component binary.
+pub struct Catalog {
+    url: String,
+    token: String,
+    client: Client,
+    max_size: u64,
+}
+
+impl Catalog {
+    pub fn new(cfg: &CatalogConfig, token: &str) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(cfg.timeout))
+            .build()?;
+        Ok(Self {
+            url: cfg.url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            client,
+            max_size: cfg.max_size,
+        })
+    }
+
+    pub fn search(&self, query: &str) -> Result<Vec<Entry>> {
+        let list: EntryList = self
+            .client
+            .get(format!("{}/v1/components", self.url))
+            .query(&[("q", query)])
+            .bearer_auth(&self.token)
+            .send()?
+            .error_for_status()?
+            .json()
+            .context("Invalid catalog response")?;
+        Ok(list.components)
+    }
+
+    pub fn download(&self, entry: &Entry) -> Result<Vec<u8>> {
+        let response = self
+            .client
+            .get(format!(
+                "{}/v1/components/{}/{}",
+                self.url, entry.name, entry.version
+            ))
+            .bearer_auth(&self.token)
+            .send()?
+            .error_for_status()?;
+        let len = response.content_length();
+        read_capped(response, len, self.max_size)
+            .with_context(|| format!("Failed to download {}@{}", entry.name, entry.version))
+    }
+
+    /// The given version of `name`, the latest if `None`.
+    pub fn find(&self, name: &str, version: Option<&str>) -> Result<Entry> {
+        let found = self
+            .search(name)?
+            .into_iter()
+            .filter(|it| it.name == name && version.is_none_or(|v| it.version == v))
+            .max_by(|a, b| compare_versions(&a.version, &b.version));
+        match (found, version) {
+            (Some(entry), _) => Ok(entry),
+            (None, Some(version)) => bail!("No version {version} of {name} in the catalog"),
+            (None, None) => bail!("No component {name} in the catalog"),
+        }
+    }
+}
+
+/// Orders dotted versions by their numeric parts, `1.10` after `1.9`.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |v: &str| -> Vec<u64> {
+        v.trim_start_matches('v')
+            .split(['.', '-', '+'])
+            .map(|it| it.parse().unwrap_or(0))
+            .collect()
+    };
+    parts(a).cmp(&parts(b)).then_with(|| a.cmp(b))
+}
+
+/// Installed components, `<dir>/<name>/<version>.wasm` next to its catalog
+/// entry in `<version>.json`.
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Where the binary of `entry` is installed to.
+    pub fn path(&self, entry: &Entry) -> PathBuf {
+        self.dir
+            .join(&entry.name)
+            .join(format!("{}.wasm", entry.version))
+    }
+
+    /// Checks `binary` against the digest of `entry` and installs it.
+    pub fn add(&self, entry: &Entry, binary: &[u8]) -> Result<PathBuf> {
+        if [&entry.name, &entry.version]
+            .iter()
+            .any(|it| it.is_empty() || it.contains(['/', '\\']) || it.starts_with('.'))
+        {
+            bail!("Invalid component {}@{}", entry.name, entry.version);
+        }
+        let digest = sha256_hex(binary);
+        if !digest.eq_ignore_ascii_case(&entry.sha256) {
+            bail!(
+                "Digest mismatch of {}@{}, expected {} got {digest}",
+                entry.name,
+                entry.version,
+                entry.sha256
+            );
+        }
+        let path = self.path(entry);
+        let dir = path.parent().expect("joined above");
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        // write then rename, a partial download must never look installed
+        let tmp = path.with_extension("part");
+        fs::write(&tmp, binary).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path)?;
+        fs::write(
+            path.with_extension("json"),
+            serde_json::to_vec_pretty(entry)?,
+        )?;
+        Ok(path)
+    }
+
+    /// Installed components ordered by name and version.
+    pub fn list(&self) -> Result<Vec<Entry>> {
+        let mut entries = vec![];
+        if !self.dir.exists() {
+            return Ok(entries);
+        }
+        for dir in fs::read_dir(&self.dir)? {
+            let dir = dir?.path();
+            if !dir.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(&dir)? {
+                let file = file?.path();
+                if file.extension().is_some_and(|it| it == "json")
+                    && file.with_extension("wasm").exists()
+                {
+                    entries.push(read_entry(&file)?);
+                }
+            }
+        }
+        entries.sort_by(|a, b| {
+            a.name
+                .cmp(&b.name)
+                .then_with(|| compare_versions(&a.version, &b.version))
+        });
+        Ok(entries)
+    }
+
+    /// The installed version of `name`, the latest if `None`.
+    pub fn installed(&self, name: &str, version: Option<&str>) -> Result<Entry> {
+        let found = self
+            .list()?
+            .into_iter()
+            .rev()
+            .find(|it| it.name == name && version.is_none_or(|v| it.version == v));
+        match (found, version) {
+            (Some(entry), _) => Ok(entry),
+            (None, Some(version)) => bail!("Version {version} of {name} is not installed"),
+            (None, None) => bail!("Component {name} is not installed"),
+        }
+    }
+}
+
+/// Hex encoded SHA-256 digest of `binary`.
+fn sha256_hex(binary: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, binary)
+        .as_ref()
+        .iter()
+        .map(|it| format!("{it:02x}"))
+        .collect()
+}
+
+/// Reads a response body of `len` bytes if known, fails before buffering
+/// more than `max` bytes of it.
+fn read_capped(body: impl Read, len: Option<u64>, max: u64) -> Result<Vec<u8>> {
+    if let Some(len) = len.filter(|it| *it > max) {
+        bail!("Component of {len} bytes exceeds the limit of {max}");
+    }
+    let mut binary = Vec::new();
+    body.take(max.saturating_add(1)).read_to_end(&mut binary)?;
+    if binary.len() as u64 > max {
+        bail!("Component exceeds the limit of {max} bytes");
+    }
+    Ok(binary)
+}
+
+fn read_entry(path: &Path) -> Result<Entry> {
+    let json = fs::read(path)?;
+    serde_json::from_slice(&json).with_context(|| format!("Invalid entry {}", path.display()))
+}
+
+fn print_entries(entries: &[Entry]) {
+    for it in entries {
+        println!("{} {}", it.name, it.version);
+        if !it.description.is_empty() {
+            println!("    {}", it.description);
+        }
+        if !it.capabilities.is_empty() {
+            println!("    needs: {}", it.capabilities.join(", "));
+        }
+    }
+}
+
+/// Runs `psh component <command>`.
+pub fn run(command: &ComponentCommand, cfg: &CatalogConfig, token: &str) -> Result<()> {
+    let store = Store::new(&cfg.store_dir);
+    match command {
+        ComponentCommand::Search { query } => {
+            let catalog = Catalog::new(cfg, token)?;
+            print_entries(&catalog.search(query.as_deref().unwrap_or_default())?);
+        }
+        ComponentCommand::Install { name, version } => {
+            let catalog = Catalog::new(cfg, token)?;
+            let entry = catalog.find(name, version.as_deref())?;
+            let binary = catalog.download(&entry)?;
+            let path = store.add(&entry, &binary)?;
+            println!(
+                "Installed {}@{} to {}",
+                entry.name,
+                entry.version,
+                path.display()
+            );
+            if !entry.capabilities.is_empty() {
+                println!("It needs: {}", entry.capabilities.join(", "));
+            }
+        }
+        ComponentCommand::List => print_entries(&store.list()?),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cmp::Ordering, fs};
+
+    use super::{Entry, Store, compare_versions, read_capped};
+
+    #[test]
+    fn test_read_capped() {
+        assert_eq!(read_capped(&b"wasm"[..], Some(4), 4).unwrap(), b"wasm");
+        // a missing or lying length does not get past the limit
+        assert!(read_capped(&b"wasm"[..], None, 3).is_err());
+        assert!(read_capped(&b"wasm"[..], Some(3), 3).is_err());
+        assert!(read_capped(&b""[..], Some(5), 4).is_err());
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("1.10.0", "1.9.3"), Ordering::Greater);
+        assert_eq!(compare_versions("v2", "1.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0", "1.0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_store() {
+        let dir = std::env::temp_dir().join(format!("psh-catalog-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let store = Store::new(&dir);
+        let entry = |version: &str, sha256: &str| Entry {
+            name: "cpu-top".to_string(),
+            version: version.to_string(),
+            description: String::new(),
+            capabilities: vec!["system".to_string()],
+            sha256: sha256.to_string(),
+        };
+        // sha256 of "wasm"
+        let sha256 = "336154bf67f765f8f75d16a0accee61b5ee5f6a75b2a2905703df913bd550f3e";
+        assert!(store.add(&entry("1.0", "00"), b"wasm").is_err());
+        assert!(store.add(&entry("../1.0", sha256), b"wasm").is_err());
+        let path = store.add(&entry("1.10", sha256), b"wasm").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"wasm");
+        store.add(&entry("1.9", sha256), b"wasm").unwrap();
+        let versions: Vec<_> = store
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|it| it.version)
+            .collect();
+        assert_eq!(versions, ["1.9", "1.10"]);
+        assert_eq!(store.installed("cpu-top", None).unwrap().version, "1.10");
+        assert_eq!(
+            store.installed("cpu-top", Some("1.9")).unwrap().version,
+            "1.9"
+        );
+        assert!(store.installed("cpu-top", Some("2.0")).is_err());
+        assert!(store.installed("mem-top", None).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
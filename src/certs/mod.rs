@@ -0,0 +1,192 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+mod x509;
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::config::CertsConfig;
+
+static GLOBAL: OnceLock<Scanner> = OnceLock::new();
+
+/// The scanner installed by [`init`], `None` if certificate scanning is disabled.
+pub fn global() -> Option<&'static Scanner> {
+    GLOBAL.get()
+}
+
+pub fn init(cfg: &CertsConfig) -> Result<()> {
+    if GLOBAL.set(Scanner::new(cfg)).is_err() {
+        bail!("Certificate scanner already initialized");
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Certificate {
+    /// file path, or `host:port` of a TLS endpoint
+    pub source: String,
+    /// e.g. `O=Example, CN=example.com`
+    pub subject: String,
+    pub issuer: String,
+    /// hex encoded
+    pub serial: String,
+    /// dns names and ip addresses
+    pub sans: Vec<String>,
+    /// in seconds since the epoch
+    pub not_before: i64,
+    /// in seconds since the epoch
+    pub not_after: i64,
+}
+
+impl Certificate {
+    fn new(source: &str, cert: x509::Certificate) -> Self {
+        Self {
+            source: source.to_string(),
+            subject: cert.subject,
+            issuer: cert.issuer,
+            serial: cert.serial,
+            sans: cert.sans,
+            not_before: cert.not_before,
+            not_after: cert.not_after,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Scan {
+    pub certificates: Vec<Certificate>,
+    /// path and why it could not be read, a bad file doesn't fail the scan
+    pub errors: Vec<(String, String)>,
+}
+
+/// Reads certificates of the configured paths, and of TLS endpoints
+/// through the prober.
+pub struct Scanner {
+    paths: Vec<PathBuf>,
+    max_file_size: u64,
+}
+
+impl Scanner {
+    pub fn new(cfg: &CertsConfig) -> Self {
+        Self {
+            paths: cfg.paths.iter().map(PathBuf::from).collect(),
+            max_file_size: cfg.max_file_size,
+        }
+    }
+
+    /// Certificates of all PEM bundles and DER files of the paths, files
+    /// without certificates, like keys, are skipped.
+    pub fn scan(&self) -> Scan {
+        let mut scan = Scan::default();
+        for path in &self.paths {
+            self.walk(path, &mut scan);
+        }
+        scan
+    }
+
+    /// The chain sent by a TLS endpoint, it is not verified. Subject to
+    /// the probe targets and rate limit.
+    pub fn fetch(
+        &self,
+        host: &str,
+        port: u16,
+        server_name: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Vec<Certificate>> {
+        let prober = crate::probe::global().context("Probes are disabled")?;
+        let source = format!("{host}:{port}");
+        prober
+            .peer_certificates(host, port, server_name, timeout)?
+            .iter()
+            .map(|der| x509::parse(der).map(|it| Certificate::new(&source, it)))
+            .collect()
+    }
+
+    fn walk(&self, path: &Path, scan: &mut Scan) {
+        let display = path.to_string_lossy().into_owned();
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => return scan.errors.push((display, e.to_string())),
+        };
+        if metadata.is_dir() {
+            let mut entries = match fs::read_dir(path) {
+                Ok(entries) => entries
+                    .filter_map(|it| it.ok().map(|it| it.path()))
+                    .collect::<Vec<_>>(),
+                Err(e) => return scan.errors.push((display, e.to_string())),
+            };
+            entries.sort();
+            for entry in entries {
+                self.walk(&entry, scan);
+            }
+            return;
+        }
+        if !metadata.is_file() || metadata.len() > self.max_file_size {
+            return;
+        }
+        if let Err(e) = read_certificates(path, &display, &mut scan.certificates) {
+            scan.errors.push((display, format!("{e:#}")));
+        }
+    }
+}
+
+fn read_certificates(path: &Path, source: &str, certificates: &mut Vec<Certificate>) -> Result<()> {
+    let content = fs::read(path)?;
+    for (i, der) in x509::decode(&content)?.iter().enumerate() {
+        let cert = x509::parse(der).with_context(|| format!("Invalid certificate #{i}"))?;
+        certificates.push(Certificate::new(source, cert));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scanner;
+    use crate::config::CertsConfig;
+
+    #[test]
+    fn test_scan() {
+        let cfg = CertsConfig {
+            enable: true,
+            paths: vec![
+                concat!(env!("CARGO_MANIFEST_DIR"), "/test_resources/certs").to_string(),
+                "/nonexistent/psh.pem".to_string(),
+            ],
+            ..Default::default()
+        };
+        let scan = Scanner::new(&cfg).scan();
+        assert!(scan.errors.is_empty(), "{:?}", scan.errors);
+        let subjects = scan
+            .certificates
+            .iter()
+            .map(|it| it.subject.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            subjects,
+            [
+                "O=Example, CN=example.com",
+                "C=US, O=PSH Test, CN=PSH Test CA"
+            ]
+        );
+        assert!(scan.certificates[0].source.ends_with("chain.pem"));
+    }
+}
@@ -0,0 +1,347 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use anyhow::{Context, Result, bail};
+use chrono::NaiveDate;
+
+const SEQUENCE: u8 = 0x30;
+const INTEGER: u8 = 0x02;
+const OID: u8 = 0x06;
+const OCTET_STRING: u8 = 0x04;
+const BOOLEAN: u8 = 0x01;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const VERSION: u8 = 0xa0;
+const EXTENSIONS: u8 = 0xa3;
+const DNS_NAME: u8 = 0x82;
+const IP_ADDRESS: u8 = 0x87;
+const SUBJECT_ALT_NAME: &str = "2.5.29.17";
+
+/// The parts of a certificate that matter for its hygiene, nothing is
+/// verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    /// e.g. `O=Example, CN=example.com`
+    pub subject: String,
+    pub issuer: String,
+    /// hex encoded
+    pub serial: String,
+    /// dns names and ip addresses
+    pub sans: Vec<String>,
+    /// in seconds since the epoch
+    pub not_before: i64,
+    /// in seconds since the epoch
+    pub not_after: i64,
+}
+
+/// A DER reader over the contents of a constructed value.
+struct Der<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    const fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    const fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn peek_tag(&self) -> Option<u8> {
+        self.buf.first().copied()
+    }
+
+    /// The next value as its tag and contents.
+    fn next(&mut self) -> Result<(u8, &'a [u8])> {
+        let [tag, first, rest @ ..] = self.buf else {
+            bail!("Truncated DER value");
+        };
+        let (len, rest) = match *first {
+            len @ 0..=0x7f => (usize::from(len), rest),
+            // long form, the low bits count the length bytes
+            first @ 0x81..=0x84 => {
+                let n = usize::from(first & 0x7f);
+                if rest.len() < n {
+                    bail!("Truncated DER length");
+                }
+                let len = rest[..n]
+                    .iter()
+                    .fold(0usize, |len, &b| (len << 8) | usize::from(b));
+                (len, &rest[n..])
+            }
+            _ => bail!("Unsupported DER length"),
+        };
+        if rest.len() < len {
+            bail!("Truncated DER value");
+        }
+        self.buf = &rest[len..];
+        Ok((*tag, &rest[..len]))
+    }
+
+    fn expect(&mut self, tag: u8) -> Result<&'a [u8]> {
+        let (actual, value) = self.next()?;
+        if actual != tag {
+            bail!("Expected DER tag {tag:#04x}, found {actual:#04x}");
+        }
+        Ok(value)
+    }
+}
+
+/// Parses a DER encoded certificate.
+pub fn parse(der: &[u8]) -> Result<Certificate> {
+    let cert = Der::new(der).expect(SEQUENCE)?;
+    let mut tbs = Der::new(Der::new(cert).expect(SEQUENCE)?);
+    if tbs.peek_tag() == Some(VERSION) {
+        tbs.next()?;
+    }
+    let serial = hex(tbs.expect(INTEGER)?);
+    // signature algorithm
+    tbs.expect(SEQUENCE)?;
+    let issuer = name(tbs.expect(SEQUENCE)?)?;
+    let mut validity = Der::new(tbs.expect(SEQUENCE)?);
+    let not_before = time(validity.next()?).context("Invalid notBefore")?;
+    let not_after = time(validity.next()?).context("Invalid notAfter")?;
+    let subject = name(tbs.expect(SEQUENCE)?)?;
+    // subject public key info
+    tbs.expect(SEQUENCE)?;
+
+    let mut sans = vec![];
+    while !tbs.is_empty() {
+        let (tag, value) = tbs.next()?;
+        if tag == EXTENSIONS {
+            sans = subject_alt_names(value)?;
+        }
+    }
+    Ok(Certificate {
+        subject,
+        issuer,
+        serial,
+        sans,
+        not_before,
+        not_after,
+    })
+}
+
+fn subject_alt_names(extensions: &[u8]) -> Result<Vec<String>> {
+    let mut extensions = Der::new(Der::new(extensions).expect(SEQUENCE)?);
+    while !extensions.is_empty() {
+        let mut extension = Der::new(extensions.expect(SEQUENCE)?);
+        let id = oid(extension.expect(OID)?);
+        if extension.peek_tag() == Some(BOOLEAN) {
+            extension.next()?;
+        }
+        if id != SUBJECT_ALT_NAME {
+            continue;
+        }
+        let value = extension.expect(OCTET_STRING)?;
+        let mut names = Der::new(Der::new(value).expect(SEQUENCE)?);
+        let mut sans = vec![];
+        while !names.is_empty() {
+            match names.next()? {
+                (DNS_NAME, name) => sans.push(String::from_utf8_lossy(name).into_owned()),
+                (IP_ADDRESS, ip) => {
+                    if let Ok(ip) = <[u8; 4]>::try_from(ip) {
+                        sans.push(Ipv4Addr::from(ip).to_string());
+                    } else if let Ok(ip) = <[u8; 16]>::try_from(ip) {
+                        sans.push(Ipv6Addr::from(ip).to_string());
+                    }
+                }
+                // emails, uris and directory names
+                _ => {}
+            }
+        }
+        return Ok(sans);
+    }
+    Ok(vec![])
+}
+
+/// Attributes in the order they are encoded, e.g. `C=US, O=Example, CN=Example CA`.
+fn name(value: &[u8]) -> Result<String> {
+    let mut rdns = Der::new(value);
+    let mut attributes = vec![];
+    while !rdns.is_empty() {
+        let mut set = Der::new(rdns.expect(0x31)?);
+        while !set.is_empty() {
+            let mut attribute = Der::new(set.expect(SEQUENCE)?);
+            let id = oid(attribute.expect(OID)?);
+            let (_, value) = attribute.next()?;
+            let key = match id.as_str() {
+                "2.5.4.3" => "CN",
+                "2.5.4.6" => "C",
+                "2.5.4.7" => "L",
+                "2.5.4.8" => "ST",
+                "2.5.4.10" => "O",
+                "2.5.4.11" => "OU",
+                other => other,
+            };
+            attributes.push(format!("{key}={}", String::from_utf8_lossy(value)));
+        }
+    }
+    Ok(attributes.join(", "))
+}
+
+fn oid(value: &[u8]) -> String {
+    let Some((&first, rest)) = value.split_first() else {
+        return String::new();
+    };
+    let mut arcs = vec![u64::from(first / 40), u64::from(first % 40)];
+    let mut arc = 0u64;
+    for &b in rest {
+        arc = (arc << 7) | u64::from(b & 0x7f);
+        // the high bit marks a continued arc
+        if b & 0x80 == 0 {
+            arcs.push(arc);
+            arc = 0;
+        }
+    }
+    arcs.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// `YYMMDDHHMMSSZ` or `YYYYMMDDHHMMSSZ` to seconds since the epoch.
+fn time((tag, value): (u8, &[u8])) -> Result<i64> {
+    let value = std::str::from_utf8(value)?;
+    let digits = value.strip_suffix('Z').context("Time is not in UTC")?;
+    // sliced by bytes below, from certificates of anyone
+    if !digits.bytes().all(|it| it.is_ascii_digit()) {
+        bail!("Invalid time {value}");
+    }
+    let (year, rest) = match tag {
+        UTC_TIME if digits.len() == 12 => {
+            let year: i32 = digits[..2].parse()?;
+            // two digit years are 1950 to 2049
+            (
+                if year < 50 { 2000 + year } else { 1900 + year },
+                &digits[2..],
+            )
+        }
+        GENERALIZED_TIME if digits.len() == 14 => (digits[..4].parse()?, &digits[4..]),
+        _ => bail!("Unsupported time {value}"),
+    };
+    let mut fields = [0u32; 5];
+    for (i, field) in fields.iter_mut().enumerate() {
+        *field = rest[i * 2..i * 2 + 2].parse()?;
+    }
+    let [month, day, hour, minute, second] = fields;
+    NaiveDate::from_ymd_opt(year, month, day)
+        .and_then(|it| it.and_hms_opt(hour, minute, second))
+        .map(|it| it.and_utc().timestamp())
+        .with_context(|| format!("Invalid time {value}"))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|it| format!("{it:02x}")).collect()
+}
+
+/// DER certificates of a PEM bundle, or the file itself if it is DER.
+pub fn decode(content: &[u8]) -> Result<Vec<Vec<u8>>> {
+    if content.first() == Some(&SEQUENCE) {
+        return Ok(vec![content.to_vec()]);
+    }
+    let text = String::from_utf8_lossy(content);
+    let mut certs = vec![];
+    let mut rest = text.as_ref();
+    while let Some(start) = rest.find("-----BEGIN CERTIFICATE-----") {
+        rest = &rest[start + "-----BEGIN CERTIFICATE-----".len()..];
+        let end = rest
+            .find("-----END CERTIFICATE-----")
+            .context("Unterminated PEM certificate")?;
+        certs.push(base64(&rest[..end])?);
+        rest = &rest[end..];
+    }
+    Ok(certs)
+}
+
+fn base64(text: &str) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in text.bytes().filter(|it| !it.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => bail!("Invalid base64"),
+        };
+        acc = (acc << 6) | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Certificate, GENERALIZED_TIME, UTC_TIME, decode, parse, time};
+
+    const CHAIN: &[u8] = include_bytes!("../../test_resources/certs/chain.pem");
+
+    #[test]
+    fn test_parse_chain() {
+        let certs = decode(CHAIN).unwrap();
+        assert_eq!(certs.len(), 2);
+
+        assert_eq!(
+            parse(&certs[0]).unwrap(),
+            Certificate {
+                subject: "O=Example, CN=example.com".to_string(),
+                issuer: "C=US, O=PSH Test, CN=PSH Test CA".to_string(),
+                serial: "1234abcd".to_string(),
+                sans: vec![
+                    "example.com".to_string(),
+                    "*.example.com".to_string(),
+                    "192.0.2.1".to_string(),
+                    "2001:db8::1".to_string(),
+                ],
+                // 2025-06-01T12:00:00Z, in UTCTime
+                not_before: 1748779200,
+                // 2051-01-01T00:00:00Z, in GeneralizedTime
+                not_after: 2556144000,
+            }
+        );
+
+        let ca = parse(&certs[1]).unwrap();
+        assert_eq!(ca.subject, ca.issuer);
+        assert!(ca.sans.is_empty());
+        assert_eq!(ca.not_after, 2051222400);
+
+        // DER files are used as they are
+        assert_eq!(decode(&certs[1]).unwrap(), [certs[1].clone()]);
+        assert!(parse(&certs[0][..100]).is_err());
+    }
+
+    #[test]
+    fn test_time() {
+        assert_eq!(time((UTC_TIME, b"250601120000Z")).unwrap(), 1748779200);
+        assert_eq!(
+            time((GENERALIZED_TIME, b"20510101000000Z")).unwrap(),
+            2556144000
+        );
+        // multi-byte characters of the right byte length
+        assert!(time((UTC_TIME, "2506011é000Z".as_bytes())).is_err());
+        assert!(time((UTC_TIME, b"25060112+000Z")).is_err());
+        assert!(time((UTC_TIME, b"250601120000")).is_err());
+    }
+}
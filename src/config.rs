@@ -12,7 +12,7 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
-use std::{fs, path::Path};
+use std::{collections::BTreeMap, fs, path::Path};
 
 use anyhow::Result;
 use serde::Deserialize;
@@ -23,6 +23,60 @@ const TEMPLATE: &str = include_str!("../doc/config.toml");
 pub struct Config {
     pub daemon: DaemonConfig,
     pub remote: RemoteConfig,
+    #[serde(default)]
+    pub engine: EngineConfig,
+    #[serde(default)]
+    pub catalog: CatalogConfig,
+    #[serde(default)]
+    pub blackbox: BlackBoxConfig,
+    #[serde(default)]
+    pub bursts: BurstsConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub store: StoreConfig,
+    #[serde(default)]
+    pub bus: BusConfig,
+    #[serde(default)]
+    pub kernel_events: KernelEventsConfig,
+    #[serde(default)]
+    pub symbolize: SymbolizeConfig,
+    #[serde(default)]
+    pub tenants: Vec<TenantConfig>,
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub kubernetes: KubernetesConfig,
+    #[serde(default)]
+    pub self_profile: SelfProfileConfig,
+    #[serde(default)]
+    pub ledger: LedgerConfig,
+    #[serde(default)]
+    pub at_rest: AtRestConfig,
+    #[serde(default)]
+    pub environ: EnvironConfig,
+    #[serde(default)]
+    pub redact: RedactConfig,
+    #[serde(default)]
+    pub control: ControlConfig,
+    #[serde(default)]
+    pub capture: CaptureConfig,
+    #[serde(default)]
+    pub probe: ProbeConfig,
+    #[serde(default)]
+    pub process_events: ProcessEventsConfig,
+    #[serde(default)]
+    pub integrity: IntegrityConfig,
+    #[serde(default)]
+    pub drift: DriftConfig,
+    #[serde(default)]
+    pub certs: CertsConfig,
+    #[serde(default)]
+    pub pmu_events: PmuEventsConfig,
+    #[serde(default)]
+    pub msr: MsrConfig,
+    #[serde(default)]
+    pub sysfs: SysfsConfig,
 }
 
 #[derive(Clone, Deserialize)]
@@ -32,6 +86,11 @@ pub struct DaemonConfig {
     pub stderr: String,
     pub workdir: String,
     pub wasm: DaemonWasmConfig,
+    /// run side by side with `wasm`
+    #[serde(default)]
+    pub components: Vec<ComponentConfig>,
+    #[serde(default)]
+    pub pipelines: Vec<PipelineConfig>,
 }
 
 #[derive(Clone, Deserialize)]
@@ -39,6 +98,234 @@ pub struct DaemonWasmConfig {
     pub enable: bool,
     pub path: String,
     pub args: Vec<String>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub schedule: Option<String>,
+    /// required if `path` is an `https://` URL
+    #[serde(default)]
+    pub sha256: Option<String>,
+    #[serde(default)]
+    pub max_cpu_ms: Option<u64>,
+    #[serde(default)]
+    pub memory_limit: Option<usize>,
+    #[serde(default)]
+    pub rate_limits: BTreeMap<String, u32>,
+    #[serde(default)]
+    pub preopens: Vec<PreopenConfig>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub restart: RestartPolicy,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct ComponentConfig {
+    /// a file or an `https://` URL, which needs `sha256`
+    pub path: String,
+    /// hex encoded digest the downloaded component must match
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// referred to by `after` of other components, the path if absent
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// set on top of the environment of psh
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// `30s` or a cron expression, runs once at startup if absent
+    #[serde(default)]
+    pub schedule: Option<String>,
+    /// runs whenever this component has succeeded, instead of at startup
+    #[serde(default)]
+    pub after: Option<String>,
+    /// CPU time limit of a run in milliseconds, `[engine]`'s if absent
+    #[serde(default)]
+    pub max_cpu_ms: Option<u64>,
+    /// max linear memory in bytes, 0 for no limit, `[engine]`'s if absent
+    #[serde(default)]
+    pub memory_limit: Option<usize>,
+    /// calls per second by host interface, on top of `[engine]`'s, the lower
+    /// one applies where both limit an interface
+    #[serde(default)]
+    pub rate_limits: BTreeMap<String, u32>,
+    /// directories the component may access besides `/` if
+    /// `[engine] preopen_root`
+    #[serde(default)]
+    pub preopens: Vec<PreopenConfig>,
+    /// wasi:random/insecure of every run is seeded with it, for replays and
+    /// tests, the OS's randomness if absent
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// started again after a run ends, only for components without
+    /// `schedule` and `after`
+    #[serde(default)]
+    pub restart: RestartPolicy,
+}
+
+/// Components running one after another, each stage gets what the one
+/// before emitted through `profiling:host/pipeline`.
+#[derive(Clone, Deserialize)]
+pub struct PipelineConfig {
+    /// names of components, every one but the first runs after the one
+    /// before it
+    pub stages: Vec<String>,
+}
+
+/// When a component is started again after its run ended.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    /// after a trap, an error or a timeout
+    OnFailure,
+    Always,
+}
+
+/// Exports the runs of a component may make per second together, past
+/// them exports fail as throttled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ExportRateConfig {
+    /// samples and points, 0 for no limit
+    pub samples_per_sec: u32,
+    /// bytes of samples, points and files, 0 for no limit
+    pub bytes_per_sec: u64,
+}
+
+/// A host directory a component may access.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PreopenConfig {
+    pub host: String,
+    /// where the component sees it, `host` if absent
+    #[serde(default)]
+    pub guest: Option<String>,
+    /// read only if false
+    #[serde(default)]
+    pub writable: bool,
+}
+
+impl ComponentConfig {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.path)
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct EngineConfig {
+    /// max linear memory of a component in bytes, 0 for no limit
+    pub memory_limit: usize,
+    /// limit of Python and JavaScript components of the config file, which
+    /// carry their interpreter, tasks of the server get `memory_limit`
+    pub interpreted_memory_limit: usize,
+    /// keep compiled components in `cache_dir`
+    pub cache: bool,
+    pub cache_dir: String,
+    /// in days, compiled components not run for this long are removed
+    pub cache_max_age: u64,
+    /// run local components again when their file is replaced
+    pub hot_reload: bool,
+    /// CPU time limit of a run in milliseconds, 0 for no limit
+    pub max_cpu_ms: u64,
+    /// in milliseconds, how long a cancelled run may take to wrap up
+    pub cancel_grace_ms: u64,
+    /// calls per second by host interface, unlimited if absent
+    pub rate_limits: BTreeMap<String, u32>,
+    /// exports a run of a component may make per second, unlimited if absent
+    pub export_rate: ExportRateConfig,
+    /// keep engines and pre-instantiated components of scheduled components
+    pub warm_pool: bool,
+    /// last artifacts of components are kept here for the ones running after
+    pub handoff_dir: String,
+    /// file of the interfaces components may import, every one if empty
+    pub policy: String,
+    /// components may read the whole filesystem as `/`
+    pub preopen_root: bool,
+    /// where stdout and stderr of components go
+    pub stdio: StdioMode,
+    /// send logged output of components to the server too
+    pub forward_stdio: bool,
+    /// in milliseconds, delay before the first restart of a component,
+    /// doubled for every restart in a row
+    pub restart_backoff_ms: u64,
+    /// in milliseconds, max delay between restarts, runs lasting longer
+    /// reset the delay
+    pub restart_backoff_max_ms: u64,
+    /// run every component in a child process of its own
+    pub isolate: bool,
+    /// export the resources every run of a task took along with its data
+    pub telemetry: bool,
+    /// source locations in backtraces of components with debug info
+    pub backtrace_details: bool,
+}
+
+/// Where stdout and stderr of components go.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StdioMode {
+    /// written to the daemon's own
+    Inherit,
+    /// logged line by line with the name of the component
+    Log,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            memory_limit: 64 << 20,
+            interpreted_memory_limit: 256 << 20,
+            cache: true,
+            cache_dir: "/var/cache/psh/components".to_string(),
+            cache_max_age: 30,
+            hot_reload: false,
+            max_cpu_ms: 0,
+            cancel_grace_ms: 2000,
+            rate_limits: BTreeMap::new(),
+            export_rate: ExportRateConfig::default(),
+            warm_pool: false,
+            handoff_dir: "/var/lib/psh/handoff".to_string(),
+            policy: String::new(),
+            preopen_root: true,
+            stdio: StdioMode::Inherit,
+            forward_stdio: false,
+            restart_backoff_ms: 1000,
+            restart_backoff_max_ms: 60_000,
+            isolate: false,
+            telemetry: true,
+            backtrace_details: true,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct CatalogConfig {
+    pub url: String,
+    /// components installed with `psh component install` are kept here
+    pub store_dir: String,
+    /// in seconds
+    pub timeout: u64,
+    /// components configured by an `https://` path are downloaded here
+    pub download_dir: String,
+    /// in bytes, larger components are not downloaded
+    pub max_size: u64,
+}
+
+impl Default for CatalogConfig {
+    fn default() -> Self {
+        Self {
+            url: "https://catalog.optimatist.com".to_string(),
+            store_dir: "/var/lib/psh/components".to_string(),
+            timeout: 30,
+            download_dir: "/var/lib/psh/downloads".to_string(),
+            max_size: 256 * 1024 * 1024,
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -46,6 +333,51 @@ pub struct RemoteConfig {
     pub token: String,
     pub rpc: RpcConfig,
     pub otlp: OtlpConfig,
+    #[serde(default)]
+    pub upload: UploadConfig,
+    #[serde(default)]
+    pub exporters: ExportersConfig,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct UploadConfig {
+    pub enable: bool,
+    /// shared by all uploads through the rpc channel
+    pub bytes_per_min: u64,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            bytes_per_min: 1 << 20,
+        }
+    }
+}
+
+/// Exporters records of components go to besides the server.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ExportersConfig {
+    /// append exported points to this file as JSON lines
+    pub jsonl_path: Option<String>,
+    /// records waiting per exporter, newer ones are dropped beyond it
+    pub queue_size: usize,
+    /// records sent at once
+    pub max_batch: usize,
+    pub flush_interval_ms: u64,
+}
+
+impl Default for ExportersConfig {
+    fn default() -> Self {
+        Self {
+            jsonl_path: None,
+            queue_size: 4096,
+            max_batch: 512,
+            flush_interval_ms: 1000,
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -54,6 +386,9 @@ pub struct RpcConfig {
     pub addr: String,
     /// in seconds
     pub heartbeat_interval: u64,
+    /// run commands the server sends along with heartbeat responses
+    #[serde(default)]
+    pub heartbeat_commands: bool,
     pub instance_id_file: String,
     pub data_export: DataExportConfig,
 }
@@ -64,12 +399,705 @@ pub struct OtlpConfig {
     pub addr: String,
     /// in seconds
     pub interval: u64,
+    /// also export host metrics named after the OpenTelemetry semantic conventions
+    #[serde(default)]
+    pub semconv: bool,
 }
 
 #[derive(Deserialize)]
 pub struct DataExportConfig {
     pub buf_size: usize,
     pub buf_watermark: usize,
+    /// rename well known tag keys to their semantic convention names
+    #[serde(default)]
+    pub normalize: bool,
+    #[serde(default)]
+    pub timestamp: TimestampConfig,
+}
+
+/// Clock the host reads when it assigns timestamps to exported data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClockSource {
+    /// leave missing timestamps to the server
+    None,
+    /// the wall clock, follows every jump of it
+    Realtime,
+    /// the wall clock at startup advanced by the boot time clock, never
+    /// jumps and keeps counting during suspend
+    Monotonic,
+    /// the wall clock while the kernel reports it NTP synchronized,
+    /// `monotonic` otherwise
+    Ntp,
+}
+
+/// What to do with guest timestamps too far off the host clock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SkewAction {
+    /// move them to the nearest bound of the allowed skew
+    Clamp,
+    /// replace them with the host clock
+    Replace,
+    /// refuse the data
+    Reject,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct TimestampConfig {
+    /// clock used for data exported without a timestamp
+    pub clock: ClockSource,
+    /// ignore guest timestamps and always use `clock`
+    pub override_guest: bool,
+    /// in seconds, guest timestamps are not validated if 0
+    pub max_skew: u64,
+    pub on_skew: SkewAction,
+}
+
+impl Default for TimestampConfig {
+    fn default() -> Self {
+        Self {
+            clock: ClockSource::None,
+            override_guest: false,
+            max_skew: 0,
+            on_skew: SkewAction::Clamp,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct BlackBoxConfig {
+    pub enable: bool,
+    /// length of the recorded window in seconds
+    pub window: u64,
+    /// in milliseconds
+    pub interval_ms: u64,
+    pub dump_dir: String,
+}
+
+impl Default for BlackBoxConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            window: 600,
+            interval_ms: 1000,
+            dump_dir: "/var/lib/psh/blackbox".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct BurstsConfig {
+    pub enable: bool,
+    /// in milliseconds, from 10 to 100
+    pub interval_ms: u64,
+    /// length of the kept window in seconds
+    pub window: u64,
+    /// share of a CPU from 0 to 1, ticks at or above it are part of a burst
+    pub util_threshold: f64,
+    /// tasks waiting for a CPU on average, ticks at or above it are part of
+    /// a burst
+    pub queue_threshold: f64,
+}
+
+impl Default for BurstsConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            interval_ms: 20,
+            window: 60,
+            util_threshold: 0.9,
+            queue_threshold: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    pub enable: bool,
+    /// in seconds, older exports are forgotten
+    pub retention: u64,
+    /// in bytes, the oldest exports are forgotten beyond it
+    pub max_size: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            retention: 3600,
+            max_size: 64 << 20,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct StoreConfig {
+    pub enable: bool,
+    pub path: String,
+    /// in bytes
+    pub max_value_size: usize,
+    /// of a component
+    pub max_keys: usize,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            path: "/var/lib/psh/store".to_string(),
+            max_value_size: 64 << 10,
+            max_keys: 10_000,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct BusConfig {
+    pub enable: bool,
+    /// messages of a subscription, newer ones are dropped beyond it
+    pub queue_size: usize,
+    /// in bytes
+    pub max_message_size: usize,
+    pub topics: Vec<TopicConfig>,
+}
+
+impl Default for BusConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            queue_size: 1024,
+            max_message_size: 64 << 10,
+            topics: vec![],
+        }
+    }
+}
+
+/// Policy of the topics matching `topic`, the first matching entry
+/// applies.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TopicConfig {
+    /// a name or a prefix ending with `*`
+    pub topic: String,
+    /// components allowed to publish, anyone if empty
+    pub publishers: Vec<String>,
+    /// components allowed to receive, anyone if empty
+    pub subscribers: Vec<String>,
+    /// overrides `queue_size` of subscriptions to the topic
+    pub queue_size: Option<usize>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct LedgerConfig {
+    pub enable: bool,
+    pub path: String,
+    /// runs kept, older ones are forgotten
+    pub max_entries: usize,
+    /// meter the fuel consumed by components, slows them down
+    pub fuel: bool,
+    /// seed wasi:random/insecure of runs without a seed of their own and
+    /// record it
+    pub record_seed: bool,
+}
+
+impl Default for LedgerConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            path: "/var/lib/psh/ledger.jsonl".to_string(),
+            max_entries: 1000,
+            fuel: false,
+            record_seed: false,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ControlConfig {
+    pub enable: bool,
+    pub path: String,
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            path: "/run/psh/control.sock".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct CaptureConfig {
+    pub enable: bool,
+    /// interfaces components may capture on, any if empty
+    pub interfaces: Vec<String>,
+    /// max bytes kept per packet
+    pub max_snaplen: u32,
+    pub max_packets: u32,
+    /// max size of a capture in bytes
+    pub max_bytes: usize,
+    /// in seconds
+    pub max_duration: u64,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            interfaces: vec![],
+            max_snaplen: 65535,
+            max_packets: 10000,
+            max_bytes: 64 << 20,
+            max_duration: 60,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ProbeConfig {
+    pub enable: bool,
+    /// hosts components may probe, `*.example.com` matches subdomains, none if
+    /// empty
+    pub targets: Vec<String>,
+    /// probes per second, shared by all components
+    pub rate: f64,
+    pub burst: u32,
+    /// in milliseconds, upper bound of probe timeouts
+    pub max_timeout: u64,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            targets: vec![],
+            rate: 10.0,
+            burst: 20,
+            max_timeout: 10000,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct IntegrityConfig {
+    pub enable: bool,
+    /// baselines survive restarts in this file
+    pub state_file: String,
+    /// in bytes, larger files are compared by size and metadata only
+    pub max_file_size: u64,
+    /// max files per set
+    pub max_files: usize,
+    /// named sets of files and directories, directories are walked recursively
+    pub sets: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct DriftConfig {
+    pub enable: bool,
+    /// `5m` or a cron expression
+    pub schedule: String,
+    /// the last snapshot survives restarts in this file
+    pub state_file: String,
+    /// names like `vm.swappiness` or prefixes ending with `*`
+    pub sysctls: Vec<String>,
+    pub cmdline: bool,
+    /// modules whose parameters are compared
+    pub modules: Vec<String>,
+    /// changes waiting to be sent
+    pub queue_size: usize,
+}
+
+impl Default for DriftConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            schedule: "5m".to_string(),
+            state_file: "/var/lib/psh/drift.json".to_string(),
+            sysctls: vec![],
+            cmdline: true,
+            modules: vec![],
+            queue_size: 256,
+        }
+    }
+}
+
+impl Default for IntegrityConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            state_file: "/var/lib/psh/integrity.json".to_string(),
+            max_file_size: 64 << 20,
+            max_files: 100_000,
+            sets: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct CertsConfig {
+    pub enable: bool,
+    /// PEM bundles, DER files and directories, which are walked recursively
+    pub paths: Vec<String>,
+    /// in bytes, larger files are skipped
+    pub max_file_size: u64,
+}
+
+impl Default for CertsConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            paths: vec![],
+            max_file_size: 1 << 20,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct PmuEventsConfig {
+    pub enable: bool,
+    /// a copy of perf's `tools/perf/pmu-events/arch`
+    pub dir: String,
+}
+
+impl Default for PmuEventsConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            dir: "/usr/share/psh/pmu-events".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct SelfProfileConfig {
+    /// profile the daemon itself on SIGUSR1, without a handler the signal
+    /// terminates the process
+    pub enable: bool,
+    /// in seconds
+    pub duration: u64,
+    /// samples per second
+    pub frequency: i32,
+    pub dump_dir: String,
+}
+
+impl Default for SelfProfileConfig {
+    fn default() -> Self {
+        Self {
+            enable: true,
+            duration: 30,
+            frequency: 99,
+            dump_dir: "/var/lib/psh/self-profile".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct KernelEventsConfig {
+    pub enable: bool,
+    /// max queued events per subscriber
+    pub queue_size: usize,
+    /// send events as alerts through the rpc channel
+    pub forward_rpc: bool,
+}
+
+impl Default for KernelEventsConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            queue_size: 256,
+            forward_rpc: true,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ProcessEventsConfig {
+    pub enable: bool,
+    /// max queued events per subscriber
+    pub queue_size: usize,
+}
+
+impl Default for ProcessEventsConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            queue_size: 4096,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    pub enable: bool,
+    pub addr: String,
+    pub ping: PingConfig,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            addr: "127.0.0.1:9464".to_string(),
+            ping: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct PingConfig {
+    /// answer pings whether or not `[health]` is enabled
+    pub enable: bool,
+    pub addr: String,
+    pub udp: bool,
+    pub tcp: bool,
+}
+
+impl Default for PingConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            addr: "127.0.0.1:9465".to_string(),
+            udp: true,
+            tcp: true,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct KubernetesConfig {
+    pub enable: bool,
+    /// downward API volume file with the pod labels
+    pub labels_file: String,
+    /// resolve pods of processes through the kubelet
+    pub kubelet: bool,
+    /// defaults to `https://$NODE_IP:10250`
+    pub kubelet_url: Option<String>,
+    pub kubelet_insecure_tls: bool,
+    /// in seconds
+    pub pod_cache_ttl: u64,
+}
+
+impl Default for KubernetesConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            labels_file: "/etc/podinfo/labels".to_string(),
+            kubelet: false,
+            kubelet_url: None,
+            kubelet_insecure_tls: false,
+            pod_cache_ttl: 30,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct SymbolizeConfig {
+    pub cache_dir: String,
+    /// max parsed objects kept in memory
+    pub max_modules: usize,
+    pub debuginfod_urls: Vec<String>,
+    /// in seconds
+    pub debuginfod_timeout: u64,
+}
+
+impl Default for SymbolizeConfig {
+    fn default() -> Self {
+        Self {
+            cache_dir: "/var/cache/psh/symbols".to_string(),
+            max_modules: 64,
+            debuginfod_urls: vec![],
+            debuginfod_timeout: 30,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct MsrConfig {
+    pub enable: bool,
+    /// names of well known MSRs or addresses like `0x19c`
+    pub allow: Vec<String>,
+}
+
+impl Default for MsrConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            allow: [
+                "IA32_MPERF",
+                "IA32_APERF",
+                "IA32_PERF_STATUS",
+                "IA32_THERM_STATUS",
+                "IA32_TEMPERATURE_TARGET",
+                "IA32_PACKAGE_THERM_STATUS",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct SysfsConfig {
+    pub enable: bool,
+    /// paths under `/sys` components may read below, nothing if empty, links
+    /// may not lead out of them
+    pub prefixes: Vec<String>,
+    /// in bytes, larger attributes are refused
+    pub max_size: u64,
+    /// JSON lines file every access is appended to
+    pub audit_file: String,
+}
+
+impl Default for SysfsConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            prefixes: vec![],
+            max_size: 65536,
+            audit_file: "/var/log/psh/sysfs-audit.jsonl".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct TenantConfig {
+    pub name: String,
+    /// rpc tasks whose id starts with this prefix belong to the tenant
+    pub task_id_prefix: Option<String>,
+    pub allow_perf_op: bool,
+    pub allow_system_op: bool,
+    /// environments of processes, off unless granted
+    pub allow_environ: bool,
+    pub allow_data_export: bool,
+    /// packet capture, off unless granted
+    pub allow_capture: bool,
+    /// DNS, TCP and TLS probes of `[probe]` targets, off unless granted
+    pub allow_probe: bool,
+    /// ICMP echo probes, off unless granted, also needs `allow_probe`
+    pub allow_ping: bool,
+    /// reading allowlisted MSRs, off unless granted
+    pub allow_msr: bool,
+    /// reading sysfs attributes under `[sysfs]` prefixes, off unless granted
+    pub allow_sysfs: bool,
+    /// replacing `[integrity]` baselines, off unless granted, verifying is
+    /// always allowed
+    pub allow_integrity_baseline: bool,
+    /// max exported bytes per minute, unlimited if absent
+    pub export_quota: Option<usize>,
+    /// exports per second of each component of the tenant, replaces
+    /// `[engine.export_rate]`
+    pub export_rate: Option<ExportRateConfig>,
+    /// tags attached to all exported data, besides `tenant = <name>`
+    pub labels: BTreeMap<String, String>,
+}
+
+impl Default for TenantConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            task_id_prefix: None,
+            allow_perf_op: true,
+            allow_system_op: true,
+            allow_environ: false,
+            allow_data_export: true,
+            allow_capture: false,
+            allow_probe: false,
+            allow_ping: false,
+            allow_msr: false,
+            allow_sysfs: false,
+            allow_integrity_baseline: false,
+            export_quota: None,
+            export_rate: None,
+            labels: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct AtRestConfig {
+    pub enable: bool,
+    /// 64 hex digits, `key_file` is used if absent
+    pub key: Option<String>,
+    /// generated if missing
+    pub key_file: String,
+}
+
+impl Default for AtRestConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            key: None,
+            key_file: "/etc/psh/at-rest.key".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct EnvironConfig {
+    pub enable: bool,
+    /// variable names, `PREFIX*` matches by prefix, all if empty
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct RedactConfig {
+    pub enable: bool,
+    /// values of fields whose name contains one of these are masked
+    pub fields: Vec<String>,
+    /// regexes masked everywhere else
+    pub patterns: Vec<String>,
+    pub mask: String,
+}
+
+impl Default for RedactConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            fields: [
+                "password",
+                "passwd",
+                "secret",
+                "token",
+                "api_key",
+                "apikey",
+                "credential",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            patterns: vec![],
+            mask: "***".to_string(),
+        }
+    }
 }
 
 pub fn read_or_gen<P>(path: P) -> Result<Config>
@@ -85,6 +1113,22 @@ where
     Ok(cfg)
 }
 
+/// Like [`read_or_gen`] without writing anything, the template applies if
+/// there is no file at `path`.
+pub fn read_or_template<P>(path: P) -> Result<Config>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let cfg = if path.exists() {
+        fs::read_to_string(path)?
+    } else {
+        TEMPLATE.to_string()
+    };
+    let cfg: Config = toml::from_str(&cfg)?;
+    Ok(cfg)
+}
+
 #[test]
 fn parse_config_template() {
     toml::from_str::<Config>(TEMPLATE).unwrap();
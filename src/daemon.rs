@@ -17,7 +17,10 @@ use std::fs::File;
 use anyhow::Result;
 use daemonize::Daemonize;
 
-use crate::config::{DaemonConfig, DaemonWasmConfig};
+use crate::{
+    config::{ComponentConfig, DaemonConfig},
+    runtime::pipeline,
+};
 
 /// run the process as daemon
 pub fn spawn_daemon(cfg: DaemonConfig) -> Result<()> {
@@ -39,12 +42,29 @@ pub fn spawn_daemon(cfg: DaemonConfig) -> Result<()> {
     Ok(())
 }
 
-pub fn get_daemon_wasm_args(cfg: DaemonWasmConfig) -> Option<Vec<String>> {
-    if !cfg.enable {
-        return None;
-    }
-    let mut vec = Vec::with_capacity(cfg.args.len() + 1);
-    vec.push(cfg.path);
-    vec.extend(cfg.args);
-    Some(vec)
+/// `[daemon.wasm]` if enabled followed by `[[daemon.components]]`, chained
+/// by `[[daemon.pipelines]]`.
+pub fn get_daemon_components(cfg: &DaemonConfig) -> Result<Vec<ComponentConfig>> {
+    let wasm = cfg.wasm.enable.then(|| ComponentConfig {
+        path: cfg.wasm.path.clone(),
+        sha256: cfg.wasm.sha256.clone(),
+        name: None,
+        args: cfg.wasm.args.clone(),
+        env: Default::default(),
+        tenant: cfg.wasm.tenant.clone(),
+        schedule: cfg.wasm.schedule.clone(),
+        after: None,
+        max_cpu_ms: cfg.wasm.max_cpu_ms,
+        memory_limit: cfg.wasm.memory_limit,
+        rate_limits: cfg.wasm.rate_limits.clone(),
+        preopens: cfg.wasm.preopens.clone(),
+        seed: cfg.wasm.seed,
+        restart: cfg.wasm.restart,
+    });
+    let mut components: Vec<_> = wasm
+        .into_iter()
+        .chain(cfg.components.iter().cloned())
+        .collect();
+    pipeline::chain(&mut components, &cfg.pipelines)?;
+    Ok(components)
 }
@@ -0,0 +1,418 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    thread,
+};
+
+use anyhow::{Context, Result, bail};
+use chrono::{Local, Utc};
+use crossbeam::channel::{self, Receiver, Sender, TrySendError};
+use influxdb_line_protocol::LineProtocolBuilder;
+use psh_proto::{Data, DataType, ExportDataReq};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::DriftConfig,
+    runtime::{policy::matches, scheduler::Schedule},
+    services::{
+        export_stats::{self, Backend},
+        rpc::RpcClient,
+    },
+};
+
+const PROC_SYS: &str = "/proc/sys";
+const CMDLINE: &str = "/proc/cmdline";
+const SYS_MODULE: &str = "/sys/module";
+// a pattern like `net.*` matches thousands of sysctls
+const MAX_KEYS: usize = 10_000;
+
+static GLOBAL: OnceLock<Receiver<Change>> = OnceLock::new();
+
+/// Values by key, `sysctl:vm.swappiness`, `cmdline` or
+/// `module:nvme_core.io_timeout`.
+pub type Snapshot = BTreeMap<String, String>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub key: String,
+    /// `None` if added
+    pub old: Option<String>,
+    /// `None` if removed
+    pub new: Option<String>,
+}
+
+impl Change {
+    /// `sysctl`, `cmdline` or `module`.
+    pub fn kind(&self) -> &str {
+        self.key.split(':').next().unwrap_or_default()
+    }
+}
+
+/// Changes between two snapshots, ordered by key.
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Vec<Change> {
+    let mut changes: Vec<Change> = new
+        .iter()
+        .filter(|(key, value)| old.get(*key) != Some(*value))
+        .map(|(key, value)| Change {
+            key: key.clone(),
+            old: old.get(key).cloned(),
+            new: Some(value.clone()),
+        })
+        .collect();
+    changes.extend(
+        old.iter()
+            .filter(|(key, _)| !new.contains_key(*key))
+            .map(|(key, value)| Change {
+                key: key.clone(),
+                old: Some(value.clone()),
+                new: None,
+            }),
+    );
+    changes.sort_by(|a, b| a.key.cmp(&b.key));
+    changes
+}
+
+/// Sysctls under `root` matching `patterns`, names like `vm.swappiness` or
+/// prefixes ending with `*` like `net.ipv4.tcp_*`.
+///
+/// Sysctls that can't be read, like write only ones, are left out.
+fn read_sysctls(root: &Path, patterns: &[String], snapshot: &mut Snapshot) {
+    for pattern in patterns {
+        // the directory holding every match
+        let prefix = pattern.trim_end_matches('*');
+        let dir = prefix.rsplit_once('.').map_or("", |(dir, _)| dir);
+        let mut pending = vec![root.join(dir.replace('.', "/"))];
+        while let Some(path) = pending.pop() {
+            if snapshot.len() >= MAX_KEYS {
+                return;
+            }
+            let Ok(entries) = fs::read_dir(&path) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Ok(relative) = path.strip_prefix(root) else {
+                    continue;
+                };
+                let name = relative.to_string_lossy().replace('/', ".");
+                if entry.file_type().is_ok_and(|it| it.is_dir()) {
+                    if pattern.ends_with('*') && name.starts_with(prefix) {
+                        pending.push(path);
+                    }
+                    continue;
+                }
+                if !matches(pattern, &name) {
+                    continue;
+                }
+                if let Ok(value) = fs::read_to_string(&path) {
+                    // multi value sysctls are tab separated
+                    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+                    snapshot.insert(format!("sysctl:{name}"), value);
+                }
+            }
+        }
+    }
+}
+
+/// Parameters of `modules` under `root` that are readable, modules not
+/// loaded have none.
+fn read_module_params(root: &Path, modules: &[String], snapshot: &mut Snapshot) {
+    for module in modules {
+        let Ok(entries) = fs::read_dir(root.join(module).join("parameters")) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if let Ok(value) = fs::read_to_string(entry.path()) {
+                let param = entry.file_name().to_string_lossy().into_owned();
+                snapshot.insert(format!("module:{module}.{param}"), value.trim().to_string());
+            }
+        }
+    }
+}
+
+pub fn snapshot(cfg: &DriftConfig) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    read_sysctls(Path::new(PROC_SYS), &cfg.sysctls, &mut snapshot);
+    if let Some(Ok(cmdline)) = cfg.cmdline.then(|| fs::read_to_string(CMDLINE)) {
+        snapshot.insert("cmdline".to_string(), cmdline.trim().to_string());
+    }
+    read_module_params(Path::new(SYS_MODULE), &cfg.modules, &mut snapshot);
+    snapshot
+}
+
+/// What a snapshot was taken of, one of other tunables is no baseline.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Watched {
+    sysctls: Vec<String>,
+    cmdline: bool,
+    modules: Vec<String>,
+}
+
+impl From<&DriftConfig> for Watched {
+    fn from(value: &DriftConfig) -> Self {
+        Self {
+            sysctls: value.sysctls.clone(),
+            cmdline: value.cmdline,
+            modules: value.modules.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct State {
+    watched: Watched,
+    snapshot: Snapshot,
+}
+
+/// The snapshot kept in `path`, `None` if there is none or it was taken of
+/// other tunables than `watched`.
+fn load(path: &Path, watched: &Watched) -> Result<Option<Snapshot>> {
+    let state: State = match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .with_context(|| format!("Failed to parse {}", path.display()))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context(format!("Failed to read {}", path.display())),
+    };
+    if &state.watched != watched {
+        tracing::info!("Watched tunables changed, taking a new config baseline");
+        return Ok(None);
+    }
+    Ok(Some(state.snapshot))
+}
+
+fn save(path: &Path, watched: &Watched, snapshot: &Snapshot) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let state = State {
+        watched: watched.clone(),
+        snapshot: snapshot.clone(),
+    };
+    fs::write(path, serde_json::to_vec(&state)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Takes a snapshot of the configured tunables on `schedule` and logs how
+/// it differs from the one before, which is kept across restarts unless
+/// other tunables are watched or the state file is corrupt.
+///
+/// Changes are queued for [`forward_to_rpc`], the oldest are kept if
+/// nothing takes them.
+pub fn spawn(cfg: &DriftConfig) -> Result<()> {
+    let schedule: Schedule = cfg.schedule.parse()?;
+    let state_file = PathBuf::from(&cfg.state_file);
+    let watched = Watched::from(cfg);
+    let mut previous = match load(&state_file, &watched) {
+        Ok(previous) => previous,
+        Err(e) => {
+            tracing::warn!("Taking a new config baseline: {e:#}");
+            None
+        }
+    };
+    let (tx, rx) = channel::bounded(cfg.queue_size.max(1));
+    if GLOBAL.set(rx).is_err() {
+        bail!("Config drift detector already started");
+    }
+    let cfg = cfg.clone();
+    thread::Builder::new()
+        .name("psh-drift".to_string())
+        .spawn(move || {
+            let mut next = schedule.next(Local::now(), true);
+            while let Some(due) = next {
+                thread::sleep((due - Local::now()).to_std().unwrap_or_default());
+                next = schedule.next(Local::now(), false);
+                let current = snapshot(&cfg);
+                if previous.as_ref() == Some(&current) {
+                    continue;
+                }
+                // the first snapshot is the baseline
+                if let Some(previous) = &previous {
+                    publish(&tx, diff(previous, &current));
+                }
+                if let Err(e) = save(&state_file, &watched, &current) {
+                    tracing::warn!("Failed to save the config snapshot: {e:#}");
+                }
+                previous = Some(current);
+            }
+        })?;
+    Ok(())
+}
+
+fn publish(tx: &Sender<Change>, changes: Vec<Change>) {
+    for change in changes {
+        tracing::warn!(
+            "Config drift of {}: {} -> {}",
+            change.key,
+            change.old.as_deref().unwrap_or("<none>"),
+            change.new.as_deref().unwrap_or("<none>")
+        );
+        if let Err(TrySendError::Full(change)) = tx.try_send(change) {
+            tracing::warn!("Config drift queue full, change of {} dropped", change.key);
+        }
+    }
+}
+
+/// Sends every change to the server as line protocol.
+pub fn forward_to_rpc(client: RpcClient, instance_id: String) {
+    let Some(rx) = GLOBAL.get() else {
+        return;
+    };
+    let rt = tokio::runtime::Handle::current();
+    thread::spawn(move || {
+        let stats = export_stats::global(Backend::Drift);
+        while let Ok(change) = rx.recv() {
+            stats.accept(1);
+            let req = ExportDataReq {
+                task_id: String::new(),
+                data: vec![Data {
+                    ty: DataType::LineProtocol as _,
+                    bytes: to_line_protocol(&change, &instance_id),
+                }],
+            };
+            let mut client = client.clone();
+            if let Err(e) = rt.block_on(client.export_data_accounted(req, None, stats)) {
+                tracing::error!("Failed to send config drift: {e}");
+            }
+        }
+    });
+}
+
+pub fn to_line_protocol(change: &Change, instance_id: &str) -> Vec<u8> {
+    LineProtocolBuilder::new()
+        .measurement("psh_config_drift")
+        .tag("instance_id", instance_id)
+        .tag("kind", change.kind())
+        .tag("key", &change.key)
+        .field("old", change.old.as_deref().unwrap_or_default())
+        .field("new", change.new.as_deref().unwrap_or_default())
+        .field("added", change.old.is_none())
+        .field("removed", change.new.is_none())
+        .timestamp(Utc::now().timestamp_nanos_opt().unwrap_or_default())
+        .close_line()
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{Change, Snapshot, Watched, diff, load, read_module_params, read_sysctls, save};
+
+    fn snapshot(entries: &[(&str, &str)]) -> Snapshot {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = snapshot(&[("cmdline", "quiet"), ("sysctl:vm.swappiness", "60")]);
+        let new = snapshot(&[
+            ("sysctl:vm.swappiness", "10"),
+            ("module:kvm.halt_poll_ns", "0"),
+        ]);
+        let changes = diff(&old, &new);
+        assert_eq!(
+            changes,
+            [
+                Change {
+                    key: "cmdline".to_string(),
+                    old: Some("quiet".to_string()),
+                    new: None,
+                },
+                Change {
+                    key: "module:kvm.halt_poll_ns".to_string(),
+                    old: None,
+                    new: Some("0".to_string()),
+                },
+                Change {
+                    key: "sysctl:vm.swappiness".to_string(),
+                    old: Some("60".to_string()),
+                    new: Some("10".to_string()),
+                },
+            ]
+        );
+        assert_eq!(changes[1].kind(), "module");
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_state_file() {
+        let path = std::env::temp_dir().join(format!("psh-drift-{}.json", std::process::id()));
+        let watched = Watched {
+            sysctls: vec!["vm.swappiness".to_string()],
+            cmdline: true,
+            modules: vec![],
+        };
+        let baseline = snapshot(&[("sysctl:vm.swappiness", "60")]);
+        assert_eq!(load(&path, &watched).unwrap(), None);
+
+        save(&path, &watched, &baseline).unwrap();
+        assert_eq!(load(&path, &watched).unwrap(), Some(baseline));
+        let other = Watched {
+            cmdline: false,
+            ..watched.clone()
+        };
+        assert_eq!(load(&path, &other).unwrap(), None);
+
+        fs::write(&path, "{\"sysctl:vm.swappiness\"").unwrap();
+        assert!(load(&path, &watched).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_read_tunables() {
+        let root = std::env::temp_dir().join(format!("psh-drift-{}", std::process::id()));
+        let sys = root.join("sys");
+        fs::create_dir_all(sys.join("net/ipv4/conf")).unwrap();
+        fs::create_dir_all(sys.join("vm")).unwrap();
+        fs::write(sys.join("net/ipv4/tcp_rmem"), "4096\t131072\t6291456\n").unwrap();
+        fs::write(sys.join("net/ipv4/tcp_sack"), "1\n").unwrap();
+        fs::write(sys.join("net/ipv4/ip_forward"), "0\n").unwrap();
+        fs::write(sys.join("net/ipv4/conf/rp_filter"), "2\n").unwrap();
+        fs::write(sys.join("vm/swappiness"), "60\n").unwrap();
+        let modules = root.join("module");
+        fs::create_dir_all(modules.join("kvm/parameters")).unwrap();
+        fs::write(modules.join("kvm/parameters/halt_poll_ns"), "200000\n").unwrap();
+
+        let mut snapshot = Snapshot::new();
+        let patterns = ["net.ipv4.tcp_*".to_string(), "vm.swappiness".to_string()];
+        read_sysctls(&sys, &patterns, &mut snapshot);
+        read_module_params(
+            &modules,
+            &["kvm".to_string(), "absent".to_string()],
+            &mut snapshot,
+        );
+        let _ = fs::remove_dir_all(&root);
+
+        let keys: Vec<&str> = snapshot.keys().map(String::as_str).collect();
+        assert_eq!(
+            keys,
+            [
+                "module:kvm.halt_poll_ns",
+                "sysctl:net.ipv4.tcp_rmem",
+                "sysctl:net.ipv4.tcp_sack",
+                "sysctl:vm.swappiness",
+            ]
+        );
+        assert_eq!(snapshot["sysctl:net.ipv4.tcp_rmem"], "4096 131072 6291456");
+        assert_eq!(snapshot["module:kvm.halt_poll_ns"], "200000");
+    }
+}
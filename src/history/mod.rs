@@ -0,0 +1,217 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::VecDeque,
+    mem,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use anyhow::{Result, bail};
+use chrono::Utc;
+
+use crate::config::HistoryConfig;
+
+// entries returned by a query, the latest ones are kept
+const MAX_RESULTS: usize = 10_000;
+
+static GLOBAL: OnceLock<History> = OnceLock::new();
+
+/// The store installed by [`init`], `None` if it is disabled.
+pub fn global() -> Option<&'static History> {
+    GLOBAL.get()
+}
+
+pub fn init(cfg: &HistoryConfig) -> Result<()> {
+    let history = History::new(Duration::from_secs(cfg.retention), cfg.max_size);
+    if GLOBAL.set(history).is_err() {
+        bail!("History already initialized");
+    }
+    Ok(())
+}
+
+/// A sample or point exported by a component, with its numeric fields.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// `None` for components without a tenant
+    pub tenant: Option<String>,
+    pub name: String,
+    pub tags: Vec<(String, String)>,
+    pub fields: Vec<(String, f64)>,
+    /// in nanoseconds since the epoch
+    pub ns_ts: u64,
+}
+
+impl Entry {
+    fn size(&self) -> usize {
+        let strings = self.tenant.as_ref().map_or(0, String::len)
+            + self.name.len()
+            + self
+                .tags
+                .iter()
+                .map(|(k, v)| k.len() + v.len())
+                .sum::<usize>()
+            + self.fields.iter().map(|(k, _)| k.len()).sum::<usize>();
+        mem::size_of::<Self>()
+            + strings
+            + self.tags.len() * mem::size_of::<(String, String)>()
+            + self.fields.len() * mem::size_of::<(String, f64)>()
+    }
+}
+
+/// What a query is restricted to.
+pub struct Query<'a> {
+    /// entries of other tenants are not seen, `None` sees all of them
+    pub tenant: Option<&'a str>,
+    pub name: &'a str,
+    /// the entries carry all of them
+    pub tags: &'a [(String, String)],
+    /// in nanoseconds since the epoch, inclusive
+    pub start_ns: u64,
+    /// in nanoseconds since the epoch, exclusive
+    pub end_ns: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    // in the order they were recorded
+    entries: VecDeque<(u64, Entry)>,
+    size: usize,
+}
+
+/// Recent exports of the components on this host, so they can compare
+/// against them without asking the server.
+pub struct History {
+    retention: Duration,
+    max_size: usize,
+    inner: Mutex<Entries>,
+}
+
+impl History {
+    pub fn new(retention: Duration, max_size: usize) -> Self {
+        Self {
+            retention,
+            max_size,
+            inner: Mutex::new(Entries::default()),
+        }
+    }
+
+    pub fn record(&self, entry: Entry) {
+        let now = Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64;
+        self.record_at(entry, now);
+    }
+
+    /// Entries recorded more than the retention before `now` or beyond the
+    /// max size are forgotten, the oldest first.
+    fn record_at(&self, entry: Entry, now: u64) {
+        if entry.fields.is_empty() {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.size += entry.size();
+        inner.entries.push_back((now, entry));
+        let oldest = now.saturating_sub(self.retention.as_nanos() as u64);
+        while let Some((recorded, entry)) = inner.entries.front() {
+            if *recorded >= oldest && inner.size <= self.max_size {
+                break;
+            }
+            let size = entry.size();
+            inner.size -= size;
+            inner.entries.pop_front();
+        }
+    }
+
+    /// Matching entries ordered as they were recorded.
+    pub fn query(&self, query: &Query) -> Vec<Entry> {
+        let inner = self.inner.lock().unwrap();
+        let mut matched: Vec<_> = inner
+            .entries
+            .iter()
+            .rev()
+            .map(|(_, it)| it)
+            .filter(|it| {
+                it.name == query.name
+                    && (query.start_ns..query.end_ns).contains(&it.ns_ts)
+                    && query.tenant.is_none_or(|t| it.tenant.as_deref() == Some(t))
+                    && query.tags.iter().all(|tag| it.tags.contains(tag))
+            })
+            .take(MAX_RESULTS)
+            .cloned()
+            .collect();
+        matched.reverse();
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Entry, History, Query};
+
+    fn entry(tenant: Option<&str>, cpu: &str, ns_ts: u64) -> Entry {
+        Entry {
+            tenant: tenant.map(str::to_string),
+            name: "cpu".to_string(),
+            tags: vec![("cpu".to_string(), cpu.to_string())],
+            fields: vec![("busy".to_string(), 0.5)],
+            ns_ts,
+        }
+    }
+
+    #[test]
+    fn test_query() {
+        let history = History::new(Duration::from_secs(60), usize::MAX);
+        history.record_at(entry(None, "0", 10), 10);
+        history.record_at(entry(None, "1", 20), 20);
+        history.record_at(entry(Some("acme"), "0", 30), 30);
+
+        let tags = [("cpu".to_string(), "0".to_string())];
+        let query = |tenant, start_ns, end_ns| {
+            let query = Query {
+                tenant,
+                name: "cpu",
+                tags: &tags,
+                start_ns,
+                end_ns,
+            };
+            history
+                .query(&query)
+                .iter()
+                .map(|it| it.ns_ts)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(query(None, 0, u64::MAX), [10, 30]);
+        assert_eq!(query(None, 0, 30), [10]);
+        assert_eq!(query(Some("acme"), 0, u64::MAX), [30]);
+    }
+
+    #[test]
+    fn test_retention_and_size() {
+        let history = History::new(Duration::from_nanos(100), usize::MAX);
+        history.record_at(entry(None, "0", 0), 0);
+        history.record_at(entry(None, "0", 150), 150);
+        assert_eq!(history.inner.lock().unwrap().entries.len(), 1);
+
+        let size = entry(None, "0", 0).size();
+        let history = History::new(Duration::from_secs(60), size * 2);
+        for ns_ts in 0..3 {
+            history.record_at(entry(None, "0", ns_ts), ns_ts);
+        }
+        let inner = history.inner.lock().unwrap();
+        assert_eq!(inner.entries.len(), 2);
+        assert_eq!(inner.size, size * 2);
+    }
+}
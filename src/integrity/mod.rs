@@ -0,0 +1,305 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Read},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::{Context, Result, bail};
+use ring::digest::{Context as Digest, SHA256};
+use serde::{Deserialize, Serialize};
+
+use crate::config::IntegrityConfig;
+
+static GLOBAL: OnceLock<Monitor> = OnceLock::new();
+
+/// The monitor installed by [`init`], `None` if integrity monitoring is disabled.
+pub fn global() -> Option<&'static Monitor> {
+    GLOBAL.get()
+}
+
+pub fn init(cfg: &IntegrityConfig) -> Result<()> {
+    if GLOBAL.set(Monitor::new(cfg)?).is_err() {
+        bail!("Integrity monitor already initialized");
+    }
+    Ok(())
+}
+
+/// What is compared of a file, its contents only by digest.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileState {
+    pub path: String,
+    /// hex encoded, `None` for files over the size limit, of the target
+    /// path for symlinks
+    pub sha256: Option<String>,
+    pub size: u64,
+    /// file type and permission bits
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// Files of a set, by path.
+pub type Snapshot = BTreeMap<String, FileState>;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Drift {
+    /// baseline and current state
+    pub changed: Vec<(FileState, FileState)>,
+    pub added: Vec<FileState>,
+    pub removed: Vec<FileState>,
+}
+
+/// Checksum baselines of the configured path sets.
+pub struct Monitor {
+    sets: BTreeMap<String, Vec<PathBuf>>,
+    max_file_size: u64,
+    max_files: usize,
+    state_file: PathBuf,
+    baselines: Mutex<BTreeMap<String, Snapshot>>,
+}
+
+impl Monitor {
+    /// Loads the baselines saved by an earlier run, those of sets no
+    /// longer configured are dropped.
+    pub fn new(cfg: &IntegrityConfig) -> Result<Self> {
+        let state_file = PathBuf::from(&cfg.state_file);
+        let mut baselines: BTreeMap<String, Snapshot> = match fs::read(&state_file) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Failed to parse {}", state_file.display()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).context(format!("Failed to read {}", state_file.display())),
+        };
+        baselines.retain(|set, _| cfg.sets.contains_key(set));
+        let sets = cfg
+            .sets
+            .iter()
+            .map(|(name, paths)| (name.clone(), paths.iter().map(PathBuf::from).collect()))
+            .collect();
+        Ok(Self {
+            sets,
+            max_file_size: cfg.max_file_size,
+            max_files: cfg.max_files,
+            state_file,
+            baselines: Mutex::new(baselines),
+        })
+    }
+
+    pub fn sets(&self) -> Vec<String> {
+        self.sets.keys().cloned().collect()
+    }
+
+    /// Replaces the baseline of `set` by its current state, returns the
+    /// number of files in it.
+    pub fn baseline(&self, set: &str) -> Result<usize> {
+        let snapshot = self.scan(set)?;
+        let len = snapshot.len();
+        let mut baselines = self.baselines.lock().unwrap();
+        baselines.insert(set.to_string(), snapshot);
+        self.save(&baselines)?;
+        Ok(len)
+    }
+
+    /// Compares the current state of `set` to its baseline, which is kept.
+    pub fn verify(&self, set: &str) -> Result<Drift> {
+        let snapshot = self.scan(set)?;
+        let baselines = self.baselines.lock().unwrap();
+        let Some(baseline) = baselines.get(set) else {
+            bail!("No baseline for set {set}");
+        };
+        Ok(diff(baseline, &snapshot))
+    }
+
+    fn scan(&self, set: &str) -> Result<Snapshot> {
+        let Some(paths) = self.sets.get(set) else {
+            bail!("Unknown set {set}");
+        };
+        let mut snapshot = Snapshot::new();
+        for path in paths {
+            self.walk(path, &mut snapshot)?;
+        }
+        Ok(snapshot)
+    }
+
+    fn walk(&self, path: &Path, snapshot: &mut Snapshot) -> Result<()> {
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            // a removed file shows up as such in the drift
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).context(format!("Failed to stat {}", path.display())),
+        };
+        if metadata.is_dir() {
+            let mut entries = fs::read_dir(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+                .filter_map(|it| it.ok().map(|it| it.path()))
+                .collect::<Vec<_>>();
+            entries.sort();
+            for entry in entries {
+                self.walk(&entry, snapshot)?;
+            }
+            return Ok(());
+        }
+        if snapshot.len() >= self.max_files {
+            bail!("More than {} files", self.max_files);
+        }
+        let sha256 = if metadata.is_symlink() {
+            let target = fs::read_link(path)?;
+            Some(hex(ring::digest::digest(
+                &SHA256,
+                target.as_os_str().as_encoded_bytes(),
+            )
+            .as_ref()))
+        } else if metadata.is_file() && metadata.len() <= self.max_file_size {
+            Some(hash_file(path).with_context(|| format!("Failed to read {}", path.display()))?)
+        } else {
+            None
+        };
+        let path = path.to_string_lossy().into_owned();
+        let state = FileState {
+            path: path.clone(),
+            sha256,
+            size: metadata.len(),
+            mode: metadata.mode(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+        };
+        snapshot.insert(path, state);
+        Ok(())
+    }
+
+    fn save(&self, baselines: &BTreeMap<String, Snapshot>) -> Result<()> {
+        if let Some(dir) = self.state_file.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        // write and rename, a crash must not lose the previous baselines
+        let tmp = self.state_file.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(baselines)?)?;
+        fs::rename(&tmp, &self.state_file)
+            .with_context(|| format!("Failed to write {}", self.state_file.display()))
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut digest = Digest::new(&SHA256);
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => digest.update(&buf[..len]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(hex(digest.finish().as_ref()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|it| format!("{it:02x}")).collect()
+}
+
+pub fn diff(baseline: &Snapshot, current: &Snapshot) -> Drift {
+    let mut drift = Drift::default();
+    for (path, before) in baseline {
+        match current.get(path) {
+            Some(after) if after != before => drift.changed.push((before.clone(), after.clone())),
+            Some(_) => {}
+            None => drift.removed.push(before.clone()),
+        }
+    }
+    drift.added = current
+        .iter()
+        .filter(|(path, _)| !baseline.contains_key(*path))
+        .map(|(_, it)| it.clone())
+        .collect();
+    drift
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs, os::unix::fs::symlink};
+
+    use super::Monitor;
+    use crate::config::IntegrityConfig;
+
+    #[test]
+    fn test_baseline_and_verify() {
+        let dir = std::env::temp_dir().join(format!("psh-integrity-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("conf.d")).unwrap();
+        fs::write(dir.join("a.conf"), "a").unwrap();
+        fs::write(dir.join("conf.d/b.conf"), "b").unwrap();
+        fs::write(dir.join("large"), "too large to hash").unwrap();
+        symlink("a.conf", dir.join("link")).unwrap();
+
+        let cfg = IntegrityConfig {
+            enable: true,
+            state_file: dir
+                .join("state/baselines.json")
+                .to_string_lossy()
+                .into_owned(),
+            max_file_size: 8,
+            max_files: 100,
+            sets: BTreeMap::from([(
+                "test".to_string(),
+                vec![
+                    dir.join("a.conf"),
+                    dir.join("conf.d"),
+                    dir.join("large"),
+                    dir.join("link"),
+                ]
+                .into_iter()
+                .map(|it| it.to_string_lossy().into_owned())
+                .collect(),
+            )]),
+        };
+        let monitor = Monitor::new(&cfg).unwrap();
+        assert!(monitor.verify("test").is_err());
+        assert_eq!(monitor.baseline("test").unwrap(), 4);
+        assert_eq!(monitor.verify("test").unwrap(), Default::default());
+
+        fs::write(dir.join("a.conf"), "changed").unwrap();
+        fs::remove_file(dir.join("conf.d/b.conf")).unwrap();
+        fs::write(dir.join("conf.d/c.conf"), "c").unwrap();
+
+        // baselines survive restarts
+        let monitor = Monitor::new(&cfg).unwrap();
+        let drift = monitor.verify("test").unwrap();
+        assert_eq!(drift.changed.len(), 1);
+        let (before, after) = &drift.changed[0];
+        assert_eq!(
+            before.sha256.as_deref(),
+            Some("ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb")
+        );
+        assert_ne!(before.sha256, after.sha256);
+        assert!(drift.removed[0].path.ends_with("b.conf"));
+        assert!(drift.added[0].path.ends_with("c.conf"));
+
+        let baseline = monitor.baselines.lock().unwrap();
+        let large = baseline["test"]
+            .values()
+            .find(|it| it.path.ends_with("large"));
+        assert_eq!(large.unwrap().sha256, None);
+        drop(baseline);
+
+        assert!(monitor.verify("unknown").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
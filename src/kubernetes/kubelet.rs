@@ -0,0 +1,183 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use reqwest::{Certificate, blocking::Client};
+use serde::Deserialize;
+
+use super::SERVICE_ACCOUNT_DIR;
+
+const KUBELET_PORT: u16 = 10250;
+// pods started since the last refresh are only looked up this often
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// the parts of a kubelet `/pods` response we use
+#[derive(Deserialize)]
+struct PodList {
+    items: Vec<PodItem>,
+}
+
+#[derive(Deserialize)]
+struct PodItem {
+    metadata: ObjectMeta,
+    #[serde(default)]
+    status: PodStatus,
+}
+
+#[derive(Deserialize)]
+struct ObjectMeta {
+    name: String,
+    namespace: String,
+    uid: String,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PodStatus {
+    #[serde(default)]
+    container_statuses: Vec<ContainerStatus>,
+    #[serde(default)]
+    init_container_statuses: Vec<ContainerStatus>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContainerStatus {
+    name: String,
+    /// e.g. `containerd://<id>`, empty until the container started
+    #[serde(default)]
+    container_id: String,
+}
+
+#[derive(Debug)]
+pub struct Pod {
+    pub namespace: String,
+    pub name: String,
+    pub uid: String,
+    pub labels: BTreeMap<String, String>,
+    /// container id to container name
+    containers: HashMap<String, String>,
+}
+
+impl Pod {
+    pub fn container_name(&self, container_id: &str) -> Option<&str> {
+        self.containers.get(container_id).map(String::as_str)
+    }
+}
+
+impl From<PodItem> for Pod {
+    fn from(value: PodItem) -> Self {
+        let containers = value
+            .status
+            .container_statuses
+            .into_iter()
+            .chain(value.status.init_container_statuses)
+            .filter_map(|it| {
+                let (_, id) = it.container_id.split_once("://")?;
+                Some((id.to_string(), it.name))
+            })
+            .collect();
+        Self {
+            namespace: value.metadata.namespace,
+            name: value.metadata.name,
+            uid: value.metadata.uid,
+            labels: value.metadata.labels,
+            containers,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Cache {
+    refreshed: Option<Instant>,
+    pods: HashMap<String, Arc<Pod>>,
+}
+
+/// Pods of this node as listed by the kubelet, authenticated with the
+/// service account token.
+pub struct Kubelet {
+    url: String,
+    client: Client,
+    ttl: Duration,
+    cache: Mutex<Cache>,
+}
+
+impl Kubelet {
+    /// `url` defaults to the kubelet of `NODE_IP`, or localhost with host
+    /// networking.
+    pub fn new(url: Option<String>, insecure_tls: bool, ttl: Duration) -> Result<Self> {
+        let url = url.unwrap_or_else(|| {
+            let ip = env::var("NODE_IP").unwrap_or_else(|_| "127.0.0.1".to_string());
+            format!("https://{ip}:{KUBELET_PORT}")
+        });
+
+        let mut builder = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            // kubelet serving certificates are often self signed
+            .danger_accept_invalid_certs(insecure_tls);
+        if let Ok(pem) = fs::read(Path::new(SERVICE_ACCOUNT_DIR).join("ca.crt")) {
+            builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+        }
+
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            client: builder.build()?,
+            ttl,
+            cache: Mutex::new(Cache::default()),
+        })
+    }
+
+    fn fetch(&self) -> Result<HashMap<String, Arc<Pod>>> {
+        // projected tokens are rotated, read it for every request
+        let token = fs::read_to_string(Path::new(SERVICE_ACCOUNT_DIR).join("token"))
+            .context("Failed to read the service account token")?;
+        let body = self
+            .client
+            .get(format!("{}/pods", self.url))
+            .bearer_auth(token.trim())
+            .send()?
+            .error_for_status()?
+            .text()?;
+        let list: PodList = serde_json::from_str(&body)?;
+        Ok(list
+            .items
+            .into_iter()
+            .map(|it| (it.metadata.uid.clone(), Arc::new(Pod::from(it))))
+            .collect())
+    }
+
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn pod(&self, uid: &str) -> Result<Option<Arc<Pod>>> {
+        let mut cache = self.cache.lock().unwrap();
+        let older_than = |age| cache.refreshed.is_none_or(|at| at.elapsed() > age);
+        let stale = older_than(self.ttl);
+        let missing = !cache.pods.contains_key(uid) && older_than(MIN_REFRESH_INTERVAL);
+        if stale || missing {
+            cache.pods = self.fetch()?;
+            cache.refreshed = Some(Instant::now());
+        }
+        Ok(cache.pods.get(uid).cloned())
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+mod kubelet;
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::Path,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use anyhow::{Result, bail};
+pub use kubelet::{Kubelet, Pod};
+
+use crate::config::KubernetesConfig;
+
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+static GLOBAL: OnceLock<Kubernetes> = OnceLock::new();
+
+/// The integration installed by [`init`], `None` if disabled.
+pub fn global() -> Option<&'static Kubernetes> {
+    GLOBAL.get()
+}
+
+pub fn init(cfg: &KubernetesConfig) -> Result<()> {
+    if GLOBAL.set(Kubernetes::new(cfg)?).is_err() {
+        bail!("Kubernetes integration already initialized");
+    }
+    Ok(())
+}
+
+/// The pod psh runs in, as told by the downward API.
+///
+/// The DaemonSet passes `NODE_NAME`, `POD_NAME` and `POD_NAMESPACE` as env
+/// vars and mounts the pod labels as a downward API volume.
+#[derive(Debug, Clone, Default)]
+pub struct Identity {
+    pub node_name: Option<String>,
+    pub pod_name: Option<String>,
+    pub namespace: Option<String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+impl Identity {
+    pub fn from_downward_api(labels_file: &Path) -> Self {
+        let var = |name| env::var(name).ok().filter(|it| !it.is_empty());
+        let namespace = var("POD_NAMESPACE").or_else(|| {
+            fs::read_to_string(Path::new(SERVICE_ACCOUNT_DIR).join("namespace"))
+                .ok()
+                .map(|it| it.trim().to_string())
+        });
+        let labels = fs::read_to_string(labels_file)
+            .map(|it| parse_labels(&it))
+            .unwrap_or_default();
+        Self {
+            node_name: var("NODE_NAME"),
+            pod_name: var("POD_NAME"),
+            namespace,
+            labels,
+        }
+    }
+
+    /// Tags attached to exported data, named after the semantic conventions.
+    pub fn tags(&self) -> Vec<(String, String)> {
+        let fields = [
+            ("k8s.node.name", &self.node_name),
+            ("k8s.pod.name", &self.pod_name),
+            ("k8s.namespace.name", &self.namespace),
+        ];
+        fields
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.to_string(), value.clone()?)))
+            .chain(
+                self.labels
+                    .iter()
+                    .map(|(k, v)| (format!("k8s.pod.label.{k}"), v.clone())),
+            )
+            .collect()
+    }
+}
+
+/// Parses a downward API labels file, one `key="value"` per line with the
+/// value quoted like a Go string.
+fn parse_labels(content: &str) -> BTreeMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+            let mut unquoted = String::with_capacity(value.len());
+            let mut chars = value.chars();
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    unquoted.push(c);
+                    continue;
+                }
+                unquoted.push(match chars.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    escaped => escaped,
+                });
+            }
+            Some((key.trim().to_string(), unquoted))
+        })
+        .collect()
+}
+
+/// The pod uid and the container id found in the contents of
+/// `/proc/<pid>/cgroup`.
+///
+/// Handles both the cgroupfs (`/kubepods/burstable/pod<uid>/<id>`) and the
+/// systemd (`kubepods-burstable-pod<uid>.slice/cri-containerd-<id>.scope`)
+/// layouts, the systemd one replaces dashes of the uid with underscores.
+fn pod_of_cgroup(content: &str) -> Option<(String, Option<String>)> {
+    content.lines().find_map(|line| {
+        // hierarchy-id:controllers:path
+        let path = line.splitn(3, ':').nth(2)?;
+        let segments: Vec<_> = path.split('/').collect();
+        let (index, uid) = segments.iter().enumerate().find_map(|(index, segment)| {
+            let (_, uid) = segment.rsplit_once("pod")?;
+            let uid = uid.trim_end_matches(".slice").replace('_', "-");
+            let valid = uid.len() == 36 && uid.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+            valid.then_some((index, uid))
+        })?;
+        let container = segments.get(index + 1).and_then(|segment| {
+            let id = segment.trim_end_matches(".scope");
+            // e.g. `cri-containerd-<id>`, `docker-<id>` or `crio-<id>`
+            let id = id.rsplit_once('-').map_or(id, |(_, id)| id);
+            (id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit())).then(|| id.to_string())
+        });
+        Some((uid, container))
+    })
+}
+
+/// The pod of a process and the name of its container.
+#[derive(Debug, Clone)]
+pub struct ProcessPod {
+    pub pod: Arc<Pod>,
+    pub container: Option<String>,
+}
+
+pub struct Kubernetes {
+    pub identity: Identity,
+    kubelet: Option<Kubelet>,
+}
+
+impl Kubernetes {
+    pub fn new(cfg: &KubernetesConfig) -> Result<Self> {
+        let identity = Identity::from_downward_api(Path::new(&cfg.labels_file));
+        let kubelet = if cfg.kubelet {
+            Some(Kubelet::new(
+                cfg.kubelet_url.clone(),
+                cfg.kubelet_insecure_tls,
+                Duration::from_secs(cfg.pod_cache_ttl),
+            )?)
+        } else {
+            None
+        };
+        Ok(Self { identity, kubelet })
+    }
+
+    /// `None` for processes outside of pods.
+    pub fn pod_of_process(&self, pid: u32) -> Result<Option<ProcessPod>> {
+        let Some(kubelet) = &self.kubelet else {
+            bail!("Kubelet lookups are disabled");
+        };
+        let cgroup = fs::read_to_string(format!("/proc/{pid}/cgroup"))?;
+        let Some((uid, container_id)) = pod_of_cgroup(&cgroup) else {
+            return Ok(None);
+        };
+        let Some(pod) = kubelet.pod(&uid)? else {
+            return Ok(None);
+        };
+        let container = container_id
+            .and_then(|id| pod.container_name(&id))
+            .map(str::to_string);
+        Ok(Some(ProcessPod { pod, container }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_labels, pod_of_cgroup};
+
+    #[test]
+    fn test_parse_labels() {
+        let labels =
+            parse_labels("app=\"psh\"\npod-template-hash=\"5d4f\"\nnote=\"a \\\"b\\\"\"\n");
+        assert_eq!(labels.len(), 3);
+        assert_eq!(labels["app"], "psh");
+        assert_eq!(labels["note"], "a \"b\"");
+    }
+
+    #[test]
+    fn test_pod_of_cgroup() {
+        let id = "4f1c2b0d9e8a7f6e5d4c3b2a1f0e9d8c7b6a5f4e3d2c1b0a9f8e7d6c5b4a3f2e";
+        let systemd = format!(
+            "0::/kubepods.slice/kubepods-burstable.slice/\
+             kubepods-burstable-pod6b2f1a3c_8d4e_4f5a_9b6c_7d8e9f0a1b2c.slice/\
+             cri-containerd-{id}.scope\n"
+        );
+        assert_eq!(
+            pod_of_cgroup(&systemd),
+            Some((
+                "6b2f1a3c-8d4e-4f5a-9b6c-7d8e9f0a1b2c".to_string(),
+                Some(id.to_string())
+            ))
+        );
+
+        let cgroupfs = "12:memory:/kubepods/besteffort/pod6b2f1a3c-8d4e-4f5a-9b6c-7d8e9f0a1b2c\n";
+        assert_eq!(
+            pod_of_cgroup(cgroupfs),
+            Some(("6b2f1a3c-8d4e-4f5a-9b6c-7d8e9f0a1b2c".to_string(), None))
+        );
+
+        assert_eq!(pod_of_cgroup("0::/user.slice/user-1000.slice\n"), None);
+    }
+}
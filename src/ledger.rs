@@ -0,0 +1,414 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::{Context, Result, bail};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    at_rest::{self, Sealer},
+    config::LedgerConfig,
+    runtime::CpuTimeExceeded,
+};
+
+static GLOBAL: OnceLock<Ledger> = OnceLock::new();
+
+/// The ledger installed by [`init`], `None` if it is disabled.
+pub fn global() -> Option<&'static Ledger> {
+    GLOBAL.get()
+}
+
+/// Loads the ledger file and installs the global ledger.
+pub fn init(cfg: &LedgerConfig) -> Result<()> {
+    let mut ledger = Ledger::open_sealed(&cfg.path, cfg.max_entries.max(1), at_rest::global())?;
+    ledger.meter_fuel = cfg.fuel;
+    ledger.record_seed = cfg.record_seed;
+    if GLOBAL.set(ledger).is_err() {
+        bail!("Run ledger already initialized");
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Success,
+    Failed,
+    /// interrupted by the CPU time limit
+    TimedOut,
+}
+
+pub fn exit_code(result: &anyhow::Result<()>) -> Option<i32> {
+    result
+        .as_ref()
+        .err()
+        .and_then(|e| e.downcast_ref::<wasmtime_wasi::I32Exit>())
+        .map(|it| it.0)
+}
+
+impl RunStatus {
+    pub fn of(result: &anyhow::Result<()>) -> Self {
+        let success = match exit_code(result) {
+            Some(code) => code == 0,
+            None => result.is_ok(),
+        };
+        match result {
+            _ if success => Self::Success,
+            Err(e) if e.downcast_ref::<CpuTimeExceeded>().is_some() => Self::TimedOut,
+            _ => Self::Failed,
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failed => "failed",
+            Self::TimedOut => "timed_out",
+        }
+    }
+}
+
+/// One component run.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Run {
+    /// `None` for components started from the command line or config
+    pub task_id: Option<String>,
+    /// arguments of the component, the first is its path or task id
+    pub args: Vec<String>,
+    pub tenant: Option<String>,
+    /// in milliseconds since the epoch
+    pub start_ms: i64,
+    /// in milliseconds since the epoch
+    pub end_ms: i64,
+    pub status: RunStatus,
+    pub exit_code: Option<i32>,
+    pub error: Option<String>,
+    /// `None` unless fuel is metered
+    pub fuel_used: Option<u64>,
+    pub bytes_exported: u64,
+    /// of wasi:random/insecure, replaying the run with it gives the same numbers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl Run {
+    /// Fills the status, exit code and error from the result of the run.
+    pub fn with_result(mut self, result: &anyhow::Result<()>) -> Self {
+        let exit_code = exit_code(result);
+        self.status = RunStatus::of(result);
+        let success = self.status == RunStatus::Success;
+        self.exit_code = exit_code.or(success.then_some(0));
+        self.error = result
+            .as_ref()
+            .err()
+            .filter(|_| !success)
+            .map(|e| format!("{e:#}"));
+        self
+    }
+}
+
+/// Last runs of components, kept in memory and appended to a JSON lines file.
+pub struct Ledger {
+    path: PathBuf,
+    max_entries: usize,
+    meter_fuel: bool,
+    record_seed: bool,
+    // encrypts every line
+    sealer: Option<&'static Sealer>,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    runs: VecDeque<Run>,
+    file: File,
+    /// lines appended since the file was last compacted
+    appended: usize,
+}
+
+impl Ledger {
+    pub fn open(path: impl Into<PathBuf>, max_entries: usize) -> Result<Self> {
+        Self::open_sealed(path, max_entries, None)
+    }
+
+    /// Like [`Self::open`], lines are encrypted with `sealer` if given.
+    /// Plain lines of a ledger written before are still read.
+    ///
+    /// Fails on a complete line that can't be read, e.g. sealed with
+    /// another key, instead of dropping it on the next compaction.
+    pub fn open_sealed(
+        path: impl Into<PathBuf>,
+        max_entries: usize,
+        sealer: Option<&'static Sealer>,
+    ) -> Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let mut runs = VecDeque::with_capacity(max_entries);
+        if let Ok(content) = fs::read_to_string(&path) {
+            for (i, line) in content.split_inclusive('\n').enumerate() {
+                let run = match decode_line(line, sealer) {
+                    Ok(run) => run,
+                    // the last line may be cut short by a crash
+                    Err(_) if !line.ends_with('\n') => break,
+                    Err(e) => bail!(
+                        "Failed to read line {} of {}, is it sealed with another key? {e}",
+                        i + 1,
+                        path.display()
+                    ),
+                };
+                if runs.len() == max_entries {
+                    runs.pop_front();
+                }
+                runs.push_back(run);
+            }
+        }
+        let file = compact(&path, &runs, sealer)?;
+        Ok(Self {
+            path,
+            max_entries,
+            meter_fuel: false,
+            record_seed: false,
+            sealer,
+            inner: Mutex::new(Inner {
+                runs,
+                file,
+                appended: 0,
+            }),
+        })
+    }
+
+    pub fn record(&self, run: Run) -> Result<()> {
+        let line = encode_line(&run, self.sealer)?;
+
+        let mut inner = self.inner.lock().unwrap();
+        if inner.runs.len() == self.max_entries {
+            inner.runs.pop_front();
+        }
+        inner.runs.push_back(run);
+        inner.appended += 1;
+        // the file holds at most twice the entries kept in memory
+        if inner.appended >= self.max_entries {
+            inner.file = compact(&self.path, &inner.runs, self.sealer)?;
+            inner.appended = 0;
+        } else {
+            inner.file.write_all(&line)?;
+        }
+        Ok(())
+    }
+
+    /// Whether components should be run with fuel metering.
+    pub const fn meter_fuel(&self) -> bool {
+        self.meter_fuel
+    }
+
+    /// Whether runs without a seed of their own get a recorded one.
+    pub const fn record_seed(&self) -> bool {
+        self.record_seed
+    }
+
+    /// The last `n` runs, oldest first.
+    pub fn recent(&self, n: usize) -> Vec<Run> {
+        let inner = self.inner.lock().unwrap();
+        let skip = inner.runs.len().saturating_sub(n);
+        inner.runs.iter().skip(skip).cloned().collect()
+    }
+
+    /// e.g. `runs=12,failed=1,last=3f2e...:success:1700000000000`
+    pub fn summary(&self) -> String {
+        let inner = self.inner.lock().unwrap();
+        let failed = inner
+            .runs
+            .iter()
+            .filter(|it| it.status != RunStatus::Success)
+            .count();
+        let mut summary = format!("runs={},failed={failed}", inner.runs.len());
+        if let Some(last) = inner.runs.back() {
+            let status = last.status.as_str();
+            summary.push_str(&format!(
+                ",last={}:{status}:{}",
+                last.task_id
+                    .as_deref()
+                    .or(last.args.first().map(String::as_str))
+                    .unwrap_or_default(),
+                last.end_ms
+            ));
+        }
+        summary
+    }
+}
+
+/// Reads the runs of a ledger file without opening it for appending.
+pub fn read(path: impl AsRef<Path>, sealer: Option<&Sealer>) -> Result<Vec<Run>> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut runs = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(run) = decode_line(&line?, sealer) {
+            runs.push(run);
+        }
+    }
+    Ok(runs)
+}
+
+/// A seed for wasi:random/insecure drawn from the OS's randomness.
+pub fn new_seed() -> Option<u64> {
+    let mut seed = [0; 8];
+    SystemRandom::new().fill(&mut seed).ok()?;
+    Some(u64::from_le_bytes(seed))
+}
+
+fn encode_line(run: &Run, sealer: Option<&Sealer>) -> Result<Vec<u8>> {
+    let json = serde_json::to_vec(run)?;
+    let mut line = match sealer {
+        Some(sealer) => sealer.seal_line(&json)?.into_bytes(),
+        None => json,
+    };
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn decode_line(line: &str, sealer: Option<&Sealer>) -> Result<Run> {
+    if let Ok(run) = serde_json::from_str(line) {
+        return Ok(run);
+    }
+    let Some(sealer) = sealer else {
+        bail!("Not a run");
+    };
+    Ok(serde_json::from_slice(&sealer.open_line(line)?)?)
+}
+
+/// Rewrites the ledger with `runs` only, returns it opened for appending.
+fn compact(path: &Path, runs: &VecDeque<Run>, sealer: Option<&Sealer>) -> Result<File> {
+    let tmp = path.with_extension("tmp");
+    let mut content = Vec::new();
+    for run in runs {
+        content.extend(encode_line(run, sealer)?);
+    }
+    fs::write(&tmp, content).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(OpenOptions::new().append(true).open(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Ledger, Run, RunStatus};
+    use crate::{at_rest::Sealer, runtime::CpuTimeExceeded};
+
+    fn run(task_id: &str, result: &anyhow::Result<()>) -> Run {
+        Run {
+            task_id: Some(task_id.to_string()),
+            args: vec![task_id.to_string()],
+            tenant: None,
+            start_ms: 1_700_000_000_000,
+            end_ms: 1_700_000_001_000,
+            status: RunStatus::Success,
+            exit_code: None,
+            error: None,
+            fuel_used: None,
+            bytes_exported: 0,
+            seed: None,
+        }
+        .with_result(result)
+    }
+
+    #[test]
+    fn test_ledger_persists_last_runs() {
+        let path = std::env::temp_dir().join(format!("psh-ledger-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let ledger = Ledger::open(&path, 2).unwrap();
+        ledger.record(run("a", &Ok(()))).unwrap();
+        ledger
+            .record(run("b", &Err(anyhow::anyhow!("Time slice exhausted"))))
+            .unwrap();
+        ledger
+            .record(run("c", &Err(wasmtime_wasi::I32Exit(0).into())))
+            .unwrap();
+        drop(ledger);
+
+        let ledger = Ledger::open(&path, 2).unwrap();
+        let runs = ledger.recent(10);
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].status, RunStatus::Failed);
+        assert_eq!(runs[0].error.as_deref(), Some("Time slice exhausted"));
+        assert_eq!(runs[1].status, RunStatus::Success);
+        assert_eq!(runs[1].exit_code, Some(0));
+        assert_eq!(
+            ledger.summary(),
+            "runs=2,failed=1,last=c:success:1700000001000"
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_cpu_time_exceeded_is_timed_out() {
+        let exceeded = anyhow::Error::from(CpuTimeExceeded { limit_ms: 5000 });
+        let run = run("a", &Err(exceeded.context("Failed to run component")));
+        assert_eq!(run.status, RunStatus::TimedOut);
+        assert_eq!(
+            run.error.as_deref(),
+            Some("Failed to run component: CPU time limit of 5000 ms exceeded")
+        );
+        assert_eq!(run.exit_code, None);
+    }
+
+    #[test]
+    fn test_sealed_ledger() {
+        let path =
+            std::env::temp_dir().join(format!("psh-ledger-sealed-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sealer: &'static Sealer = Box::leak(Box::new(Sealer::new(&[7; 32]).unwrap()));
+
+        // plain runs of before are kept and sealed on compaction
+        let ledger = Ledger::open(&path, 3).unwrap();
+        ledger.record(run("plain", &Ok(()))).unwrap();
+        drop(ledger);
+        let ledger = Ledger::open_sealed(&path, 3, Some(sealer)).unwrap();
+        ledger.record(run("sealed", &Ok(()))).unwrap();
+        drop(ledger);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("plain") && !content.contains("sealed"));
+
+        let ledger = Ledger::open_sealed(&path, 3, Some(sealer)).unwrap();
+        let ids: Vec<_> = ledger
+            .recent(10)
+            .into_iter()
+            .filter_map(|it| it.task_id)
+            .collect();
+        assert_eq!(ids, ["plain", "sealed"]);
+        drop(ledger);
+        let content = std::fs::read_to_string(&path).unwrap();
+
+        // runs of another key are neither dropped nor compacted away
+        let other: &'static Sealer = Box::leak(Box::new(Sealer::new(&[8; 32]).unwrap()));
+        assert!(Ledger::open_sealed(&path, 3, Some(other)).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+
+        // a line cut short by a crash is skipped
+        std::fs::write(&path, format!("{content}abcd")).unwrap();
+        let ledger = Ledger::open_sealed(&path, 3, Some(sealer)).unwrap();
+        assert_eq!(ledger.recent(10).len(), 2);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -13,89 +13,252 @@
 // see <https://www.gnu.org/licenses/>.
 
 mod args;
+mod at_rest;
+mod blackbox;
+mod build_info;
+mod bundle;
+mod bursts;
+mod bus;
+mod capture;
+mod catalog;
+mod certs;
 mod config;
 mod daemon;
+mod drift;
+mod history;
+mod integrity;
+mod kubernetes;
+mod ledger;
 mod log;
+mod msr;
 mod otlp;
+mod probe;
 mod runtime;
 mod services;
+mod store;
+mod symbolize;
+mod sysfs;
+mod trace;
 
 use std::{fs, thread, time::Duration};
 
-use anyhow::{Error, Result, bail};
-use args::Args;
-use chrono::{TimeZone, Utc};
+use anyhow::{Context, Error, Result, bail};
+use args::{Args, Command};
 use clap::Parser;
-use config::RemoteConfig;
-use daemon::{get_daemon_wasm_args, spawn_daemon};
+use config::{Config, ControlConfig, HealthConfig, RemoteConfig, RestartPolicy, SelfProfileConfig};
+use daemon::{get_daemon_components, spawn_daemon};
+use host_op_system::{environ::EnvironPolicy, redact::Redactor};
 use log::log_init;
 use mimalloc::MiMalloc;
 use nix::unistd::geteuid;
 use opentelemetry_otlp::ExportConfig;
 use psh_proto::HeartbeatReq;
-use runtime::{Task, TaskRuntime};
-use services::rpc::RpcClient;
-use tokio::try_join;
+use runtime::{
+    Task, TaskRuntime, Tenants, pipeline::Pipelines, scheduler::Scheduled, supervisor::Backoff,
+};
+use services::{commands::Dispatcher, rpc::RpcClient};
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    try_join,
+};
 
 #[global_allocator]
 static GLOBAL: MiMalloc = mimalloc::MiMalloc;
 
+// cancelled components still have to leave the engine after their grace
+const SHUTDOWN_MARGIN: Duration = Duration::from_millis(500);
+const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+// exporters of finished components get this long to send what is left
+const SHUTDOWN_DRAIN: Duration = Duration::from_secs(5);
+
 fn main() -> Result<()> {
     log_init();
 
+    let args = Args::parse();
+    if let Some(&Command::Version { json }) = args.command.as_ref() {
+        let info = build_info::get();
+        if json {
+            println!("{}", info.to_json());
+        } else {
+            println!("{info}");
+        }
+        return Ok(());
+    }
+    // offline, needs neither root nor a config file written
+    if let Some(Command::Validate { path, name, tenant }) = args.command.as_ref() {
+        let cfg = config::read_or_template(&args.config)?;
+        return runtime::validate::run(path, &cfg, name.as_deref(), tenant.as_deref());
+    }
+
     if !geteuid().is_root() {
         bail!("Insufficient privileges. Please run psh with root permissions.");
     }
 
-    let args = Args::parse();
     let cfg = config::read_or_gen(args.config.clone())?;
+    let dispatcher = Dispatcher::new(args.config.clone(), &cfg.catalog);
 
-    let wasm_with_args = match args {
-        Args {
-            daemon: true,
-            wasm_with_args: None,
-            ..
-        } => {
-            spawn_daemon(cfg.daemon.clone())?;
-            get_daemon_wasm_args(cfg.daemon.wasm.clone())
-        }
-        Args {
-            daemon: true,
-            wasm_with_args: Some(_),
-            ..
-        } => {
-            bail!("Invalid argument, WASM can only be configured in the config file in daemon mode")
+    match &args.command {
+        Some(Command::Component(command)) => {
+            return catalog::run(command, &cfg.catalog, &cfg.remote.token);
         }
-        Args {
-            daemon: false,
-            wasm_from_daemon_config,
-            wasm_with_args,
-            ..
-        } => {
-            if wasm_from_daemon_config {
-                get_daemon_wasm_args(cfg.daemon.wasm.clone())
-            } else {
-                wasm_with_args
-            }
+        Some(Command::Decrypt { path }) => return at_rest::decrypt(path, &cfg.at_rest),
+        Some(Command::Precompile { paths }) => {
+            let fuel = cfg.ledger.enable && cfg.ledger.fuel;
+            return runtime::cache::precompile(paths, &cfg.engine, fuel);
+        }
+        Some(Command::Bundle { output }) => {
+            return bundle::run(&args.config, &cfg, output.as_deref());
+        }
+        Some(Command::Isolated) => {
+            init_host_ops(&cfg)?;
+            return runtime::isolate::serve(&cfg);
         }
+        Some(Command::Version { .. } | Command::Validate { .. }) | None => {}
+    }
+    // read by child processes, the daemon leaves the working directory
+    let isolate = cfg
+        .engine
+        .isolate
+        .then(|| fs::canonicalize(&args.config))
+        .transpose()
+        .context("Failed to resolve the config file")?;
+
+    let cli_components = args.components();
+    let mut components = if args.daemon {
+        if !cli_components.is_empty() {
+            bail!("Invalid argument, WASM can only be configured in the config file in daemon mode")
+        }
+        spawn_daemon(cfg.daemon.clone())?;
+        get_daemon_components(&cfg.daemon)?
+    } else if args.wasm_from_daemon_config {
+        get_daemon_components(&cfg.daemon)?
+    } else {
+        cli_components
     };
+    catalog::download_components(&mut components, &cfg.catalog)?;
+
+    if cfg.remote.upload.enable {
+        services::upload_budget::init(&cfg.remote.upload)?;
+    }
+
+    init_host_ops(&cfg)?;
+
+    // after `[at_rest]`, which seals the files written
+    runtime::exporter::init(&cfg.remote.exporters)?;
+
+    if cfg.blackbox.enable {
+        blackbox::spawn(&cfg.blackbox)?;
+    }
+
+    if cfg.bursts.enable {
+        bursts::spawn(&cfg.bursts)?;
+    }
+
+    if cfg.history.enable {
+        history::init(&cfg.history)?;
+    }
+
+    if cfg.store.enable {
+        store::init(&cfg.store)?;
+    }
+
+    if cfg.bus.enable {
+        bus::init(&cfg.bus)?;
+    }
+
+    if cfg.kernel_events.enable {
+        services::kernel_events::spawn(&cfg.kernel_events)?;
+    }
+
+    if cfg.process_events.enable {
+        services::process_events::spawn(&cfg.process_events)?;
+    }
+
+    if cfg.kubernetes.enable {
+        kubernetes::init(&cfg.kubernetes)?;
+    }
 
-    let task_rt = TaskRuntime::new()?;
+    if cfg.ledger.enable {
+        ledger::init(&cfg.ledger)?;
+    }
 
-    if let Some(args) = wasm_with_args {
-        let task = Task {
-            id: None,
-            wasm_component: fs::read(&args[0])?,
-            wasm_component_args: args,
-            end_time: Utc.with_ymd_and_hms(3000, 1, 1, 1, 1, 1).unwrap(),
+    if cfg.integrity.enable {
+        integrity::init(&cfg.integrity)?;
+    }
+
+    if cfg.drift.enable {
+        drift::spawn(&cfg.drift)?;
+    }
+
+    let mut task_rt = TaskRuntime::new(Tenants::new(&cfg.tenants)?, &cfg.engine)?;
+    task_rt.set_pipelines(Pipelines::new(&components, &cfg.engine.handoff_dir)?);
+    if let Some(config) = isolate {
+        task_rt.set_isolation(config.to_string_lossy().into_owned());
+    }
+
+    let mut scheduled = vec![];
+    let mut watched = vec![];
+    let mut supervised = vec![];
+    for component in components {
+        if component.restart != RestartPolicy::Never
+            && (component.after.is_some() || component.schedule.is_some())
+        {
+            bail!(
+                "Component {} runs after another one or on a schedule, it can't be restarted",
+                component.name()
+            );
+        }
+        // runs once the one before has succeeded
+        if component.after.is_some() {
+            continue;
+        }
+        let Some(schedule) = &component.schedule else {
+            if component.restart != RestartPolicy::Never {
+                supervised.push(component);
+            } else if cfg.engine.hot_reload {
+                watched.push(component);
+            } else {
+                task_rt.schedule(Task::local(&component)?)?;
+            }
+            continue;
         };
-        task_rt.schedule(task)?;
-    };
+        let schedule = schedule
+            .parse()
+            .with_context(|| format!("Invalid schedule of {}", component.path))?;
+        scheduled.push(Scheduled {
+            schedule,
+            component,
+        });
+    }
+    if !scheduled.is_empty() {
+        runtime::scheduler::spawn(task_rt.sender(), scheduled)?;
+    }
+    if !watched.is_empty() {
+        runtime::watcher::spawn(task_rt.sender(), watched)?;
+    }
+    if !supervised.is_empty() {
+        runtime::supervisor::spawn(
+            task_rt.sender(),
+            task_rt.cancellations(),
+            supervised,
+            Backoff::new(&cfg.engine),
+        )?;
+    }
 
     thread::spawn(move || -> Result<()> {
         let rt = tokio::runtime::Runtime::new()?;
-        let tasks = async_tasks(cfg.remote, task_rt);
+        let tasks = async_tasks(
+            cfg.remote,
+            cfg.health,
+            cfg.self_profile,
+            cfg.control,
+            cfg.kernel_events.forward_rpc,
+            task_rt,
+            dispatcher,
+        );
         rt.block_on(tasks)?;
+        // servers blocked in accept or read are not waited for
+        rt.shutdown_timeout(SHUTDOWN_MARGIN);
         Ok(())
     })
     .join()
@@ -104,19 +267,95 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// What host interfaces need, for the daemon and the child processes of
+/// `[engine] isolate` alike.
+fn init_host_ops(cfg: &Config) -> Result<()> {
+    // before the ledger and black box, which write sealed files
+    if cfg.at_rest.enable {
+        at_rest::init(&cfg.at_rest)?;
+    }
+
+    if cfg.environ.enable {
+        host_op_system::environ::init(EnvironPolicy::new(&cfg.environ.allow, &cfg.environ.deny))?;
+    }
+
+    if cfg.redact.enable {
+        let redact = &cfg.redact;
+        host_op_system::redact::init(Redactor::new(
+            &redact.fields,
+            &redact.patterns,
+            &redact.mask,
+        )?)?;
+    }
+
+    symbolize::init(&cfg.symbolize)?;
+
+    if cfg.capture.enable {
+        capture::init(&cfg.capture)?;
+    }
+
+    if cfg.probe.enable {
+        probe::init(&cfg.probe)?;
+    }
+
+    if cfg.certs.enable {
+        certs::init(&cfg.certs)?;
+    }
+
+    if cfg.msr.enable {
+        msr::init(&cfg.msr)?;
+    }
+
+    if cfg.sysfs.enable {
+        sysfs::init(&cfg.sysfs)?;
+    }
+
+    if cfg.pmu_events.enable {
+        host_op_perf::pmu_events::init(&cfg.pmu_events.dir)?;
+    }
+
+    if cfg.engine.cache {
+        if let Err(e) = runtime::cache::init(&cfg.engine) {
+            tracing::warn!("Running components uncached: {e:#}");
+        }
+    }
+
+    if !cfg.engine.policy.is_empty() {
+        runtime::policy::init(&cfg.engine.policy)?;
+    }
+
+    Ok(())
+}
+
 #[expect(clippy::significant_drop_tightening)]
-async fn async_tasks(remote_cfg: RemoteConfig, mut task_rt: TaskRuntime) -> Result<()> {
+async fn async_tasks(
+    remote_cfg: RemoteConfig,
+    health_cfg: HealthConfig,
+    self_profile_cfg: SelfProfileConfig,
+    control_cfg: ControlConfig,
+    forward_kernel_events: bool,
+    mut task_rt: TaskRuntime,
+    dispatcher: Dispatcher,
+) -> Result<()> {
     let token_cloned = remote_cfg.token.clone();
+    let rpc_enabled = remote_cfg.rpc.enable;
+    let (cancellations, cancel_grace) = (task_rt.cancellations(), task_rt.cancel_grace());
+    let export_tasks = task_rt.export_tasks();
     let rpc_task = async move {
         if !remote_cfg.rpc.enable {
             let handle = task_rt.spawn(
                 None,
                 remote_cfg.rpc.data_export.buf_size,
                 remote_cfg.rpc.data_export.buf_watermark,
+                remote_cfg.rpc.data_export.normalize,
+                remote_cfg.rpc.data_export.timestamp.clone(),
                 "unknown".to_string(),
             )?;
             drop(task_rt);
-            handle.join().expect("TaskRuntime has panicked");
+            // joined off the executor, the other tasks keep running meanwhile
+            tokio::task::spawn_blocking(move || handle.join())
+                .await?
+                .expect("TaskRuntime has panicked");
             return Ok(());
         }
 
@@ -136,9 +375,16 @@ async fn async_tasks(remote_cfg: RemoteConfig, mut task_rt: TaskRuntime) -> Resu
             Some(client.clone()),
             remote_cfg.rpc.data_export.buf_size,
             remote_cfg.rpc.data_export.buf_watermark,
+            remote_cfg.rpc.data_export.normalize,
+            remote_cfg.rpc.data_export.timestamp.clone(),
             instance_id.clone(),
         )?;
         client.send_host_info(instance_id.clone()).await?;
+        services::health::global().set_rpc_connected(true);
+        if let Some(hub) = services::kernel_events::global().filter(|_| forward_kernel_events) {
+            services::kernel_events::forward_to_rpc(hub, client.clone(), instance_id.clone());
+        }
+        drift::forward_to_rpc(client.clone(), instance_id.clone());
         loop {
             let idle = task_rt.is_idle();
             if idle {
@@ -153,15 +399,26 @@ async fn async_tasks(remote_cfg: RemoteConfig, mut task_rt: TaskRuntime) -> Resu
                 }
             }
 
-            client
-                .heartbeat(HeartbeatReq {
-                    instance_id: instance_id.clone(),
-                    idle,
-                })
-                .await?;
+            let commands = client
+                .heartbeat(
+                    HeartbeatReq {
+                        instance_id: instance_id.clone(),
+                        idle,
+                    },
+                    remote_cfg.rpc.heartbeat_commands,
+                )
+                .await
+                .inspect_err(|_| services::health::global().set_rpc_connected(false))?;
+            for command in &commands {
+                if let Err(e) = dispatcher.dispatch(command, &task_rt) {
+                    tracing::warn!("Server command {command}: {e:#}");
+                }
+            }
 
-            if let Some(id) = task_rt.finished_task_id() {
-                let _ = client.task_done(id).await;
+            if let Some(task) = task_rt.finished_task() {
+                let _ = client
+                    .task_done(task.id, task.status, task.result.as_ref())
+                    .await;
             }
 
             tokio::time::sleep(duration).await;
@@ -181,6 +438,7 @@ async fn async_tasks(remote_cfg: RemoteConfig, mut task_rt: TaskRuntime) -> Resu
         let otlp = otlp::Otlp::new(
             remote_cfg.token,
             Duration::from_secs(remote_cfg.otlp.interval),
+            remote_cfg.otlp.semconv,
             export_conf,
         )?;
 
@@ -188,7 +446,93 @@ async fn async_tasks(remote_cfg: RemoteConfig, mut task_rt: TaskRuntime) -> Resu
         Ok::<(), Error>(())
     };
 
-    try_join!(rpc_task, otlp_task)?;
+    let health_task = async {
+        if !health_cfg.enable {
+            return Ok(());
+        }
+        services::health::serve(&health_cfg, rpc_enabled).await
+    };
+
+    let ping_task = async {
+        if !health_cfg.ping.enable {
+            return Ok(());
+        }
+        services::health::serve_ping(&health_cfg.ping, rpc_enabled).await
+    };
+
+    let blackbox_task = async {
+        let Some(blackbox) = blackbox::global() else {
+            return Ok(());
+        };
+        let mut sigusr2 = signal(SignalKind::user_defined2())?;
+        while sigusr2.recv().await.is_some() {
+            if let Err(e) = blackbox.dump("sigusr2") {
+                tracing::error!("Black box: {e}");
+            }
+        }
+        Ok::<(), Error>(())
+    };
+
+    let self_profile_task = async move {
+        if !self_profile_cfg.enable {
+            return Ok(());
+        }
+        services::self_profile::listen(self_profile_cfg).await
+    };
+
+    let control_task = async {
+        if !control_cfg.enable {
+            return Ok(());
+        }
+        services::control::serve(&control_cfg).await
+    };
+
+    // components get the grace period to export what they have
+    let shutdown_task = async {
+        let mut sigterm = signal(SignalKind::terminate())?;
+        let mut sigint = signal(SignalKind::interrupt())?;
+        tokio::select! {
+            _ = sigterm.recv() => {}
+            _ = sigint.recv() => {}
+        }
+        tracing::info!("Shutting down");
+        cancellations.cancel_all();
+        let deadline = tokio::time::Instant::now() + cancel_grace + SHUTDOWN_MARGIN;
+        while !cancellations.is_empty() && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(SHUTDOWN_POLL).await;
+        }
+        // finished components dropped their exporters, which send what
+        // they still hold and end
+        if !export_tasks.drain(SHUTDOWN_DRAIN).await {
+            tracing::warn!("Exports still pending after {SHUTDOWN_DRAIN:?} are lost");
+        }
+        runtime::exporter::flush();
+        if let Err(e) = store::global().map_or(Ok(()), store::Store::flush) {
+            tracing::warn!("Failed to flush the store: {e:#}");
+        }
+        Ok::<(), Error>(())
+    };
+    tokio::pin!(shutdown_task);
+
+    let tasks = async {
+        try_join!(
+            rpc_task,
+            otlp_task,
+            health_task,
+            ping_task,
+            blackbox_task,
+            self_profile_task,
+            control_task
+        )
+    };
+    // the daemon ends once the shutdown is done, whatever still runs
+    tokio::select! {
+        res = tasks => {
+            res?;
+            (&mut shutdown_task).await?;
+        }
+        res = &mut shutdown_task => res?,
+    }
 
     Ok(())
 }
@@ -0,0 +1,144 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{collections::BTreeMap, fs::File, os::unix::fs::FileExt, sync::OnceLock};
+
+use anyhow::{Context, Result, bail};
+
+use crate::config::MsrConfig;
+
+/// Well known MSRs that can be allowed by name.
+const KNOWN: &[(&str, u32)] = &[
+    ("MSR_PLATFORM_INFO", 0xce),
+    ("IA32_MPERF", 0xe7),
+    ("IA32_APERF", 0xe8),
+    ("IA32_PERF_STATUS", 0x198),
+    ("IA32_THERM_STATUS", 0x19c),
+    ("IA32_TEMPERATURE_TARGET", 0x1a2),
+    ("IA32_PACKAGE_THERM_STATUS", 0x1b1),
+    ("MSR_RAPL_POWER_UNIT", 0x606),
+    ("MSR_PKG_ENERGY_STATUS", 0x611),
+    ("MSR_DRAM_ENERGY_STATUS", 0x619),
+    ("MSR_PP0_ENERGY_STATUS", 0x639),
+];
+
+static GLOBAL: OnceLock<MsrReader> = OnceLock::new();
+
+/// The MSR allowlist installed by [`init`], `None` if MSR reading is disabled.
+pub fn global() -> Option<&'static MsrReader> {
+    GLOBAL.get()
+}
+
+pub fn init(cfg: &MsrConfig) -> Result<()> {
+    let reader = MsrReader::new(&cfg.allow)?;
+    if GLOBAL.set(reader).is_err() {
+        bail!("MSR reader already initialized");
+    }
+    Ok(())
+}
+
+/// Parses an allowlist entry, a name of [`KNOWN`] or an address like `0x19c`.
+fn parse_entry(entry: &str) -> Result<(String, u32)> {
+    if let Some((name, address)) = KNOWN
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(entry))
+    {
+        return Ok((name.to_string(), *address));
+    }
+    let address = match entry
+        .strip_prefix("0x")
+        .or_else(|| entry.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => entry.parse(),
+    }
+    .with_context(|| format!("Unknown MSR {entry}"))?;
+    let name = KNOWN
+        .iter()
+        .find(|(_, it)| *it == address)
+        .map_or_else(|| format!("{address:#x}"), |(name, _)| name.to_string());
+    Ok((name, address))
+}
+
+/// Reads allowed MSRs from `/dev/cpu/<cpu>/msr`, which needs the `msr`
+/// kernel module and `CAP_SYS_RAWIO`.
+pub struct MsrReader {
+    /// address to name
+    allow: BTreeMap<u32, String>,
+}
+
+impl MsrReader {
+    pub fn new(allow: &[String]) -> Result<Self> {
+        let allow = allow
+            .iter()
+            .map(|it| parse_entry(it).map(|(name, address)| (address, name)))
+            .collect::<Result<_>>()?;
+        Ok(Self { allow })
+    }
+
+    /// Allowed MSRs as name and address, ordered by address.
+    pub fn allowed(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.allow
+            .iter()
+            .map(|(address, name)| (name.as_str(), *address))
+    }
+
+    pub fn read(&self, cpu: u32, msr: u32) -> Result<u64> {
+        if !self.allow.contains_key(&msr) {
+            bail!("Reading MSR {msr:#x} is not allowed");
+        }
+        let path = format!("/dev/cpu/{cpu}/msr");
+        let file = File::open(&path).with_context(|| format!("Failed to open {path}"))?;
+        let mut buf = [0; 8];
+        // the file offset selects the MSR
+        file.read_exact_at(&mut buf, u64::from(msr))
+            .with_context(|| format!("Failed to read MSR {msr:#x} of cpu {cpu}"))?;
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MsrReader, parse_entry};
+
+    #[test]
+    fn test_parse_entry() {
+        assert_eq!(
+            parse_entry("IA32_THERM_STATUS").unwrap(),
+            ("IA32_THERM_STATUS".to_string(), 0x19c)
+        );
+        assert_eq!(
+            parse_entry("ia32_aperf").unwrap(),
+            ("IA32_APERF".to_string(), 0xe8)
+        );
+        assert_eq!(
+            parse_entry("0xE7").unwrap(),
+            ("IA32_MPERF".to_string(), 0xe7)
+        );
+        assert_eq!(parse_entry("0x10").unwrap(), ("0x10".to_string(), 0x10));
+        assert_eq!(parse_entry("16").unwrap(), ("0x10".to_string(), 0x10));
+        assert!(parse_entry("IA32_FOO").is_err());
+    }
+
+    #[test]
+    fn test_not_allowed() {
+        let reader = MsrReader::new(&["IA32_APERF".to_string(), "0x10".to_string()]).unwrap();
+        assert_eq!(
+            reader.allowed().collect::<Vec<_>>(),
+            [("0x10", 0x10), ("IA32_APERF", 0xe8)]
+        );
+        let err = reader.read(0, 0x19c).unwrap_err();
+        assert_eq!(err.to_string(), "Reading MSR 0x19c is not allowed");
+    }
+}
@@ -0,0 +1,55 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+use opentelemetry::{KeyValue, metrics::ObservableGauge};
+
+use crate::services::component_stats;
+
+impl super::super::Otlp {
+    pub fn component_gauges(&self) -> anyhow::Result<ObservableGauge<u64>> {
+        let host = self.host.clone();
+
+        let gauge = self
+            .meter
+            .u64_observable_gauge("Component")
+            .with_description("Resources the runs of each component took.")
+            .with_callback(move |gauge| {
+                for (component, stats) in component_stats::snapshot() {
+                    let stat = |name: &'static str| {
+                        [
+                            KeyValue::new("stat", name),
+                            KeyValue::new("component", component.clone()),
+                            KeyValue::new("host", host.clone()),
+                        ]
+                    };
+                    gauge.observe(stats.runs, &stat("runs"));
+                    gauge.observe(stats.wall_ms, &stat("wall_ms"));
+                    gauge.observe(stats.fuel, &stat("fuel"));
+                    gauge.observe(stats.peak_memory, &stat("peak_memory"));
+                    for (interface, calls) in stats.host_calls {
+                        gauge.observe(
+                            calls,
+                            &[
+                                KeyValue::new("stat", "rate_limited_calls"),
+                                KeyValue::new("interface", interface),
+                                KeyValue::new("component", component.clone()),
+                                KeyValue::new("host", host.clone()),
+                            ],
+                        );
+                    }
+                }
+            })
+            .build();
+        Ok(gauge)
+    }
+}
@@ -12,10 +12,12 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
+pub mod component;
 pub mod cpu;
 pub mod disk;
 pub mod interrupt;
 pub mod memory;
 pub mod network;
 pub mod rps;
+pub mod semconv;
 pub mod vmstat;
@@ -0,0 +1,120 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::time::Duration;
+
+use opentelemetry::metrics::AsyncInstrument;
+use psh_system::{cpu::CpuHandle, disk::DiskHandle, memory::MemoryHandle, network::NetworkHandle};
+
+use crate::otlp::semconv::{self, Descriptor, Family, Instrument, Metric, Value};
+
+fn collect(family: Family, interval: Duration) -> Vec<Metric> {
+    let interval = Some(interval);
+    let metrics = match family {
+        Family::Cpu => CpuHandle::new()
+            .stat(interval)
+            .map(|it| semconv::cpu_metrics(&it)),
+        Family::Memory => MemoryHandle::new()
+            .stat(interval)
+            .map(|it| semconv::memory_metrics(&it)),
+        Family::Network => NetworkHandle::new()
+            .stat(interval)
+            .map(|it| semconv::network_metrics(&it)),
+        Family::Disk => DiskHandle::new()
+            .stat(interval)
+            .map(|it| semconv::disk_metrics(&it)),
+    };
+    metrics.unwrap_or_default()
+}
+
+/// Observes the metrics of `desc`, the handles cache the raw data for one
+/// interval so instruments of the same family share a read.
+fn observe<T>(
+    desc: &'static Descriptor,
+    interval: Duration,
+    convert: fn(Value) -> T,
+) -> impl Fn(&dyn AsyncInstrument<T>) + Send + Sync + 'static {
+    move |observer| {
+        for metric in collect(desc.family, interval) {
+            if metric.name == desc.name {
+                observer.observe(convert(metric.value), &metric.attributes);
+            }
+        }
+    }
+}
+
+const fn as_u64(value: Value) -> u64 {
+    match value {
+        Value::Int(v) => v as u64,
+        Value::Double(v) => v as u64,
+    }
+}
+
+const fn as_i64(value: Value) -> i64 {
+    match value {
+        Value::Int(v) => v,
+        Value::Double(v) => v as i64,
+    }
+}
+
+const fn as_f64(value: Value) -> f64 {
+    match value {
+        Value::Int(v) => v as f64,
+        Value::Double(v) => v,
+    }
+}
+
+impl super::super::Otlp {
+    /// Instruments named after the OpenTelemetry semantic conventions, the
+    /// host is reported as the `host.name` resource attribute.
+    pub fn semconv_instruments(&self) {
+        let interval = self.interval;
+        for desc in semconv::DESCRIPTORS {
+            match desc.instrument {
+                Instrument::IntCounter => {
+                    self.meter
+                        .u64_observable_counter(desc.name)
+                        .with_unit(desc.unit)
+                        .with_description(desc.description)
+                        .with_callback(observe(desc, interval, as_u64))
+                        .build();
+                }
+                Instrument::DoubleCounter => {
+                    self.meter
+                        .f64_observable_counter(desc.name)
+                        .with_unit(desc.unit)
+                        .with_description(desc.description)
+                        .with_callback(observe(desc, interval, as_f64))
+                        .build();
+                }
+                Instrument::IntUpDownCounter => {
+                    self.meter
+                        .i64_observable_up_down_counter(desc.name)
+                        .with_unit(desc.unit)
+                        .with_description(desc.description)
+                        .with_callback(observe(desc, interval, as_i64))
+                        .build();
+                }
+                Instrument::DoubleGauge => {
+                    self.meter
+                        .f64_observable_gauge(desc.name)
+                        .with_unit(desc.unit)
+                        .with_description(desc.description)
+                        .with_callback(observe(desc, interval, as_f64))
+                        .build();
+                }
+            }
+        }
+    }
+}
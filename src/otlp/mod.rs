@@ -13,6 +13,7 @@
 // see <https://www.gnu.org/licenses/>.
 
 pub mod gauges;
+pub mod semconv;
 
 use std::{sync::LazyLock, time::Duration};
 
@@ -38,22 +39,29 @@ static NET_DEV_SPEED: LazyLock<TinyUfo<String, Option<u32>>> =
 pub struct Otlp {
     host: String,
     interval: Duration,
+    semconv: bool,
     meter: Meter,
     // NOTE: the field avoid provider early drop see: <https://github.com/open-telemetry/opentelemetry-rust/issues/1661>
     _provider: SdkMeterProvider,
 }
 
 impl Otlp {
-    pub fn new(token: String, interval: Duration, export_config: ExportConfig) -> Result<Self> {
-        let provider = meter_provider(export_config, &token, interval)?;
+    pub fn new(
+        token: String,
+        interval: Duration,
+        semconv: bool,
+        export_config: ExportConfig,
+    ) -> Result<Self> {
         let host = nix::unistd::gethostname()
             .ok()
             .map(|v| v.to_string_lossy().to_string())
             .unwrap_or_else(|| token.clone());
+        let provider = meter_provider(export_config, &token, &host, interval)?;
         let meter = provider.meter("SystemProfile");
         Ok(Self {
             host,
             interval,
+            semconv,
             meter,
             _provider: provider,
         })
@@ -94,6 +102,12 @@ impl Otlp {
         if let Err(e) = self.vmstat_gauges() {
             tracing::error!("Otlp vmstat: {e}")
         }
+        if let Err(e) = self.component_gauges() {
+            tracing::error!("Otlp component: {e}")
+        }
+        if self.semconv {
+            self.semconv_instruments();
+        }
 
         loop {
             tokio::time::sleep(interval).await;
@@ -104,6 +118,7 @@ impl Otlp {
 fn meter_provider(
     export_config: ExportConfig,
     token: &str,
+    host: &str,
     interval: Duration,
 ) -> Result<SdkMeterProvider> {
     let mut meta = MetadataMap::new();
@@ -121,7 +136,10 @@ fn meter_provider(
 
     let a = SdkMeterProvider::builder()
         .with_reader(reader)
-        .with_resource(Resource::new(vec![KeyValue::new("service.name", "PSH")]))
+        .with_resource(Resource::new(vec![
+            KeyValue::new("service.name", "PSH"),
+            KeyValue::new("host.name", host.to_string()),
+        ]))
         .build();
 
     Ok(a)
@@ -0,0 +1,340 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+// Host metrics named after the OpenTelemetry semantic conventions
+// <https://opentelemetry.io/docs/specs/semconv/system/system-metrics/>, so
+// they line up with every other semconv producer without per-deployment
+// renaming rules.
+
+use std::collections::HashMap;
+
+use opentelemetry::KeyValue;
+use psh_system::{cpu::CpuStats, disk::DiskStat, memory::Meminfo, network::DeviceStatus};
+
+// diskstats always counts 512 bytes sectors
+const SECTOR_SIZE: u64 = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    Cpu,
+    Memory,
+    Network,
+    Disk,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instrument {
+    IntCounter,
+    DoubleCounter,
+    IntUpDownCounter,
+    DoubleGauge,
+}
+
+#[derive(Debug)]
+pub struct Descriptor {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub description: &'static str,
+    pub instrument: Instrument,
+    pub family: Family,
+}
+
+macro_rules! descriptors {
+    ($($name:literal, $unit:literal, $instrument:ident, $family:ident, $description:literal;)+) => {
+        pub const DESCRIPTORS: &[Descriptor] = &[
+            $(Descriptor {
+                name: $name,
+                unit: $unit,
+                description: $description,
+                instrument: Instrument::$instrument,
+                family: Family::$family,
+            },)+
+        ];
+    };
+}
+
+descriptors! {
+    "system.cpu.time", "s", DoubleCounter, Cpu, "Seconds each logical CPU spent on each mode";
+    "system.cpu.logical.count", "{cpu}", IntUpDownCounter, Cpu, "Number of logical CPUs";
+    "system.memory.usage", "By", IntUpDownCounter, Memory, "Memory in use by state";
+    "system.memory.limit", "By", IntUpDownCounter, Memory, "Total memory available to the system";
+    "system.memory.utilization", "1", DoubleGauge, Memory, "Fraction of memory in each state";
+    "system.paging.usage", "By", IntUpDownCounter, Memory, "Swap space in use by state";
+    "system.network.io", "By", IntCounter, Network, "Bytes transmitted and received";
+    "system.network.packet.count", "{packet}", IntCounter, Network, "Packets transmitted and received";
+    "system.network.errors", "{error}", IntCounter, Network, "Errors encountered";
+    "system.network.packet.dropped", "{packet}", IntCounter, Network, "Packets dropped";
+    "system.disk.io", "By", IntCounter, Disk, "Bytes read from and written to disk";
+    "system.disk.operations", "{operation}", IntCounter, Disk, "Disk operations";
+    "system.disk.operation_time", "s", DoubleCounter, Disk, "Time spent on disk operations";
+    "system.disk.io_time", "s", DoubleCounter, Disk, "Time the disk had operations in flight";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Double(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub name: &'static str,
+    pub value: Value,
+    pub attributes: Vec<KeyValue>,
+}
+
+impl Metric {
+    fn int(name: &'static str, value: u64, attributes: Vec<KeyValue>) -> Self {
+        Self {
+            name,
+            value: Value::Int(value as i64),
+            attributes,
+        }
+    }
+
+    fn double(name: &'static str, value: f64, attributes: Vec<KeyValue>) -> Self {
+        Self {
+            name,
+            value: Value::Double(value),
+            attributes,
+        }
+    }
+}
+
+pub fn cpu_metrics(stats: &CpuStats) -> Vec<Metric> {
+    let mut metrics = vec![Metric::int(
+        "system.cpu.logical.count",
+        stats.per_cpu.len() as u64,
+        vec![],
+    )];
+    for (cpu, time) in stats.per_cpu.iter().enumerate() {
+        let modes = [
+            ("user", time.user_ms()),
+            ("nice", time.nice_ms()),
+            ("system", time.system_ms()),
+            ("idle", time.idle_ms()),
+            ("iowait", time.iowait_ms().unwrap_or(0)),
+            (
+                "interrupt",
+                time.irq_ms().unwrap_or(0) + time.softirq_ms().unwrap_or(0),
+            ),
+            ("steal", time.steal_ms().unwrap_or(0)),
+        ];
+        metrics.extend(modes.into_iter().map(|(mode, ms)| {
+            Metric::double(
+                "system.cpu.time",
+                ms as f64 / 1000.0,
+                vec![
+                    KeyValue::new("cpu.mode", mode),
+                    KeyValue::new("cpu.logical_number", cpu as i64),
+                ],
+            )
+        }));
+    }
+    metrics
+}
+
+pub fn memory_metrics(meminfo: &Meminfo) -> Vec<Metric> {
+    let total = meminfo.mem_total;
+    // reclaimable slab is cache too, like the collector's hostmetrics receiver counts it
+    let cached = meminfo.cached + meminfo.s_reclaimable.unwrap_or(0);
+    let used = total
+        .saturating_sub(meminfo.mem_free)
+        .saturating_sub(meminfo.buffers)
+        .saturating_sub(cached);
+    let states = [
+        ("used", used),
+        ("free", meminfo.mem_free),
+        ("buffers", meminfo.buffers),
+        ("cached", cached),
+    ];
+
+    let mut metrics = vec![Metric::int("system.memory.limit", total, vec![])];
+    for (state, bytes) in states {
+        let attributes = vec![KeyValue::new("system.memory.state", state)];
+        metrics.push(Metric::int(
+            "system.memory.usage",
+            bytes,
+            attributes.clone(),
+        ));
+        if total > 0 {
+            metrics.push(Metric::double(
+                "system.memory.utilization",
+                bytes as f64 / total as f64,
+                attributes,
+            ));
+        }
+    }
+    let swap_used = meminfo.swap_total.saturating_sub(meminfo.swap_free);
+    for (state, bytes) in [("used", swap_used), ("free", meminfo.swap_free)] {
+        metrics.push(Metric::int(
+            "system.paging.usage",
+            bytes,
+            vec![KeyValue::new("system.paging.state", state)],
+        ));
+    }
+    metrics
+}
+
+pub fn network_metrics(devices: &HashMap<String, DeviceStatus>) -> Vec<Metric> {
+    let mut metrics = Vec::new();
+    for (name, status) in devices {
+        let directions = [
+            (
+                "receive",
+                [
+                    status.recv_bytes,
+                    status.recv_packets,
+                    status.recv_errs,
+                    status.recv_drop,
+                ],
+            ),
+            (
+                "transmit",
+                [
+                    status.sent_bytes,
+                    status.sent_packets,
+                    status.sent_errs,
+                    status.sent_drop,
+                ],
+            ),
+        ];
+        for (direction, [bytes, packets, errors, dropped]) in directions {
+            let attributes = vec![
+                KeyValue::new("network.interface.name", name.clone()),
+                KeyValue::new("network.io.direction", direction),
+            ];
+            metrics.extend([
+                Metric::int("system.network.io", bytes, attributes.clone()),
+                Metric::int("system.network.packet.count", packets, attributes.clone()),
+                Metric::int("system.network.errors", errors, attributes.clone()),
+                Metric::int("system.network.packet.dropped", dropped, attributes),
+            ]);
+        }
+    }
+    metrics
+}
+
+pub fn disk_metrics(disks: &[DiskStat]) -> Vec<Metric> {
+    let mut metrics = Vec::new();
+    for disk in disks {
+        let directions = [
+            ("read", disk.sectors_read, disk.reads, disk.time_reading),
+            (
+                "write",
+                disk.sectors_written,
+                disk.writes,
+                disk.time_writing,
+            ),
+        ];
+        for (direction, sectors, operations, ms) in directions {
+            let attributes = vec![
+                KeyValue::new("system.device", disk.name.clone()),
+                KeyValue::new("disk.io.direction", direction),
+            ];
+            metrics.extend([
+                Metric::int("system.disk.io", sectors * SECTOR_SIZE, attributes.clone()),
+                Metric::int("system.disk.operations", operations, attributes.clone()),
+                Metric::double("system.disk.operation_time", ms as f64 / 1000.0, attributes),
+            ]);
+        }
+        metrics.push(Metric::double(
+            "system.disk.io_time",
+            disk.time_in_progress as f64 / 1000.0,
+            vec![KeyValue::new("system.device", disk.name.clone())],
+        ));
+    }
+    metrics
+}
+
+/// Semconv names for the tag keys psh and components commonly use, unknown
+/// keys are kept.
+pub fn normalize_key(key: &str) -> &str {
+    match key {
+        "host" | "hostname" => "host.name",
+        "instance_id" => "service.instance.id",
+        "task_id" => "psh.task.id",
+        "tenant" => "psh.tenant",
+        "cpu" => "cpu.logical_number",
+        "interface" | "iface" => "network.interface.name",
+        "disk" | "device" => "system.device",
+        "pid" => "process.pid",
+        "comm" => "process.executable.name",
+        "cgroup" => "container.id",
+        key => key,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use opentelemetry::KeyValue;
+    use psh_system::network::DeviceStatus;
+
+    use super::{DESCRIPTORS, Value, network_metrics, normalize_key};
+
+    #[test]
+    fn test_network_metrics() {
+        let status = DeviceStatus {
+            name: "eth0".to_string(),
+            recv_bytes: 1024,
+            recv_packets: 8,
+            recv_errs: 0,
+            recv_drop: 1,
+            recv_fifo: 0,
+            recv_frame: 0,
+            recv_compressed: 0,
+            recv_multicast: 0,
+            sent_bytes: 512,
+            sent_packets: 4,
+            sent_errs: 2,
+            sent_drop: 0,
+            sent_fifo: 0,
+            sent_colls: 0,
+            sent_carrier: 0,
+            sent_compressed: 0,
+        };
+        let metrics = network_metrics(&HashMap::from([("eth0".to_string(), status)]));
+        assert_eq!(metrics.len(), 8);
+
+        let io = metrics
+            .iter()
+            .find(|it| {
+                it.name == "system.network.io"
+                    && it
+                        .attributes
+                        .contains(&KeyValue::new("network.io.direction", "transmit"))
+            })
+            .unwrap();
+        assert_eq!(io.value, Value::Int(512));
+        assert!(
+            io.attributes
+                .contains(&KeyValue::new("network.interface.name", "eth0"))
+        );
+        // every mapped metric is described
+        assert!(
+            metrics
+                .iter()
+                .all(|it| DESCRIPTORS.iter().any(|desc| desc.name == it.name))
+        );
+    }
+
+    #[test]
+    fn test_normalize_key() {
+        assert_eq!(normalize_key("interface"), "network.interface.name");
+        assert_eq!(normalize_key("task_id"), "psh.task.id");
+        assert_eq!(normalize_key("custom"), "custom");
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use anyhow::{Result, bail};
+
+// just enough of RFC 1035 to time a single A or AAAA query
+const CLASS_IN: u16 = 1;
+const FLAG_QR: u16 = 0x8000;
+const FLAG_TC: u16 = 0x0200;
+const FLAG_RD: u16 = 0x0100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordType {
+    A = 1,
+    Aaaa = 28,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Answer {
+    /// 0 is NOERROR, 3 NXDOMAIN
+    pub rcode: u8,
+    pub addresses: Vec<IpAddr>,
+    /// lowest ttl of the address records, in seconds
+    pub ttl: Option<u32>,
+}
+
+pub fn query(id: u16, name: &str, ty: RecordType) -> Result<Vec<u8>> {
+    let name = name.trim_end_matches('.');
+    if name.is_empty() || name.len() > 253 {
+        bail!("Invalid domain name {name}");
+    }
+    let mut buf = Vec::with_capacity(18 + name.len());
+    for it in [id, FLAG_RD, 1, 0, 0, 0] {
+        buf.extend_from_slice(&it.to_be_bytes());
+    }
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("Invalid domain name {name}");
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+    buf.extend_from_slice(&(ty as u16).to_be_bytes());
+    buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(buf)
+}
+
+pub fn parse_response(id: u16, ty: RecordType, buf: &[u8]) -> Result<Answer> {
+    let u16_at = |pos: usize| -> Result<u16> {
+        match buf.get(pos..pos + 2) {
+            Some(b) => Ok(u16::from_be_bytes([b[0], b[1]])),
+            None => bail!("Truncated DNS response"),
+        }
+    };
+    if u16_at(0)? != id {
+        bail!("DNS response id mismatch");
+    }
+    let flags = u16_at(2)?;
+    if flags & FLAG_QR == 0 {
+        bail!("Not a DNS response");
+    }
+    if flags & FLAG_TC != 0 {
+        bail!("DNS response truncated, retry over TCP is not supported");
+    }
+    let (questions, answers) = (u16_at(4)?, u16_at(6)?);
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(buf, pos)? + 4;
+    }
+    let mut addresses = vec![];
+    let mut ttl: Option<u32> = None;
+    for _ in 0..answers {
+        pos = skip_name(buf, pos)?;
+        let rtype = u16_at(pos)?;
+        let record_ttl = (u32::from(u16_at(pos + 4)?) << 16) | u32::from(u16_at(pos + 6)?);
+        let len = u16_at(pos + 8)? as usize;
+        pos += 10;
+        let Some(data) = buf.get(pos..pos + len) else {
+            bail!("Truncated DNS response");
+        };
+        pos += len;
+        // CNAMEs come along with the addresses they resolve to
+        if rtype != ty as u16 {
+            continue;
+        }
+        let addr = match (ty, <[u8; 4]>::try_from(data), <[u8; 16]>::try_from(data)) {
+            (RecordType::A, Ok(octets), _) => IpAddr::V4(Ipv4Addr::from(octets)),
+            (RecordType::Aaaa, _, Ok(octets)) => IpAddr::V6(Ipv6Addr::from(octets)),
+            _ => bail!("Malformed address record"),
+        };
+        addresses.push(addr);
+        ttl = Some(ttl.map_or(record_ttl, |it| it.min(record_ttl)));
+    }
+
+    Ok(Answer {
+        rcode: (flags & 0xf) as u8,
+        addresses,
+        ttl,
+    })
+}
+
+/// Returns the position after the name at `pos`.
+fn skip_name(buf: &[u8], mut pos: usize) -> Result<usize> {
+    loop {
+        let Some(&len) = buf.get(pos) else {
+            bail!("Truncated DNS response");
+        };
+        match len {
+            0 => return Ok(pos + 1),
+            // compression pointer, ends the name
+            len if len & 0xc0 == 0xc0 => return Ok(pos + 2),
+            len => pos += 1 + len as usize,
+        }
+    }
+}
+
+/// The first nameserver of a `resolv.conf`.
+pub fn nameserver(resolv_conf: &str) -> Option<IpAddr> {
+    resolv_conf.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        (fields.next() == Some("nameserver"))
+            .then(|| fields.next()?.parse().ok())
+            .flatten()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{RecordType, nameserver, parse_response, query};
+
+    #[test]
+    fn test_query() {
+        let buf = query(0x1234, "example.com.", RecordType::A).unwrap();
+        assert_eq!(&buf[..4], &[0x12, 0x34, 0x01, 0x00]);
+        assert_eq!(&buf[12..], b"\x07example\x03com\x00\x00\x01\x00\x01");
+        assert!(query(1, "a..b", RecordType::A).is_err());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let mut buf = query(7, "www.example.com", RecordType::A).unwrap();
+        // QR, RD, RA, NOERROR, 2 answers
+        buf[2..4].copy_from_slice(&0x8180u16.to_be_bytes());
+        buf[6..8].copy_from_slice(&2u16.to_be_bytes());
+        // www.example.com CNAME example.com, ttl 300
+        buf.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 1, 44, 0, 2, 0xc0, 16]);
+        // example.com A 93.184.216.34, ttl 60
+        buf.extend_from_slice(&[0xc0, 16, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 93, 184, 216, 34]);
+
+        let answer = parse_response(7, RecordType::A, &buf).unwrap();
+        assert_eq!(answer.rcode, 0);
+        assert_eq!(
+            answer.addresses,
+            ["93.184.216.34".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(answer.ttl, Some(60));
+        assert!(parse_response(8, RecordType::A, &buf).is_err());
+        assert!(parse_response(7, RecordType::A, &buf[..buf.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn test_nameserver() {
+        let conf = "# generated\nsearch lan\nnameserver 10.0.0.1\nnameserver 10.0.0.2\n";
+        assert_eq!(nameserver(conf), "10.0.0.1".parse().ok());
+        assert_eq!(nameserver(""), None);
+    }
+}
@@ -0,0 +1,256 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    io, mem,
+    net::{IpAddr, SocketAddr},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+
+const ECHO_REQUEST_V4: u8 = 8;
+const ECHO_REPLY_V4: u8 = 0;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6: u8 = 129;
+const HEADER_LEN: usize = 8;
+
+#[derive(Clone, Copy)]
+pub struct Options {
+    pub count: u32,
+    pub interval: Duration,
+    /// how long to wait for each reply
+    pub timeout: Duration,
+    pub payload_size: usize,
+    /// no echo request is sent or waited for past it
+    pub deadline: Option<Instant>,
+}
+
+/// Sends `count` echo requests to `addr`, returns the round trip time of
+/// each sent before the deadline, `None` if it was lost.
+///
+/// Raw sockets need `CAP_NET_RAW`, which is why the host does it for guests.
+pub fn ping(addr: IpAddr, opts: &Options) -> Result<Vec<Option<Duration>>> {
+    let socket = open(addr)?;
+    // raw sockets see every reply, ours are told apart by the identifier
+    let id = std::process::id() as u16 ^ (socket.as_raw_fd() as u16).rotate_left(8);
+    let payload: Vec<u8> = (0..opts.payload_size).map(|i| i as u8).collect();
+
+    let count = opts.count as usize;
+    let mut sent_at = Vec::with_capacity(count);
+    let mut rtts = vec![None; count];
+    let mut buf = vec![0; 128 + HEADER_LEN + payload.len()];
+    let start = Instant::now();
+    for seq in 0..count {
+        if opts.deadline.is_some_and(|it| Instant::now() >= it) {
+            break;
+        }
+        let packet = echo_request(addr.is_ipv6(), id, seq as u16, &payload);
+        send_to(&socket, &packet, addr)?;
+        sent_at.push(Instant::now());
+
+        let until = if seq + 1 < count {
+            start + opts.interval * (seq as u32 + 1)
+        } else {
+            sent_at[seq] + opts.timeout
+        };
+        let until = opts.deadline.map_or(until, |it| until.min(it));
+        loop {
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            let Some(len) = recv(&socket, &mut buf, left)? else {
+                break;
+            };
+            let received = Instant::now();
+            let Some(reply) = parse_reply(addr.is_ipv6(), &buf[..len], id) else {
+                continue;
+            };
+            let reply = reply as usize;
+            if let (Some(sent), Some(rtt @ None)) = (sent_at.get(reply), rtts.get_mut(reply)) {
+                let elapsed = received.saturating_duration_since(*sent);
+                if elapsed <= opts.timeout {
+                    *rtt = Some(elapsed);
+                }
+            }
+        }
+    }
+    if sent_at.is_empty() {
+        bail!("The time slice of the run is over");
+    }
+    rtts.truncate(sent_at.len());
+    Ok(rtts)
+}
+
+fn open(addr: IpAddr) -> Result<OwnedFd> {
+    let (domain, protocol) = match addr {
+        IpAddr::V4(_) => (libc::AF_INET, libc::IPPROTO_ICMP),
+        IpAddr::V6(_) => (libc::AF_INET6, libc::IPPROTO_ICMPV6),
+    };
+    let fd = unsafe { libc::socket(domain, libc::SOCK_RAW | libc::SOCK_CLOEXEC, protocol) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error()).context("Failed to open raw ICMP socket");
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+fn echo_request(v6: bool, id: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER_LEN + payload.len());
+    packet.push(if v6 { ECHO_REQUEST_V6 } else { ECHO_REQUEST_V4 });
+    packet.push(0);
+    packet.extend_from_slice(&[0, 0]);
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(payload);
+    // the kernel fills in the ICMPv6 checksum, it covers a pseudo header
+    if !v6 {
+        let sum = checksum(&packet);
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+    packet
+}
+
+/// The sequence number of an echo reply with identifier `id`.
+fn parse_reply(v6: bool, packet: &[u8], id: u16) -> Option<u16> {
+    // raw IPv4 sockets pass the IP header along, IPv6 ones don't
+    let icmp = if v6 {
+        packet
+    } else {
+        let ihl = (*packet.first()? & 0x0f) as usize * 4;
+        packet.get(ihl..)?
+    };
+    let header = icmp.get(..HEADER_LEN)?;
+    let reply = if v6 { ECHO_REPLY_V6 } else { ECHO_REPLY_V4 };
+    if header[0] != reply || u16::from_be_bytes([header[4], header[5]]) != id {
+        return None;
+    }
+    Some(u16::from_be_bytes([header[6], header[7]]))
+}
+
+/// RFC 1071 internet checksum.
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|it| u32::from(u16::from_be_bytes([it[0], it.get(1).copied().unwrap_or(0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn send_to(socket: &OwnedFd, packet: &[u8], addr: IpAddr) -> Result<()> {
+    let ret = match SocketAddr::new(addr, 0) {
+        SocketAddr::V4(addr) => {
+            let mut sa: libc::sockaddr_in = unsafe { mem::zeroed() };
+            sa.sin_family = libc::AF_INET as libc::sa_family_t;
+            sa.sin_addr.s_addr = u32::from_ne_bytes(addr.ip().octets());
+            unsafe {
+                libc::sendto(
+                    socket.as_raw_fd(),
+                    packet.as_ptr().cast(),
+                    packet.len(),
+                    0,
+                    (&raw const sa).cast(),
+                    mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+                )
+            }
+        }
+        SocketAddr::V6(addr) => {
+            let mut sa: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+            sa.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sa.sin6_addr.s6_addr = addr.ip().octets();
+            unsafe {
+                libc::sendto(
+                    socket.as_raw_fd(),
+                    packet.as_ptr().cast(),
+                    packet.len(),
+                    0,
+                    (&raw const sa).cast(),
+                    mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+                )
+            }
+        }
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error()).context(format!("Failed to send echo to {addr}"));
+    }
+    Ok(())
+}
+
+/// `None` if nothing arrived within `timeout`.
+fn recv(socket: &OwnedFd, buf: &mut [u8], timeout: Duration) -> Result<Option<usize>> {
+    // a zero timeval blocks forever
+    let timeout = timeout.max(Duration::from_millis(1));
+    let tv = libc::timeval {
+        tv_sec: timeout.as_secs() as _,
+        tv_usec: timeout.subsec_micros() as _,
+    };
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVTIMEO,
+            (&raw const tv).cast(),
+            mem::size_of::<libc::timeval>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error()).context("Failed to set receive timeout");
+    }
+    let len = unsafe { libc::recv(socket.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+    if len < 0 {
+        let err = io::Error::last_os_error();
+        return match err.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => Ok(None),
+            _ => Err(err).context("Failed to receive echo reply"),
+        };
+    }
+    Ok(Some(len as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checksum, echo_request, parse_reply};
+
+    #[test]
+    fn test_echo_request() {
+        let packet = echo_request(false, 0x1234, 1, &[0xab; 4]);
+        assert_eq!(&packet[..2], &[8, 0]);
+        assert_eq!(&packet[4..8], &[0x12, 0x34, 0, 1]);
+        // a packet including its checksum sums up to 0
+        assert_eq!(checksum(&packet), 0);
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let mut reply = echo_request(false, 0x1234, 7, &[]);
+        reply[0] = 0;
+        // 20 bytes IPv4 header in front
+        let mut packet = vec![0x45];
+        packet.resize(20, 0);
+        packet.extend_from_slice(&reply);
+        assert_eq!(parse_reply(false, &packet, 0x1234), Some(7));
+        assert_eq!(parse_reply(false, &packet, 0x4321), None);
+        // our own request looped back
+        packet[20] = 8;
+        assert_eq!(parse_reply(false, &packet, 0x1234), None);
+
+        let mut reply = echo_request(true, 1, 3, &[]);
+        reply[0] = 129;
+        assert_eq!(parse_reply(true, &reply, 1), Some(3));
+    }
+}
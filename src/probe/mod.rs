@@ -0,0 +1,510 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+pub mod dns;
+mod icmp;
+
+use std::{
+    fs,
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    process,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, bail};
+use dns::{Answer, RecordType};
+pub use icmp::Options as PingOptions;
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+};
+
+use crate::config::ProbeConfig;
+
+const DNS_PORT: u16 = 53;
+// keeps a single ping from flooding the target
+const MIN_PING_INTERVAL: Duration = Duration::from_millis(10);
+// keeps a single ping from blocking the run for long
+const MAX_PING_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_PING_COUNT: u32 = 100;
+// max IPv4 payload minus the ICMP header
+const MAX_PING_PAYLOAD: usize = 65507 - 8;
+
+static GLOBAL: OnceLock<Prober> = OnceLock::new();
+
+/// The prober installed by [`init`], `None` if probes are disabled.
+pub fn global() -> Option<&'static Prober> {
+    GLOBAL.get()
+}
+
+pub fn init(cfg: &ProbeConfig) -> Result<()> {
+    if GLOBAL.set(Prober::new(cfg)).is_err() {
+        bail!("Prober already initialized");
+    }
+    Ok(())
+}
+
+pub struct Resolved {
+    pub answer: Answer,
+    pub duration: Duration,
+}
+
+pub struct Connected {
+    pub address: SocketAddr,
+    pub duration: Duration,
+}
+
+pub struct Handshake {
+    pub address: SocketAddr,
+    pub connect: Duration,
+    pub handshake: Duration,
+    pub version: String,
+    pub cipher_suite: String,
+    pub peer_certificates: usize,
+}
+
+pub struct Pinged {
+    pub address: IpAddr,
+    /// `None` if the echo request was lost
+    pub rtts: Vec<Option<Duration>>,
+}
+
+struct Tls {
+    address: SocketAddr,
+    connect: Duration,
+    handshake: Duration,
+    conn: ClientConnection,
+}
+
+/// Runs probes against allowed targets, all components share one rate limit.
+pub struct Prober {
+    targets: Vec<String>,
+    max_timeout: Duration,
+    rate: f64,
+    burst: f64,
+    // available tokens and when they were counted
+    bucket: Mutex<(f64, Instant)>,
+    tls: OnceLock<Arc<ClientConfig>>,
+    // accepts any certificate, see `peer_certificates`
+    tls_capture: OnceLock<Arc<ClientConfig>>,
+}
+
+impl Prober {
+    pub fn new(cfg: &ProbeConfig) -> Self {
+        let burst = f64::from(cfg.burst.max(1));
+        Self {
+            targets: cfg.targets.clone(),
+            max_timeout: Duration::from_millis(cfg.max_timeout),
+            rate: cfg.rate,
+            burst,
+            bucket: Mutex::new((burst, Instant::now())),
+            tls: OnceLock::new(),
+            tls_capture: OnceLock::new(),
+        }
+    }
+
+    /// Sends one query over UDP to `server` (`host` or `host:port`), or the
+    /// first nameserver of `/etc/resolv.conf`.
+    pub fn resolve(
+        &self,
+        name: &str,
+        ty: RecordType,
+        server: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Resolved> {
+        self.admit(name)?;
+        let server = match server {
+            Some(server) => {
+                self.admit_target(host_of(server))?;
+                resolve_addr(server, DNS_PORT)?
+            }
+            None => {
+                let conf = fs::read_to_string("/etc/resolv.conf")
+                    .context("Failed to read /etc/resolv.conf")?;
+                let Some(ip) = dns::nameserver(&conf) else {
+                    bail!("No nameserver in /etc/resolv.conf");
+                };
+                SocketAddr::new(ip, DNS_PORT)
+            }
+        };
+        let timeout = self.timeout(timeout);
+
+        // not meant to withstand spoofing, only to pair replies with queries
+        let id = (SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos()
+            ^ process::id()) as u16;
+        let query = dns::query(id, name, ty)?;
+        let bind: SocketAddr = match server {
+            SocketAddr::V4(_) => "0.0.0.0:0".parse()?,
+            SocketAddr::V6(_) => "[::]:0".parse()?,
+        };
+        let socket = UdpSocket::bind(bind)?;
+        socket.connect(server)?;
+
+        let start = Instant::now();
+        socket.send(&query)?;
+        let mut buf = [0; 4096];
+        loop {
+            let left = timeout.saturating_sub(start.elapsed());
+            if left.is_zero() {
+                bail!("DNS query to {server} timed out");
+            }
+            socket.set_read_timeout(Some(left))?;
+            let len = match socket.recv(&mut buf) {
+                Ok(len) => len,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e).context(format!("DNS query to {server} failed")),
+            };
+            // late replies to earlier queries are skipped
+            if len < 2 || u16::from_be_bytes([buf[0], buf[1]]) != id {
+                continue;
+            }
+            return Ok(Resolved {
+                answer: dns::parse_response(id, ty, &buf[..len])?,
+                duration: start.elapsed(),
+            });
+        }
+    }
+
+    pub fn tcp_connect(&self, host: &str, port: u16, timeout: Duration) -> Result<Connected> {
+        self.admit(host)?;
+        let address = resolve_addr(host, port)?;
+        let start = Instant::now();
+        TcpStream::connect_timeout(&address, self.timeout(timeout))
+            .with_context(|| format!("Failed to connect to {address}"))?;
+        Ok(Connected {
+            address,
+            duration: start.elapsed(),
+        })
+    }
+
+    /// Verifies the certificate of `server_name`, `host` if none, against
+    /// the system roots.
+    pub fn tls_handshake(
+        &self,
+        host: &str,
+        port: u16,
+        server_name: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Handshake> {
+        let tls = self.connect_tls(host, port, server_name, timeout, self.tls_config()?)?;
+        let conn = &tls.conn;
+        Ok(Handshake {
+            address: tls.address,
+            connect: tls.connect,
+            handshake: tls.handshake,
+            version: conn
+                .protocol_version()
+                .map(|it| format!("{it:?}"))
+                .unwrap_or_default(),
+            cipher_suite: conn
+                .negotiated_cipher_suite()
+                .map(|it| format!("{:?}", it.suite()))
+                .unwrap_or_default(),
+            peer_certificates: conn.peer_certificates().map_or(0, <[_]>::len),
+        })
+    }
+
+    /// DER encoded certificates sent by the peer, leaf first. They are not
+    /// verified, so expired or self signed ones can be inspected.
+    pub fn peer_certificates(
+        &self,
+        host: &str,
+        port: u16,
+        server_name: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Vec<Vec<u8>>> {
+        let tls = self.connect_tls(host, port, server_name, timeout, self.capture_config()?)?;
+        Ok(tls
+            .conn
+            .peer_certificates()
+            .unwrap_or_default()
+            .iter()
+            .map(|it| it.to_vec())
+            .collect())
+    }
+
+    fn connect_tls(
+        &self,
+        host: &str,
+        port: u16,
+        server_name: Option<&str>,
+        timeout: Duration,
+        config: Arc<ClientConfig>,
+    ) -> Result<Tls> {
+        self.admit(host)?;
+        let server_name = ServerName::try_from(server_name.unwrap_or(host).to_string())
+            .context("Invalid server name")?;
+        let address = resolve_addr(host, port)?;
+        let timeout = self.timeout(timeout);
+
+        let start = Instant::now();
+        let mut stream = TcpStream::connect_timeout(&address, timeout)
+            .with_context(|| format!("Failed to connect to {address}"))?;
+        let connect = start.elapsed();
+
+        let left = timeout
+            .saturating_sub(connect)
+            .max(Duration::from_millis(1));
+        stream.set_read_timeout(Some(left))?;
+        stream.set_write_timeout(Some(left))?;
+        let mut conn = ClientConnection::new(config, server_name)?;
+        let start = Instant::now();
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)
+                .context("TLS handshake failed")?;
+        }
+        let handshake = start.elapsed();
+        // best effort close_notify, the handshake is all we wanted
+        conn.send_close_notify();
+        let _ = conn.complete_io(&mut stream);
+
+        Ok(Tls {
+            address,
+            connect,
+            handshake,
+            conn,
+        })
+    }
+
+    /// Every echo request takes a rate limit token, all up front. Count and
+    /// interval are clamped, none is sent past the deadline of `opts`.
+    pub fn ping(&self, host: &str, opts: &PingOptions) -> Result<Pinged> {
+        self.admit_target(host)?;
+        if opts.count == 0 {
+            bail!("No echo requests to send");
+        }
+        if opts.payload_size > MAX_PING_PAYLOAD {
+            bail!("Payload of {} bytes is too large", opts.payload_size);
+        }
+        if opts.deadline.is_some_and(|it| Instant::now() >= it) {
+            bail!("The time slice of the run is over");
+        }
+        let opts = PingOptions {
+            count: opts.count.min(MAX_PING_COUNT),
+            interval: opts.interval.clamp(MIN_PING_INTERVAL, MAX_PING_INTERVAL),
+            timeout: self.timeout(opts.timeout),
+            ..*opts
+        };
+        self.take_tokens(opts.count)?;
+        let address = resolve_addr(host, 0)?.ip();
+        Ok(Pinged {
+            address,
+            rtts: icmp::ping(address, &opts)?,
+        })
+    }
+
+    fn tls_config(&self) -> Result<Arc<ClientConfig>> {
+        if let Some(config) = self.tls.get() {
+            return Ok(Arc::clone(config));
+        }
+        let mut roots = RootCertStore::empty();
+        let native = rustls_native_certs::load_native_certs();
+        let (added, _) = roots.add_parsable_certificates(native.certs);
+        if added == 0 {
+            bail!("No system root certificates");
+        }
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(Arc::clone(self.tls.get_or_init(|| Arc::new(config))))
+    }
+
+    fn capture_config(&self) -> Result<Arc<ClientConfig>> {
+        if let Some(config) = self.tls_capture.get() {
+            return Ok(Arc::clone(config));
+        }
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(CaptureVerifier(provider)))
+            .with_no_client_auth();
+        Ok(Arc::clone(
+            self.tls_capture.get_or_init(|| Arc::new(config)),
+        ))
+    }
+
+    fn timeout(&self, requested: Duration) -> Duration {
+        match requested {
+            Duration::ZERO => self.max_timeout,
+            t => t.min(self.max_timeout),
+        }
+    }
+
+    /// Checks the target against the allowlist and takes a rate limit token.
+    fn admit(&self, target: &str) -> Result<()> {
+        self.admit_target(target)?;
+        self.take_tokens(1)
+    }
+
+    fn take_tokens(&self, n: u32) -> Result<()> {
+        let n = f64::from(n);
+        if n > self.burst {
+            bail!("{n} probes exceed the rate limit burst");
+        }
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(bucket.1).as_secs_f64();
+        let tokens = (bucket.0 + elapsed * self.rate).min(self.burst);
+        if tokens < n {
+            *bucket = (tokens, now);
+            bail!("Probe rate limit exceeded");
+        }
+        *bucket = (tokens - n, now);
+        Ok(())
+    }
+
+    /// Only listed targets may be probed, none if the list is empty.
+    fn admit_target(&self, target: &str) -> Result<()> {
+        if self.targets.iter().any(|it| matches(it, target)) {
+            return Ok(());
+        }
+        bail!("Probing {target} is not allowed")
+    }
+}
+
+/// Trusts any certificate but still checks the handshake signatures, the
+/// peer must hold the key of the certificate it sent.
+#[derive(Debug)]
+struct CaptureVerifier(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for CaptureVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// `pattern` is a host or address, `*.example.com` also matches subdomains.
+fn matches(pattern: &str, target: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let target = target.trim_end_matches('.').to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => target
+            .strip_suffix(domain)
+            .is_some_and(|it| it.ends_with('.')),
+        None => pattern == target,
+    }
+}
+
+/// `host` of `host`, `host:port` or `[v6]:port`.
+fn host_of(addr: &str) -> &str {
+    if let Some(rest) = addr.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest);
+    }
+    match addr.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') && port.parse::<u16>().is_ok() => host,
+        _ => addr,
+    }
+}
+
+fn resolve_addr(addr: &str, default_port: u16) -> Result<SocketAddr> {
+    let host = host_of(addr);
+    let port = match addr.rsplit_once(':') {
+        Some((_, port)) if host.len() != addr.len() => port.parse().unwrap_or(default_port),
+        _ => default_port,
+    };
+    (host, port)
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {addr}"))?
+        .next()
+        .with_context(|| format!("No address for {addr}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Prober, host_of, matches};
+    use crate::config::ProbeConfig;
+
+    #[test]
+    fn test_admit_target() {
+        let prober = |targets: &[&str]| {
+            Prober::new(&ProbeConfig {
+                targets: targets.iter().map(|it| it.to_string()).collect(),
+                ..Default::default()
+            })
+        };
+        assert!(prober(&[]).admit_target("169.254.169.254").is_err());
+        assert!(prober(&[]).admit_target("localhost").is_err());
+        let prober = prober(&["*.example.com"]);
+        assert!(prober.admit_target("www.example.com").is_ok());
+        assert!(prober.admit_target("localhost").is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("example.com", "example.com"));
+        assert!(matches("example.com", "Example.com."));
+        assert!(!matches("example.com", "www.example.com"));
+        assert!(matches("*.example.com", "www.example.com"));
+        assert!(!matches("*.example.com", "example.com"));
+        assert!(!matches("*.example.com", "badexample.com"));
+        assert!(matches("10.0.0.1", "10.0.0.1"));
+    }
+
+    #[test]
+    fn test_host_of() {
+        assert_eq!(host_of("1.1.1.1"), "1.1.1.1");
+        assert_eq!(host_of("1.1.1.1:5353"), "1.1.1.1");
+        assert_eq!(host_of("dns.example.com:53"), "dns.example.com");
+        assert_eq!(host_of("[::1]:53"), "::1");
+        assert_eq!(host_of("::1"), "::1");
+    }
+}
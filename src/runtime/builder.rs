@@ -12,24 +12,92 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, atomic::AtomicBool},
+    time::Duration,
+};
+
 use anyhow::Context;
 use host_op_perf::PerfCtx;
 use host_op_system::SysCtx;
+use psh_system::rate_limit::RateLimiter;
+use rand_chacha::{ChaCha20Rng, rand_core::SeedableRng};
 use wasmtime::{
-    Config, Engine, Store,
+    Config, Engine, Store, WasmBacktraceDetails,
     component::{Linker, ResourceTable},
 };
 use wasmtime_wasi::{DirPerms, FilePerms, StdinStream, StdoutStream, WasiCtxBuilder};
+use wasmtime_wasi_http::WasiHttpCtx;
 
-use super::{DataExportCtx, PshEngine, PshState, data_export};
+use super::{
+    CancelToken, DataExportCtx, MemoryLimits, PshEngine, PshState, data_export,
+    host::{
+        blackbox::{self, BlackBoxCtx},
+        bursts::{self, BurstsCtx},
+        bus::{self, BusCtx},
+        cancellation::{self, CancelCtx},
+        capture::{self, CaptureCtx},
+        certificates::{self, CertificatesCtx},
+        clock,
+        history::{self, HistoryCtx},
+        integrity::{self, IntegrityCtx},
+        introspect::{self, Grants, IntrospectCtx, Linked},
+        kernel_events::{self, KernelEventsCtx},
+        kubernetes::{self, KubernetesCtx},
+        log::{self, LogCtx},
+        msr::{self, MsrCtx},
+        pipeline::{self, PipelineCtx},
+        process_events::{self, ProcessEventsCtx},
+        scheduling::{self, SchedulingCtx},
+        store::{self, StoreCtx},
+        streams,
+        symbolize::{self, SymbolizeCtx},
+        sysfs::{self, SysfsCtx},
+        task_result::{self, TaskResult, TaskResultCtx},
+    },
+    pipeline::Payload,
+    policy::{HttpPolicy, InterfacePolicy, SocketPolicy},
+    pool::{EngineKey, EnginePool},
+    probe::{self, ProbeCtx},
+};
+use crate::{config::PreopenConfig, sysfs::Caller};
 
 #[allow(dead_code)]
 pub struct PshEngineBuilder {
     wasi_ctx_builder: WasiCtxBuilder,
     engine_config: Config,
+    preopen_root: bool,
+    preopens: Vec<PreopenConfig>,
+    sockets: Option<SocketPolicy>,
+    http: Option<HttpPolicy>,
     use_perf_op: bool,
     use_system_op: bool,
+    use_environ_op: bool,
+    use_capture_op: bool,
+    use_probe_op: bool,
+    use_ping_op: bool,
+    use_msr_op: bool,
+    use_integrity_baseline: bool,
+    sysfs_caller: Option<Caller>,
+    tenant: Option<String>,
+    log_ctx: LogCtx,
+    pipeline_ctx: PipelineCtx,
+    store_namespace: Option<String>,
+    task_result: Option<Arc<Mutex<Option<TaskResult>>>>,
     data_export_ctx: Option<DataExportCtx>,
+    memory_limits: MemoryLimits,
+    memory_limit: Option<usize>,
+    stop: Option<Arc<AtomicBool>>,
+    cancel: CancelToken,
+    cancel_grace: Duration,
+    policy: Option<&'static InterfacePolicy>,
+    isolated: bool,
+    max_cpu_ms: u64,
+    rate_limits: BTreeMap<String, u32>,
+    consume_fuel: bool,
+    engine_pool: Option<Arc<EnginePool>>,
+    warm: bool,
 }
 
 #[allow(dead_code)]
@@ -38,43 +106,191 @@ impl PshEngineBuilder {
         let mut engine_config = Config::new();
         engine_config.wasm_component_model(true);
         engine_config.epoch_interruption(true);
+        // WASI and data export calls await on the tokio runtime of the daemon
+        engine_config.async_support(true);
+        // compiling interpreters of Python and JavaScript components is slow
+        engine_config.parallel_compilation(true);
         Self {
             wasi_ctx_builder: WasiCtxBuilder::new(),
             engine_config,
+            preopen_root: true,
+            preopens: vec![],
+            sockets: None,
+            http: None,
             use_perf_op: false,
             use_system_op: false,
+            use_environ_op: false,
+            use_capture_op: false,
+            use_probe_op: false,
+            use_ping_op: false,
+            use_msr_op: false,
+            use_integrity_baseline: false,
+            sysfs_caller: None,
+            tenant: None,
+            log_ctx: LogCtx::default(),
+            pipeline_ctx: PipelineCtx::default(),
+            store_namespace: None,
+            task_result: None,
             data_export_ctx: None,
+            memory_limits: MemoryLimits::default(),
+            memory_limit: None,
+            stop: None,
+            cancel: CancelToken::default(),
+            cancel_grace: Duration::ZERO,
+            policy: None,
+            isolated: false,
+            max_cpu_ms: 0,
+            rate_limits: BTreeMap::new(),
+            consume_fuel: false,
+            engine_pool: None,
+            warm: false,
         }
     }
 
+    /// The engine alone, components compiled by it run in engines built
+    /// with the same settings.
+    pub fn build_engine(&self) -> anyhow::Result<Engine> {
+        Engine::new(&self.engine_config).context("Failed to create Wasi Engine.")
+    }
+
     pub fn build(mut self) -> anyhow::Result<PshEngine> {
-        let engine = Engine::new(&self.engine_config).context("Failed to create Wasi Engine.")?;
-        let mut linker: Linker<PshState> = Linker::new(&engine);
-        wasmtime_wasi::add_to_linker_sync(&mut linker)
-            .context("Failed to link wasi sync module")?;
-        if self.use_perf_op {
-            host_op_perf::add_to_linker(&mut linker, |state| &mut state.perf_ctx)
-                .context("Failed to link perf module")?;
+        let key = EngineKey {
+            consume_fuel: self.consume_fuel,
+            perf_op: self.use_perf_op,
+            system_op: self.use_system_op,
+            data_export_op: self.data_export_ctx.is_some(),
+        };
+        let (engine, linker, shared) = match self.engine_pool.take() {
+            Some(pool) => {
+                let shared = pool.engine(key, || {
+                    let engine = self.build_engine()?;
+                    let linker = self.link(&engine)?;
+                    Ok((engine, linker))
+                })?;
+                (shared.engine.clone(), shared.linker.clone(), Some(shared))
+            }
+            None => {
+                let engine = self.build_engine()?;
+                let linker = self.link(&engine)?;
+                (engine, linker, None)
+            }
+        };
+
+        if self.preopen_root {
+            self.wasi_ctx_builder
+                .preopened_dir("/", "/", DirPerms::READ, FilePerms::READ)?;
         }
-        if self.use_system_op {
-            host_op_system::add_to_linker(&mut linker, |state| &mut state.sys_ctx)
-                .context("Failed to link system module")?;
-        }
-        if self.data_export_ctx.is_some() {
-            data_export::add_to_linker(&mut linker, |state| &mut state.data_export_ctx)
-                .context("Failed to link data-export module")?;
+        for preopen in &self.preopens {
+            let (dir_perms, file_perms) = if preopen.writable {
+                (DirPerms::all(), FilePerms::all())
+            } else {
+                (DirPerms::READ, FilePerms::READ)
+            };
+            let guest = preopen.guest.as_deref().unwrap_or(&preopen.host);
+            self.wasi_ctx_builder
+                .preopened_dir(&preopen.host, guest, dir_perms, file_perms)
+                .with_context(|| format!("Failed to preopen {}", preopen.host))?;
         }
 
-        self.wasi_ctx_builder
-            .preopened_dir("/", "/", DirPerms::READ, FilePerms::READ)?;
+        let linked = Linked {
+            perf: self.use_perf_op,
+            system: self.use_system_op,
+            data_export: self.data_export_ctx.is_some(),
+        };
+        let sockets = self.sockets.unwrap_or_default();
+        let grants = Grants {
+            environ: self.use_environ_op,
+            data_export: self.data_export_ctx.is_some(),
+            capture: self.use_capture_op,
+            probe: self.use_probe_op,
+            ping: self.use_probe_op && self.use_ping_op,
+            msr: self.use_msr_op,
+            sysfs: self.sysfs_caller.is_some(),
+            tcp: sockets.tcp,
+            udp: sockets.udp,
+            name_lookup: sockets.name_lookup,
+            http: self.http.is_some(),
+            preopens: self
+                .preopen_root
+                .then(|| "/".to_string())
+                .into_iter()
+                .chain(
+                    self.preopens
+                        .iter()
+                        .map(|it| it.guest.clone().unwrap_or_else(|| it.host.clone())),
+                )
+                .collect(),
+        };
+        let introspect_ctx = IntrospectCtx::new(&linked, self.policy, grants);
 
+        let capture_ctx = CaptureCtx {
+            enable: self.use_capture_op,
+            export: self.data_export_ctx.clone(),
+        };
+        let cancel_ctx = CancelCtx {
+            token: self.cancel.clone(),
+            deadline: None,
+            grace: self.cancel_grace,
+            stop: self.stop.clone(),
+        };
         let state = PshState {
             name: "PSH Wasi Runtime".to_owned(),
             table: ResourceTable::new(),
             wasi_ctx: self.wasi_ctx_builder.build(),
-            perf_ctx: PerfCtx::new(),
-            sys_ctx: SysCtx::default(),
-            data_export_ctx: self.data_export_ctx.unwrap_or(DataExportCtx { ctx: None }),
+            http_ctx: WasiHttpCtx::new(),
+            http_policy: self.http,
+            perf_ctx: PerfCtx::new().rate_limiter(RateLimiter::new(&self.rate_limits)),
+            sys_ctx: SysCtx::default()
+                .allow_environ(self.use_environ_op)
+                .rate_limiter(RateLimiter::new(&self.rate_limits)),
+            data_export_ctx: self.data_export_ctx.unwrap_or(DataExportCtx {
+                ctx: None,
+                artifact: None,
+                tenant: None,
+            }),
+            scheduling_ctx: SchedulingCtx::default(),
+            blackbox_ctx: BlackBoxCtx,
+            bursts_ctx: BurstsCtx,
+            bus_ctx: BusCtx::new(
+                self.tenant.clone(),
+                Arc::clone(&self.log_ctx.component),
+                self.log_ctx.task_id.as_deref(),
+                cancel_ctx.clone(),
+            ),
+            cancel_ctx,
+            kernel_events_ctx: KernelEventsCtx::default(),
+            process_events_ctx: ProcessEventsCtx::default(),
+            integrity_ctx: IntegrityCtx {
+                allow_baseline: self.use_integrity_baseline,
+            },
+            introspect_ctx,
+            certificates_ctx: CertificatesCtx,
+            history_ctx: HistoryCtx {
+                tenant: self.tenant,
+            },
+            store_ctx: StoreCtx {
+                namespace: self.store_namespace,
+            },
+            symbolize_ctx: SymbolizeCtx,
+            kubernetes_ctx: KubernetesCtx,
+            log_ctx: self.log_ctx,
+            pipeline_ctx: self.pipeline_ctx,
+            msr_ctx: MsrCtx {
+                enable: self.use_msr_op,
+            },
+            sysfs_ctx: SysfsCtx {
+                caller: self.sysfs_caller,
+            },
+            task_result_ctx: TaskResultCtx {
+                result: self.task_result.unwrap_or_default(),
+            },
+            capture_ctx,
+            probe_ctx: ProbeCtx {
+                allow_probe: self.use_probe_op,
+                allow_ping: self.use_ping_op,
+                deadline: None,
+            },
+            limits: Default::default(),
         };
         let store = Store::new(&engine, state);
 
@@ -82,9 +298,91 @@ impl PshEngineBuilder {
             engine,
             store,
             linker,
+            memory_limits: self.memory_limits,
+            memory_limit: self.memory_limit,
+            stop: self.stop,
+            cancel: self.cancel,
+            cancel_grace: self.cancel_grace,
+            policy: self.policy,
+            isolated: self.isolated,
+            max_cpu_ms: self.max_cpu_ms,
+            shared,
+            warm: self.warm,
         })
     }
 
+    /// The linker of [`Self::build`] without a store, to check the imports
+    /// of a component without running it.
+    pub fn build_linker(&self, engine: &Engine) -> anyhow::Result<Linker<PshState>> {
+        self.link(engine)
+    }
+
+    fn link(&self, engine: &Engine) -> anyhow::Result<Linker<PshState>> {
+        let mut linker: Linker<PshState> = Linker::new(engine);
+        wasmtime_wasi::add_to_linker_async(&mut linker)
+            .context("Failed to link wasi async module")?;
+        wasmtime_wasi_http::add_only_http_to_linker_async(&mut linker)
+            .context("Failed to link wasi http module")?;
+        scheduling::add_to_linker(&mut linker, |state| &mut state.scheduling_ctx)
+            .context("Failed to link scheduling module")?;
+        blackbox::add_to_linker(&mut linker, |state| &mut state.blackbox_ctx)
+            .context("Failed to link blackbox module")?;
+        bursts::add_to_linker(&mut linker, |state| &mut state.bursts_ctx)
+            .context("Failed to link bursts module")?;
+        bus::add_to_linker(&mut linker, |state| &mut state.bus_ctx)
+            .context("Failed to link bus module")?;
+        kernel_events::add_to_linker(&mut linker, |state| &mut state.kernel_events_ctx)
+            .context("Failed to link kernel-events module")?;
+        process_events::add_to_linker(&mut linker, |state| &mut state.process_events_ctx)
+            .context("Failed to link process-events module")?;
+        integrity::add_to_linker(&mut linker, |state| &mut state.integrity_ctx)
+            .context("Failed to link integrity module")?;
+        introspect::add_to_linker(&mut linker, |state| &mut state.introspect_ctx)
+            .context("Failed to link introspect module")?;
+        certificates::add_to_linker(&mut linker, |state| &mut state.certificates_ctx)
+            .context("Failed to link certificates module")?;
+        history::add_to_linker(&mut linker, |state| &mut state.history_ctx)
+            .context("Failed to link history module")?;
+        store::add_to_linker(&mut linker, |state| &mut state.store_ctx)
+            .context("Failed to link store module")?;
+        symbolize::add_to_linker(&mut linker, |state| &mut state.symbolize_ctx)
+            .context("Failed to link symbolize module")?;
+        kubernetes::add_to_linker(&mut linker, |state| &mut state.kubernetes_ctx)
+            .context("Failed to link kubernetes module")?;
+        log::add_to_linker(&mut linker, |state| &mut state.log_ctx)
+            .context("Failed to link log module")?;
+        msr::add_to_linker(&mut linker, |state| &mut state.msr_ctx)
+            .context("Failed to link msr module")?;
+        pipeline::add_to_linker(&mut linker, |state| &mut state.pipeline_ctx)
+            .context("Failed to link pipeline module")?;
+        sysfs::add_to_linker(&mut linker, |state| &mut state.sysfs_ctx)
+            .context("Failed to link sysfs module")?;
+        task_result::add_to_linker(&mut linker, |state| &mut state.task_result_ctx)
+            .context("Failed to link task-result module")?;
+        streams::add_to_linker(&mut linker, |state| state)
+            .context("Failed to link streams module")?;
+        cancellation::add_to_linker(&mut linker, |state| state)
+            .context("Failed to link cancellation module")?;
+        clock::add_to_linker(&mut linker, |state| state).context("Failed to link clock module")?;
+        if self.use_perf_op {
+            host_op_perf::add_to_linker(&mut linker, |state| &mut state.perf_ctx)
+                .context("Failed to link perf module")?;
+        }
+        if self.use_system_op {
+            host_op_system::add_to_linker(&mut linker, |state| &mut state.sys_ctx)
+                .context("Failed to link system module")?;
+        }
+        if self.data_export_ctx.is_some() {
+            data_export::add_to_linker(&mut linker, |state| &mut state.data_export_ctx)
+                .context("Failed to link data-export module")?;
+        }
+        capture::add_to_linker(&mut linker, |state| &mut state.capture_ctx)
+            .context("Failed to link capture module")?;
+        probe::add_to_linker(&mut linker, |state| &mut state.probe_ctx)
+            .context("Failed to link probe module")?;
+        Ok(linker)
+    }
+
     pub fn wasi_stdin(mut self, stdin: impl StdinStream + 'static) -> Self {
         self.wasi_ctx_builder.stdin(stdin);
         self
@@ -139,6 +437,59 @@ impl PshEngineBuilder {
         self
     }
 
+    /// The whole filesystem read only as `/`, on by default.
+    pub const fn wasi_preopen_root(mut self, enable: bool) -> Self {
+        self.preopen_root = enable;
+        self
+    }
+
+    /// Host directories the component may access besides `/`, the longest
+    /// matching guest path wins.
+    pub fn wasi_preopens(mut self, preopens: &[PreopenConfig]) -> Self {
+        self.preopens.extend(preopens.iter().cloned());
+        self
+    }
+
+    /// Sockets the component may open through `wasi:sockets`, none if
+    /// `None`.
+    pub fn wasi_sockets(mut self, policy: Option<&SocketPolicy>) -> Self {
+        let Some(policy) = policy.cloned() else {
+            return self;
+        };
+        self.sockets = Some(policy.clone());
+        self.wasi_ctx_builder
+            .allow_tcp(policy.tcp)
+            .allow_udp(policy.udp)
+            .allow_ip_name_lookup(policy.name_lookup)
+            .socket_addr_check(move |addr, _| {
+                let allowed = policy.allows(addr);
+                Box::pin(async move { allowed })
+            });
+        self
+    }
+
+    /// Destinations the component may send requests to through
+    /// `wasi:http`, none if `None`.
+    pub fn wasi_http(mut self, policy: Option<&HttpPolicy>) -> Self {
+        self.http = policy.cloned();
+        self
+    }
+
+    /// wasi:random/insecure draws from ChaCha20 seeded with `seed` instead
+    /// of the OS, runs with the same seed see the same numbers.
+    ///
+    /// wasi:random/random stays on the OS's randomness, the seed is
+    /// recorded in the ledger and anyone reading it could predict keys and
+    /// nonces.
+    pub fn wasi_random_seed(mut self, seed: Option<u64>) -> Self {
+        if let Some(seed) = seed {
+            self.wasi_ctx_builder
+                .insecure_random(ChaCha20Rng::seed_from_u64(seed))
+                .insecure_random_seed(u128::from(seed));
+        }
+        self
+    }
+
     pub fn wasi_inherit_network(mut self) -> Self {
         self.wasi_ctx_builder.inherit_network();
         self
@@ -159,6 +510,95 @@ impl PshEngineBuilder {
         self
     }
 
+    /// Limits are picked by the language of the component, see [`Language`](super::Language).
+    pub const fn memory_limits(mut self, limits: MemoryLimits) -> Self {
+        self.memory_limits = limits;
+        self
+    }
+
+    /// Max linear memory of the component in bytes whatever its language,
+    /// 0 for no limit.
+    pub const fn wasm_memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Interrupts the component once `stop` is set.
+    pub fn stop_flag(mut self, stop: Option<Arc<AtomicBool>>) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    /// The component is asked to wrap up once `token` is cancelled and
+    /// interrupted `grace` later, or `grace` before its time slice runs out.
+    pub fn cancel_token(mut self, token: CancelToken, grace: Duration) -> Self {
+        self.cancel = token;
+        self.cancel_grace = grace;
+        self
+    }
+
+    /// Components importing interfaces `policy` denies fail to start, all
+    /// of them are linked otherwise.
+    pub const fn interface_policy(mut self, policy: Option<&'static InterfacePolicy>) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Runs in a child process of `[engine] isolate`, components importing
+    /// the event streams only the daemon follows fail to start.
+    pub const fn isolated(mut self, isolated: bool) -> Self {
+        self.isolated = isolated;
+        self
+    }
+
+    /// Interrupts the component with [`CpuTimeExceeded`](super::CpuTimeExceeded)
+    /// once it has used `ms` milliseconds of CPU time, 0 for no limit.
+    pub const fn max_cpu_ms(mut self, ms: u64) -> Self {
+        self.max_cpu_ms = ms;
+        self
+    }
+
+    /// Max calls per second to host interfaces like `process` or `perf`,
+    /// further calls fail with a "Rate limited" error the component sees.
+    pub fn host_call_rate_limits(mut self, limits: BTreeMap<String, u32>) -> Self {
+        self.rate_limits = limits;
+        self
+    }
+
+    /// Takes the engine and linker from `pool`, shared by all components
+    /// built with the same settings, the store is still new.
+    pub fn engine_pool(mut self, pool: Option<Arc<EnginePool>>) -> Self {
+        self.engine_pool = pool;
+        self
+    }
+
+    /// Keeps the pre-instantiated component in the engine of the pool for
+    /// the next run, which only has to instantiate it.
+    pub const fn warm(mut self, enable: bool) -> Self {
+        self.warm = enable;
+        self
+    }
+
+    /// Counts the fuel consumed by components, at some cost in speed.
+    pub fn consume_fuel(mut self, enable: bool) -> Self {
+        self.consume_fuel = enable;
+        self.engine_config.consume_fuel(enable);
+        self
+    }
+
+    /// Backtraces of traps carry the source file and line of every frame,
+    /// read from the DWARF of components built with debug info.
+    ///
+    /// Engines of a pool must agree on it, it is not part of their key.
+    pub fn backtrace_details(mut self, enable: bool) -> Self {
+        self.engine_config.wasm_backtrace_details(if enable {
+            WasmBacktraceDetails::Enable
+        } else {
+            WasmBacktraceDetails::Disable
+        });
+        self
+    }
+
     pub const fn allow_perf_op(mut self, enable: bool) -> Self {
         self.use_perf_op = enable;
         self
@@ -169,6 +609,95 @@ impl PshEngineBuilder {
         self
     }
 
+    /// Process environments also need `[environ]` to be enabled.
+    pub const fn allow_environ_op(mut self, enable: bool) -> Self {
+        self.use_environ_op = enable;
+        self
+    }
+
+    /// Packet capture also needs `[capture]` to be enabled.
+    pub const fn allow_capture_op(mut self, enable: bool) -> Self {
+        self.use_capture_op = enable;
+        self
+    }
+
+    /// Probes also need `[probe]` to be enabled and the target listed.
+    pub const fn allow_probe_op(mut self, enable: bool) -> Self {
+        self.use_probe_op = enable;
+        self
+    }
+
+    /// ICMP ping also needs probes to be allowed.
+    pub const fn allow_ping_op(mut self, enable: bool) -> Self {
+        self.use_ping_op = enable;
+        self
+    }
+
+    /// MSR reading also needs `[msr]` to be enabled.
+    pub const fn allow_msr_op(mut self, enable: bool) -> Self {
+        self.use_msr_op = enable;
+        self
+    }
+
+    /// Replacing integrity baselines also needs `[integrity]` to be enabled.
+    pub const fn allow_integrity_baseline(mut self, enable: bool) -> Self {
+        self.use_integrity_baseline = enable;
+        self
+    }
+
+    /// Sysfs reading also needs `[sysfs]` to be enabled, accesses are
+    /// audited as `caller`.
+    pub fn allow_sysfs_op(mut self, caller: Option<Caller>) -> Self {
+        self.sysfs_caller = caller;
+        self
+    }
+
+    /// Tenant of the component, it only sees the history and bus topics of
+    /// its tenant.
+    pub fn tenant(mut self, tenant: Option<String>) -> Self {
+        self.tenant = tenant;
+        self
+    }
+
+    /// Names the records the component logs through `profiling:host/log`
+    /// and publishes through `profiling:host/bus`.
+    pub fn log_source(mut self, component: &str, task_id: Option<&str>) -> Self {
+        self.log_ctx = LogCtx {
+            component: component.into(),
+            task_id: task_id.map(Into::into),
+        };
+        self
+    }
+
+    /// What the component reads and emits through `profiling:host/pipeline`,
+    /// what it emits is dropped if `output` is absent.
+    pub fn pipeline(
+        mut self,
+        input: Vec<Payload>,
+        output: Option<Arc<Mutex<Vec<Payload>>>>,
+    ) -> Self {
+        self.pipeline_ctx = PipelineCtx {
+            input,
+            output,
+            output_size: 0,
+        };
+        self
+    }
+
+    /// Keys of the component in `profiling:host/store`, it has none if
+    /// absent.
+    pub fn store_namespace(mut self, namespace: Option<String>) -> Self {
+        self.store_namespace = namespace;
+        self
+    }
+
+    /// The result the component sets ends up in `result`, it is dropped
+    /// if absent.
+    pub fn task_result(mut self, result: Option<Arc<Mutex<Option<TaskResult>>>>) -> Self {
+        self.task_result = result;
+        self
+    }
+
     pub fn allow_data_export_op(mut self, ctx: Option<DataExportCtx>) -> Self {
         self.data_export_ctx = ctx;
         self
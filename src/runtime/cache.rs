@@ -0,0 +1,220 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fs::{self, File},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result, bail};
+use wasmtime::{Engine, component::Component};
+
+use super::PshEngineBuilder;
+use crate::config::EngineConfig;
+
+const EXTENSION: &str = "cwasm";
+
+static GLOBAL: OnceLock<ComponentCache> = OnceLock::new();
+
+/// The cache installed by [`init`], `None` if compiled components are not kept.
+pub fn global() -> Option<&'static ComponentCache> {
+    GLOBAL.get()
+}
+
+fn open(cfg: &EngineConfig) -> Result<ComponentCache> {
+    ComponentCache::new(
+        &cfg.cache_dir,
+        Duration::from_secs(cfg.cache_max_age * 86400),
+    )
+}
+
+pub fn init(cfg: &EngineConfig) -> Result<()> {
+    let cache = open(cfg)?;
+    if GLOBAL.set(cache).is_err() {
+        bail!("Component cache already initialized");
+    }
+    Ok(())
+}
+
+/// Compiles the components at `paths` into the cache ahead of their first
+/// run, with the engine settings of the daemon.
+pub fn precompile(paths: &[String], cfg: &EngineConfig, consume_fuel: bool) -> Result<()> {
+    if !cfg.cache {
+        bail!("The component cache is disabled, see `cache` in [engine]");
+    }
+    let cache = open(cfg)?;
+    let engine = PshEngineBuilder::new()
+        .consume_fuel(consume_fuel)
+        .backtrace_details(cfg.backtrace_details)
+        .build_engine()?;
+    for path in paths {
+        let binary = fs::read(path).with_context(|| format!("Failed to read {path}"))?;
+        let (entry, compiled) = cache
+            .precompile(&engine, &binary)
+            .with_context(|| format!("Failed to compile {path}"))?;
+        if compiled {
+            println!("{path}: compiled into {}", entry.display());
+        } else {
+            println!("{path}: already cached in {}", entry.display());
+        }
+    }
+    Ok(())
+}
+
+/// Compiled components by digest of their binary, compiling embedded
+/// interpreters takes seconds.
+pub struct ComponentCache {
+    dir: PathBuf,
+    /// entries not used for this long are removed
+    max_age: Duration,
+}
+
+impl ComponentCache {
+    pub fn new(dir: impl Into<PathBuf>, max_age: Duration) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(Self { dir, max_age })
+    }
+
+    /// Loads the compilation of an earlier run, or compiles `binary` and
+    /// keeps the result.
+    pub fn load(&self, engine: &Engine, binary: &[u8]) -> Result<Component> {
+        let path = self.path(engine, binary);
+        if path.exists() {
+            // SAFETY: entries are written by `store` only, the key covers
+            // the binary and the engine settings it was compiled with
+            match unsafe { Component::deserialize_file(engine, &path) } {
+                Ok(component) => {
+                    // keeps used entries from expiring
+                    let _ = File::options()
+                        .write(true)
+                        .open(&path)
+                        .and_then(|it| it.set_modified(SystemTime::now()));
+                    return Ok(component);
+                }
+                Err(e) => tracing::warn!("Dropping cached {}: {e}", path.display()),
+            }
+        }
+        let component = Component::from_binary(engine, binary)?;
+        if let Err(e) = component
+            .serialize()
+            .and_then(|bytes| self.store(&path, &bytes))
+        {
+            tracing::warn!("Failed to cache component {}: {e:#}", path.display());
+        }
+        Ok(component)
+    }
+
+    /// Compiles `binary` unless it is cached already, returns the entry and
+    /// whether it was compiled now.
+    pub fn precompile(&self, engine: &Engine, binary: &[u8]) -> Result<(PathBuf, bool)> {
+        let path = self.path(engine, binary);
+        if path.exists() {
+            return Ok((path, false));
+        }
+        let bytes = engine.precompile_component(binary)?;
+        self.store(&path, &bytes)?;
+        Ok((path, true))
+    }
+
+    fn path(&self, engine: &Engine, binary: &[u8]) -> PathBuf {
+        let digest = ring::digest::digest(&ring::digest::SHA256, binary);
+        let mut hasher = DefaultHasher::new();
+        engine.precompile_compatibility_hash().hash(&mut hasher);
+        let name: String = digest
+            .as_ref()
+            .iter()
+            .map(|it| format!("{it:02x}"))
+            .collect();
+        self.dir
+            .join(format!("{name}-{:016x}.{EXTENSION}", hasher.finish()))
+    }
+
+    fn store(&self, path: &Path, bytes: &[u8]) -> Result<()> {
+        // renamed into place, so other runs never see a partial entry
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, path)?;
+        self.evict()
+    }
+
+    fn evict(&self) -> Result<()> {
+        let now = SystemTime::now();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|it| it != EXTENSION) {
+                continue;
+            }
+            let used = fs::metadata(&path).and_then(|it| it.modified())?;
+            if now.duration_since(used).unwrap_or_default() > self.max_age {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use wasmtime::{Config, Engine};
+
+    use super::ComponentCache;
+
+    #[test]
+    fn test_cache_reuses_compilation() {
+        let dir = std::env::temp_dir().join(format!("psh-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = ComponentCache::new(&dir, Duration::from_secs(3600)).unwrap();
+        // an empty component
+        let binary = b"\0asm\x0d\0\x01\0";
+
+        let engine = Engine::new(Config::new().wasm_component_model(true)).unwrap();
+        cache.load(&engine, binary).unwrap();
+        let entries = || fs::read_dir(&dir).unwrap().count();
+        assert_eq!(entries(), 1);
+        cache.load(&engine, binary).unwrap();
+        assert_eq!(entries(), 1);
+
+        // compiled with other settings, kept apart
+        let mut config = Config::new();
+        config.wasm_component_model(true).consume_fuel(true);
+        cache.load(&Engine::new(&config).unwrap(), binary).unwrap();
+        assert_eq!(entries(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_precompile_warms_cache() {
+        let dir = std::env::temp_dir().join(format!("psh-precompile-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = ComponentCache::new(&dir, Duration::from_secs(3600)).unwrap();
+        let binary = b"\0asm\x0d\0\x01\0";
+
+        let engine = Engine::new(Config::new().wasm_component_model(true)).unwrap();
+        let (entry, compiled) = cache.precompile(&engine, binary).unwrap();
+        assert!(compiled && entry.exists());
+        let (again, compiled) = cache.precompile(&engine, binary).unwrap();
+        assert!(!compiled);
+        assert_eq!(again, entry);
+        // the run after loads what was precompiled
+        cache.load(&engine, binary).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+
+use anyhow::{Result, bail};
+use tokio::sync::Notify;
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    at: Mutex<Option<Instant>>,
+    notify: Notify,
+}
+
+/// Asks a run to wrap up, the component sees it through
+/// `profiling:host/cancellation`.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<Inner>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        if !self.0.cancelled.swap(true, Ordering::AcqRel) {
+            *self.0.at.lock().unwrap() = Some(Instant::now());
+        }
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Acquire)
+    }
+
+    /// When the token was first cancelled.
+    pub fn cancelled_at(&self) -> Option<Instant> {
+        *self.0.at.lock().unwrap()
+    }
+
+    /// Returns once the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            // registered before the check, a cancel in between still wakes it
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Tokens of the running tasks, shared by the runtime and whatever cancels
+/// them.
+#[derive(Clone, Default)]
+pub struct Cancellations {
+    // task id if the task is of the server
+    running: Arc<Mutex<Vec<(Option<String>, CancelToken)>>>,
+    // set by `cancel_all`
+    closed: Arc<AtomicBool>,
+}
+
+impl Cancellations {
+    pub(super) fn register(&self, task_id: Option<String>) -> CancelToken {
+        let token = CancelToken::default();
+        self.running.lock().unwrap().push((task_id, token.clone()));
+        token
+    }
+
+    pub(super) fn unregister(&self, token: &CancelToken) {
+        self.running
+            .lock()
+            .unwrap()
+            .retain(|(_, it)| !Arc::ptr_eq(&it.0, &token.0));
+    }
+
+    /// Cancels the task `task_id` of the server.
+    pub fn cancel(&self, task_id: &str) -> Result<()> {
+        let running = self.running.lock().unwrap();
+        let Some((_, token)) = running
+            .iter()
+            .find(|(id, _)| id.as_deref() == Some(task_id))
+        else {
+            bail!("No running task {task_id}");
+        };
+        token.cancel();
+        Ok(())
+    }
+
+    /// Cancels every running task, local components included.
+    pub fn cancel_all(&self) {
+        self.closed.store(true, Ordering::Release);
+        for (_, token) in self.running.lock().unwrap().iter() {
+            token.cancel();
+        }
+    }
+
+    /// Every task was cancelled, nothing should start anymore.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.running.lock().unwrap().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cancellations;
+
+    #[test]
+    fn test_cancel() {
+        let cancellations = Cancellations::default();
+        let local = cancellations.register(None);
+        let task = cancellations.register(Some("t1".to_string()));
+
+        assert!(cancellations.cancel("t2").is_err());
+        cancellations.cancel("t1").unwrap();
+        assert!(task.is_cancelled());
+        assert!(task.cancelled_at().is_some());
+        assert!(!local.is_cancelled());
+
+        assert!(!cancellations.is_closed());
+        cancellations.cancel_all();
+        assert!(local.is_cancelled());
+        assert!(cancellations.is_closed());
+
+        cancellations.unregister(&local);
+        cancellations.unregister(&task);
+        assert!(cancellations.is_empty());
+    }
+}
@@ -13,24 +13,50 @@
 // see <https://www.gnu.org/licenses/>.
 
 use std::{
+    borrow::Cow,
+    mem,
     sync::{
-        Arc,
-        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
     },
-    thread::{self, JoinHandle},
+    time::Duration,
 };
 
+use chrono::Utc;
 use crossbeam::queue::SegQueue;
+use ext::profiling::data_export_ext::{
+    quota::{Limit as QuotaLimit, Throttled},
+    schema::FieldType,
+    trace::{Event as TraceEvent, Format as TraceFormat, Thread as TraceThread},
+};
+use host_op_system::redact;
 use influxdb_line_protocol::{LineProtocolBuilder, builder::FieldValue};
 use profiling::data_export::{
     common::FieldValue as WitFieldValue, measurement::Point, metric::Sample,
 };
 use prost::Message;
 use psh_proto::{Data, DataType, ExportDataReq};
-use tokio::runtime::Runtime;
+use tokio::{runtime::Handle, sync::Notify, task::JoinHandle};
 use wasmtime::component::Linker;
 
-use crate::services::rpc::RpcClient;
+use super::{
+    exporter::{self, Record, Value},
+    pipeline::Artifact,
+    schema::{Schema, Schemas},
+    tenant::Tenant,
+    throttle::{ExportRate, Limit},
+    timestamp::Timestamper,
+};
+use crate::{
+    history::{self, Entry},
+    kubernetes,
+    otlp::semconv,
+    services::{
+        export_stats::{self, Backend},
+        rpc::{FileMeta, RpcClient},
+    },
+    trace::{self, Event, Thread, Trace},
+};
 
 wasmtime::component::bindgen!({
     path: "psh-sdk-wit/wit/deps/data-export",
@@ -41,8 +67,23 @@ wasmtime::component::bindgen!({
     // by default the value is false, we use true here to compatible with our
     // previous implementations.
     trappable_imports: true,
+    // exports are queued on the tokio runtime of the daemon
+    async: true,
 });
 
+/// Host interfaces not yet published in psh-sdk-wit, see `wit/data-export-ext`.
+pub mod ext {
+    wasmtime::component::bindgen!({
+        path: "wit/data-export-ext",
+        world: "imports",
+        trappable_imports: true,
+        async: true,
+    });
+}
+
+// measurement the declared schemas are forwarded as
+const SCHEMA_MEASUREMENT: &str = "psh_measurement_schema";
+
 impl FieldValue for WitFieldValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -56,16 +97,55 @@ impl FieldValue for WitFieldValue {
     }
 }
 
+/// Queues what a run exports for a task on the tokio runtime of the daemon,
+/// the async export calls of the component never wait on the network.
 pub struct DataExporter {
     bytes_len: Arc<AtomicUsize>,
+    /// bytes scheduled since the exporter was created
+    bytes_total: AtomicU64,
     bytes_watermark: usize,
-    data_queue: Arc<SegQueue<Option<Data>>>,
+    data_queue: Arc<SegQueue<Option<(Data, Option<FileMeta>)>>>,
     task_id: String,
-    exporter: JoinHandle<()>,
+    // wakes the exporter task
+    notify: Arc<Notify>,
+    // takes the data instead of the queue
+    relay: Option<Relay>,
 }
 
+/// Tasks of the exporters still sending what they were given, awaited on
+/// shutdown.
+#[derive(Clone, Default)]
+pub struct ExportTasks(Arc<Mutex<Vec<JoinHandle<()>>>>);
+
+impl ExportTasks {
+    fn push(&self, handle: JoinHandle<()>) {
+        let mut tasks = self.0.lock().unwrap();
+        tasks.retain(|it| !it.is_finished());
+        tasks.push(handle);
+    }
+
+    /// Waits at most `timeout` for the exporters of finished components to
+    /// send their data, `false` if some did not make it.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let tasks = mem::take(&mut *self.0.lock().unwrap());
+        let all = async {
+            for task in tasks {
+                let _ = task.await;
+            }
+        };
+        tokio::time::timeout(timeout, all).await.is_ok()
+    }
+}
+
+/// Where an exporter of a component in a child process sends its data.
+pub type Relay = Box<dyn Fn(Data, Option<FileMeta>) + Send + Sync>;
+
 impl DataExporter {
+    /// The exporter runs as a task of `rt`, shared with the rest of the
+    /// daemon.
     pub fn new(
+        rt: &Handle,
+        tasks: &ExportTasks,
         bytes_capacity: usize,
         bytes_watermark: usize,
         task_id: String,
@@ -74,38 +154,59 @@ impl DataExporter {
         // TODO: `bytes_capacity` is not used because we not have a static allocation
         // for `Data`, maybe we will remove this in the future
         let _ = bytes_capacity;
-        let data_queue = Arc::new(SegQueue::<Option<Data>>::new());
+        let data_queue = Arc::new(SegQueue::<Option<(Data, Option<FileMeta>)>>::new());
         let bytes_len = Arc::new(AtomicUsize::new(0));
+        let notify = Arc::new(Notify::new());
 
-        let exporter = thread::spawn({
+        let handle = rt.spawn({
             let data_queue = Arc::clone(&data_queue);
             let bytes_len = Arc::clone(&bytes_len);
+            let notify = Arc::clone(&notify);
             let task_id = task_id.clone();
-            move || {
-                let rt = Runtime::new().expect("Failed to init exporter runtime");
+            async move {
+                let stats = export_stats::global(Backend::Rpc);
+                let export = |data: Vec<Data>, file: Option<FileMeta>| {
+                    let merged = ExportDataReq {
+                        task_id: task_id.clone(),
+                        data,
+                    };
+                    let mut rpc_client = rpc_client.clone();
+                    async move {
+                        if let Err(e) = rpc_client
+                            .export_data_accounted(merged, file.as_ref(), stats)
+                            .await
+                        {
+                            tracing::warn!("Failed to export data: {e}");
+                        }
+                    }
+                };
                 let mut data = Vec::new();
                 loop {
                     match data_queue.pop() {
-                        Some(Some(o)) => {
+                        Some(Some((o, file))) => {
+                            let encoded_len = o.encoded_len();
                             // No critical section, relaxed ordering is fine.
-                            bytes_len.fetch_sub(o.encoded_len(), Ordering::Relaxed);
-                            data.push(o);
+                            bytes_len.fetch_sub(encoded_len, Ordering::Relaxed);
+                            stats.dequeue(1, encoded_len as u64);
+                            match file {
+                                None => data.push(o),
+                                // the metadata applies to the whole request, so
+                                // files go alone, after what was queued before
+                                Some(file) => {
+                                    if !data.is_empty() {
+                                        export(mem::take(&mut data), None).await;
+                                    }
+                                    export(vec![o], Some(file)).await;
+                                }
+                            }
                         }
                         poped => {
                             if !data.is_empty() {
-                                let merged = ExportDataReq {
-                                    task_id: task_id.clone(),
-                                    data: data.clone(),
-                                };
-
-                                let mut rpc_client = rpc_client.clone();
-                                rt.block_on(async move {
-                                    let _ = rpc_client.export_data(merged).await;
-                                });
-                                data.clear();
+                                export(mem::take(&mut data), None).await;
                             }
                             match poped {
-                                None => thread::park(),
+                                // a notification sent meanwhile is kept
+                                None => notify.notified().await,
                                 Some(None) => break,
                                 _ => unreachable!(),
                             }
@@ -114,27 +215,54 @@ impl DataExporter {
                 }
             }
         });
+        tasks.push(handle);
 
         Self {
             bytes_len,
+            bytes_total: AtomicU64::new(0),
             bytes_watermark,
             data_queue,
             task_id,
-            exporter,
+            notify,
+            relay: None,
+        }
+    }
+
+    /// Hands every data to `relay` as soon as it is scheduled.
+    pub fn relay(task_id: String, relay: Relay) -> Self {
+        Self {
+            bytes_len: Arc::new(AtomicUsize::new(0)),
+            bytes_total: AtomicU64::new(0),
+            bytes_watermark: 0,
+            data_queue: Arc::new(SegQueue::new()),
+            task_id,
+            notify: Arc::new(Notify::new()),
+            relay: Some(relay),
         }
     }
 
     pub fn flush(&self) {
-        self.exporter.thread().unpark();
+        self.notify.notify_one();
+    }
+
+    pub fn bytes_total(&self) -> u64 {
+        self.bytes_total.load(Ordering::Relaxed)
     }
 
-    pub fn schedule(&self, data: Data) {
+    pub fn schedule(&self, data: Data, file: Option<FileMeta>) {
         let encoded_len = data.encoded_len();
-        self.data_queue.push(Some(data));
+        self.bytes_total
+            .fetch_add(data.bytes.len() as u64, Ordering::Relaxed);
+        if let Some(relay) = &self.relay {
+            relay(data, file);
+            return;
+        }
+        export_stats::global(Backend::Rpc).enqueue(1, encoded_len as u64);
+        self.data_queue.push(Some((data, file)));
         // No critical section, relaxed ordering is fine.
         let prev = self.bytes_len.fetch_add(encoded_len, Ordering::Relaxed);
         if prev > self.bytes_watermark {
-            self.exporter.thread().unpark();
+            self.notify.notify_one();
         }
     }
 }
@@ -143,7 +271,7 @@ impl Drop for DataExporter {
     fn drop(&mut self) {
         // Notify the consumer that there is no more data.
         self.data_queue.push(None);
-        self.exporter.thread().unpark();
+        self.notify.notify_one();
     }
 }
 
@@ -151,24 +279,210 @@ impl Drop for DataExporter {
 pub struct Ctx {
     pub instance_id: String,
     pub exporter: Arc<DataExporter>,
+    pub tenant: Option<Arc<Tenant>>,
+    /// rename tag keys to their semantic convention names
+    pub normalize: bool,
+    pub schemas: Schemas,
+    pub timestamper: Arc<Timestamper>,
+    /// `[engine.export_rate]` or the tenant's, shared by the runs of the
+    /// component
+    pub rate: Option<Arc<ExportRate>>,
+}
+
+impl Ctx {
+    fn tag_key<'a>(&self, key: &'a str) -> &'a str {
+        if self.normalize {
+            semconv::normalize_key(key)
+        } else {
+            key
+        }
+    }
+
+    fn tags(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        [
+            ("task_id".to_string(), self.exporter.task_id.clone()),
+            ("instance_id".to_string(), self.instance_id.clone()),
+        ]
+        .into_iter()
+        .chain(self.tenant.iter().flat_map(|it| it.labels.iter().cloned()))
+        .chain(
+            kubernetes::global()
+                .into_iter()
+                .flat_map(|it| it.identity.tags()),
+        )
+    }
+
+    fn schedule(&self, data: Data) -> Result<(), String> {
+        self.schedule_file(data, None)
+    }
+
+    pub fn schedule_file(&self, data: Data, file: Option<FileMeta>) -> Result<(), String> {
+        export_stats::global(Backend::Rpc).accept(1);
+        if let Some(rate) = &self.rate {
+            let samples = u64::from(data.ty == DataType::LineProtocol as i32);
+            if let Err(throttled) = rate.try_consume(samples, data.bytes.len() as u64) {
+                export_stats::global(Backend::Rpc).dropped(1);
+                return Err(throttled.to_string());
+            }
+        }
+        if let Some(tenant) = &self.tenant {
+            let within_quota = tenant
+                .quota
+                .as_ref()
+                .is_none_or(|quota| quota.try_consume(data.bytes.len()));
+            if !within_quota {
+                export_stats::global(Backend::Rpc).dropped(1);
+                return Err(format!("Export quota of tenant {} exceeded", tenant.name));
+            }
+        }
+        self.exporter.schedule(data, file);
+        Ok(())
+    }
+}
+
+/// Applies `[redact]` to tags set by a component.
+fn redact_tags(tags: &mut [(String, String)]) {
+    let Some(redactor) = redact::global() else {
+        return;
+    };
+    for (k, v) in tags {
+        if let Cow::Owned(redacted) = redactor.redact_value(k, v) {
+            *v = redacted;
+        }
+    }
+}
+
+// history keeps numbers only
+fn numeric(value: &WitFieldValue) -> Option<f64> {
+    match value {
+        WitFieldValue::Float(x) => Some(*x),
+        WitFieldValue::Int(x) => Some(*x as f64),
+        WitFieldValue::Uint(x) => Some(*x as f64),
+        WitFieldValue::Boolean(b) => Some(f64::from(u8::from(*b))),
+        WitFieldValue::NsTs(x) => Some(*x as f64),
+        WitFieldValue::Text(_) => None,
+    }
+}
+
+fn value(value: &WitFieldValue) -> Value {
+    match value {
+        WitFieldValue::Float(x) => Value::Float(*x),
+        WitFieldValue::Int(x) => Value::Int(*x),
+        WitFieldValue::Uint(x) | WitFieldValue::NsTs(x) => Value::Uint(*x),
+        WitFieldValue::Boolean(b) => Value::Boolean(*b),
+        WitFieldValue::Text(s) => Value::Text(s.clone()),
+    }
+}
+
+fn redact_field(name: &str, value: &mut WitFieldValue) {
+    let (Some(redactor), WitFieldValue::Text(text)) = (redact::global(), &mut *value) else {
+        return;
+    };
+    if let Cow::Owned(redacted) = redactor.redact_value(name, text) {
+        *text = redacted;
+    }
 }
 
 #[derive(Clone)]
 pub struct DataExportCtx {
     pub ctx: Option<Ctx>,
+    /// keeps the last exported file for components running after this one
+    pub artifact: Option<Arc<Mutex<Option<Artifact>>>>,
+    /// exports are kept in the history of this tenant
+    pub tenant: Option<String>,
 }
 
+impl DataExportCtx {
+    /// Keeps the numeric fields of an accepted export in `[history]` if it is
+    /// enabled, whether or not the exports reach the server.
+    fn remember<'a>(
+        &self,
+        name: &str,
+        tags: &[(String, String)],
+        fields: impl IntoIterator<Item = (&'a str, &'a WitFieldValue)>,
+        ns_ts: Option<u64>,
+    ) {
+        let Some(history) = history::global() else {
+            return;
+        };
+        history.record(Entry {
+            tenant: self.tenant.clone(),
+            name: name.to_string(),
+            tags: tags.to_vec(),
+            fields: fields
+                .into_iter()
+                .filter_map(|(k, v)| Some((k.to_string(), numeric(v)?)))
+                .collect(),
+            ns_ts: ns_ts
+                .unwrap_or_else(|| Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64),
+        });
+    }
+
+    /// Hands a point to the registered exporters once it passed every check
+    /// of the server path, whether or not the exports reach the server.
+    fn route_point<'a>(
+        &self,
+        name: &str,
+        tags: &[(String, String)],
+        fields: impl IntoIterator<Item = (&'a str, &'a WitFieldValue)>,
+        ns_ts: Option<u64>,
+    ) {
+        if !exporter::enabled() {
+            return;
+        }
+        exporter::route(&Record::Point {
+            name: name.to_string(),
+            tags: tags.to_vec(),
+            fields: fields
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), value(v)))
+                .collect(),
+            ns_ts,
+            tenant: self.tenant.clone(),
+        });
+    }
+
+    fn route_file(
+        &self,
+        name: &str,
+        content_type: &str,
+        attributes: &[(String, String)],
+        bytes: &[u8],
+    ) {
+        if !exporter::wants_files() {
+            return;
+        }
+        exporter::route(&Record::File {
+            name: name.to_string(),
+            content_type: content_type.to_string(),
+            attributes: attributes.to_vec(),
+            bytes: bytes.to_vec(),
+            tenant: self.tenant.clone(),
+        });
+    }
+}
+
+#[async_trait::async_trait]
 impl profiling::data_export::common::Host for DataExportCtx {
-    fn flush_buf(&mut self) -> wasmtime::Result<Result<(), String>> {
+    async fn flush_buf(&mut self) -> wasmtime::Result<Result<(), String>> {
         if let Some(ctx) = &mut self.ctx {
             ctx.exporter.flush();
         }
+        exporter::flush();
         Ok(Ok(()))
     }
 }
 
+#[async_trait::async_trait]
 impl profiling::data_export::file::Host for DataExportCtx {
-    fn export_bytes(&mut self, bytes: Vec<u8>) -> wasmtime::Result<Result<(), String>> {
+    async fn export_bytes(&mut self, bytes: Vec<u8>) -> wasmtime::Result<Result<(), String>> {
+        if let Some(artifact) = &self.artifact {
+            *artifact.lock().unwrap() = Some(Artifact {
+                name: "artifact".to_string(),
+                bytes: bytes.clone(),
+            });
+        }
+        self.route_file("artifact", "application/octet-stream", &[], &bytes);
         let Some(ctx) = &mut self.ctx else {
             return Ok(Ok(()));
         };
@@ -177,26 +491,58 @@ impl profiling::data_export::file::Host for DataExportCtx {
             ty: DataType::File as _,
             bytes,
         };
-        ctx.exporter.schedule(data);
-
-        Ok(Ok(()))
+        Ok(ctx.schedule(data))
     }
 }
 
+#[async_trait::async_trait]
 impl profiling::data_export::metric::Host for DataExportCtx {
-    fn export_sample(&mut self, mut sample: Sample) -> wasmtime::Result<Result<(), String>> {
+    async fn export_sample(&mut self, mut sample: Sample) -> wasmtime::Result<Result<(), String>> {
+        redact_tags(&mut sample.tags);
+        redact_field("value", &mut sample.value);
+        if let Some(schema) = self
+            .ctx
+            .as_ref()
+            .and_then(|it| it.schemas.get(&sample.name))
+        {
+            let tags = sample.tags.iter().map(|(k, _)| k.as_str());
+            if let Err(e) = schema.validate(tags, [("value", &sample.value)]) {
+                return Ok(Err(format!("Sample {}: {e}", sample.name)));
+            }
+        }
+        let ns_ts = match &self.ctx {
+            Some(ctx) => match ctx.timestamper.assign(sample.ns_ts) {
+                Ok(ns_ts) => ns_ts,
+                Err(e) => return Ok(Err(format!("Sample {}: {e}", sample.name))),
+            },
+            None => sample.ns_ts,
+        };
+        self.remember(
+            &sample.name,
+            &sample.tags,
+            [("value", &sample.value)],
+            ns_ts,
+        );
+        self.route_point(
+            &sample.name,
+            &sample.tags,
+            [("value", &sample.value)],
+            ns_ts,
+        );
         let Some(ctx) = &mut self.ctx else {
             return Ok(Ok(()));
         };
 
         let tags = &mut sample.tags;
-        tags.push(("task_id".to_string(), ctx.exporter.task_id.clone()));
-        tags.push(("instance_id".to_string(), ctx.instance_id.clone()));
+        tags.extend(ctx.tags());
 
         let lp = LineProtocolBuilder::new().measurement(&sample.name);
-        let lp = sample.tags.iter().fold(lp, |lp, (k, v)| lp.tag(k, v));
+        let lp = sample
+            .tags
+            .iter()
+            .fold(lp, |lp, (k, v)| lp.tag(ctx.tag_key(k), v));
         let lp = lp.field::<WitFieldValue>("value", sample.value);
-        let bytes = if let Some(ts) = sample.ns_ts {
+        let bytes = if let Some(ts) = ns_ts {
             lp.timestamp(ts as i64).close_line().build()
         } else {
             lp.close_line().build()
@@ -206,34 +552,65 @@ impl profiling::data_export::metric::Host for DataExportCtx {
             ty: DataType::LineProtocol as _,
             bytes,
         };
-        ctx.exporter.schedule(data);
-
-        Ok(Ok(()))
+        Ok(ctx.schedule(data))
     }
 }
 
+#[async_trait::async_trait]
 impl profiling::data_export::measurement::Host for DataExportCtx {
-    fn export_point(&mut self, mut point: Point) -> wasmtime::Result<Result<(), String>> {
+    async fn export_point(&mut self, mut point: Point) -> wasmtime::Result<Result<(), String>> {
+        redact_tags(&mut point.tags);
+        for (k, v) in &mut point.fields {
+            redact_field(k, v);
+        }
+        if point.fields.is_empty() {
+            return Ok(Err("No fields provided in point".to_string()));
+        }
+        if let Some(schema) = self.ctx.as_ref().and_then(|it| it.schemas.get(&point.name)) {
+            let tags = point.tags.iter().map(|(k, _)| k.as_str());
+            let fields = point.fields.iter().map(|(k, v)| (k.as_str(), v));
+            if let Err(e) = schema.validate(tags, fields) {
+                return Ok(Err(format!("Point {}: {e}", point.name)));
+            }
+        }
+        let ns_ts = match &self.ctx {
+            Some(ctx) => match ctx.timestamper.assign(point.ns_ts) {
+                Ok(ns_ts) => ns_ts,
+                Err(e) => return Ok(Err(format!("Point {}: {e}", point.name))),
+            },
+            None => point.ns_ts,
+        };
+        self.remember(
+            &point.name,
+            &point.tags,
+            point.fields.iter().map(|(k, v)| (k.as_str(), v)),
+            ns_ts,
+        );
+        self.route_point(
+            &point.name,
+            &point.tags,
+            point.fields.iter().map(|(k, v)| (k.as_str(), v)),
+            ns_ts,
+        );
         let Some(ctx) = &mut self.ctx else {
             return Ok(Ok(()));
         };
 
         let tags = &mut point.tags;
-        tags.push(("task_id".to_string(), ctx.exporter.task_id.clone()));
-        tags.push(("instance_id".to_string(), ctx.instance_id.clone()));
+        tags.extend(ctx.tags());
 
         let lp = LineProtocolBuilder::new().measurement(&point.name);
-        let lp = point.tags.iter().fold(lp, |lp, (k, v)| lp.tag(k, v));
+        let lp = point
+            .tags
+            .iter()
+            .fold(lp, |lp, (k, v)| lp.tag(ctx.tag_key(k), v));
 
         let mut fields = point.fields.into_iter();
-
-        let Some((first_key, first_val)) = fields.next() else {
-            return Ok(Err("No fields provided in point".to_string()));
-        };
+        let (first_key, first_val) = fields.next().expect("checked above");
 
         let lp = lp.field::<WitFieldValue>(&first_key, first_val);
         let lp = fields.fold(lp, |lp, (k, v)| lp.field::<WitFieldValue>(&k, v));
-        let bytes = if let Some(ts) = point.ns_ts {
+        let bytes = if let Some(ts) = ns_ts {
             lp.timestamp(ts as i64).close_line().build()
         } else {
             lp.close_line().build()
@@ -243,15 +620,228 @@ impl profiling::data_export::measurement::Host for DataExportCtx {
             ty: DataType::LineProtocol as _,
             bytes,
         };
-        ctx.exporter.schedule(data);
+        Ok(ctx.schedule(data))
+    }
+}
 
-        Ok(Ok(()))
+impl DataExportCtx {
+    /// Exports `bytes` as a file with its metadata, the task tags are added
+    /// to `attributes`.
+    pub fn export_file(
+        &mut self,
+        name: String,
+        content_type: String,
+        attributes: Vec<(String, String)>,
+        bytes: Vec<u8>,
+    ) -> Result<(), String> {
+        if name.is_empty() {
+            return Err("No file name provided".to_string());
+        }
+        // sent as is in the request metadata
+        if !content_type.is_ascii() || !content_type.contains('/') {
+            return Err(format!("Invalid content type {content_type}"));
+        }
+        if let Some(artifact) = &self.artifact {
+            *artifact.lock().unwrap() = Some(Artifact {
+                name: name.clone(),
+                bytes: bytes.clone(),
+            });
+        }
+        let mut attributes = attributes;
+        redact_tags(&mut attributes);
+        self.route_file(&name, &content_type, &attributes, &bytes);
+        let Some(ctx) = &mut self.ctx else {
+            return Ok(());
+        };
+
+        let attributes = attributes
+            .into_iter()
+            .chain(ctx.tags())
+            .map(|(k, v)| (ctx.tag_key(&k).to_string(), v))
+            .collect();
+        let file = FileMeta {
+            name,
+            content_type,
+            attributes,
+        };
+        let data = Data {
+            ty: DataType::File as _,
+            bytes,
+        };
+        ctx.schedule_file(data, Some(file))
+    }
+}
+
+#[async_trait::async_trait]
+impl ext::profiling::data_export_ext::file::Host for DataExportCtx {
+    async fn export_file(
+        &mut self,
+        name: String,
+        content_type: String,
+        attributes: Vec<(String, String)>,
+        bytes: Vec<u8>,
+    ) -> wasmtime::Result<Result<(), String>> {
+        Ok(DataExportCtx::export_file(
+            self,
+            name,
+            content_type,
+            attributes,
+            bytes,
+        ))
+    }
+}
+
+#[async_trait::async_trait]
+impl ext::profiling::data_export_ext::schema::Host for DataExportCtx {
+    async fn declare_measurement(
+        &mut self,
+        name: String,
+        fields: Vec<String>,
+        tags: Vec<String>,
+        types: Vec<FieldType>,
+    ) -> wasmtime::Result<Result<(), String>> {
+        let Some(ctx) = &mut self.ctx else {
+            return Ok(Ok(()));
+        };
+
+        let schema = match Schema::new(fields, tags, types) {
+            Ok(schema) => schema,
+            Err(e) => return Ok(Err(format!("Measurement {name}: {e}"))),
+        };
+        let fields_desc = schema.fields_desc();
+        let tags_desc = schema.tags_desc();
+        match ctx.schemas.declare(name.clone(), schema) {
+            Ok(true) => {}
+            Ok(false) => return Ok(Ok(())),
+            Err(e) => return Ok(Err(e)),
+        }
+
+        // the server learns the schema from a point of its own, before any
+        // point of the measurement
+        let lp = LineProtocolBuilder::new().measurement(SCHEMA_MEASUREMENT);
+        let lp = ctx
+            .tags()
+            .chain([("measurement".to_string(), name)])
+            .fold(lp, |lp, (k, v)| lp.tag(ctx.tag_key(&k), &v));
+        let bytes = lp
+            .field::<WitFieldValue>("fields", WitFieldValue::Text(fields_desc))
+            .field::<WitFieldValue>("tags", WitFieldValue::Text(tags_desc))
+            .close_line()
+            .build();
+
+        let data = Data {
+            ty: DataType::LineProtocol as _,
+            bytes,
+        };
+        Ok(ctx.schedule(data))
+    }
+}
+
+impl DataExportCtx {
+    /// Encodes `trace` for trace viewers and exports it as a file.
+    fn export_trace(
+        &mut self,
+        name: String,
+        format: TraceFormat,
+        trace: &Trace,
+    ) -> Result<(), String> {
+        let format = match format {
+            TraceFormat::ChromeJson => trace::Format::ChromeJson,
+            TraceFormat::Perfetto => trace::Format::Perfetto,
+        };
+        let attributes = vec![("trace_format".to_string(), format.name().to_string())];
+        let bytes = trace.encode(format);
+        self.export_file(name, format.content_type().to_string(), attributes, bytes)
+    }
+}
+
+#[async_trait::async_trait]
+impl ext::profiling::data_export_ext::trace::Host for DataExportCtx {
+    async fn export_trace(
+        &mut self,
+        name: String,
+        format: TraceFormat,
+        threads: Vec<TraceThread>,
+        events: Vec<TraceEvent>,
+    ) -> wasmtime::Result<Result<(), String>> {
+        let threads = threads
+            .into_iter()
+            .map(|it| Thread {
+                pid: it.pid,
+                tid: it.tid,
+                name: it.name,
+            })
+            .collect();
+        let events = events
+            .into_iter()
+            .map(|it| match it {
+                TraceEvent::Slice(it) => Event::Slice {
+                    name: it.name,
+                    category: it.category,
+                    pid: it.pid,
+                    tid: it.tid,
+                    start_ns: it.start_ns,
+                    duration_ns: it.duration_ns,
+                    args: it.args,
+                },
+                TraceEvent::Instant(it) => Event::Instant {
+                    name: it.name,
+                    category: it.category,
+                    pid: it.pid,
+                    tid: it.tid,
+                    ns_ts: it.ns_ts,
+                    args: it.args,
+                },
+                TraceEvent::Counter(it) => Event::Counter {
+                    name: it.name,
+                    pid: it.pid,
+                    ns_ts: it.ns_ts,
+                    value: it.value,
+                },
+            })
+            .collect();
+        let trace = Trace { threads, events };
+        Ok(DataExportCtx::export_trace(self, name, format, &trace))
+    }
+
+    async fn export_thread_timeline(
+        &mut self,
+        name: String,
+        format: TraceFormat,
+        pid: u32,
+        timeline: Vec<u8>,
+    ) -> wasmtime::Result<Result<(), String>> {
+        let trace = match Trace::from_thread_timeline(pid, &timeline) {
+            Ok(trace) => trace,
+            Err(e) => return Ok(Err(e.to_string())),
+        };
+        Ok(DataExportCtx::export_trace(self, name, format, &trace))
+    }
+}
+
+#[async_trait::async_trait]
+impl ext::profiling::data_export_ext::quota::Host for DataExportCtx {
+    async fn throttled(&mut self) -> wasmtime::Result<Option<Throttled>> {
+        let last = self
+            .ctx
+            .as_ref()
+            .and_then(|it| it.rate.as_ref())
+            .and_then(|it| it.last_throttled());
+        Ok(last.map(|it| Throttled {
+            limit: match it.limit {
+                Limit::Samples => QuotaLimit::Samples,
+                Limit::Bytes => QuotaLimit::Bytes,
+            },
+            per_sec: it.per_sec,
+            retry_after_ms: it.retry_after.as_millis() as u64,
+        }))
     }
 }
 
-pub fn add_to_linker<T>(
+pub fn add_to_linker<T: Send>(
     l: &mut Linker<T>,
     f: impl (Fn(&mut T) -> &mut DataExportCtx) + Copy + Send + Sync + 'static,
 ) -> anyhow::Result<()> {
-    Imports::add_to_linker(l, f)
+    Imports::add_to_linker(l, f)?;
+    ext::Imports::add_to_linker(l, f)
 }
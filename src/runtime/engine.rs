@@ -12,38 +12,234 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
-use std::{thread, time::Duration};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
-use anyhow::Context;
+use anyhow::{Context, bail};
+use nix::time::{ClockId, clock_gettime};
 use wasmtime::{
-    Engine, Store,
+    Engine, Store, StoreLimitsBuilder, UpdateDeadline,
     component::{Component, Linker},
 };
-use wasmtime_wasi::bindings::sync::Command;
+use wasmtime_wasi::bindings::CommandPre;
+
+use super::{
+    CancelToken, Language, MemoryLimits, PshState, cache, host::scheduling::SchedulingCtx, isolate,
+    policy::InterfacePolicy, pool::SharedEngine,
+};
+
+/// Interval of epoch increments, every tick is a chance for the running
+/// component to be interrupted or yield the host thread.
+pub(super) const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// The component was interrupted for using more CPU time than allowed.
+#[derive(Debug)]
+pub struct CpuTimeExceeded {
+    pub limit_ms: u64,
+}
+
+impl fmt::Display for CpuTimeExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CPU time limit of {} ms exceeded", self.limit_ms)
+    }
+}
+
+impl std::error::Error for CpuTimeExceeded {}
+
+// the component runs on the calling thread, host calls included
+fn thread_cpu_time() -> Duration {
+    clock_gettime(ClockId::CLOCK_THREAD_CPUTIME_ID).map_or(Duration::ZERO, Duration::from)
+}
 
-use super::PshState;
+/// Outcome of [`PshEngine::run_metered`].
+pub struct RunReport {
+    pub result: anyhow::Result<()>,
+    /// `None` unless the engine was built to consume fuel
+    pub fuel_used: Option<u64>,
+    pub usage: Usage,
+}
+
+/// What a run took of the host besides time and fuel.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// in bytes, linear memory the component grew to
+    pub peak_memory: u64,
+    /// calls by rate limited host interface, as named in
+    /// `[engine.rate_limits]`
+    pub host_calls: BTreeMap<String, u64>,
+}
 
+/// Runs a component as a future on the thread driving it, WASI and data
+/// export calls are async and run on the tokio runtime of the daemon, other
+/// host calls block that thread.
+///
+/// Task threads drive it with the shared runtime's handle rather than
+/// spawning it, a component must not hold up a worker of the runtime.
 pub struct PshEngine {
     pub engine: Engine,
     pub store: Store<PshState>,
     pub linker: Linker<PshState>,
+    pub memory_limits: MemoryLimits,
+    /// replaces `memory_limits` if set, 0 for no limit
+    pub memory_limit: Option<usize>,
+    /// the component is interrupted once set
+    pub stop: Option<Arc<AtomicBool>>,
+    /// the component is asked to wrap up once cancelled and interrupted
+    /// `cancel_grace` later
+    pub cancel: CancelToken,
+    pub cancel_grace: Duration,
+    /// interfaces the component may import, every one if `None`
+    pub policy: Option<&'static InterfacePolicy>,
+    /// runs in a child process of `[engine] isolate`
+    pub isolated: bool,
+    /// max CPU time of a run in milliseconds, 0 for no limit
+    pub max_cpu_ms: u64,
+    /// engine and linker are shared with other components if set
+    pub shared: Option<Arc<SharedEngine>>,
+    /// reuses the pre-instantiated component of earlier runs
+    pub warm: bool,
 }
 
 impl PshEngine {
-    pub fn run(mut self, binary: &[u8], time_slice: u64) -> anyhow::Result<()> {
-        let component =
-            Component::from_binary(&self.engine, binary).context("Failed to load component!")?;
-        let cmd = Command::instantiate(&mut self.store, &component, &self.linker)
-            .context("Failed to instantiate Wasi Command!")?;
+    pub async fn run(self, binary: &[u8], time_slice: u64) -> anyhow::Result<()> {
+        self.run_metered(binary, time_slice).await.result
+    }
+
+    pub async fn run_metered(mut self, binary: &[u8], time_slice: u64) -> RunReport {
+        // fails if the engine doesn't consume fuel
+        let metered = self.store.set_fuel(u64::MAX).is_ok();
+        let result = self.run_component(binary, time_slice).await;
+        let fuel_used = metered
+            .then(|| self.store.get_fuel().ok())
+            .flatten()
+            .map(|left| u64::MAX - left);
+        let state = self.store.data();
+        let mut host_calls = BTreeMap::new();
+        for (interface, calls) in state
+            .sys_ctx
+            .host_calls()
+            .chain(state.perf_ctx.host_calls())
+        {
+            *host_calls.entry(interface.to_string()).or_default() += calls;
+        }
+        let usage = Usage {
+            peak_memory: state.limits.memory as u64,
+            host_calls,
+        };
+        RunReport {
+            result,
+            fuel_used,
+            usage,
+        }
+    }
+
+    async fn run_component(&mut self, binary: &[u8], time_slice: u64) -> anyhow::Result<()> {
+        let language = Language::detect(binary);
+        let pre = match &self.shared {
+            Some(shared) if self.warm => shared.warm_instance_pre(binary)?,
+            Some(shared) => shared.instance_pre(binary)?,
+            None => {
+                let component = match cache::global() {
+                    Some(cache) => cache.load(&self.engine, binary),
+                    None => Component::from_binary(&self.engine, binary),
+                }
+                .context("Failed to load component!")?;
+                self.linker
+                    .instantiate_pre(&component)
+                    .context("Failed to pre-instantiate component")?
+            }
+        };
+        if let Some(policy) = self.policy {
+            policy.check(pre.component(), &self.engine)?;
+        }
+        if self.isolated {
+            isolate::check_imports(pre.component(), &self.engine)?;
+        }
+        let limit = match self.memory_limit {
+            Some(0) => None,
+            Some(limit) => Some(limit),
+            None => self.memory_limits.of(language),
+        };
+        if let Some(limit) = limit {
+            tracing::debug!("Limiting {language:?} component to {limit} bytes of memory");
+            self.store.data_mut().limits.limits =
+                StoreLimitsBuilder::new().memory_size(limit).build();
+        }
+        // counts the memory of components without a limit too
+        self.store.limiter(|state| &mut state.limits);
+        let cmd = match CommandPre::new(pre) {
+            Ok(pre) => pre.instantiate_async(&mut self.store).await,
+            Err(e) => Err(e),
+        }
+        .context("Failed to instantiate Wasi Command!")?;
+
+        let deadline = Instant::now() + Duration::from_millis(time_slice);
+        self.store.data_mut().scheduling_ctx = SchedulingCtx::new(deadline);
+        self.store.data_mut().cancel_ctx.deadline = Some(deadline);
+        self.store.data_mut().bus_ctx.cancel.deadline = Some(deadline);
+        self.store.data_mut().probe_ctx.deadline = Some(deadline);
+        let stop = self.stop.clone();
+        let (cancel, cancel_grace) = (self.cancel.clone(), self.cancel_grace);
+        let max_cpu_ms = self.max_cpu_ms;
+        let cpu_start = thread_cpu_time();
+        self.store.epoch_deadline_callback(move |_| {
+            if Instant::now() >= deadline {
+                bail!("Time slice exhausted");
+            }
+            if stop.as_ref().is_some_and(|it| it.load(Ordering::Relaxed)) {
+                bail!("Component stopped");
+            }
+            if cancel
+                .cancelled_at()
+                .is_some_and(|it| it.elapsed() >= cancel_grace)
+            {
+                bail!("Component cancelled");
+            }
+            if max_cpu_ms > 0
+                && thread_cpu_time().saturating_sub(cpu_start) > Duration::from_millis(max_cpu_ms)
+            {
+                return Err(CpuTimeExceeded {
+                    limit_ms: max_cpu_ms,
+                }
+                .into());
+            }
+            thread::yield_now();
+            Ok(UpdateDeadline::Continue(1))
+        });
         self.store.set_epoch_deadline(1);
-        thread::spawn(move || {
-            thread::sleep(Duration::from_millis(time_slice as _));
-            self.engine.increment_epoch();
+
+        // shared engines have a ticker of their own
+        let stopped = Arc::new(AtomicBool::new(false));
+        let ticker = self.shared.is_none().then(|| {
+            thread::spawn({
+                let engine = self.engine.clone();
+                let stopped = Arc::clone(&stopped);
+                move || {
+                    while !stopped.load(Ordering::Relaxed) {
+                        thread::sleep(EPOCH_TICK);
+                        engine.increment_epoch();
+                    }
+                }
+            })
         });
-        let _ = cmd
-            .wasi_cli_run()
-            .call_run(&mut self.store)
-            .context("Failed to run component")?;
+
+        let result = cmd.wasi_cli_run().call_run(&mut self.store).await;
+        stopped.store(true, Ordering::Relaxed);
+        if let Some(ticker) = ticker {
+            let _ = ticker.join();
+        }
+
+        if result.context("Failed to run component")?.is_err() {
+            bail!("Component exited with an error");
+        }
         Ok(())
     }
 }
@@ -0,0 +1,377 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    mem,
+    sync::{Arc, Mutex, RwLock},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use crossbeam::channel::{self, Receiver, RecvTimeoutError, Sender, TrySendError};
+use serde::Serialize;
+use serde_json::{Map, json};
+
+use crate::{
+    at_rest::{self, Sealer},
+    config::ExportersConfig,
+};
+
+static ROUTES: RwLock<Vec<Route>> = RwLock::new(Vec::new());
+
+/// A field value of an exported point.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+    Float(f64),
+    Int(i64),
+    Uint(u64),
+    Boolean(bool),
+    Text(String),
+}
+
+/// What a component exported, redacted but not yet encoded for the server.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Record {
+    /// a sample is a point with a single `value` field
+    Point {
+        name: String,
+        tags: Vec<(String, String)>,
+        fields: Vec<(String, Value)>,
+        ns_ts: Option<u64>,
+        tenant: Option<String>,
+    },
+    File {
+        name: String,
+        content_type: String,
+        attributes: Vec<(String, String)>,
+        bytes: Vec<u8>,
+        tenant: Option<String>,
+    },
+}
+
+/// A wire format and destination exports go to besides the server, those
+/// built in are registered by [`init`] as configured in `[exporters]`.
+///
+/// Records are serialized on the thread of the exporting component, then
+/// sent in batches from a thread of the exporter, which flushes whenever a
+/// component flushes its buffer or the flush interval has passed.
+trait Exporter: Send + Sync + 'static {
+    fn name(&self) -> &str;
+
+    /// Whether files are exported too, they aren't copied for the exporter
+    /// if not.
+    fn files(&self) -> bool {
+        true
+    }
+
+    /// Encodes `record`, `None` skips it.
+    fn serialize(&self, record: &Record) -> Result<Option<Vec<u8>>>;
+
+    fn send(&self, batch: Vec<Vec<u8>>) -> Result<()>;
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+enum Message {
+    Record(Vec<u8>),
+    Flush,
+}
+
+struct Route {
+    exporter: Arc<dyn Exporter>,
+    tx: Sender<Message>,
+}
+
+/// Registers the exporters enabled in `cfg`.
+pub fn init(cfg: &ExportersConfig) -> Result<()> {
+    if let Some(path) = &cfg.jsonl_path {
+        register(Arc::new(JsonLines::create(path, at_rest::global())?), cfg)?;
+    }
+    Ok(())
+}
+
+/// Sends every record exported from now on to `exporter` too.
+fn register(exporter: Arc<dyn Exporter>, cfg: &ExportersConfig) -> Result<()> {
+    let (tx, rx) = channel::bounded(cfg.queue_size.max(1));
+    let max_batch = cfg.max_batch.max(1);
+    let interval = Duration::from_millis(cfg.flush_interval_ms.max(1));
+    thread::Builder::new()
+        .name(format!("psh-export-{}", exporter.name()))
+        .spawn({
+            let exporter = Arc::clone(&exporter);
+            move || run(exporter.as_ref(), &rx, max_batch, interval)
+        })
+        .with_context(|| format!("Failed to start exporter {}", exporter.name()))?;
+    ROUTES.write().unwrap().push(Route { exporter, tx });
+    Ok(())
+}
+
+/// Whether any exporter is registered, records need not be built if not.
+pub fn enabled() -> bool {
+    !ROUTES.read().unwrap().is_empty()
+}
+
+/// Whether any exporter takes files, file records need not be built if not.
+pub fn wants_files() -> bool {
+    ROUTES.read().unwrap().iter().any(|it| it.exporter.files())
+}
+
+/// Queues `record` for every exporter, it is dropped for exporters lagging
+/// behind.
+pub fn route(record: &Record) {
+    for route in ROUTES.read().unwrap().iter() {
+        let name = route.exporter.name();
+        let bytes = match route.exporter.serialize(record) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!("Exporter {name} failed to serialize a record: {e:#}");
+                continue;
+            }
+        };
+        if let Err(TrySendError::Full(_)) = route.tx.try_send(Message::Record(bytes)) {
+            tracing::warn!("Exporter {name} lagging, record dropped");
+        }
+    }
+}
+
+/// Sends what the exporters have queued and flushes them.
+pub fn flush() {
+    for route in ROUTES.read().unwrap().iter() {
+        let _ = route.tx.try_send(Message::Flush);
+    }
+}
+
+fn run(exporter: &dyn Exporter, rx: &Receiver<Message>, max_batch: usize, interval: Duration) {
+    let name = exporter.name();
+    let mut batch = Vec::new();
+    loop {
+        let (flush, closed) = match rx.recv_timeout(interval) {
+            Ok(Message::Record(bytes)) => {
+                batch.push(bytes);
+                if batch.len() < max_batch {
+                    continue;
+                }
+                (false, false)
+            }
+            Ok(Message::Flush) | Err(RecvTimeoutError::Timeout) => (true, false),
+            Err(RecvTimeoutError::Disconnected) => (true, true),
+        };
+        if !batch.is_empty() {
+            let len = batch.len();
+            if let Err(e) = exporter.send(mem::take(&mut batch)) {
+                tracing::warn!("Exporter {name} failed to send {len} records: {e:#}");
+            }
+        }
+        if flush {
+            if let Err(e) = exporter.flush() {
+                tracing::warn!("Exporter {name} failed to flush: {e:#}");
+            }
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+/// Writes points as JSON objects, one per line, files are skipped.
+pub struct JsonLines {
+    path: String,
+    writer: Mutex<BufWriter<File>>,
+    // encrypts every line
+    sealer: Option<&'static Sealer>,
+}
+
+impl JsonLines {
+    /// Lines are encrypted with `sealer` if given.
+    pub fn create(path: &str, sealer: Option<&'static Sealer>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {path}"))?;
+        Ok(Self {
+            path: path.to_string(),
+            writer: Mutex::new(BufWriter::new(file)),
+            sealer,
+        })
+    }
+}
+
+impl Exporter for JsonLines {
+    fn name(&self) -> &str {
+        "jsonl"
+    }
+
+    fn files(&self) -> bool {
+        false
+    }
+
+    fn serialize(&self, record: &Record) -> Result<Option<Vec<u8>>> {
+        let Record::Point {
+            name,
+            tags,
+            fields,
+            ns_ts,
+            tenant,
+        } = record
+        else {
+            return Ok(None);
+        };
+        let line = json!({
+            "name": name,
+            "tags": tags.iter().map(|(k, v)| (k.clone(), json!(v))).collect::<Map<_, _>>(),
+            "fields": fields.iter().map(|(k, v)| (k.clone(), json!(v))).collect::<Map<_, _>>(),
+            "ns_ts": ns_ts,
+            "tenant": tenant,
+        });
+        let mut bytes = serde_json::to_vec(&line)?;
+        if let Some(sealer) = self.sealer {
+            bytes = sealer.seal_line(&bytes)?.into_bytes();
+        }
+        bytes.push(b'\n');
+        Ok(Some(bytes))
+    }
+
+    fn send(&self, batch: Vec<Vec<u8>>) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        for line in batch {
+            writer
+                .write_all(&line)
+                .with_context(|| format!("Failed to write {}", self.path))?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.writer
+            .lock()
+            .unwrap()
+            .flush()
+            .with_context(|| format!("Failed to write {}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use anyhow::Result;
+    use crossbeam::channel;
+    use serde_json::json;
+
+    use super::{Exporter, JsonLines, Message, Record, Value, run};
+
+    #[derive(Default)]
+    struct Capture {
+        batches: Mutex<Vec<Vec<Vec<u8>>>>,
+        flushes: Mutex<usize>,
+    }
+
+    impl Exporter for Capture {
+        fn name(&self) -> &str {
+            "capture"
+        }
+
+        fn serialize(&self, _: &Record) -> Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+
+        fn send(&self, batch: Vec<Vec<u8>>) -> Result<()> {
+            self.batches.lock().unwrap().push(batch);
+            Ok(())
+        }
+
+        fn flush(&self) -> Result<()> {
+            *self.flushes.lock().unwrap() += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_run_batches() {
+        let exporter = Arc::new(Capture::default());
+        let (tx, rx) = channel::unbounded();
+        for i in 0..3 {
+            tx.send(Message::Record(vec![i])).unwrap();
+        }
+        tx.send(Message::Flush).unwrap();
+        tx.send(Message::Record(vec![3])).unwrap();
+        drop(tx);
+        let handle = thread::spawn({
+            let exporter = Arc::clone(&exporter);
+            move || run(exporter.as_ref(), &rx, 2, Duration::from_secs(60))
+        });
+        handle.join().unwrap();
+
+        let batches = exporter.batches.lock().unwrap();
+        assert_eq!(
+            *batches,
+            [vec![vec![0], vec![1]], vec![vec![2]], vec![vec![3]]]
+        );
+        // by the flush and once closed
+        assert_eq!(*exporter.flushes.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let path = std::env::temp_dir().join(format!("psh-exports-{}.jsonl", std::process::id()));
+        let exporter = JsonLines::create(path.to_str().unwrap(), None).unwrap();
+        let point = Record::Point {
+            name: "cpu".to_string(),
+            tags: vec![("host".to_string(), "a".to_string())],
+            fields: vec![
+                ("usage".to_string(), Value::Float(0.5)),
+                ("cores".to_string(), Value::Uint(8)),
+            ],
+            ns_ts: Some(1),
+            tenant: None,
+        };
+        let line = exporter.serialize(&point).unwrap().unwrap();
+        assert_eq!(line.last(), Some(&b'\n'));
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&line).unwrap(),
+            json!({
+                "name": "cpu",
+                "tags": { "host": "a" },
+                "fields": { "usage": 0.5, "cores": 8 },
+                "ns_ts": 1,
+                "tenant": null,
+            })
+        );
+        let file = Record::File {
+            name: "profile.pb".to_string(),
+            content_type: "application/octet-stream".to_string(),
+            attributes: vec![],
+            bytes: vec![],
+            tenant: None,
+        };
+        assert!(exporter.serialize(&file).unwrap().is_none());
+
+        exporter.send(vec![line.clone()]).unwrap();
+        exporter.flush().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), line);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{fmt, time::Duration};
+
+use influxdb_line_protocol::LineProtocolBuilder;
+use psh_proto::{Data, DataType, ExportDataReq};
+use wasmtime::{Trap, WasmBacktrace};
+
+use crate::ledger::{RunStatus, exit_code};
+
+/// Frames kept of a backtrace, the innermost ones.
+const MAX_FRAMES: usize = 32;
+
+/// A trap of a run in a child process, see `[engine] isolate`, the wasmtime
+/// types of the child don't cross the pipe to the daemon.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IsolatedTrap {
+    /// the whole error of the run
+    pub message: String,
+    pub trap: Option<String>,
+    /// as [`frames`] gives them
+    pub backtrace: Vec<String>,
+}
+
+impl fmt::Display for IsolatedTrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for IsolatedTrap {}
+
+/// Why a component run failed, sent to the server so a failing plugin is
+/// noticed outside of the local logs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FailureReport {
+    pub component: String,
+    pub task_id: Option<String>,
+    pub status: RunStatus,
+    pub exit_code: Option<i32>,
+    /// e.g. `wasm trap: integer divide by zero`
+    pub trap: Option<String>,
+    pub message: String,
+    /// innermost frame first, e.g. `my_component.wasm!parse_line at
+    /// src/lib.rs:12:9` if the component has debug info
+    pub backtrace: Vec<String>,
+    pub duration: Duration,
+}
+
+impl FailureReport {
+    /// `None` if the run succeeded.
+    pub fn of(
+        component: &str,
+        task_id: Option<&str>,
+        result: &anyhow::Result<()>,
+        duration: Duration,
+    ) -> Option<Self> {
+        let status = RunStatus::of(result);
+        if status == RunStatus::Success {
+            return None;
+        }
+        let (message, trap, backtrace) = match result {
+            Ok(()) => (String::new(), None, vec![]),
+            Err(e) => match e.downcast_ref::<IsolatedTrap>() {
+                Some(isolated) => (
+                    format!("{e:#}"),
+                    isolated.trap.clone(),
+                    isolated.backtrace.clone(),
+                ),
+                None => (
+                    format!("{e:#}"),
+                    e.downcast_ref::<Trap>().map(ToString::to_string),
+                    e.downcast_ref::<WasmBacktrace>()
+                        .map(frames)
+                        .unwrap_or_default(),
+                ),
+            },
+        };
+        Some(Self {
+            component: component.to_string(),
+            task_id: task_id.map(str::to_string),
+            status,
+            exit_code: exit_code(result),
+            trap,
+            message,
+            backtrace,
+            duration,
+        })
+    }
+
+    pub fn to_line_protocol(&self, instance_id: &str) -> Vec<u8> {
+        let lp = LineProtocolBuilder::new()
+            .measurement("psh_component_failure")
+            .tag("instance_id", instance_id)
+            .tag("component", &self.component)
+            .tag("status", self.status.as_str());
+        let lp = match &self.task_id {
+            Some(task_id) => lp.tag("task_id", task_id),
+            None => lp,
+        };
+        let lp = lp
+            .field("message", self.message.as_str())
+            .field("backtrace", self.backtrace.join("\n").as_str())
+            .field("duration_ms", self.duration.as_millis() as u64);
+        let lp = match &self.trap {
+            Some(trap) => lp.field("trap", trap.as_str()),
+            None => lp,
+        };
+        let lp = match self.exit_code {
+            Some(code) => lp.field("exit_code", i64::from(code)),
+            None => lp,
+        };
+        lp.timestamp(chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default())
+            .close_line()
+            .build()
+    }
+
+    pub fn to_export_req(&self, instance_id: &str) -> ExportDataReq {
+        ExportDataReq {
+            task_id: self.task_id.clone().unwrap_or_default(),
+            data: vec![Data {
+                ty: DataType::LineProtocol as _,
+                bytes: self.to_line_protocol(instance_id),
+            }],
+        }
+    }
+}
+
+/// Innermost frame first, at most [`MAX_FRAMES`] of them.
+pub fn frames(backtrace: &WasmBacktrace) -> Vec<String> {
+    backtrace
+        .frames()
+        .iter()
+        .take(MAX_FRAMES)
+        .map(|frame| {
+            let module = frame.module().name().unwrap_or("<module>");
+            let func = match frame.func_name() {
+                Some(func) => format!("{module}!{func}"),
+                None => format!("{module}!<wasm function {}>", frame.func_index()),
+            };
+            // of the innermost function inlined into the frame, only read
+            // with `backtrace_details`
+            let location = frame
+                .symbols()
+                .iter()
+                .find_map(|it| Some((it.file()?, it.line()?, it.column())));
+            match location {
+                Some((file, line, Some(column))) => format!("{func} at {file}:{line}:{column}"),
+                Some((file, line, None)) => format!("{func} at {file}:{line}"),
+                None => func,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::anyhow;
+
+    use super::FailureReport;
+    use crate::ledger::RunStatus;
+
+    #[test]
+    fn test_failure_report() {
+        let duration = Duration::from_millis(1500);
+        assert!(FailureReport::of("cpu-top", None, &Ok(()), duration).is_none());
+
+        let result = Err(anyhow!("boom").context("Failed to call main"));
+        let report = FailureReport::of("cpu-top", Some("42"), &result, duration).unwrap();
+        assert_eq!(report.status, RunStatus::Failed);
+        assert_eq!(report.message, "Failed to call main: boom");
+        assert!(report.trap.is_none() && report.backtrace.is_empty());
+
+        let lp = String::from_utf8(report.to_line_protocol("host-1")).unwrap();
+        assert!(lp.starts_with(
+            "psh_component_failure,instance_id=host-1,component=cpu-top,status=failed,task_id=42 "
+        ));
+        assert!(lp.contains("duration_ms=1500u"));
+    }
+}
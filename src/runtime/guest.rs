@@ -0,0 +1,234 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+/// Source language of a component, from the `producers` sections of the
+/// modules in it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    /// compiled to wasm, e.g. Rust, C or Go
+    Native,
+    /// componentize-py, CPython is in the component
+    Python,
+    /// jco or ComponentizeJS, StarlingMonkey is in the component
+    JavaScript,
+}
+
+impl Language {
+    pub fn detect(binary: &[u8]) -> Self {
+        let mut producers = vec![];
+        walk(binary, 0, &mut producers);
+        let has = |it: &[&str]| {
+            producers
+                .iter()
+                .any(|p| it.iter().any(|it| p.eq_ignore_ascii_case(it)))
+        };
+        if has(&["Python", "componentize-py"]) {
+            Self::Python
+        } else if has(&["JavaScript", "ComponentizeJS", "StarlingMonkey", "jco"]) {
+            Self::JavaScript
+        } else {
+            Self::Native
+        }
+    }
+
+    /// Guests that carry their interpreter and its heap in the component.
+    pub const fn is_interpreted(self) -> bool {
+        !matches!(self, Self::Native)
+    }
+}
+
+/// Max linear memory of a component in bytes, 0 for no limit.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryLimits {
+    pub native: usize,
+    pub interpreted: usize,
+}
+
+impl MemoryLimits {
+    pub const fn of(&self, language: Language) -> Option<usize> {
+        let limit = if language.is_interpreted() {
+            self.interpreted
+        } else {
+            self.native
+        };
+        if limit == 0 { None } else { Some(limit) }
+    }
+
+    /// The native limit whatever the component claims to be, the producers
+    /// sections are written by whoever built it.
+    pub const fn distrusted(self) -> Self {
+        Self {
+            native: self.native,
+            interpreted: self.native,
+        }
+    }
+}
+
+// modules in components in components, deeper ones are not looked at
+const MAX_DEPTH: usize = 4;
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let (first, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(*first)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= u32::from(byte & 0x7f).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.0.len() {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn name(&mut self) -> Option<&'a str> {
+        let len = self.u32()? as usize;
+        std::str::from_utf8(self.bytes(len)?).ok()
+    }
+}
+
+/// Collects the field values of the `producers` sections of a module or
+/// component, and of the modules and components nested in it.
+fn walk(binary: &[u8], depth: usize, producers: &mut Vec<String>) -> Option<()> {
+    let mut reader = Reader(binary);
+    if reader.bytes(4)? != b"\0asm" {
+        return None;
+    }
+    // version 1 for modules, layer 1 for components
+    let component = reader.bytes(4)?[2] == 1;
+    while !reader.0.is_empty() {
+        let id = reader.byte()?;
+        let len = reader.u32()? as usize;
+        let payload = reader.bytes(len)?;
+        match id {
+            0 => {
+                let mut section = Reader(payload);
+                if section.name()? == "producers" {
+                    read_producers(&mut section, producers);
+                }
+            }
+            // core module and component
+            1 | 4 if component && depth < MAX_DEPTH => {
+                walk(payload, depth + 1, producers);
+            }
+            _ => {}
+        }
+    }
+    Some(())
+}
+
+/// Fields like `language` and `processed-by`, each with names and versions.
+fn read_producers(section: &mut Reader, producers: &mut Vec<String>) -> Option<()> {
+    for _ in 0..section.u32()? {
+        section.name()?;
+        for _ in 0..section.u32()? {
+            producers.push(section.name()?.to_string());
+            section.name()?;
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Language, MemoryLimits};
+
+    fn name(out: &mut Vec<u8>, name: &str) {
+        out.push(name.len() as u8);
+        out.extend_from_slice(name.as_bytes());
+    }
+
+    fn section(out: &mut Vec<u8>, id: u8, payload: &[u8]) {
+        out.push(id);
+        // two byte leb128, to cover continuation bytes
+        out.extend_from_slice(&[
+            (payload.len() as u8 & 0x7f) | 0x80,
+            (payload.len() >> 7) as u8,
+        ]);
+        out.extend_from_slice(payload);
+    }
+
+    fn module(fields: &[(&str, &str)]) -> Vec<u8> {
+        let mut producers = vec![];
+        name(&mut producers, "producers");
+        producers.push(fields.len() as u8);
+        for (field, value) in fields {
+            name(&mut producers, field);
+            producers.push(1);
+            name(&mut producers, value);
+            name(&mut producers, "1.0.0");
+        }
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        // a type section, skipped
+        section(&mut module, 1, &[0]);
+        section(&mut module, 0, &producers);
+        module
+    }
+
+    fn component(modules: &[Vec<u8>]) -> Vec<u8> {
+        let mut component = b"\0asm\x0d\0\x01\0".to_vec();
+        for module in modules {
+            section(&mut component, 1, module);
+        }
+        component
+    }
+
+    #[test]
+    fn test_detect() {
+        let rust = module(&[("language", "Rust"), ("processed-by", "rustc")]);
+        let python = module(&[("language", "C11"), ("processed-by", "componentize-py")]);
+        let js = module(&[("processed-by", "ComponentizeJS")]);
+        assert_eq!(Language::detect(&rust), Language::Native);
+        assert_eq!(
+            Language::detect(&component(&[rust.clone()])),
+            Language::Native
+        );
+        assert_eq!(
+            Language::detect(&component(&[rust.clone(), python])),
+            Language::Python
+        );
+        assert_eq!(
+            Language::detect(&component(&[component(&[js]), rust])),
+            Language::JavaScript
+        );
+        assert_eq!(Language::detect(b"not wasm"), Language::Native);
+        assert_eq!(Language::detect(&component(&[])[..6]), Language::Native);
+    }
+
+    #[test]
+    fn test_memory_limits() {
+        let limits = MemoryLimits {
+            native: 1 << 20,
+            interpreted: 0,
+        };
+        assert_eq!(limits.of(Language::Native), Some(1 << 20));
+        assert_eq!(limits.of(Language::Python), None);
+    }
+}
@@ -0,0 +1,39 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::blackbox;
+
+#[derive(Clone, Debug, Default)]
+pub struct BlackBoxCtx;
+
+impl blackbox::Host for BlackBoxCtx {
+    fn dump(&mut self, reason: String) -> wasmtime::Result<Result<String, String>> {
+        let Some(blackbox) = crate::blackbox::global() else {
+            return Ok(Err("Black box recorder is disabled".to_string()));
+        };
+        Ok(blackbox
+            .dump(&reason)
+            .map(|path| path.to_string_lossy().to_string())
+            .map_err(|err| err.to_string()))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut BlackBoxCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    blackbox::add_to_linker(l, f)
+}
@@ -0,0 +1,96 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::bursts::{self, Burst, CpuSample, Summary, Tick, Window};
+
+const DISABLED: &str = "Micro-burst sampler is disabled, see [bursts]";
+
+#[derive(Clone, Debug, Default)]
+pub struct BurstsCtx;
+
+impl bursts::Host for BurstsCtx {
+    fn window(&mut self, since_ns: i64) -> wasmtime::Result<Result<Window, String>> {
+        let Some(bursts) = crate::bursts::global() else {
+            return Ok(Err(DISABLED.to_string()));
+        };
+        let window = bursts.window(since_ns);
+        Ok(Ok(Window {
+            cpus: window.cpus,
+            interval_ms: bursts.interval_ms(),
+            ticks: window
+                .ticks
+                .into_iter()
+                .map(|it| Tick {
+                    ns_ts: it.ns_ts,
+                    cpus: it
+                        .cpus
+                        .iter()
+                        .map(|it| CpuSample {
+                            util: it.util,
+                            queue: it.queue,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }))
+    }
+
+    fn bursts(&mut self, since_ns: i64) -> wasmtime::Result<Result<Vec<Burst>, String>> {
+        let Some(bursts) = crate::bursts::global() else {
+            return Ok(Err(DISABLED.to_string()));
+        };
+        Ok(Ok(bursts
+            .bursts(since_ns)
+            .into_iter()
+            .map(|it| Burst {
+                cpu: it.cpu,
+                start_ns: it.start_ns,
+                duration_ms: it.duration_ms,
+                peak_util: it.peak_util,
+                peak_queue: it.peak_queue,
+            })
+            .collect()))
+    }
+
+    fn summary(&mut self, since_ns: i64) -> wasmtime::Result<Result<Vec<Summary>, String>> {
+        let Some(bursts) = crate::bursts::global() else {
+            return Ok(Err(DISABLED.to_string()));
+        };
+        Ok(Ok(bursts
+            .summary(since_ns)
+            .into_iter()
+            .map(|it| Summary {
+                cpu: it.cpu,
+                samples: it.samples,
+                mean_util: it.mean_util,
+                p99_util: it.p99_util,
+                max_util: it.max_util,
+                mean_queue: it.mean_queue,
+                max_queue: it.max_queue,
+                bursts: it.bursts,
+                burst_ms: it.burst_ms,
+                longest_burst_ms: it.longest_burst_ms,
+            })
+            .collect()))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut BurstsCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    bursts::add_to_linker(l, f)
+}
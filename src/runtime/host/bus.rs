@@ -0,0 +1,146 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use wasmtime::component::{Linker, Resource, ResourceTable};
+
+use super::{
+    cancellation::CancelCtx,
+    profiling::host::bus::{self, Message},
+};
+use crate::bus::Peer;
+
+// a component waits at most this long for a message per call
+const MAX_WAIT: Duration = Duration::from_secs(60);
+// how often a waiting component checks whether it was cancelled
+const CANCEL_POLL: Duration = Duration::from_millis(10);
+
+pub use crate::bus::Subscription;
+
+pub struct BusCtx {
+    tenant: Option<String>,
+    component: Arc<str>,
+    table: ResourceTable,
+    /// ends waits for messages, its deadline is set once the run starts
+    pub cancel: CancelCtx,
+}
+
+impl BusCtx {
+    /// Messages are published as `component`, or as `task:<id>` for tasks
+    /// of the server, which can't pass for a component configured on the
+    /// host that way.
+    pub fn new(
+        tenant: Option<String>,
+        component: Arc<str>,
+        task_id: Option<&str>,
+        cancel: CancelCtx,
+    ) -> Self {
+        let component = match task_id {
+            Some(id) => format!("task:{id}").into(),
+            None => component,
+        };
+        Self {
+            tenant,
+            component,
+            table: ResourceTable::new(),
+            cancel,
+        }
+    }
+
+    fn peer(&self) -> Peer<'_> {
+        Peer {
+            tenant: self.tenant.as_deref(),
+            component: &self.component,
+        }
+    }
+}
+
+impl From<crate::bus::Message> for Message {
+    fn from(value: crate::bus::Message) -> Self {
+        Self {
+            topic: value.topic,
+            publisher: value.publisher.to_string(),
+            payload: value.payload.to_vec(),
+            ns_ts: value.ns_ts,
+        }
+    }
+}
+
+impl bus::HostSubscription for BusCtx {
+    fn next(
+        &mut self,
+        self_: Resource<Subscription>,
+        timeout_ms: u64,
+    ) -> wasmtime::Result<Option<Message>> {
+        let subscription = self.table.get(&self_)?;
+        let until = Instant::now() + Duration::from_millis(timeout_ms).min(MAX_WAIT);
+        loop {
+            let slice = until.saturating_duration_since(Instant::now());
+            if let Some(message) = subscription.next(slice.min(CANCEL_POLL)) {
+                return Ok(Some(message.into()));
+            }
+            if self.cancel.is_cancelled() || Instant::now() >= until {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn dropped(&mut self, self_: Resource<Subscription>) -> wasmtime::Result<u64> {
+        Ok(self.table.get(&self_)?.dropped())
+    }
+
+    fn drop(&mut self, rep: Resource<Subscription>) -> wasmtime::Result<()> {
+        self.table.delete(rep)?;
+        Ok(())
+    }
+}
+
+impl bus::Host for BusCtx {
+    fn publish(
+        &mut self,
+        topic: String,
+        payload: Vec<u8>,
+    ) -> wasmtime::Result<Result<u32, String>> {
+        let Some(bus) = crate::bus::global() else {
+            return Ok(Err("Bus is disabled".to_string()));
+        };
+        Ok(bus
+            .publish(self.peer(), &topic, payload)
+            .map_err(|e| e.to_string()))
+    }
+
+    fn subscribe(
+        &mut self,
+        topic_pattern: String,
+    ) -> wasmtime::Result<Result<Resource<Subscription>, String>> {
+        let Some(bus) = crate::bus::global() else {
+            return Ok(Err("Bus is disabled".to_string()));
+        };
+        match bus.subscribe(self.peer(), &topic_pattern) {
+            Ok(subscription) => Ok(Ok(self.table.push(subscription)?)),
+            Err(e) => Ok(Err(e.to_string())),
+        }
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut BusCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    bus::add_to_linker(l, f)
+}
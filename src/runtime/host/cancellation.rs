@@ -0,0 +1,107 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use wasmtime::component::{Linker, Resource};
+use wasmtime_wasi::{Pollable, Subscribe};
+
+use super::profiling::host::cancellation;
+use crate::runtime::{PshState, cancel::CancelToken};
+
+// how often an async wait checks the stop flag
+const STOP_POLL: Duration = Duration::from_millis(10);
+
+#[derive(Clone, Debug, Default)]
+pub struct CancelCtx {
+    pub token: CancelToken,
+    /// end of the time slice, `None` means the task is allowed to run
+    /// forever
+    pub deadline: Option<Instant>,
+    /// the run is cancelled this long before its deadline
+    pub grace: Duration,
+    /// set to interrupt the run, e.g. on shutdown
+    pub stop: Option<Arc<AtomicBool>>,
+}
+
+impl CancelCtx {
+    // the time slice of the run is about to run out
+    fn wrap_up_at(&self) -> Option<Instant> {
+        self.deadline
+            .map(|it| it.checked_sub(self.grace).unwrap_or(it))
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stop
+            .as_ref()
+            .is_some_and(|it| it.load(Ordering::Relaxed))
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+            || self.is_stopped()
+            || self.wrap_up_at().is_some_and(|it| Instant::now() >= it)
+    }
+
+    async fn stopped(&self) {
+        if self.stop.is_none() {
+            return std::future::pending().await;
+        }
+        while !self.is_stopped() {
+            tokio::time::sleep(STOP_POLL).await;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Subscribe for CancelCtx {
+    async fn ready(&mut self) {
+        match self.wrap_up_at() {
+            Some(at) => tokio::select! {
+                _ = self.token.cancelled() => {}
+                _ = self.stopped() => {}
+                _ = tokio::time::sleep_until(at.into()) => {}
+            },
+            None => tokio::select! {
+                _ = self.token.cancelled() => {}
+                _ = self.stopped() => {}
+            },
+        }
+    }
+}
+
+impl cancellation::Host for PshState {
+    fn cancelled(&mut self) -> wasmtime::Result<bool> {
+        Ok(self.cancel_ctx.is_cancelled())
+    }
+
+    fn subscribe(&mut self) -> wasmtime::Result<Resource<Pollable>> {
+        // wasi:io looks pollables up in the shared table
+        let ctx = self.table.push(self.cancel_ctx.clone())?;
+        wasmtime_wasi::subscribe(&mut self.table, ctx)
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut PshState) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    cancellation::add_to_linker(l, f)
+}
@@ -0,0 +1,85 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::time::Duration;
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::capture::{self, Options};
+use crate::{
+    capture::{Request, bpf},
+    runtime::DataExportCtx,
+};
+
+const PCAP_CONTENT_TYPE: &str = "application/vnd.tcpdump.pcap";
+
+#[derive(Clone, Default)]
+pub struct CaptureCtx {
+    pub enable: bool,
+    /// where `capture-to-export` sends captures, `None` if data export is not allowed
+    pub export: Option<DataExportCtx>,
+}
+
+impl CaptureCtx {
+    fn capture(&self, opts: &Options) -> Result<Vec<u8>, String> {
+        if !self.enable {
+            return Err("Capture is not allowed".to_string());
+        }
+        let Some(capturer) = crate::capture::global() else {
+            return Err("Capture is disabled".to_string());
+        };
+        let req = Request {
+            device: opts.device.clone(),
+            filter: bpf::parse(&opts.filter).map_err(|err| err.to_string())?,
+            snaplen: opts.snaplen,
+            max_packets: opts.max_packets,
+            timeout: Duration::from_millis(opts.timeout_ms),
+            promiscuous: opts.promiscuous,
+        };
+        capturer.capture(&req).map_err(|err| format!("{err:#}"))
+    }
+}
+
+impl capture::Host for CaptureCtx {
+    fn capture(&mut self, opts: Options) -> wasmtime::Result<Result<Vec<u8>, String>> {
+        Ok(CaptureCtx::capture(self, &opts))
+    }
+
+    fn capture_to_export(
+        &mut self,
+        opts: Options,
+        name: String,
+    ) -> wasmtime::Result<Result<u64, String>> {
+        if self.export.is_none() {
+            return Ok(Err("Data export is not allowed".to_string()));
+        }
+        let bytes = match CaptureCtx::capture(self, &opts) {
+            Ok(bytes) => bytes,
+            Err(e) => return Ok(Err(e)),
+        };
+        let len = bytes.len() as u64;
+        let attributes = vec![("device".to_string(), opts.device)];
+        let export = self.export.as_mut().expect("checked above");
+        Ok(export
+            .export_file(name, PCAP_CONTENT_TYPE.to_string(), attributes, bytes)
+            .map(|()| len))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut CaptureCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    capture::add_to_linker(l, f)
+}
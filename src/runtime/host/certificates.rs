@@ -0,0 +1,83 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::time::Duration;
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::certificates::{self, Certificate, ScanError, ScanResult};
+use crate::certs::{Certificate as HostCertificate, Scanner};
+
+#[derive(Clone, Debug, Default)]
+pub struct CertificatesCtx;
+
+impl From<HostCertificate> for Certificate {
+    fn from(value: HostCertificate) -> Self {
+        Self {
+            source: value.source,
+            subject: value.subject,
+            issuer: value.issuer,
+            serial: value.serial,
+            sans: value.sans,
+            not_before: value.not_before,
+            not_after: value.not_after,
+        }
+    }
+}
+
+fn scanner() -> Result<&'static Scanner, String> {
+    crate::certs::global().ok_or_else(|| "Certificate scanning is disabled".to_string())
+}
+
+impl certificates::Host for CertificatesCtx {
+    fn scan(&mut self) -> wasmtime::Result<Result<ScanResult, String>> {
+        Ok(scanner().map(|it| {
+            let scan = it.scan();
+            ScanResult {
+                certificates: scan.certificates.into_iter().map(Into::into).collect(),
+                errors: scan
+                    .errors
+                    .into_iter()
+                    .map(|(path, message)| ScanError { path, message })
+                    .collect(),
+            }
+        }))
+    }
+
+    fn fetch(
+        &mut self,
+        host: String,
+        port: u16,
+        server_name: Option<String>,
+        timeout_ms: u64,
+    ) -> wasmtime::Result<Result<Vec<Certificate>, String>> {
+        Ok(scanner().and_then(|it| {
+            it.fetch(
+                &host,
+                port,
+                server_name.as_deref(),
+                Duration::from_millis(timeout_ms),
+            )
+            .map(|certs| certs.into_iter().map(Into::into).collect())
+            .map_err(|err| format!("{err:#}"))
+        }))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut CertificatesCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    certificates::add_to_linker(l, f)
+}
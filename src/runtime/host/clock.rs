@@ -0,0 +1,191 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use nix::time::{ClockId, clock_gettime};
+use wasmtime::component::{Linker, Resource};
+use wasmtime_wasi::{Pollable, Subscribe};
+
+use super::{cancellation::CancelCtx, profiling::host::clock};
+use crate::runtime::PshState;
+
+// how often a waiting component checks whether it was cancelled
+const CANCEL_POLL: Duration = Duration::from_millis(10);
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Sleeps until `at` in slices, false if the run was cancelled or stopped
+/// before.
+fn sleep_until(cancel: &CancelCtx, at: Instant) -> bool {
+    loop {
+        if cancel.is_cancelled() {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= at {
+            return true;
+        }
+        thread::sleep((at - now).min(CANCEL_POLL));
+    }
+}
+
+pub struct Ticker {
+    interval: Duration,
+    next: Instant,
+}
+
+impl Ticker {
+    fn new(interval: Duration, now: Instant) -> Self {
+        let interval = interval.max(MIN_INTERVAL);
+        Self {
+            interval,
+            next: now + interval,
+        }
+    }
+
+    /// Ticks passed by `now`, the next one is the first after it.
+    fn advance(&mut self, now: Instant) -> u64 {
+        if now < self.next {
+            return 0;
+        }
+        let interval = self.interval.as_nanos();
+        let ticks = (now - self.next).as_nanos() / interval + 1;
+        self.next += Duration::from_nanos((ticks * interval) as u64);
+        ticks as u64
+    }
+}
+
+/// Ready at the next tick of a ticker, or as soon as the run is cancelled
+/// or stopped.
+struct Tick {
+    at: Instant,
+    cancel: CancelCtx,
+}
+
+#[async_trait::async_trait]
+impl Subscribe for Tick {
+    async fn ready(&mut self) {
+        let at = self.at;
+        tokio::select! {
+            _ = tokio::time::sleep_until(at.into()) => {}
+            _ = self.cancel.ready() => {}
+        }
+    }
+}
+
+impl clock::HostTicker for PshState {
+    fn new(&mut self, interval_ns: u64) -> wasmtime::Result<Resource<Ticker>> {
+        let ticker = Ticker::new(Duration::from_nanos(interval_ns), Instant::now());
+        Ok(self.table.push(ticker)?)
+    }
+
+    fn wait(&mut self, self_: Resource<Ticker>) -> wasmtime::Result<u64> {
+        let next = self.table.get(&self_)?.next;
+        if !sleep_until(&self.cancel_ctx, next) {
+            return Ok(0);
+        }
+        let ticker = self.table.get_mut(&self_)?;
+        Ok(ticker.advance(Instant::now()))
+    }
+
+    fn subscribe(&mut self, self_: Resource<Ticker>) -> wasmtime::Result<Resource<Pollable>> {
+        let next = self.table.get(&self_)?.next;
+        let tick = self.table.push(Tick {
+            at: next,
+            cancel: self.cancel_ctx.clone(),
+        })?;
+        wasmtime_wasi::subscribe(&mut self.table, tick)
+    }
+
+    fn drop(&mut self, rep: Resource<Ticker>) -> wasmtime::Result<()> {
+        self.table.delete(rep)?;
+        Ok(())
+    }
+}
+
+impl clock::Host for PshState {
+    fn monotonic_now(&mut self) -> wasmtime::Result<u64> {
+        let now = clock_gettime(ClockId::CLOCK_MONOTONIC)?;
+        Ok(now.tv_sec() as u64 * 1_000_000_000 + now.tv_nsec() as u64)
+    }
+
+    fn wall_now(&mut self) -> wasmtime::Result<u64> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        Ok(now.as_nanos() as u64)
+    }
+
+    fn sleep(&mut self, duration_ns: u64) -> wasmtime::Result<bool> {
+        let at = Instant::now() + Duration::from_nanos(duration_ns);
+        Ok(sleep_until(&self.cancel_ctx, at))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut PshState) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    clock::add_to_linker(l, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{CancelCtx, Ticker, sleep_until};
+
+    #[test]
+    fn test_sleep_until_stopped() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let cancel = CancelCtx {
+            stop: Some(Arc::clone(&stop)),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            stop.store(true, Ordering::Relaxed);
+        });
+        assert!(!sleep_until(&cancel, start + Duration::from_secs(60)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        stopper.join().unwrap();
+
+        assert!(sleep_until(&CancelCtx::default(), Instant::now()));
+    }
+
+    #[test]
+    fn test_ticker_advance() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut ticker = Ticker::new(ms(100), start);
+        assert_eq!(ticker.advance(start + ms(50)), 0);
+        assert_eq!(ticker.advance(start + ms(100)), 1);
+        assert_eq!(ticker.next, start + ms(200));
+        // fell behind by two ticks
+        assert_eq!(ticker.advance(start + ms(420)), 3);
+        assert_eq!(ticker.next, start + ms(500));
+
+        let ticker = Ticker::new(Duration::ZERO, start);
+        assert_eq!(ticker.interval, ms(1));
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::history::{self, Entry};
+use crate::history::Query;
+
+#[derive(Clone, Debug, Default)]
+pub struct HistoryCtx {
+    /// only exports of this tenant are seen, all of them if `None`
+    pub tenant: Option<String>,
+}
+
+impl history::Host for HistoryCtx {
+    fn query(
+        &mut self,
+        name: String,
+        tags: Vec<(String, String)>,
+        start_ns: u64,
+        end_ns: u64,
+    ) -> wasmtime::Result<Result<Vec<Entry>, String>> {
+        let Some(history) = crate::history::global() else {
+            return Ok(Err("History is disabled".to_string()));
+        };
+        let query = Query {
+            tenant: self.tenant.as_deref(),
+            name: &name,
+            tags: &tags,
+            start_ns,
+            end_ns,
+        };
+        Ok(Ok(history
+            .query(&query)
+            .into_iter()
+            .map(|it| Entry {
+                tags: it.tags,
+                fields: it.fields,
+                ns_ts: it.ns_ts,
+            })
+            .collect()))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut HistoryCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    history::add_to_linker(l, f)
+}
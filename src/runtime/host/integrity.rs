@@ -0,0 +1,92 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::integrity::{self, Change, Drift, FileState};
+use crate::integrity::{Drift as HostDrift, FileState as HostFileState, Monitor};
+
+#[derive(Clone, Debug, Default)]
+pub struct IntegrityCtx {
+    /// whether the component may replace baselines
+    pub allow_baseline: bool,
+}
+
+impl From<HostFileState> for FileState {
+    fn from(value: HostFileState) -> Self {
+        Self {
+            path: value.path,
+            sha256: value.sha256,
+            size: value.size,
+            mode: value.mode,
+            uid: value.uid,
+            gid: value.gid,
+        }
+    }
+}
+
+impl From<HostDrift> for Drift {
+    fn from(value: HostDrift) -> Self {
+        Self {
+            changed: value
+                .changed
+                .into_iter()
+                .map(|(before, after)| Change {
+                    before: before.into(),
+                    after: after.into(),
+                })
+                .collect(),
+            added: value.added.into_iter().map(Into::into).collect(),
+            removed: value.removed.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+fn monitor() -> Result<&'static Monitor, String> {
+    crate::integrity::global().ok_or_else(|| "Integrity monitoring is disabled".to_string())
+}
+
+impl integrity::Host for IntegrityCtx {
+    fn sets(&mut self) -> wasmtime::Result<Result<Vec<String>, String>> {
+        Ok(monitor().map(Monitor::sets))
+    }
+
+    fn baseline(&mut self, set: String) -> wasmtime::Result<Result<u32, String>> {
+        if !self.allow_baseline {
+            return Ok(Err(
+                "Replacing integrity baselines is not allowed".to_string()
+            ));
+        }
+        Ok(monitor().and_then(|it| {
+            it.baseline(&set)
+                .map(|len| len as u32)
+                .map_err(|err| format!("{err:#}"))
+        }))
+    }
+
+    fn verify(&mut self, set: String) -> wasmtime::Result<Result<Drift, String>> {
+        Ok(monitor().and_then(|it| {
+            it.verify(&set)
+                .map(Into::into)
+                .map_err(|err| format!("{err:#}"))
+        }))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut IntegrityCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    integrity::add_to_linker(l, f)
+}
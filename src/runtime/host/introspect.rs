@@ -0,0 +1,231 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use wasmtime::component::Linker;
+
+pub use super::profiling::host::introspect::Grants;
+use super::profiling::host::introspect::{self, BuildInfo, HostInterface};
+use crate::{
+    build_info,
+    runtime::policy::{self, InterfacePolicy},
+};
+
+/// Version of the WASI interfaces guests are built against, later 0.2
+/// releases are linked as well.
+const WASI_VERSION: &str = "0.2.0";
+const WASI: &[&str] = &[
+    "wasi:cli/*",
+    "wasi:clocks/*",
+    "wasi:filesystem/*",
+    "wasi:http/outgoing-handler",
+    "wasi:http/types",
+    "wasi:io/*",
+    "wasi:random/*",
+    "wasi:sockets/*",
+];
+/// Linked for every component, see `wit/host` and `wit/probe`.
+const HOST: &[&str] = &[
+    "profiling:host/blackbox",
+    "profiling:host/bursts",
+    "profiling:host/bus",
+    "profiling:host/cancellation",
+    "profiling:host/capture",
+    "profiling:host/certificates",
+    "profiling:host/clock",
+    "profiling:host/history",
+    "profiling:host/integrity",
+    "profiling:host/introspect",
+    "profiling:host/kernel-events",
+    "profiling:host/kubernetes",
+    "profiling:host/log",
+    "profiling:host/msr",
+    "profiling:host/pipeline",
+    "profiling:host/process-events",
+    "profiling:host/scheduling",
+    "profiling:host/store",
+    "profiling:host/streams",
+    "profiling:host/symbolize",
+    "profiling:host/sysfs",
+    "profiling:host/task-result",
+    "profiling:probe/probe",
+];
+const PERF: &[&str] = &[
+    "profiling:perf/*",
+    "profiling:perf-ext/analysis",
+    "profiling:perf-ext/batch",
+    "profiling:perf-ext/numa",
+    "profiling:perf-ext/perf",
+];
+const SYSTEM: &[&str] = &[
+    "profiling:system/*",
+    "profiling:system-ext/bulk",
+    "profiling:system-ext/cgroup",
+    "profiling:system-ext/cpu",
+    "profiling:system-ext/filesystem",
+    "profiling:system-ext/interrupt",
+    "profiling:system-ext/latency",
+    "profiling:system-ext/memory",
+    "profiling:system-ext/process",
+    "profiling:system-ext/tcp",
+];
+const DATA_EXPORT: &[&str] = &[
+    "profiling:data-export/*",
+    "profiling:data-export-ext/file",
+    "profiling:data-export-ext/schema",
+    "profiling:data-export-ext/quota",
+    "profiling:data-export-ext/trace",
+];
+
+/// Interface names the builder links, patterns ending in `*` cover a
+/// whole package.
+pub struct Linked {
+    pub perf: bool,
+    pub system: bool,
+    pub data_export: bool,
+}
+
+impl Linked {
+    fn names(&self) -> impl Iterator<Item = &'static str> {
+        let optional = [
+            (self.perf, PERF),
+            (self.system, SYSTEM),
+            (self.data_export, DATA_EXPORT),
+        ];
+        WASI.iter().chain(HOST).copied().chain(
+            optional
+                .into_iter()
+                .filter(|(linked, _)| *linked)
+                .flat_map(|(_, names)| names.iter().copied()),
+        )
+    }
+
+    /// Whether an interface of this name is linked, the version is ignored.
+    pub fn provides(&self, name: &str) -> bool {
+        let name = name.split_once('@').map_or(name, |(name, _)| name);
+        self.names().any(|it| policy::matches(it, name))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct IntrospectCtx {
+    names: Vec<&'static str>,
+    policy: Option<&'static InterfacePolicy>,
+    grants: Grants,
+}
+
+impl IntrospectCtx {
+    pub fn new(linked: &Linked, policy: Option<&'static InterfacePolicy>, grants: Grants) -> Self {
+        let mut names: Vec<_> = linked
+            .names()
+            .filter(|it| policy.is_none_or(|policy| policy.allows(it)))
+            .collect();
+        names.sort_unstable();
+        Self {
+            names,
+            policy,
+            grants,
+        }
+    }
+
+    fn is_available(&self, name: &str) -> bool {
+        let name = name.split_once('@').map_or(name, |(name, _)| name);
+        self.names.iter().any(|it| policy::matches(it, name))
+            && self.policy.is_none_or(|it| it.allows(name))
+    }
+}
+
+impl introspect::Host for IntrospectCtx {
+    fn interfaces(&mut self) -> wasmtime::Result<Vec<HostInterface>> {
+        Ok(self
+            .names
+            .iter()
+            .map(|it| HostInterface {
+                name: it.to_string(),
+                version: it.starts_with("wasi:").then(|| WASI_VERSION.to_string()),
+            })
+            .collect())
+    }
+
+    fn available(&mut self, name: String) -> wasmtime::Result<bool> {
+        Ok(self.is_available(&name))
+    }
+
+    fn grants(&mut self) -> wasmtime::Result<Grants> {
+        Ok(self.grants.clone())
+    }
+
+    fn build_info(&mut self) -> wasmtime::Result<BuildInfo> {
+        let info = build_info::get();
+        Ok(BuildInfo {
+            version: info.version.to_string(),
+            commit: info.commit.to_string(),
+            build_date: info.build_date,
+            target: info.target,
+            profile: info.profile.to_string(),
+            features: info.features.iter().map(|it| it.to_string()).collect(),
+            wasmtime: info.wasmtime.to_string(),
+        })
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut IntrospectCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    introspect::add_to_linker(l, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Grants, IntrospectCtx, Linked};
+    use crate::runtime::policy::InterfacePolicy;
+
+    #[test]
+    fn test_available() {
+        let grants = Grants {
+            environ: false,
+            data_export: false,
+            capture: false,
+            probe: false,
+            ping: false,
+            msr: false,
+            sysfs: false,
+            tcp: false,
+            udp: false,
+            name_lookup: false,
+            http: false,
+            preopens: vec![],
+        };
+        let linked = Linked {
+            perf: true,
+            system: false,
+            data_export: false,
+        };
+        let ctx = IntrospectCtx::new(&linked, None, grants.clone());
+        assert!(ctx.is_available("profiling:perf/counter@0.1.0"));
+        assert!(ctx.is_available("profiling:perf-ext/numa"));
+        assert!(ctx.is_available("wasi:io/poll@0.2.0"));
+        assert!(!ctx.is_available("profiling:system/cpu"));
+        assert!(!ctx.is_available("profiling:perf-ext/unknown"));
+
+        let policy: &'static InterfacePolicy = Box::leak(Box::new(InterfacePolicy {
+            deny: vec!["profiling:perf-ext/*".to_string()],
+            ..Default::default()
+        }));
+        let ctx = IntrospectCtx::new(&linked, Some(policy), grants);
+        assert!(ctx.is_available("profiling:perf/counter"));
+        assert!(!ctx.is_available("profiling:perf-ext/numa"));
+        assert!(!ctx.names.contains(&"profiling:perf-ext/numa"));
+    }
+}
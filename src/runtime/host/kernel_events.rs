@@ -0,0 +1,104 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::time::Duration;
+
+use crossbeam::channel::Receiver;
+use psh_system::kmsg::{KernelEvent as HostKernelEvent, KernelEventKind as HostKernelEventKind};
+use wasmtime::component::{Linker, Resource, ResourceTable};
+
+use super::profiling::host::kernel_events::{
+    self, EventKind, HungTask, KernelEvent, SoftLockup, TaskRef,
+};
+
+pub struct Subscription {
+    rx: Receiver<HostKernelEvent>,
+}
+
+#[derive(Default)]
+pub struct KernelEventsCtx {
+    table: ResourceTable,
+}
+
+impl From<HostKernelEvent> for KernelEvent {
+    fn from(value: HostKernelEvent) -> Self {
+        let kind = match value.kind {
+            HostKernelEventKind::OomKill { pid, comm } => EventKind::OomKill(TaskRef { pid, comm }),
+            HostKernelEventKind::HungTask {
+                pid,
+                comm,
+                blocked_secs,
+            } => EventKind::HungTask(HungTask {
+                task: TaskRef { pid, comm },
+                blocked_secs,
+            }),
+            HostKernelEventKind::SoftLockup {
+                cpu,
+                stuck_secs,
+                pid,
+                comm,
+            } => EventKind::SoftLockup(SoftLockup {
+                cpu,
+                stuck_secs,
+                task: TaskRef { pid, comm },
+            }),
+            HostKernelEventKind::HardLockup { cpu } => EventKind::HardLockup(cpu),
+        };
+        Self {
+            seq: value.seq,
+            timestamp_us: value.timestamp_us,
+            kind,
+            message: value.message,
+        }
+    }
+}
+
+impl kernel_events::HostSubscription for KernelEventsCtx {
+    fn next(
+        &mut self,
+        self_: Resource<Subscription>,
+        timeout_ms: u64,
+    ) -> wasmtime::Result<Option<KernelEvent>> {
+        let subscription = self.table.get(&self_)?;
+        Ok(subscription
+            .rx
+            .recv_timeout(Duration::from_millis(timeout_ms))
+            .ok()
+            .map(Into::into))
+    }
+
+    fn drop(&mut self, rep: Resource<Subscription>) -> wasmtime::Result<()> {
+        self.table.delete(rep)?;
+        Ok(())
+    }
+}
+
+impl kernel_events::Host for KernelEventsCtx {
+    fn subscribe(&mut self) -> wasmtime::Result<Result<Resource<Subscription>, String>> {
+        let Some(hub) = crate::services::kernel_events::global() else {
+            return Ok(Err("Kernel event watcher is disabled".to_string()));
+        };
+        let subscription = Subscription {
+            rx: hub.subscribe(),
+        };
+        Ok(Ok(self.table.push(subscription)?))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut KernelEventsCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    kernel_events::add_to_linker(l, f)
+}
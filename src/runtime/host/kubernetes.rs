@@ -0,0 +1,67 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::kubernetes::{self, Identity, Pod};
+use crate::kubernetes::ProcessPod;
+
+#[derive(Clone, Debug, Default)]
+pub struct KubernetesCtx;
+
+impl From<ProcessPod> for Pod {
+    fn from(value: ProcessPod) -> Self {
+        let pod = value.pod;
+        Self {
+            namespace: pod.namespace.clone(),
+            name: pod.name.clone(),
+            uid: pod.uid.clone(),
+            labels: pod.labels.clone().into_iter().collect(),
+            container: value.container,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl kubernetes::Host for KubernetesCtx {
+    fn local_identity(&mut self) -> wasmtime::Result<Identity> {
+        let identity = crate::kubernetes::global()
+            .map(|it| it.identity.clone())
+            .unwrap_or_default();
+        Ok(Identity {
+            node_name: identity.node_name,
+            pod_name: identity.pod_name,
+            namespace: identity.namespace,
+            labels: identity.labels.into_iter().collect(),
+        })
+    }
+
+    async fn pod_of_process(&mut self, pid: u32) -> wasmtime::Result<Result<Option<Pod>, String>> {
+        let Some(k8s) = crate::kubernetes::global() else {
+            return Ok(Err("Kubernetes integration is disabled".to_string()));
+        };
+        // asks the kubelet on a cache miss
+        let pod = tokio::task::spawn_blocking(move || k8s.pod_of_process(pid)).await?;
+        Ok(pod
+            .map(|it| it.map(Into::into))
+            .map_err(|err| err.to_string()))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut KubernetesCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    kubernetes::add_to_linker(l, f)
+}
@@ -0,0 +1,141 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{fmt::Write, sync::Arc};
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::log::{self, Level};
+
+const MAX_MESSAGE_LEN: usize = 4096;
+
+#[derive(Clone, Debug)]
+pub struct LogCtx {
+    pub component: Arc<str>,
+    pub task_id: Option<Arc<str>>,
+}
+
+impl Default for LogCtx {
+    fn default() -> Self {
+        Self {
+            component: "unnamed".into(),
+            task_id: None,
+        }
+    }
+}
+
+/// `k=v` pairs, values with spaces or quotes are quoted.
+fn format_fields(fields: &[(String, String)]) -> String {
+    let mut formatted = String::new();
+    for (k, v) in fields {
+        if !formatted.is_empty() {
+            formatted.push(' ');
+        }
+        if v.is_empty() || v.contains([' ', '"', '=']) {
+            let _ = write!(formatted, "{k}={v:?}");
+        } else {
+            let _ = write!(formatted, "{k}={v}");
+        }
+    }
+    formatted
+}
+
+fn truncate(message: &str) -> &str {
+    if message.len() <= MAX_MESSAGE_LEN {
+        return message;
+    }
+    let mut end = MAX_MESSAGE_LEN;
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    &message[..end]
+}
+
+impl log::Host for LogCtx {
+    fn log(
+        &mut self,
+        level: Level,
+        message: String,
+        fields: Vec<(String, String)>,
+    ) -> wasmtime::Result<()> {
+        let message = truncate(&message);
+        let fields = format_fields(&fields);
+        let (component, task_id) = (&*self.component, self.task_id.as_deref());
+        macro_rules! emit {
+            ($level:ident) => {
+                tracing::$level!(target: "component", component, task_id, fields, "{message}")
+            };
+        }
+        match level {
+            Level::Trace => emit!(trace),
+            Level::Debug => emit!(debug),
+            Level::Info => emit!(info),
+            Level::Warn => emit!(warn),
+            Level::Error => emit!(error),
+        }
+        Ok(())
+    }
+
+    fn enabled(&mut self, level: Level) -> wasmtime::Result<bool> {
+        macro_rules! enabled {
+            ($level:ident) => {
+                tracing::enabled!(target: "component", tracing::Level::$level)
+            };
+        }
+        Ok(match level {
+            Level::Trace => enabled!(TRACE),
+            Level::Debug => enabled!(DEBUG),
+            Level::Info => enabled!(INFO),
+            Level::Warn => enabled!(WARN),
+            Level::Error => enabled!(ERROR),
+        })
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut LogCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    log::add_to_linker(l, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_MESSAGE_LEN, format_fields, truncate};
+
+    #[test]
+    fn test_format_fields() {
+        let fields = |pairs: &[(&str, &str)]| {
+            let pairs: Vec<_> = pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            format_fields(&pairs)
+        };
+        assert_eq!(fields(&[]), "");
+        assert_eq!(fields(&[("cpu", "3"), ("busy", "0.9")]), "cpu=3 busy=0.9");
+        assert_eq!(
+            fields(&[("path", "/a b"), ("x", "")]),
+            r#"path="/a b" x="""#
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short"), "short");
+        let long = "é".repeat(MAX_MESSAGE_LEN);
+        assert!(truncate(&long).len() <= MAX_MESSAGE_LEN);
+        assert!(truncate(&long).chars().all(|it| it == 'é'));
+    }
+}
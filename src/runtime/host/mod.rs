@@ -0,0 +1,55 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+pub mod blackbox;
+pub mod bursts;
+pub mod bus;
+pub mod cancellation;
+pub mod capture;
+pub mod certificates;
+pub mod clock;
+pub mod history;
+pub mod integrity;
+pub mod introspect;
+pub mod kernel_events;
+pub mod kubernetes;
+pub mod log;
+pub mod msr;
+pub mod pipeline;
+pub mod process_events;
+pub mod scheduling;
+pub mod store;
+pub mod streams;
+pub mod symbolize;
+pub mod sysfs;
+pub mod task_result;
+
+// Host interfaces implemented by the daemon itself.
+wasmtime::component::bindgen!({
+    path: "wit/host",
+    world: "imports",
+    trappable_imports: true,
+    // they may fetch over HTTP with a blocking client, which must not run on
+    // the thread driving the engine
+    async: {
+        only_imports: ["pod-of-process", "symbolize"],
+    },
+    with: {
+        "profiling:host/bus/subscription": bus::Subscription,
+        "profiling:host/clock/ticker": clock::Ticker,
+        "profiling:host/kernel-events/subscription": kernel_events::Subscription,
+        "profiling:host/process-events/subscription": process_events::Subscription,
+        "wasi:io": wasmtime_wasi::bindings::io,
+    },
+});
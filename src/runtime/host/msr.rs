@@ -0,0 +1,58 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::msr::{self, AllowedMsr};
+use crate::msr::MsrReader;
+
+#[derive(Clone, Debug, Default)]
+pub struct MsrCtx {
+    pub enable: bool,
+}
+
+impl MsrCtx {
+    fn reader(&self) -> Result<&'static MsrReader, String> {
+        if !self.enable {
+            return Err("MSR reading is not allowed".to_string());
+        }
+        crate::msr::global().ok_or_else(|| "MSR reading is disabled".to_string())
+    }
+}
+
+impl msr::Host for MsrCtx {
+    fn allowed(&mut self) -> wasmtime::Result<Result<Vec<AllowedMsr>, String>> {
+        Ok(self.reader().map(|it| {
+            it.allowed()
+                .map(|(name, address)| AllowedMsr {
+                    name: name.to_string(),
+                    address,
+                })
+                .collect()
+        }))
+    }
+
+    fn read_msr(&mut self, cpu: u32, msr: u32) -> wasmtime::Result<Result<u64, String>> {
+        Ok(self
+            .reader()
+            .and_then(|it| it.read(cpu, msr).map_err(|err| format!("{err:#}"))))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut MsrCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    msr::add_to_linker(l, f)
+}
@@ -0,0 +1,112 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::sync::{Arc, Mutex};
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::pipeline::{self, Payload};
+use crate::runtime::pipeline::Payload as HostPayload;
+
+const MAX_OUTPUT_SIZE: usize = 16 << 20;
+const MAX_CONTENT_TYPE_LEN: usize = 256;
+
+#[derive(Clone, Debug, Default)]
+pub struct PipelineCtx {
+    pub input: Vec<HostPayload>,
+    /// read by the runtime once the run has finished, `None` if no
+    /// component runs after this one
+    pub output: Option<Arc<Mutex<Vec<HostPayload>>>>,
+    pub output_size: usize,
+}
+
+impl PipelineCtx {
+    fn push(&mut self, payload: HostPayload) -> Result<(), String> {
+        if payload.content_type.len() > MAX_CONTENT_TYPE_LEN {
+            return Err(format!(
+                "Content type longer than {MAX_CONTENT_TYPE_LEN} bytes"
+            ));
+        }
+        let size = self.output_size + payload.data.len();
+        if size > MAX_OUTPUT_SIZE {
+            return Err(format!("More than {MAX_OUTPUT_SIZE} bytes emitted"));
+        }
+        self.output_size = size;
+        if let Some(output) = &self.output {
+            output.lock().unwrap().push(payload);
+        }
+        Ok(())
+    }
+}
+
+impl pipeline::Host for PipelineCtx {
+    fn input(&mut self) -> wasmtime::Result<Vec<Payload>> {
+        Ok(self
+            .input
+            .iter()
+            .map(|it| Payload {
+                content_type: it.content_type.clone(),
+                data: it.data.clone(),
+            })
+            .collect())
+    }
+
+    fn emit(&mut self, payload: Payload) -> wasmtime::Result<Result<(), String>> {
+        Ok(self.push(HostPayload {
+            content_type: payload.content_type,
+            data: payload.data,
+        }))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut PipelineCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    pipeline::add_to_linker(l, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{MAX_OUTPUT_SIZE, PipelineCtx};
+    use crate::runtime::pipeline::Payload;
+
+    fn payload(size: usize) -> Payload {
+        Payload {
+            content_type: "application/json".to_string(),
+            data: vec![b'0'; size],
+        }
+    }
+
+    #[test]
+    fn test_emit_limits() {
+        let output = Arc::new(Mutex::new(vec![]));
+        let mut ctx = PipelineCtx {
+            output: Some(Arc::clone(&output)),
+            ..Default::default()
+        };
+        ctx.push(payload(MAX_OUTPUT_SIZE - 1)).unwrap();
+        assert!(ctx.push(payload(2)).is_err());
+        ctx.push(payload(1)).unwrap();
+        assert_eq!(output.lock().unwrap().len(), 2);
+
+        let mut ctx = PipelineCtx::default();
+        ctx.push(payload(1)).unwrap();
+        let mut invalid = payload(0);
+        invalid.content_type = "x".repeat(300);
+        assert!(ctx.push(invalid).is_err());
+    }
+}
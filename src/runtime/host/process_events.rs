@@ -0,0 +1,91 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::time::Duration;
+
+use crossbeam::channel::Receiver;
+use wasmtime::component::{Linker, Resource, ResourceTable};
+
+use super::profiling::host::process_events::{self, EventKind, ExitStatus, ProcessEvent};
+use crate::services::process_events::{
+    ProcessEvent as HostProcessEvent, ProcessEventKind as HostProcessEventKind,
+};
+
+pub struct Subscription {
+    rx: Receiver<HostProcessEvent>,
+}
+
+#[derive(Default)]
+pub struct ProcessEventsCtx {
+    table: ResourceTable,
+}
+
+impl From<HostProcessEvent> for ProcessEvent {
+    fn from(value: HostProcessEvent) -> Self {
+        let kind = match value.kind {
+            HostProcessEventKind::Fork => EventKind::Fork,
+            HostProcessEventKind::Exec => EventKind::Exec,
+            HostProcessEventKind::Exit { exit_code, signal } => EventKind::Exit(ExitStatus {
+                code: exit_code,
+                signal,
+            }),
+        };
+        Self {
+            timestamp_ns: value.timestamp_ns,
+            kind,
+            pid: value.pid,
+            ppid: value.ppid,
+            comm: value.comm,
+        }
+    }
+}
+
+impl process_events::HostSubscription for ProcessEventsCtx {
+    fn next(
+        &mut self,
+        self_: Resource<Subscription>,
+        timeout_ms: u64,
+    ) -> wasmtime::Result<Option<ProcessEvent>> {
+        let subscription = self.table.get(&self_)?;
+        Ok(subscription
+            .rx
+            .recv_timeout(Duration::from_millis(timeout_ms))
+            .ok()
+            .map(Into::into))
+    }
+
+    fn drop(&mut self, rep: Resource<Subscription>) -> wasmtime::Result<()> {
+        self.table.delete(rep)?;
+        Ok(())
+    }
+}
+
+impl process_events::Host for ProcessEventsCtx {
+    fn subscribe(&mut self) -> wasmtime::Result<Result<Resource<Subscription>, String>> {
+        let Some(hub) = crate::services::process_events::global() else {
+            return Ok(Err("Process event stream is disabled".to_string()));
+        };
+        let subscription = Subscription {
+            rx: hub.subscribe(),
+        };
+        Ok(Ok(self.table.push(subscription)?))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut ProcessEventsCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    process_events::add_to_linker(l, f)
+}
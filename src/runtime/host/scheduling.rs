@@ -0,0 +1,59 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{thread, time::Instant};
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::scheduling;
+
+#[derive(Clone, Debug, Default)]
+pub struct SchedulingCtx {
+    /// `None` means the task is allowed to run forever.
+    pub deadline: Option<Instant>,
+}
+
+impl SchedulingCtx {
+    pub const fn new(deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+        }
+    }
+
+    pub fn remaining_ms(&self) -> u64 {
+        self.deadline.map_or(u64::MAX, |deadline| {
+            deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis() as u64
+        })
+    }
+}
+
+impl scheduling::Host for SchedulingCtx {
+    fn yield_now(&mut self) -> wasmtime::Result<()> {
+        thread::yield_now();
+        Ok(())
+    }
+
+    fn checkpoint(&mut self) -> wasmtime::Result<u64> {
+        Ok(self.remaining_ms())
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut SchedulingCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    scheduling::add_to_linker(l, f)
+}
@@ -0,0 +1,64 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::store;
+
+#[derive(Clone, Debug, Default)]
+pub struct StoreCtx {
+    /// keys of the component, see [`crate::store::namespace`], `None` if it
+    /// has no name
+    pub namespace: Option<String>,
+}
+
+impl StoreCtx {
+    fn with<T>(
+        &self,
+        f: impl FnOnce(&crate::store::Store, &str) -> anyhow::Result<T>,
+    ) -> Result<T, String> {
+        let Some(store) = crate::store::global() else {
+            return Err("Store is disabled".to_string());
+        };
+        let Some(namespace) = &self.namespace else {
+            return Err("Only components configured on the host have a store".to_string());
+        };
+        f(store, namespace).map_err(|e| format!("{e:#}"))
+    }
+}
+
+impl store::Host for StoreCtx {
+    fn get(&mut self, key: String) -> wasmtime::Result<Result<Option<Vec<u8>>, String>> {
+        Ok(self.with(|store, namespace| store.get(namespace, &key)))
+    }
+
+    fn set(&mut self, key: String, value: Vec<u8>) -> wasmtime::Result<Result<(), String>> {
+        Ok(self.with(|store, namespace| store.set(namespace, &key, &value)))
+    }
+
+    fn delete(&mut self, key: String) -> wasmtime::Result<Result<bool, String>> {
+        Ok(self.with(|store, namespace| store.delete(namespace, &key)))
+    }
+
+    fn list_keys(&mut self, prefix: String) -> wasmtime::Result<Result<Vec<String>, String>> {
+        Ok(self.with(|store, namespace| store.keys(namespace, &prefix)))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut StoreCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    store::add_to_linker(l, f)
+}
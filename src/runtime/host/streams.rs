@@ -0,0 +1,196 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+    thread::{self, JoinHandle},
+};
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use psh_system::kmsg::KmsgReader;
+use tokio::sync::mpsc::{self, error::TryRecvError};
+use wasmtime::component::{Linker, Resource};
+use wasmtime_wasi::{HostInputStream, InputStream, StreamError, StreamResult, Subscribe};
+
+use super::profiling::host::streams::{self, Source};
+use crate::{runtime::PshState, services::kernel_events};
+
+// records buffered per stream before the source is no longer read
+const STREAM_CAPACITY: usize = 1024;
+
+type Close = Box<dyn FnOnce() + Send + Sync>;
+
+/// Input stream of records read by a host thread.
+///
+/// The thread exits with the next record after the stream is dropped, or
+/// right away if the source can be closed, and is joined then.
+struct RecordStream {
+    rx: mpsc::Receiver<Vec<u8>>,
+    /// the part of the current record not read by the guest yet
+    pending: Bytes,
+    closed: bool,
+    reader: Option<JoinHandle<()>>,
+    /// unblocks the reader
+    close: Option<Close>,
+}
+
+impl RecordStream {
+    fn spawn(mut next: impl FnMut() -> Result<Vec<u8>> + Send + 'static) -> Self {
+        let (tx, rx) = mpsc::channel(STREAM_CAPACITY);
+        let reader = thread::spawn(move || {
+            loop {
+                let record = match next() {
+                    Ok(record) => record,
+                    Err(e) => {
+                        tracing::warn!("Stream source stopped: {e}");
+                        break;
+                    }
+                };
+                if tx.blocking_send(record).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            rx,
+            pending: Bytes::new(),
+            closed: false,
+            reader: Some(reader),
+            close: None,
+        }
+    }
+
+    fn closed_by(mut self, close: impl FnOnce() + Send + Sync + 'static) -> Self {
+        self.close = Some(Box::new(close));
+        self
+    }
+}
+
+impl Drop for RecordStream {
+    fn drop(&mut self) {
+        let Some(close) = self.close.take() else {
+            return;
+        };
+        close();
+        // a reader waiting for room in the channel gives up too
+        self.rx.close();
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Subscribe for RecordStream {
+    async fn ready(&mut self) {
+        if !self.pending.is_empty() || self.closed {
+            return;
+        }
+        match self.rx.recv().await {
+            Some(record) => self.pending = record.into(),
+            None => self.closed = true,
+        }
+    }
+}
+
+impl HostInputStream for RecordStream {
+    fn read(&mut self, size: usize) -> StreamResult<Bytes> {
+        if self.pending.is_empty() && !self.closed {
+            match self.rx.try_recv() {
+                Ok(record) => self.pending = record.into(),
+                Err(TryRecvError::Empty) => return Ok(Bytes::new()),
+                Err(TryRecvError::Disconnected) => self.closed = true,
+            }
+        }
+        if self.pending.is_empty() {
+            return Err(StreamError::Closed);
+        }
+        let len = size.min(self.pending.len());
+        Ok(self.pending.split_to(len))
+    }
+}
+
+fn open_kmsg() -> Result<RecordStream> {
+    let mut reader = KmsgReader::new()?;
+    Ok(RecordStream::spawn(move || {
+        Ok(reader.next_record()?.into_bytes())
+    }))
+}
+
+fn open_journald() -> Result<RecordStream> {
+    let mut child = Command::new("journalctl")
+        .args(["--follow", "--lines=0", "--output=json"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to run journalctl")?;
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+    let stream = RecordStream::spawn(move || {
+        let mut line = Vec::new();
+        if stdout.read_until(b'\n', &mut line)? == 0 {
+            anyhow::bail!("journalctl exited");
+        }
+        Ok(line)
+    });
+    // the reader sees the end of stdout once journalctl is gone
+    Ok(stream.closed_by(move || {
+        let _ = child.kill();
+        let _ = child.wait();
+    }))
+}
+
+fn open_kernel_events() -> Result<RecordStream> {
+    let Some(hub) = kernel_events::global() else {
+        anyhow::bail!("Kernel event watcher is disabled");
+    };
+    let rx = hub.subscribe();
+    Ok(RecordStream::spawn(move || {
+        let event = rx.recv()?;
+        let json = serde_json::json!({
+            "seq": event.seq,
+            "timestamp_us": event.timestamp_us,
+            "kind": kernel_events::kind_name(&event.kind),
+            "message": event.message,
+        });
+        let mut line = serde_json::to_vec(&json)?;
+        line.push(b'\n');
+        Ok(line)
+    }))
+}
+
+impl streams::Host for PshState {
+    fn open(&mut self, source: Source) -> wasmtime::Result<Result<Resource<InputStream>, String>> {
+        let stream = match source {
+            Source::Kmsg => open_kmsg(),
+            Source::Journald => open_journald(),
+            Source::KernelEvents => open_kernel_events(),
+        };
+        let stream: InputStream = match stream {
+            Ok(stream) => Box::new(stream),
+            Err(e) => return Ok(Err(e.to_string())),
+        };
+        // wasi:io looks streams up in the shared table
+        Ok(Ok(self.table.push(stream)?))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut PshState) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    streams::add_to_linker(l, f)
+}
@@ -0,0 +1,74 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::symbolize::{self, Frame};
+use crate::symbolize::{BuildId, Frame as HostFrame};
+
+#[derive(Clone, Debug, Default)]
+pub struct SymbolizeCtx;
+
+impl From<HostFrame> for Frame {
+    fn from(value: HostFrame) -> Self {
+        Self {
+            function: value.function,
+            file: value.file,
+            line: value.line,
+            inlined: value.inlined,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl symbolize::Host for SymbolizeCtx {
+    fn register(&mut self, path: String) -> wasmtime::Result<Result<Vec<u8>, String>> {
+        let Some(symbolizer) = crate::symbolize::global() else {
+            return Ok(Err("Symbolizer is not initialized".to_string()));
+        };
+        Ok(symbolizer
+            .register(path)
+            .map(|build_id| build_id.0)
+            .map_err(|err| err.to_string()))
+    }
+
+    async fn symbolize(
+        &mut self,
+        build_id: Vec<u8>,
+        offsets: Vec<u64>,
+    ) -> wasmtime::Result<Result<Vec<Vec<Frame>>, String>> {
+        let Some(symbolizer) = crate::symbolize::global() else {
+            return Ok(Err("Symbolizer is not initialized".to_string()));
+        };
+        // may fetch debug info from debuginfod
+        let symbols =
+            tokio::task::spawn_blocking(move || symbolizer.symbolize(&BuildId(build_id), &offsets))
+                .await?;
+        Ok(symbols
+            .map(|symbols| {
+                symbols
+                    .into_iter()
+                    .map(|frames| frames.into_iter().map(Into::into).collect())
+                    .collect()
+            })
+            .map_err(|err| err.to_string()))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut SymbolizeCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    symbolize::add_to_linker(l, f)
+}
@@ -0,0 +1,43 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use wasmtime::component::Linker;
+
+use super::profiling::host::sysfs;
+use crate::sysfs::Caller;
+
+#[derive(Clone, Debug, Default)]
+pub struct SysfsCtx {
+    /// `None` if sysfs reading is not allowed
+    pub caller: Option<Caller>,
+}
+
+impl sysfs::Host for SysfsCtx {
+    fn sysfs_read(&mut self, path: String) -> wasmtime::Result<Result<Vec<u8>, String>> {
+        let Some(caller) = &self.caller else {
+            return Ok(Err("Sysfs reading is not allowed".to_string()));
+        };
+        let Some(reader) = crate::sysfs::global() else {
+            return Ok(Err("Sysfs reading is disabled".to_string()));
+        };
+        Ok(reader.read(&path, caller).map_err(|err| format!("{err:#}")))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut SysfsCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    sysfs::add_to_linker(l, f)
+}
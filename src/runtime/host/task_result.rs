@@ -0,0 +1,121 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::sync::{Arc, Mutex};
+
+use host_op_system::redact;
+use serde::Serialize;
+use wasmtime::component::Linker;
+
+use super::profiling::host::task_result::{self, Status};
+
+const MAX_SUMMARY_LEN: usize = 2048;
+const MAX_ARTIFACTS: usize = 16;
+const MAX_ARTIFACT_LEN: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultStatus {
+    Ok,
+    Warning,
+    Failed,
+}
+
+impl From<Status> for ResultStatus {
+    fn from(value: Status) -> Self {
+        match value {
+            Status::Ok => Self::Ok,
+            Status::Warning => Self::Warning,
+            Status::Failed => Self::Failed,
+        }
+    }
+}
+
+/// What a component reported about its run, unlike its exit status it is
+/// meant for the operator.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TaskResult {
+    pub status: ResultStatus,
+    pub summary: String,
+    pub artifacts: Vec<String>,
+}
+
+impl TaskResult {
+    /// The result goes along as request metadata, which must stay small.
+    fn new(status: Status, summary: String, artifacts: Vec<String>) -> Result<Self, String> {
+        if summary.len() > MAX_SUMMARY_LEN {
+            return Err(format!("Summary longer than {MAX_SUMMARY_LEN} bytes"));
+        }
+        if artifacts.len() > MAX_ARTIFACTS {
+            return Err(format!("More than {MAX_ARTIFACTS} artifacts"));
+        }
+        if artifacts.iter().any(|it| it.len() > MAX_ARTIFACT_LEN) {
+            return Err(format!(
+                "Artifact name longer than {MAX_ARTIFACT_LEN} bytes"
+            ));
+        }
+        // the summary leaves the host like exported data
+        let summary = match redact::global() {
+            Some(redactor) => redactor.redact(&summary).into_owned(),
+            None => summary,
+        };
+        Ok(Self {
+            status: status.into(),
+            summary,
+            artifacts,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TaskResultCtx {
+    /// read by the runtime once the run has finished
+    pub result: Arc<Mutex<Option<TaskResult>>>,
+}
+
+impl task_result::Host for TaskResultCtx {
+    fn set_result(
+        &mut self,
+        status: Status,
+        summary: String,
+        artifacts: Vec<String>,
+    ) -> wasmtime::Result<Result<(), String>> {
+        Ok(TaskResult::new(status, summary, artifacts)
+            .map(|it| *self.result.lock().unwrap() = Some(it)))
+    }
+}
+
+pub fn add_to_linker<T: Send>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut TaskResultCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    task_result::add_to_linker(l, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_ARTIFACTS, MAX_SUMMARY_LEN, ResultStatus, Status, TaskResult};
+
+    #[test]
+    fn test_task_result_limits() {
+        let result = TaskResult::new(Status::Warning, "2 disks degraded".to_string(), vec![]);
+        assert_eq!(result.unwrap().status, ResultStatus::Warning);
+
+        let summary = "x".repeat(MAX_SUMMARY_LEN + 1);
+        assert!(TaskResult::new(Status::Ok, summary, vec![]).is_err());
+
+        let artifacts = vec!["a.pprof".to_string(); MAX_ARTIFACTS + 1];
+        assert!(TaskResult::new(Status::Ok, String::new(), artifacts).is_err());
+    }
+}
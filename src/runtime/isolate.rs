@@ -0,0 +1,959 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+use std::{
+    fs,
+    io::{self, BufReader, Read, Write},
+    os::unix::process::ExitStatusExt,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use prost::{Message, Oneof};
+use psh_proto::Data;
+use wasmtime::{Engine, Trap, WasmBacktrace, component::Component};
+use wasmtime_wasi::I32Exit;
+
+use super::{
+    CancelToken, CpuTimeExceeded, MemoryLimits, PshEngineBuilder, ResultStatus, RunReport,
+    TaskResult, Usage,
+    data_export::{Ctx, DataExportCtx, DataExporter},
+    failure::{self, IsolatedTrap},
+    pipeline::{Artifact, Payload},
+    policy,
+    stdio::{LogOutput, Stream},
+    tenant::{ExportQuota, Tenant},
+    throttle::ExportRate,
+    timestamp::Timestamper,
+};
+use crate::{
+    config::{Config, ExportRateConfig, PreopenConfig},
+    services::rpc::FileMeta,
+    sysfs::Caller,
+};
+
+// frames are the task, exports and the outcome of a run, a corrupt length
+// must not make the daemon allocate that much
+const MAX_FRAME_LEN: usize = 256 << 20;
+const POLL: Duration = Duration::from_millis(50);
+// a child stuck in a host call past its time slice and grace is killed
+const KILL_MARGIN: Duration = Duration::from_secs(5);
+// event streams only the daemon follows, a child would never see an event
+const DAEMON_STREAMS: &[&str] = &[
+    "profiling:host/kernel-events",
+    "profiling:host/process-events",
+];
+
+/// What the daemon sends the child process to run.
+#[derive(Clone, PartialEq, Message)]
+pub struct Request {
+    #[prost(bytes = "vec", tag = "1")]
+    pub wasm: Vec<u8>,
+    #[prost(string, repeated, tag = "2")]
+    pub args: Vec<String>,
+    #[prost(message, repeated, tag = "3")]
+    pub envs: Vec<Pair>,
+    #[prost(string, optional, tag = "4")]
+    pub task_id: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub component: Option<String>,
+    /// as the daemon resolved it, the child doesn't read `[[tenants]]`
+    #[prost(message, optional, tag = "6")]
+    pub tenant: Option<TenantGrant>,
+    #[prost(string, tag = "7")]
+    pub instance_id: String,
+    /// in milliseconds
+    #[prost(uint64, tag = "8")]
+    pub time_slice: u64,
+    #[prost(uint64, tag = "9")]
+    pub max_cpu_ms: u64,
+    #[prost(uint64, optional, tag = "10")]
+    pub memory_limit: Option<u64>,
+    #[prost(message, repeated, tag = "11")]
+    pub rate_limits: Vec<RateLimit>,
+    #[prost(message, repeated, tag = "12")]
+    pub preopens: Vec<Preopen>,
+    #[prost(uint64, optional, tag = "13")]
+    pub seed: Option<u64>,
+    #[prost(message, repeated, tag = "14")]
+    pub input: Vec<Content>,
+    /// components run after this one, which get its output and artifact
+    #[prost(bool, tag = "15")]
+    pub pipeline: bool,
+    /// the daemon takes exports of this task
+    #[prost(bool, tag = "16")]
+    pub export: bool,
+    #[prost(bool, tag = "17")]
+    pub consume_fuel: bool,
+    /// `[engine.export_rate]` or the tenant's, 0 for no limit
+    #[prost(uint32, tag = "18")]
+    pub export_samples_per_sec: u32,
+    #[prost(uint64, tag = "19")]
+    pub export_bytes_per_sec: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Pair {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RateLimit {
+    #[prost(string, tag = "1")]
+    pub interface: String,
+    #[prost(uint32, tag = "2")]
+    pub per_sec: u32,
+}
+
+/// What a [`Tenant`] grants, its export rate is part of the request.
+#[derive(Clone, PartialEq, Message)]
+pub struct TenantGrant {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(bool, tag = "2")]
+    pub allow_perf_op: bool,
+    #[prost(bool, tag = "3")]
+    pub allow_system_op: bool,
+    #[prost(bool, tag = "4")]
+    pub allow_environ: bool,
+    #[prost(bool, tag = "5")]
+    pub allow_data_export: bool,
+    #[prost(bool, tag = "6")]
+    pub allow_capture: bool,
+    #[prost(bool, tag = "7")]
+    pub allow_probe: bool,
+    #[prost(bool, tag = "8")]
+    pub allow_ping: bool,
+    #[prost(bool, tag = "9")]
+    pub allow_msr: bool,
+    #[prost(bool, tag = "10")]
+    pub allow_sysfs: bool,
+    #[prost(bool, tag = "11")]
+    pub allow_integrity_baseline: bool,
+    #[prost(message, repeated, tag = "12")]
+    pub labels: Vec<Pair>,
+    /// bytes per minute
+    #[prost(uint64, optional, tag = "13")]
+    pub export_quota: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Preopen {
+    #[prost(string, tag = "1")]
+    pub host: String,
+    #[prost(string, optional, tag = "2")]
+    pub guest: Option<String>,
+    #[prost(bool, tag = "3")]
+    pub writable: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Content {
+    #[prost(string, tag = "1")]
+    pub content_type: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data: Vec<u8>,
+}
+
+/// What the child process sends back while and once it ran the task.
+#[derive(Clone, PartialEq, Message)]
+pub struct Frame {
+    #[prost(oneof = "Kind", tags = "1, 2")]
+    pub kind: Option<Kind>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+pub enum Kind {
+    #[prost(message, tag = "1")]
+    Export(Export),
+    #[prost(message, tag = "2")]
+    Done(Done),
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Export {
+    #[prost(message, optional, tag = "1")]
+    pub data: Option<Data>,
+    #[prost(message, optional, tag = "2")]
+    pub file: Option<File>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct File {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub content_type: String,
+    #[prost(message, repeated, tag = "3")]
+    pub attributes: Vec<Pair>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Done {
+    /// of `wasi:cli/exit`
+    #[prost(int32, optional, tag = "1")]
+    pub exit_code: Option<i32>,
+    /// set if the run was interrupted for exceeding it
+    #[prost(uint64, optional, tag = "2")]
+    pub cpu_limit_ms: Option<u64>,
+    #[prost(string, optional, tag = "3")]
+    pub error: Option<String>,
+    #[prost(uint64, optional, tag = "4")]
+    pub fuel_used: Option<u64>,
+    #[prost(message, optional, tag = "5")]
+    pub result: Option<Outcome>,
+    #[prost(message, repeated, tag = "6")]
+    pub output: Vec<Content>,
+    #[prost(string, optional, tag = "7")]
+    pub artifact_name: Option<String>,
+    #[prost(bytes = "vec", optional, tag = "8")]
+    pub artifact: Option<Vec<u8>>,
+    #[prost(uint64, tag = "9")]
+    pub peak_memory: u64,
+    #[prost(message, repeated, tag = "10")]
+    pub host_calls: Vec<HostCalls>,
+    /// e.g. `wasm trap: integer divide by zero`
+    #[prost(string, optional, tag = "11")]
+    pub trap: Option<String>,
+    /// innermost frame first
+    #[prost(string, repeated, tag = "12")]
+    pub backtrace: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct HostCalls {
+    #[prost(string, tag = "1")]
+    pub interface: String,
+    #[prost(uint64, tag = "2")]
+    pub calls: u64,
+}
+
+/// A [`TaskResult`].
+#[derive(Clone, PartialEq, Message)]
+pub struct Outcome {
+    /// 0 for ok, 1 for warning, 2 for failed
+    #[prost(uint32, tag = "1")]
+    pub status: u32,
+    #[prost(string, tag = "2")]
+    pub summary: String,
+    #[prost(string, repeated, tag = "3")]
+    pub artifacts: Vec<String>,
+}
+
+impl From<&Tenant> for TenantGrant {
+    fn from(value: &Tenant) -> Self {
+        Self {
+            name: value.name.clone(),
+            allow_perf_op: value.allow_perf_op,
+            allow_system_op: value.allow_system_op,
+            allow_environ: value.allow_environ,
+            allow_data_export: value.allow_data_export,
+            allow_capture: value.allow_capture,
+            allow_probe: value.allow_probe,
+            allow_ping: value.allow_ping,
+            allow_msr: value.allow_msr,
+            allow_sysfs: value.allow_sysfs,
+            allow_integrity_baseline: value.allow_integrity_baseline,
+            labels: pairs(value.labels.clone()),
+            export_quota: value.quota.as_ref().map(|it| it.bytes_per_min() as u64),
+        }
+    }
+}
+
+impl From<TenantGrant> for Tenant {
+    fn from(value: TenantGrant) -> Self {
+        Self {
+            name: value.name,
+            task_id_prefix: None,
+            allow_perf_op: value.allow_perf_op,
+            allow_system_op: value.allow_system_op,
+            allow_environ: value.allow_environ,
+            allow_data_export: value.allow_data_export,
+            allow_capture: value.allow_capture,
+            allow_probe: value.allow_probe,
+            allow_ping: value.allow_ping,
+            allow_msr: value.allow_msr,
+            allow_sysfs: value.allow_sysfs,
+            allow_integrity_baseline: value.allow_integrity_baseline,
+            labels: value
+                .labels
+                .into_iter()
+                .map(|it| (it.key, it.value))
+                .collect(),
+            quota: value.export_quota.map(|it| ExportQuota::new(it as usize)),
+            export_rate: None,
+        }
+    }
+}
+
+impl From<&PreopenConfig> for Preopen {
+    fn from(value: &PreopenConfig) -> Self {
+        Self {
+            host: value.host.clone(),
+            guest: value.guest.clone(),
+            writable: value.writable,
+        }
+    }
+}
+
+impl From<Preopen> for PreopenConfig {
+    fn from(value: Preopen) -> Self {
+        Self {
+            host: value.host,
+            guest: value.guest,
+            writable: value.writable,
+        }
+    }
+}
+
+impl From<&Payload> for Content {
+    fn from(value: &Payload) -> Self {
+        Self {
+            content_type: value.content_type.clone(),
+            data: value.data.clone(),
+        }
+    }
+}
+
+impl From<Content> for Payload {
+    fn from(value: Content) -> Self {
+        Self {
+            content_type: value.content_type,
+            data: value.data,
+        }
+    }
+}
+
+impl From<FileMeta> for File {
+    fn from(value: FileMeta) -> Self {
+        Self {
+            name: value.name,
+            content_type: value.content_type,
+            attributes: pairs(value.attributes),
+        }
+    }
+}
+
+impl From<File> for FileMeta {
+    fn from(value: File) -> Self {
+        Self {
+            name: value.name,
+            content_type: value.content_type,
+            attributes: value
+                .attributes
+                .into_iter()
+                .map(|it| (it.key, it.value))
+                .collect(),
+        }
+    }
+}
+
+impl From<&TaskResult> for Outcome {
+    fn from(value: &TaskResult) -> Self {
+        let status = match value.status {
+            ResultStatus::Ok => 0,
+            ResultStatus::Warning => 1,
+            ResultStatus::Failed => 2,
+        };
+        Self {
+            status,
+            summary: value.summary.clone(),
+            artifacts: value.artifacts.clone(),
+        }
+    }
+}
+
+impl From<Outcome> for TaskResult {
+    fn from(value: Outcome) -> Self {
+        let status = match value.status {
+            0 => ResultStatus::Ok,
+            1 => ResultStatus::Warning,
+            _ => ResultStatus::Failed,
+        };
+        Self {
+            status,
+            summary: value.summary,
+            artifacts: value.artifacts,
+        }
+    }
+}
+
+impl Done {
+    fn new(report: &RunReport) -> Self {
+        let error = report.result.as_ref().err();
+        Self {
+            exit_code: error
+                .and_then(|e| e.downcast_ref::<I32Exit>())
+                .map(|it| it.0),
+            cpu_limit_ms: error
+                .and_then(|e| e.downcast_ref::<CpuTimeExceeded>())
+                .map(|it| it.limit_ms),
+            error: error.map(|e| format!("{e:#}")),
+            trap: error
+                .and_then(|e| e.downcast_ref::<Trap>())
+                .map(ToString::to_string),
+            backtrace: error
+                .and_then(|e| e.downcast_ref::<WasmBacktrace>())
+                .map(failure::frames)
+                .unwrap_or_default(),
+            fuel_used: report.fuel_used,
+            peak_memory: report.usage.peak_memory,
+            host_calls: report
+                .usage
+                .host_calls
+                .iter()
+                .map(|(interface, calls)| HostCalls {
+                    interface: interface.clone(),
+                    calls: *calls,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// The result of the run in the child, as far as the daemon tells
+    /// results apart.
+    fn result(&self) -> Result<()> {
+        if let Some(code) = self.exit_code {
+            return Err(I32Exit(code).into());
+        }
+        if let Some(limit_ms) = self.cpu_limit_ms {
+            return Err(CpuTimeExceeded { limit_ms }.into());
+        }
+        match &self.error {
+            Some(e) if self.trap.is_some() || !self.backtrace.is_empty() => Err(IsolatedTrap {
+                message: e.clone(),
+                trap: self.trap.clone(),
+                backtrace: self.backtrace.clone(),
+            }
+            .into()),
+            Some(e) => Err(anyhow!("{e}")),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn pairs(pairs: Vec<(String, String)>) -> Vec<Pair> {
+    pairs
+        .into_iter()
+        .map(|(key, value)| Pair { key, value })
+        .collect()
+}
+
+fn write_frame(writer: &mut impl Write, message: &impl Message) -> io::Result<()> {
+    let len = message.encoded_len() as u32;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(&message.encode_to_vec())?;
+    writer.flush()
+}
+
+/// `None` once the writer has closed the stream.
+fn read_frame<M: Message + Default>(reader: &mut impl Read) -> Result<Option<M>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        bail!("Frame of {len} bytes exceeds {MAX_FRAME_LEN}");
+    }
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    Ok(Some(M::decode(buf.as_slice())?))
+}
+
+/// Where a run in a child process leaves what the component reported.
+pub struct Sinks<'a> {
+    pub ctx: Option<&'a Ctx>,
+    pub task_result: &'a Mutex<Option<TaskResult>>,
+    pub output: Option<&'a Mutex<Vec<Payload>>>,
+    pub artifact: Option<&'a Mutex<Option<Artifact>>>,
+}
+
+/// Runs `request` in a child process of this binary reading `config`,
+/// taking its exports as they come.
+///
+/// The child is asked to wrap up once `cancel` is cancelled or `stop` is
+/// set, and killed once it outlived its time slice and `grace`.
+pub fn run(
+    config: &str,
+    request: &Request,
+    sinks: &Sinks,
+    cancel: &CancelToken,
+    stop: Option<&AtomicBool>,
+    grace: Duration,
+) -> RunReport {
+    let failed = |e: anyhow::Error| RunReport {
+        result: Err(e),
+        fuel_used: None,
+        usage: Default::default(),
+    };
+    let mut child = match spawn(config) {
+        Ok(child) => child,
+        Err(e) => return failed(e),
+    };
+    let mut stdin = child.stdin.take();
+    let sent = match &mut stdin {
+        Some(stdin) => write_frame(stdin, request).context("Failed to send the task"),
+        None => Err(anyhow!("No stdin of the child process")),
+    };
+    if let Err(e) = sent {
+        let _ = child.kill();
+        let _ = child.wait();
+        return failed(e);
+    }
+    let Some(stdout) = child.stdout.take() else {
+        let _ = child.kill();
+        let _ = child.wait();
+        return failed(anyhow!("No stdout of the child process"));
+    };
+
+    let deadline = Duration::from_millis(request.time_slice)
+        .saturating_add(grace)
+        .saturating_add(KILL_MARGIN);
+    let start = Instant::now();
+    let done = thread::scope(|scope| {
+        let reader = scope.spawn(|| relay(stdout, sinks));
+        let mut asked_since = None;
+        while !reader.is_finished() {
+            let asked = cancel.is_cancelled() || stop.is_some_and(|it| it.load(Ordering::Relaxed));
+            if asked && asked_since.is_none() {
+                // the child cancels its run once its stdin is closed
+                stdin = None;
+                asked_since = Some(Instant::now());
+            }
+            let late = start.elapsed() > deadline
+                || asked_since.is_some_and(|it| it.elapsed() > grace.saturating_add(KILL_MARGIN));
+            if late {
+                let _ = child.kill();
+            }
+            thread::sleep(POLL);
+        }
+        reader
+            .join()
+            .unwrap_or_else(|_| Err(anyhow!("Relay of the child process panicked")))
+    });
+    if done.is_err() {
+        let _ = child.kill();
+    }
+    drop(stdin);
+    let status = child.wait();
+
+    match (done, status) {
+        (Ok(Some(done)), _) => settle(done, sinks),
+        (Ok(None), Ok(status)) => failed(anyhow!(
+            "Child process of the component exited with {}",
+            describe(status)
+        )),
+        (Ok(None), Err(e)) => failed(e.into()),
+        (Err(e), _) => failed(e),
+    }
+}
+
+fn spawn(config: &str) -> Result<Child> {
+    // the binary of the daemon, even if it was replaced since
+    Command::new("/proc/self/exe")
+        .args(["--config", config, "isolated"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("Failed to spawn the child process of the component")
+}
+
+fn describe(status: ExitStatus) -> String {
+    match status.signal() {
+        // SIGKILL
+        Some(9) => "SIGKILL, out of memory or late".to_string(),
+        Some(signal) => format!("signal {signal}"),
+        None => status.to_string(),
+    }
+}
+
+/// Takes the exports of the child until it sent how the run went.
+fn relay(stdout: impl Read, sinks: &Sinks) -> Result<Option<Done>> {
+    let mut stdout = BufReader::new(stdout);
+    while let Some(frame) = read_frame::<Frame>(&mut stdout)? {
+        match frame.kind {
+            Some(Kind::Export(Export {
+                data: Some(data),
+                file,
+            })) => {
+                let Some(ctx) = sinks.ctx else {
+                    continue;
+                };
+                if let Err(e) = ctx.schedule_file(data, file.map(Into::into)) {
+                    tracing::debug!("Dropped export of a child process: {e}");
+                }
+            }
+            Some(Kind::Done(done)) => return Ok(Some(done)),
+            _ => bail!("Unexpected frame of the child process"),
+        }
+    }
+    Ok(None)
+}
+
+fn settle(done: Done, sinks: &Sinks) -> RunReport {
+    if let Some(result) = done.result.clone() {
+        *sinks.task_result.lock().unwrap() = Some(result.into());
+    }
+    if let Some(output) = sinks.output {
+        output
+            .lock()
+            .unwrap()
+            .extend(done.output.iter().cloned().map(Into::into));
+    }
+    if let (Some(artifact), Some(name), Some(bytes)) =
+        (sinks.artifact, &done.artifact_name, &done.artifact)
+    {
+        *artifact.lock().unwrap() = Some(Artifact {
+            name: name.clone(),
+            bytes: bytes.clone(),
+        });
+    }
+    RunReport {
+        result: done.result(),
+        fuel_used: done.fuel_used,
+        usage: Usage {
+            peak_memory: done.peak_memory,
+            host_calls: done
+                .host_calls
+                .into_iter()
+                .map(|it| (it.interface, it.calls))
+                .collect(),
+        },
+    }
+}
+
+/// Fails if `component` imports an event stream only the daemon follows,
+/// rather than letting it wait for events that never come.
+pub fn check_imports(component: &Component, engine: &Engine) -> Result<()> {
+    let streams: Vec<_> = component
+        .component_type()
+        .imports(engine)
+        .map(|(name, _)| name)
+        .filter(|it| {
+            let name = it.split_once('@').map_or(*it, |(name, _)| name);
+            DAEMON_STREAMS.contains(&name)
+        })
+        .collect();
+    if !streams.is_empty() {
+        bail!(
+            "Component imports event streams not available in isolation: {}",
+            streams.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Runs the task the daemon sends on stdin with its own engine, the child
+/// side of [`run`].
+///
+/// Exports and the outcome go to stdout, stdout and stderr of the
+/// component are logged.
+pub fn serve(cfg: &Config) -> Result<()> {
+    // the kernel picks this process rather than the daemon when memory
+    // runs out
+    if let Err(e) = fs::write("/proc/self/oom_score_adj", "1000") {
+        tracing::debug!("Failed to adjust the OOM score: {e}");
+    }
+    let mut stdin = io::stdin();
+    let request: Request = read_frame(&mut stdin)?.context("No task sent")?;
+    let cancel = CancelToken::default();
+    thread::spawn({
+        let cancel = cancel.clone();
+        move || {
+            // closed by the daemon to cancel the run, or as it went away
+            let _ = io::copy(&mut stdin, &mut io::sink());
+            cancel.cancel();
+        }
+    });
+
+    let stdout = Arc::new(Mutex::new(io::stdout()));
+    let tenant_name = request.tenant.as_ref().map(|it| it.name.clone());
+    let tenant = request.tenant.clone().map(|it| Arc::new(Tenant::from(it)));
+    let ctx = match (&request.task_id, request.export) {
+        (Some(task_id), true) => {
+            let stdout = Arc::clone(&stdout);
+            let relay = Box::new(move |data: Data, file: Option<FileMeta>| {
+                let frame = Frame {
+                    kind: Some(Kind::Export(Export {
+                        data: Some(data),
+                        file: file.map(Into::into),
+                    })),
+                };
+                if let Err(e) = write_frame(&mut *stdout.lock().unwrap(), &frame) {
+                    tracing::warn!("Failed to relay export: {e}");
+                }
+            });
+            let data_export = &cfg.remote.rpc.data_export;
+            Some(Ctx {
+                instance_id: request.instance_id.clone(),
+                exporter: Arc::new(DataExporter::relay(task_id.clone(), relay)),
+                tenant: tenant.clone(),
+                normalize: data_export.normalize,
+                schemas: Default::default(),
+                timestamper: Arc::new(Timestamper::new(data_export.timestamp.clone())),
+                rate: ExportRate::new(&ExportRateConfig {
+                    samples_per_sec: request.export_samples_per_sec,
+                    bytes_per_sec: request.export_bytes_per_sec,
+                })
+                .map(Arc::new),
+            })
+        }
+        _ => None,
+    };
+    let artifact = request.pipeline.then(|| Arc::new(Mutex::new(None)));
+    let output = request.pipeline.then(|| Arc::new(Mutex::new(vec![])));
+    let data_export_ctx = DataExportCtx {
+        ctx,
+        artifact: artifact.clone(),
+        tenant: tenant_name.clone(),
+    };
+    let task_result = Arc::new(Mutex::new(None));
+    let policy = policy::global().map(|it| it.of(request.component.as_deref()));
+    let allow = |f: fn(&Tenant) -> bool| tenant.as_deref().is_none_or(f);
+    let component = request
+        .component
+        .as_deref()
+        .or(request.task_id.as_deref())
+        .unwrap_or("unnamed");
+    let output_of = |stream| LogOutput::new(component, request.task_id.as_deref(), stream, None);
+    let envs: Vec<_> = request
+        .envs
+        .iter()
+        .map(|it| (it.key.clone(), it.value.clone()))
+        .collect();
+    let preopens: Vec<PreopenConfig> = request.preopens.iter().cloned().map(Into::into).collect();
+    let mut builder = PshEngineBuilder::new()
+        .wasi_stdout(output_of(Stream::Stdout))
+        .wasi_stderr(output_of(Stream::Stderr))
+        .wasi_envs(&envs)
+        .wasi_args(&request.args)
+        .wasi_preopen_root(cfg.engine.preopen_root)
+        .wasi_preopens(&preopens)
+        .wasi_random_seed(request.seed)
+        .allow_perf_op(allow(|it| it.allow_perf_op))
+        .allow_system_op(allow(|it| it.allow_system_op))
+        .allow_environ_op(allow(|it| it.allow_environ))
+        .allow_capture_op(allow(|it| it.allow_capture))
+        .allow_probe_op(allow(|it| it.allow_probe))
+        .allow_ping_op(allow(|it| it.allow_ping))
+        .allow_msr_op(allow(|it| it.allow_msr))
+        .allow_integrity_baseline(allow(|it| it.allow_integrity_baseline))
+        .allow_sysfs_op(allow(|it| it.allow_sysfs).then(|| Caller {
+            task_id: request.task_id.clone(),
+            tenant: tenant_name.clone(),
+        }))
+        .allow_data_export_op(allow(|it| it.allow_data_export).then(|| data_export_ctx.clone()))
+        .task_result(Some(Arc::clone(&task_result)))
+        .pipeline(
+            request.input.iter().cloned().map(Into::into).collect(),
+            output.clone(),
+        )
+        .tenant(tenant_name)
+        .isolated(true)
+        .log_source(component, request.task_id.as_deref())
+        .memory_limits({
+            let limits = MemoryLimits {
+                native: cfg.engine.memory_limit,
+                interpreted: cfg.engine.interpreted_memory_limit,
+            };
+            if request.task_id.is_some() {
+                limits.distrusted()
+            } else {
+                limits
+            }
+        })
+        .cancel_token(cancel, Duration::from_millis(cfg.engine.cancel_grace_ms))
+        .interface_policy(policy)
+        .wasi_sockets(policy.and_then(|it| it.sockets.as_ref()))
+        .wasi_http(policy.and_then(|it| it.http.as_ref()))
+        .max_cpu_ms(request.max_cpu_ms)
+        .host_call_rate_limits(
+            request
+                .rate_limits
+                .iter()
+                .map(|it| (it.interface.clone(), it.per_sec))
+                .collect(),
+        )
+        .consume_fuel(request.consume_fuel)
+        .backtrace_details(cfg.engine.backtrace_details);
+    if let Some(limit) = request.memory_limit {
+        builder = builder.wasm_memory_limit(limit as usize);
+    }
+    // drives the async WASI and data export calls, the child runs nothing else
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let report = match builder.build().context("Failed to build PshEngine.") {
+        Ok(engine) => rt.block_on(engine.run_metered(&request.wasm, request.time_slice)),
+        Err(e) => RunReport {
+            result: Err(e),
+            fuel_used: None,
+            usage: Default::default(),
+        },
+    };
+
+    let mut done = Done::new(&report);
+    done.result = task_result.lock().unwrap().as_ref().map(Into::into);
+    if let Some(output) = output {
+        done.output = output.lock().unwrap().iter().map(Into::into).collect();
+    }
+    if let Some(Artifact { name, bytes }) = artifact.and_then(|it| it.lock().unwrap().take()) {
+        done.artifact_name = Some(name);
+        done.artifact = Some(bytes);
+    }
+    let frame = Frame {
+        kind: Some(Kind::Done(done)),
+    };
+    write_frame(&mut *stdout.lock().unwrap(), &frame)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use prost::Message;
+
+    use super::{Done, Frame, Kind, MAX_FRAME_LEN, Outcome, TenantGrant, read_frame, write_frame};
+    use crate::{
+        config::TenantConfig,
+        ledger::RunStatus,
+        runtime::{
+            CpuTimeExceeded, ResultStatus, TaskResult, failure::FailureReport, tenant::Tenant,
+        },
+    };
+
+    #[test]
+    fn test_tenant_grant() {
+        let tenant = Tenant::from(&TenantConfig {
+            name: "acme".to_string(),
+            task_id_prefix: Some("acme-".to_string()),
+            allow_perf_op: false,
+            allow_probe: true,
+            export_quota: Some(1024),
+            labels: [("team".to_string(), "infra".to_string())].into(),
+            ..Default::default()
+        });
+        let grant = TenantGrant::from(&tenant);
+        let sent = TenantGrant::decode(grant.encode_to_vec().as_slice()).unwrap();
+        let child = Tenant::from(sent);
+        assert_eq!(child.name, "acme");
+        assert!(!child.allow_perf_op);
+        assert!(child.allow_system_op);
+        assert!(child.allow_probe);
+        assert!(!child.allow_ping);
+        assert_eq!(child.labels, tenant.labels);
+        assert_eq!(child.quota.map(|it| it.bytes_per_min()), Some(1024));
+    }
+
+    #[test]
+    fn test_frames_round_trip() {
+        let frames = [
+            Frame {
+                kind: Some(Kind::Done(Done {
+                    exit_code: Some(3),
+                    ..Default::default()
+                })),
+            },
+            Frame { kind: None },
+        ];
+        let mut buf = vec![];
+        for frame in &frames {
+            write_frame(&mut buf, frame).unwrap();
+        }
+        let mut reader = buf.as_slice();
+        for frame in &frames {
+            assert_eq!(
+                read_frame::<Frame>(&mut reader).unwrap().as_ref(),
+                Some(frame)
+            );
+        }
+        assert_eq!(read_frame::<Frame>(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_oversized_frames_are_refused() {
+        let len = (MAX_FRAME_LEN as u32 + 1).to_le_bytes();
+        assert!(read_frame::<Frame>(&mut len.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_done_keeps_the_status() {
+        let status = |done: &Done| RunStatus::of(&done.result());
+        assert_eq!(status(&Done::default()), RunStatus::Success);
+        let exited = Done {
+            exit_code: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(status(&exited), RunStatus::Success);
+        let timed_out = Done {
+            cpu_limit_ms: Some(100),
+            error: Some("CPU time limit of 100 ms exceeded".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(status(&timed_out), RunStatus::TimedOut);
+        assert!(
+            timed_out
+                .result()
+                .unwrap_err()
+                .downcast_ref::<CpuTimeExceeded>()
+                .is_some()
+        );
+        let failed = Done {
+            error: Some("boom".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(status(&failed), RunStatus::Failed);
+    }
+
+    #[test]
+    fn test_done_keeps_the_trap() {
+        let trapped = Done {
+            error: Some("error while executing: wasm trap: integer divide by zero".to_string()),
+            trap: Some("wasm trap: integer divide by zero".to_string()),
+            backtrace: vec!["cpu-top.wasm!parse_line at src/lib.rs:12:9".to_string()],
+            ..Default::default()
+        };
+        let result = trapped.result();
+        assert_eq!(RunStatus::of(&result), RunStatus::Failed);
+        let report =
+            FailureReport::of("cpu-top", Some("42"), &result, Duration::from_secs(1)).unwrap();
+        assert_eq!(report.trap, trapped.trap);
+        assert_eq!(report.backtrace, trapped.backtrace);
+        assert_eq!(report.message, trapped.error.unwrap());
+    }
+
+    #[test]
+    fn test_outcome_round_trips() {
+        let result = TaskResult {
+            status: ResultStatus::Warning,
+            summary: "disk almost full".to_string(),
+            artifacts: vec!["df.json".to_string()],
+        };
+        assert_eq!(TaskResult::from(Outcome::from(&result)), result);
+    }
+}
@@ -13,78 +13,326 @@
 // see <https://www.gnu.org/licenses/>.
 
 mod builder;
+pub mod cache;
+mod cancel;
 mod data_export;
 mod engine;
+pub mod exporter;
+mod failure;
+mod guest;
+mod host;
+pub mod isolate;
+pub mod pipeline;
+pub mod policy;
+mod pool;
+mod probe;
+pub mod scheduler;
+mod schema;
 mod state;
+mod stdio;
+pub mod supervisor;
+mod tenant;
+mod throttle;
+mod timestamp;
+pub mod validate;
+pub mod watcher;
 
 #[cfg(test)]
 mod tests;
 
 use std::{
+    collections::BTreeMap,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    mem,
+    panic::{self, AssertUnwindSafe},
     sync::{
         Arc, Mutex,
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         mpsc::{Receiver, Sender, channel},
     },
     thread,
     thread::JoinHandle,
+    time::Duration,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow};
 pub use builder::PshEngineBuilder;
-use chrono::{DateTime, Utc};
+pub use cancel::{CancelToken, Cancellations};
+use chrono::{DateTime, TimeZone, Utc};
+pub use data_export::ExportTasks;
 use data_export::{Ctx, DataExportCtx, DataExporter};
-pub use engine::PshEngine;
+pub use engine::{CpuTimeExceeded, PshEngine, RunReport, Usage};
+use failure::FailureReport;
+use guest::Language;
+pub use guest::MemoryLimits;
+pub use host::task_result::{ResultStatus, TaskResult};
+use pipeline::{Artifact, Payload, Pipelines};
+use pool::EnginePool;
+use psh_proto::{Data, DataType};
 pub use state::PshState;
+use stdio::{Forwarder, LogOutput, Stream};
+use tenant::Tenant;
+pub use tenant::Tenants;
+use throttle::ExportRates;
+use timestamp::Timestamper;
+use tokio::runtime::Handle;
 
-use crate::services::rpc::RpcClient;
+use crate::{
+    config::{
+        ComponentConfig, EngineConfig, ExportRateConfig, PreopenConfig, StdioMode, TimestampConfig,
+    },
+    ledger::{self, Run, RunStatus},
+    services::{
+        component_stats::{self, RunStats},
+        export_stats::{self, Backend},
+        health,
+        rpc::RpcClient,
+    },
+    store,
+    sysfs::Caller,
+};
 
 pub struct Task {
     pub id: Option<String>,
     pub wasm_component: Vec<u8>,
     pub wasm_component_args: Vec<String>,
+    /// set on top of the environment of psh
+    pub wasm_component_envs: Vec<(String, String)>,
     pub end_time: DateTime<Utc>,
+    /// explicit tenant, see [`Tenants::resolve`]
+    pub tenant: Option<String>,
+    /// cleared once the task has finished
+    pub running: Option<Arc<AtomicBool>>,
+    /// interrupts the task once set
+    pub stop: Option<Arc<AtomicBool>>,
+    /// in milliseconds, the limit of the runtime if absent
+    pub max_cpu_ms: Option<u64>,
+    /// in bytes, picked by the language of the component if absent
+    pub memory_limit: Option<usize>,
+    /// calls per second by host interface, on top of the runtime's, it may
+    /// only tighten them
+    pub rate_limits: BTreeMap<String, u32>,
+    /// reuses the engine and pre-instantiated component of earlier runs,
+    /// if the runtime keeps a warm pool
+    pub warm: bool,
+    /// name of the local component, whose dependents run once it succeeded
+    pub component: Option<String>,
+    /// host directories the component may access
+    pub preopens: Vec<PreopenConfig>,
+    /// of wasi:random/insecure, the OS's randomness if absent
+    pub seed: Option<u64>,
+    /// gets the status once the task has finished
+    pub done: Option<Sender<RunStatus>>,
+    /// what the component it runs after emitted through
+    /// `profiling:host/pipeline`
+    pub input: Vec<Payload>,
 }
 
-pub struct TaskRuntime {
+impl Task {
+    /// What the runs of the task are accounted to: the name of the local
+    /// component, a hash of the wasm for tasks of the server, whose
+    /// arguments start with their id, so accounts don't grow with tasks.
+    pub fn program(&self) -> String {
+        if let Some(component) = &self.component {
+            return component.clone();
+        }
+        let mut hasher = DefaultHasher::new();
+        self.wasm_component.hash(&mut hasher);
+        format!("wasm-{:016x}", hasher.finish())
+    }
+
+    /// A run of a component configured on this host, without an end time.
+    pub fn local(component: &ComponentConfig) -> Result<Self> {
+        let mut args = vec![component.path.clone()];
+        args.extend(component.args.iter().cloned());
+        Ok(Self {
+            id: None,
+            wasm_component: fs::read(&component.path)
+                .with_context(|| format!("Failed to read {}", component.path))?,
+            wasm_component_args: args,
+            wasm_component_envs: component
+                .env
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            end_time: Utc.with_ymd_and_hms(3000, 1, 1, 1, 1, 1).unwrap(),
+            tenant: component.tenant.clone(),
+            running: None,
+            stop: None,
+            max_cpu_ms: component.max_cpu_ms,
+            memory_limit: component.memory_limit,
+            rate_limits: component.rate_limits.clone(),
+            warm: false,
+            component: Some(component.name().to_string()),
+            preopens: component.preopens.clone(),
+            seed: component.seed,
+            done: None,
+            input: vec![],
+        })
+    }
+}
+
+/// A task of the server that has finished, to report to it.
+pub struct FinishedTask {
+    pub id: String,
+    pub status: RunStatus,
+    /// set by the component through `profiling:host/task-result`
+    pub result: Option<TaskResult>,
+}
+
+/// Schedules tasks on a [`TaskRuntime`] from other threads.
+#[derive(Clone)]
+pub struct TaskSender {
     tx: Sender<Task>,
-    rx: Option<Receiver<Task>>,
+    // tasks of the server, local components don't keep it busy
     len: Arc<AtomicUsize>,
-    finished_task_id: Arc<Mutex<Vec<String>>>,
+}
+
+impl TaskSender {
+    pub fn schedule(&self, task: Task) -> Result<()> {
+        if task.id.is_some() {
+            self.len.fetch_add(1, Ordering::Release);
+        }
+        self.tx.send(task)?;
+        Ok(())
+    }
+}
+
+/// Runs every task on its own thread with its own engine and store, so
+/// several components run side by side.
+pub struct TaskRuntime {
+    sender: TaskSender,
+    rx: Option<Receiver<Task>>,
+    finished_tasks: Arc<Mutex<Vec<FinishedTask>>>,
+    tenants: Arc<Mutex<Arc<Tenants>>>,
+    memory_limits: MemoryLimits,
+    max_cpu_ms: u64,
+    rate_limits: BTreeMap<String, u32>,
+    export_rate: ExportRateConfig,
+    engines: Arc<EnginePool>,
+    warm_pool: bool,
+    pipelines: Arc<Pipelines>,
+    // exporters of the running components
+    exporters: Arc<Mutex<Vec<Arc<DataExporter>>>>,
+    export_tasks: ExportTasks,
+    cancellations: Cancellations,
+    cancel_grace: Duration,
+    preopen_root: bool,
+    stdio: StdioMode,
+    forward_stdio: bool,
+    // config file of the child processes tasks run in
+    isolate: Option<String>,
+    telemetry: bool,
+    backtrace_details: bool,
 }
 
 impl TaskRuntime {
-    pub fn new() -> Result<Self> {
+    /// Limits of `engine` apply to tasks without their own.
+    pub fn new(tenants: Tenants, engine: &EngineConfig) -> Result<Self> {
         let (tx, rx) = channel();
 
         Ok(Self {
-            tx,
+            sender: TaskSender {
+                tx,
+                len: Arc::new(AtomicUsize::new(0)),
+            },
             rx: Some(rx),
-            len: Arc::new(AtomicUsize::new(0)),
-            finished_task_id: Arc::new(Mutex::new(vec![])),
+            finished_tasks: Arc::new(Mutex::new(vec![])),
+            tenants: Arc::new(Mutex::new(Arc::new(tenants))),
+            memory_limits: MemoryLimits {
+                native: engine.memory_limit,
+                interpreted: engine.interpreted_memory_limit,
+            },
+            max_cpu_ms: engine.max_cpu_ms,
+            rate_limits: engine.rate_limits.clone(),
+            export_rate: engine.export_rate,
+            engines: Arc::new(EnginePool::default()),
+            warm_pool: engine.warm_pool,
+            pipelines: Arc::new(Pipelines::default()),
+            exporters: Arc::new(Mutex::new(vec![])),
+            export_tasks: ExportTasks::default(),
+            cancellations: Cancellations::default(),
+            cancel_grace: Duration::from_millis(engine.cancel_grace_ms),
+            preopen_root: engine.preopen_root,
+            stdio: engine.stdio,
+            forward_stdio: engine.forward_stdio,
+            isolate: None,
+            telemetry: engine.telemetry,
+            backtrace_details: engine.backtrace_details,
         })
     }
 
     pub fn schedule(&self, task: Task) -> Result<()> {
-        self.len.fetch_add(1, Ordering::Release);
-        self.tx.send(task)?;
-        Ok(())
+        self.sender.schedule(task)
+    }
+
+    pub fn sender(&self) -> TaskSender {
+        self.sender.clone()
     }
 
+    /// No task of the server is running.
     pub fn is_idle(&self) -> bool {
-        let len = self.len.load(Ordering::Acquire);
+        let len = self.sender.len.load(Ordering::Acquire);
         len == 0
     }
 
-    pub fn finished_task_id(&self) -> Option<String> {
-        self.finished_task_id.lock().unwrap().pop()
+    /// A finished task of the server.
+    pub fn finished_task(&self) -> Option<FinishedTask> {
+        self.finished_tasks.lock().unwrap().pop()
     }
-    #[allow(clippy::significant_drop_tightening)]
+
+    /// Replaces the tenants, tasks scheduled from now on are resolved
+    /// against the new ones.
+    pub fn set_tenants(&self, tenants: Tenants) {
+        *self.tenants.lock().unwrap() = Arc::new(tenants);
+    }
+
+    /// Components to run after others, must be set before the runtime is
+    /// spawned.
+    pub fn set_pipelines(&mut self, pipelines: Pipelines) {
+        self.pipelines = Arc::new(pipelines);
+    }
+
+    /// Runs every task in a child process of its own reading `config`, see
+    /// `[engine] isolate`, must be set before the runtime is spawned.
+    pub fn set_isolation(&mut self, config: String) {
+        self.isolate = Some(config);
+    }
+
+    /// Sends the data buffered by the running components without waiting
+    /// for the watermark.
+    pub fn flush(&self) {
+        for exporter in self.exporters.lock().unwrap().iter() {
+            exporter.flush();
+        }
+    }
+
+    /// Exporters of finished tasks still sending their data, e.g. on
+    /// shutdown.
+    pub fn export_tasks(&self) -> ExportTasks {
+        self.export_tasks.clone()
+    }
+
+    /// Cancels running tasks from other threads, e.g. on shutdown.
+    pub fn cancellations(&self) -> Cancellations {
+        self.cancellations.clone()
+    }
+
+    /// How long cancelled tasks may take to wrap up.
+    pub const fn cancel_grace(&self) -> Duration {
+        self.cancel_grace
+    }
+
+    /// Runs scheduled tasks until the runtime is dropped, the handle
+    /// returns once all of them have finished.
     pub fn spawn(
         &mut self,
         rpc_client: Option<RpcClient>,
         data_export_buf_size: usize,
         data_export_buf_watermark: usize,
+        data_export_normalize: bool,
+        data_export_timestamp: TimestampConfig,
         instance_id: String,
     ) -> Result<JoinHandle<()>> {
         let rx = self
@@ -92,56 +340,496 @@ impl TaskRuntime {
             .take()
             .map_or_else(|| panic!("twice spawned"), |rx| rx);
 
-        let envs: Vec<(String, String)> = std::env::vars().collect();
-
-        let len = self.len.clone();
-        let finished_task_id = self.finished_task_id.clone();
+        // exporters run on it, the caller is in the async part of the daemon
+        let rt = Handle::try_current().context("No tokio runtime for the task runtime")?;
+        let forwarder = rpc_client
+            .clone()
+            .filter(|_| self.stdio == StdioMode::Log && self.forward_stdio)
+            .map(|client| Forwarder::spawn(rt.clone(), client, instance_id.clone()));
+        let worker = Worker {
+            rt,
+            rpc_client,
+            data_export_buf_size,
+            data_export_buf_watermark,
+            data_export_normalize,
+            timestamper: Arc::new(Timestamper::new(data_export_timestamp)),
+            instance_id,
+            envs: std::env::vars().collect(),
+            len: self.sender.len.clone(),
+            finished_tasks: self.finished_tasks.clone(),
+            tenants: self.tenants.clone(),
+            memory_limits: self.memory_limits,
+            max_cpu_ms: self.max_cpu_ms,
+            rate_limits: self.rate_limits.clone(),
+            export_rate: self.export_rate,
+            export_rates: Arc::new(ExportRates::default()),
+            engines: Arc::clone(&self.engines),
+            warm_pool: self.warm_pool,
+            pipelines: Arc::clone(&self.pipelines),
+            exporters: self.exporters.clone(),
+            export_tasks: self.export_tasks.clone(),
+            cancellations: self.cancellations.clone(),
+            cancel_grace: self.cancel_grace,
+            preopen_root: self.preopen_root,
+            stdio: self.stdio,
+            forwarder,
+            isolate: self.isolate.clone(),
+            telemetry: self.telemetry,
+            backtrace_details: self.backtrace_details,
+        };
         let handle = thread::spawn(move || {
+            health::global().set_engine_running(true);
+            let mut running: Vec<JoinHandle<()>> = vec![];
             while let Ok(task) = rx.recv() {
-                let mut envs = envs.clone();
-                let task_time_slice = {
-                    let delta = task.end_time.timestamp_millis() - Utc::now().timestamp_millis();
-                    delta.max(0) as u64
-                };
-                envs.push(("TASK_TIME_SLICE".to_string(), task_time_slice.to_string()));
-
-                #[expect(clippy::significant_drop_in_scrutinee)]
-                let ctx = match (rpc_client.clone(), task.id.clone()) {
-                    (Some(rpc_client), Some(task_id)) => Some(Ctx {
-                        instance_id: instance_id.clone(),
-                        exporter: Arc::new(DataExporter::new(
-                            data_export_buf_size,
-                            data_export_buf_watermark,
-                            task_id,
-                            rpc_client,
-                        )),
-                    }),
-                    _ => None,
-                };
-                let data_export_ctx = DataExportCtx { ctx };
-                let engine = PshEngineBuilder::new()
-                    .wasi_inherit_stdio()
-                    .wasi_envs(&envs)
-                    .wasi_args(&task.wasm_component_args)
-                    .allow_perf_op(true)
-                    .allow_system_op(true)
-                    .allow_data_export_op(Some(data_export_ctx.clone()))
-                    .build()
-                    .context("Failed to build PshEngine.");
-
-                match engine {
-                    Ok(o) => {
-                        let _ = o.run(&task.wasm_component, task_time_slice);
+                running.retain(|it| !it.is_finished());
+                let (id, running, done) =
+                    (task.id.clone(), task.running.clone(), task.done.clone());
+                let task_worker = worker.clone();
+                let spawned = thread::Builder::new()
+                    .name("psh-task".to_string())
+                    .spawn(move || task_worker.run(task));
+                match spawned {
+                    Ok(handle) => running.push(handle),
+                    Err(e) => {
+                        eprintln!("Failed to spawn task thread: {e}");
+                        worker.finish(id, running, done, RunStatus::Failed, None);
                     }
-                    Err(e) => eprintln!("{}", e),
-                };
-                if let Some(id) = task.id {
-                    finished_task_id.lock().unwrap().push(id);
                 }
-                len.fetch_sub(1, Ordering::Release);
             }
+            for handle in running {
+                let _ = handle.join();
+            }
+            health::global().set_engine_running(false);
         });
 
         Ok(handle)
     }
 }
+
+/// Fails a run that panicked, in a host function most likely, like any
+/// other so the task is still finished, recorded and its exports flushed.
+fn unwound(run: impl FnOnce() -> RunReport) -> RunReport {
+    panic::catch_unwind(AssertUnwindSafe(run)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|it| (*it).to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        RunReport {
+            result: Err(anyhow!("Component run panicked: {message}")),
+            fuel_used: None,
+            usage: Default::default(),
+        }
+    })
+}
+
+/// Merges the rate limits of a task into the runtime's, keeping the lower
+/// one where both limit an interface so a component cannot raise them.
+fn tightest(engine: &BTreeMap<String, u32>, task: &BTreeMap<String, u32>) -> BTreeMap<String, u32> {
+    let mut limits = engine.clone();
+    for (interface, per_sec) in task {
+        limits
+            .entry(interface.clone())
+            .and_modify(|it| *it = (*it).min(*per_sec))
+            .or_insert(*per_sec);
+    }
+    limits
+}
+
+/// What a task thread needs, cloned for every task.
+#[derive(Clone)]
+struct Worker {
+    rt: Handle,
+    rpc_client: Option<RpcClient>,
+    data_export_buf_size: usize,
+    data_export_buf_watermark: usize,
+    data_export_normalize: bool,
+    timestamper: Arc<Timestamper>,
+    instance_id: String,
+    envs: Vec<(String, String)>,
+    len: Arc<AtomicUsize>,
+    finished_tasks: Arc<Mutex<Vec<FinishedTask>>>,
+    tenants: Arc<Mutex<Arc<Tenants>>>,
+    memory_limits: MemoryLimits,
+    max_cpu_ms: u64,
+    rate_limits: BTreeMap<String, u32>,
+    export_rate: ExportRateConfig,
+    // by component, shared by its runs
+    export_rates: Arc<ExportRates>,
+    engines: Arc<EnginePool>,
+    warm_pool: bool,
+    pipelines: Arc<Pipelines>,
+    exporters: Arc<Mutex<Vec<Arc<DataExporter>>>>,
+    export_tasks: ExportTasks,
+    cancellations: Cancellations,
+    cancel_grace: Duration,
+    preopen_root: bool,
+    stdio: StdioMode,
+    forwarder: Option<Forwarder>,
+    isolate: Option<String>,
+    // exports the resources of every run of a task along with its data
+    telemetry: bool,
+    backtrace_details: bool,
+}
+
+impl Worker {
+    fn finish(
+        &self,
+        task_id: Option<String>,
+        running: Option<Arc<AtomicBool>>,
+        done: Option<Sender<RunStatus>>,
+        status: RunStatus,
+        result: Option<TaskResult>,
+    ) {
+        if let Some(id) = task_id {
+            self.finished_tasks
+                .lock()
+                .unwrap()
+                .push(FinishedTask { id, status, result });
+            self.len.fetch_sub(1, Ordering::Release);
+        }
+        if let Some(running) = running {
+            running.store(false, Ordering::Release);
+        }
+        if let Some(done) = done {
+            // the supervisor may be gone on shutdown
+            let _ = done.send(status);
+        }
+    }
+
+    /// Lets the server know a component failed, if there is one.
+    fn report_failure(&self, failure: &FailureReport) {
+        let Some(mut client) = self.rpc_client.clone() else {
+            return;
+        };
+        let req = failure.to_export_req(&self.instance_id);
+        let stats = export_stats::global(Backend::Rpc);
+        stats.accept(1);
+        let component = failure.component.clone();
+        self.rt.spawn(async move {
+            if let Err(e) = client.export_data_accounted(req, None, stats).await {
+                tracing::warn!("Failed to report the failure of {component}: {e}");
+            }
+        });
+    }
+
+    #[allow(clippy::significant_drop_tightening)]
+    fn run(&self, task: Task) {
+        let resolved = self.tenants.lock().unwrap().resolve(&task);
+        let tenant = match resolved {
+            Ok(tenant) => tenant,
+            Err(e) => {
+                eprintln!("{}", e);
+                self.finish(task.id, task.running, task.done, RunStatus::Failed, None);
+                return;
+            }
+        };
+
+        let program = task.program();
+        let mut envs = self.envs.clone();
+        envs.extend(task.wasm_component_envs.iter().cloned());
+        let task_time_slice = {
+            let delta = task.end_time.timestamp_millis() - Utc::now().timestamp_millis();
+            delta.max(0) as u64
+        };
+        envs.push(("TASK_TIME_SLICE".to_string(), task_time_slice.to_string()));
+
+        let export_rate = tenant
+            .as_ref()
+            .and_then(|it| it.export_rate)
+            .unwrap_or(self.export_rate);
+        #[expect(clippy::significant_drop_in_scrutinee)]
+        let ctx = match (self.rpc_client.clone(), task.id.clone()) {
+            (Some(rpc_client), Some(task_id)) => Some(Ctx {
+                instance_id: self.instance_id.clone(),
+                exporter: Arc::new(DataExporter::new(
+                    &self.rt,
+                    &self.export_tasks,
+                    self.data_export_buf_size,
+                    self.data_export_buf_watermark,
+                    task_id,
+                    rpc_client,
+                )),
+                tenant: tenant.clone(),
+                normalize: self.data_export_normalize,
+                schemas: Default::default(),
+                timestamper: self.timestamper.clone(),
+                // an isolated run is throttled in its child process too, so
+                // it learns it was, but only here along with other runs
+                rate: self.export_rates.of(&program, &export_rate),
+            }),
+            _ => None,
+        };
+        let exporter = ctx.as_ref().map(|it| Arc::clone(&it.exporter));
+        if let Some(exporter) = &exporter {
+            self.exporters.lock().unwrap().push(Arc::clone(exporter));
+        }
+        let dependents = task
+            .component
+            .as_deref()
+            .map_or(&[][..], |it| self.pipelines.dependents(it));
+        let artifact = (!dependents.is_empty()).then(|| Arc::new(Mutex::new(None)));
+        let output = (!dependents.is_empty()).then(|| Arc::new(Mutex::new(vec![])));
+        let data_export_ctx = DataExportCtx {
+            ctx,
+            artifact: artifact.clone(),
+            tenant: tenant.as_ref().map(|it| it.name.clone()),
+        };
+        let task_result = Arc::new(Mutex::new(None));
+        let policy = policy::global().map(|it| it.of(task.component.as_deref()));
+        let cancel = self.cancellations.register(task.id.clone());
+        let ledger = ledger::global();
+        let seed = task.seed.or_else(|| {
+            ledger
+                .filter(|it| it.record_seed())
+                .and_then(|_| ledger::new_seed())
+        });
+        let allow = |f: fn(&Tenant) -> bool| tenant.as_deref().is_none_or(f);
+        let rate_limits = tightest(&self.rate_limits, &task.rate_limits);
+        let component = task
+            .component
+            .as_deref()
+            .or(task.id.as_deref())
+            .unwrap_or("unnamed");
+        let start_ms = Utc::now().timestamp_millis();
+        let report = if let Some(config) = &self.isolate {
+            let request = isolate::Request {
+                wasm: task.wasm_component,
+                args: task.wasm_component_args.clone(),
+                envs: isolate::pairs(envs),
+                task_id: task.id.clone(),
+                component: task.component.clone(),
+                tenant: tenant.as_deref().map(Into::into),
+                instance_id: self.instance_id.clone(),
+                time_slice: task_time_slice,
+                max_cpu_ms: task.max_cpu_ms.unwrap_or(self.max_cpu_ms),
+                memory_limit: task.memory_limit.map(|it| it as u64),
+                rate_limits: rate_limits
+                    .iter()
+                    .map(|(interface, per_sec)| isolate::RateLimit {
+                        interface: interface.clone(),
+                        per_sec: *per_sec,
+                    })
+                    .collect(),
+                preopens: task.preopens.iter().map(Into::into).collect(),
+                seed,
+                input: task.input.iter().map(Into::into).collect(),
+                pipeline: !dependents.is_empty(),
+                export: data_export_ctx.ctx.is_some(),
+                consume_fuel: ledger.is_some_and(|it| it.meter_fuel()),
+                export_samples_per_sec: export_rate.samples_per_sec,
+                export_bytes_per_sec: export_rate.bytes_per_sec,
+            };
+            let sinks = isolate::Sinks {
+                ctx: data_export_ctx.ctx.as_ref(),
+                task_result: &task_result,
+                output: output.as_deref(),
+                artifact: artifact.as_deref(),
+            };
+            unwound(|| {
+                isolate::run(
+                    config,
+                    &request,
+                    &sinks,
+                    &cancel,
+                    task.stop.as_deref(),
+                    self.cancel_grace,
+                )
+            })
+        } else {
+            let mut builder = PshEngineBuilder::new();
+            builder = match self.stdio {
+                StdioMode::Inherit => builder.wasi_inherit_stdio(),
+                StdioMode::Log => {
+                    let output = |stream| {
+                        LogOutput::new(
+                            component,
+                            task.id.as_deref(),
+                            stream,
+                            self.forwarder.clone(),
+                        )
+                    };
+                    builder
+                        .wasi_stdout(output(Stream::Stdout))
+                        .wasi_stderr(output(Stream::Stderr))
+                }
+            };
+            builder = builder
+                .wasi_envs(&envs)
+                .wasi_args(&task.wasm_component_args)
+                .wasi_preopen_root(self.preopen_root)
+                .wasi_preopens(&task.preopens)
+                .wasi_random_seed(seed)
+                .allow_perf_op(allow(|it| it.allow_perf_op))
+                .allow_system_op(allow(|it| it.allow_system_op))
+                .allow_environ_op(allow(|it| it.allow_environ))
+                .allow_capture_op(allow(|it| it.allow_capture))
+                .allow_probe_op(allow(|it| it.allow_probe))
+                .allow_ping_op(allow(|it| it.allow_ping))
+                .allow_msr_op(allow(|it| it.allow_msr))
+                .allow_integrity_baseline(allow(|it| it.allow_integrity_baseline))
+                .allow_sysfs_op(allow(|it| it.allow_sysfs).then(|| Caller {
+                    task_id: task.id.clone(),
+                    tenant: tenant.as_ref().map(|it| it.name.clone()),
+                }))
+                .allow_data_export_op(
+                    allow(|it| it.allow_data_export).then(|| data_export_ctx.clone()),
+                )
+                .task_result(Some(Arc::clone(&task_result)))
+                .pipeline(task.input, output.clone())
+                .tenant(tenant.as_ref().map(|it| it.name.clone()))
+                .log_source(component, task.id.as_deref())
+                .store_namespace(task.component.as_deref().map(|component| {
+                    store::namespace(tenant.as_ref().map(|it| it.name.as_str()), component)
+                }))
+                .memory_limits(if task.id.is_some() {
+                    self.memory_limits.distrusted()
+                } else {
+                    self.memory_limits
+                })
+                .stop_flag(task.stop.clone())
+                .cancel_token(cancel.clone(), self.cancel_grace)
+                .interface_policy(policy)
+                .wasi_sockets(policy.and_then(|it| it.sockets.as_ref()))
+                .wasi_http(policy.and_then(|it| it.http.as_ref()))
+                .max_cpu_ms(task.max_cpu_ms.unwrap_or(self.max_cpu_ms))
+                .host_call_rate_limits(rate_limits)
+                .engine_pool(Some(Arc::clone(&self.engines)))
+                .warm(self.warm_pool && task.warm)
+                .consume_fuel(ledger.is_some_and(|it| it.meter_fuel()))
+                .backtrace_details(self.backtrace_details);
+            if let Some(limit) = task.memory_limit {
+                builder = builder.wasm_memory_limit(limit);
+            }
+            let engine = builder.build().context("Failed to build PshEngine.");
+            match engine {
+                Ok(o) => unwound(|| {
+                    self.rt
+                        .block_on(o.run_metered(&task.wasm_component, task_time_slice))
+                }),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    RunReport {
+                        result: Err(e),
+                        fuel_used: None,
+                        usage: Default::default(),
+                    }
+                }
+            }
+        };
+        self.cancellations.unregister(&cancel);
+        let status = RunStatus::of(&report.result);
+        let name = task
+            .id
+            .as_deref()
+            .or(task.wasm_component_args.first().map(String::as_str))
+            .unwrap_or_default();
+        match (status, &report.result) {
+            (RunStatus::TimedOut, Err(e)) => tracing::error!("Component {name} interrupted: {e:#}"),
+            // with the backtrace of a trap, and its source lines if the
+            // component has debug info
+            (RunStatus::Failed, Err(e)) => tracing::warn!("Component {name} failed: {e:#}"),
+            _ => {}
+        }
+        let duration =
+            Duration::from_millis((Utc::now().timestamp_millis() - start_ms).max(0) as u64);
+        let component = task.component.as_deref().unwrap_or(name);
+        if let Some(failure) =
+            FailureReport::of(component, task.id.as_deref(), &report.result, duration)
+        {
+            self.report_failure(&failure);
+        }
+        let run_stats = RunStats {
+            component: &program,
+            wall: duration,
+            fuel: report.fuel_used,
+            usage: &report.usage,
+        };
+        component_stats::record(&run_stats);
+        if let (true, Some(exporter), Some(task_id)) = (self.telemetry, &exporter, &task.id) {
+            // not the component's own, so not counted against its quota
+            let data = Data {
+                ty: DataType::LineProtocol as _,
+                bytes: component_stats::to_line_protocol(&run_stats, task_id, &self.instance_id),
+            };
+            exporter.schedule(data, None);
+        }
+        let task_result = task_result.lock().unwrap().take();
+        if let (None, Some(result)) = (&task.id, &task_result) {
+            // nobody dispatched a local component to read it
+            tracing::info!(
+                "Component {name} result {:?}: {} {:?}",
+                result.status,
+                result.summary,
+                result.artifacts
+            );
+        }
+        if let Some(exporter) = &exporter {
+            self.exporters
+                .lock()
+                .unwrap()
+                .retain(|it| !Arc::ptr_eq(it, exporter));
+        }
+        if let Some(ledger) = ledger {
+            let run = Run {
+                task_id: task.id.clone(),
+                args: task.wasm_component_args.clone(),
+                tenant: tenant.as_ref().map(|it| it.name.clone()),
+                start_ms,
+                end_ms: Utc::now().timestamp_millis(),
+                status: RunStatus::Success,
+                exit_code: None,
+                error: None,
+                fuel_used: report.fuel_used,
+                bytes_exported: exporter.as_ref().map_or(0, |it| it.bytes_total()),
+                seed,
+            };
+            if let Err(e) = ledger.record(run.with_result(&report.result)) {
+                tracing::warn!("Failed to record run: {e}");
+            }
+        }
+        self.finish(task.id, task.running, task.done, status, task_result);
+
+        if let (Some(name), Some(artifact), Some(output)) = (&task.component, artifact, output) {
+            let artifact = artifact.lock().unwrap().take();
+            let output = mem::take(&mut *output.lock().unwrap());
+            self.run_dependents(name, dependents, status, artifact.as_ref(), &output);
+        }
+    }
+
+    /// Runs the components after `name` one by one on this thread, they are
+    /// joined with it on shutdown.
+    fn run_dependents(
+        &self,
+        name: &str,
+        dependents: &[ComponentConfig],
+        status: RunStatus,
+        artifact: Option<&Artifact>,
+        output: &[Payload],
+    ) {
+        if status != RunStatus::Success {
+            tracing::warn!(
+                "Skipped {} components after {name}, which did not succeed",
+                dependents.len()
+            );
+            return;
+        }
+        let envs = match self.pipelines.handoff(name, artifact) {
+            Ok(envs) => envs,
+            Err(e) => {
+                tracing::warn!("Skipped components after {name}: {e:#}");
+                return;
+            }
+        };
+        for dependent in dependents {
+            match Task::local(dependent) {
+                Ok(mut task) => {
+                    task.wasm_component_envs.extend(envs.iter().cloned());
+                    task.input = output.to_vec();
+                    self.run(task);
+                }
+                Err(e) => tracing::warn!("Skipped {} after {name}: {e:#}", dependent.name()),
+            }
+        }
+    }
+}
@@ -0,0 +1,310 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::config::{ComponentConfig, PipelineConfig};
+
+/// The last file a component exported in a run, see
+/// `profiling:data-export-ext/file`.
+pub struct Artifact {
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+/// What a stage emitted for the next through `profiling:host/pipeline`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Payload {
+    /// a media type like `application/json`
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Sets `after` of every stage of `pipelines` but the first to the stage
+/// before it.
+pub fn chain(components: &mut [ComponentConfig], pipelines: &[PipelineConfig]) -> Result<()> {
+    for pipeline in pipelines {
+        if pipeline.stages.len() < 2 {
+            bail!("Pipeline {:?} has less than two stages", pipeline.stages);
+        }
+        for stages in pipeline.stages.windows(2) {
+            let [upstream, stage] = stages else {
+                continue;
+            };
+            let Some(component) = components.iter_mut().find(|it| it.name() == stage) else {
+                bail!("Unknown stage {stage} of pipeline {:?}", pipeline.stages);
+            };
+            match &component.after {
+                Some(after) if after != upstream => {
+                    bail!("Component {stage} runs after {after}, not {upstream}")
+                }
+                _ => component.after = Some(upstream.clone()),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Components that run after another one, like `collect → analyze →
+/// summarize`, each stage gets the last artifact of the one before and the
+/// payloads it emitted.
+#[derive(Default)]
+pub struct Pipelines {
+    /// by name of the component they run after
+    dependents: HashMap<String, Vec<ComponentConfig>>,
+    handoff_dir: PathBuf,
+}
+
+impl Pipelines {
+    /// Fails on unknown, ambiguous or cyclic dependencies, on duplicated
+    /// explicit names and on components that have both `after` and a
+    /// schedule.
+    pub fn new(components: &[ComponentConfig], handoff_dir: impl Into<PathBuf>) -> Result<Self> {
+        let mut by_name = HashMap::new();
+        // paths of unnamed components run more than once, e.g. with other
+        // args, which can't be referred to by `after`
+        let mut ambiguous = HashSet::new();
+        for component in components {
+            let Some(other) = by_name.insert(component.name(), component) else {
+                continue;
+            };
+            if component.name.is_some() || other.name.is_some() {
+                bail!("Duplicated component name {}", component.name());
+            }
+            ambiguous.insert(component.name());
+        }
+        let mut dependents: HashMap<String, Vec<ComponentConfig>> = HashMap::new();
+        for component in components {
+            let Some(after) = &component.after else {
+                continue;
+            };
+            if component.schedule.is_some() {
+                bail!(
+                    "Component {} runs after {after}, it can't have a schedule",
+                    component.name()
+                );
+            }
+            if !by_name.contains_key(after.as_str()) {
+                bail!("Component {} runs after unknown {after}", component.name());
+            }
+            if ambiguous.contains(after.as_str()) {
+                bail!(
+                    "Component {} runs after {after}, which runs more than once, name the runs",
+                    component.name()
+                );
+            }
+            // every component has one upstream, a chain longer than all
+            // components loops
+            let mut upstream = Some(after);
+            for _ in 0..=components.len() {
+                let Some(name) = upstream else {
+                    break;
+                };
+                if name == component.name() {
+                    bail!("Component {} depends on itself", component.name());
+                }
+                upstream = by_name.get(name.as_str()).and_then(|it| it.after.as_ref());
+            }
+            dependents
+                .entry(after.clone())
+                .or_default()
+                .push(component.clone());
+        }
+        Ok(Self {
+            dependents,
+            handoff_dir: handoff_dir.into(),
+        })
+    }
+
+    /// Components to run once `name` has succeeded.
+    pub fn dependents(&self, name: &str) -> &[ComponentConfig] {
+        self.dependents.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Keeps `artifact` of a run of `upstream` for its dependents, returns
+    /// the env vars that tell them about it.
+    pub fn handoff(
+        &self,
+        upstream: &str,
+        artifact: Option<&Artifact>,
+    ) -> Result<Vec<(String, String)>> {
+        let mut envs = vec![("PSH_AFTER".to_string(), upstream.to_string())];
+        let Some(artifact) = artifact else {
+            return Ok(envs);
+        };
+        // an artifact name is not trusted to be a plain file name
+        let file_name = Path::new(&artifact.name)
+            .file_name()
+            .map_or("artifact".into(), |it| it.to_string_lossy());
+        let dir = self.handoff_dir.join(sanitize(upstream));
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let path = dir.join(file_name.as_ref());
+        fs::write(&path, &artifact.bytes)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        envs.push(("PSH_ARTIFACT".to_string(), artifact.name.clone()));
+        envs.push((
+            "PSH_ARTIFACT_PATH".to_string(),
+            path.to_string_lossy().into_owned(),
+        ));
+        Ok(envs)
+    }
+}
+
+// component names default to their path
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|it| match it {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => it,
+            _ => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{Artifact, Pipelines, chain};
+    use crate::config::{ComponentConfig, PipelineConfig};
+
+    fn component(name: &str, after: Option<&str>) -> ComponentConfig {
+        ComponentConfig {
+            path: format!("/opt/{name}.wasm"),
+            sha256: None,
+            name: Some(name.to_string()),
+            args: vec![],
+            env: Default::default(),
+            tenant: None,
+            schedule: None,
+            after: after.map(str::to_string),
+            max_cpu_ms: None,
+            memory_limit: None,
+            rate_limits: Default::default(),
+            preopens: vec![],
+            seed: None,
+            restart: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_pipeline_dependencies() {
+        let components = [
+            component("collect", None),
+            component("analyze", Some("collect")),
+            component("summarize", Some("analyze")),
+        ];
+        let pipelines = Pipelines::new(&components, "/tmp").unwrap();
+        let names = |name| {
+            pipelines
+                .dependents(name)
+                .iter()
+                .map(|it| it.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("collect"), ["analyze"]);
+        assert_eq!(names("analyze"), ["summarize"]);
+        assert!(names("summarize").is_empty());
+
+        let unknown = [component("analyze", Some("collect"))];
+        assert!(Pipelines::new(&unknown, "/tmp").is_err());
+        let cyclic = [
+            component("a", Some("b")),
+            component("b", Some("a")),
+            component("c", None),
+        ];
+        assert!(Pipelines::new(&cyclic, "/tmp").is_err());
+        let mut scheduled = component("analyze", Some("collect"));
+        scheduled.schedule = Some("5m".to_string());
+        assert!(Pipelines::new(&[component("collect", None), scheduled], "/tmp").is_err());
+    }
+
+    #[test]
+    fn test_same_path_runs() {
+        let unnamed = |args: &[&str]| ComponentConfig {
+            name: None,
+            args: args.iter().map(|it| it.to_string()).collect(),
+            ..component("collect", None)
+        };
+        let runs = [unnamed(&["--cpu"]), unnamed(&["--memory"])];
+        assert!(Pipelines::new(&runs, "/tmp").is_ok());
+
+        let mut after = component("analyze", None);
+        after.after = Some("/opt/collect.wasm".to_string());
+        let [first, second] = runs;
+        assert!(Pipelines::new(&[first.clone(), second, after.clone()], "/tmp").is_err());
+        assert!(Pipelines::new(&[first, after], "/tmp").is_ok());
+
+        let named = [component("collect", None), component("collect", None)];
+        assert!(Pipelines::new(&named, "/tmp").is_err());
+    }
+
+    #[test]
+    fn test_chain_pipeline_stages() {
+        let mut components = [
+            component("collect", None),
+            component("aggregate", None),
+            component("export", Some("aggregate")),
+        ];
+        let pipeline = |stages: &[&str]| PipelineConfig {
+            stages: stages.iter().map(|it| it.to_string()).collect(),
+        };
+        chain(
+            &mut components,
+            &[pipeline(&["collect", "aggregate", "export"])],
+        )
+        .unwrap();
+        assert_eq!(components[0].after, None);
+        assert_eq!(components[1].after.as_deref(), Some("collect"));
+        assert_eq!(components[2].after.as_deref(), Some("aggregate"));
+        assert!(Pipelines::new(&components, "/tmp").is_ok());
+
+        assert!(chain(&mut components, &[pipeline(&["collect", "export"])]).is_err());
+        assert!(chain(&mut components, &[pipeline(&["collect", "unknown"])]).is_err());
+        assert!(chain(&mut components, &[pipeline(&["collect"])]).is_err());
+    }
+
+    #[test]
+    fn test_handoff_writes_artifact() {
+        let dir = std::env::temp_dir().join(format!("psh-handoff-{}", std::process::id()));
+        let pipelines = Pipelines::new(&[], &dir).unwrap();
+        let artifact = Artifact {
+            name: "../profile.pb".to_string(),
+            bytes: b"pprof".to_vec(),
+        };
+        let envs = pipelines.handoff("collect", Some(&artifact)).unwrap();
+        let path = dir.join("collect").join("profile.pb");
+        assert_eq!(
+            envs,
+            [
+                ("PSH_AFTER".to_string(), "collect".to_string()),
+                ("PSH_ARTIFACT".to_string(), "../profile.pb".to_string()),
+                (
+                    "PSH_ARTIFACT_PATH".to_string(),
+                    path.to_string_lossy().into_owned()
+                ),
+            ]
+        );
+        assert_eq!(fs::read(&path).unwrap(), b"pprof");
+
+        let envs = pipelines.handoff("collect", None).unwrap();
+        assert_eq!(envs.len(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,338 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use wasmtime::{Engine, component::Component};
+
+static GLOBAL: OnceLock<Policy> = OnceLock::new();
+
+/// The policy installed by [`init`], `None` if components may import every
+/// interface.
+pub fn global() -> Option<&'static Policy> {
+    GLOBAL.get()
+}
+
+pub fn init(path: &str) -> Result<()> {
+    let policy = Policy::read(path)?;
+    if GLOBAL.set(policy).is_err() {
+        bail!("Interface policy already initialized");
+    }
+    Ok(())
+}
+
+/// Interfaces a component may import, e.g. `profiling:system/cpu`, without
+/// their version. A pattern ending in `*` matches names it is a prefix of.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InterfacePolicy {
+    /// `profiling:` interfaces the component may import, every one if
+    /// absent, WASI ones are always allowed
+    pub allow: Option<Vec<String>>,
+    /// wins over `allow`, WASI ones included
+    pub deny: Vec<String>,
+    /// no sockets can be opened if absent
+    pub sockets: Option<SocketPolicy>,
+    /// no requests can be sent through `wasi:http` if absent
+    pub http: Option<HttpPolicy>,
+}
+
+/// What `wasi:sockets` lets the component do.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SocketPolicy {
+    pub tcp: bool,
+    pub udp: bool,
+    pub name_lookup: bool,
+    /// addresses the component may connect to or bind, e.g. `10.0.0.1`,
+    /// `127.0.0.1:4317`, `10.0.0.0/8` or `[::1]:8080`
+    pub addrs: Vec<AddrRule>,
+}
+
+impl SocketPolicy {
+    pub fn allows(&self, addr: SocketAddr) -> bool {
+        self.addrs.iter().any(|it| it.matches(addr))
+    }
+}
+
+/// Where the component may send requests through `wasi:http`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpPolicy {
+    /// e.g. `app.internal`, `127.0.0.1:8080`, `[::1]:9090` or
+    /// `*.svc.cluster.local`, on any port unless one is given
+    pub hosts: Vec<String>,
+    /// plain `http://` requests are refused unless set
+    pub insecure: bool,
+}
+
+impl HttpPolicy {
+    /// `host` as in the URI, IPv6 addresses in brackets.
+    pub fn allows(&self, https: bool, host: &str, port: u16) -> bool {
+        if !https && !self.insecure {
+            return false;
+        }
+        let host = host.to_ascii_lowercase();
+        self.hosts.iter().any(|rule| {
+            let rule = rule.to_ascii_lowercase();
+            let (pattern, rule_port) = match rule.rsplit_once(':') {
+                Some((pattern, port)) if !pattern.ends_with(':') && !port.ends_with(']') => {
+                    (pattern, port.parse().ok())
+                }
+                _ => (rule.as_str(), None),
+            };
+            if rule_port.is_some_and(|it: u16| it != port) {
+                return false;
+            }
+            match pattern.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|it| it.ends_with('.')),
+                None => pattern == host,
+            }
+        })
+    }
+}
+
+/// An address or network, on any port unless one is given.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct AddrRule {
+    ip: IpAddr,
+    prefix: u8,
+    port: Option<u16>,
+}
+
+impl FromStr for AddrRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(Self::new(addr.ip(), None, Some(addr.port())));
+        }
+        let (ip, prefix) = match s.split_once('/') {
+            Some((ip, prefix)) => {
+                let prefix = prefix
+                    .parse()
+                    .with_context(|| format!("Invalid prefix of {s}"))?;
+                (ip, Some(prefix))
+            }
+            None => (s, None),
+        };
+        let ip: IpAddr = ip.parse().with_context(|| format!("Invalid address {s}"))?;
+        let max = if ip.is_ipv4() { 32 } else { 128 };
+        if prefix.is_some_and(|it| it > max) {
+            bail!("Invalid prefix of {s}");
+        }
+        Ok(Self::new(ip, prefix, None))
+    }
+}
+
+impl TryFrom<String> for AddrRule {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl AddrRule {
+    fn new(ip: IpAddr, prefix: Option<u8>, port: Option<u16>) -> Self {
+        let max = if ip.is_ipv4() { 32 } else { 128 };
+        Self {
+            ip,
+            prefix: prefix.unwrap_or(max),
+            port,
+        }
+    }
+
+    pub fn matches(&self, addr: SocketAddr) -> bool {
+        if self.port.is_some_and(|it| it != addr.port()) {
+            return false;
+        }
+        match (self.ip, addr.ip()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// `pattern` is a name or a prefix followed by `*`.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+impl InterfacePolicy {
+    /// `name` may carry a version, e.g. `profiling:system/cpu@0.1.0`.
+    pub fn allows(&self, name: &str) -> bool {
+        let name = name.split_once('@').map_or(name, |(name, _)| name);
+        if self.deny.iter().any(|it| matches(it, name)) {
+            return false;
+        }
+        if !name.starts_with("profiling:") {
+            return true;
+        }
+        self.allow
+            .as_ref()
+            .is_none_or(|allow| allow.iter().any(|it| matches(it, name)))
+    }
+
+    /// Fails before `component` is linked if it imports a denied interface,
+    /// so it never gets hold of one.
+    pub fn check(&self, component: &Component, engine: &Engine) -> Result<()> {
+        let denied: Vec<_> = component
+            .component_type()
+            .imports(engine)
+            .map(|(name, _)| name)
+            .filter(|it| !self.allows(it))
+            .collect();
+        if !denied.is_empty() {
+            bail!("Component imports denied interfaces: {}", denied.join(", "));
+        }
+        Ok(())
+    }
+}
+
+/// Interfaces components may import, by name of the component.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// components without an entry of their own, tasks of the server included
+    pub default: InterfacePolicy,
+    pub components: BTreeMap<String, InterfacePolicy>,
+}
+
+impl Policy {
+    pub fn read(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?;
+        toml::from_str(&text).with_context(|| format!("Invalid interface policy {path}"))
+    }
+
+    /// The policy of the component `name`, see `name` of components in the
+    /// config.
+    pub fn of(&self, name: Option<&str>) -> &InterfacePolicy {
+        name.and_then(|it| self.components.get(it))
+            .unwrap_or(&self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddrRule, HttpPolicy, Policy};
+
+    #[test]
+    fn test_interface_policy() {
+        let policy: Policy = toml::from_str(
+            r#"
+            [default]
+            deny = ["profiling:data-export/file"]
+
+            [components.cpu-top]
+            allow = ["profiling:system/cpu", "profiling:system/memory", "profiling:data-export/*"]
+
+            [components.svc-probe.sockets]
+            tcp = true
+            addrs = ["127.0.0.1:8080"]
+            "#,
+        )
+        .unwrap();
+
+        let default = policy.of(None);
+        assert!(!default.allows("profiling:data-export/file@0.1.0"));
+        assert!(default.allows("profiling:perf/counter"));
+        assert!(default.allows("wasi:cli/environment@0.2.0"));
+
+        let cpu_top = policy.of(Some("cpu-top"));
+        assert!(cpu_top.allows("profiling:system/cpu@0.1.0"));
+        assert!(cpu_top.allows("profiling:data-export/metric"));
+        assert!(!cpu_top.allows("profiling:system/process"));
+        assert!(cpu_top.allows("wasi:io/poll@0.2.0"));
+
+        assert!(std::ptr::eq(policy.of(Some("other")), default));
+
+        assert!(default.sockets.is_none());
+        let sockets = policy.of(Some("svc-probe")).sockets.as_ref().unwrap();
+        assert!(sockets.tcp && !sockets.udp);
+        assert!(sockets.allows("127.0.0.1:8080".parse().unwrap()));
+        assert!(!sockets.allows("127.0.0.1:8081".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_http_policy() {
+        let policy = HttpPolicy {
+            hosts: vec![
+                "app.internal".to_string(),
+                "127.0.0.1:8080".to_string(),
+                "[::1]:9090".to_string(),
+                "*.svc.cluster.local".to_string(),
+            ],
+            insecure: false,
+        };
+        assert!(policy.allows(true, "app.internal", 443));
+        assert!(policy.allows(true, "App.Internal", 8443));
+        assert!(!policy.allows(false, "app.internal", 80));
+        assert!(policy.allows(true, "127.0.0.1", 8080));
+        assert!(!policy.allows(true, "127.0.0.1", 8081));
+        assert!(policy.allows(true, "[::1]", 9090));
+        assert!(policy.allows(true, "web.default.svc.cluster.local", 443));
+        assert!(!policy.allows(true, "svc.cluster.local", 443));
+        assert!(!policy.allows(true, "evilsvc.cluster.local", 443));
+        assert!(!policy.allows(true, "example.com", 443));
+
+        let insecure = HttpPolicy {
+            insecure: true,
+            ..policy
+        };
+        assert!(insecure.allows(false, "app.internal", 80));
+    }
+
+    #[test]
+    fn test_addr_rules() {
+        let rule = |s: &str| s.parse::<AddrRule>().unwrap();
+        let addr = |s: &str| s.parse().unwrap();
+
+        assert!(rule("127.0.0.1:4317").matches(addr("127.0.0.1:4317")));
+        assert!(!rule("127.0.0.1:4317").matches(addr("127.0.0.1:4318")));
+        assert!(rule("10.0.0.0/8").matches(addr("10.1.2.3:443")));
+        assert!(!rule("10.0.0.0/8").matches(addr("11.0.0.1:443")));
+        assert!(rule("0.0.0.0/0").matches(addr("1.1.1.1:53")));
+        assert!(rule("[::1]:8080").matches(addr("[::1]:8080")));
+        assert!(!rule("::1").matches(addr("127.0.0.1:80")));
+
+        assert!("10.0.0.0/33".parse::<AddrRule>().is_err());
+        assert!("localhost".parse::<AddrRule>().is_err());
+    }
+}
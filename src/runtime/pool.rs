@@ -0,0 +1,133 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use anyhow::{Context, Result};
+use wasmtime::{
+    Engine,
+    component::{Component, InstancePre, Linker},
+};
+
+use super::{PshState, cache, engine::EPOCH_TICK};
+
+// pre-instantiated components kept per engine, the oldest is dropped
+const MAX_COMPONENTS: usize = 16;
+
+/// What the linker of an engine is made of, runs with the same settings
+/// share it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EngineKey {
+    pub consume_fuel: bool,
+    pub perf_op: bool,
+    pub system_op: bool,
+    pub data_export_op: bool,
+}
+
+/// Engines and linkers shared by all components, created once per
+/// [`EngineKey`] instead of for every run.
+#[derive(Default)]
+pub struct EnginePool {
+    engines: Mutex<Vec<Arc<SharedEngine>>>,
+}
+
+impl EnginePool {
+    /// The engine for `key`, created by `build` on first use.
+    pub fn engine(
+        &self,
+        key: EngineKey,
+        build: impl FnOnce() -> Result<(Engine, Linker<PshState>)>,
+    ) -> Result<Arc<SharedEngine>> {
+        let mut engines = self.engines.lock().unwrap();
+        if let Some(engine) = engines.iter().find(|it| it.key == key) {
+            return Ok(Arc::clone(engine));
+        }
+        let (engine, linker) = build()?;
+        // one ticker for all stores of the engine, it lives as long as psh
+        thread::Builder::new()
+            .name("psh-epoch".to_string())
+            .spawn({
+                let engine = engine.clone();
+                move || {
+                    loop {
+                        thread::sleep(EPOCH_TICK);
+                        engine.increment_epoch();
+                    }
+                }
+            })
+            .context("Failed to spawn epoch ticker")?;
+        let engine = Arc::new(SharedEngine {
+            key,
+            engine,
+            linker,
+            components: Mutex::new(VecDeque::new()),
+        });
+        engines.push(Arc::clone(&engine));
+        Ok(engine)
+    }
+}
+
+pub struct SharedEngine {
+    key: EngineKey,
+    pub engine: Engine,
+    pub linker: Linker<PshState>,
+    /// warm components by digest of the binary
+    components: Mutex<VecDeque<(Vec<u8>, InstancePre<PshState>)>>,
+}
+
+impl SharedEngine {
+    /// `binary` compiled and type-checked against the linker, kept for the
+    /// next warm run so it only has to instantiate, picked up again once
+    /// the binary changes.
+    pub fn warm_instance_pre(&self, binary: &[u8]) -> Result<InstancePre<PshState>> {
+        let digest = ring::digest::digest(&ring::digest::SHA256, binary);
+        let digest = digest.as_ref();
+        let warm = |components: &VecDeque<(Vec<u8>, InstancePre<PshState>)>| {
+            components
+                .iter()
+                .find(|(it, _)| it == digest)
+                .map(|(_, pre)| pre.clone())
+        };
+        if let Some(pre) = warm(&self.components.lock().unwrap()) {
+            return Ok(pre);
+        }
+        // Compile without the lock so one slow component does not stall
+        // every other run; if two runs race, the first one cached wins.
+        let pre = self.instance_pre(binary)?;
+        let mut components = self.components.lock().unwrap();
+        if let Some(pre) = warm(&components) {
+            return Ok(pre);
+        }
+        if components.len() >= MAX_COMPONENTS {
+            components.pop_front();
+        }
+        components.push_back((digest.to_vec(), pre.clone()));
+        Ok(pre)
+    }
+
+    pub fn instance_pre(&self, binary: &[u8]) -> Result<InstancePre<PshState>> {
+        let component = match cache::global() {
+            Some(cache) => cache.load(&self.engine, binary),
+            None => Component::from_binary(&self.engine, binary),
+        }
+        .context("Failed to load component!")?;
+        self.linker
+            .instantiate_pre(&component)
+            .context("Failed to pre-instantiate component")
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::time::{Duration, Instant};
+
+use profiling::probe::probe::{self, DnsResult, PingResult, RecordType, TcpResult, TlsResult};
+use wasmtime::component::Linker;
+
+use crate::probe::{PingOptions, Prober, dns};
+
+wasmtime::component::bindgen!({
+    path: "wit/probe",
+    world: "imports",
+    trappable_imports: true,
+});
+
+#[derive(Clone, Debug, Default)]
+pub struct ProbeCtx {
+    pub allow_probe: bool,
+    pub allow_ping: bool,
+    /// end of the time slice of the run, pings stop there
+    pub deadline: Option<Instant>,
+}
+
+impl ProbeCtx {
+    fn prober(&self) -> Result<&'static Prober, String> {
+        if !self.allow_probe {
+            return Err("Probing is not allowed".to_string());
+        }
+        crate::probe::global().ok_or_else(|| "Probes are disabled".to_string())
+    }
+}
+
+impl probe::Host for ProbeCtx {
+    fn resolve(
+        &mut self,
+        name: String,
+        kind: RecordType,
+        server: Option<String>,
+        timeout_ms: u64,
+    ) -> wasmtime::Result<Result<DnsResult, String>> {
+        let kind = match kind {
+            RecordType::A => dns::RecordType::A,
+            RecordType::Aaaa => dns::RecordType::Aaaa,
+        };
+        Ok(self.prober().and_then(|prober| {
+            let resolved = prober
+                .resolve(
+                    &name,
+                    kind,
+                    server.as_deref(),
+                    Duration::from_millis(timeout_ms),
+                )
+                .map_err(|err| format!("{err:#}"))?;
+            Ok(DnsResult {
+                rcode: resolved.answer.rcode,
+                addresses: resolved
+                    .answer
+                    .addresses
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+                ttl: resolved.answer.ttl,
+                duration_ns: resolved.duration.as_nanos() as u64,
+            })
+        }))
+    }
+
+    fn tcp_connect(
+        &mut self,
+        host: String,
+        port: u16,
+        timeout_ms: u64,
+    ) -> wasmtime::Result<Result<TcpResult, String>> {
+        Ok(self.prober().and_then(|prober| {
+            let connected = prober
+                .tcp_connect(&host, port, Duration::from_millis(timeout_ms))
+                .map_err(|err| format!("{err:#}"))?;
+            Ok(TcpResult {
+                address: connected.address.to_string(),
+                duration_ns: connected.duration.as_nanos() as u64,
+            })
+        }))
+    }
+
+    fn tls_handshake(
+        &mut self,
+        host: String,
+        port: u16,
+        server_name: Option<String>,
+        timeout_ms: u64,
+    ) -> wasmtime::Result<Result<TlsResult, String>> {
+        Ok(self.prober().and_then(|prober| {
+            let handshake = prober
+                .tls_handshake(
+                    &host,
+                    port,
+                    server_name.as_deref(),
+                    Duration::from_millis(timeout_ms),
+                )
+                .map_err(|err| format!("{err:#}"))?;
+            Ok(TlsResult {
+                address: handshake.address.to_string(),
+                connect_ns: handshake.connect.as_nanos() as u64,
+                handshake_ns: handshake.handshake.as_nanos() as u64,
+                version: handshake.version,
+                cipher_suite: handshake.cipher_suite,
+                peer_certificates: handshake.peer_certificates as u32,
+            })
+        }))
+    }
+
+    fn ping(
+        &mut self,
+        host: String,
+        count: u32,
+        interval_ms: u64,
+        timeout_ms: u64,
+        payload_size: u32,
+    ) -> wasmtime::Result<Result<PingResult, String>> {
+        if !self.allow_ping {
+            return Ok(Err("Ping is not allowed".to_string()));
+        }
+        let opts = PingOptions {
+            count,
+            interval: Duration::from_millis(interval_ms),
+            timeout: Duration::from_millis(timeout_ms),
+            payload_size: payload_size as usize,
+            deadline: self.deadline,
+        };
+        Ok(self.prober().and_then(|prober| {
+            let pinged = prober
+                .ping(&host, &opts)
+                .map_err(|err| format!("{err:#}"))?;
+            let lost = pinged.rtts.iter().filter(|it| it.is_none()).count();
+            Ok(PingResult {
+                address: pinged.address.to_string(),
+                loss: lost as f64 / pinged.rtts.len() as f64,
+                rtts: pinged
+                    .rtts
+                    .iter()
+                    .map(|it| it.map(|rtt| rtt.as_nanos() as u64))
+                    .collect(),
+            })
+        }))
+    }
+}
+
+pub fn add_to_linker<T>(
+    l: &mut Linker<T>,
+    f: impl (Fn(&mut T) -> &mut ProbeCtx) + Copy + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    probe::add_to_linker(l, f)
+}
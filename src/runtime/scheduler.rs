@@ -0,0 +1,361 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Datelike, Local, Months, NaiveDateTime, TimeDelta, Timelike};
+
+use super::{Task, TaskSender};
+use crate::config::ComponentConfig;
+
+// every step moves on by at least a minute, plenty for February 29, which
+// may be 8 years away
+const MAX_CRON_STEPS: usize = 100_000;
+
+/// When a component runs, `30s`, `5m`, `1h` or a cron expression like
+/// `*/5 * * * *`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Schedule {
+    /// at startup and then every interval after the previous start
+    Interval(Duration),
+    /// minute, hour, day of month, month and day of week in local time
+    Cron(Cron),
+}
+
+impl Schedule {
+    /// The first run after `now`, `None` if there is none.
+    pub fn next(&self, now: DateTime<Local>, first: bool) -> Option<DateTime<Local>> {
+        match self {
+            Self::Interval(_) if first => Some(now),
+            Self::Interval(interval) => {
+                now.checked_add_signed(TimeDelta::from_std(*interval).ok()?)
+            }
+            Self::Cron(cron) => {
+                let mut t = now.naive_local();
+                loop {
+                    t = cron.next_after(t)?;
+                    // skipped by a daylight saving time change if `None`
+                    if let Some(t) = t.and_local_timezone(Local).earliest() {
+                        return Some(t);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.split_whitespace().count() > 1 {
+            return Ok(Self::Cron(s.parse()?));
+        }
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (n, unit) = s.split_at(split);
+        let n: u64 = n.parse().with_context(|| format!("Invalid schedule {s}"))?;
+        let unit_secs = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => bail!("Invalid unit of {s}, expected s, m, h or d"),
+        };
+        let Some(secs) = n.checked_mul(unit_secs) else {
+            bail!("Interval {s} is too long");
+        };
+        if secs == 0 {
+            bail!("Interval {s} must not be 0");
+        }
+        Ok(Self::Interval(Duration::from_secs(secs)))
+    }
+}
+
+/// A parsed five field cron expression, lists, ranges and steps are
+/// supported, names of months and days are not.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // a restricted day of month or week matches if either does
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl FromStr for Cron {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!("Cron expression {s} must have 5 fields");
+        };
+        let mut weekdays = parse_field(weekday, 0, 7).context("Invalid day of week")?;
+        // both 0 and 7 are sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59).context("Invalid minute")?,
+            hours: parse_field(hour, 0, 23).context("Invalid hour")?,
+            days: parse_field(day, 1, 31).context("Invalid day of month")?,
+            months: parse_field(month, 1, 12).context("Invalid month")?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+}
+
+/// Bit `n` is set if `n` matches `field`.
+fn parse_field(field: &str, min: u64, max: u64) -> Result<u64> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u64>()?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            bail!("Step of {part} must not be 0");
+        }
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (start.parse()?, end.parse()?),
+                None => {
+                    let n = range.parse()?;
+                    // `5/10` runs from 5 to the end
+                    (n, if part.contains('/') { max } else { n })
+                }
+            },
+        };
+        if start < min || end > max || start > end {
+            return Err(anyhow!("{part} is out of {min}-{max}"));
+        }
+        for n in (start..=end).step_by(step as usize) {
+            bits |= 1 << n;
+        }
+    }
+    Ok(bits)
+}
+
+const fn has(bits: u64, n: u32) -> bool {
+    bits & (1 << n) != 0
+}
+
+impl Cron {
+    fn day_matches(&self, t: NaiveDateTime) -> bool {
+        let day = has(self.days, t.day());
+        let weekday = has(self.weekdays, t.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// The first matching minute after `t`.
+    pub fn next_after(&self, t: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut t = t.with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        for _ in 0..MAX_CRON_STEPS {
+            if !has(self.months, t.month()) {
+                t = t
+                    .date()
+                    .with_day(1)?
+                    .checked_add_months(Months::new(1))?
+                    .and_hms_opt(0, 0, 0)?;
+            } else if !self.day_matches(t) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !has(self.hours, t.hour()) {
+                t = t.with_minute(0)? + TimeDelta::hours(1);
+            } else if !has(self.minutes, t.minute()) {
+                t += TimeDelta::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+}
+
+/// A component run on its schedule, every run instantiates it anew from
+/// the file.
+pub struct Scheduled {
+    pub schedule: Schedule,
+    pub component: ComponentConfig,
+}
+
+struct Entry {
+    scheduled: Scheduled,
+    next: Option<DateTime<Local>>,
+    // set while a run is queued or running
+    running: Arc<AtomicBool>,
+}
+
+/// Schedules the components until the task runtime is gone, a run is
+/// skipped while the previous one is still running.
+pub fn spawn(sender: TaskSender, scheduled: Vec<Scheduled>) -> Result<JoinHandle<()>> {
+    let handle = thread::Builder::new()
+        .name("psh-scheduler".to_string())
+        .spawn(move || {
+            let now = Local::now();
+            let mut entries: Vec<Entry> = scheduled
+                .into_iter()
+                .map(|it| Entry {
+                    next: it.schedule.next(now, true),
+                    scheduled: it,
+                    running: Arc::new(AtomicBool::new(false)),
+                })
+                .collect();
+            while let Some(due) = entries.iter().filter_map(|it| it.next).min() {
+                let wait = (due - Local::now()).to_std().unwrap_or_default();
+                thread::sleep(wait);
+                let now = Local::now();
+                for Entry {
+                    scheduled,
+                    next,
+                    running,
+                } in &mut entries
+                {
+                    if next.is_none_or(|it| it > now) {
+                        continue;
+                    }
+                    *next = scheduled.schedule.next(now, false);
+                    let path = &scheduled.component.path;
+                    if running.load(Ordering::Acquire) {
+                        tracing::warn!(
+                            "Skipped a run of {path}, the previous one is still running"
+                        );
+                        continue;
+                    }
+                    let task = match Task::local(&scheduled.component) {
+                        Ok(task) => task,
+                        Err(e) => {
+                            tracing::warn!("Skipped a run of {path}: {e:#}");
+                            continue;
+                        }
+                    };
+                    running.store(true, Ordering::Release);
+                    let task = Task {
+                        running: Some(Arc::clone(running)),
+                        warm: true,
+                        ..task
+                    };
+                    if sender.schedule(task).is_err() {
+                        // the task runtime is gone
+                        return;
+                    }
+                }
+            }
+        })?;
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::NaiveDateTime;
+
+    use super::{Cron, Schedule};
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(
+            "30s".parse::<Schedule>().unwrap(),
+            Schedule::Interval(Duration::from_secs(30))
+        );
+        assert_eq!(
+            "2h".parse::<Schedule>().unwrap(),
+            Schedule::Interval(Duration::from_secs(7200))
+        );
+        assert!("0s".parse::<Schedule>().is_err());
+        assert!("30".parse::<Schedule>().is_err());
+        assert!("s".parse::<Schedule>().is_err());
+        assert!("213503982334602d".parse::<Schedule>().is_err());
+        assert!(matches!(
+            "*/5 * * * *".parse::<Schedule>().unwrap(),
+            Schedule::Cron(_)
+        ));
+    }
+
+    #[test]
+    fn test_parse_cron() {
+        assert!("* * * *".parse::<Cron>().is_err());
+        assert!("60 * * * *".parse::<Cron>().is_err());
+        assert!("*/0 * * * *".parse::<Cron>().is_err());
+        assert!("5-1 * * * *".parse::<Cron>().is_err());
+        assert!("0 0 * * 7".parse::<Cron>().is_ok());
+    }
+
+    #[test]
+    fn test_cron_next() {
+        let next = |cron: &str, t: &str| cron.parse::<Cron>().unwrap().next_after(at(t));
+        assert_eq!(
+            next("*/5 * * * *", "2024-03-01 10:02"),
+            Some(at("2024-03-01 10:05"))
+        );
+        assert_eq!(
+            next("*/5 * * * *", "2024-03-01 10:05"),
+            Some(at("2024-03-01 10:10"))
+        );
+        assert_eq!(
+            next("30 4 * * *", "2024-03-01 10:02"),
+            Some(at("2024-03-02 04:30"))
+        );
+        assert_eq!(
+            next("0 0 1 1 *", "2024-03-01 10:02"),
+            Some(at("2025-01-01 00:00"))
+        );
+        // 2024-03-03 is a sunday
+        assert_eq!(
+            next("0 12 * * 0", "2024-03-01 10:02"),
+            Some(at("2024-03-03 12:00"))
+        );
+        assert_eq!(
+            next("0 12 * * 7", "2024-03-01 10:02"),
+            Some(at("2024-03-03 12:00"))
+        );
+        // day of month or day of week
+        assert_eq!(
+            next("0 0 15 * 0", "2024-03-01 10:02"),
+            Some(at("2024-03-03 00:00"))
+        );
+        assert_eq!(
+            next("0 0 29 2 *", "2024-03-01 10:02"),
+            Some(at("2028-02-29 00:00"))
+        );
+        assert_eq!(next("0 0 30 2 *", "2024-03-01 10:02"), None);
+        assert_eq!(
+            next("0-10/5,30 8 * * *", "2024-03-01 08:06"),
+            Some(at("2024-03-01 08:10"))
+        );
+    }
+}
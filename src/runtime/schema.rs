@@ -0,0 +1,192 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, HashSet};
+
+use super::data_export::{
+    ext::profiling::data_export_ext::schema::FieldType,
+    profiling::data_export::common::FieldValue as WitFieldValue,
+};
+
+/// Fields and tags a component declared for one measurement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schema {
+    pub fields: HashMap<String, FieldType>,
+    pub tags: HashSet<String>,
+}
+
+impl Schema {
+    pub fn new(
+        fields: Vec<String>,
+        tags: Vec<String>,
+        types: Vec<FieldType>,
+    ) -> Result<Self, String> {
+        if fields.len() != types.len() {
+            return Err(format!(
+                "{} fields declared with {} types",
+                fields.len(),
+                types.len()
+            ));
+        }
+        if fields.is_empty() {
+            return Err("No fields declared".to_string());
+        }
+        let len = fields.len();
+        let fields: HashMap<_, _> = fields.into_iter().zip(types).collect();
+        if fields.len() != len {
+            return Err("Duplicated field".to_string());
+        }
+        Ok(Self {
+            fields,
+            tags: tags.into_iter().collect(),
+        })
+    }
+
+    pub fn validate<'a>(
+        &self,
+        tags: impl IntoIterator<Item = &'a str>,
+        fields: impl IntoIterator<Item = (&'a str, &'a WitFieldValue)>,
+    ) -> Result<(), String> {
+        if let Some(tag) = tags.into_iter().find(|it| !self.tags.contains(*it)) {
+            return Err(format!("Undeclared tag {tag}"));
+        }
+        for (name, value) in fields {
+            let Some(ty) = self.fields.get(name) else {
+                return Err(format!("Undeclared field {name}"));
+            };
+            let actual = field_type(value);
+            if actual != *ty {
+                return Err(format!(
+                    "Field {name} is declared as {} but got {}",
+                    type_name(*ty),
+                    type_name(actual)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// `name:type` pairs sorted by name, e.g. `cpu:uint,usage:float`.
+    pub fn fields_desc(&self) -> String {
+        let mut fields: Vec<_> = self
+            .fields
+            .iter()
+            .map(|(name, ty)| format!("{name}:{}", type_name(*ty)))
+            .collect();
+        fields.sort_unstable();
+        fields.join(",")
+    }
+
+    pub fn tags_desc(&self) -> String {
+        let mut tags: Vec<_> = self.tags.iter().map(String::as_str).collect();
+        tags.sort_unstable();
+        tags.join(",")
+    }
+}
+
+/// Schemas declared by a component, keyed by measurement name.
+#[derive(Clone, Debug, Default)]
+pub struct Schemas(HashMap<String, Schema>);
+
+impl Schemas {
+    /// Returns `true` if the measurement was not declared before.
+    pub fn declare(&mut self, name: String, schema: Schema) -> Result<bool, String> {
+        match self.0.get(&name) {
+            Some(declared) if *declared == schema => Ok(false),
+            Some(_) => Err(format!(
+                "Measurement {name} is already declared with another schema"
+            )),
+            None => {
+                self.0.insert(name, schema);
+                Ok(true)
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Schema> {
+        self.0.get(name)
+    }
+}
+
+const fn field_type(value: &WitFieldValue) -> FieldType {
+    match value {
+        WitFieldValue::Float(_) => FieldType::Float,
+        WitFieldValue::Int(_) => FieldType::Int,
+        WitFieldValue::Uint(_) => FieldType::Uint,
+        WitFieldValue::Text(_) => FieldType::Text,
+        WitFieldValue::Boolean(_) => FieldType::Boolean,
+        WitFieldValue::NsTs(_) => FieldType::NsTs,
+    }
+}
+
+const fn type_name(ty: FieldType) -> &'static str {
+    match ty {
+        FieldType::Float => "float",
+        FieldType::Int => "int",
+        FieldType::Uint => "uint",
+        FieldType::Text => "text",
+        FieldType::Boolean => "boolean",
+        FieldType::NsTs => "ns-ts",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FieldType, Schema, Schemas, WitFieldValue};
+
+    fn schema() -> Schema {
+        Schema::new(
+            vec!["usage".to_string(), "comm".to_string()],
+            vec!["cpu".to_string()],
+            vec![FieldType::Float, FieldType::Text],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        let schema = schema();
+        let usage = WitFieldValue::Float(0.5);
+        let comm = WitFieldValue::Text("psh".to_string());
+        assert_eq!(
+            schema.validate(["cpu"], [("usage", &usage), ("comm", &comm)]),
+            Ok(())
+        );
+        assert_eq!(schema.validate([], [("usage", &usage)]), Ok(()));
+        assert_eq!(
+            schema.validate(["pid"], [("usage", &usage)]),
+            Err("Undeclared tag pid".to_string())
+        );
+        assert_eq!(
+            schema.validate([], [("load", &usage)]),
+            Err("Undeclared field load".to_string())
+        );
+        assert_eq!(
+            schema.validate([], [("usage", &comm)]),
+            Err("Field usage is declared as float but got text".to_string())
+        );
+        assert_eq!(schema.fields_desc(), "comm:text,usage:float");
+    }
+
+    #[test]
+    fn test_declare() {
+        assert!(Schema::new(vec!["usage".to_string()], vec![], vec![]).is_err());
+
+        let mut schemas = Schemas::default();
+        assert_eq!(schemas.declare("cpu".to_string(), schema()), Ok(true));
+        assert_eq!(schemas.declare("cpu".to_string(), schema()), Ok(false));
+        let other = Schema::new(vec!["usage".to_string()], vec![], vec![FieldType::Int]).unwrap();
+        assert!(schemas.declare("cpu".to_string(), other).is_err());
+    }
+}
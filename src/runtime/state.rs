@@ -14,22 +14,109 @@
 
 use host_op_perf::PerfCtx;
 use host_op_system::SysCtx;
-use wasmtime::component::ResourceTable;
+use wasmtime::{ResourceLimiter, StoreLimits, component::ResourceTable};
 use wasmtime_wasi::{WasiCtx, WasiView};
+use wasmtime_wasi_http::{
+    HttpResult, WasiHttpCtx, WasiHttpView,
+    bindings::http::types::ErrorCode,
+    body::HyperOutgoingBody,
+    types::{HostFutureIncomingResponse, OutgoingRequestConfig, default_send_request},
+};
 
-use super::DataExportCtx;
+use super::{
+    DataExportCtx,
+    host::{
+        blackbox::BlackBoxCtx, bursts::BurstsCtx, bus::BusCtx, cancellation::CancelCtx,
+        capture::CaptureCtx, certificates::CertificatesCtx, history::HistoryCtx,
+        integrity::IntegrityCtx, introspect::IntrospectCtx, kernel_events::KernelEventsCtx,
+        kubernetes::KubernetesCtx, log::LogCtx, msr::MsrCtx, pipeline::PipelineCtx,
+        process_events::ProcessEventsCtx, scheduling::SchedulingCtx, store::StoreCtx,
+        symbolize::SymbolizeCtx, sysfs::SysfsCtx, task_result::TaskResultCtx,
+    },
+    policy::HttpPolicy,
+    probe::ProbeCtx,
+};
 
 pub struct PshState {
     #[allow(dead_code)]
     pub name: String,
     pub table: ResourceTable,
     pub wasi_ctx: WasiCtx,
+    pub http_ctx: WasiHttpCtx,
+    /// every request is refused if `None`
+    pub http_policy: Option<HttpPolicy>,
     pub perf_ctx: PerfCtx,
     pub sys_ctx: SysCtx,
     pub data_export_ctx: DataExportCtx,
+    pub scheduling_ctx: SchedulingCtx,
+    pub blackbox_ctx: BlackBoxCtx,
+    pub bursts_ctx: BurstsCtx,
+    pub bus_ctx: BusCtx,
+    pub cancel_ctx: CancelCtx,
+    pub kernel_events_ctx: KernelEventsCtx,
+    pub integrity_ctx: IntegrityCtx,
+    pub introspect_ctx: IntrospectCtx,
+    pub certificates_ctx: CertificatesCtx,
+    pub history_ctx: HistoryCtx,
+    pub store_ctx: StoreCtx,
+    pub process_events_ctx: ProcessEventsCtx,
+    pub symbolize_ctx: SymbolizeCtx,
+    pub kubernetes_ctx: KubernetesCtx,
+    pub log_ctx: LogCtx,
+    pub pipeline_ctx: PipelineCtx,
+    pub msr_ctx: MsrCtx,
+    pub sysfs_ctx: SysfsCtx,
+    pub task_result_ctx: TaskResultCtx,
+    pub capture_ctx: CaptureCtx,
+    pub probe_ctx: ProbeCtx,
+    pub limits: MeteredLimits,
     // TODO: add more context for modules
 }
 
+/// [`StoreLimits`] keeping how much linear memory the component grew to.
+#[derive(Default)]
+pub struct MeteredLimits {
+    pub limits: StoreLimits,
+    /// in bytes, of every memory together, which never shrink
+    pub memory: usize,
+}
+
+impl ResourceLimiter for MeteredLimits {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        let allowed = self.limits.memory_growing(current, desired, maximum)?;
+        if allowed {
+            self.memory += desired.saturating_sub(current);
+        }
+        Ok(allowed)
+    }
+
+    fn table_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        self.limits.table_growing(current, desired, maximum)
+    }
+
+    fn instances(&self) -> usize {
+        self.limits.instances()
+    }
+
+    fn tables(&self) -> usize {
+        self.limits.tables()
+    }
+
+    fn memories(&self) -> usize {
+        self.limits.memories()
+    }
+}
+
 impl WasiView for PshState {
     fn table(&mut self) -> &mut ResourceTable {
         &mut self.table
@@ -39,3 +126,33 @@ impl WasiView for PshState {
         &mut self.wasi_ctx
     }
 }
+
+impl WasiHttpView for PshState {
+    fn ctx(&mut self) -> &mut WasiHttpCtx {
+        &mut self.http_ctx
+    }
+
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+
+    fn send_request(
+        &mut self,
+        request: http::Request<HyperOutgoingBody>,
+        config: OutgoingRequestConfig,
+    ) -> HttpResult<HostFutureIncomingResponse> {
+        let uri = request.uri();
+        let https = config.use_tls;
+        let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+        let allowed = self
+            .http_policy
+            .as_ref()
+            .zip(uri.host())
+            .is_some_and(|(policy, host)| policy.allows(https, host, port));
+        if !allowed {
+            tracing::debug!("Denied HTTP request to {uri}");
+            return Err(ErrorCode::HttpRequestDenied.into());
+        }
+        Ok(default_send_request(request, config))
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{borrow::Cow, sync::Arc, thread};
+
+use bytes::Bytes;
+use chrono::Utc;
+use crossbeam::channel::{self, Receiver, Sender, TrySendError};
+use influxdb_line_protocol::LineProtocolBuilder;
+use psh_proto::{Data, DataType, ExportDataReq};
+use tokio::runtime::Handle;
+use wasmtime_wasi::{HostOutputStream, StdoutStream, StreamResult, Subscribe};
+
+use crate::services::{
+    export_stats::{self, Backend},
+    rpc::RpcClient,
+};
+
+/// Longer lines are cut, the rest goes on the next line.
+const MAX_LINE_LEN: usize = 4096;
+// lines buffered for the server before new ones are dropped
+const FORWARD_QUEUE: usize = 4096;
+// lines sent in one request
+const FORWARD_BATCH: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Stdout => "stdout",
+            Self::Stderr => "stderr",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Line {
+    component: Arc<str>,
+    task_id: Option<Arc<str>>,
+    stream: Stream,
+    text: String,
+    ns_ts: i64,
+}
+
+/// Sends output lines of components to the server.
+#[derive(Clone)]
+pub struct Forwarder {
+    tx: Sender<Line>,
+}
+
+impl Forwarder {
+    pub fn spawn(rt: Handle, client: RpcClient, instance_id: String) -> Self {
+        let (tx, rx) = channel::bounded(FORWARD_QUEUE);
+        thread::spawn(move || forward(&rt, client, &instance_id, &rx));
+        Self { tx }
+    }
+
+    fn send(&self, line: Line) {
+        let stats = export_stats::global(Backend::ComponentOutput);
+        stats.accept(1);
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(line) {
+            stats.dropped(1);
+        }
+    }
+}
+
+fn forward(rt: &Handle, client: RpcClient, instance_id: &str, rx: &Receiver<Line>) {
+    let stats = export_stats::global(Backend::ComponentOutput);
+    while let Ok(first) = rx.recv() {
+        let lines: Vec<_> = std::iter::once(first)
+            .chain(rx.try_iter().take(FORWARD_BATCH - 1))
+            .collect();
+        let req = ExportDataReq {
+            task_id: String::new(),
+            data: lines
+                .iter()
+                .map(|it| Data {
+                    ty: DataType::LineProtocol as _,
+                    bytes: to_line_protocol(it, instance_id),
+                })
+                .collect(),
+        };
+        let mut client = client.clone();
+        if let Err(e) = rt.block_on(client.export_data_accounted(req, None, stats)) {
+            tracing::error!("Failed to send component output: {e}");
+        }
+    }
+}
+
+fn to_line_protocol(line: &Line, instance_id: &str) -> Vec<u8> {
+    let lp = LineProtocolBuilder::new()
+        .measurement("psh_component_output")
+        .tag("instance_id", instance_id)
+        .tag("component", &*line.component)
+        .tag("stream", line.stream.name());
+    let lp = match line.task_id.as_deref() {
+        Some(task_id) => lp.tag("task_id", task_id),
+        None => lp,
+    };
+    // redacted like every other text leaving the host
+    let text = host_op_system::redact::global().map_or(Cow::Borrowed(line.text.as_str()), |it| {
+        it.redact(&line.text)
+    });
+    lp.field("line", &*text)
+        .timestamp(line.ns_ts)
+        .close_line()
+        .build()
+}
+
+/// Stdout or stderr of a component, logged line by line with a
+/// `component` field instead of being written to the daemon's own.
+#[derive(Clone)]
+pub struct LogOutput {
+    component: Arc<str>,
+    task_id: Option<Arc<str>>,
+    stream: Stream,
+    forwarder: Option<Forwarder>,
+}
+
+impl LogOutput {
+    pub fn new(
+        component: &str,
+        task_id: Option<&str>,
+        stream: Stream,
+        forwarder: Option<Forwarder>,
+    ) -> Self {
+        Self {
+            component: component.into(),
+            task_id: task_id.map(Into::into),
+            stream,
+            forwarder,
+        }
+    }
+
+    fn emit(&self, bytes: &[u8]) {
+        let text = String::from_utf8_lossy(bytes);
+        let text = text.trim_end_matches('\r');
+        match self.stream {
+            Stream::Stdout => {
+                tracing::info!(component = %self.component, stream = "stdout", "{text}")
+            }
+            Stream::Stderr => {
+                tracing::warn!(component = %self.component, stream = "stderr", "{text}")
+            }
+        }
+        if let Some(forwarder) = &self.forwarder {
+            forwarder.send(Line {
+                component: Arc::clone(&self.component),
+                task_id: self.task_id.clone(),
+                stream: self.stream,
+                text: text.to_string(),
+                ns_ts: Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+            });
+        }
+    }
+}
+
+impl StdoutStream for LogOutput {
+    fn stream(&self) -> Box<dyn HostOutputStream> {
+        Box::new(LineWriter {
+            output: self.clone(),
+            pending: Vec::new(),
+        })
+    }
+
+    fn isatty(&self) -> bool {
+        false
+    }
+}
+
+/// Buffers the output until a line is complete.
+struct LineWriter {
+    output: LogOutput,
+    pending: Vec<u8>,
+}
+
+impl LineWriter {
+    fn push(&mut self, mut bytes: &[u8]) {
+        while let Some(pos) = bytes.iter().position(|it| *it == b'\n') {
+            self.pending.extend_from_slice(&bytes[..pos]);
+            self.take_line();
+            bytes = &bytes[pos + 1..];
+        }
+        self.pending.extend_from_slice(bytes);
+        while self.pending.len() >= MAX_LINE_LEN {
+            let rest = self.pending.split_off(MAX_LINE_LEN);
+            self.take_line();
+            self.pending = rest;
+        }
+    }
+
+    fn take_line(&mut self) {
+        self.output.emit(&self.pending);
+        self.pending.clear();
+    }
+}
+
+impl Drop for LineWriter {
+    fn drop(&mut self) {
+        if !self.pending.is_empty() {
+            self.take_line();
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Subscribe for LineWriter {
+    async fn ready(&mut self) {}
+}
+
+impl HostOutputStream for LineWriter {
+    fn write(&mut self, bytes: Bytes) -> StreamResult<()> {
+        self.push(&bytes);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> StreamResult<()> {
+        Ok(())
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        Ok(usize::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LineWriter, LogOutput, MAX_LINE_LEN, Stream};
+
+    #[test]
+    fn test_line_writer() {
+        let mut writer = LineWriter {
+            output: LogOutput::new("cpu-top", None, Stream::Stdout, None),
+            pending: Vec::new(),
+        };
+        writer.push(b"first\nsec");
+        assert_eq!(writer.pending, b"sec");
+        writer.push(b"ond\n");
+        assert!(writer.pending.is_empty());
+
+        writer.push(&vec![b'x'; MAX_LINE_LEN + 10]);
+        assert_eq!(writer.pending.len(), 10);
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    sync::mpsc::channel,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use super::{Cancellations, Task, TaskSender};
+use crate::{
+    config::{ComponentConfig, EngineConfig, RestartPolicy},
+    ledger::RunStatus,
+};
+
+/// Delays between restarts, doubled for every run in a row that did not
+/// last long.
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(engine: &EngineConfig) -> Self {
+        let initial = Duration::from_millis(engine.restart_backoff_ms);
+        Self {
+            initial,
+            max: Duration::from_millis(engine.restart_backoff_max_ms).max(initial),
+            current: initial,
+        }
+    }
+
+    /// The delay before the next run, the previous one lasted `ran`.
+    pub fn next(&mut self, ran: Duration) -> Duration {
+        if ran >= self.max {
+            self.current = self.initial;
+        }
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+}
+
+const fn restarts(policy: RestartPolicy, status: RunStatus) -> bool {
+    match policy {
+        RestartPolicy::Never => false,
+        RestartPolicy::OnFailure => !matches!(status, RunStatus::Success),
+        RestartPolicy::Always => true,
+    }
+}
+
+/// Runs the components and starts them again by their `restart` once a run
+/// ended, until the task runtime is gone or every task was cancelled.
+///
+/// Every run starts from the file, a component that fails to load counts as
+/// failed.
+pub fn spawn(
+    sender: TaskSender,
+    cancellations: Cancellations,
+    components: Vec<ComponentConfig>,
+    backoff: Backoff,
+) -> Result<Vec<JoinHandle<()>>> {
+    components
+        .into_iter()
+        .map(|component| {
+            let (sender, cancellations) = (sender.clone(), cancellations.clone());
+            let handle = thread::Builder::new()
+                .name("psh-supervisor".to_string())
+                .spawn(move || supervise(&sender, &cancellations, &component, backoff))?;
+            Ok(handle)
+        })
+        .collect()
+}
+
+fn supervise(
+    sender: &TaskSender,
+    cancellations: &Cancellations,
+    component: &ComponentConfig,
+    mut backoff: Backoff,
+) {
+    let name = component.name();
+    let mut first = true;
+    loop {
+        let start = Instant::now();
+        let status = match Task::local(component) {
+            Ok(task) => {
+                let (tx, rx) = channel();
+                let task = Task {
+                    done: Some(tx),
+                    warm: !first,
+                    ..task
+                };
+                if sender.schedule(task).is_err() {
+                    // the task runtime is gone
+                    return;
+                }
+                // dropped without a status if the task thread panicked
+                rx.recv().unwrap_or(RunStatus::Failed)
+            }
+            Err(e) => {
+                tracing::warn!("Failed to start {name}: {e:#}");
+                RunStatus::Failed
+            }
+        };
+        first = false;
+        if cancellations.is_closed() || !restarts(component.restart, status) {
+            return;
+        }
+        let delay = backoff.next(start.elapsed());
+        tracing::warn!(
+            "Component {name} ended as {}, restarting it in {delay:?}",
+            status.as_str()
+        );
+        thread::sleep(delay);
+        if cancellations.is_closed() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Backoff, restarts};
+    use crate::{
+        config::{EngineConfig, RestartPolicy},
+        ledger::RunStatus,
+    };
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(&EngineConfig {
+            restart_backoff_ms: 1000,
+            restart_backoff_max_ms: 5000,
+            ..Default::default()
+        });
+        let quick = Duration::from_millis(10);
+        let secs = |n| Duration::from_secs(n);
+        assert_eq!(backoff.next(quick), secs(1));
+        assert_eq!(backoff.next(quick), secs(2));
+        assert_eq!(backoff.next(quick), secs(4));
+        assert_eq!(backoff.next(quick), secs(5));
+        assert_eq!(backoff.next(quick), secs(5));
+        // ran long enough to start over
+        assert_eq!(backoff.next(secs(5)), secs(1));
+        assert_eq!(backoff.next(quick), secs(2));
+    }
+
+    #[test]
+    fn test_restarts() {
+        assert!(!restarts(RestartPolicy::Never, RunStatus::Failed));
+        assert!(restarts(RestartPolicy::OnFailure, RunStatus::Failed));
+        assert!(restarts(RestartPolicy::OnFailure, RunStatus::TimedOut));
+        assert!(!restarts(RestartPolicy::OnFailure, RunStatus::Success));
+        assert!(restarts(RestartPolicy::Always, RunStatus::Success));
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail};
+
+use super::Task;
+use crate::config::{ExportRateConfig, TenantConfig};
+
+const QUOTA_WINDOW: Duration = Duration::from_secs(60);
+
+// tenant of server tasks no other tenant claims, with the defaults of
+// [[tenants]] unless configured
+const DEFAULT_TENANT: &str = "default";
+
+/// Limits the bytes a tenant may export per minute, shared by all its tasks.
+pub struct ExportQuota {
+    bytes_per_min: usize,
+    /// start of the current window and bytes used in it
+    window: Mutex<(Instant, usize)>,
+}
+
+impl ExportQuota {
+    pub fn new(bytes_per_min: usize) -> Self {
+        Self {
+            bytes_per_min,
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    pub const fn bytes_per_min(&self) -> usize {
+        self.bytes_per_min
+    }
+
+    /// Returns `false` if exporting `bytes` more would exceed the quota.
+    pub fn try_consume(&self, bytes: usize) -> bool {
+        self.try_consume_at(bytes, Instant::now())
+    }
+
+    fn try_consume_at(&self, bytes: usize, now: Instant) -> bool {
+        let mut window = self.window.lock().unwrap();
+        if now.saturating_duration_since(window.0) >= QUOTA_WINDOW {
+            *window = (now, 0);
+        }
+        if window.1 + bytes > self.bytes_per_min {
+            return false;
+        }
+        window.1 += bytes;
+        true
+    }
+}
+
+pub struct Tenant {
+    pub name: String,
+    pub task_id_prefix: Option<String>,
+    pub allow_perf_op: bool,
+    pub allow_system_op: bool,
+    pub allow_environ: bool,
+    pub allow_data_export: bool,
+    pub allow_capture: bool,
+    pub allow_probe: bool,
+    pub allow_ping: bool,
+    pub allow_msr: bool,
+    pub allow_sysfs: bool,
+    pub allow_integrity_baseline: bool,
+    /// tags attached to everything the tenant exports, `tenant` first
+    pub labels: Vec<(String, String)>,
+    pub quota: Option<ExportQuota>,
+    /// of tasks of the server, which can't set their own
+    pub export_rate: Option<ExportRateConfig>,
+}
+
+impl From<&TenantConfig> for Tenant {
+    fn from(value: &TenantConfig) -> Self {
+        let labels = [("tenant".to_string(), value.name.clone())]
+            .into_iter()
+            .chain(value.labels.clone())
+            .collect();
+        Self {
+            name: value.name.clone(),
+            task_id_prefix: value.task_id_prefix.clone(),
+            allow_perf_op: value.allow_perf_op,
+            allow_system_op: value.allow_system_op,
+            allow_environ: value.allow_environ,
+            allow_data_export: value.allow_data_export,
+            allow_capture: value.allow_capture,
+            allow_probe: value.allow_probe,
+            allow_ping: value.allow_ping,
+            allow_msr: value.allow_msr,
+            allow_sysfs: value.allow_sysfs,
+            allow_integrity_baseline: value.allow_integrity_baseline,
+            labels,
+            quota: value.export_quota.map(ExportQuota::new),
+            export_rate: value.export_rate,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Tenants(Vec<Arc<Tenant>>);
+
+impl Tenants {
+    pub fn new(cfgs: &[TenantConfig]) -> Result<Self> {
+        let mut tenants: Vec<Arc<Tenant>> = Vec::with_capacity(cfgs.len());
+        for cfg in cfgs {
+            if cfg.name.is_empty() {
+                bail!("Tenant name must not be empty");
+            }
+            if tenants.iter().any(|it| it.name == cfg.name) {
+                bail!("Duplicated tenant {}", cfg.name);
+            }
+            tenants.push(Arc::new(cfg.into()));
+        }
+        if !tenants.iter().any(|it| it.name == DEFAULT_TENANT) {
+            let cfg = TenantConfig {
+                name: DEFAULT_TENANT.to_string(),
+                ..Default::default()
+            };
+            tenants.push(Arc::new((&cfg).into()));
+        }
+        Ok(Self(tenants))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Tenant>> {
+        self.0.iter().find(|it| it.name == name).cloned()
+    }
+
+    /// The tenant `task` belongs to, `None` means it is unrestricted, which
+    /// only components of the config file are.
+    ///
+    /// An explicit tenant wins, otherwise the task id is matched against
+    /// the tenants' `task_id_prefix`, tasks of the server no tenant claims
+    /// belong to the `default` one.
+    pub fn resolve(&self, task: &Task) -> Result<Option<Arc<Tenant>>> {
+        if let Some(name) = &task.tenant {
+            return match self.get(name) {
+                Some(tenant) => Ok(Some(tenant)),
+                None => bail!("Unknown tenant {name}"),
+            };
+        }
+        let Some(id) = &task.id else {
+            return Ok(None);
+        };
+        Ok(self
+            .0
+            .iter()
+            .find(|it| {
+                it.task_id_prefix
+                    .as_ref()
+                    .is_some_and(|prefix| id.starts_with(prefix))
+            })
+            .or_else(|| self.0.iter().find(|it| it.name == DEFAULT_TENANT))
+            .cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use chrono::Utc;
+
+    use super::{ExportQuota, Tenants};
+    use crate::{config::TenantConfig, runtime::Task};
+
+    fn task(id: Option<&str>, tenant: Option<&str>) -> Task {
+        Task {
+            id: id.map(str::to_string),
+            wasm_component: vec![],
+            wasm_component_args: vec![],
+            wasm_component_envs: vec![],
+            end_time: Utc::now(),
+            tenant: tenant.map(str::to_string),
+            running: None,
+            stop: None,
+            max_cpu_ms: None,
+            memory_limit: None,
+            rate_limits: Default::default(),
+            warm: false,
+            component: None,
+            preopens: vec![],
+            seed: None,
+            done: None,
+            input: vec![],
+        }
+    }
+
+    #[test]
+    fn test_resolve_tenant() {
+        let tenants = Tenants::new(&[TenantConfig {
+            name: "acme".to_string(),
+            task_id_prefix: Some("acme-".to_string()),
+            ..Default::default()
+        }])
+        .unwrap();
+
+        let name = |task| tenants.resolve(&task).unwrap().map(|it| it.name.clone());
+        assert_eq!(name(task(Some("acme-1"), None)).as_deref(), Some("acme"));
+        assert_eq!(name(task(None, Some("acme"))).as_deref(), Some("acme"));
+        assert_eq!(
+            name(task(Some("other-1"), None)).as_deref(),
+            Some("default")
+        );
+        assert_eq!(name(task(None, None)), None);
+        assert!(tenants.resolve(&task(None, Some("other"))).is_err());
+
+        let default = tenants
+            .resolve(&task(Some("other-1"), None))
+            .unwrap()
+            .unwrap();
+        assert!(!default.allow_environ && !default.allow_capture && !default.allow_sysfs);
+
+        let tenants = Tenants::new(&[TenantConfig {
+            name: "default".to_string(),
+            allow_capture: true,
+            ..Default::default()
+        }])
+        .unwrap();
+        let default = tenants
+            .resolve(&task(Some("other-1"), None))
+            .unwrap()
+            .unwrap();
+        assert!(default.allow_capture);
+    }
+
+    #[test]
+    fn test_export_quota() {
+        let quota = ExportQuota::new(100);
+        let now = Instant::now();
+        assert!(quota.try_consume_at(60, now));
+        assert!(!quota.try_consume_at(60, now));
+        assert!(quota.try_consume_at(40, now));
+        assert!(quota.try_consume_at(60, now + Duration::from_secs(60)));
+    }
+}
@@ -12,11 +12,12 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
-use std::{fs, path::Path, process::Command};
+use std::{collections::BTreeMap, fs, path::Path, process::Command};
 
 use anyhow::Context as _;
 
-use super::{PshEngine, PshEngineBuilder};
+use super::{PshEngine, PshEngineBuilder, Task};
+use crate::config::ComponentConfig;
 
 // FIXME(Chengdong Li): This function is no longer used in `cargo test` as
 // host-op-perf requires root permission to run test. But there is often no `cargo`
@@ -51,7 +52,8 @@ fn test_wasm_component(wasm: &str) {
     let path = format!("./test_resources/profiling/{wasm}/target/wasm32-wasip1/debug/{wasm}.wasm");
     assert!(Path::new(&path).exists());
     let binary = fs::read(path).unwrap();
-    assert!(engine.run(&binary, 60 * 1000).is_ok());
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    assert!(rt.block_on(engine.run(&binary, 60 * 1000)).is_ok());
 }
 
 #[ignore]
@@ -133,3 +135,57 @@ fn test_perf_counter_group() {
     compile_component(&format!("./test_resources/profiling/{wasm}"));
     test_wasm_component(wasm);
 }
+
+#[test]
+fn test_unwound() {
+    let report = super::unwound(|| panic!("host function failed"));
+    let err = report.result.unwrap_err();
+    assert!(err.to_string().contains("host function failed"));
+}
+
+#[test]
+fn test_tightest() {
+    let limits = |pairs: &[(&str, u32)]| {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), *v))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let engine = limits(&[("process", 10), ("perf", 100)]);
+    let task = limits(&[("process", 1000), ("perf", 5), ("net", 2)]);
+    assert_eq!(
+        super::tightest(&engine, &task),
+        limits(&[("process", 10), ("perf", 5), ("net", 2)])
+    );
+}
+
+#[test]
+fn test_same_wasm_with_args() {
+    let path = std::env::temp_dir().join(format!("psh-same-wasm-{}.wasm", std::process::id()));
+    fs::write(&path, b"\0asm\x01\0\0\0").unwrap();
+    let component = |args: &[&str]| ComponentConfig {
+        path: path.display().to_string(),
+        sha256: None,
+        name: None,
+        args: args.iter().map(|it| it.to_string()).collect(),
+        env: Default::default(),
+        tenant: None,
+        schedule: None,
+        after: None,
+        max_cpu_ms: None,
+        memory_limit: None,
+        rate_limits: Default::default(),
+        preopens: vec![],
+        seed: None,
+        restart: Default::default(),
+    };
+    let cpu = Task::local(&component(&["--cpu"])).unwrap();
+    let memory = Task::local(&component(&["--memory"])).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(cpu.wasm_component, memory.wasm_component);
+    assert_eq!(cpu.wasm_component_args[1..], ["--cpu"]);
+    assert_eq!(memory.wasm_component_args[1..], ["--memory"]);
+    // runs of one component count as one
+    assert_eq!(cpu.program(), memory.program());
+}
@@ -0,0 +1,236 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::config::ExportRateConfig;
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+// past this many components, those not running lose their window
+const MAX_RATES: usize = 256;
+
+/// Which limit of [`ExportRate`] an export ran into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Samples,
+    Bytes,
+}
+
+/// Why an export was refused by [`ExportRate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Throttled {
+    pub limit: Limit,
+    pub per_sec: u64,
+    /// until the current window ends
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for Throttled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self.limit {
+            Limit::Samples => "samples",
+            Limit::Bytes => "bytes",
+        };
+        write!(
+            f,
+            "throttled: export rate of {} {unit}/s exceeded, retry after {}ms",
+            self.per_sec,
+            self.retry_after.as_millis()
+        )
+    }
+}
+
+struct Window {
+    start: Instant,
+    samples: u64,
+    bytes: u64,
+    last: Option<Throttled>,
+}
+
+/// Limits the samples and bytes the runs of a component may export per
+/// second, so a noisy component can't crowd out the others on the channel
+/// to the server.
+pub struct ExportRate {
+    samples_per_sec: u64,
+    bytes_per_sec: u64,
+    window: Mutex<Window>,
+}
+
+impl ExportRate {
+    /// `None` if `cfg` limits nothing.
+    pub fn new(cfg: &ExportRateConfig) -> Option<Self> {
+        if cfg.samples_per_sec == 0 && cfg.bytes_per_sec == 0 {
+            return None;
+        }
+        Some(Self {
+            samples_per_sec: cfg.samples_per_sec.into(),
+            bytes_per_sec: cfg.bytes_per_sec,
+            window: Mutex::new(Window {
+                start: Instant::now(),
+                samples: 0,
+                bytes: 0,
+                last: None,
+            }),
+        })
+    }
+
+    /// Accounts an export of `samples` samples in `bytes` bytes, refused
+    /// if it would exceed either limit.
+    pub fn try_consume(&self, samples: u64, bytes: u64) -> Result<(), Throttled> {
+        self.try_consume_at(samples, bytes, Instant::now())
+    }
+
+    fn try_consume_at(&self, samples: u64, bytes: u64, now: Instant) -> Result<(), Throttled> {
+        let mut window = self.window.lock().unwrap();
+        if now.saturating_duration_since(window.start) >= RATE_WINDOW {
+            window.start = now;
+            window.samples = 0;
+            window.bytes = 0;
+        }
+        let over =
+            |limit: u64, used: u64, more: u64| (limit != 0 && used + more > limit).then_some(limit);
+        let exceeded = over(self.samples_per_sec, window.samples, samples)
+            .map(|it| (Limit::Samples, it))
+            .or_else(|| over(self.bytes_per_sec, window.bytes, bytes).map(|it| (Limit::Bytes, it)));
+        if let Some((limit, per_sec)) = exceeded {
+            let throttled = Throttled {
+                limit,
+                per_sec,
+                retry_after: RATE_WINDOW
+                    .saturating_sub(now.saturating_duration_since(window.start)),
+            };
+            window.last = Some(throttled);
+            return Err(throttled);
+        }
+        window.samples += samples;
+        window.bytes += bytes;
+        window.last = None;
+        Ok(())
+    }
+
+    /// Why the last export was refused, `None` if it was accepted.
+    pub fn last_throttled(&self) -> Option<Throttled> {
+        self.window.lock().unwrap().last
+    }
+
+    fn limits(&self, cfg: &ExportRateConfig) -> bool {
+        self.samples_per_sec == u64::from(cfg.samples_per_sec)
+            && self.bytes_per_sec == cfg.bytes_per_sec
+    }
+}
+
+/// The [`ExportRate`] of every component, shared by its runs side by side
+/// and one after the other, so restarting a component or running it twice
+/// doesn't double its rate.
+#[derive(Default)]
+pub struct ExportRates(Mutex<HashMap<String, Arc<ExportRate>>>);
+
+impl ExportRates {
+    /// The rate of `component`, a new one if its limits changed to `cfg`,
+    /// `None` if `cfg` limits nothing.
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn of(&self, component: &str, cfg: &ExportRateConfig) -> Option<Arc<ExportRate>> {
+        let mut rates = self.0.lock().unwrap();
+        let Some(rate) = ExportRate::new(cfg) else {
+            rates.remove(component);
+            return None;
+        };
+        match rates.get(component) {
+            Some(it) if it.limits(cfg) => return Some(Arc::clone(it)),
+            _ => {}
+        }
+        if rates.len() >= MAX_RATES {
+            rates.retain(|_, it| Arc::strong_count(it) > 1);
+        }
+        let rate = Arc::new(rate);
+        rates.insert(component.to_string(), Arc::clone(&rate));
+        Some(rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use super::{ExportRate, ExportRates, Limit};
+    use crate::config::ExportRateConfig;
+
+    #[test]
+    fn test_export_rate() {
+        assert!(ExportRate::new(&ExportRateConfig::default()).is_none());
+
+        let rate = ExportRate::new(&ExportRateConfig {
+            samples_per_sec: 2,
+            bytes_per_sec: 100,
+        })
+        .unwrap();
+        let now = Instant::now();
+        assert!(rate.try_consume_at(1, 10, now).is_ok());
+        assert!(rate.try_consume_at(1, 10, now).is_ok());
+        let throttled = rate.try_consume_at(1, 10, now).unwrap_err();
+        assert_eq!(throttled.limit, Limit::Samples);
+        assert_eq!(throttled.per_sec, 2);
+        assert_eq!(rate.last_throttled(), Some(throttled));
+
+        // files count against the bytes only
+        assert!(rate.try_consume_at(0, 80, now).is_ok());
+        assert!(rate.last_throttled().is_none());
+        let throttled = rate
+            .try_consume_at(0, 1, now + Duration::from_millis(400))
+            .unwrap_err();
+        assert_eq!(throttled.limit, Limit::Bytes);
+        assert_eq!(throttled.retry_after, Duration::from_millis(600));
+
+        let later = now + Duration::from_secs(1);
+        assert!(rate.try_consume_at(2, 100, later).is_ok());
+    }
+
+    #[test]
+    fn test_export_rates() {
+        let rates = ExportRates::default();
+        let cfg = ExportRateConfig {
+            samples_per_sec: 1,
+            bytes_per_sec: 0,
+        };
+        assert!(rates.of("cpu-top", &ExportRateConfig::default()).is_none());
+
+        // two runs of a component share its window, others have their own
+        let first = rates.of("cpu-top", &cfg).unwrap();
+        let second = rates.of("cpu-top", &cfg).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(first.try_consume(1, 10).is_ok());
+        let throttled = second.try_consume(1, 10).unwrap_err();
+        assert_eq!(throttled.limit, Limit::Samples);
+        assert!(
+            rates
+                .of("mem-top", &cfg)
+                .unwrap()
+                .try_consume(1, 10)
+                .is_ok()
+        );
+
+        let raised = ExportRateConfig {
+            samples_per_sec: 2,
+            ..cfg
+        };
+        assert!(!Arc::ptr_eq(&first, &rates.of("cpu-top", &raised).unwrap()));
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use nix::time::{ClockId, clock_gettime};
+
+use crate::config::{ClockSource, SkewAction, TimestampConfig};
+
+/// Assigns and validates the timestamps of exported data.
+pub struct Timestamper {
+    cfg: TimestampConfig,
+    /// wall clock and boot time clock read at startup
+    anchor: (Duration, Duration),
+}
+
+impl Timestamper {
+    pub fn new(cfg: TimestampConfig) -> Self {
+        Self {
+            cfg,
+            anchor: (realtime(), boottime()),
+        }
+    }
+
+    /// Timestamp in nanoseconds of data exported with `guest`, `None` lets
+    /// the server assign it.
+    pub fn assign(&self, guest: Option<u64>) -> Result<Option<u64>, String> {
+        let now = self.now();
+        let reference = now.unwrap_or_else(|| as_nanos(realtime()));
+        self.assign_at(guest, now, reference)
+    }
+
+    fn now(&self) -> Option<u64> {
+        let now = match self.cfg.clock {
+            ClockSource::None => return None,
+            ClockSource::Realtime => realtime(),
+            ClockSource::Monotonic => self.monotonic(),
+            ClockSource::Ntp if ntp_synchronized() => realtime(),
+            ClockSource::Ntp => self.monotonic(),
+        };
+        Some(as_nanos(now))
+    }
+
+    fn monotonic(&self) -> Duration {
+        let (realtime, boottime_at_start) = self.anchor;
+        realtime + boottime().saturating_sub(boottime_at_start)
+    }
+
+    fn assign_at(
+        &self,
+        guest: Option<u64>,
+        now: Option<u64>,
+        reference: u64,
+    ) -> Result<Option<u64>, String> {
+        let Some(ts) = guest.filter(|_| !self.cfg.override_guest) else {
+            return Ok(now);
+        };
+        let max_skew = as_nanos(Duration::from_secs(self.cfg.max_skew));
+        let skew = ts.abs_diff(reference);
+        if max_skew == 0 || skew <= max_skew {
+            return Ok(Some(ts));
+        }
+        match self.cfg.on_skew {
+            SkewAction::Clamp => Ok(Some(ts.clamp(
+                reference.saturating_sub(max_skew),
+                reference.saturating_add(max_skew),
+            ))),
+            SkewAction::Replace => Ok(Some(reference)),
+            SkewAction::Reject => Err(format!(
+                "Timestamp {ts} is {}s off the host clock",
+                skew / 1_000_000_000
+            )),
+        }
+    }
+}
+
+const fn as_nanos(duration: Duration) -> u64 {
+    duration.as_nanos() as u64
+}
+
+fn realtime() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Unlike the monotonic clock, the boot time clock keeps counting while the
+/// system is suspended.
+fn boottime() -> Duration {
+    clock_gettime(ClockId::CLOCK_BOOTTIME)
+        .map(Duration::from)
+        .unwrap_or_default()
+}
+
+fn ntp_synchronized() -> bool {
+    // SAFETY: `timex` is plain data, with `modes` zeroed adjtimex only reads
+    // the kernel clock state into it
+    let state = unsafe {
+        let mut timex: libc::timex = std::mem::zeroed();
+        libc::adjtimex(&mut timex)
+    };
+    state != -1 && state != libc::TIME_ERROR
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Timestamper;
+    use crate::config::{ClockSource, SkewAction, TimestampConfig};
+
+    const SEC: u64 = 1_000_000_000;
+
+    fn timestamper(override_guest: bool, on_skew: SkewAction) -> Timestamper {
+        Timestamper::new(TimestampConfig {
+            clock: ClockSource::Realtime,
+            override_guest,
+            max_skew: 60,
+            on_skew,
+        })
+    }
+
+    #[test]
+    fn test_assign() {
+        let now = 1_700_000_000 * SEC;
+        let clamp = timestamper(false, SkewAction::Clamp);
+        assert_eq!(clamp.assign_at(None, Some(now), now), Ok(Some(now)));
+        assert_eq!(clamp.assign_at(None, None, now), Ok(None));
+        assert_eq!(
+            clamp.assign_at(Some(now - 10 * SEC), Some(now), now),
+            Ok(Some(now - 10 * SEC))
+        );
+        assert_eq!(
+            clamp.assign_at(Some(now - 3600 * SEC), Some(now), now),
+            Ok(Some(now - 60 * SEC))
+        );
+        assert_eq!(
+            clamp.assign_at(Some(now + 3600 * SEC), Some(now), now),
+            Ok(Some(now + 60 * SEC))
+        );
+
+        let replace = timestamper(false, SkewAction::Replace);
+        assert_eq!(replace.assign_at(Some(0), Some(now), now), Ok(Some(now)));
+
+        let reject = timestamper(false, SkewAction::Reject);
+        assert_eq!(
+            reject.assign_at(Some(now - 120 * SEC), Some(now), now),
+            Err("Timestamp 1699999880000000000 is 120s off the host clock".to_string())
+        );
+
+        let override_guest = timestamper(true, SkewAction::Reject);
+        assert_eq!(
+            override_guest.assign_at(Some(0), Some(now), now),
+            Ok(Some(now))
+        );
+    }
+
+    #[test]
+    fn test_monotonic_follows_wall_clock() {
+        let timestamper = Timestamper::new(TimestampConfig {
+            clock: ClockSource::Monotonic,
+            ..Default::default()
+        });
+        let monotonic = timestamper.now().unwrap();
+        let realtime = super::as_nanos(super::realtime());
+        assert!(monotonic.abs_diff(realtime) < SEC);
+    }
+}
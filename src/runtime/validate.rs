@@ -0,0 +1,302 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::fs;
+
+use anyhow::{Context, Result, bail};
+use wasmtime::component::Component;
+
+use super::{
+    DataExportCtx, PshEngineBuilder,
+    host::introspect::Linked,
+    policy::{self, InterfacePolicy, Policy},
+    tenant::{Tenant, Tenants},
+};
+use crate::config::Config;
+
+/// Imports only usable with a permission, the ones of `perf`, `system` and
+/// `data-export` are not even linked without it.
+const PERMISSIONS: &[(&str, Permission)] = &[
+    ("profiling:perf/*", Permission::PerfOp),
+    ("profiling:perf-ext/*", Permission::PerfOp),
+    ("profiling:system/*", Permission::SystemOp),
+    ("profiling:system-ext/*", Permission::SystemOp),
+    ("profiling:data-export/*", Permission::DataExport),
+    ("profiling:data-export-ext/*", Permission::DataExport),
+    ("profiling:host/capture", Permission::Capture),
+    ("profiling:probe/probe", Permission::Probe),
+    ("profiling:host/msr", Permission::Msr),
+    ("profiling:host/sysfs", Permission::Sysfs),
+    ("wasi:sockets/*", Permission::Sockets),
+    ("wasi:http/outgoing-handler", Permission::Http),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Permission {
+    PerfOp,
+    SystemOp,
+    DataExport,
+    Capture,
+    Probe,
+    Msr,
+    Sysfs,
+    Sockets,
+    Http,
+}
+
+impl Permission {
+    fn of(name: &str) -> Option<Self> {
+        let name = name.split_once('@').map_or(name, |(name, _)| name);
+        PERMISSIONS
+            .iter()
+            .find(|(pattern, _)| policy::matches(pattern, name))
+            .map(|(_, it)| *it)
+    }
+
+    /// Without a tenant every operation is allowed, as for components of
+    /// the config file.
+    fn granted(self, tenant: Option<&Tenant>, policy: &InterfacePolicy) -> bool {
+        let allow = |f: fn(&Tenant) -> bool| tenant.is_none_or(f);
+        match self {
+            Self::PerfOp => allow(|it| it.allow_perf_op),
+            Self::SystemOp => allow(|it| it.allow_system_op),
+            Self::DataExport => allow(|it| it.allow_data_export),
+            Self::Capture => allow(|it| it.allow_capture),
+            Self::Probe => allow(|it| it.allow_probe),
+            Self::Msr => allow(|it| it.allow_msr),
+            Self::Sysfs => allow(|it| it.allow_sysfs),
+            Self::Sockets => policy
+                .sockets
+                .as_ref()
+                .is_some_and(|it| it.tcp || it.udp || it.name_lookup),
+            Self::Http => policy.http.is_some(),
+        }
+    }
+
+    /// Whether the interface is linked at all without the permission.
+    const fn linked_without(self) -> bool {
+        !matches!(self, Self::PerfOp | Self::SystemOp | Self::DataExport)
+    }
+}
+
+impl std::fmt::Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::PerfOp => "allow_perf_op of the tenant",
+            Self::SystemOp => "allow_system_op of the tenant",
+            Self::DataExport => "allow_data_export of the tenant",
+            Self::Capture => "allow_capture of the tenant",
+            Self::Probe => "allow_probe of the tenant",
+            Self::Msr => "allow_msr of the tenant",
+            Self::Sysfs => "allow_sysfs of the tenant",
+            Self::Sockets => "sockets of the interface policy",
+            Self::Http => "http of the interface policy",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What the host makes of an import of a component.
+#[derive(Debug, PartialEq, Eq)]
+enum Verdict {
+    Ok,
+    /// linked with the permission only, calls fail without it
+    Needs {
+        permission: Permission,
+        granted: bool,
+    },
+    /// no version of psh links it
+    Missing,
+    /// refused by the interface policy, the component does not start
+    Denied,
+}
+
+impl Verdict {
+    fn of(name: &str, tenant: Option<&Tenant>, policy: &InterfacePolicy) -> Self {
+        let linked = Linked {
+            perf: true,
+            system: true,
+            data_export: true,
+        };
+        if !linked.provides(name) {
+            return Self::Missing;
+        }
+        if !policy.allows(name) {
+            return Self::Denied;
+        }
+        match Permission::of(name) {
+            Some(permission) => Self::Needs {
+                permission,
+                granted: permission.granted(tenant, policy),
+            },
+            None => Self::Ok,
+        }
+    }
+
+    /// Whether the component fails to start because of the import.
+    const fn fatal(&self) -> bool {
+        match self {
+            Self::Ok => false,
+            Self::Needs {
+                permission,
+                granted,
+            } => !granted && !permission.linked_without(),
+            Self::Missing | Self::Denied => true,
+        }
+    }
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ok => f.write_str("ok"),
+            Self::Needs {
+                permission,
+                granted: true,
+            } => write!(f, "ok, with {permission}"),
+            Self::Needs {
+                permission,
+                granted: false,
+            } if permission.linked_without() => {
+                write!(f, "calls fail, needs {permission}")
+            }
+            Self::Needs { permission, .. } => write!(f, "not linked, needs {permission}"),
+            Self::Missing => f.write_str("missing, no host interface of this name"),
+            Self::Denied => f.write_str("denied by the interface policy"),
+        }
+    }
+}
+
+/// Checks the imports of the component at `path` against the host without
+/// running it, as the component `name` of `tenant` would be run by the
+/// daemon, and fails if it could not start.
+pub fn run(path: &str, cfg: &Config, name: Option<&str>, tenant: Option<&str>) -> Result<()> {
+    let tenant = match tenant {
+        Some(tenant) => Some(
+            Tenants::new(&cfg.tenants)?
+                .get(tenant)
+                .with_context(|| format!("No tenant {tenant} in [[tenants]]"))?,
+        ),
+        None => None,
+    };
+    let policies = if cfg.engine.policy.is_empty() {
+        Policy::default()
+    } else {
+        Policy::read(&cfg.engine.policy)?
+    };
+    let policy = policies.of(name);
+
+    let builder = PshEngineBuilder::new()
+        .backtrace_details(cfg.engine.backtrace_details)
+        .allow_perf_op(Permission::PerfOp.granted(tenant.as_deref(), policy))
+        .allow_system_op(Permission::SystemOp.granted(tenant.as_deref(), policy))
+        .allow_data_export_op(
+            Permission::DataExport
+                .granted(tenant.as_deref(), policy)
+                .then_some(DataExportCtx {
+                    ctx: None,
+                    artifact: None,
+                    tenant: None,
+                }),
+        );
+    let engine = builder.build_engine()?;
+    let binary = fs::read(path).with_context(|| format!("Failed to read {path}"))?;
+    let component = Component::from_binary(&engine, &binary)
+        .with_context(|| format!("{path} is not a valid component"))?;
+
+    let mut imports: Vec<_> = component
+        .component_type()
+        .imports(&engine)
+        .map(|(name, _)| name.to_string())
+        .collect();
+    imports.sort_unstable();
+    let width = imports.iter().map(String::len).max().unwrap_or_default();
+    let mut fatal = 0;
+    for import in &imports {
+        let verdict = Verdict::of(import, tenant.as_deref(), policy);
+        if verdict.fatal() {
+            fatal += 1;
+        }
+        println!("{import:width$}  {verdict}");
+    }
+    if fatal > 0 {
+        bail!("{fatal} of {} imports can't be satisfied", imports.len());
+    }
+
+    // signatures and versions of the linked interfaces
+    builder
+        .build_linker(&engine)?
+        .instantiate_pre(&component)
+        .context("Failed to link the component")?;
+    println!("{path}: all {} imports can be satisfied", imports.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Permission, Verdict};
+    use crate::runtime::policy::{InterfacePolicy, SocketPolicy};
+
+    #[test]
+    fn test_verdict() {
+        let policy = InterfacePolicy {
+            deny: vec!["profiling:host/store".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            Verdict::of("wasi:cli/environment@0.2.0", None, &policy),
+            Verdict::Ok
+        );
+        assert_eq!(
+            Verdict::of("profiling:host/nonexistent@0.1.0", None, &policy),
+            Verdict::Missing
+        );
+        assert_eq!(
+            Verdict::of("profiling:host/store@0.1.0", None, &policy),
+            Verdict::Denied
+        );
+
+        let verdict = Verdict::of("wasi:sockets/tcp@0.2.0", None, &policy);
+        assert_eq!(
+            verdict,
+            Verdict::Needs {
+                permission: Permission::Sockets,
+                granted: false
+            }
+        );
+        assert!(!verdict.fatal());
+
+        let policy = InterfacePolicy {
+            sockets: Some(SocketPolicy {
+                tcp: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            Verdict::of("wasi:sockets/tcp@0.2.0", None, &policy),
+            Verdict::Needs {
+                permission: Permission::Sockets,
+                granted: true
+            }
+        );
+        assert_eq!(
+            Verdict::of("profiling:perf/counter@0.1.0", None, &policy),
+            Verdict::Needs {
+                permission: Permission::PerfOp,
+                granted: true
+            }
+        );
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::HashMap,
+    mem,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+use wasmtime::component::Component;
+
+use super::{PshEngineBuilder, Task, TaskSender};
+use crate::config::ComponentConfig;
+
+// how long a replaced component may take to notice it was stopped before
+// it is reported
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+const STOP_POLL: Duration = Duration::from_millis(10);
+
+/// A component and its current run.
+struct Watched {
+    component: ComponentConfig,
+    running: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Watched {
+    /// Starts a run from the file, once the previous one has stopped.
+    fn start(&mut self, sender: &TaskSender) -> Result<()> {
+        // read and compile before stopping, a broken file leaves the old run
+        // alone
+        let task = Task::local(&self.component)?;
+        if self.running.load(Ordering::Acquire) {
+            let engine = PshEngineBuilder::new().build_engine()?;
+            Component::from_binary(&engine, &task.wasm_component)
+                .with_context(|| format!("{} is not a valid component", self.component.path))?;
+        }
+
+        let previous = mem::replace(&mut self.running, Arc::new(AtomicBool::new(true)));
+        mem::replace(&mut self.stop, Arc::new(AtomicBool::new(false)))
+            .store(true, Ordering::Release);
+        let (running, stop) = (Arc::clone(&self.running), Arc::clone(&self.stop));
+        let task = Task {
+            running: Some(Arc::clone(&running)),
+            stop: Some(Arc::clone(&stop)),
+            ..task
+        };
+        if !previous.load(Ordering::Acquire) {
+            return sender.schedule(task);
+        }
+        // the watcher goes on meanwhile, a later change stops this run in
+        // turn before it even started
+        let sender = sender.clone();
+        let path = self.component.path.clone();
+        thread::Builder::new()
+            .name("psh-reload".to_string())
+            .spawn(move || {
+                let since = Instant::now();
+                let mut reported = false;
+                while previous.load(Ordering::Acquire) {
+                    if !reported && since.elapsed() > STOP_TIMEOUT {
+                        tracing::warn!("{path} is not reloaded until its previous run stops");
+                        reported = true;
+                    }
+                    thread::sleep(STOP_POLL);
+                }
+                if stop.load(Ordering::Acquire) {
+                    running.store(false, Ordering::Release);
+                    return;
+                }
+                if let Err(e) = sender.schedule(task) {
+                    tracing::warn!("Failed to reload {path}: {e:#}");
+                    running.store(false, Ordering::Release);
+                }
+            })?;
+        Ok(())
+    }
+}
+
+/// Runs the components and runs them again from the file whenever it is
+/// replaced, until the task runtime is gone.
+///
+/// Directories are watched rather than files, as deploying a component
+/// usually renames a new file over the old one.
+pub fn spawn(sender: TaskSender, components: Vec<ComponentConfig>) -> Result<JoinHandle<()>> {
+    let inotify = Inotify::init(InitFlags::IN_CLOEXEC).context("Failed to init inotify")?;
+    let mut dirs: HashMap<WatchDescriptor, PathBuf> = HashMap::new();
+    let mut watched = Vec::with_capacity(components.len());
+    for component in components {
+        let path = Path::new(&component.path);
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        if !dirs.values().any(|it| *it == dir) {
+            let wd = inotify
+                .add_watch(
+                    &dir,
+                    AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO,
+                )
+                .with_context(|| format!("Failed to watch {}", dir.display()))?;
+            dirs.insert(wd, dir);
+        }
+        let mut it = Watched {
+            component,
+            running: Arc::new(AtomicBool::new(false)),
+            stop: Arc::new(AtomicBool::new(false)),
+        };
+        it.start(&sender)?;
+        watched.push(it);
+    }
+
+    let handle = thread::Builder::new()
+        .name("psh-watcher".to_string())
+        .spawn(move || {
+            loop {
+                let events = match inotify.read_events() {
+                    Ok(events) => events,
+                    Err(nix::errno::Errno::EINTR) => continue,
+                    Err(e) => {
+                        tracing::error!("Watching components: {e}");
+                        return;
+                    }
+                };
+                let mut changed: Vec<usize> = vec![];
+                for event in events {
+                    let (Some(dir), Some(name)) = (dirs.get(&event.wd), event.name) else {
+                        continue;
+                    };
+                    let path = dir.join(name);
+                    changed.extend(
+                        watched
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, it)| same_file(&it.component.path, &path))
+                            .map(|(i, _)| i),
+                    );
+                }
+                changed.sort_unstable();
+                changed.dedup();
+                for i in changed {
+                    let it = &mut watched[i];
+                    tracing::info!("{} changed, reloading it", it.component.path);
+                    if let Err(e) = it.start(&sender) {
+                        tracing::warn!("Failed to reload {}: {e:#}", it.component.path);
+                    }
+                }
+            }
+        })?;
+    Ok(handle)
+}
+
+/// `path` as configured and the one of an event, which is joined to the
+/// watched directory.
+fn same_file(path: &str, changed: &Path) -> bool {
+    let path = Path::new(path);
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => path == changed,
+        _ => Path::new(".").join(path) == changed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::same_file;
+
+    #[test]
+    fn test_same_file() {
+        assert!(same_file("/opt/psh/a.wasm", Path::new("/opt/psh/a.wasm")));
+        assert!(!same_file("/opt/psh/a.wasm", Path::new("/opt/psh/b.wasm")));
+        assert!(same_file("a.wasm", Path::new("./a.wasm")));
+        assert!(same_file("./a.wasm", Path::new("./a.wasm")));
+    }
+}
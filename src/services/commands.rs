@@ -0,0 +1,238 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{fmt, fs, str::FromStr};
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{TimeZone, Utc};
+
+use crate::{
+    catalog::Store,
+    config::{self, CatalogConfig},
+    runtime::{Task, TaskRuntime, Tenants},
+};
+
+/// Separates commands carried in one header, metadata values can't hold
+/// newlines.
+const SEPARATOR: char = ';';
+
+/// Commands the server sends along with heartbeat responses, e.g.
+/// `x-psh-commands: flush; run cpu-top@1.2 --interval 5`.
+pub const HEADER: &str = "x-psh-commands";
+/// Sent with heartbeats so the server knows which commands it may send.
+pub const ACCEPT_HEADER: &str = "x-psh-accept-commands";
+pub const ACCEPTED: &str = "flush,reload-config,run,cancel";
+
+/// A command of the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServerCommand {
+    /// send buffered data of the running component now
+    Flush,
+    /// read the config file again and apply `[[tenants]]`
+    ReloadConfig,
+    /// run an installed component of the catalog, the latest version if
+    /// none is given
+    Run {
+        name: String,
+        version: Option<String>,
+        args: Vec<String>,
+    },
+    /// ask a running task of the server to wrap up, it is interrupted after
+    /// `[engine] cancel_grace_ms`
+    Cancel { task_id: String },
+}
+
+impl FromStr for ServerCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split_whitespace();
+        let command = match parts.next() {
+            Some("flush") => Self::Flush,
+            Some("reload-config") => Self::ReloadConfig,
+            Some("run") => {
+                let component = parts.next().ok_or_else(|| anyhow!("Missing component"))?;
+                let (name, version) = match component.split_once('@') {
+                    Some((name, version)) => (name, Some(version.to_string())),
+                    None => (component, None),
+                };
+                return Ok(Self::Run {
+                    name: name.to_string(),
+                    version,
+                    args: parts.map(str::to_string).collect(),
+                });
+            }
+            Some("cancel") => {
+                let task_id = parts.next().ok_or_else(|| anyhow!("Missing task id"))?;
+                Self::Cancel {
+                    task_id: task_id.to_string(),
+                }
+            }
+            Some(other) => bail!("Unknown command {other}"),
+            None => bail!("Empty command"),
+        };
+        if parts.next().is_some() {
+            bail!("Unexpected arguments of {s}");
+        }
+        Ok(command)
+    }
+}
+
+impl fmt::Display for ServerCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flush => write!(f, "flush"),
+            Self::ReloadConfig => write!(f, "reload-config"),
+            Self::Run {
+                name,
+                version,
+                args,
+            } => {
+                write!(f, "run {name}")?;
+                if let Some(version) = version {
+                    write!(f, "@{version}")?;
+                }
+                for arg in args {
+                    write!(f, " {arg}")?;
+                }
+                Ok(())
+            }
+            Self::Cancel { task_id } => write!(f, "cancel {task_id}"),
+        }
+    }
+}
+
+/// Commands of a header value, invalid ones are logged and skipped.
+pub fn parse_all(value: &str) -> Vec<ServerCommand> {
+    value
+        .split(SEPARATOR)
+        .filter(|it| !it.trim().is_empty())
+        .filter_map(|it| {
+            it.parse()
+                .inspect_err(|e| tracing::warn!("Server command {:?}: {e}", it.trim()))
+                .ok()
+        })
+        .collect()
+}
+
+/// Executes server commands, whichever channel they arrive on.
+pub struct Dispatcher {
+    config_path: String,
+    store: Store,
+}
+
+impl Dispatcher {
+    pub fn new(config_path: String, catalog: &CatalogConfig) -> Self {
+        Self {
+            config_path,
+            store: Store::new(&catalog.store_dir),
+        }
+    }
+
+    pub fn dispatch(&self, command: &ServerCommand, task_rt: &TaskRuntime) -> Result<()> {
+        tracing::info!("Server command: {command}");
+        match command {
+            ServerCommand::Flush => task_rt.flush(),
+            ServerCommand::ReloadConfig => {
+                // other sections are read once at startup
+                let cfg = config::read_or_gen(&self.config_path)
+                    .with_context(|| format!("Failed to read {}", self.config_path))?;
+                task_rt.set_tenants(Tenants::new(&cfg.tenants)?);
+            }
+            ServerCommand::Run {
+                name,
+                version,
+                args,
+            } => {
+                let entry = self.store.installed(name, version.as_deref())?;
+                let path = self.store.path(&entry);
+                let id = format!(
+                    "{}@{}-{}",
+                    entry.name,
+                    entry.version,
+                    Utc::now().timestamp_millis()
+                );
+                // like rpc tasks, the task id comes first
+                let mut wasm_component_args = vec![id.clone()];
+                wasm_component_args.extend(args.iter().cloned());
+                task_rt.schedule(Task {
+                    id: Some(id),
+                    wasm_component: fs::read(&path)
+                        .with_context(|| format!("Failed to read {}", path.display()))?,
+                    wasm_component_args,
+                    wasm_component_envs: vec![],
+                    end_time: Utc.with_ymd_and_hms(3000, 1, 1, 1, 1, 1).unwrap(),
+                    tenant: None,
+                    running: None,
+                    stop: None,
+                    max_cpu_ms: None,
+                    memory_limit: None,
+                    rate_limits: Default::default(),
+                    warm: false,
+                    component: None,
+                    preopens: vec![],
+                    seed: None,
+                    done: None,
+                    input: vec![],
+                })?;
+            }
+            ServerCommand::Cancel { task_id } => task_rt.cancellations().cancel(task_id)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ServerCommand, parse_all};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "flush".parse::<ServerCommand>().unwrap(),
+            ServerCommand::Flush
+        );
+        assert_eq!(
+            " reload-config ".parse::<ServerCommand>().unwrap(),
+            ServerCommand::ReloadConfig
+        );
+        assert_eq!(
+            "run cpu-top@1.2 --interval 5"
+                .parse::<ServerCommand>()
+                .unwrap(),
+            ServerCommand::Run {
+                name: "cpu-top".to_string(),
+                version: Some("1.2".to_string()),
+                args: vec!["--interval".to_string(), "5".to_string()],
+            }
+        );
+        assert_eq!(
+            "cancel cpu-top@1.2-1".parse::<ServerCommand>().unwrap(),
+            ServerCommand::Cancel {
+                task_id: "cpu-top@1.2-1".to_string(),
+            }
+        );
+        assert!("run".parse::<ServerCommand>().is_err());
+        assert!("cancel".parse::<ServerCommand>().is_err());
+        assert!("flush now".parse::<ServerCommand>().is_err());
+        assert!("reboot".parse::<ServerCommand>().is_err());
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let commands = parse_all("flush; reboot;; run mem-top");
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[1].to_string(), "run mem-top");
+    }
+}
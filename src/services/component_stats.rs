@@ -0,0 +1,258 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+use std::{collections::BTreeMap, fmt::Write as _, sync::Mutex, time::Duration};
+
+use chrono::Utc;
+use influxdb_line_protocol::LineProtocolBuilder;
+
+use crate::runtime::Usage;
+
+static STATS: Mutex<BTreeMap<String, ComponentStats>> = Mutex::new(BTreeMap::new());
+
+// components beyond these are summed up as `OTHER`, so metrics stay bounded
+const MAX_COMPONENTS: usize = 256;
+const OTHER: &str = "other";
+
+/// One finished run of a component.
+pub struct RunStats<'a> {
+    /// `name` of the local component, a hash of the wasm for those of the
+    /// server, see `Task::program`
+    pub component: &'a str,
+    pub wall: Duration,
+    /// `None` unless runs are metered, see `[ledger] fuel`
+    pub fuel: Option<u64>,
+    pub usage: &'a Usage,
+}
+
+/// What the runs of a component took so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComponentStats {
+    pub runs: u64,
+    /// in milliseconds
+    pub wall_ms: u64,
+    pub fuel: u64,
+    /// in bytes, the most linear memory a single run grew to
+    pub peak_memory: u64,
+    /// calls to the rate limited interfaces, `sys` and `perf`
+    pub host_calls: BTreeMap<String, u64>,
+}
+
+impl ComponentStats {
+    fn add(&mut self, run: &RunStats) {
+        self.runs += 1;
+        self.wall_ms += run.wall.as_millis() as u64;
+        self.fuel += run.fuel.unwrap_or_default();
+        self.peak_memory = self.peak_memory.max(run.usage.peak_memory);
+        for (interface, calls) in &run.usage.host_calls {
+            *self.host_calls.entry(interface.clone()).or_default() += calls;
+        }
+    }
+}
+
+pub fn record(run: &RunStats) {
+    add(&mut STATS.lock().unwrap(), run);
+}
+
+fn add(stats: &mut BTreeMap<String, ComponentStats>, run: &RunStats) {
+    let component = if stats.len() < MAX_COMPONENTS || stats.contains_key(run.component) {
+        run.component
+    } else {
+        OTHER
+    };
+    stats.entry(component.to_string()).or_default().add(run);
+}
+
+/// The stats of every component that has run, by its name.
+pub fn snapshot() -> BTreeMap<String, ComponentStats> {
+    STATS.lock().unwrap().clone()
+}
+
+/// The resources of `run` as measurement `psh_component_run`, the calls
+/// to every rate limited host interface in a field of their own.
+pub fn to_line_protocol(run: &RunStats, task_id: &str, instance_id: &str) -> Vec<u8> {
+    let lp = LineProtocolBuilder::new()
+        .measurement("psh_component_run")
+        .tag("instance_id", instance_id)
+        .tag("task_id", task_id)
+        .tag("component", run.component)
+        .field("wall_ms", run.wall.as_millis() as u64)
+        .field("peak_memory", run.usage.peak_memory);
+    let lp = match run.fuel {
+        Some(fuel) => lp.field("fuel", fuel),
+        None => lp,
+    };
+    let lp = run
+        .usage
+        .host_calls
+        .iter()
+        .fold(lp, |lp, (interface, calls)| {
+            lp.field(&format!("calls_{interface}"), *calls)
+        });
+    lp.timestamp(Utc::now().timestamp_nanos_opt().unwrap_or_default())
+        .close_line()
+        .build()
+}
+
+/// Renders the stats of every component in the Prometheus text format.
+pub fn prometheus() -> String {
+    render(&snapshot())
+}
+
+fn render(stats: &BTreeMap<String, ComponentStats>) -> String {
+    let families: [(&str, &str, &str, fn(&ComponentStats) -> u64); 4] = [
+        (
+            "psh_component_runs_total",
+            "counter",
+            "Finished runs of the component.",
+            |it| it.runs,
+        ),
+        (
+            "psh_component_wall_milliseconds_total",
+            "counter",
+            "Wall time the runs of the component took.",
+            |it| it.wall_ms,
+        ),
+        (
+            "psh_component_fuel_total",
+            "counter",
+            "Fuel the metered runs of the component consumed.",
+            |it| it.fuel,
+        ),
+        (
+            "psh_component_peak_memory_bytes",
+            "gauge",
+            "Most linear memory a run of the component grew to.",
+            |it| it.peak_memory,
+        ),
+    ];
+
+    let mut text = String::new();
+    for (name, ty, help, value) in families {
+        let _ = writeln!(text, "# HELP {name} {help}");
+        let _ = writeln!(text, "# TYPE {name} {ty}");
+        for (component, stats) in stats {
+            let _ = writeln!(
+                text,
+                "{name}{{component=\"{}\"}} {}",
+                escape(component),
+                value(stats)
+            );
+        }
+    }
+    let name = "psh_component_rate_limited_calls_total";
+    let _ = writeln!(
+        text,
+        "# HELP {name} Calls of the component to rate limited host interfaces."
+    );
+    let _ = writeln!(text, "# TYPE {name} counter");
+    for (component, stats) in stats {
+        for (interface, calls) in &stats.host_calls {
+            let _ = writeln!(
+                text,
+                "{name}{{component=\"{}\",interface=\"{}\"}} {calls}",
+                escape(component),
+                escape(interface)
+            );
+        }
+    }
+    text
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, time::Duration};
+
+    use super::{ComponentStats, MAX_COMPONENTS, OTHER, RunStats, add, render, to_line_protocol};
+    use crate::runtime::Usage;
+
+    #[test]
+    fn test_add_and_render() {
+        let usage = Usage {
+            peak_memory: 2 << 20,
+            host_calls: BTreeMap::from([("process".to_string(), 3)]),
+        };
+        let run = RunStats {
+            component: "cpu-top",
+            wall: Duration::from_millis(120),
+            fuel: Some(1000),
+            usage: &usage,
+        };
+        let mut stats = ComponentStats::default();
+        stats.add(&run);
+        stats.add(&RunStats {
+            fuel: None,
+            usage: &Usage::default(),
+            ..run
+        });
+        assert_eq!(stats.runs, 2);
+        assert_eq!(stats.wall_ms, 240);
+        assert_eq!(stats.fuel, 1000);
+        assert_eq!(stats.peak_memory, 2 << 20);
+        assert_eq!(stats.host_calls["process"], 3);
+
+        let text = render(&BTreeMap::from([("cpu \"top\"".to_string(), stats)]));
+        assert!(text.contains("# TYPE psh_component_runs_total counter\n"));
+        assert!(text.contains("psh_component_runs_total{component=\"cpu \\\"top\\\"\"} 2\n"));
+        assert!(text.contains(
+            "psh_component_rate_limited_calls_total{component=\"cpu \\\"top\\\"\",interface=\"process\"} 3\n"
+        ));
+    }
+
+    #[test]
+    fn test_bounded_components() {
+        let usage = Usage::default();
+        let mut stats = BTreeMap::new();
+        let names: Vec<_> = (0..=MAX_COMPONENTS).map(|it| it.to_string()).collect();
+        for name in names.iter().chain(&names) {
+            add(
+                &mut stats,
+                &RunStats {
+                    component: name,
+                    wall: Duration::ZERO,
+                    fuel: None,
+                    usage: &usage,
+                },
+            );
+        }
+        assert_eq!(stats.len(), MAX_COMPONENTS + 1);
+        assert_eq!(stats["0"].runs, 2);
+        assert_eq!(stats[OTHER].runs, 2);
+    }
+
+    #[test]
+    fn test_to_line_protocol() {
+        let usage = Usage {
+            peak_memory: 65536,
+            host_calls: BTreeMap::from([("cpu".to_string(), 7)]),
+        };
+        let run = RunStats {
+            component: "cpu-top",
+            wall: Duration::from_millis(15),
+            fuel: None,
+            usage: &usage,
+        };
+        let line = String::from_utf8(to_line_protocol(&run, "t1", "i1")).unwrap();
+        assert!(line.starts_with(
+            "psh_component_run,instance_id=i1,task_id=t1,component=cpu-top \
+             wall_ms=15u,peak_memory=65536u,calls_cpu=7u "
+        ));
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fs::{self, Permissions},
+    io,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+
+use crate::{build_info, config::ControlConfig, ledger};
+
+const MAX_COMMAND_LEN: u64 = 1024;
+const DEFAULT_LEDGER_RUNS: usize = 20;
+
+const HELP: &str = "\
+commands:
+  help          show this message
+  ledger [N]    last N component runs as JSON lines, 20 by default
+  version       how the daemon was built as JSON
+";
+
+/// Answers a single command line, e.g. `ledger 10`.
+fn respond(command: &str) -> String {
+    let mut parts = command.split_whitespace();
+    match parts.next() {
+        None | Some("help") => HELP.to_string(),
+        Some("ledger") => {
+            let Some(ledger) = ledger::global() else {
+                return "error: run ledger is disabled\n".to_string();
+            };
+            let n = match parts.next().map(str::parse) {
+                None => DEFAULT_LEDGER_RUNS,
+                Some(Ok(n)) => n,
+                Some(Err(_)) => return "error: invalid count\n".to_string(),
+            };
+            ledger
+                .recent(n)
+                .iter()
+                .filter_map(|it| serde_json::to_string(it).ok())
+                .map(|it| it + "\n")
+                .collect()
+        }
+        Some("version") => build_info::get().to_json() + "\n",
+        Some(other) => format!("error: unknown command {other}\n"),
+    }
+}
+
+async fn handle(stream: UnixStream) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut command = String::new();
+    BufReader::new(read.take(MAX_COMMAND_LEN))
+        .read_line(&mut command)
+        .await?;
+    write.write_all(respond(command.trim()).as_bytes()).await?;
+    write.shutdown().await?;
+    Ok(())
+}
+
+/// Serves the control socket until the daemon exits.
+pub async fn serve(cfg: &ControlConfig) -> Result<()> {
+    let path = Path::new(&cfg.path);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    // the socket of a previous run makes bind fail
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            return Err(e).with_context(|| format!("Failed to remove {}", path.display()));
+        }
+        _ => {}
+    }
+    let listener = UnixListener::bind(path)?;
+    // only the user running the daemon may send commands
+    fs::set_permissions(path, Permissions::from_mode(0o600))?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream).await {
+                tracing::debug!("Control request: {e}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HELP, respond};
+
+    #[test]
+    fn test_respond() {
+        assert_eq!(respond(""), HELP);
+        assert_eq!(respond("help"), HELP);
+        assert_eq!(respond("ledger"), "error: run ledger is disabled\n");
+        assert_eq!(respond("reboot"), "error: unknown command reboot\n");
+        assert!(respond("version").contains(env!("CARGO_PKG_VERSION")));
+    }
+}
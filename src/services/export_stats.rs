@@ -0,0 +1,263 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fmt::{self, Display, Write as _},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use tonic::metadata::MetadataMap;
+
+static STATS: [BackendStats; Backend::ALL.len()] = [
+    BackendStats::new(),
+    BackendStats::new(),
+    BackendStats::new(),
+    BackendStats::new(),
+];
+
+/// Accounting of the items exported through `backend`.
+pub fn global(backend: Backend) -> &'static BackendStats {
+    &STATS[backend as usize]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// data exported by wasm components
+    Rpc,
+    /// kernel events forwarded as alerts
+    KernelEvents,
+    /// stdout and stderr of components
+    ComponentOutput,
+    /// changes of kernel tunables
+    Drift,
+}
+
+impl Backend {
+    pub const ALL: [Self; 4] = [
+        Self::Rpc,
+        Self::KernelEvents,
+        Self::ComponentOutput,
+        Self::Drift,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Rpc => "rpc",
+            Self::KernelEvents => "kernel_events",
+            Self::ComponentOutput => "component_output",
+            Self::Drift => "drift",
+        }
+    }
+
+    const fn metadata_key(self) -> &'static str {
+        match self {
+            Self::Rpc => "x-psh-export-rpc",
+            Self::KernelEvents => "x-psh-export-kernel-events",
+            Self::ComponentOutput => "x-psh-export-component-output",
+            Self::Drift => "x-psh-export-drift",
+        }
+    }
+}
+
+/// Counters of a single export backend, an item is one `Data` entry.
+///
+/// Every accepted item ends up either sent or dropped, retries are counted
+/// once per failed attempt.
+#[derive(Debug, Default)]
+pub struct BackendStats {
+    accepted: AtomicU64,
+    sent: AtomicU64,
+    retried: AtomicU64,
+    dropped: AtomicU64,
+    queue_items: AtomicU64,
+    queue_bytes: AtomicU64,
+}
+
+// No critical section, relaxed ordering is fine.
+impl BackendStats {
+    pub const fn new() -> Self {
+        Self {
+            accepted: AtomicU64::new(0),
+            sent: AtomicU64::new(0),
+            retried: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            queue_items: AtomicU64::new(0),
+            queue_bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn accept(&self, items: u64) {
+        self.accepted.fetch_add(items, Ordering::Relaxed);
+    }
+
+    pub fn enqueue(&self, items: u64, bytes: u64) {
+        self.queue_items.fetch_add(items, Ordering::Relaxed);
+        self.queue_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn dequeue(&self, items: u64, bytes: u64) {
+        self.queue_items.fetch_sub(items, Ordering::Relaxed);
+        self.queue_bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    pub fn sent(&self, items: u64) {
+        self.sent.fetch_add(items, Ordering::Relaxed);
+    }
+
+    pub fn retried(&self, items: u64) {
+        self.retried.fetch_add(items, Ordering::Relaxed);
+    }
+
+    pub fn dropped(&self, items: u64) {
+        self.dropped.fetch_add(items, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            accepted: self.accepted.load(Ordering::Relaxed),
+            sent: self.sent.load(Ordering::Relaxed),
+            retried: self.retried.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            queue_items: self.queue_items.load(Ordering::Relaxed),
+            queue_bytes: self.queue_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub accepted: u64,
+    pub sent: u64,
+    pub retried: u64,
+    pub dropped: u64,
+    pub queue_items: u64,
+    pub queue_bytes: u64,
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "accepted={},sent={},retried={},dropped={},queue_items={},queue_bytes={}",
+            self.accepted,
+            self.sent,
+            self.retried,
+            self.dropped,
+            self.queue_items,
+            self.queue_bytes
+        )
+    }
+}
+
+/// `HeartbeatReq` has no fields for the export stats, they go along as
+/// request metadata, one `x-psh-export-<backend>` entry per backend.
+pub fn insert_stats(metadata: &mut MetadataMap) {
+    for backend in Backend::ALL {
+        if let Ok(value) = global(backend).snapshot().to_string().parse() {
+            metadata.insert(backend.metadata_key(), value);
+        }
+    }
+}
+
+/// Renders the stats of every backend in the Prometheus text format.
+pub fn prometheus() -> String {
+    render(Backend::ALL.map(|it| (it, global(it).snapshot())))
+}
+
+fn render(snapshots: [(Backend, Snapshot); Backend::ALL.len()]) -> String {
+    let families: [(&str, &str, &str, fn(&Snapshot) -> u64); 6] = [
+        (
+            "psh_export_accepted_items_total",
+            "counter",
+            "Items accepted for export.",
+            |it| it.accepted,
+        ),
+        (
+            "psh_export_sent_items_total",
+            "counter",
+            "Items delivered to the backend.",
+            |it| it.sent,
+        ),
+        (
+            "psh_export_retried_items_total",
+            "counter",
+            "Items sent again after a failed attempt.",
+            |it| it.retried,
+        ),
+        (
+            "psh_export_dropped_items_total",
+            "counter",
+            "Items given up on after failed attempts or exceeded quotas.",
+            |it| it.dropped,
+        ),
+        (
+            "psh_export_queue_items",
+            "gauge",
+            "Items waiting to be sent.",
+            |it| it.queue_items,
+        ),
+        (
+            "psh_export_queue_bytes",
+            "gauge",
+            "Encoded bytes waiting to be sent.",
+            |it| it.queue_bytes,
+        ),
+    ];
+
+    let mut text = String::new();
+    for (name, ty, help, value) in families {
+        let _ = writeln!(text, "# HELP {name} {help}");
+        let _ = writeln!(text, "# TYPE {name} {ty}");
+        for (backend, snapshot) in &snapshots {
+            let _ = writeln!(
+                text,
+                "{name}{{backend=\"{}\"}} {}",
+                backend.name(),
+                value(snapshot)
+            );
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, BackendStats, render};
+
+    #[test]
+    fn test_render() {
+        let stats = BackendStats::new();
+        stats.accept(3);
+        stats.enqueue(3, 120);
+        stats.dequeue(2, 80);
+        stats.retried(2);
+        stats.sent(2);
+
+        let text = render([
+            (Backend::Rpc, stats.snapshot()),
+            (Backend::KernelEvents, Default::default()),
+            (Backend::ComponentOutput, Default::default()),
+            (Backend::Drift, Default::default()),
+        ]);
+        assert!(text.contains("# TYPE psh_export_accepted_items_total counter\n"));
+        assert!(text.contains("psh_export_accepted_items_total{backend=\"rpc\"} 3\n"));
+        assert!(text.contains("psh_export_retried_items_total{backend=\"rpc\"} 2\n"));
+        assert!(text.contains("psh_export_dropped_items_total{backend=\"kernel_events\"} 0\n"));
+        assert!(text.contains("psh_export_queue_bytes{backend=\"rpc\"} 40\n"));
+        assert_eq!(
+            stats.snapshot().to_string(),
+            "accepted=3,sent=2,retried=2,dropped=0,queue_items=1,queue_bytes=40"
+        );
+    }
+}
@@ -0,0 +1,333 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    net::SocketAddr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use anyhow::Result;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    try_join,
+};
+
+use crate::{
+    build_info,
+    config::{HealthConfig, PingConfig},
+    services::{component_stats, export_stats},
+};
+
+// probes send tiny requests, anything bigger is not for us
+const MAX_REQUEST_LEN: usize = 8192;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// Asks for the liveness of the daemon over UDP or TCP.
+const PING: &[u8; 4] = b"PSH?";
+/// Starts the answer to [`PING`], followed by the [`Readiness`] byte, the
+/// version and a newline.
+const PONG: &[u8; 4] = b"PSH!";
+
+static HEALTH: Health = Health::new();
+
+/// Readiness of the daemon, updated by the rpc loop and the task runtime.
+pub fn global() -> &'static Health {
+    &HEALTH
+}
+
+#[derive(Debug, Default)]
+pub struct Health {
+    rpc_connected: AtomicBool,
+    engine_running: AtomicBool,
+}
+
+impl Health {
+    pub const fn new() -> Self {
+        Self {
+            rpc_connected: AtomicBool::new(false),
+            engine_running: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set_rpc_connected(&self, connected: bool) {
+        self.rpc_connected.store(connected, Ordering::Relaxed);
+    }
+
+    pub fn set_engine_running(&self, running: bool) {
+        self.engine_running.store(running, Ordering::Relaxed);
+    }
+
+    fn readiness(&self, rpc_enabled: bool) -> Readiness {
+        if !self.engine_running.load(Ordering::Relaxed) {
+            return Readiness::EngineNotRunning;
+        }
+        if rpc_enabled && !self.rpc_connected.load(Ordering::Relaxed) {
+            return Readiness::RpcNotConnected;
+        }
+        Readiness::Ready
+    }
+
+    /// The reason the daemon is not ready, if any.
+    fn not_ready(&self, rpc_enabled: bool) -> Option<&'static str> {
+        match self.readiness(rpc_enabled) {
+            Readiness::Ready => None,
+            Readiness::EngineNotRunning => Some("engine not running"),
+            Readiness::RpcNotConnected => Some("rpc not connected"),
+        }
+    }
+}
+
+/// Status byte of the answer to a ping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Readiness {
+    Ready = 0,
+    EngineNotRunning = 1,
+    RpcNotConnected = 2,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn text(status: &'static str, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{body}\n"),
+        }
+    }
+}
+
+/// Answers the request line, e.g. `GET /healthz HTTP/1.1`.
+fn respond(health: &Health, rpc_enabled: bool, request_line: &str) -> Response {
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Response::text("400 Bad Request", "bad request");
+    };
+    // probes may append a query string
+    let path = target.split('?').next().unwrap_or(target);
+    if !matches!(path, "/healthz" | "/readyz" | "/buildinfo" | "/metrics") {
+        return Response::text("404 Not Found", "not found");
+    }
+    if method != "GET" && method != "HEAD" {
+        return Response::text("405 Method Not Allowed", "method not allowed");
+    }
+
+    match path {
+        "/healthz" => Response::text("200 OK", "ok"),
+        "/readyz" => match health.not_ready(rpc_enabled) {
+            None => Response::text("200 OK", "ok"),
+            Some(reason) => Response::text("503 Service Unavailable", reason),
+        },
+        "/metrics" => Response {
+            status: "200 OK",
+            content_type: "text/plain; version=0.0.4; charset=utf-8",
+            body: export_stats::prometheus() + &component_stats::prometheus(),
+        },
+        _ => Response {
+            status: "200 OK",
+            content_type: "application/json",
+            body: build_info::get().to_json(),
+        },
+    }
+}
+
+async fn handle(mut stream: TcpStream, rpc_enabled: bool) -> Result<()> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0; 1024];
+    // only the request line matters, but read the whole head so the client
+    // isn't reset while still sending
+    while !buf.windows(4).any(|it| it == b"\r\n\r\n") && buf.len() < MAX_REQUEST_LEN {
+        let len = tokio::time::timeout(READ_TIMEOUT, stream.read(&mut chunk)).await??;
+        if len == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..len]);
+    }
+    let head = String::from_utf8_lossy(&buf);
+    let request_line = head.lines().next().unwrap_or_default();
+    let response = respond(global(), rpc_enabled, request_line);
+
+    let mut reply = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    if !request_line.starts_with("HEAD ") {
+        reply.push_str(&response.body);
+    }
+    stream.write_all(reply.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Serves `/healthz`, `/readyz`, `/buildinfo` and `/metrics` until the daemon exits.
+pub async fn serve(cfg: &HealthConfig, rpc_enabled: bool) -> Result<()> {
+    let addr: SocketAddr = cfg.addr.parse()?;
+    if !addr.ip().is_loopback() {
+        tracing::warn!("Health endpoint listening on non-loopback address {addr}");
+    }
+    let listener = TcpListener::bind(addr).await?;
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, rpc_enabled).await {
+                tracing::debug!("Health request from {peer}: {e}");
+            }
+        });
+    }
+}
+
+fn pong(health: &Health, rpc_enabled: bool) -> Vec<u8> {
+    let version = env!("CARGO_PKG_VERSION");
+    let mut pong = Vec::with_capacity(PONG.len() + version.len() + 2);
+    pong.extend_from_slice(PONG);
+    pong.push(health.readiness(rpc_enabled) as u8);
+    pong.extend_from_slice(version.as_bytes());
+    pong.push(b'\n');
+    pong
+}
+
+async fn answer(mut stream: TcpStream, rpc_enabled: bool) -> Result<()> {
+    let mut ping = [0; PING.len()];
+    tokio::time::timeout(READ_TIMEOUT, stream.read_exact(&mut ping)).await??;
+    if &ping == PING {
+        stream.write_all(&pong(global(), rpc_enabled)).await?;
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
+
+async fn serve_ping_tcp(addr: SocketAddr, rpc_enabled: bool) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        tokio::spawn(async move {
+            if let Err(e) = answer(stream, rpc_enabled).await {
+                tracing::debug!("Ping from {peer}: {e}");
+            }
+        });
+    }
+}
+
+async fn serve_ping_udp(addr: SocketAddr, rpc_enabled: bool) -> Result<()> {
+    let socket = UdpSocket::bind(addr).await?;
+    let mut buf = [0; 64];
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf).await?;
+        // anything else goes unanswered, the answer is only a few bytes
+        // longer than the ping, so the responder is of little use to
+        // amplify traffic
+        if &buf[..len] != PING {
+            continue;
+        }
+        if let Err(e) = socket.send_to(&pong(global(), rpc_enabled), peer).await {
+            tracing::debug!("Ping from {peer}: {e}");
+        }
+    }
+}
+
+/// Answers pings over UDP and TCP on `cfg.addr` until the daemon exits,
+/// independently of the HTTP endpoint and the heartbeat.
+pub async fn serve_ping(cfg: &PingConfig, rpc_enabled: bool) -> Result<()> {
+    let addr: SocketAddr = cfg.addr.parse()?;
+    let udp = async {
+        if !cfg.udp {
+            return Ok(());
+        }
+        serve_ping_udp(addr, rpc_enabled).await
+    };
+    let tcp = async {
+        if !cfg.tcp {
+            return Ok(());
+        }
+        serve_ping_tcp(addr, rpc_enabled).await
+    };
+    try_join!(udp, tcp)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Health, PONG, pong, respond};
+
+    #[test]
+    fn test_pong() {
+        let health = Health::new();
+        let version = env!("CARGO_PKG_VERSION");
+        let answer = pong(&health, false);
+        assert_eq!(&answer[..4], PONG);
+        assert_eq!(answer[4], 1);
+        assert_eq!(&answer[5..answer.len() - 1], version.as_bytes());
+        assert_eq!(answer.last(), Some(&b'\n'));
+
+        health.set_engine_running(true);
+        assert_eq!(pong(&health, true)[4], 2);
+        health.set_rpc_connected(true);
+        assert_eq!(pong(&health, true)[4], 0);
+    }
+
+    #[test]
+    fn test_respond() {
+        let health = Health::new();
+        assert_eq!(
+            respond(&health, true, "GET /healthz HTTP/1.1").status,
+            "200 OK"
+        );
+        assert_eq!(
+            respond(&health, true, "GET /readyz HTTP/1.1").status,
+            "503 Service Unavailable"
+        );
+
+        health.set_engine_running(true);
+        assert_eq!(
+            respond(&health, false, "GET /readyz HTTP/1.1").status,
+            "200 OK"
+        );
+        assert_eq!(
+            respond(&health, true, "GET /readyz?verbose HTTP/1.1").body,
+            "rpc not connected\n"
+        );
+        health.set_rpc_connected(true);
+        assert_eq!(
+            respond(&health, true, "GET /readyz HTTP/1.1").status,
+            "200 OK"
+        );
+
+        assert!(
+            respond(&health, true, "GET /buildinfo HTTP/1.1")
+                .body
+                .contains(env!("CARGO_PKG_VERSION"))
+        );
+        assert!(
+            respond(&health, true, "GET /metrics HTTP/1.1")
+                .body
+                .contains("psh_export_dropped_items_total{backend=\"rpc\"}")
+        );
+        assert_eq!(
+            respond(&health, true, "POST /healthz HTTP/1.1").status,
+            "405 Method Not Allowed"
+        );
+        assert_eq!(
+            respond(&health, true, "GET / HTTP/1.1").status,
+            "404 Not Found"
+        );
+    }
+}
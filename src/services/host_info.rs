@@ -21,9 +21,13 @@ use psh_system::{
 };
 
 pub fn new_info_req(instance_id: String) -> SendHostInfoReq {
-    let hostname = nix::unistd::gethostname()
-        .ok()
-        .map(|v| v.to_string_lossy().to_string());
+    // inside a pod the hostname is the pod's, the host is the node
+    let node_name = crate::kubernetes::global().and_then(|it| it.identity.node_name.clone());
+    let hostname = node_name.or_else(|| {
+        nix::unistd::gethostname()
+            .ok()
+            .map(|v| v.to_string_lossy().to_string())
+    });
 
     let local_ipv4_addr = match local_ip_address::local_ip() {
         Ok(IpAddr::V4(it)) => Some(it.to_bits().to_be()),
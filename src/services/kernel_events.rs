@@ -0,0 +1,216 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    sync::{Mutex, OnceLock},
+    thread,
+};
+
+use anyhow::{Result, bail};
+use chrono::Utc;
+use crossbeam::channel::{self, Receiver, Sender, TrySendError};
+use influxdb_line_protocol::LineProtocolBuilder;
+use psh_proto::{Data, DataType, ExportDataReq};
+use psh_system::kmsg::{KernelEvent, KernelEventKind, KmsgReader};
+
+use crate::{
+    config::KernelEventsConfig,
+    services::{
+        export_stats::{self, Backend},
+        rpc::RpcClient,
+    },
+};
+
+static GLOBAL: OnceLock<KernelEventHub> = OnceLock::new();
+
+/// The hub started by [`spawn`], `None` if the watcher is disabled.
+pub fn global() -> Option<&'static KernelEventHub> {
+    GLOBAL.get()
+}
+
+/// Fans kernel events out to every subscriber.
+///
+/// Each subscriber has its own bounded queue, a slow subscriber loses its
+/// newest events instead of blocking the watcher.
+pub struct KernelEventHub {
+    queue_size: usize,
+    subscribers: Mutex<Vec<Sender<KernelEvent>>>,
+}
+
+impl KernelEventHub {
+    pub const fn new(queue_size: usize) -> Self {
+        Self {
+            queue_size,
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn subscribe(&self) -> Receiver<KernelEvent> {
+        let (tx, rx) = channel::bounded(self.queue_size);
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub fn publish(&self, event: &KernelEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| match tx.try_send(event.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    tracing::warn!(
+                        "Kernel event subscriber lagging, event {} dropped",
+                        event.seq
+                    );
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+}
+
+/// Starts watching `/dev/kmsg` and installs the global hub.
+pub fn spawn(cfg: &KernelEventsConfig) -> Result<()> {
+    let mut reader = KmsgReader::new()?;
+    if GLOBAL
+        .set(KernelEventHub::new(cfg.queue_size.max(1)))
+        .is_err()
+    {
+        bail!("Kernel event watcher already started");
+    }
+    let hub = global().expect("Kernel event hub not installed");
+
+    thread::spawn(move || {
+        loop {
+            match reader.next_event() {
+                Ok(event) => {
+                    tracing::warn!("Kernel event: {}", event.message);
+                    hub.publish(&event);
+                }
+                Err(e) => {
+                    tracing::error!("Kernel event watcher stopped: {e}");
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Forwards every kernel event to the server as a line protocol alert.
+pub fn forward_to_rpc(hub: &KernelEventHub, client: RpcClient, instance_id: String) {
+    let rx = hub.subscribe();
+    let rt = tokio::runtime::Handle::current();
+    thread::spawn(move || {
+        let stats = export_stats::global(Backend::KernelEvents);
+        while let Ok(event) = rx.recv() {
+            stats.accept(1);
+            let req = ExportDataReq {
+                task_id: String::new(),
+                data: vec![Data {
+                    ty: DataType::LineProtocol as _,
+                    bytes: to_line_protocol(&event, &instance_id),
+                }],
+            };
+            let mut client = client.clone();
+            if let Err(e) = rt.block_on(client.export_data_accounted(req, None, stats)) {
+                tracing::error!("Failed to send kernel event alert: {e}");
+            }
+        }
+    });
+}
+
+pub const fn kind_name(kind: &KernelEventKind) -> &'static str {
+    match kind {
+        KernelEventKind::OomKill { .. } => "oom_kill",
+        KernelEventKind::HungTask { .. } => "hung_task",
+        KernelEventKind::SoftLockup { .. } => "soft_lockup",
+        KernelEventKind::HardLockup { .. } => "hard_lockup",
+    }
+}
+
+pub fn to_line_protocol(event: &KernelEvent, instance_id: &str) -> Vec<u8> {
+    let (pid, comm, cpu, secs) = match &event.kind {
+        KernelEventKind::OomKill { pid, comm } => (Some(*pid), Some(comm), None, None),
+        KernelEventKind::HungTask {
+            pid,
+            comm,
+            blocked_secs,
+        } => (Some(*pid), Some(comm), None, Some(*blocked_secs)),
+        KernelEventKind::SoftLockup {
+            cpu,
+            stuck_secs,
+            pid,
+            comm,
+        } => (Some(*pid), Some(comm), Some(*cpu), Some(*stuck_secs)),
+        KernelEventKind::HardLockup { cpu } => (None, None, Some(*cpu), None),
+    };
+
+    let lp = LineProtocolBuilder::new()
+        .measurement("psh_kernel_event")
+        .tag("instance_id", instance_id)
+        .tag("kind", kind_name(&event.kind));
+    let lp = match comm {
+        Some(comm) => lp.tag("comm", comm),
+        None => lp,
+    };
+    let lp = lp
+        .field("seq", event.seq)
+        .field("timestamp_us", event.timestamp_us)
+        .field("message", event.message.as_str());
+    let lp = match pid {
+        Some(pid) => lp.field("pid", u64::from(pid)),
+        None => lp,
+    };
+    let lp = match cpu {
+        Some(cpu) => lp.field("cpu", u64::from(cpu)),
+        None => lp,
+    };
+    let lp = match secs {
+        Some(secs) => lp.field("duration_secs", secs),
+        None => lp,
+    };
+    lp.timestamp(Utc::now().timestamp_nanos_opt().unwrap_or_default())
+        .close_line()
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use psh_system::kmsg::{KernelEvent, KernelEventKind};
+
+    use super::KernelEventHub;
+
+    #[test]
+    fn test_hub_drops_closed_subscribers() {
+        let hub = KernelEventHub::new(1);
+        let rx = hub.subscribe();
+        drop(hub.subscribe());
+
+        let event = KernelEvent {
+            seq: 1,
+            timestamp_us: 0,
+            kind: KernelEventKind::HardLockup { cpu: 0 },
+            message: String::new(),
+        };
+        hub.publish(&event);
+        // queue full, dropped but still subscribed
+        hub.publish(&event);
+
+        assert_eq!(hub.subscribers.lock().unwrap().len(), 1);
+        assert_eq!(rx.try_recv().unwrap(), event);
+        assert!(rx.try_recv().is_err());
+    }
+}
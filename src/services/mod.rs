@@ -12,5 +12,14 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
+pub mod commands;
+pub mod component_stats;
+pub mod control;
+pub mod export_stats;
+pub mod health;
 pub mod host_info;
+pub mod kernel_events;
+pub mod process_events;
 pub mod rpc;
+pub mod self_profile;
+pub mod upload_budget;
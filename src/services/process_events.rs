@@ -0,0 +1,272 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{Mutex, OnceLock},
+    thread,
+};
+
+use anyhow::{Result, bail};
+use crossbeam::channel::{self, Receiver, Sender, TrySendError};
+use psh_system::proc_events::{ProcConnector, ProcEvent, ProcEventKind};
+
+use crate::config::ProcessEventsConfig;
+
+static GLOBAL: OnceLock<ProcessEventHub> = OnceLock::new();
+
+/// The hub started by [`spawn`], `None` if the stream is disabled.
+pub fn global() -> Option<&'static ProcessEventHub> {
+    GLOBAL.get()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessEventKind {
+    Fork,
+    Exec,
+    Exit {
+        /// `None` if killed by a signal
+        exit_code: Option<i32>,
+        signal: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessEvent {
+    /// nanoseconds since boot
+    pub timestamp_ns: u64,
+    pub kind: ProcessEventKind,
+    pub pid: u32,
+    pub ppid: u32,
+    /// the new command after an exec
+    pub comm: String,
+}
+
+/// Fans process events out to every subscriber, like
+/// [`KernelEventHub`](super::kernel_events::KernelEventHub).
+pub struct ProcessEventHub {
+    queue_size: usize,
+    subscribers: Mutex<Vec<Sender<ProcessEvent>>>,
+}
+
+impl ProcessEventHub {
+    pub const fn new(queue_size: usize) -> Self {
+        Self {
+            queue_size,
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn subscribe(&self) -> Receiver<ProcessEvent> {
+        let (tx, rx) = channel::bounded(self.queue_size);
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub fn publish(&self, event: &ProcessEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| match tx.try_send(event.clone()) {
+                // process events come in bursts, don't warn for each
+                Ok(()) | Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+}
+
+/// Keeps the parent and command of live processes, an exited process is
+/// often already reaped when its exit event is read.
+#[derive(Debug, Default)]
+struct Tracker {
+    known: HashMap<u32, (u32, String)>,
+}
+
+impl Tracker {
+    /// Events of threads are skipped.
+    fn resolve(&mut self, event: ProcEvent) -> Option<ProcessEvent> {
+        if event.kind.is_thread() {
+            return None;
+        }
+        let (kind, pid, ppid, comm) = match event.kind {
+            ProcEventKind::Fork {
+                parent_tgid,
+                child_tgid,
+                ..
+            } => {
+                let comm = read_comm(child_tgid)
+                    .or_else(|| self.known.get(&parent_tgid).map(|it| it.1.clone()))
+                    .unwrap_or_default();
+                self.known.insert(child_tgid, (parent_tgid, comm.clone()));
+                (ProcessEventKind::Fork, child_tgid, parent_tgid, comm)
+            }
+            ProcEventKind::Exec { tgid, .. } => {
+                let known = self.known.get(&tgid);
+                let ppid = known
+                    .map(|it| it.0)
+                    .or_else(|| read_ppid(tgid))
+                    .unwrap_or_default();
+                let comm = read_comm(tgid)
+                    .or_else(|| known.map(|it| it.1.clone()))
+                    .unwrap_or_default();
+                self.known.insert(tgid, (ppid, comm.clone()));
+                (ProcessEventKind::Exec, tgid, ppid, comm)
+            }
+            ProcEventKind::Exit { tgid, status, .. } => {
+                let (ppid, comm) = self.known.remove(&tgid).unwrap_or_else(|| {
+                    (
+                        read_ppid(tgid).unwrap_or_default(),
+                        read_comm(tgid).unwrap_or_default(),
+                    )
+                });
+                (exit_kind(status), tgid, ppid, comm)
+            }
+        };
+        Some(ProcessEvent {
+            timestamp_ns: event.timestamp_ns,
+            kind,
+            pid,
+            ppid,
+            comm,
+        })
+    }
+}
+
+const fn exit_kind(status: u32) -> ProcessEventKind {
+    let signal = status & 0x7f;
+    if signal == 0 {
+        ProcessEventKind::Exit {
+            exit_code: Some(((status >> 8) & 0xff) as i32),
+            signal: None,
+        }
+    } else {
+        ProcessEventKind::Exit {
+            exit_code: None,
+            signal: Some(signal),
+        }
+    }
+}
+
+fn read_comm(pid: u32) -> Option<String> {
+    let comm = fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+    Some(comm.trim_end().to_string())
+}
+
+fn read_ppid(pid: u32) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // the comm in parentheses may contain spaces
+    let (_, rest) = stat.rsplit_once(") ")?;
+    rest.split(' ').nth(1)?.parse().ok()
+}
+
+/// Subscribes to the proc connector and installs the global hub.
+pub fn spawn(cfg: &ProcessEventsConfig) -> Result<()> {
+    let mut connector = ProcConnector::new()?;
+    if GLOBAL
+        .set(ProcessEventHub::new(cfg.queue_size.max(1)))
+        .is_err()
+    {
+        bail!("Process event stream already started");
+    }
+    let hub = global().expect("Process event hub not installed");
+
+    thread::spawn(move || {
+        let mut tracker = Tracker::default();
+        loop {
+            match connector.next_event() {
+                Ok(event) => {
+                    if let Some(event) = tracker.resolve(event) {
+                        hub.publish(&event);
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Process event stream stopped: {e}");
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use psh_system::proc_events::{ProcEvent, ProcEventKind};
+
+    use super::{ProcessEventKind, Tracker, exit_kind};
+
+    #[test]
+    fn test_exit_kind() {
+        assert_eq!(
+            exit_kind(3 << 8),
+            ProcessEventKind::Exit {
+                exit_code: Some(3),
+                signal: None,
+            }
+        );
+        // SIGKILL, with core dump flag
+        assert_eq!(
+            exit_kind(0x80 | 9),
+            ProcessEventKind::Exit {
+                exit_code: None,
+                signal: Some(9),
+            }
+        );
+    }
+
+    #[test]
+    fn test_tracker_remembers_reaped_processes() {
+        // pids above pid_max never exist
+        let (parent, child) = (u32::MAX - 1, u32::MAX - 2);
+        let mut tracker = Tracker::default();
+        tracker.known.insert(parent, (1, "sh".to_string()));
+
+        let event = |kind| ProcEvent {
+            timestamp_ns: 0,
+            kind,
+        };
+        let fork = tracker
+            .resolve(event(ProcEventKind::Fork {
+                parent_pid: parent,
+                parent_tgid: parent,
+                child_pid: child,
+                child_tgid: child,
+            }))
+            .unwrap();
+        assert_eq!(
+            (fork.pid, fork.ppid, fork.comm.as_str()),
+            (child, parent, "sh")
+        );
+
+        let thread = ProcEventKind::Exit {
+            pid: child - 1,
+            tgid: child,
+            status: 0,
+        };
+        assert_eq!(tracker.resolve(event(thread)), None);
+
+        let exit = tracker
+            .resolve(event(ProcEventKind::Exit {
+                pid: child,
+                tgid: child,
+                status: 1 << 8,
+            }))
+            .unwrap();
+        assert_eq!(exit.ppid, parent);
+        assert_eq!(exit.comm, "sh");
+        assert!(!tracker.known.contains_key(&child));
+    }
+}
@@ -12,18 +12,37 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
+use std::time::Duration;
+
 use anyhow::{Result, bail};
 use chrono::{TimeZone, Utc, offset::LocalResult};
+use prost::Message;
 use psh_proto::{
     ExportDataReq, GetTaskReq, HeartbeatReq, TaskDoneReq, Unit,
     psh_service_client::PshServiceClient,
 };
 use tonic::{
     Request,
+    metadata::{MetadataMap, MetadataValue},
     transport::{Channel, ClientTlsConfig, Endpoint},
 };
 
-use crate::{config::RpcConfig, runtime::Task, services::host_info::new_info_req};
+use crate::{
+    build_info,
+    config::RpcConfig,
+    kubernetes::{self, Identity},
+    ledger::{self, RunStatus},
+    runtime::{Task, TaskResult},
+    services::{
+        commands::{self, ServerCommand},
+        export_stats::{self, BackendStats},
+        host_info::new_info_req,
+        upload_budget::{self, Priority},
+    },
+};
+
+const EXPORT_ATTEMPTS: u32 = 3;
+const EXPORT_RETRY_BACKOFF: Duration = Duration::from_millis(500);
 
 #[derive(Clone)]
 pub struct RpcClient {
@@ -38,6 +57,59 @@ fn into_req<T>(message: T, token: &str) -> Result<Request<T>> {
     Ok(req)
 }
 
+/// `SendHostInfoReq` has no fields for Kubernetes metadata, it goes along
+/// as request metadata.
+fn insert_identity(metadata: &mut MetadataMap, identity: &Identity) {
+    let labels = identity
+        .labels
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect::<Vec<_>>()
+        .join(",");
+    let values = [
+        ("x-psh-k8s-node-name", identity.node_name.clone()),
+        ("x-psh-k8s-pod-name", identity.pod_name.clone()),
+        ("x-psh-k8s-namespace", identity.namespace.clone()),
+        ("x-psh-k8s-labels", Some(labels).filter(|it| !it.is_empty())),
+    ];
+    for (key, value) in values {
+        // non-ascii values can't be sent as metadata
+        if let Some(value) = value.and_then(|it| it.parse().ok()) {
+            metadata.insert(key, value);
+        }
+    }
+}
+
+/// Describes the file carried by an `ExportDataReq`, `Data` has no fields
+/// for it so it goes along as request metadata.
+#[derive(Clone, Debug)]
+pub struct FileMeta {
+    pub name: String,
+    pub content_type: String,
+    pub attributes: Vec<(String, String)>,
+}
+
+impl FileMeta {
+    fn insert_into(&self, metadata: &mut MetadataMap) -> Result<()> {
+        metadata.insert("x-psh-file-content-type", self.content_type.parse()?);
+        // binary values, names and attributes may be any utf-8
+        metadata.insert_bin(
+            "x-psh-file-name-bin",
+            MetadataValue::from_bytes(self.name.as_bytes()),
+        );
+        let attributes: serde_json::Map<_, _> = self
+            .attributes
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone().into()))
+            .collect();
+        metadata.insert_bin(
+            "x-psh-file-attributes-bin",
+            MetadataValue::from_bytes(&serde_json::to_vec(&attributes)?),
+        );
+        Ok(())
+    }
+}
+
 impl RpcClient {
     pub async fn new(config: &RpcConfig, token: String) -> Result<Self> {
         let ep = Endpoint::from_shared(config.addr.clone())?
@@ -47,22 +119,96 @@ impl RpcClient {
     }
 
     pub async fn send_host_info(&mut self, instance_id: String) -> Result<()> {
-        let req = into_req(new_info_req(instance_id), &self.token)?;
+        let mut req = into_req(new_info_req(instance_id), &self.token)?;
+        if let Some(k8s) = kubernetes::global() {
+            insert_identity(req.metadata_mut(), &k8s.identity);
+        }
+        build_info::get().insert_into(req.metadata_mut());
         let resp = self.client.send_host_info(req).await?;
         tracing::trace!("{:?}", resp.get_ref());
         Ok(())
     }
 
-    pub async fn export_data(&mut self, message: ExportDataReq) -> Result<()> {
-        let req = into_req(message, &self.token)?;
+    pub async fn export_data(
+        &mut self,
+        message: ExportDataReq,
+        file: Option<&FileMeta>,
+    ) -> Result<()> {
+        if let Some(budget) = upload_budget::global() {
+            let priority = match file {
+                Some(_) => Priority::Files,
+                None => Priority::Metrics,
+            };
+            budget.acquire(message.encoded_len(), priority).await;
+        }
+        let mut req = into_req(message, &self.token)?;
+        if let Some(file) = file {
+            file.insert_into(req.metadata_mut())?;
+        }
         self.client.export_data(req).await?;
         Ok(())
     }
 
-    pub async fn heartbeat(&mut self, message: HeartbeatReq) -> Result<()> {
-        let req = into_req(message, &self.token)?;
-        self.client.heartbeat(req).await?;
-        Ok(())
+    /// Sends `message` and accounts its items in `stats`, failed attempts
+    /// are retried with a linear backoff before the items are dropped.
+    pub async fn export_data_accounted(
+        &mut self,
+        message: ExportDataReq,
+        file: Option<&FileMeta>,
+        stats: &BackendStats,
+    ) -> Result<()> {
+        let items = message.data.len() as u64;
+        let mut attempt = 1;
+        loop {
+            match self.export_data(message.clone(), file).await {
+                Ok(()) => {
+                    stats.sent(items);
+                    return Ok(());
+                }
+                Err(e) if attempt < EXPORT_ATTEMPTS => {
+                    tracing::debug!("Export attempt {attempt} failed, retrying: {e}");
+                    stats.retried(items);
+                    tokio::time::sleep(EXPORT_RETRY_BACKOFF * attempt).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    stats.dropped(items);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Commands of the response are returned only if `accept_commands`,
+    /// `HeartbeatReq` and its response have no fields for them so they go
+    /// along as metadata.
+    pub async fn heartbeat(
+        &mut self,
+        message: HeartbeatReq,
+        accept_commands: bool,
+    ) -> Result<Vec<ServerCommand>> {
+        let mut req = into_req(message, &self.token)?;
+        export_stats::insert_stats(req.metadata_mut());
+        if let Some(summary) = ledger::global().and_then(|it| it.summary().parse().ok()) {
+            req.metadata_mut().insert("x-psh-ledger", summary);
+        }
+        if accept_commands {
+            req.metadata_mut().insert(
+                commands::ACCEPT_HEADER,
+                MetadataValue::from_static(commands::ACCEPTED),
+            );
+        }
+        let resp = self.client.heartbeat(req).await?;
+        if !accept_commands {
+            return Ok(vec![]);
+        }
+        Ok(resp
+            .metadata()
+            .get_all(commands::HEADER)
+            .iter()
+            .filter_map(|it| it.to_str().ok())
+            .flat_map(commands::parse_all)
+            .collect())
     }
 
     pub async fn get_task(&mut self, instance_id: String) -> Result<Option<Task>> {
@@ -80,14 +226,44 @@ impl RpcClient {
             id: Some(task.id),
             wasm_component: task.wasm,
             wasm_component_args: task.wasm_args,
+            wasm_component_envs: vec![],
             end_time,
+            tenant: None,
+            running: None,
+            stop: None,
+            max_cpu_ms: None,
+            memory_limit: None,
+            rate_limits: Default::default(),
+            warm: false,
+            component: None,
+            preopens: vec![],
+            seed: None,
+            done: None,
+            input: vec![],
         };
 
         Ok(Some(task))
     }
 
-    pub async fn task_done(&mut self, task_id: String) -> Result<()> {
-        let req = into_req(TaskDoneReq { task_id }, &self.token)?;
+    /// `TaskDoneReq` has no fields for the status and the result the
+    /// component set, they go along as metadata.
+    pub async fn task_done(
+        &mut self,
+        task_id: String,
+        status: RunStatus,
+        result: Option<&TaskResult>,
+    ) -> Result<()> {
+        let mut req = into_req(TaskDoneReq { task_id }, &self.token)?;
+        req.metadata_mut().insert(
+            "x-psh-task-status",
+            MetadataValue::from_static(status.as_str()),
+        );
+        if let Some(result) = result {
+            req.metadata_mut().insert_bin(
+                "x-psh-task-result-bin",
+                MetadataValue::from_bytes(&serde_json::to_vec(result)?),
+            );
+        }
         self.client.task_done(req).await?;
         Ok(())
     }
@@ -0,0 +1,196 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use pprof::protos::Message;
+use tokio::{
+    runtime::Handle,
+    signal::unix::{SignalKind, signal},
+};
+
+use crate::config::SelfProfileConfig;
+
+// the profiler hooks SIGPROF process wide, only one capture can run at a time
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+
+/// Captures a profile of the daemon itself every time it receives SIGUSR1.
+pub async fn listen(cfg: SelfProfileConfig) -> Result<()> {
+    let mut sigusr1 = signal(SignalKind::user_defined1())?;
+    while sigusr1.recv().await.is_some() {
+        if CAPTURING.swap(true, Ordering::AcqRel) {
+            tracing::warn!("Self profile: a capture is already running, ignoring SIGUSR1");
+            continue;
+        }
+        let cfg = cfg.clone();
+        let task_dump = task_dump(&Handle::current());
+        thread::spawn(move || {
+            if let Err(e) = capture(&cfg, &task_dump) {
+                tracing::error!("Self profile: {e}");
+            }
+            CAPTURING.store(false, Ordering::Release);
+        });
+    }
+    Ok(())
+}
+
+/// Samples the daemon for the configured duration, writes a pprof encoded
+/// CPU profile and a text dump of the runtime and its threads next to it.
+fn capture(cfg: &SelfProfileConfig, task_dump: &str) -> Result<PathBuf> {
+    let dump_dir = Path::new(&cfg.dump_dir);
+    fs::create_dir_all(dump_dir)
+        .with_context(|| format!("Failed to create {}", dump_dir.display()))?;
+    let prefix = format!("psh-{}", Utc::now().format("%Y%m%dT%H%M%S"));
+
+    tracing::info!(
+        "Self profile: sampling for {}s at {}Hz",
+        cfg.duration,
+        cfg.frequency
+    );
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(cfg.frequency)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()?;
+    thread::sleep(Duration::from_secs(cfg.duration));
+    let profile = guard.report().build()?.pprof()?;
+    drop(guard);
+
+    let mut content = Vec::new();
+    profile.encode(&mut content)?;
+    let profile_path = dump_dir.join(format!("{prefix}.pb"));
+    fs::write(&profile_path, content)
+        .with_context(|| format!("Failed to write {}", profile_path.display()))?;
+
+    // threads are listed again at the end since the interesting ones may be
+    // spawned while sampling
+    let mut dump = task_dump.to_string();
+    dump.push_str("\n# at end of capture\n");
+    dump.push_str(&threads_dump());
+    let dump_path = dump_dir.join(format!("{prefix}-tasks.txt"));
+    fs::write(&dump_path, dump)
+        .with_context(|| format!("Failed to write {}", dump_path.display()))?;
+
+    tracing::info!(
+        "Self profile written to {} and {}",
+        profile_path.display(),
+        dump_path.display()
+    );
+    Ok(profile_path)
+}
+
+/// Async runtime counters followed by every thread of the daemon.
+fn task_dump(handle: &Handle) -> String {
+    let metrics = handle.metrics();
+    let mut dump = String::new();
+    let _ = writeln!(dump, "# tokio runtime");
+    let _ = writeln!(dump, "workers: {}", metrics.num_workers());
+    let _ = writeln!(dump, "alive_tasks: {}", metrics.num_alive_tasks());
+    let _ = writeln!(dump, "global_queue_depth: {}", metrics.global_queue_depth());
+    dump.push_str("\n# at start of capture\n");
+    dump.push_str(&threads_dump());
+    dump
+}
+
+fn threads_dump() -> String {
+    let mut dump = String::new();
+    let Ok(tasks) = fs::read_dir("/proc/self/task") else {
+        return dump;
+    };
+    let mut tids: Vec<u32> = tasks
+        .filter_map(|it| it.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    tids.sort_unstable();
+
+    for tid in tids {
+        let dir = PathBuf::from(format!("/proc/self/task/{tid}"));
+        let Some(stat) = fs::read_to_string(dir.join("stat"))
+            .ok()
+            .and_then(|it| TaskStat::parse(&it))
+        else {
+            continue;
+        };
+        let wchan = fs::read_to_string(dir.join("wchan")).unwrap_or_default();
+        let _ = writeln!(
+            dump,
+            "tid={tid} comm={} state={} utime_ticks={} stime_ticks={} wchan={}",
+            stat.comm,
+            stat.state,
+            stat.utime,
+            stat.stime,
+            if wchan.is_empty() { "-" } else { &wchan }
+        );
+        // kernel stacks are only readable with CAP_SYS_ADMIN
+        if let Ok(stack) = fs::read_to_string(dir.join("stack")) {
+            for frame in stack.lines() {
+                let _ = writeln!(dump, "    {frame}");
+            }
+        }
+    }
+    dump
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct TaskStat {
+    comm: String,
+    state: char,
+    utime: u64,
+    stime: u64,
+}
+
+impl TaskStat {
+    /// Parses `/proc/<pid>/task/<tid>/stat`, comm may contain spaces and
+    /// parentheses so the fields are counted from its last `)`.
+    fn parse(stat: &str) -> Option<Self> {
+        let start = stat.find('(')?;
+        let end = stat.rfind(')')?;
+        let comm = stat.get(start + 1..end)?.to_string();
+        let fields: Vec<&str> = stat.get(end + 1..)?.split_whitespace().collect();
+        Some(Self {
+            comm,
+            state: fields.first()?.chars().next()?,
+            utime: fields.get(11)?.parse().ok()?,
+            stime: fields.get(12)?.parse().ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TaskStat;
+
+    #[test]
+    fn test_parse_task_stat() {
+        let stat = "4242 (tokio (rt) 1) S 1 4242 4242 0 -1 4194368 1320 0 0 0 57 13 0 0 20 0 9 0 \
+                    1253 123456 789 18446744073709551615 1 1 0 0 0 0 0 4096 0 0 0 0 17 3 0 0 0 0 0";
+        assert_eq!(
+            TaskStat::parse(stat),
+            Some(TaskStat {
+                comm: "tokio (rt) 1".to_string(),
+                state: 'S',
+                utime: 57,
+                stime: 13,
+            })
+        );
+        assert_eq!(TaskStat::parse("4242 (psh"), None);
+    }
+}
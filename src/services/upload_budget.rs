@@ -0,0 +1,165 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail};
+
+use crate::config::UploadConfig;
+
+// a file upload waiting behind metrics checks again after this long
+const YIELD_INTERVAL: Duration = Duration::from_millis(100);
+
+static GLOBAL: OnceLock<UploadBudget> = OnceLock::new();
+
+pub fn global() -> Option<&'static UploadBudget> {
+    GLOBAL.get()
+}
+
+pub fn init(cfg: &UploadConfig) -> Result<()> {
+    if cfg.bytes_per_min == 0 {
+        bail!("The upload budget must not be 0");
+    }
+    if GLOBAL.set(UploadBudget::new(cfg.bytes_per_min)).is_err() {
+        bail!("Upload budget already initialized");
+    }
+    Ok(())
+}
+
+/// What an upload carries, metrics go first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// data points, samples and alerts
+    Metrics,
+    /// files exported by components, e.g. packet captures
+    Files,
+}
+
+/// Bytes per minute all uploads through the rpc channel share, a token
+/// bucket holding at most one minute of budget.
+///
+/// An upload larger than the bucket waits until it is full and then
+/// overdraws it, so later uploads wait for it to be paid back.
+pub struct UploadBudget {
+    bytes_per_min: f64,
+    // available bytes and when they were counted
+    bucket: Mutex<(f64, Instant)>,
+    metrics_waiting: AtomicUsize,
+}
+
+impl UploadBudget {
+    pub fn new(bytes_per_min: u64) -> Self {
+        let bytes_per_min = bytes_per_min as f64;
+        Self {
+            bytes_per_min,
+            bucket: Mutex::new((bytes_per_min, Instant::now())),
+            metrics_waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Waits until `bytes` may be sent, files also wait for every waiting
+    /// metrics upload.
+    pub async fn acquire(&self, bytes: usize, priority: Priority) {
+        let _waiting = (priority == Priority::Metrics).then(|| Waiting::new(&self.metrics_waiting));
+        while let Some(wait) = self.try_take_at(bytes, priority, Instant::now()) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Takes `bytes` from the bucket, or returns how long to wait before
+    /// trying again.
+    fn try_take_at(&self, bytes: usize, priority: Priority, now: Instant) -> Option<Duration> {
+        if priority == Priority::Files && self.metrics_waiting.load(Ordering::Acquire) > 0 {
+            return Some(YIELD_INTERVAL);
+        }
+        let mut bucket = self.bucket.lock().unwrap();
+        let elapsed = now.saturating_duration_since(bucket.1).as_secs_f64();
+        let available = elapsed.mul_add(self.bytes_per_min / 60.0, bucket.0);
+        *bucket = (available.min(self.bytes_per_min), now);
+        let needed = (bytes as f64).min(self.bytes_per_min);
+        if bucket.0 < needed {
+            let missing = needed - bucket.0;
+            return Some(Duration::from_secs_f64(missing * 60.0 / self.bytes_per_min));
+        }
+        bucket.0 -= bytes as f64;
+        None
+    }
+}
+
+/// Counts a waiting metrics upload until dropped, also when the upload is
+/// cancelled.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl<'a> Waiting<'a> {
+    fn new(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::AcqRel);
+        Self(count)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::AtomicUsize,
+        time::{Duration, Instant},
+    };
+
+    use super::{Priority, UploadBudget, Waiting};
+
+    #[test]
+    fn test_budget() {
+        let budget = UploadBudget::new(600);
+        let now = Instant::now();
+        assert_eq!(budget.try_take_at(400, Priority::Metrics, now), None);
+        // 200 left, 100 more take 10 seconds at 10 bytes per second
+        assert_eq!(
+            budget.try_take_at(300, Priority::Metrics, now),
+            Some(Duration::from_secs(10))
+        );
+        let later = now + Duration::from_secs(10);
+        assert_eq!(budget.try_take_at(300, Priority::Metrics, later), None);
+        // larger than the bucket, waits for it to be full and overdraws it
+        let full = later + Duration::from_secs(60);
+        assert!(budget.try_take_at(1000, Priority::Files, later).is_some());
+        assert_eq!(budget.try_take_at(1000, Priority::Files, full), None);
+        assert!(budget.try_take_at(1, Priority::Metrics, full).is_some());
+    }
+
+    #[test]
+    fn test_metrics_first() {
+        let budget = UploadBudget::new(600);
+        let now = Instant::now();
+        let waiting = Waiting::new(&budget.metrics_waiting);
+        assert!(budget.try_take_at(1, Priority::Files, now).is_some());
+        assert_eq!(budget.try_take_at(1, Priority::Metrics, now), None);
+        drop(waiting);
+        assert_eq!(budget.try_take_at(1, Priority::Files, now), None);
+
+        let count = AtomicUsize::new(0);
+        drop(Waiting::new(&count));
+        assert_eq!(count.into_inner(), 0);
+    }
+}
@@ -0,0 +1,256 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result, anyhow, bail};
+use sled::{
+    Transactional,
+    transaction::{ConflictableTransactionError, TransactionError},
+};
+
+use crate::{
+    at_rest::{self, Sealer},
+    config::StoreConfig,
+};
+
+// longer keys are more likely a value stored by mistake
+const MAX_KEY_LEN: usize = 256;
+// keys by namespace, no namespace starts with `_`
+const COUNTS_TREE: &str = "_psh_key_counts";
+
+static GLOBAL: OnceLock<Store> = OnceLock::new();
+
+/// The store opened by [`init`], `None` if it is disabled.
+pub fn global() -> Option<&'static Store> {
+    GLOBAL.get()
+}
+
+pub fn init(cfg: &StoreConfig) -> Result<()> {
+    let db = sled::open(&cfg.path).with_context(|| format!("Failed to open {}", cfg.path))?;
+    let store = Store::new(db, cfg.max_value_size, cfg.max_keys, at_rest::global())?;
+    if GLOBAL.set(store).is_err() {
+        bail!("Store already initialized");
+    }
+    Ok(())
+}
+
+/// Where a component keeps its state, its name within its tenant.
+///
+/// The tenant is prefixed by its length, names of components may contain
+/// `/` as paths do.
+pub fn namespace(tenant: Option<&str>, component: &str) -> String {
+    match tenant {
+        Some(tenant) => format!("{}:{tenant}/{component}", tenant.len()),
+        None => format!("-:{component}"),
+    }
+}
+
+/// Why a transaction of [`Store::set`] gave up.
+enum Refused {
+    Full,
+}
+
+/// Values components keep across runs, like counters and baselines, on
+/// disk and by namespace, which components can't see out of.
+pub struct Store {
+    db: sled::Db,
+    counts: sled::Tree,
+    max_value_size: usize,
+    max_keys: usize,
+    // encrypts every value
+    sealer: Option<&'static Sealer>,
+}
+
+impl Store {
+    /// Values are encrypted with `sealer` if given.
+    pub fn new(
+        db: sled::Db,
+        max_value_size: usize,
+        max_keys: usize,
+        sealer: Option<&'static Sealer>,
+    ) -> Result<Self> {
+        let counts = db
+            .open_tree(COUNTS_TREE)
+            .context("Failed to open the key counts")?;
+        Ok(Self {
+            db,
+            counts,
+            max_value_size,
+            max_keys,
+            sealer,
+        })
+    }
+
+    /// The tree of `namespace` and its key count, counted once if the
+    /// store was written before counts were kept.
+    fn tree(&self, namespace: &str) -> Result<sled::Tree> {
+        let tree = self
+            .db
+            .open_tree(namespace)
+            .with_context(|| format!("Failed to open namespace {namespace}"))?;
+        if !self.counts.contains_key(namespace)? {
+            let len = (tree.len() as u64).to_le_bytes();
+            let _ = self
+                .counts
+                .compare_and_swap(namespace, None::<&[u8]>, Some(&len[..]))?;
+        }
+        Ok(tree)
+    }
+
+    pub fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        let Some(value) = self.tree(namespace)?.get(key)? else {
+            return Ok(None);
+        };
+        let Some(sealer) = self.sealer else {
+            return Ok(Some(value.to_vec()));
+        };
+        let plain = sealer
+            .open(&value)
+            .with_context(|| format!("Failed to decrypt {key}, was it stored with another key?"))?;
+        Ok(Some(plain))
+    }
+
+    /// Fails on keys that are empty or too long, on values that are too
+    /// large, and on new keys once the namespace is full.
+    pub fn set(&self, namespace: &str, key: &str, value: &[u8]) -> Result<()> {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            bail!("Key must be 1 to {MAX_KEY_LEN} bytes long");
+        }
+        if value.len() > self.max_value_size {
+            bail!(
+                "Value of {} bytes is larger than {} bytes",
+                value.len(),
+                self.max_value_size
+            );
+        }
+        let value = match self.sealer {
+            Some(sealer) => sealer.seal(value)?,
+            None => value.to_vec(),
+        };
+        let tree = self.tree(namespace)?;
+        let max_keys = self.max_keys as u64;
+        // the count and the key change together, concurrent runs can't
+        // go past the limit
+        let res = (&tree, &self.counts).transaction(|(tree, counts)| {
+            if tree.insert(key, &value[..])?.is_none() {
+                let count = count(counts.get(namespace)?.as_deref());
+                if count >= max_keys {
+                    return Err(ConflictableTransactionError::Abort(Refused::Full));
+                }
+                counts.insert(namespace, &(count + 1).to_le_bytes())?;
+            }
+            Ok(())
+        });
+        match res {
+            Ok(()) => Ok(()),
+            Err(TransactionError::Abort(Refused::Full)) => {
+                bail!("Namespace has {} keys already", self.max_keys)
+            }
+            Err(TransactionError::Storage(e)) => Err(e.into()),
+        }
+    }
+
+    /// Whether the key was set.
+    pub fn delete(&self, namespace: &str, key: &str) -> Result<bool> {
+        let tree = self.tree(namespace)?;
+        let res = (&tree, &self.counts).transaction(|(tree, counts)| {
+            if tree.remove(key)?.is_none() {
+                return Ok(false);
+            }
+            let count = count(counts.get(namespace)?.as_deref());
+            counts.insert(namespace, &count.saturating_sub(1).to_le_bytes())?;
+            Ok(true)
+        });
+        res.map_err(|e: TransactionError<()>| anyhow!("{e:?}"))
+    }
+
+    /// Keys starting with `prefix`, in order.
+    pub fn keys(&self, namespace: &str, prefix: &str) -> Result<Vec<String>> {
+        self.tree(namespace)?
+            .scan_prefix(prefix)
+            .keys()
+            .map(|key| Ok(String::from_utf8_lossy(&key?).into_owned()))
+            .collect()
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+fn count(bytes: Option<&[u8]>) -> u64 {
+    bytes
+        .and_then(|it| it.try_into().ok())
+        .map_or(0, u64::from_le_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Store, namespace};
+    use crate::at_rest::Sealer;
+
+    fn store(max_keys: usize) -> Store {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        Store::new(db, 8, max_keys, None).unwrap()
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let store = store(16);
+        let (a, b) = (namespace(None, "a"), namespace(Some("acme"), "a"));
+        assert_eq!(b, "4:acme/a");
+        // a path name can't pass for a tenant
+        assert_ne!(namespace(None, "acme/a"), b);
+        assert_ne!(namespace(Some("ac"), "me/a"), b);
+        store.set(&a, "runs", b"1").unwrap();
+        assert_eq!(store.get(&a, "runs").unwrap().as_deref(), Some(&b"1"[..]));
+        assert_eq!(store.get(&b, "runs").unwrap(), None);
+
+        store.set(&a, "baseline/cpu", b"0.5").unwrap();
+        assert_eq!(store.keys(&a, "").unwrap(), ["baseline/cpu", "runs"]);
+        assert_eq!(store.keys(&a, "base").unwrap(), ["baseline/cpu"]);
+        assert!(store.delete(&a, "runs").unwrap());
+        assert!(!store.delete(&a, "runs").unwrap());
+    }
+
+    #[test]
+    fn test_limits() {
+        let store = store(1);
+        assert!(store.set("a", "", b"1").is_err());
+        assert!(store.set("a", "k", b"123456789").is_err());
+        store.set("a", "k", b"1").unwrap();
+        // replacing a key is fine, a new one is not
+        store.set("a", "k", b"2").unwrap();
+        assert!(store.set("a", "l", b"1").is_err());
+        // a deleted key makes room
+        assert!(store.delete("a", "k").unwrap());
+        store.set("a", "l", b"1").unwrap();
+    }
+
+    #[test]
+    fn test_sealed_values() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let sealer: &'static Sealer = Box::leak(Box::new(Sealer::new(&[7; 32]).unwrap()));
+        let store = Store::new(db.clone(), 64, 16, Some(sealer)).unwrap();
+        store.set("a", "cmdline", b"/usr/bin/sshd").unwrap();
+        assert_eq!(
+            store.get("a", "cmdline").unwrap().as_deref(),
+            Some(&b"/usr/bin/sshd"[..])
+        );
+        let raw = db.open_tree("a").unwrap().get("cmdline").unwrap().unwrap();
+        assert!(!raw.windows(4).any(|it| it == b"sshd"));
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{fs, path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+
+use super::BuildId;
+
+/// Downloads the debug info of `build_id` to `dest`.
+///
+/// See <https://sourceware.org/elfutils/Debuginfod.html> for the protocol.
+pub fn fetch(url: &str, build_id: &BuildId, dest: &Path, timeout: Duration) -> Result<()> {
+    let url = format!("{}/buildid/{build_id}/debuginfo", url.trim_end_matches('/'));
+    let bytes = reqwest::blocking::Client::builder()
+        .timeout(timeout)
+        .build()?
+        .get(&url)
+        .send()?
+        .error_for_status()?
+        .bytes()?;
+
+    if let Some(dir) = dest.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // write then rename, a partial download must never look like a cache hit
+    let tmp = dest.with_extension("part");
+    fs::write(&tmp, &bytes).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, dest)?;
+    tracing::info!("Fetched debug info for {build_id} from {url}");
+    Ok(())
+}
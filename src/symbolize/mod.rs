@@ -0,0 +1,163 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+mod debuginfod;
+mod module;
+
+use std::{
+    fmt, fs,
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+pub use module::{Frame, Module};
+use tinyufo::TinyUfo;
+
+use crate::config::SymbolizeConfig;
+
+// where distributions install separate debug info
+const SYSTEM_DEBUG_DIR: &str = "/usr/lib/debug/.build-id";
+
+static GLOBAL: OnceLock<Symbolizer> = OnceLock::new();
+
+/// The shared symbolizer installed by [`init`].
+pub fn global() -> Option<&'static Symbolizer> {
+    GLOBAL.get()
+}
+
+pub fn init(cfg: &SymbolizeConfig) -> Result<()> {
+    if GLOBAL.set(Symbolizer::new(cfg)).is_err() {
+        bail!("Symbolizer already initialized");
+    }
+    Ok(())
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct BuildId(pub Vec<u8>);
+
+impl fmt::Display for BuildId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+impl BuildId {
+    /// `ab/cdef...` as used by debuginfod and `/usr/lib/debug/.build-id`.
+    fn split_path(&self) -> Option<PathBuf> {
+        let hex = self.to_string();
+        (hex.len() > 2).then(|| Path::new(&hex[..2]).join(&hex[2..]))
+    }
+}
+
+/// Build-id indexed symbol cache shared by all components.
+///
+/// Parsed modules are kept in memory, debug info fetched from debuginfod
+/// and binaries registered by components are remembered in `cache_dir`
+/// so they survive daemon restarts.
+pub struct Symbolizer {
+    cache_dir: PathBuf,
+    debuginfod_urls: Vec<String>,
+    debuginfod_timeout: Duration,
+    modules: TinyUfo<BuildId, Arc<Module>>,
+}
+
+impl Symbolizer {
+    pub fn new(cfg: &SymbolizeConfig) -> Self {
+        Self {
+            cache_dir: PathBuf::from(&cfg.cache_dir),
+            debuginfod_urls: cfg.debuginfod_urls.clone(),
+            debuginfod_timeout: Duration::from_secs(cfg.debuginfod_timeout),
+            modules: TinyUfo::new_compact(cfg.max_modules.max(1), cfg.max_modules.max(1)),
+        }
+    }
+
+    /// Remembers a local ELF file by its build-id, returns the build-id.
+    pub fn register(&self, path: impl AsRef<Path>) -> Result<BuildId> {
+        let path = fs::canonicalize(path.as_ref())
+            .with_context(|| format!("Failed to resolve {}", path.as_ref().display()))?;
+        let module = Module::load(&path)?;
+        let build_id = module.build_id().context("Object has no build-id")?;
+
+        fs::create_dir_all(&self.cache_dir)
+            .with_context(|| format!("Failed to create {}", self.cache_dir.display()))?;
+        let link = self.cache_dir.join(build_id.to_string());
+        let _ = fs::remove_file(&link);
+        symlink(&path, &link).with_context(|| format!("Failed to create {}", link.display()))?;
+
+        self.modules.put(build_id.clone(), Arc::new(module), 1);
+        Ok(build_id)
+    }
+
+    /// Resolves `addresses` of the object identified by `build_id`, every
+    /// address maps to its frames with the innermost inlined function first.
+    pub fn symbolize(&self, build_id: &BuildId, addresses: &[u64]) -> Result<Vec<Vec<Frame>>> {
+        let module = self.module(build_id)?;
+        addresses.iter().map(|addr| module.frames(*addr)).collect()
+    }
+
+    fn module(&self, build_id: &BuildId) -> Result<Arc<Module>> {
+        if let Some(module) = self.modules.get(build_id) {
+            return Ok(module);
+        }
+        let path = self.locate(build_id)?;
+        let module = Arc::new(Module::load(&path)?);
+        if module.build_id().as_ref() != Some(build_id) {
+            bail!("Build-id mismatch for {}", path.display());
+        }
+        self.modules.put(build_id.clone(), Arc::clone(&module), 1);
+        Ok(module)
+    }
+
+    fn locate(&self, build_id: &BuildId) -> Result<PathBuf> {
+        let hex = build_id.to_string();
+        let split = build_id.split_path().context("Invalid build-id")?;
+
+        let candidates = [
+            self.cache_dir.join(format!("{hex}.debug")),
+            self.cache_dir.join(&hex),
+            Path::new(SYSTEM_DEBUG_DIR).join(split.with_extension("debug")),
+        ];
+        if let Some(path) = candidates.into_iter().find(|it| it.exists()) {
+            return Ok(path);
+        }
+
+        let dest = self.cache_dir.join(format!("{hex}.debug"));
+        for url in &self.debuginfod_urls {
+            match debuginfod::fetch(url, build_id, &dest, self.debuginfod_timeout) {
+                Ok(()) => return Ok(dest),
+                Err(e) => tracing::debug!("debuginfod {url}: {e}"),
+            }
+        }
+
+        bail!("No debug info found for build-id {hex}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::BuildId;
+
+    #[test]
+    fn test_build_id_path() {
+        let build_id = BuildId(vec![0xab, 0x01, 0xff]);
+        assert_eq!(build_id.to_string(), "ab01ff");
+        assert_eq!(build_id.split_path().unwrap(), Path::new("ab/01ff"));
+        assert_eq!(BuildId(vec![0xab]).split_path(), None);
+    }
+}
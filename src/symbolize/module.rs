@@ -0,0 +1,137 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    borrow::Cow,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use addr2line::{
+    Context as DwarfContext,
+    gimli::{self, EndianArcSlice, RunTimeEndian},
+};
+use anyhow::{Context, Result};
+use object::{Object, ObjectSection, ObjectSymbol};
+
+use super::BuildId;
+
+type Reader = EndianArcSlice<RunTimeEndian>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    /// `true` if this function was inlined into the next frame
+    pub inlined: bool,
+}
+
+/// A parsed ELF object, DWARF units are parsed lazily on first lookup
+/// and kept for later ones.
+pub struct Module {
+    build_id: Option<BuildId>,
+    dwarf: Mutex<DwarfContext<Reader>>,
+    /// `(address, name)` sorted by address, fallback for objects without DWARF
+    symbols: Vec<(u64, String)>,
+}
+
+impl Module {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let object = object::File::parse(&*data)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        let endian = if object.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+
+        let load_section = |id: gimli::SectionId| -> Result<Reader, object::Error> {
+            let data = object
+                .section_by_name(id.name())
+                .map(|section| section.uncompressed_data())
+                .transpose()?
+                .unwrap_or_default();
+            Ok(EndianArcSlice::new(Arc::from(&*data), endian))
+        };
+        let dwarf = gimli::Dwarf::load(load_section)?;
+        let dwarf = DwarfContext::from_dwarf(dwarf)?;
+
+        let mut symbols: Vec<_> = object
+            .symbols()
+            .chain(object.dynamic_symbols())
+            .filter(|sym| sym.is_definition() && sym.address() != 0)
+            .filter_map(|sym| Some((sym.address(), sym.name().ok()?.to_string())))
+            .collect();
+        symbols.sort_unstable();
+        symbols.dedup_by_key(|(addr, _)| *addr);
+
+        Ok(Self {
+            build_id: object.build_id()?.map(|id| BuildId(id.to_vec())),
+            dwarf: Mutex::new(dwarf),
+            symbols,
+        })
+    }
+
+    pub fn build_id(&self) -> Option<BuildId> {
+        self.build_id.clone()
+    }
+
+    /// Frames of `addr`, a virtual address of the object (runtime address
+    /// minus load bias).
+    pub fn frames(&self, addr: u64) -> Result<Vec<Frame>> {
+        let mut frames = Vec::new();
+        {
+            let dwarf = self.dwarf.lock().unwrap();
+            let mut iter = dwarf.find_frames(addr).skip_all_loads()?;
+            while let Some(frame) = iter.next()? {
+                let function = frame
+                    .function
+                    .map(|name| name.demangle().map(Cow::into_owned))
+                    .transpose()?;
+                let location = frame.location;
+                frames.push(Frame {
+                    function,
+                    file: location.as_ref().and_then(|it| it.file).map(str::to_string),
+                    line: location.and_then(|it| it.line),
+                    inlined: true,
+                });
+            }
+        }
+
+        match frames.last_mut() {
+            Some(outermost) => {
+                outermost.inlined = false;
+                if outermost.function.is_none() {
+                    outermost.function = self.symbol(addr);
+                }
+            }
+            None => frames.push(Frame {
+                function: self.symbol(addr),
+                file: None,
+                line: None,
+                inlined: false,
+            }),
+        }
+        Ok(frames)
+    }
+
+    fn symbol(&self, addr: u64) -> Option<String> {
+        let idx = self.symbols.partition_point(|(start, _)| *start <= addr);
+        let (_, name) = self.symbols.get(idx.checked_sub(1)?)?;
+        Some(addr2line::demangle_auto(Cow::from(name.as_str()), None).into_owned())
+    }
+}
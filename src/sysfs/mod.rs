@@ -0,0 +1,241 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::{Context, Result, bail};
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::config::SysfsConfig;
+
+static GLOBAL: OnceLock<SysfsReader> = OnceLock::new();
+
+/// The reader installed by [`init`], `None` if sysfs reading is disabled.
+pub fn global() -> Option<&'static SysfsReader> {
+    GLOBAL.get()
+}
+
+pub fn init(cfg: &SysfsConfig) -> Result<()> {
+    let reader = SysfsReader::new("/sys", &cfg.prefixes, cfg.max_size, &cfg.audit_file)?;
+    if GLOBAL.set(reader).is_err() {
+        bail!("Sysfs reader already initialized");
+    }
+    Ok(())
+}
+
+/// Who asked for an attribute, recorded in the audit file.
+#[derive(Clone, Debug, Default)]
+pub struct Caller {
+    pub task_id: Option<String>,
+    pub tenant: Option<String>,
+}
+
+/// One line of the audit file.
+#[derive(Serialize)]
+struct Access<'a> {
+    /// in milliseconds since the epoch
+    time_ms: i64,
+    task_id: Option<&'a str>,
+    tenant: Option<&'a str>,
+    path: &'a str,
+    /// bytes returned, 0 if denied or failed
+    bytes: usize,
+    error: Option<String>,
+}
+
+/// Reads attributes under the configured prefixes, every access is appended
+/// to a JSON lines audit file.
+pub struct SysfsReader {
+    root: PathBuf,
+    prefixes: Vec<PathBuf>,
+    /// `prefixes` with their links resolved, symlinks are followed as long
+    /// as they stay in one of these
+    resolved: Vec<PathBuf>,
+    max_size: u64,
+    audit: Mutex<File>,
+}
+
+impl SysfsReader {
+    pub fn new(
+        root: impl Into<PathBuf>,
+        prefixes: &[String],
+        max_size: u64,
+        audit_file: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let root = root.into();
+        let prefixes = prefixes
+            .iter()
+            .map(|it| {
+                let prefix = PathBuf::from(it);
+                if !prefix.starts_with(&root) || !is_normal(&prefix) {
+                    bail!("Sysfs prefix {it} is not under {}", root.display());
+                }
+                Ok(prefix)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let root = fs::canonicalize(&root)
+            .with_context(|| format!("Failed to resolve {}", root.display()))?;
+        // a prefix missing for now only allows what appears lexically in it
+        let resolved = prefixes
+            .iter()
+            .map(|it| fs::canonicalize(it).unwrap_or_else(|_| it.clone()))
+            .collect();
+        let audit_file = audit_file.as_ref();
+        if let Some(dir) = audit_file.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let audit = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(audit_file)
+            .with_context(|| format!("Failed to open {}", audit_file.display()))?;
+        Ok(Self {
+            root,
+            prefixes,
+            resolved,
+            max_size,
+            audit: Mutex::new(audit),
+        })
+    }
+
+    pub fn read(&self, path: &str, caller: &Caller) -> Result<Vec<u8>> {
+        let result = self.read_unaudited(Path::new(path));
+        let access = Access {
+            time_ms: Utc::now().timestamp_millis(),
+            task_id: caller.task_id.as_deref(),
+            tenant: caller.tenant.as_deref(),
+            path,
+            bytes: result.as_ref().map_or(0, Vec::len),
+            error: result.as_ref().err().map(|err| format!("{err:#}")),
+        };
+        // an access that can't be audited is refused
+        self.audit(&access)
+            .context("Failed to write sysfs audit record")?;
+        result
+    }
+
+    fn audit(&self, access: &Access) -> Result<()> {
+        let mut line = serde_json::to_vec(access)?;
+        line.push(b'\n');
+        self.audit.lock().unwrap().write_all(&line)?;
+        Ok(())
+    }
+
+    fn read_unaudited(&self, path: &Path) -> Result<Vec<u8>> {
+        if !is_normal(path) || !self.prefixes.iter().any(|it| path.starts_with(it)) {
+            bail!("Reading {} is not allowed", path.display());
+        }
+        let target = fs::canonicalize(path)
+            .with_context(|| format!("Failed to resolve {}", path.display()))?;
+        if !target.starts_with(&self.root) {
+            bail!("{} links out of {}", path.display(), self.root.display());
+        }
+        if !self.resolved.iter().any(|it| target.starts_with(it)) {
+            bail!("{} links out of the allowed prefixes", path.display());
+        }
+        if !target.is_file() {
+            bail!("{} is not an attribute", path.display());
+        }
+        let mut buf = vec![];
+        // sysfs reports the page size as size of every attribute
+        File::open(&target)
+            .with_context(|| format!("Failed to open {}", path.display()))?
+            .take(self.max_size + 1)
+            .read_to_end(&mut buf)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if buf.len() as u64 > self.max_size {
+            bail!("{} is larger than {} bytes", path.display(), self.max_size);
+        }
+        Ok(buf)
+    }
+}
+
+/// Absolute paths without `.` and `..`, which would escape the prefixes.
+fn is_normal(path: &Path) -> bool {
+    let mut components = path.components();
+    components.next() == Some(Component::RootDir)
+        && components.all(|it| matches!(it, Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::symlink};
+
+    use super::{Caller, SysfsReader};
+
+    #[test]
+    fn test_read_audited() {
+        let dir = std::env::temp_dir().join(format!("psh-sysfs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let root = dir.join("sys");
+        let hwmon = root.join("class/hwmon/hwmon0");
+        fs::create_dir_all(&hwmon).unwrap();
+        fs::write(hwmon.join("temp1_input"), "42000\n").unwrap();
+        fs::write(hwmon.join("big"), "x".repeat(64)).unwrap();
+        fs::write(root.join("secret"), "root only\n").unwrap();
+        fs::write(dir.join("outside"), "not sysfs\n").unwrap();
+        symlink("../../../secret", hwmon.join("secret")).unwrap();
+        symlink("../../../../outside", hwmon.join("outside")).unwrap();
+        symlink("temp1_input", hwmon.join("alias")).unwrap();
+        // like /sys/class, a prefix that links to the device
+        let device = root.join("devices/coretemp");
+        fs::create_dir_all(&device).unwrap();
+        fs::write(device.join("temp2_input"), "43000\n").unwrap();
+        symlink("../../devices/coretemp", hwmon.with_file_name("hwmon1")).unwrap();
+
+        let audit_file = dir.join("audit.jsonl");
+        let prefixes = [
+            hwmon.to_str().unwrap().to_string(),
+            hwmon.with_file_name("hwmon1").to_str().unwrap().to_string(),
+        ];
+        let reader = SysfsReader::new(&root, &prefixes, 16, &audit_file).unwrap();
+        let caller = Caller {
+            task_id: Some("t1".to_string()),
+            tenant: Some("acme".to_string()),
+        };
+        let read = |path: &str| reader.read(&format!("{}/{path}", root.display()), &caller);
+
+        assert_eq!(read("class/hwmon/hwmon0/temp1_input").unwrap(), b"42000\n");
+        // in sysfs but not under a prefix, lexically and through `..`
+        assert!(read("secret").is_err());
+        assert!(read("class/hwmon/../../secret").is_err());
+        // links are followed as long as they stay under a prefix
+        assert_eq!(read("class/hwmon/hwmon0/alias").unwrap(), b"42000\n");
+        assert_eq!(read("class/hwmon/hwmon1/temp2_input").unwrap(), b"43000\n");
+        assert!(read("class/hwmon/hwmon0/secret").is_err());
+        assert!(read("class/hwmon/hwmon0/outside").is_err());
+        assert!(read("class/hwmon/hwmon0").is_err());
+        assert!(read("class/hwmon/hwmon0/big").is_err());
+
+        let audit = fs::read_to_string(&audit_file).unwrap();
+        let lines: Vec<serde_json::Value> = audit
+            .lines()
+            .map(|it| serde_json::from_str(it).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0]["tenant"], "acme");
+        assert_eq!(lines[0]["bytes"], 6);
+        assert!(lines[0]["error"].is_null());
+        assert_eq!(lines[1]["bytes"], 0);
+        assert!(lines[1]["error"].as_str().unwrap().contains("not allowed"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+use serde_json::{Map, Value, json};
+
+use super::{Event, Trace};
+
+// timestamps of the format are in microseconds
+fn us(ns: u64) -> f64 {
+    ns as f64 / 1000.0
+}
+
+fn args(args: &[(String, String)]) -> Value {
+    Value::Object(
+        args.iter()
+            .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+            .collect::<Map<_, _>>(),
+    )
+}
+
+/// The trace as a JSON object of the trace event format.
+pub fn encode(trace: &Trace) -> Vec<u8> {
+    let processes = trace.pids().into_iter().filter_map(|pid| {
+        let name = trace.process_name(pid)?;
+        Some(json!({
+            "ph": "M",
+            "name": "process_name",
+            "pid": pid,
+            "args": { "name": name },
+        }))
+    });
+    let threads = trace.threads.iter().map(|it| {
+        json!({
+            "ph": "M",
+            "name": "thread_name",
+            "pid": it.pid,
+            "tid": it.tid,
+            "args": { "name": it.name },
+        })
+    });
+    let events = trace.events.iter().map(|it| match it {
+        Event::Slice {
+            name,
+            category,
+            pid,
+            tid,
+            start_ns,
+            duration_ns,
+            args: slice_args,
+        } => json!({
+            "ph": "X",
+            "name": name,
+            "cat": category,
+            "pid": pid,
+            "tid": tid,
+            "ts": us(*start_ns),
+            "dur": us(*duration_ns),
+            "args": args(slice_args),
+        }),
+        Event::Instant {
+            name,
+            category,
+            pid,
+            tid,
+            ns_ts,
+            args: instant_args,
+        } => json!({
+            "ph": "i",
+            "s": "t",
+            "name": name,
+            "cat": category,
+            "pid": pid,
+            "tid": tid,
+            "ts": us(*ns_ts),
+            "args": args(instant_args),
+        }),
+        Event::Counter {
+            name,
+            pid,
+            ns_ts,
+            value,
+        } => json!({
+            "ph": "C",
+            "name": name,
+            "pid": pid,
+            "ts": us(*ns_ts),
+            "args": { name: value },
+        }),
+    });
+    let events: Vec<_> = processes.chain(threads).chain(events).collect();
+    let trace = json!({
+        "traceEvents": events,
+        "displayTimeUnit": "ns",
+    });
+    serde_json::to_vec(&trace).unwrap_or_default()
+}
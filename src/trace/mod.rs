@@ -0,0 +1,260 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+mod chrome;
+mod perfetto;
+
+use anyhow::{Result, bail};
+
+const BULK_MAGIC: &[u8; 4] = b"PSHB";
+const BULK_HEADER_LEN: usize = 16;
+const BULK_KIND_THREAD_TIMELINE: u16 = 3;
+const TIMELINE_RECORD_LEN: usize = 40;
+
+/// Trace formats standard viewers such as Perfetto UI and
+/// `chrome://tracing` open.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// the JSON trace event format
+    ChromeJson,
+    /// `perfetto.protos.Trace` of track events
+    Perfetto,
+}
+
+impl Format {
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            Self::ChromeJson => "application/json",
+            Self::Perfetto => "application/vnd.google.protobuf",
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::ChromeJson => "chrome-json",
+            Self::Perfetto => "perfetto",
+        }
+    }
+}
+
+/// A named thread, the one whose `tid` is its `pid` names the process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Thread {
+    pub pid: u32,
+    pub tid: u32,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// something a thread spent time in
+    Slice {
+        name: String,
+        category: String,
+        pid: u32,
+        tid: u32,
+        start_ns: u64,
+        duration_ns: u64,
+        args: Vec<(String, String)>,
+    },
+    /// something that happened on a thread
+    Instant {
+        name: String,
+        category: String,
+        pid: u32,
+        tid: u32,
+        ns_ts: u64,
+        args: Vec<(String, String)>,
+    },
+    /// a value of process `pid` over time, one track by name
+    Counter {
+        name: String,
+        pid: u32,
+        ns_ts: u64,
+        value: f64,
+    },
+}
+
+/// Threads and their events, encoded for trace viewers.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trace {
+    pub threads: Vec<Thread>,
+    pub events: Vec<Event>,
+}
+
+impl Trace {
+    pub fn encode(&self, format: Format) -> Vec<u8> {
+        match format {
+            Format::ChromeJson => chrome::encode(self),
+            Format::Perfetto => perfetto::encode(self),
+        }
+    }
+
+    /// Processes of the threads and events, in order of appearance.
+    fn pids(&self) -> Vec<u32> {
+        let mut pids: Vec<u32> = vec![];
+        let threads = self.threads.iter().map(|it| it.pid);
+        let events = self.events.iter().map(|it| match it {
+            Event::Slice { pid, .. } | Event::Instant { pid, .. } | Event::Counter { pid, .. } => {
+                *pid
+            }
+        });
+        for pid in threads.chain(events) {
+            if !pids.contains(&pid) {
+                pids.push(pid);
+            }
+        }
+        pids
+    }
+
+    fn process_name(&self, pid: u32) -> Option<&str> {
+        self.threads
+            .iter()
+            .find(|it| it.pid == pid && it.tid == pid)
+            .map(|it| it.name.as_str())
+    }
+
+    /// Reads a thread timeline of `profiling:system-ext/bulk` of process
+    /// `pid`, every interval becomes a slice named after the state of the
+    /// thread.
+    pub fn from_thread_timeline(pid: u32, bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < BULK_HEADER_LEN || &bytes[..4] != BULK_MAGIC {
+            bail!("Not a bulk result");
+        }
+        let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let u32_at = |buf: &[u8], offset: usize| {
+            u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+        };
+        if u16_at(6) != BULK_KIND_THREAD_TIMELINE {
+            bail!(
+                "Not a thread timeline but bulk result of kind {}",
+                u16_at(6)
+            );
+        }
+        let count = u32_at(bytes, 8) as usize;
+        let record_len = u32_at(bytes, 12) as usize;
+        if record_len < TIMELINE_RECORD_LEN {
+            bail!("Thread timeline records of {record_len} bytes are too short");
+        }
+        let records_end = count
+            .checked_mul(record_len)
+            .and_then(|it| it.checked_add(BULK_HEADER_LEN))
+            .filter(|it| *it <= bytes.len());
+        let Some(records_end) = records_end else {
+            bail!("Truncated thread timeline");
+        };
+        let strings = &bytes[records_end..];
+
+        let mut trace = Self::default();
+        for record in bytes[BULK_HEADER_LEN..records_end].chunks_exact(record_len) {
+            let u64_at =
+                |offset: usize| u64::from_le_bytes(record[offset..offset + 8].try_into().unwrap());
+            let tid = u32_at(record, 0);
+            let (start_ns, end_ns) = (u64_at(8), u64_at(16));
+            let cpu = u32_at(record, 24);
+            let waker = u32_at(record, 28);
+            let (name_at, name_len) = (u32_at(record, 32) as usize, u32_at(record, 36) as usize);
+            let Some(name) = strings.get(name_at..name_at + name_len) else {
+                bail!("Thread name of {tid} is out of the string pool");
+            };
+            if !trace.threads.iter().any(|it| it.tid == tid) {
+                trace.threads.push(Thread {
+                    pid,
+                    tid,
+                    name: String::from_utf8_lossy(name).into_owned(),
+                });
+            }
+            let state = match record[4] {
+                0 => "running",
+                1 => "preempted",
+                2 => "woken up",
+                3 => "sleeping",
+                4 => "blocked",
+                _ => "other",
+            };
+            let mut args = vec![("cpu".to_string(), cpu.to_string())];
+            if waker != 0 {
+                args.push(("waker".to_string(), waker.to_string()));
+            }
+            trace.events.push(Event::Slice {
+                name: state.to_string(),
+                category: "sched".to_string(),
+                pid,
+                tid,
+                start_ns,
+                duration_ns: end_ns.saturating_sub(start_ns),
+                args,
+            });
+        }
+        Ok(trace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Thread, Trace};
+
+    fn timeline() -> Vec<u8> {
+        let mut bytes = b"PSHB".to_vec();
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&3u16.to_le_bytes());
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&40u32.to_le_bytes());
+        for (tid, state, start, end, waker) in
+            [(7u32, 0u8, 100u64, 250u64, 0u32), (7, 2, 250, 300, 9)]
+        {
+            bytes.extend_from_slice(&tid.to_le_bytes());
+            bytes.extend_from_slice(&[state, 0, 0, 0]);
+            bytes.extend_from_slice(&start.to_le_bytes());
+            bytes.extend_from_slice(&end.to_le_bytes());
+            bytes.extend_from_slice(&3u32.to_le_bytes());
+            bytes.extend_from_slice(&waker.to_le_bytes());
+            bytes.extend_from_slice(&0u32.to_le_bytes());
+            bytes.extend_from_slice(&4u32.to_le_bytes());
+        }
+        bytes.extend_from_slice(b"main");
+        bytes
+    }
+
+    #[test]
+    fn test_from_thread_timeline() {
+        let trace = Trace::from_thread_timeline(7, &timeline()).unwrap();
+        assert_eq!(
+            trace.threads,
+            [Thread {
+                pid: 7,
+                tid: 7,
+                name: "main".to_string()
+            }]
+        );
+        assert_eq!(trace.process_name(7), Some("main"));
+        assert_eq!(trace.events.len(), 2);
+        let Event::Slice {
+            name,
+            duration_ns,
+            args,
+            ..
+        } = &trace.events[1]
+        else {
+            panic!("not a slice");
+        };
+        assert_eq!(name, "woken up");
+        assert_eq!(*duration_ns, 50);
+        assert_eq!(args[1], ("waker".to_string(), "9".to_string()));
+
+        let mut truncated = timeline();
+        truncated.truncate(60);
+        assert!(Trace::from_thread_timeline(7, &truncated).is_err());
+        assert!(Trace::from_thread_timeline(7, b"PSHB").is_err());
+    }
+}
@@ -0,0 +1,328 @@
+// Copyright (c) 2023-2024 Optimatist Technology Co., Ltd. All rights reserved.
+// DO NOT ALTER OR REMOVE COPYRIGHT NOTICES OR THIS FILE HEADER.
+//
+// This file is part of PSH.
+//
+// PSH is free software: you can redistribute it and/or modify it under the terms of the GNU Lesser General Public License
+// as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// PSH is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
+// see <https://www.gnu.org/licenses/>.
+use std::collections::BTreeMap;
+
+use prost::{Enumeration, Message};
+
+use super::{Event, Trace};
+
+// messages and field numbers of protos/perfetto/trace, only what track
+// events need
+
+// timestamps of the host are of CLOCK_MONOTONIC
+const BUILTIN_CLOCK_MONOTONIC: u32 = 3;
+const SEQUENCE_ID: u32 = 1;
+// uuids of the tracks of threads and counters, those of processes are
+// their pid
+const THREAD_TRACKS: u64 = 1 << 32;
+const COUNTER_TRACKS: u64 = 2 << 32;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PerfettoTrace {
+    #[prost(message, repeated, tag = "1")]
+    pub packet: Vec<TracePacket>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TracePacket {
+    #[prost(uint64, optional, tag = "8")]
+    pub timestamp: Option<u64>,
+    #[prost(uint32, optional, tag = "10")]
+    pub trusted_packet_sequence_id: Option<u32>,
+    #[prost(message, optional, tag = "11")]
+    pub track_event: Option<TrackEvent>,
+    #[prost(uint32, optional, tag = "58")]
+    pub timestamp_clock_id: Option<u32>,
+    #[prost(message, optional, tag = "60")]
+    pub track_descriptor: Option<TrackDescriptor>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TrackDescriptor {
+    #[prost(uint64, optional, tag = "1")]
+    pub uuid: Option<u64>,
+    #[prost(string, optional, tag = "2")]
+    pub name: Option<String>,
+    #[prost(message, optional, tag = "3")]
+    pub process: Option<ProcessDescriptor>,
+    #[prost(message, optional, tag = "4")]
+    pub thread: Option<ThreadDescriptor>,
+    #[prost(uint64, optional, tag = "5")]
+    pub parent_uuid: Option<u64>,
+    #[prost(message, optional, tag = "8")]
+    pub counter: Option<CounterDescriptor>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ProcessDescriptor {
+    #[prost(int32, optional, tag = "1")]
+    pub pid: Option<i32>,
+    #[prost(string, optional, tag = "6")]
+    pub process_name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ThreadDescriptor {
+    #[prost(int32, optional, tag = "1")]
+    pub pid: Option<i32>,
+    #[prost(int32, optional, tag = "2")]
+    pub tid: Option<i32>,
+    #[prost(string, optional, tag = "5")]
+    pub thread_name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CounterDescriptor {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration)]
+#[repr(i32)]
+pub enum TrackEventType {
+    SliceBegin = 1,
+    SliceEnd = 2,
+    Instant = 3,
+    Counter = 4,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TrackEvent {
+    #[prost(message, repeated, tag = "4")]
+    pub debug_annotations: Vec<DebugAnnotation>,
+    #[prost(enumeration = "TrackEventType", optional, tag = "9")]
+    pub r#type: Option<i32>,
+    #[prost(uint64, optional, tag = "11")]
+    pub track_uuid: Option<u64>,
+    #[prost(string, repeated, tag = "22")]
+    pub categories: Vec<String>,
+    #[prost(string, optional, tag = "23")]
+    pub name: Option<String>,
+    #[prost(double, optional, tag = "44")]
+    pub double_counter_value: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct DebugAnnotation {
+    #[prost(string, optional, tag = "6")]
+    pub string_value: Option<String>,
+    #[prost(string, optional, tag = "10")]
+    pub name: Option<String>,
+}
+
+fn thread_uuid(tid: u32) -> u64 {
+    THREAD_TRACKS | u64::from(tid)
+}
+
+fn annotations(args: &[(String, String)]) -> Vec<DebugAnnotation> {
+    args.iter()
+        .map(|(k, v)| DebugAnnotation {
+            string_value: Some(v.clone()),
+            name: Some(k.clone()),
+        })
+        .collect()
+}
+
+fn descriptor(track_descriptor: TrackDescriptor) -> TracePacket {
+    TracePacket {
+        trusted_packet_sequence_id: Some(SEQUENCE_ID),
+        track_descriptor: Some(track_descriptor),
+        ..Default::default()
+    }
+}
+
+/// The trace as a `perfetto.protos.Trace`, every thread and counter on a
+/// track of its own below the track of its process.
+pub fn encode(trace: &Trace) -> Vec<u8> {
+    let mut packets: Vec<_> = trace
+        .pids()
+        .into_iter()
+        .map(|pid| {
+            descriptor(TrackDescriptor {
+                uuid: Some(pid.into()),
+                process: Some(ProcessDescriptor {
+                    pid: Some(pid as i32),
+                    process_name: trace.process_name(pid).map(str::to_string),
+                }),
+                ..Default::default()
+            })
+        })
+        .collect();
+    packets.extend(trace.threads.iter().map(|it| {
+        descriptor(TrackDescriptor {
+            uuid: Some(thread_uuid(it.tid)),
+            thread: Some(ThreadDescriptor {
+                pid: Some(it.pid as i32),
+                tid: Some(it.tid as i32),
+                thread_name: Some(it.name.clone()),
+            }),
+            parent_uuid: Some(it.pid.into()),
+            ..Default::default()
+        })
+    }));
+
+    let mut counters: BTreeMap<(u32, &str), u64> = BTreeMap::new();
+    // the end of a slice goes before what starts at the same time
+    let mut events: Vec<(u64, bool, TrackEvent)> = vec![];
+    for event in &trace.events {
+        match event {
+            Event::Slice {
+                name,
+                category,
+                tid,
+                start_ns,
+                duration_ns,
+                args,
+                ..
+            } => {
+                events.push((
+                    *start_ns,
+                    true,
+                    TrackEvent {
+                        debug_annotations: annotations(args),
+                        r#type: Some(TrackEventType::SliceBegin as i32),
+                        track_uuid: Some(thread_uuid(*tid)),
+                        categories: vec![category.clone()],
+                        name: Some(name.clone()),
+                        ..Default::default()
+                    },
+                ));
+                events.push((
+                    start_ns.saturating_add(*duration_ns),
+                    false,
+                    TrackEvent {
+                        r#type: Some(TrackEventType::SliceEnd as i32),
+                        track_uuid: Some(thread_uuid(*tid)),
+                        ..Default::default()
+                    },
+                ));
+            }
+            Event::Instant {
+                name,
+                category,
+                tid,
+                ns_ts,
+                args,
+                ..
+            } => events.push((
+                *ns_ts,
+                true,
+                TrackEvent {
+                    debug_annotations: annotations(args),
+                    r#type: Some(TrackEventType::Instant as i32),
+                    track_uuid: Some(thread_uuid(*tid)),
+                    categories: vec![category.clone()],
+                    name: Some(name.clone()),
+                    ..Default::default()
+                },
+            )),
+            Event::Counter {
+                name,
+                pid,
+                ns_ts,
+                value,
+            } => {
+                let next = COUNTER_TRACKS | counters.len() as u64;
+                let uuid = *counters.entry((*pid, name.as_str())).or_insert_with(|| {
+                    packets.push(descriptor(TrackDescriptor {
+                        uuid: Some(next),
+                        name: Some(name.clone()),
+                        parent_uuid: Some((*pid).into()),
+                        counter: Some(CounterDescriptor {}),
+                        ..Default::default()
+                    }));
+                    next
+                });
+                events.push((
+                    *ns_ts,
+                    true,
+                    TrackEvent {
+                        r#type: Some(TrackEventType::Counter as i32),
+                        track_uuid: Some(uuid),
+                        double_counter_value: Some(*value),
+                        ..Default::default()
+                    },
+                ));
+            }
+        }
+    }
+    events.sort_by_key(|(ns_ts, starts, _)| (*ns_ts, *starts));
+    packets.extend(
+        events
+            .into_iter()
+            .map(|(ns_ts, _, track_event)| TracePacket {
+                timestamp: Some(ns_ts),
+                trusted_packet_sequence_id: Some(SEQUENCE_ID),
+                track_event: Some(track_event),
+                timestamp_clock_id: Some(BUILTIN_CLOCK_MONOTONIC),
+                ..Default::default()
+            }),
+    );
+    PerfettoTrace { packet: packets }.encode_to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+
+    use super::{PerfettoTrace, TrackEventType, encode};
+    use crate::trace::{Event, Thread, Trace};
+
+    #[test]
+    fn test_encode() {
+        let slice = |start_ns| Event::Slice {
+            name: "running".to_string(),
+            category: "sched".to_string(),
+            pid: 7,
+            tid: 8,
+            start_ns,
+            duration_ns: 10,
+            args: vec![],
+        };
+        let trace = Trace {
+            threads: vec![Thread {
+                pid: 7,
+                tid: 8,
+                name: "worker".to_string(),
+            }],
+            events: vec![
+                slice(10),
+                slice(0),
+                Event::Counter {
+                    name: "rss".to_string(),
+                    pid: 7,
+                    ns_ts: 5,
+                    value: 1.5,
+                },
+            ],
+        };
+        let decoded = PerfettoTrace::decode(&*encode(&trace)).unwrap();
+        // a process, a thread and a counter track
+        let (descriptors, events) = decoded.packet.split_at(3);
+        assert!(descriptors.iter().all(|it| it.track_descriptor.is_some()));
+        let types: Vec<_> = events
+            .iter()
+            .map(|it| {
+                let event = it.track_event.as_ref().unwrap();
+                (it.timestamp.unwrap(), event.r#type.unwrap())
+            })
+            .collect();
+        let (begin, end, counter) = (
+            TrackEventType::SliceBegin as i32,
+            TrackEventType::SliceEnd as i32,
+            TrackEventType::Counter as i32,
+        );
+        assert_eq!(
+            types,
+            [(0, begin), (5, counter), (10, end), (10, begin), (20, end)]
+        );
+    }
+}
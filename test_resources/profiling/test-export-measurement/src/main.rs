@@ -12,23 +12,14 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
-#[rustfmt::skip]
-#[allow(dead_code)]
-mod imports;
-
-use imports::profiling::data_export::{
-    common::FieldValue,
-    measurement::{export_point, Point},
-};
+use psh_sdk::export::PointBuilder;
 
 fn main() {
-    for i in 0..100 {
-        let point = Point {
-            name: "test-export-measurement".to_string(),
-            tags: vec![("tag".to_string(), "hello".to_string())],
-            fields: vec![("count".to_string(), FieldValue::Int(i))],
-            ns_ts: None,
-        };
-        export_point(&point).expect("Failed to export point");
+    for i in 0..100i64 {
+        PointBuilder::new("test-export-measurement")
+            .tag("tag", "hello")
+            .field("count", i)
+            .export()
+            .expect("Failed to export point");
     }
 }
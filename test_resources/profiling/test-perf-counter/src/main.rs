@@ -12,38 +12,15 @@
 // You should have received a copy of the GNU Lesser General Public License along with Performance Savior Home (PSH). If not,
 // see <https://www.gnu.org/licenses/>.
 
-#[allow(dead_code)]
-mod imports;
-
-use crate::imports::profiling;
-use profiling::perf::{
-    config::{Config, Cpu, Event, EventScope, ExtraConfig, HardwareEvent, Process},
-    counter::Counter,
+use psh_sdk::{
+    bindings::perf::profiling::perf::config::{Cpu, Event, HardwareEvent, Process},
+    perf::ConfigBuilder,
 };
 
 fn main() {
-    let cfg = Config {
-        event: Event::Hardware(HardwareEvent::CpuCycles),
-        scopes: vec![
-            EventScope::User,
-            EventScope::Kernel,
-            EventScope::Hv,
-            EventScope::Idle,
-            EventScope::Host,
-            EventScope::Guest,
-        ],
-        extra_config: ExtraConfig {
-            pinned: false,
-            exclusive: false,
-            inherit: false,
-            inherit_stat: false,
-            inherit_thread: false,
-            enable_on_exec: false,
-            remove_on_exec: false,
-        },
-    };
-
-    let counter = Counter::new(Process::Current, Cpu::Any, &cfg).unwrap();
+    let counter = ConfigBuilder::new(Event::Hardware(HardwareEvent::CpuCycles))
+        .open(Process::Current, Cpu::Any)
+        .unwrap();
 
     counter.enable().unwrap();
     println!("do something here...");